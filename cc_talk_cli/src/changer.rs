@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use cc_talk_core::cc_talk::{Category, ChangerDevice, ChecksumType, Describe, Device};
+use cc_talk_tokio_host::{device::changer::Changer, transport::tokio_transport::TransportMessage};
+use clap::Subcommand;
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+
+use crate::exit::{CliError, CliResult, CommandContext};
+
+#[derive(Subcommand, Debug)]
+pub enum ChangerCommands {
+    /// Pay out a value and wait until it is paid
+    Pay {
+        /// Value to pay out, in smallest currency units
+        amount: u32,
+
+        /// Interval between polls in milliseconds
+        #[arg(short, long, default_value_t = 500)]
+        poll_interval: u64,
+    },
+
+    /// Print the value paid and unpaid by the last payout
+    Verify {},
+
+    /// Print the activity flags raised by the changer
+    Activity {},
+
+    /// Print the last error of the changer
+    Errors {},
+
+    /// Print the coin and balance of the hoppers
+    Balance {
+        /// Hopper number, starting at 1
+        #[arg(default_values_t = [1])]
+        hopper: Vec<u8>,
+    },
+
+    /// Overwrite the balance of a hopper, e.g. after a manual refill
+    SetBalance {
+        /// Hopper number, starting at 1
+        hopper: u8,
+
+        /// Number of coins in the hopper
+        balance: u16,
+    },
+
+    /// Print the value held in the cashbox
+    Cashbox {},
+
+    /// Empty a hopper into the cashbox
+    Purge {
+        /// Hopper number, starting at 1, 255 purges the whole changer
+        hopper: u8,
+
+        /// Number of coins to purge, 0 empties the hopper
+        #[arg(short, long, default_value_t = 0)]
+        count: u8,
+    },
+}
+
+/// Runs the changer command.
+///
+/// # Errors
+///
+/// Errors if a command fails, a payout is left unpaid or the changer reports an error.
+pub async fn handler(
+    transport: Sender<TransportMessage>,
+    address: u8,
+    action: &ChangerCommands,
+) -> CliResult {
+    let changer = Changer::new(
+        Device::new(address, Category::Changer, ChecksumType::Crc8),
+        transport,
+    );
+
+    let result = match action {
+        ChangerCommands::Pay {
+            amount,
+            poll_interval,
+        } => pay(&changer, *amount, *poll_interval).await,
+        ChangerCommands::Verify {} => verify(&changer).await,
+        ChangerCommands::Activity {} => activity(&changer).await,
+        ChangerCommands::Errors {} => errors(&changer).await,
+        ChangerCommands::Balance { hopper } => balance(&changer, hopper).await,
+        ChangerCommands::SetBalance { hopper, balance } => changer
+            .modify_hopper_balance(*hopper, *balance)
+            .await
+            .context("unable to modify the hopper balance")
+            .map(|()| info!("hopper {} balance set to {}", hopper, balance)),
+        ChangerCommands::Cashbox {} => changer
+            .request_cashbox_value()
+            .await
+            .context("unable to request the cashbox value")
+            .map(|value| info!("cashbox value: {}", value)),
+        ChangerCommands::Purge { hopper, count } => changer
+            .purge_hopper(*hopper, *count)
+            .await
+            .context("unable to purge the hopper")
+            .map(|()| info!("hopper {} purge started", hopper)),
+    };
+    result.map_err(|e| e.at(address))
+}
+
+async fn pay(changer: &Changer, amount: u32, poll_interval: u64) -> CliResult {
+    changer
+        .pay_money_out(amount)
+        .await
+        .context("unable to pay money out")?;
+    info!("paying out {}", amount);
+
+    loop {
+        tokio::time::sleep(Duration::from_millis(poll_interval)).await;
+        match changer.verify_money_out().await {
+            Ok(result) => {
+                info!("paid: {}, unpaid: {}", result.paid, result.unpaid);
+                if result.unpaid > 0 {
+                    return Err(CliError::device_fault(format!(
+                        "changer stopped with {} unpaid",
+                        result.unpaid
+                    )));
+                }
+                if result.paid >= amount {
+                    return Ok(());
+                }
+            }
+            Err(e) => {
+                warn!("unable to verify money out: {}", e);
+            }
+        }
+    }
+}
+
+async fn verify(changer: &Changer) -> CliResult {
+    let result = changer
+        .verify_money_out()
+        .await
+        .context("unable to verify money out")?;
+    info!("event counter: {}", result.event_counter);
+    info!("paid: {}", result.paid);
+    info!("unpaid: {}", result.unpaid);
+    Ok(())
+}
+
+async fn activity(changer: &Changer) -> CliResult {
+    let flags = changer
+        .request_activity_register()
+        .await
+        .context("unable to request the activity register")?;
+    if flags.is_empty() {
+        info!("no activity");
+    }
+    for flag in flags {
+        info!("{:?}", flag);
+    }
+    Ok(())
+}
+
+async fn errors(changer: &Changer) -> CliResult {
+    let (device, error) = changer
+        .request_error_status()
+        .await
+        .context("unable to request the error status")?;
+    if device == ChangerDevice::Unknown {
+        info!("no error");
+        return Ok(());
+    }
+    Err(CliError::device_fault(format!(
+        "{device:?}: {}",
+        error.describe()
+    )))
+}
+
+async fn balance(changer: &Changer, hoppers: &[u8]) -> CliResult {
+    for &hopper in hoppers {
+        let (token, balance) = changer
+            .request_hopper_balance(hopper)
+            .await
+            .context("unable to request the hopper balance")?;
+        info!("hopper {}: {} x {:?}", hopper, balance, token);
+    }
+    Ok(())
+}
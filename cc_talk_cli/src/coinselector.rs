@@ -1,14 +1,19 @@
 use std::time::Duration;
 
-use cc_talk_core::cc_talk::{Category, ChecksumType, CoinEvent, CurrencyToken, Device};
+use cc_talk_core::cc_talk::{Category, ChecksumType, CoinEvent, CurrencyToken, Describe, Device};
 use cc_talk_tokio_host::{
-    device::{base::DeviceCommon, coin_validator::CoinValidator},
+    device::{
+        base::DeviceCommon, coin_event_stream::CoinStreamEvent, coin_validator::CoinValidator,
+        reject_histogram::RejectHistogram,
+    },
     transport::tokio_transport::TransportMessage,
 };
 use clap::Subcommand;
 use tokio::sync::mpsc::Sender;
 use tracing::{error, info};
 
+use crate::exit::{CliError, CliResult, CommandContext, FailureClass};
+
 #[derive(Subcommand, Debug)]
 pub enum CoinSelectorCommands {
     /// Get the currently selected coin types
@@ -18,45 +23,165 @@ pub enum CoinSelectorCommands {
         #[arg(short, long, default_value_t = 0)]
         count: u32,
     },
+    /// Teach a new coin at the given position, press Ctrl-C to abort
+    Teach {
+        /// Coin position to teach
+        position: u8,
+
+        /// Coin orientation, only supported by some selectors
+        #[arg(short, long)]
+        orientation: Option<u8>,
+    },
+    /// Accepts coins and shows the top reject reasons over the last hour and day, press Ctrl-C
+    /// to stop
+    Rejects {
+        /// Number of reject reasons to show
+        #[arg(short, long, default_value_t = 5)]
+        top: usize,
+
+        /// Seconds between two refreshes of the view
+        #[arg(short, long, default_value_t = 10)]
+        refresh: u64,
+    },
 }
 
+/// Runs the coin selector command.
+///
+/// # Errors
+///
+/// Errors if a command fails.
 pub async fn handler(
     transport: Sender<TransportMessage>,
     address: u8,
     action: &CoinSelectorCommands,
-) {
+) -> CliResult {
     let selector = CoinValidator::new(
         Device::new(address, Category::CoinAcceptor, ChecksumType::Crc8),
         transport,
     );
 
-    match action {
-        CoinSelectorCommands::Info {} => {
-            info_selector(selector).await;
+    let result = match action {
+        CoinSelectorCommands::Info {} => info_selector(selector).await,
+        CoinSelectorCommands::Accept { count } => accept_coins(selector, *count, *count == 0).await,
+        CoinSelectorCommands::Teach {
+            position,
+            orientation,
+        } => teach_coin(selector, *position, *orientation).await,
+        CoinSelectorCommands::Rejects { top, refresh } => {
+            show_rejects(selector, *top, Duration::from_secs(*refresh)).await
         }
-        CoinSelectorCommands::Accept { count } => {
-            accept_coins(selector, *count, *count == 0).await;
+    };
+    result.map_err(|e| e.at(address))
+}
+
+const HOUR: Duration = Duration::from_hours(1);
+const DAY: Duration = Duration::from_hours(24);
+
+async fn show_rejects(selector: CoinValidator, top: usize, refresh: Duration) -> CliResult {
+    let polling_priority = enable_coins(&selector).await?;
+
+    let mut events = selector
+        .event_stream(polling_priority, 8)
+        .map_err(|e| CliError::new(FailureClass::Other, format!("unable to poll: {e}")))?;
+    let mut histogram = RejectHistogram::new();
+    let mut refresh = tokio::time::interval(refresh);
+    info!("accepting coins, press Ctrl-C to stop...");
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => {
+                print_rejects(&histogram, top);
+                return Ok(());
+            }
+            _ = refresh.tick() => print_rejects(&histogram, top),
+            event = events.next() => match event {
+                Some(Ok(CoinStreamEvent::Event(event))) => histogram.record_event(&event),
+                Some(Ok(CoinStreamEvent::Lost(count))) => error!("lost events: {}", count),
+                Some(Err(e)) => info!("Error polling for event: {}", e),
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+fn print_rejects(histogram: &RejectHistogram, top: usize) {
+    for (label, window) in [("last hour", HOUR), ("last day", DAY)] {
+        info!(
+            "Top reject reasons over the {} ({} total):",
+            label,
+            histogram.total(window)
+        );
+        for (error, count) in histogram.top(window, top) {
+            info!("  {:>5}  [{}] {}", count, error as u8, error.describe());
         }
     }
 }
 
-async fn accept_coins(mut selector: CoinValidator, mut count: u32, infinite: bool) {
+async fn teach_coin(selector: CoinValidator, position: u8, orientation: Option<u8>) -> CliResult {
+    let session = selector
+        .start_teach(position, orientation)
+        .await
+        .context("unable to start teach mode")?;
+    info!("teaching coin position {}, insert coins...", position);
+
+    // Created once so a Ctrl-C pressed while the progress is requested is not lost.
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut coins_entered = 0;
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => {
+                let progress = session.abort().await.context("unable to abort teach")?;
+                info!(
+                    "teach aborted after {} coins, status: {:?}",
+                    progress.coins_entered, progress.status
+                );
+                return Ok(());
+            }
+            () = tokio::time::sleep(Duration::from_millis(250)) => {}
+        }
+
+        match session.progress().await {
+            Ok(progress) => {
+                if progress.coins_entered != coins_entered {
+                    coins_entered = progress.coins_entered;
+                    info!("samples entered: {}", coins_entered);
+                }
+                if progress.is_finished() {
+                    info!(
+                        "teach finished with status {:?} after {} coins",
+                        progress.status, progress.coins_entered
+                    );
+                    return Ok(());
+                }
+            }
+            Err(e) => error!("error requesting teach status: {}", e),
+        }
+    }
+}
+
+/// Lifts the inhibits of the selector and returns its polling interval.
+async fn enable_coins(selector: &CoinValidator) -> Result<Duration, CliError> {
     selector
         .disable_master_inhibit()
         .await
-        .expect("should disable master inhibit");
-
+        .context("unable to disable the master inhibit")?;
     selector
         .set_all_coin_inhibits(false)
         .await
-        .expect("should enable all coins");
-
-    let polling_priority = selector
+        .context("unable to enable all coins")?;
+    Ok(selector
         .get_polling_priority()
         .await
-        .expect("should get polling priority")
+        .context("unable to get the polling priority")?
         .as_duration()
-        .unwrap_or(Duration::from_millis(200));
+        .unwrap_or(Duration::from_millis(200)))
+}
+
+async fn accept_coins(selector: CoinValidator, mut count: u32, infinite: bool) -> CliResult {
+    let polling_priority = enable_coins(&selector).await?;
 
     let mut event_counter: u8 = 0;
     while count > 0 || infinite {
@@ -83,7 +208,7 @@ async fn accept_coins(mut selector: CoinValidator, mut count: u32, infinite: boo
                                 error!(
                                     "error {}: {}",
                                     coin_acceptor_error as u8,
-                                    coin_acceptor_error.description()
+                                    coin_acceptor_error.describe()
                                 );
                             }
                             CoinEvent::Credit(coin_credit) => {
@@ -107,60 +232,57 @@ async fn accept_coins(mut selector: CoinValidator, mut count: u32, infinite: boo
         }
         tokio::time::sleep(polling_priority).await;
     }
+    Ok(())
 }
 
-#[allow(clippy::explicit_iter_loop)]
-async fn info_selector(selector: CoinValidator) {
+async fn info_selector(selector: CoinValidator) -> CliResult {
     let product_code = selector
         .get_product_code()
         .await
-        .expect("should get product code");
+        .context("unable to get the product code")?;
     let manufacturer_id = selector
         .get_manufacturer_id()
         .await
-        .expect("should get manufacturer id");
+        .context("unable to get the manufacturer id")?;
     let serial_number = selector
         .get_serial_number()
         .await
-        .expect("should get serial number");
+        .context("unable to get the serial number")?;
     let software_revision = selector
         .get_software_revision()
         .await
-        .expect("should get software revision");
-    let coin_ids = selector
-        .request_all_coin_id()
+        .context("unable to get the software revision")?;
+    let coin_table = selector
+        .coin_table()
         .await
-        .expect("should request all coin IDs")
-        .into_iter()
-        .filter(|(_, opt)| opt.is_some())
-        .collect::<Vec<_>>();
+        .context("unable to read the coin table")?;
+    let sorter = selector
+        .sorter()
+        .await
+        .context("unable to detect the sorter")?;
     let mut coin_sorter_paths = vec![];
-    // TODO: Improve this mess
-    for coin in coin_ids.iter() {
-        let csp = selector
-            .get_coin_sorter_path(coin.0)
-            .await
-            .expect("should get coin sorter path");
-        coin_sorter_paths.push((coin.0, csp));
+    if let Some(sorter) = &sorter {
+        for (position, _) in coin_table.iter() {
+            let csp = sorter
+                .get_coin_sorter_path(position)
+                .await
+                .context("unable to get the coin sorter path")?;
+            coin_sorter_paths.push((position, csp));
+        }
     }
-    let coin_ids = coin_ids
-        .into_iter()
-        .map(|(id, opt)| {
-            opt.map_or_else(
-                || format!("{id}: Unknown Coin ID"),
-                |coin_id| match coin_id {
-                    CurrencyToken::Token => format!("{id}: Token"),
-                    CurrencyToken::Currency(value) => {
-                        format!("{id}: {} {}", value.monetary_value(), value.country_code())
-                    }
-                },
-            )
+    let coin_ids = coin_table
+        .iter()
+        .map(|(id, coin_id)| match coin_id {
+            CurrencyToken::Token => format!("{id}: Token"),
+            CurrencyToken::Currency(value) => {
+                format!("{id}: {} {}", value.monetary_value(), value.country_code())
+            }
         })
         .collect::<Vec<_>>();
     let polling_priority = selector
         .get_polling_priority()
         .await
-        .expect("should get polling priority");
+        .context("unable to get the polling priority")?;
 
     info!("Coin Selector Information:");
     info!("  Product Code: {}", product_code);
@@ -168,9 +290,14 @@ async fn info_selector(selector: CoinValidator) {
     info!("  Serial Number: {}", serial_number);
     info!("  Software Revision: {}", software_revision);
     info!("  Coin Ids: {:#?}", coin_ids);
-    info!("  Coin Sorter Paths: {:#?}", coin_sorter_paths);
+    if sorter.is_some() {
+        info!("  Coin Sorter Paths: {:#?}", coin_sorter_paths);
+    } else {
+        info!("  Sorter: not fitted");
+    }
     info!(
         "  Polling Priority: {:?}",
         polling_priority.as_duration().unwrap_or(Duration::ZERO)
     );
+    Ok(())
 }
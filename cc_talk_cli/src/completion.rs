@@ -0,0 +1,140 @@
+//! Shell completion support.
+//!
+//! Static completion scripts are printed by the `completions` command, dynamic completion is
+//! enabled by sourcing the output of `COMPLETE=<shell> cc_talk_cli`. With dynamic completion
+//! device addresses are completed from the scenario file set in [`COMPLETION_SCENARIO_ENV`], and
+//! header names for the `raw` command.
+
+use std::{ffi::OsStr, path::PathBuf};
+
+use cc_talk_core::cc_talk::Header;
+use clap_complete::CompletionCandidate;
+
+use cc_talk_emulator::{IdentityScenario, Scenario};
+
+/// Environment variable holding the scenario file whose devices are completed.
+///
+/// It only drives completion, the scenario emulated by a command is set with `--mock`.
+pub const COMPLETION_SCENARIO_ENV: &str = "CCTALK_COMPLETION_SCENARIO";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown header '{0}', expected a header name or number")]
+pub struct UnknownHeader(String);
+
+/// Parses a header from its name, case insensitive, or its number.
+///
+/// # Errors
+///
+/// Errors if the value is neither a known header name nor a known header number.
+pub fn parse_header(value: &str) -> Result<Header, UnknownHeader> {
+    let value = value.trim();
+    if let Ok(number) = value.parse::<u8>() {
+        return Header::try_from(number).map_err(|_| UnknownHeader(value.to_string()));
+    }
+    headers()
+        .find(|header| format!("{header:?}").eq_ignore_ascii_case(value))
+        .ok_or_else(|| UnknownHeader(value.to_string()))
+}
+
+/// Completes every header name, with its number as help.
+#[must_use]
+pub fn header_candidates() -> Vec<CompletionCandidate> {
+    headers()
+        .map(|header| {
+            CompletionCandidate::new(format!("{header:?}"))
+                .help(Some((header as u8).to_string().into()))
+        })
+        .collect()
+}
+
+/// Completes the addresses of the hoppers of the scenario file.
+#[must_use]
+pub fn hopper_candidates() -> Vec<CompletionCandidate> {
+    scenario().map_or_else(Vec::new, |scenario| {
+        scenario
+            .hoppers
+            .iter()
+            .map(|hopper| device_candidate(hopper.address, hopper.name.as_ref(), &hopper.identity))
+            .collect()
+    })
+}
+
+/// Completes the addresses of the coin selectors of the scenario file.
+#[must_use]
+pub fn selector_candidates() -> Vec<CompletionCandidate> {
+    scenario().map_or_else(Vec::new, |scenario| {
+        scenario
+            .selectors
+            .iter()
+            .map(|selector| {
+                device_candidate(selector.address, selector.name.as_ref(), &selector.identity)
+            })
+            .collect()
+    })
+}
+
+/// Completes the addresses of the bill validators of the scenario file.
+#[must_use]
+pub fn validator_candidates() -> Vec<CompletionCandidate> {
+    scenario().map_or_else(Vec::new, |scenario| {
+        scenario
+            .validators
+            .iter()
+            .map(|validator| {
+                device_candidate(
+                    validator.address,
+                    validator.name.as_ref(),
+                    &validator.identity,
+                )
+            })
+            .collect()
+    })
+}
+
+/// Completes the addresses of every device of the scenario file.
+#[must_use]
+pub fn device_candidates() -> Vec<CompletionCandidate> {
+    let mut candidates = hopper_candidates();
+    candidates.extend(selector_candidates());
+    candidates.extend(validator_candidates());
+    candidates
+}
+
+fn headers() -> impl Iterator<Item = Header> {
+    (0..=u8::MAX).filter_map(|number| Header::try_from(number).ok())
+}
+
+fn scenario() -> Option<Scenario> {
+    let path = std::env::var_os(COMPLETION_SCENARIO_ENV).filter(|path| !OsStr::is_empty(path))?;
+    Scenario::load(&PathBuf::from(path)).ok()
+}
+
+fn device_candidate(
+    address: u8,
+    name: Option<&String>,
+    identity: &IdentityScenario,
+) -> CompletionCandidate {
+    let help = name.map_or_else(|| identity.describe(), Clone::clone);
+    CompletionCandidate::new(address.to_string()).help(Some(help.into()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn headers_are_parsed_by_name_or_number() {
+        assert_eq!(parse_header("SimplePoll"), Ok(Header::SimplePoll));
+        assert_eq!(parse_header("simplepoll"), Ok(Header::SimplePoll));
+        assert_eq!(parse_header("254"), Ok(Header::SimplePoll));
+        assert_eq!(
+            parse_header("NotAHeader"),
+            Err(UnknownHeader("NotAHeader".to_string()))
+        );
+        assert!(
+            header_candidates()
+                .iter()
+                .any(|candidate| candidate.get_value() == "RequestSerialNumber")
+        );
+    }
+}
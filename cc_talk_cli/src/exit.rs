@@ -0,0 +1,239 @@
+//! Exit codes and error output of the CLI.
+//!
+//! Every failure is classified so shell scripts can branch on the exit code, the codes are
+//! stable across releases:
+//!
+//! | Code | Class          | Cause                                                        |
+//! |------|----------------|--------------------------------------------------------------|
+//! | 0    |                | success                                                      |
+//! | 1    | `other`        | invalid scenario, file not written, ...                      |
+//! | 2    | `usage`        | invalid arguments, e.g. a coin the hopper does not dispense  |
+//! | 3    | `transport`    | socket unreachable, transport stopped, echo or queue failure |
+//! | 4    | `timeout`      | the device did not answer                                    |
+//! | 5    | `nak`          | the device answered with a NAK                               |
+//! | 6    | `parse`        | the reply is corrupted or can't be parsed                    |
+//! | 7    | `device_fault` | the device answered but reports a fault or stays busy        |
+//!
+//! With `--json` the error is printed on stderr as a single JSON object, e.g.
+//! `{"error":"nak","exit_code":5,"message":"...","address":3}`.
+
+use cc_talk_tokio_host::{device::base::CommandError, transport::tokio_transport::TransportError};
+use serde::Serialize;
+use thiserror::Error;
+
+/// Failure class of a [`CliError`], each class has its own exit code.
+///
+/// Classes are serialized with the names of the table above.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    Other,
+    Usage,
+    Transport,
+    Timeout,
+    Nak,
+    Parse,
+    DeviceFault,
+}
+
+impl FailureClass {
+    /// Returns the exit code of the process for this class.
+    #[must_use]
+    pub const fn exit_code(self) -> u8 {
+        match self {
+            Self::Other => 1,
+            Self::Usage => 2,
+            Self::Transport => 3,
+            Self::Timeout => 4,
+            Self::Nak => 5,
+            Self::Parse => 6,
+            Self::DeviceFault => 7,
+        }
+    }
+}
+
+impl From<&CommandError> for FailureClass {
+    fn from(error: &CommandError) -> Self {
+        match error {
+            CommandError::Timeout
+            | CommandError::MaxRetriesExceeded
+            | CommandError::Unresponsive { .. } => Self::Timeout,
+            CommandError::Nack | CommandError::Rejected { .. } => Self::Nak,
+            CommandError::SocketWriteError
+            | CommandError::SocketReadError
+            | CommandError::QueueFull
+            | CommandError::EchoMismatch
+            | CommandError::SendError
+            | CommandError::ReceiveError => Self::Transport,
+            CommandError::BufferOverflow
+            | CommandError::ResponseTooLong
+            | CommandError::ChecksumError
+            | CommandError::DataLengthMismatch(_, _)
+            | CommandError::InvalidHeader(_)
+            | CommandError::InvalidPacket
+            | CommandError::ParseError(_) => Self::Parse,
+            CommandError::Busy { .. } => Self::DeviceFault,
+            CommandError::PacketCreationError
+            | CommandError::WrongCoin { .. }
+            | CommandError::InvalidAddress(_)
+            | CommandError::AddressInUse(_)
+            | CommandError::InvalidAcceptLimit(_)
+            | CommandError::InvalidDataBlock(_)
+            | CommandError::InvalidDivert(_)
+            | CommandError::NotAllowed(_) => Self::Usage,
+        }
+    }
+}
+
+/// A failed command of the CLI.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message}")]
+pub struct CliError {
+    pub class: FailureClass,
+    pub message: String,
+    /// Address of the device the failure is about, if any.
+    pub address: Option<u8>,
+}
+
+/// Result of a command handler.
+pub type CliResult = Result<(), CliError>;
+
+impl CliError {
+    pub fn new(class: FailureClass, message: impl Into<String>) -> Self {
+        Self {
+            class,
+            message: message.into(),
+            address: None,
+        }
+    }
+
+    /// The device answered but reports a fault.
+    pub fn device_fault(message: impl Into<String>) -> Self {
+        Self::new(FailureClass::DeviceFault, message)
+    }
+
+    /// Classifies a failed command, `context` tells what the command was for.
+    #[must_use]
+    pub fn command(context: &str, error: &CommandError) -> Self {
+        let address = match error {
+            CommandError::Rejected { address, .. }
+            | CommandError::Busy { address, .. }
+            | CommandError::Unresponsive { address, .. }
+            | CommandError::WrongCoin { address, .. } => Some(*address),
+            _ => None,
+        };
+        Self {
+            class: error.into(),
+            message: format!("{context}: {error}"),
+            address,
+        }
+    }
+
+    /// Classifies a failed exchange of the transport.
+    #[must_use]
+    pub fn transport(context: &str, error: TransportError) -> Self {
+        Self::command(context, &CommandError::from(error))
+    }
+
+    /// Sets the address of the device the failure is about, unless already known.
+    #[must_use]
+    pub const fn at(mut self, address: u8) -> Self {
+        if self.address.is_none() {
+            self.address = Some(address);
+        }
+        self
+    }
+
+    #[must_use]
+    pub const fn exit_code(&self) -> u8 {
+        self.class.exit_code()
+    }
+
+    /// Renders the error as a single line JSON object.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let json = JsonError {
+            error: self.class,
+            exit_code: self.exit_code(),
+            message: &self.message,
+            address: self.address,
+        };
+        serde_json::to_string(&json).unwrap_or_default()
+    }
+}
+
+/// JSON output of a [`CliError`].
+#[derive(Serialize)]
+struct JsonError<'a> {
+    error: FailureClass,
+    exit_code: u8,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<u8>,
+}
+
+/// Classifies the failed commands of a handler.
+pub trait CommandContext<T> {
+    /// Turns the [`CommandError`] into a [`CliError`], `context` tells what the command was for.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the command failed.
+    fn context(self, context: &str) -> Result<T, CliError>;
+}
+
+impl<T> CommandContext<T> for Result<T, CommandError> {
+    fn context(self, context: &str) -> Result<T, CliError> {
+        self.map_err(|error| CliError::command(context, &error))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cc_talk_core::cc_talk::Header;
+    use cc_talk_tokio_host::device::nak::NakCause;
+
+    use super::*;
+
+    #[test]
+    fn failures_are_classified_with_stable_exit_codes() {
+        let timeout = CliError::command("unable to poll", &CommandError::Timeout).at(3);
+        assert_eq!(timeout.exit_code(), 4);
+        assert_eq!(timeout.message, "unable to poll: Timeout");
+
+        let nak = CliError::command(
+            "unable to dispense",
+            &CommandError::Rejected {
+                address: 4,
+                header: Header::DispenseHopperCoins,
+                cause: NakCause::UnsupportedHeader,
+            },
+        )
+        .at(3);
+        assert_eq!((nak.class, nak.address), (FailureClass::Nak, Some(4)));
+
+        assert_eq!(
+            CliError::transport("transport", TransportError::Closed).exit_code(),
+            3
+        );
+        assert_eq!(
+            CliError::command("reply", &CommandError::ChecksumError).exit_code(),
+            6
+        );
+        assert_eq!(CliError::device_fault("jammed").exit_code(), 7);
+    }
+
+    #[test]
+    fn errors_render_as_json() {
+        let error = CliError::device_fault("flags \"jam\"\n").at(3);
+        assert_eq!(
+            error.to_json(),
+            "{\"error\":\"device_fault\",\"exit_code\":7,\
+             \"message\":\"flags \\\"jam\\\"\\n\",\"address\":3}"
+        );
+        assert_eq!(
+            CliError::new(FailureClass::Other, "bad scenario").to_json(),
+            "{\"error\":\"other\",\"exit_code\":1,\"message\":\"bad scenario\"}"
+        );
+    }
+}
@@ -1,13 +1,18 @@
 use std::time::Duration;
 
-use cc_talk_core::cc_talk::{Category, ChecksumType, CurrencyToken, Device};
+use cc_talk_core::cc_talk::{Category, ChecksumType, CurrencyToken, Describe, Device};
 use cc_talk_tokio_host::{
     device::{base::DeviceCommon, payout::PayoutDevice},
     transport::tokio_transport::TransportMessage,
 };
 use clap::{Subcommand, ValueEnum};
 use tokio::sync::mpsc::Sender;
-use tracing::{error, info};
+use tracing::{info, warn};
+
+use crate::exit::{CliError, CliResult, CommandContext};
+
+/// Coin types read by `hopper info` on multi-coin hoppers.
+const MAX_COIN_TYPES: u8 = 8;
 
 #[derive(Subcommand, Debug)]
 pub enum HopperCommands {
@@ -36,11 +41,30 @@ pub enum HopperCommands {
         /// Interval between polls in milliseconds
         #[arg(short, long, default_value_t = 1000)]
         poll_interval: u64,
+
+        /// Coin expected in the hopper, e.g. EU200A, nothing is paid if it holds another one
+        #[arg(short, long, value_parser = parse_coin)]
+        coin: Option<CurrencyToken>,
     },
 
-    /// Retrieve hopper information
+    /// Retrieve hopper information, its coin and the coin values of multi-coin hoppers
     Info {},
 
+    /// Run the hopper self test and print the raised flags
+    Test {},
+
+    /// Print the hopper dispense status
+    Status {},
+
+    /// Print the hopper level sensors status
+    Levels {},
+
+    /// Print the coin type dispensed by the hopper
+    Coin {},
+
+    /// Print the total number of coins dispensed by the hopper
+    DispenseCount {},
+
     /// Adjust the hopper speed
     AdjustSpeed {
         #[arg(short, long, default_value_t = false, action = clap::ArgAction::SetTrue)]
@@ -51,6 +75,17 @@ pub enum HopperCommands {
         /// On the WHM 100.C hopper, valid values are 0 (30%) to 7 (100%)
         speed: u8,
     },
+
+    /// Print the hopper variable set, modifying the given flags first
+    Variables {
+        /// Pay coins one at a time
+        #[arg(long)]
+        single_coin_mode: Option<bool>,
+
+        /// Hand over the payout to another hopper when this one runs empty
+        #[arg(long)]
+        use_other_hopper: Option<bool>,
+    },
 }
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum PayoutType {
@@ -62,32 +97,59 @@ pub enum PayoutType {
     NoEncryption,
 }
 
-pub async fn handler(transport: Sender<TransportMessage>, address: u8, action: &HopperCommands) {
+/// Runs the hopper command.
+///
+/// # Errors
+///
+/// Errors if a command fails or the hopper reports a fault.
+pub async fn handler(
+    transport: Sender<TransportMessage>,
+    address: u8,
+    action: &HopperCommands,
+) -> CliResult {
     let hopper = PayoutDevice::new(
         Device::new(address, Category::Payout, ChecksumType::Crc8),
         transport,
     );
 
-    match action {
-        HopperCommands::Poll { repeat, infinite } => {
-            poll(hopper, *repeat, *infinite).await;
-        }
+    let result = match action {
+        HopperCommands::Poll { repeat, infinite } => poll(hopper, *repeat, *infinite).await,
         HopperCommands::Dispense {
             amount,
             repeat,
             payout_type,
             poll_interval,
+            coin,
         } => {
-            dispense_coins(hopper, *amount, *repeat, *payout_type, *poll_interval).await;
+            dispense_coins(
+                hopper,
+                *amount,
+                *repeat,
+                *payout_type,
+                *poll_interval,
+                coin.as_ref(),
+            )
+            .await
         }
         HopperCommands::Info {} => info(hopper).await,
+        HopperCommands::Test {} => test(hopper).await,
+        HopperCommands::Status {} => status(hopper).await,
+        HopperCommands::Levels {} => levels(hopper).await,
+        HopperCommands::Coin {} => coin(hopper).await,
+        HopperCommands::DispenseCount {} => dispense_count(hopper).await,
         HopperCommands::AdjustSpeed { temporary, speed } => {
-            adjust_speed(hopper, *temporary, *speed).await;
+            adjust_speed(hopper, *temporary, *speed).await
         }
-    }
+        HopperCommands::Variables {
+            single_coin_mode,
+            use_other_hopper,
+        } => variables(hopper, *single_coin_mode, *use_other_hopper).await,
+    };
+    result.map_err(|e| e.at(address))
 }
 
-async fn poll(hopper: PayoutDevice, repeat: u8, infinite: bool) {
+async fn poll(hopper: PayoutDevice, repeat: u8, infinite: bool) -> CliResult {
+    let mut failure = None;
     loop {
         for _ in 0..repeat {
             match hopper.simple_poll().await {
@@ -95,7 +157,8 @@ async fn poll(hopper: PayoutDevice, repeat: u8, infinite: bool) {
                     info!("simple_poll succeeded");
                 }
                 Err(e) => {
-                    error!("simple_poll failed: {}", e);
+                    warn!("simple_poll failed: {}", e);
+                    failure = Some(CliError::command("simple_poll failed", &e));
                 }
             }
         }
@@ -104,6 +167,7 @@ async fn poll(hopper: PayoutDevice, repeat: u8, infinite: bool) {
             break;
         }
     }
+    failure.map_or(Ok(()), Err)
 }
 
 async fn dispense_coins(
@@ -112,9 +176,16 @@ async fn dispense_coins(
     repeat: u8,
     payout_type: PayoutType,
     poll_interval: u64,
-) {
+    coin: Option<&CurrencyToken>,
+) -> CliResult {
     if repeat == 0 {
-        return;
+        return Ok(());
+    }
+    if let Some(coin) = coin {
+        hopper
+            .check_hopper_coin(coin)
+            .await
+            .context("Refusing to dispense")?;
     }
 
     for i in 0..repeat {
@@ -122,9 +193,10 @@ async fn dispense_coins(
             info!("Dispense iteration {}/{}", i + 1, repeat);
         }
 
-        hopper.enable_hopper().await.unwrap_or_else(|e| {
-            error!("Failed to enable hopper: {}", e);
-        });
+        hopper
+            .enable_hopper()
+            .await
+            .context("Failed to enable hopper")?;
 
         let result = match payout_type {
             PayoutType::Simple => hopper.payout(amount).await,
@@ -132,56 +204,61 @@ async fn dispense_coins(
             PayoutType::NoEncryption => hopper.payout_no_encryption(amount).await,
         };
 
-        match result {
-            Ok(Some(r)) => {
+        match result.context("Failed to dispense coins")? {
+            Some(r) => {
                 info!("Dispensing {}, response => {}", amount, r);
             }
-            Ok(None) => {
+            None => {
                 info!("Dispensing {}, no response", amount);
             }
-            Err(e) => {
-                error!("Failed to dispense coins: {}", e);
-                panic!("Aborting dispensing due to error");
-            }
         }
 
         let mut remaining = u8::MAX;
+        let mut unpaid = 0;
         while remaining > 0 {
             match hopper.get_payout_status().await {
                 Ok(status) => {
                     info!("{}", status);
                     remaining = status.coins_remaining;
+                    unpaid = status.unpaid;
                 }
                 Err(e) => {
-                    error!("Error getting payout status: {}", e);
+                    warn!("Error getting payout status: {}", e);
                 }
             }
             tokio::time::sleep(Duration::from_millis(poll_interval)).await;
         }
 
-        hopper.disable_hopper().await.unwrap_or_else(|e| {
-            error!("Failed to disable hopper: {}", e);
-        });
+        hopper
+            .disable_hopper()
+            .await
+            .context("Failed to disable hopper")?;
+        if unpaid > 0 {
+            return Err(CliError::device_fault(format!(
+                "Hopper stopped with {unpaid} coins unpaid"
+            )));
+        }
     }
+    Ok(())
 }
 
-async fn info(hopper: PayoutDevice) {
+async fn info(hopper: PayoutDevice) -> CliResult {
     let product_code = hopper
         .get_product_code()
         .await
-        .expect("should get product code");
+        .context("Failed to get product code")?;
     let manufacturer_id = hopper
         .get_manufacturer_id()
         .await
-        .expect("should get manufacturer id");
+        .context("Failed to get manufacturer id")?;
     let serial_number = hopper
         .get_serial_number()
         .await
-        .expect("should get serial number");
+        .context("Failed to get serial number")?;
     let software_revision = hopper
         .get_software_revision()
         .await
-        .expect("should get software revision");
+        .context("Failed to get software revision")?;
     let coin_type = hopper
         .get_hopper_coin()
         .await
@@ -194,19 +271,122 @@ async fn info(hopper: PayoutDevice) {
     info!("  Serial Number: {}", serial_number);
     info!("  Software Revision: {}", software_revision);
     info!("  Coin Type: {:?}", coin_type);
+    // Single coin hoppers NAK the request, multi-coin hoppers answer for each coin type.
+    for coin_type in 1..=MAX_COIN_TYPES {
+        let Ok((token, value)) = hopper.get_hopper_coin_value(coin_type).await else {
+            break;
+        };
+        info!("  Coin Type {}: {:?}, value {}", coin_type, token, value);
+    }
     info!("  Supports Speed Adjust: {}", supports_speed_adjust);
+    Ok(())
+}
+
+/// Parses a coin id such as `EU200A`.
+fn parse_coin(id: &str) -> Result<CurrencyToken, String> {
+    CurrencyToken::build(id).map_err(|error| format!("invalid coin '{id}': {error:?}"))
+}
+
+async fn test(hopper: PayoutDevice) -> CliResult {
+    let flags = hopper
+        .self_test()
+        .await
+        .context("Failed to run hopper self test")?;
+    if flags.is_empty() {
+        info!("Hopper self test: no flags raised");
+        return Ok(());
+    }
+    info!("Hopper self test: {} flag(s) raised", flags.len());
+    let last = flags.len() - 1;
+    for (i, flag) in flags.iter().enumerate() {
+        let branch = if i == last { "└─" } else { "├─" };
+        info!("{} {}", branch, flag.describe());
+    }
+    let descriptions: Vec<_> = flags.iter().map(Describe::describe).collect();
+    Err(CliError::device_fault(format!(
+        "Hopper self test raised: {}",
+        descriptions.join(", ")
+    )))
+}
+
+async fn status(hopper: PayoutDevice) -> CliResult {
+    let status = hopper
+        .get_payout_status()
+        .await
+        .context("Failed to get hopper status")?;
+    info!("{}", status);
+    Ok(())
+}
+
+async fn levels(hopper: PayoutDevice) -> CliResult {
+    let (_, status) = hopper
+        .get_sensor_status()
+        .await
+        .context("Failed to get hopper level sensors")?;
+    info!("{}", status);
+    Ok(())
+}
+
+async fn coin(hopper: PayoutDevice) -> CliResult {
+    match hopper
+        .get_hopper_coin()
+        .await
+        .context("Failed to get hopper coin")?
+    {
+        CurrencyToken::Token => info!("Hopper coin: Token"),
+        CurrencyToken::Currency(value) => info!(
+            "Hopper coin: {} {}",
+            value.monetary_value(),
+            value.country_code()
+        ),
+    }
+    Ok(())
+}
+
+async fn dispense_count(hopper: PayoutDevice) -> CliResult {
+    let count = hopper
+        .get_dispense_count()
+        .await
+        .context("Failed to get hopper dispense count")?;
+    info!("Hopper dispense count: {} coins", count);
+    Ok(())
+}
+
+async fn adjust_speed(hopper: PayoutDevice, temporary: bool, speed: u8) -> CliResult {
+    hopper
+        .whm_100_speed_adjust(!temporary, speed)
+        .await
+        .context("Failed to adjust hopper speed")?;
+    info!(
+        "Hopper speed adjusted to {} (temporary: {})",
+        speed, temporary
+    );
+    Ok(())
 }
 
-async fn adjust_speed(hopper: PayoutDevice, temporary: bool, speed: u8) {
-    match hopper.whm_100_speed_adjust(!temporary, speed).await {
-        Ok(()) => {
-            info!(
-                "Hopper speed adjusted to {} (temporary: {})",
-                speed, temporary
-            );
+async fn variables(
+    hopper: PayoutDevice,
+    single_coin_mode: Option<bool>,
+    use_other_hopper: Option<bool>,
+) -> CliResult {
+    let mut variables = hopper
+        .get_variables()
+        .await
+        .context("Failed to get hopper variables")?;
+
+    if single_coin_mode.is_some() || use_other_hopper.is_some() {
+        if let Some(enabled) = single_coin_mode {
+            variables = variables.with_single_coin_mode(enabled);
         }
-        Err(e) => {
-            error!("Failed to adjust hopper speed: {}", e);
+        if let Some(enabled) = use_other_hopper {
+            variables = variables.with_use_other_hopper(enabled);
         }
+        hopper
+            .set_variables(variables)
+            .await
+            .context("Failed to modify hopper variables")?;
     }
+
+    info!("{}", variables);
+    Ok(())
 }
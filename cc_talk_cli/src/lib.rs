@@ -1,12 +1,29 @@
+use std::path::PathBuf;
+
+use cc_talk_core::cc_talk::{BusAddress, BusAddressError, Header};
 use clap::{Parser, Subcommand};
+use clap_complete::{ArgValueCandidates, Shell};
 
-use crate::hopper::HopperCommands;
+use crate::{
+    completion::{
+        device_candidates, header_candidates, hopper_candidates, parse_header, selector_candidates,
+    },
+    hopper::HopperCommands,
+    storage::StorageCommands,
+};
 
+pub mod changer;
 pub mod coinselector;
+pub mod completion;
+pub mod exit;
 pub mod hopper;
+pub mod raw;
+pub mod stats;
+pub mod storage;
+pub mod topology;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = EXIT_CODES)]
 pub struct Cli {
     /// Unix domain socket path to connect to the ccTalk bus
     #[arg(short, long, default_value = "/tmp/cctalk.sock")]
@@ -20,23 +37,151 @@ pub struct Cli {
     #[arg(short, long, default_value_t = false, action = clap::ArgAction::SetTrue)]
     pub no_echo: bool,
 
+    /// Emulates the devices described in the scenario file instead of connecting to the socket
+    #[arg(short, long, value_name = "SCENARIO.TOML")]
+    pub mock: Option<PathBuf>,
+
+    /// Serves the controls of the emulated devices on this socket, e.g. `insert_coin 2 5`
+    #[arg(long, value_name = "SOCKET", requires = "mock")]
+    pub mock_control: Option<PathBuf>,
+
+    /// Keeps the last raw frames of the bus and appends them to this file on transport errors
+    #[arg(long, value_name = "FILE")]
+    pub frame_log: Option<PathBuf>,
+
+    /// Prints no logs, only the error if the command fails
+    #[arg(short, long, default_value_t = false, action = clap::ArgAction::SetTrue)]
+    pub quiet: bool,
+
+    /// Prints the error as a JSON object on stderr, see the exit codes
+    #[arg(long, default_value_t = false, action = clap::ArgAction::SetTrue)]
+    pub json: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// Exit codes listed in the help, see [`exit`].
+const EXIT_CODES: &str = "Exit codes:
+  0  success
+  1  other failure, e.g. invalid scenario or file not written
+  2  invalid arguments
+  3  transport failure, e.g. socket unreachable
+  4  device timeout
+  5  device NAKed the command
+  6  corrupted or unparsable reply
+  7  device fault, e.g. self test flags, unpaid coins or a changer error";
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Hopper {
-        address: u8,
+        /// Peripheral address of the hopper, usually 3
+        #[arg(value_parser = parse_peripheral_address, add = ArgValueCandidates::new(hopper_candidates))]
+        address: BusAddress,
 
         #[command(subcommand)]
         action: HopperCommands,
     },
 
     Selector {
-        address: u8,
+        /// Peripheral address of the coin selector, usually 2
+        #[arg(value_parser = parse_peripheral_address, add = ArgValueCandidates::new(selector_candidates))]
+        address: BusAddress,
 
         #[command(subcommand)]
         action: coinselector::CoinSelectorCommands,
     },
+
+    /// Exercises a changer: payouts, hopper balances, purges and error reporting
+    Changer {
+        /// Peripheral address of the changer
+        #[arg(value_parser = parse_peripheral_address, add = ArgValueCandidates::new(device_candidates))]
+        address: BusAddress,
+
+        #[command(subcommand)]
+        action: changer::ChangerCommands,
+    },
+
+    /// Inspects the data storage of a device
+    Storage {
+        /// Peripheral address of the device
+        #[arg(value_parser = parse_peripheral_address, add = ArgValueCandidates::new(device_candidates))]
+        address: BusAddress,
+
+        #[command(subcommand)]
+        action: StorageCommands,
+    },
+
+    /// Sends a single command and prints the reply
+    Raw {
+        /// Peripheral address of the device
+        #[arg(value_parser = parse_peripheral_address, add = ArgValueCandidates::new(device_candidates))]
+        address: BusAddress,
+
+        /// Header name, e.g. `RequestSerialNumber`, or number
+        #[arg(value_parser = parse_header, add = ArgValueCandidates::new(header_candidates))]
+        header: Header,
+
+        /// Data bytes, decimal or `0x` prefixed hexadecimal
+        #[arg(value_parser = parse_byte)]
+        data: Vec<u8>,
+    },
+
+    /// Polls a device and prints the transport statistics
+    Stats {
+        /// Peripheral address of the device
+        #[arg(value_parser = parse_peripheral_address, add = ArgValueCandidates::new(device_candidates))]
+        address: BusAddress,
+
+        /// Number of simple polls to send
+        #[arg(short, long, default_value_t = 100)]
+        count: u32,
+    },
+
+    /// Enumerates the bus and exports its topology as JSON and as a `dot` graph
+    ///
+    /// The JSON document is printed when neither `--json` nor `--dot` is given.
+    Topology {
+        /// Address of a device to enumerate, the bus is discovered with an address poll if none
+        /// is given
+        #[arg(short, long, value_parser = parse_peripheral_address)]
+        address: Vec<BusAddress>,
+
+        /// Writes the topology as JSON to this file
+        #[arg(long, value_name = "FILE")]
+        json: Option<PathBuf>,
+
+        /// Writes the topology as a `dot` graph to this file
+        #[arg(long, value_name = "FILE")]
+        dot: Option<PathBuf>,
+    },
+
+    /// Prints the completion script for a shell
+    ///
+    /// For completion of device addresses and header names use the dynamic completion
+    /// instead, e.g. `source <(COMPLETE=bash cc_talk_cli)`.
+    Completions { shell: Shell },
+}
+
+/// Parses a byte written in decimal or in `0x` prefixed hexadecimal.
+///
+/// # Errors
+///
+/// Errors if the value is not a byte.
+pub fn parse_byte(value: &str) -> Result<u8, std::num::ParseIntError> {
+    let value = value.trim();
+    value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .map_or_else(|| value.parse(), |hex| u8::from_str_radix(hex, 16))
+}
+
+/// Parses a device address, rejecting the broadcast and host addresses.
+///
+/// # Errors
+///
+/// Errors if the value is not a number or is a reserved address.
+pub fn parse_peripheral_address(value: &str) -> Result<BusAddress, BusAddressError> {
+    let address: BusAddress = value.parse()?;
+    BusAddress::peripheral(address.get())
 }
@@ -1,18 +1,35 @@
-use std::time::Duration;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use cc_talk_cli::{
     Cli,
-    Commands::{Hopper, Selector},
-    coinselector, hopper,
+    Commands::{Changer, Completions, Hopper, Raw, Selector, Stats, Storage, Topology},
+    changer, coinselector,
+    exit::{CliError, FailureClass},
+    hopper, raw, stats, storage, topology,
+};
+use cc_talk_emulator::{MockBus, Scenario};
+use cc_talk_tokio_host::transport::{
+    frame_log::FrameLog, retry::RetryConfig, tokio_transport::CcTalkTokioTransport,
 };
-use cc_talk_tokio_host::transport::{retry::RetryConfig, tokio_transport::CcTalkTokioTransport};
-use clap::Parser;
-use tokio::sync::mpsc;
-use tracing::info;
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
+use tokio::{net::UnixListener, sync::mpsc, task::JoinHandle};
+use tracing::{error, info, level_filters::LevelFilter};
 
 #[tokio::main]
 async fn main() {
+    CompleteEnv::with_factory(Cli::command).complete();
+
+    let cli = Cli::parse();
     let subscriber = tracing_subscriber::fmt()
+        .with_max_level(if cli.quiet {
+            LevelFilter::OFF
+        } else {
+            LevelFilter::INFO
+        })
         .pretty()
         .with_file(false)
         .with_line_number(false)
@@ -22,22 +39,53 @@ async fn main() {
         .finish();
     tracing::subscriber::set_global_default(subscriber).expect("tracing subscriber should work");
 
-    let cli = Cli::parse();
+    if let Completions { shell } = cli.command {
+        clap_complete::generate(
+            shell,
+            &mut Cli::command(),
+            env!("CARGO_BIN_NAME"),
+            &mut std::io::stdout(),
+        );
+        return;
+    }
     let timeout = Duration::from_millis(cli.timeout);
 
+    let mock = match cli
+        .mock
+        .as_ref()
+        .map(|scenario_path| start_mock(scenario_path, cli.mock_control.as_deref(), !cli.no_echo))
+        .transpose()
+    {
+        Ok(mock) => mock,
+        Err(e) => exit_with(&e, cli.json),
+    };
+    let sock = mock.as_ref().map_or_else(
+        || cli.sock.clone(),
+        |(_, mock_sock)| mock_sock.to_string_lossy().to_string(),
+    );
+
     let (tx, rx) = mpsc::channel(8);
-    let transport = CcTalkTokioTransport::new(
+    let mut transport = CcTalkTokioTransport::new(
         rx,
-        cli.sock.clone(),
+        sock.clone(),
         timeout,
         timeout,
         RetryConfig::default(),
         !cli.no_echo,
     );
+    let frame_log = cli
+        .frame_log
+        .as_ref()
+        .map(|path| FrameLog::default().with_dump_file(path));
+    if let Some(frame_log) = &frame_log {
+        transport = transport.with_frame_log(frame_log.clone());
+    }
+
+    let transport_stats = transport.stats();
 
     info!(
         "Transport initialized using sock: '{}' with {}ms timeout and echo support '{}'",
-        cli.sock, cli.timeout, !cli.no_echo
+        sock, cli.timeout, !cli.no_echo
     );
 
     let handle = tokio::spawn(async move {
@@ -46,12 +94,93 @@ async fn main() {
         }
     });
     tokio::time::sleep(timeout).await;
-    {
-        match &cli.command {
-            Hopper { address, action } => hopper::handler(tx, *address, action).await,
-            Selector { address, action } => coinselector::handler(tx, *address, action).await,
-        }
+    let result = {
+        let result = match &cli.command {
+            Hopper { address, action } => hopper::handler(tx, address.get(), action).await,
+            Selector { address, action } => coinselector::handler(tx, address.get(), action).await,
+            Changer { address, action } => changer::handler(tx, address.get(), action).await,
+            Storage { address, action } => storage::handler(tx, address.get(), action).await,
+            Raw {
+                address,
+                header,
+                data,
+            } => raw::handler(tx, address.get(), *header, data).await,
+            Stats { address, count } => {
+                stats::handler(tx, &transport_stats, address.get(), *count).await
+            }
+            Topology { address, json, dot } => {
+                topology::handler(tx, address, json.as_deref(), dot.as_deref()).await
+            }
+            Completions { .. } => unreachable!("completions are printed before connecting"),
+        };
         handle.abort();
-    }
+        result
+    };
     tokio::time::sleep(Duration::from_millis(100)).await;
+    if let Some(frame_log) = &frame_log {
+        frame_log.flush().await;
+    }
+    if let Some((bus, mock_sock)) = mock {
+        bus.abort();
+        let _ = std::fs::remove_file(mock_sock);
+    }
+    if let Err(e) = result {
+        exit_with(&e, cli.json);
+    }
+}
+
+/// Prints the error and exits with the code of its class.
+fn exit_with(error: &CliError, json: bool) -> ! {
+    if json {
+        eprintln!("{}", error.to_json());
+    } else if tracing::enabled!(tracing::Level::ERROR) {
+        error!("{}", error);
+    } else {
+        eprintln!("error: {error}");
+    }
+    std::process::exit(i32::from(error.exit_code()));
+}
+
+/// Spawns the emulated bus described by the scenario.
+///
+/// The controls of the devices are served on `control_path` when given.
+fn start_mock(
+    scenario_path: &Path,
+    control_path: Option<&Path>,
+    echo: bool,
+) -> Result<(JoinHandle<()>, PathBuf), CliError> {
+    let bus = Scenario::load(scenario_path)
+        .and_then(|scenario| MockBus::new(&scenario, echo))
+        .map_err(|e| {
+            CliError::new(
+                FailureClass::Other,
+                format!(
+                    "Unable to load mock scenario {}: {e}",
+                    scenario_path.display()
+                ),
+            )
+        })?;
+
+    let mock_sock = std::env::temp_dir().join(format!("cctalk-mock-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&mock_sock);
+    let listener = UnixListener::bind(&mock_sock).expect("should bind the mock socket");
+    info!("Emulating the bus described in {:?}", scenario_path);
+
+    let control = control_path.map(|control_path| {
+        let _ = std::fs::remove_file(control_path);
+        let listener = UnixListener::bind(control_path).expect("should bind the control socket");
+        info!("Serving the mock controls on {:?}", control_path);
+        bus.control().serve(listener)
+    });
+    let bus = bus.serve(listener);
+    let task = tokio::spawn(async move {
+        match control {
+            Some(control) => {
+                tokio::join!(bus, control);
+            }
+            None => bus.await,
+        }
+    });
+
+    Ok((task, mock_sock))
 }
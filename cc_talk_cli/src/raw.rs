@@ -0,0 +1,81 @@
+use cc_talk_core::cc_talk::{Category, ChecksumType, Header, Packet};
+use cc_talk_tokio_host::{
+    device::nak::NakCause,
+    transport::{
+        correlation::CorrelationId,
+        timestamp::ReceptionSlot,
+        tokio_transport::{TransportError, TransportMessage},
+    },
+};
+use tokio::sync::{mpsc::Sender, oneshot};
+use tracing::info;
+
+use crate::exit::{CliError, CliResult, FailureClass};
+
+/// Sends a single command and prints the reply as is.
+///
+/// # Errors
+///
+/// Errors if the exchange fails or the reply is invalid.
+pub async fn handler(
+    transport: Sender<TransportMessage>,
+    address: u8,
+    header: Header,
+    data: &[u8],
+) -> CliResult {
+    let (respond_to, response) = oneshot::channel();
+    let message = TransportMessage {
+        correlation_id: CorrelationId::next(),
+        address,
+        checksum_type: ChecksumType::Crc8,
+        header,
+        data: data.to_vec(),
+        respond_to,
+        received_at: ReceptionSlot::default(),
+    };
+    info!(
+        "sending {:?} ({}) with data {:02X?} as message {}",
+        header, header as u8, data, message.correlation_id
+    );
+    if transport.send(message).await.is_err() {
+        return Err(CliError::new(
+            FailureClass::Transport,
+            "transport is not running",
+        ));
+    }
+
+    match response.await {
+        Ok(Ok(reply)) => match Packet::parse(&reply, ChecksumType::Crc8) {
+            Ok(packet) => {
+                info!(
+                    "reply {}\n{}",
+                    Packet::new(packet.as_slice()).display(),
+                    Packet::new(packet.as_slice()).hexdump()
+                );
+                Ok(())
+            }
+            Err(e) => Err(CliError::new(
+                FailureClass::Parse,
+                format!(
+                    "invalid reply {}: {}\n{}",
+                    Packet::new(reply.as_slice()).display(),
+                    e,
+                    Packet::new(reply.as_slice()).hexdump()
+                ),
+            )),
+        },
+        Ok(Err(TransportError::Nack)) => Err(CliError::new(
+            FailureClass::Nak,
+            format!(
+                "command NAKed, {}",
+                NakCause::diagnose(header, !data.is_empty(), &Category::Unknown)
+            ),
+        )),
+        Ok(Err(e)) => Err(CliError::transport("command failed", e)),
+        Err(_) => Err(CliError::new(
+            FailureClass::Transport,
+            "transport dropped the command",
+        )),
+    }
+    .map_err(|e| e.at(address))
+}
@@ -0,0 +1,86 @@
+use cc_talk_core::cc_talk::{ChecksumType, Header};
+use cc_talk_tokio_host::transport::{
+    correlation::CorrelationId, stats::TransportStats, timestamp::ReceptionSlot,
+    tokio_transport::TransportMessage,
+};
+use tokio::sync::{mpsc::Sender, oneshot};
+use tracing::{info, warn};
+
+use crate::exit::{CliError, CliResult, FailureClass};
+
+/// Sends `count` simple polls to the device and prints the transport counters.
+///
+/// # Errors
+///
+/// Errors if the transport stops, failed polls are only counted.
+pub async fn handler(
+    transport: Sender<TransportMessage>,
+    stats: &TransportStats,
+    address: u8,
+    count: u32,
+) -> CliResult {
+    stats.reset();
+    let mut failures = 0u32;
+    for _ in 0..count {
+        let (respond_to, response) = oneshot::channel();
+        let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::SimplePoll,
+            data: vec![],
+            respond_to,
+            received_at: ReceptionSlot::default(),
+        };
+        if transport.send(message).await.is_err() {
+            return Err(CliError::new(
+                FailureClass::Transport,
+                "transport is not running",
+            ));
+        }
+        match response.await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                warn!("simple_poll failed: {}", e);
+                failures += 1;
+            }
+            Err(_) => {
+                return Err(CliError::new(
+                    FailureClass::Transport,
+                    "transport dropped the command",
+                ));
+            }
+        }
+    }
+
+    let snapshot = stats.snapshot();
+    info!(
+        "{} polls to {}, {} failed, over {:?}",
+        count,
+        address,
+        failures,
+        snapshot.since.elapsed()
+    );
+    info!(
+        "frames tx: {}, frames rx: {}, unsolicited: {}",
+        snapshot.frames_tx, snapshot.frames_rx, snapshot.unsolicited_frames
+    );
+    info!(
+        "retries: {}, timeouts: {}, checksum failures: {}, NAKs: {}, busy replies: {}",
+        snapshot.retries,
+        snapshot.timeouts,
+        snapshot.checksum_failures,
+        snapshot.nacks,
+        snapshot.busy_replies
+    );
+    info!(
+        "max queue depth: {}, rejected: {}, dropped polls: {}",
+        snapshot.max_queue_depth, snapshot.rejected_messages, snapshot.dropped_polls
+    );
+    if let Some(latency) = snapshot.average_latency() {
+        info!("average latency: {:?}", latency);
+    } else {
+        info!("average latency: n/a");
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use cc_talk_core::cc_talk::{Category, ChecksumType, DataBlocks, Device};
+use cc_talk_tokio_host::{
+    device::base::DeviceCommon, transport::tokio_transport::TransportMessage,
+};
+use clap::Subcommand;
+use tokio::sync::mpsc::Sender;
+use tracing::info;
+
+use crate::exit::{CliResult, CommandContext};
+
+#[derive(Subcommand, Debug)]
+pub enum StorageCommands {
+    /// Prints the memory type and the readable and writable data blocks
+    Info {},
+}
+
+/// Any device, data storage is common to all categories.
+struct StorageDevice {
+    device: Device,
+    sender: Sender<TransportMessage>,
+}
+
+impl DeviceCommon for StorageDevice {
+    fn get_device(&self) -> &Device {
+        &self.device
+    }
+
+    fn get_sender(&self) -> &Sender<TransportMessage> {
+        &self.sender
+    }
+}
+
+/// Runs the data storage command.
+///
+/// # Errors
+///
+/// Errors if a command fails.
+pub async fn handler(
+    transport: Sender<TransportMessage>,
+    address: u8,
+    action: &StorageCommands,
+) -> CliResult {
+    let device = StorageDevice {
+        device: Device::new(address, Category::Unknown, ChecksumType::Crc8),
+        sender: transport,
+    };
+
+    match action {
+        StorageCommands::Info {} => info_storage(&device).await,
+    }
+    .map_err(|e| e.at(address))
+}
+
+async fn info_storage(device: &StorageDevice) -> CliResult {
+    let storage = device
+        .get_data_storage_availability()
+        .await
+        .context("unable to request the data storage availability")?;
+    info!("memory type: {:?}", storage.memory_type);
+    info!("read: {}", describe_blocks(storage.read));
+    info!("write: {}", describe_blocks(storage.write));
+    Ok(())
+}
+
+fn describe_blocks(blocks: Option<DataBlocks>) -> String {
+    blocks.map_or_else(
+        || "not available".to_string(),
+        |blocks| {
+            format!(
+                "{} blocks of {} bytes, {} bytes",
+                blocks.blocks,
+                blocks.bytes_per_block,
+                blocks.capacity()
+            )
+        },
+    )
+}
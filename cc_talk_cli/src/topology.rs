@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use cc_talk_core::cc_talk::{BusAddress, ChecksumType};
+use cc_talk_tokio_host::{
+    device::{enumeration::enumerate, manager::DeviceManager, topology::BusTopology},
+    transport::tokio_transport::TransportMessage,
+};
+use tokio::sync::mpsc::Sender;
+use tracing::{error, info};
+
+use crate::exit::{CliError, CliResult, CommandContext, FailureClass};
+
+/// Enumerates the bus and writes its topology, the JSON document is printed when no file is
+/// given.
+///
+/// Without addresses the devices are found with an MDCES `AddressPoll`.
+///
+/// # Errors
+///
+/// Errors if the address poll fails or a file can't be written.
+pub async fn handler(
+    transport: Sender<TransportMessage>,
+    addresses: &[BusAddress],
+    json: Option<&Path>,
+    dot: Option<&Path>,
+) -> CliResult {
+    let addresses: Vec<u8> = if addresses.is_empty() {
+        let mut manager = DeviceManager::new(transport.clone());
+        let devices = manager
+            .discover()
+            .await
+            .context("address poll failed, pass the addresses instead")?;
+        devices.iter().map(|device| device.address).collect()
+    } else {
+        addresses.iter().map(BusAddress::get).collect()
+    };
+
+    let enumeration = enumerate(&transport, addresses, ChecksumType::Crc8).await;
+    let topology = BusTopology::collect(&transport, &enumeration, ChecksumType::Crc8).await;
+    info!("{} devices enumerated", topology.devices.len());
+    for duplicate in &topology.duplicates {
+        error!(
+            "{:?} devices {:?} share the serial number {}",
+            duplicate.category, duplicate.addresses, duplicate.serial
+        );
+    }
+
+    let json_document = serde_json::to_string_pretty(&topology).map_err(|e| {
+        CliError::new(
+            FailureClass::Other,
+            format!("unable to serialize the topology: {e}"),
+        )
+    })?;
+    if json.is_none() && dot.is_none() {
+        println!("{json_document}");
+        return Ok(());
+    }
+    for (path, contents) in [(json, json_document + "\n"), (dot, topology.to_dot())] {
+        let Some(path) = path else {
+            continue;
+        };
+        std::fs::write(path, contents).map_err(|e| {
+            CliError::new(
+                FailureClass::Other,
+                format!("unable to write {}: {e}", path.display()),
+            )
+        })?;
+        info!("topology written to {:?}", path);
+    }
+    Ok(())
+}
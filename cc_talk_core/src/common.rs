@@ -1,6 +1,8 @@
+pub mod accept_limit;
 pub mod bill_event_types;
 pub mod bill_routing;
 pub mod bit_mask;
+pub mod bus_address;
 pub mod category;
 pub mod changer_device;
 pub mod changer_error;
@@ -14,14 +16,35 @@ pub mod coin_value_format;
 pub mod currency;
 pub mod data_storage;
 pub mod date;
+#[cfg(feature = "descriptions")]
+pub mod descriptions;
 pub mod device;
+pub mod echo_canceller;
+#[cfg(feature = "hopper-encryption")]
+pub mod encrypted_events;
+#[cfg(feature = "hopper-encryption")]
+pub mod encrypted_monetary_id;
+#[cfg(feature = "aes-encryption")]
+pub mod encrypted_product_id;
+pub mod encryption_session;
 pub mod escrow_status;
 pub mod fault_code;
+#[cfg(feature = "hopper-encryption")]
+pub mod hopper_encryption;
 pub mod hopper_flags;
 pub mod hopper_status;
+pub mod hopper_variables;
 pub mod lamp_control;
 pub mod manufacturers;
 pub mod option_flags;
+pub mod opto_voltage;
 pub mod packet;
+pub mod packet_builder;
+pub mod packet_display;
+pub mod packet_framer;
+#[cfg(feature = "hopper-encryption")]
+pub mod peripheral_encryption;
 pub mod power_option;
+pub mod protocol_encryption;
 pub mod teach_mode_status;
+pub mod wrapping_counter;
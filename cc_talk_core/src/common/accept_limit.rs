@@ -0,0 +1,81 @@
+/// Payload size of `SetAcceptLimit` supported by a coin acceptor.
+///
+/// The specification defines a single byte, some devices accept the limit as a 16-bit
+/// value sent LSB first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AcceptLimitFormat {
+    #[default]
+    SingleByte,
+    TwoBytes,
+}
+
+impl AcceptLimitFormat {
+    /// Number of payload bytes sent with `SetAcceptLimit`.
+    #[must_use]
+    pub const fn payload_len(self) -> usize {
+        match self {
+            Self::SingleByte => 1,
+            Self::TwoBytes => 2,
+        }
+    }
+
+    /// Largest accept limit the format can carry.
+    #[must_use]
+    pub const fn max(self) -> u16 {
+        match self {
+            Self::SingleByte => u8::MAX as u16,
+            Self::TwoBytes => u16::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[error("accept limit {limit} does not fit the {format:?} format of the device")]
+pub struct AcceptLimitError {
+    pub limit: u16,
+    pub format: AcceptLimitFormat,
+}
+
+/// Encodes the accept limit in the given format.
+///
+/// Returns the payload buffer and the number of bytes used, limits which do not fit the
+/// format are rejected instead of being truncated.
+///
+/// # Errors
+///
+/// Returns an [`AcceptLimitError`] if `limit` is larger than [`AcceptLimitFormat::max`].
+pub const fn encode_accept_limit(
+    limit: u16,
+    format: AcceptLimitFormat,
+) -> Result<([u8; 2], usize), AcceptLimitError> {
+    if limit > format.max() {
+        return Err(AcceptLimitError { limit, format });
+    }
+    Ok((limit.to_le_bytes(), format.payload_len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_range_checked_per_format() {
+        assert_eq!(
+            encode_accept_limit(255, AcceptLimitFormat::SingleByte),
+            Ok(([255, 0], 1))
+        );
+        assert_eq!(
+            encode_accept_limit(256, AcceptLimitFormat::SingleByte),
+            Err(AcceptLimitError {
+                limit: 256,
+                format: AcceptLimitFormat::SingleByte
+            })
+        );
+        assert_eq!(
+            encode_accept_limit(0x1234, AcceptLimitFormat::TwoBytes),
+            Ok(([0x34, 0x12], 2))
+        );
+    }
+}
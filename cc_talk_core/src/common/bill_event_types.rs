@@ -109,43 +109,43 @@ pub enum BillEventReason {
     UnknownBillTypeStacked = 21,
 }
 
-impl core::fmt::Display for BillEventReason {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+impl BillEventReason {
+    /// Returns the English description of the reason.
+    #[must_use]
+    pub const fn description(&self) -> &'static str {
         match self {
-            Self::MasterInhibitActive => write!(f, "Master inhibit active"),
-            Self::BillReturnedFromEscrow => write!(f, "Bill returned from escrow"),
-            Self::InvalidBillValidationFailed => {
-                write!(f, "Invalid bill validation failed")
-            }
-            Self::InvalidBillTransportFailed => {
-                write!(f, "Invalid bill transport failed")
-            }
-            Self::InhibitedBillViaSerial => write!(f, "Inhibited bill via serial"),
-            Self::InhibitedBillViaDipSwitch => {
-                write!(f, "Inhibited bill via dip switch")
-            }
-            Self::BillJammedInTrasport => write!(f, "Bill jammed in transport"),
-            Self::BillJammedInStacker => write!(f, "Bill jammed in stacker"),
-            Self::BillPulledBackwards => write!(f, "Bill pulled backwards"),
-            Self::BillTamper => write!(f, "Bill tamper detected"),
-            Self::StackerOk => write!(f, "Stacker ok"),
-            Self::StackerRemoved => write!(f, "Stacker removed"),
-            Self::StackerInserted => write!(f, "Stacker inserted"),
-            Self::StackerFaulty => write!(f, "Stacker faulty"),
-            Self::StackerFull => write!(f, "Stacker full"),
-            Self::StackerJammed => write!(f, "Stacker jammed"),
-            Self::BillJammedInTransportSafe => {
-                write!(f, "Bill jammed in transport safe")
-            }
-            Self::OptoFraudDetected => write!(f, "Opto fraud detected"),
-            Self::StringFraudDetected => write!(f, "String fraud detected"),
-            Self::AntiStringMechanismFaulty => write!(f, "Anti-string mechanism faulty"),
-            Self::BarCodeDetected => write!(f, "Bar code detected"),
-            Self::UnknownBillTypeStacked => write!(f, "Unknown bill type stacked"),
+            Self::MasterInhibitActive => "Master inhibit active",
+            Self::BillReturnedFromEscrow => "Bill returned from escrow",
+            Self::InvalidBillValidationFailed => "Invalid bill validation failed",
+            Self::InvalidBillTransportFailed => "Invalid bill transport failed",
+            Self::InhibitedBillViaSerial => "Inhibited bill via serial",
+            Self::InhibitedBillViaDipSwitch => "Inhibited bill via dip switch",
+            Self::BillJammedInTrasport => "Bill jammed in transport",
+            Self::BillJammedInStacker => "Bill jammed in stacker",
+            Self::BillPulledBackwards => "Bill pulled backwards",
+            Self::BillTamper => "Bill tamper detected",
+            Self::StackerOk => "Stacker ok",
+            Self::StackerRemoved => "Stacker removed",
+            Self::StackerInserted => "Stacker inserted",
+            Self::StackerFaulty => "Stacker faulty",
+            Self::StackerFull => "Stacker full",
+            Self::StackerJammed => "Stacker jammed",
+            Self::BillJammedInTransportSafe => "Bill jammed in transport safe",
+            Self::OptoFraudDetected => "Opto fraud detected",
+            Self::StringFraudDetected => "String fraud detected",
+            Self::AntiStringMechanismFaulty => "Anti-string mechanism faulty",
+            Self::BarCodeDetected => "Bar code detected",
+            Self::UnknownBillTypeStacked => "Unknown bill type stacked",
         }
     }
 }
 
+impl core::fmt::Display for BillEventReason {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
 const MAX_BILL_EVENT_SIZE: usize = 5;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -198,7 +198,7 @@ impl TryFrom<(&[u8], u8)> for BillValidatorPollResult {
         if received_event_counter == 0 {
             // No events, just a reset
             return Ok(Self {
-                event_counter,
+                event_counter: 0,
                 events: heapless::Vec::new(),
                 lost_events: 0,
             });
@@ -252,6 +252,11 @@ mod test {
             .expect("Failed to parse zero events");
         assert_eq!(result.event_counter, 0);
         assert!(result.is_empty());
+
+        let result =
+            BillValidatorPollResult::try_from((&buffer[..], 42)).expect("Failed to parse reset");
+        assert_eq!(result.event_counter, 0);
+        assert!(result.is_empty());
     }
 
     #[test]
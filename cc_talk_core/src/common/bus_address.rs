@@ -0,0 +1,192 @@
+use crate::cc_talk::{Address, Category};
+
+/// Role of an address on the ccTalk bus.
+///
+/// You can find the reference in the specification cctalk-part-1-v4-7.pdf section 7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusAddressKind {
+    /// Address 0, every peripheral on the bus receives the message.
+    Broadcast,
+    /// Address 1, reserved for the host (master) device.
+    Host,
+    /// Addresses 2 to 255, usable by peripherals.
+    Peripheral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusAddressError {
+    #[error("address {0} is reserved and cannot be used by a peripheral")]
+    Reserved(u8),
+    #[error("address is not a number between 0 and 255")]
+    Invalid,
+}
+
+/// A single ccTalk bus address.
+///
+/// Unlike a raw `u8` this knows which addresses are reserved by the protocol, use
+/// [`BusAddress::peripheral`] when the address must target a peripheral.
+///
+/// # Example
+///
+/// ```
+/// use cc_talk_core::cc_talk::{BusAddress, BusAddressKind};
+///
+/// assert_eq!(BusAddress::HOPPER.kind(), BusAddressKind::Peripheral);
+/// assert!(BusAddress::peripheral(1).is_err());
+/// assert_eq!("40".parse::<BusAddress>(), Ok(BusAddress::BILL_VALIDATOR));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BusAddress(u8);
+
+impl BusAddress {
+    pub const BROADCAST: Self = Self(0);
+    pub const HOST: Self = Self(1);
+
+    pub const COIN_ACCEPTOR: Self = Self(2);
+    pub const HOPPER: Self = Self(3);
+    pub const REEL: Self = Self(30);
+    pub const BILL_VALIDATOR: Self = Self(40);
+    pub const CARD_READER: Self = Self(50);
+    pub const CHANGER: Self = Self(55);
+    pub const DISPLAY: Self = Self(60);
+    pub const KEYPAD: Self = Self(70);
+    pub const DONGLE: Self = Self(80);
+    pub const METER: Self = Self(90);
+    pub const BOOTLOADER: Self = Self(99);
+    pub const POWER: Self = Self(100);
+    pub const PRINTER: Self = Self(110);
+    pub const RNG: Self = Self(120);
+    pub const HOPPER_SCALE: Self = Self(130);
+    pub const COIN_FEEDER: Self = Self(140);
+    pub const BILL_RECYCLER: Self = Self(150);
+    pub const ESCROW: Self = Self(160);
+    pub const DEBUG: Self = Self(240);
+
+    /// Creates an address without any validation.
+    #[must_use]
+    pub const fn new(address: u8) -> Self {
+        Self(address)
+    }
+
+    /// Creates an address which can be used by a peripheral.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the address is the broadcast or host address.
+    pub const fn peripheral(address: u8) -> Result<Self, BusAddressError> {
+        match address {
+            0 | 1 => Err(BusAddressError::Reserved(address)),
+            _ => Ok(Self(address)),
+        }
+    }
+
+    /// Returns the default address for a category, see [`Category::default_address`].
+    #[must_use]
+    pub const fn default_for(category: &Category) -> Self {
+        match category.default_address() {
+            Address::Single(address) | Address::SingleAndRange(address, _) => Self(address),
+        }
+    }
+
+    #[must_use]
+    pub const fn get(&self) -> u8 {
+        self.0
+    }
+
+    #[must_use]
+    pub const fn kind(&self) -> BusAddressKind {
+        match self.0 {
+            0 => BusAddressKind::Broadcast,
+            1 => BusAddressKind::Host,
+            _ => BusAddressKind::Peripheral,
+        }
+    }
+
+    #[must_use]
+    pub const fn is_peripheral(&self) -> bool {
+        matches!(self.kind(), BusAddressKind::Peripheral)
+    }
+}
+
+impl From<u8> for BusAddress {
+    fn from(address: u8) -> Self {
+        Self(address)
+    }
+}
+
+impl From<BusAddress> for u8 {
+    fn from(address: BusAddress) -> Self {
+        address.0
+    }
+}
+
+impl core::str::FromStr for BusAddress {
+    type Err = BusAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse::<u8>()
+            .map(Self)
+            .map_err(|_| BusAddressError::Invalid)
+    }
+}
+
+impl core::fmt::Display for BusAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.kind() {
+            BusAddressKind::Broadcast => write!(f, "{} (broadcast)", self.0),
+            BusAddressKind::Host => write!(f, "{} (host)", self.0),
+            BusAddressKind::Peripheral => write!(f, "{}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reserved_addresses_are_rejected_for_peripherals() {
+        assert_eq!(BusAddress::peripheral(0), Err(BusAddressError::Reserved(0)));
+        assert_eq!(BusAddress::peripheral(1), Err(BusAddressError::Reserved(1)));
+        assert_eq!(BusAddress::peripheral(2), Ok(BusAddress::COIN_ACCEPTOR));
+        assert_eq!(BusAddress::peripheral(255), Ok(BusAddress::new(255)));
+    }
+
+    #[test]
+    fn kind_matches_reserved_ranges() {
+        assert_eq!(BusAddress::BROADCAST.kind(), BusAddressKind::Broadcast);
+        assert_eq!(BusAddress::HOST.kind(), BusAddressKind::Host);
+        assert!(BusAddress::HOPPER.is_peripheral());
+    }
+
+    #[test]
+    fn default_for_category_uses_single_address() {
+        assert_eq!(
+            BusAddress::default_for(&Category::CoinAcceptor),
+            BusAddress::COIN_ACCEPTOR
+        );
+        assert_eq!(
+            BusAddress::default_for(&Category::Payout),
+            BusAddress::HOPPER
+        );
+        assert_eq!(
+            BusAddress::default_for(&Category::BillValidator),
+            BusAddress::BILL_VALIDATOR
+        );
+        assert_eq!(
+            BusAddress::default_for(&Category::Unknown),
+            BusAddress::BROADCAST
+        );
+    }
+
+    #[test]
+    fn parse_from_str() {
+        assert_eq!(" 3 ".parse::<BusAddress>(), Ok(BusAddress::HOPPER));
+        assert_eq!("256".parse::<BusAddress>(), Err(BusAddressError::Invalid));
+        assert_eq!("abc".parse::<BusAddress>(), Err(BusAddressError::Invalid));
+    }
+}
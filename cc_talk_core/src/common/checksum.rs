@@ -17,6 +17,115 @@ pub enum ChecksumType {
     Crc16,
 }
 
+/// Builds and verifies the checksum of ccTalk frames.
+///
+/// The simple checksum takes the last byte of the frame. The CRC-16 checksum takes the last byte
+/// and the source address byte, its least significant byte replacing the source address. A
+/// CRC-16 frame has no source address, it is implied by the transaction: the host for a
+/// request, the addressed device for its reply.
+///
+/// The frames given are complete up to their checksum byte, trailing bytes are ignored.
+pub trait ChecksumKind {
+    /// Writes the checksum of `frame`.
+    ///
+    /// # Errors
+    ///
+    /// [`ChecksumError::FrameTooShort`] if the frame is shorter than its length byte.
+    fn sign(&self, frame: &mut [u8]) -> Result<(), ChecksumError>;
+
+    /// Verifies the checksum of `frame`, returns the source address of the frame, `None` for
+    /// CRC-16 frames.
+    ///
+    /// # Errors
+    ///
+    /// - [`ChecksumError::FrameTooShort`] if the frame is shorter than its length byte.
+    /// - [`ChecksumError::Mismatch`] if the checksum does not match.
+    fn verify(&self, frame: &[u8]) -> Result<Option<u8>, ChecksumError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChecksumError {
+    #[error("the frame is shorter than its length byte")]
+    FrameTooShort,
+    #[error("checksum mismatch: expected {expected}, got {actual}")]
+    Mismatch { expected: u16, actual: u16 },
+}
+
+impl ChecksumType {
+    /// Returns the checksum type `frame` is valid with, the simple checksum is tried first.
+    ///
+    /// A corrupted frame may still match the other checksum type, use it to identify the
+    /// checksum of a device on a quiet bus rather than to validate frames.
+    ///
+    /// ```rust
+    /// use cc_talk_core::cc_talk::ChecksumType;
+    ///
+    /// assert_eq!(ChecksumType::detect(&[1, 0, 2, 0, 253]), Some(ChecksumType::Crc8));
+    /// assert_eq!(ChecksumType::detect(&[40, 0, 0x46, 1, 0x3F]), Some(ChecksumType::Crc16));
+    /// assert_eq!(ChecksumType::detect(&[1, 0, 2, 0, 254]), None);
+    /// ```
+    #[must_use]
+    pub fn detect(frame: &[u8]) -> Option<Self> {
+        [Self::Crc8, Self::Crc16]
+            .into_iter()
+            .find(|checksum_type| checksum_type.verify(frame).is_ok())
+    }
+}
+
+impl ChecksumKind for ChecksumType {
+    #[allow(clippy::cast_possible_truncation)]
+    fn sign(&self, frame: &mut [u8]) -> Result<(), ChecksumError> {
+        let checksum_offset = checksum_offset(frame)?;
+        match self {
+            Self::Crc8 => frame[checksum_offset] = crc8(frame),
+            Self::Crc16 => {
+                let checksum = crc16(frame);
+                frame[SOURCE_OFFSET] = (checksum & 0xFF) as u8;
+                frame[checksum_offset] = (checksum >> 8) as u8;
+            }
+        }
+        Ok(())
+    }
+
+    fn verify(&self, frame: &[u8]) -> Result<Option<u8>, ChecksumError> {
+        let checksum_offset = checksum_offset(frame)?;
+        let checksum = frame[checksum_offset];
+        match self {
+            Self::Crc8 => {
+                let expected = crc8(frame);
+                if checksum != expected {
+                    return Err(ChecksumError::Mismatch {
+                        expected: u16::from(expected),
+                        actual: u16::from(checksum),
+                    });
+                }
+                Ok(Some(frame[SOURCE_OFFSET]))
+            }
+            Self::Crc16 => {
+                let expected = crc16(frame);
+                let actual = u16::from(checksum) << 8 | u16::from(frame[SOURCE_OFFSET]);
+                if actual != expected {
+                    return Err(ChecksumError::Mismatch { expected, actual });
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Returns the offset of the checksum byte, checking the frame holds it.
+fn checksum_offset(frame: &[u8]) -> Result<usize, ChecksumError> {
+    let length = frame
+        .get(DATA_LENGTH_OFFSET)
+        .ok_or(ChecksumError::FrameTooShort)?;
+    let checksum_offset = DATA_OFFSET + usize::from(*length);
+    if frame.len() <= checksum_offset {
+        return Err(ChecksumError::FrameTooShort);
+    }
+    Ok(checksum_offset)
+}
+
 /// Calculates the crc16 checksum for a ccTalk block.
 ///
 /// This function assumes a valid ccTalk block, which would be at least 4 bytes long, maximum 256
@@ -62,6 +171,21 @@ pub fn crc16(block: &[u8]) -> u16 {
     return crc16_lookup(block);
 }
 
+/// Calculates the crc16 checksum of raw bytes, used by the encrypted payloads carrying their own
+/// CRC.
+#[must_use]
+pub fn crc16_bytes(bytes: &[u8]) -> u16 {
+    #[cfg(not(feature = "crc-lookup"))]
+    return bytes
+        .iter()
+        .fold(0u16, |crc, &byte| crc16_compute_pass(crc, byte));
+
+    #[cfg(feature = "crc-lookup")]
+    return bytes
+        .iter()
+        .fold(0u16, |crc, &byte| crc16_lookup_pass(crc, byte));
+}
+
 fn crc16_compute(block: &[u8]) -> u16 {
     let data_end_offset = DATA_OFFSET + block[DATA_LENGTH_OFFSET] as usize;
     [
@@ -183,4 +307,33 @@ mod test {
         assert_eq!(crc16_lookup(&[40, 0, 0x3F, 1, 0x46]), 0x3F46);
         assert_eq!(crc16_lookup(&[1, 0, 0x37, 0, 0x30]), 0x3730);
     }
+
+    #[test]
+    fn frames_are_signed_and_verified() {
+        for checksum_type in [ChecksumType::Crc8, ChecksumType::Crc16] {
+            let mut frame = [40, 2, 1, 231, 0xFF, 0x01, 0];
+            checksum_type.sign(&mut frame).expect("should fit");
+            let source = match checksum_type {
+                ChecksumType::Crc8 => Some(1),
+                ChecksumType::Crc16 => None,
+            };
+            assert_eq!(checksum_type.verify(&frame), Ok(source));
+            assert_eq!(ChecksumType::detect(&frame), Some(checksum_type));
+
+            frame[4] = 0xFE;
+            assert!(matches!(
+                checksum_type.verify(&frame),
+                Err(ChecksumError::Mismatch { .. })
+            ));
+        }
+
+        assert_eq!(
+            ChecksumType::Crc8.verify(&[40, 2, 1, 231, 0xFF]),
+            Err(ChecksumError::FrameTooShort)
+        );
+        assert_eq!(
+            ChecksumType::Crc16.sign(&mut [40]),
+            Err(ChecksumError::FrameTooShort)
+        );
+    }
 }
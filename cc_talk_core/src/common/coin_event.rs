@@ -1,4 +1,4 @@
-use crate::cc_talk::CoinAcceptorError;
+use crate::cc_talk::{CoinAcceptorError, CoinType, CreditCodeFormat};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -23,6 +23,30 @@ pub struct CoinCredit {
     pub sorter_path: SorterPath,
 }
 
+impl CoinCredit {
+    /// Interprets the credit code (result A) according to the acceptor credit code format.
+    ///
+    /// Coin position acceptors report the coin position, which has to be looked up in the coin
+    /// id table. Coin value format acceptors report the coin value directly.
+    #[must_use]
+    pub fn credit_code(&self, format: CreditCodeFormat) -> CreditCode {
+        match format {
+            CreditCodeFormat::CoinPosition => CreditCode::Position(self.credit),
+            CreditCodeFormat::CoinValueFormat => CreditCode::Value(CoinType::from(self.credit)),
+        }
+    }
+}
+
+/// Credit code of a [`CoinCredit`], see [`CoinCredit::credit_code`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CreditCode {
+    /// Coin position, 1 to 16 on most acceptors.
+    Position(u8),
+    /// Coin value, decoded using the coin value format.
+    Value(CoinType),
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum CoinEvent {
@@ -153,6 +177,23 @@ impl TryFrom<(&[u8], u8)> for CoinAcceptorPollResult {
 mod test {
     use super::*;
 
+    #[test]
+    fn credit_code_follows_format() {
+        let credit = CoinCredit {
+            credit: 148,
+            sorter_path: SorterPath::Path(1),
+        };
+
+        assert_eq!(
+            credit.credit_code(CreditCodeFormat::CoinPosition),
+            CreditCode::Position(148)
+        );
+        assert_eq!(
+            credit.credit_code(CreditCodeFormat::CoinValueFormat),
+            CreditCode::Value(CoinType::Coin(200))
+        );
+    }
+
     #[test]
     fn parse_zero_events() {
         let buffer = [0u8];
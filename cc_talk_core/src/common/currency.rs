@@ -152,13 +152,30 @@ impl CurrencyToken {
 /// decimals, and value.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CurrencyValue {
-    country_code: heapless::String<2>,
+    /// ISO 3166 country code, or ISO 4217 currency code for encrypted monetary ids.
+    country_code: heapless::String<3>,
     factor: Factor,
     decimals: u8,
     value: u32, // Value in smallest currency units (cents, pence, etc.)
 }
 
 impl CurrencyValue {
+    /// Returns `None` if `country_code` is longer than 3 characters.
+    #[cfg(feature = "hopper-encryption")]
+    pub(crate) fn new(
+        country_code: &str,
+        factor: Factor,
+        decimals: u8,
+        value: u32,
+    ) -> Option<Self> {
+        Some(Self {
+            country_code: heapless::String::from_str(country_code).ok()?,
+            factor,
+            decimals,
+            value,
+        })
+    }
+
     /// Get the monetary value as a float
     #[cfg(feature = "std")]
     #[must_use]
@@ -189,6 +206,54 @@ impl CurrencyValue {
     }
 }
 
+impl CurrencyValue {
+    /// Returns the bill value scaled with the factor reported by the bill validator.
+    ///
+    /// Bill ids hold the value in major units, the device scaling factor gives the number of
+    /// smallest currency units per major unit. Only meaningful for bill ids, coin ids are not
+    /// scaled. Returns `None` if the country is not supported or the value overflows.
+    #[must_use]
+    pub fn scaled(&self, scaling: CountryScalingFactor) -> Option<Self> {
+        if !scaling.is_supported() {
+            return None;
+        }
+        let major_units = self.value / 10u32.checked_pow(u32::from(self.decimals))?;
+        Some(Self {
+            country_code: self.country_code.clone(),
+            factor: self.factor,
+            decimals: scaling.decimal_places,
+            value: major_units.checked_mul(u32::from(scaling.scaling_factor))?,
+        })
+    }
+}
+
+/// Scaling factor and decimal places of a country, as reported by a bill validator with
+/// `RequestCountryScalingFactor`.
+///
+/// You can find the reference in the specification cctalk-part-3-v4-7.pdf appendix 15.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CountryScalingFactor {
+    pub scaling_factor: u16,
+    pub decimal_places: u8,
+}
+
+impl CountryScalingFactor {
+    #[must_use]
+    pub const fn new(scaling_factor: u16, decimal_places: u8) -> Self {
+        Self {
+            scaling_factor,
+            decimal_places,
+        }
+    }
+
+    /// Devices reply with a zero scaling factor for countries they do not support.
+    #[must_use]
+    pub const fn is_supported(&self) -> bool {
+        self.scaling_factor != 0
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum CurrencyTokenError {
@@ -310,6 +375,28 @@ mod test {
         }
     }
 
+    #[test]
+    fn bill_value_is_scaled() {
+        let CurrencyToken::Currency(bill) =
+            CurrencyToken::build("EU0005A").expect("should build currency token")
+        else {
+            panic!("Expected currency");
+        };
+
+        let scaled = bill
+            .scaled(CountryScalingFactor::new(100, 2))
+            .expect("should scale");
+        assert_eq!(scaled.smallest_unit_value(), 500);
+
+        let scaled = bill
+            .scaled(CountryScalingFactor::new(1000, 3))
+            .expect("should scale");
+        assert_eq!(scaled.smallest_unit_value(), 5000);
+        assert_eq!(scaled.decimals(), 3);
+
+        assert_eq!(bill.scaled(CountryScalingFactor::new(0, 0)), None);
+    }
+
     #[test]
     fn test_token() {
         let result = CurrencyToken::build("TK001A").expect("should build currency token");
@@ -67,6 +67,133 @@ impl DataStorage {
     }
 }
 
+/// Blocks of a readable or writable data storage area.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DataBlocks {
+    /// Number of blocks, from 1 to 256.
+    pub blocks: u16,
+    /// Size of each block, never 0.
+    pub bytes_per_block: u8,
+}
+
+impl DataBlocks {
+    /// Resolves the raw block count and size, a block count of 0 means 256 blocks and a block
+    /// size of 0 means the area is not available.
+    #[must_use]
+    pub const fn from_raw(blocks: u8, bytes_per_block: u8) -> Option<Self> {
+        if bytes_per_block == 0 {
+            return None;
+        }
+        Some(Self {
+            blocks: if blocks == 0 { 256 } else { blocks as u16 },
+            bytes_per_block,
+        })
+    }
+
+    /// Total size of the area in bytes.
+    #[must_use]
+    pub const fn capacity(self) -> u32 {
+        self.blocks as u32 * self.bytes_per_block as u32
+    }
+
+    const fn check_block(self, block_number: u8) -> Result<u8, DataBlockError> {
+        if block_number as u16 >= self.blocks {
+            return Err(DataBlockError::BlockOutOfRange {
+                block_number,
+                blocks: self.blocks,
+            });
+        }
+        Ok(self.bytes_per_block)
+    }
+}
+
+/// Response to `RequestDataStorageAvailability` (header 216), with the special raw values
+/// resolved.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DataStorageAvailability {
+    pub memory_type: MemoryType,
+    /// `None` if the device cannot be read.
+    pub read: Option<DataBlocks>,
+    /// `None` if the device cannot be written.
+    pub write: Option<DataBlocks>,
+}
+
+impl DataStorageAvailability {
+    /// Checks that `block_number` can be read and returns the size of its block.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the device has no readable storage or fewer blocks.
+    pub const fn read_block_size(&self, block_number: u8) -> Result<u8, DataBlockError> {
+        match &self.read {
+            Some(read) => read.check_block(block_number),
+            None => Err(DataBlockError::Unavailable),
+        }
+    }
+
+    /// Checks that `len` bytes can be written to `block_number` and returns the size of its
+    /// block.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the device has no writable storage, fewer blocks or smaller blocks.
+    pub const fn write_block_size(
+        &self,
+        block_number: u8,
+        len: usize,
+    ) -> Result<u8, DataBlockError> {
+        let Some(write) = &self.write else {
+            return Err(DataBlockError::Unavailable);
+        };
+        let block_size = match write.check_block(block_number) {
+            Ok(block_size) => block_size,
+            Err(error) => return Err(error),
+        };
+        if len > block_size as usize {
+            return Err(DataBlockError::TooLarge { len, block_size });
+        }
+        Ok(block_size)
+    }
+}
+
+impl From<DataStorage> for DataStorageAvailability {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from(storage: DataStorage) -> Self {
+        Self {
+            memory_type: storage.memory_type,
+            read: DataBlocks::from_raw(storage.read_blocks as u8, storage.read_bytes_per_block),
+            write: DataBlocks::from_raw(storage.write_blocks as u8, storage.write_bytes_per_block),
+        }
+    }
+}
+
+impl TryFrom<[u8; 5]> for DataStorageAvailability {
+    type Error = MemoryTypeError;
+
+    fn try_from(bytes: [u8; 5]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            memory_type: MemoryType::try_from(bytes[0])?,
+            read: DataBlocks::from_raw(bytes[1], bytes[2]),
+            write: DataBlocks::from_raw(bytes[3], bytes[4]),
+        })
+    }
+}
+
+/// Data block access rejected before sending, see [`DataStorageAvailability::read_block_size`]
+/// and [`DataStorageAvailability::write_block_size`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataBlockError {
+    #[error("the device does not provide this kind of data storage")]
+    Unavailable,
+    #[error("block {block_number} is out of range, the device has {blocks} blocks")]
+    BlockOutOfRange { block_number: u8, blocks: u16 },
+    #[error("{len} bytes do not fit in a block of {block_size} bytes")]
+    TooLarge { len: usize, block_size: u8 },
+}
+
 impl From<DataStorage> for [u8; 5] {
     fn from(value: DataStorage) -> Self {
         value.as_bytes()
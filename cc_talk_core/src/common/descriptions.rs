@@ -0,0 +1,351 @@
+//! Human readable descriptions of device errors and events.
+//!
+//! Every description is available in English through [`Describe::describe`]. Other languages
+//! are added by implementing [`DescriptionTable`], entries missing from a table fall back to
+//! English.
+//!
+//! ```rust
+//! use cc_talk_core::cc_talk::{ChangerError, Describe, DescriptionTable};
+//!
+//! struct German;
+//!
+//! impl DescriptionTable for German {
+//!     fn changer_error(&self, error: ChangerError) -> Option<&'static str> {
+//!         match error {
+//!             ChangerError::CashboxFull => Some("Kassette voll - Kassette leeren"),
+//!             _ => None,
+//!         }
+//!     }
+//! }
+//!
+//! assert_eq!(ChangerError::CashboxFull.describe_with(&German), "Kassette voll - Kassette leeren");
+//! assert_eq!(ChangerError::HopperJam.describe_with(&German), "Hopper jam - remove hopper shelf and clear jam");
+//! ```
+
+use super::{
+    bill_event_types::{BillEvent, BillEventReason},
+    changer_error::ChangerError,
+    coin_acceptor_errors::CoinAcceptorError,
+    fault_code::FaultCode,
+    hopper_flags::HopperFlag,
+};
+
+/// Table of descriptions in one language.
+///
+/// Every method returns `None` by default, tables only need to provide the entries they
+/// translate.
+pub trait DescriptionTable {
+    fn fault_code(&self, code: FaultCode) -> Option<&'static str> {
+        let _ = code;
+        None
+    }
+
+    /// Describes `Credit` and `PendingCredit`, other events are described by their reason.
+    fn bill_event(&self, event: &BillEvent) -> Option<&'static str> {
+        let _ = event;
+        None
+    }
+
+    fn bill_event_reason(&self, reason: &BillEventReason) -> Option<&'static str> {
+        let _ = reason;
+        None
+    }
+
+    fn coin_acceptor_error(&self, error: CoinAcceptorError) -> Option<&'static str> {
+        let _ = error;
+        None
+    }
+
+    fn changer_error(&self, error: ChangerError) -> Option<&'static str> {
+        let _ = error;
+        None
+    }
+
+    fn hopper_flag(&self, flag: HopperFlag) -> Option<&'static str> {
+        let _ = flag;
+        None
+    }
+}
+
+/// English descriptions, the fallback of every other table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct English;
+
+impl DescriptionTable for English {
+    fn fault_code(&self, code: FaultCode) -> Option<&'static str> {
+        Some(english_fault_code(code))
+    }
+
+    fn bill_event(&self, event: &BillEvent) -> Option<&'static str> {
+        Some(english_bill_event(event))
+    }
+
+    fn bill_event_reason(&self, reason: &BillEventReason) -> Option<&'static str> {
+        Some(reason.description())
+    }
+
+    fn coin_acceptor_error(&self, error: CoinAcceptorError) -> Option<&'static str> {
+        Some(error.description())
+    }
+
+    fn changer_error(&self, error: ChangerError) -> Option<&'static str> {
+        Some(english_changer_error(error))
+    }
+
+    fn hopper_flag(&self, flag: HopperFlag) -> Option<&'static str> {
+        Some(english_hopper_flag(flag))
+    }
+}
+
+/// Types with a human readable description.
+pub trait Describe {
+    /// Returns the English description.
+    fn describe(&self) -> &'static str {
+        self.describe_with(&English)
+    }
+
+    /// Returns the description from `table`, or the English one if the table has no entry.
+    fn describe_with(&self, table: &dyn DescriptionTable) -> &'static str;
+}
+
+impl Describe for FaultCode {
+    fn describe_with(&self, table: &dyn DescriptionTable) -> &'static str {
+        table
+            .fault_code(*self)
+            .unwrap_or_else(|| english_fault_code(*self))
+    }
+}
+
+impl Describe for BillEvent {
+    fn describe_with(&self, table: &dyn DescriptionTable) -> &'static str {
+        if let Some(description) = table.bill_event(self) {
+            return description;
+        }
+        match self {
+            Self::Credit(_) | Self::PendingCredit(_) => english_bill_event(self),
+            Self::Reject(reason)
+            | Self::FraudAttempt(reason)
+            | Self::FatalError(reason)
+            | Self::Status(reason) => reason.describe_with(table),
+        }
+    }
+}
+
+impl Describe for BillEventReason {
+    fn describe_with(&self, table: &dyn DescriptionTable) -> &'static str {
+        table
+            .bill_event_reason(self)
+            .unwrap_or_else(|| self.description())
+    }
+}
+
+impl Describe for CoinAcceptorError {
+    fn describe_with(&self, table: &dyn DescriptionTable) -> &'static str {
+        table
+            .coin_acceptor_error(*self)
+            .unwrap_or_else(|| self.description())
+    }
+}
+
+impl Describe for ChangerError {
+    fn describe_with(&self, table: &dyn DescriptionTable) -> &'static str {
+        table
+            .changer_error(*self)
+            .unwrap_or_else(|| english_changer_error(*self))
+    }
+}
+
+impl Describe for HopperFlag {
+    fn describe_with(&self, table: &dyn DescriptionTable) -> &'static str {
+        table
+            .hopper_flag(*self)
+            .unwrap_or_else(|| english_hopper_flag(*self))
+    }
+}
+
+const fn english_fault_code(code: FaultCode) -> &'static str {
+    match code {
+        FaultCode::Ok => "No fault detected",
+        FaultCode::EepromChecksumCorrupted => "EEPROM checksum corrupted",
+        FaultCode::InductiveCoilsFault => "Fault on inductive coils",
+        FaultCode::CreditSensorFault => "Fault on credit sensor",
+        FaultCode::PiezoSensorFault => "Fault on piezo sensor",
+        FaultCode::ReflectiveSensorFault => "Fault on reflective sensor",
+        FaultCode::DiameterSensorFault => "Fault on diameter sensor",
+        FaultCode::WakeUpSensorFault => "Fault on wake-up sensor",
+        FaultCode::SorterExitSensorsFault => "Fault on sorter exit sensors",
+        FaultCode::NvramChecksumCorrupted => "NVRAM checksum corrupted",
+        FaultCode::CoinDispensingError => "Coin dispensing error",
+        FaultCode::LowLevelSensorError => "Low level sensor error",
+        FaultCode::HighLevelSensorError => "High level sensor error",
+        FaultCode::CoinCountingError => "Coin counting error",
+        FaultCode::KeypadError => "Keypad error",
+        FaultCode::ButtonError => "Button error",
+        FaultCode::DisplayError => "Display error",
+        FaultCode::CoinAuditingError => "Coin auditing error",
+        FaultCode::RejectSensorFault => "Fault on reject sensor",
+        FaultCode::CoinReturnMechanismFault => "Fault on coin return mechanism",
+        FaultCode::CosMechanismFault => "Fault on C.O.S. mechanism",
+        FaultCode::RimSensorFault => "Fault on rim sensor",
+        FaultCode::ThermistorFault => "Fault on thermistor",
+        FaultCode::PayoutMotorFault => "Payout motor fault",
+        FaultCode::PayoutTimeout => "Payout timeout",
+        FaultCode::PayoutJammed => "Payout jammed",
+        FaultCode::PayoutSensorFault => "Payout sensor fault",
+        FaultCode::LevelSensorError => "Level sensor error",
+        FaultCode::PersonalityModuleNotFitted => "Personality module not fitted",
+        FaultCode::PersonalityChecksumCorrupted => "Personality checksum corrupted",
+        FaultCode::RomChecksumMismatch => "ROM checksum mismatch",
+        FaultCode::MissingSlaveDevice => "Missing slave device",
+        FaultCode::InternalCommsBad => "Internal comms bad",
+        FaultCode::SupplyVoltageOutsideLimits => "Supply voltage outside operating limits",
+        FaultCode::TemperatureOutsideLimits => "Temperature outside operating limits",
+        FaultCode::DceFault => "D.C.E. fault",
+        FaultCode::BillValidationSensorFault => "Fault on bill validation sensor",
+        FaultCode::BillTransportMotorFault => "Fault on bill transport motor",
+        FaultCode::StackerFault => "Fault on stacker",
+        FaultCode::BillJammed => "Bill jammed",
+        FaultCode::RamTestFail => "RAM test fail",
+        FaultCode::StringSensorFault => "Fault on string sensor",
+        FaultCode::AcceptGateFailedOpen => "Accept gate failed open",
+        FaultCode::AcceptGateFailedClosed => "Accept gate failed closed",
+        FaultCode::StackerMissing => "Stacker missing",
+        FaultCode::StackerFull => "Stacker full",
+        FaultCode::FlashMemoryEraseFail => "Flash memory erase fail",
+        FaultCode::FlashMemoryWriteFail => "Flash memory write fail",
+        FaultCode::SlaveDeviceNotResponding => "Slave device not responding",
+        FaultCode::OptoSensorFault => "Fault on opto sensor",
+        FaultCode::BatteryFault => "Battery fault",
+        FaultCode::DoorOpen => "Door open",
+        FaultCode::MicroswitchFault => "Microswitch fault",
+        FaultCode::RtcFault => "RTC fault",
+        FaultCode::FirmwareError => "Firmware error",
+        FaultCode::InitialisationError => "Initialisation error",
+        FaultCode::SupplyCurrentOutsideLimits => "Supply current outside operating limits",
+        FaultCode::ForcedBootloaderMode => "Forced bootloader mode",
+        FaultCode::UnspecifiedFault => "Unspecified fault code",
+    }
+}
+
+const fn english_bill_event(event: &BillEvent) -> &'static str {
+    match event {
+        BillEvent::Credit(_) => "Bill accepted",
+        BillEvent::PendingCredit(_) => "Bill held in escrow",
+        BillEvent::Reject(reason)
+        | BillEvent::FraudAttempt(reason)
+        | BillEvent::FatalError(reason)
+        | BillEvent::Status(reason) => reason.description(),
+    }
+}
+
+const fn english_changer_error(error: ChangerError) -> &'static str {
+    match error {
+        ChangerError::HopperEmpty => "Hopper is empty - requires refill",
+        ChangerError::HopperJam => "Hopper jam - remove hopper shelf and clear jam",
+        ChangerError::HopperFraud => "Hopper fraud detected - alert security",
+        ChangerError::HopperFault => "Hopper fault - service callout required",
+        ChangerError::CoinAcceptorJam => "Coin acceptor jam - remove coin acceptor and clear jam",
+        ChangerError::CoinAcceptorFraudAttempt => "Coin acceptor fraud attempt - alert security",
+        ChangerError::CoinAcceptorFault => "Coin acceptor fault - service callout required",
+        ChangerError::CoinAcceptorToManifoldOptoFault => {
+            "Coin acceptor to manifold opto fault - check connector"
+        }
+        ChangerError::CashboxFull => "Cashbox is full - empty cashbox",
+        ChangerError::CashboxMissing => "Cashbox is missing - insert cashbox",
+        ChangerError::Other => "Other changer error",
+    }
+}
+
+const fn english_hopper_flag(flag: HopperFlag) -> &'static str {
+    match flag {
+        HopperFlag::AbsoluteMaximumCurrentExceeded => {
+            "Maximum current exceeded - possible jam, reset required"
+        }
+        HopperFlag::PayoutTimeoutOccurred => "Payout timed out - hopper may be empty",
+        HopperFlag::MotorReversedToClearJam => "Motor reversed to clear a jam",
+        HopperFlag::OptoFraudPathBlockedDuringIdle => {
+            "Exit opto blocked while idle - clear exit path, reset required"
+        }
+        HopperFlag::OptoFraudShortCircuitDuringIdle => {
+            "Exit opto short-circuit while idle - reset required"
+        }
+        HopperFlag::OptoBlockedPermanentlyDuringPayout => {
+            "Exit opto blocked during payout - clear exit path, reset required"
+        }
+        HopperFlag::PowerUpDetected => "Power up detected",
+        HopperFlag::PayoutDisabled => "Payout disabled - enable hopper",
+        HopperFlag::OptoFraudPathBlockedDuringPayout => {
+            "Exit opto short-circuit during payout - reset required"
+        }
+        HopperFlag::SingleCoinPayoutMode => "Single coin payout mode",
+        HopperFlag::UseOtherHopper => "Use other hopper",
+        HopperFlag::OptoFraudAttemptFinger => "Finger sensor fraud attempt - reset required",
+        HopperFlag::MotorReverseLimitReached => {
+            "Motor reverse limit reached - clear jam, reset required"
+        }
+        HopperFlag::InductiveCoilFault => "Inductive coil fault - reset required",
+        HopperFlag::NVMemoryChecksumError => "NV memory checksum error - counters may be wrong",
+        HopperFlag::PinNumberMechanism => "PIN number mechanism enabled",
+        HopperFlag::PowerDownDuringPayout => "Power down during payout - reset required",
+        HopperFlag::UnknownCoinTypePaid => "Unknown coin type paid - reset required",
+        HopperFlag::PinNumberIncorrect => "PIN number missing or incorrect - reset required",
+        HopperFlag::IncorrectCipherKey => "Incorrect cipher key - reset required",
+        HopperFlag::EncryptionEnabled => "Encryption enabled",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Partial;
+
+    impl DescriptionTable for Partial {
+        fn bill_event_reason(&self, reason: &BillEventReason) -> Option<&'static str> {
+            match reason {
+                BillEventReason::StackerFull => Some("Stapler voll"),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn english_descriptions() {
+        assert_eq!(FaultCode::StackerFull.describe(), "Stacker full");
+        assert_eq!(FaultCode::Ok.describe(), "No fault detected");
+        assert_eq!(
+            CoinAcceptorError::RejectCoin.describe(),
+            CoinAcceptorError::RejectCoin.description()
+        );
+        assert_eq!(BillEvent::Credit(3).describe(), "Bill accepted");
+        assert_eq!(
+            BillEvent::Reject(BillEventReason::InhibitedBillViaSerial).describe(),
+            "Inhibited bill via serial"
+        );
+        assert_eq!(
+            BillEventReason::StackerJammed.describe(),
+            std::format!("{}", BillEventReason::StackerJammed)
+        );
+        assert_eq!(
+            HopperFlag::PayoutDisabled.describe(),
+            "Payout disabled - enable hopper"
+        );
+    }
+
+    #[test]
+    fn tables_fall_back_to_english() {
+        assert_eq!(
+            BillEvent::Status(BillEventReason::StackerFull).describe_with(&Partial),
+            "Stapler voll"
+        );
+        assert_eq!(
+            BillEventReason::StackerRemoved.describe_with(&Partial),
+            "Stacker removed"
+        );
+        assert_eq!(
+            ChangerError::CashboxMissing.describe_with(&Partial),
+            "Cashbox is missing - insert cashbox"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::cc_talk::{Category, ChecksumType};
+use crate::cc_talk::{BnvKey, Category, ChecksumType};
 
 /// Represents a ccTalk Device
 /// This can be used to remove some boilerplate when sending packets
@@ -8,25 +8,37 @@ pub struct Device {
     address: u8,
     category: Category,
     checksum_type: ChecksumType,
-    encrypted: bool,
+    encryption_key: Option<BnvKey>,
 }
 
 impl Device {
     /// Creates a new device, this has no impact on the wire.
     ///
-    /// # Note
-    ///
-    /// Encryption is not implemented yet, so its set to false by default.
+    /// Frames are not encrypted, see [`Device::with_encryption_key`].
     #[must_use]
     pub const fn new(address: u8, category: Category, checksum_type: ChecksumType) -> Self {
         Self {
             address,
             category,
             checksum_type,
-            encrypted: false,
+            encryption_key: None,
         }
     }
 
+    /// Encrypts the frames sent to the device with its protocol encryption key.
+    #[must_use]
+    pub const fn with_encryption_key(mut self, key: BnvKey) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
+
+    /// Changes the checksum the frames exchanged with the device are built and verified with.
+    #[must_use]
+    pub const fn with_checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+        self.checksum_type = checksum_type;
+        self
+    }
+
     #[must_use]
     pub const fn address(&self) -> u8 {
         self.address
@@ -44,19 +56,59 @@ impl Device {
 
     #[must_use]
     pub const fn encrypted(&self) -> bool {
-        self.encrypted
+        self.encryption_key.is_some()
+    }
+
+    #[must_use]
+    pub const fn encryption_key(&self) -> Option<&BnvKey> {
+        self.encryption_key.as_ref()
     }
 }
 
 /// Represents the device serial number.
+///
+/// The ccTalk specification defines a 3 byte serial number, some devices extend it with a fourth
+/// most significant byte.
 #[derive(Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct SerialCode(u8, u8, u8);
+pub struct SerialCode(u8, u8, u8, Option<u8>);
 impl SerialCode {
     /// Creates a new serial code.
     #[must_use]
     pub const fn new(a: u8, b: u8, c: u8) -> Self {
-        Self(a, b, c)
+        Self(a, b, c, None)
+    }
+
+    /// Creates a new 4 byte serial code, `extension` being the most significant byte.
+    #[must_use]
+    pub const fn new_extended(extension: u8, a: u8, b: u8, c: u8) -> Self {
+        Self(a, b, c, Some(extension))
+    }
+
+    /// Creates a serial code from the bytes sent on the wire, byte 0 is LSB.
+    ///
+    /// Returns `None` unless 3 or 4 bytes are provided.
+    #[must_use]
+    pub const fn from_le_bytes(bytes: &[u8]) -> Option<Self> {
+        match *bytes {
+            [fix, minor, major] => Some(Self::new(major, minor, fix)),
+            [fix, minor, major, extension] => {
+                Some(Self::new_extended(extension, major, minor, fix))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the fourth, most significant, byte of an extended serial code.
+    #[must_use]
+    pub const fn extension(&self) -> Option<u8> {
+        self.3
+    }
+
+    /// Returns `true` if the serial code is 4 bytes long.
+    #[must_use]
+    pub const fn is_extended(&self) -> bool {
+        self.3.is_some()
     }
 
     /// Returns the first byte of the serial code.
@@ -88,19 +140,29 @@ impl SerialCode {
     /// Returns the serial number in decimal as specified by the ccTalk protocol.
     #[must_use]
     pub const fn as_number(&self) -> u32 {
-        self.fix() as u32 + (256 * (self.minor() as u32)) + (65536 * (self.major() as u32))
+        let extension = match self.3 {
+            Some(extension) => extension as u32,
+            None => 0,
+        };
+        self.fix() as u32
+            + (256 * (self.minor() as u32))
+            + (65536 * (self.major() as u32))
+            + (extension << 24)
     }
 }
 
 impl core::fmt::Display for SerialCode {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(extension) = self.3 {
+            write!(f, "{extension}.")?;
+        }
         write!(f, "{}.{}.{}", self.0, self.1, self.2)
     }
 }
 
 impl core::fmt::Debug for SerialCode {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+        core::fmt::Display::fmt(self, f)
     }
 }
 
@@ -114,6 +176,22 @@ mod test {
         assert_eq!(std::format!("{code}"), "1.2.3");
     }
 
+    #[test]
+    fn extended_serial_code() {
+        let code = SerialCode::from_le_bytes(&[4, 3, 2, 1]).expect("4 bytes are valid");
+        assert!(code.is_extended());
+        assert_eq!(code.extension(), Some(1));
+        assert_eq!(code.as_number(), 0x0102_0304);
+        assert_eq!(std::format!("{code}"), "1.2.3.4");
+
+        let code = SerialCode::from_le_bytes(&[3, 2, 1]).expect("3 bytes are valid");
+        assert!(!code.is_extended());
+        assert_eq!(code, SerialCode::new(1, 2, 3));
+
+        assert_eq!(SerialCode::from_le_bytes(&[1, 2]), None);
+        assert_eq!(SerialCode::from_le_bytes(&[1, 2, 3, 4, 5]), None);
+    }
+
     #[test]
     fn as_decimal() {
         let code = SerialCode::new(255, 255, 255);
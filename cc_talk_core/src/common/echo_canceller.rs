@@ -0,0 +1,144 @@
+use super::packet::MAX_BLOCK_LENGTH;
+
+/// Strips the echo of the transmitted frames from the received bytes.
+///
+/// On a single wire bus every byte written is read back before the reply, a byte differing from
+/// the one written means another device talked at the same time. Bytes received while no echo
+/// is pending are passed through.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let mut echo = EchoCanceller::new();
+/// echo.transmitted(&[2, 0, 1, 254, 255]);
+/// assert_eq!(echo.strip(&[2, 0, 1]), Ok(&[][..]));
+/// assert_eq!(echo.strip(&[254, 255, 1, 0, 2, 0, 253]), Ok(&[1, 0, 2, 0, 253][..]));
+///
+/// echo.transmitted(&[2, 0, 1, 254, 255]);
+/// assert!(echo.strip(&[2, 0, 3]).is_err());
+/// assert_eq!(echo.pending(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EchoCanceller {
+    expected: [u8; MAX_BLOCK_LENGTH],
+    length: usize,
+    matched: usize,
+}
+
+/// A received byte differs from the transmitted one, see [`EchoCanceller`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[error("echo byte {position} is {received:#04x}, {expected:#04x} was sent")]
+pub struct EchoMismatch {
+    pub position: usize,
+    pub expected: u8,
+    pub received: u8,
+}
+
+impl Default for EchoCanceller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EchoCanceller {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            expected: [0; MAX_BLOCK_LENGTH],
+            length: 0,
+            matched: 0,
+        }
+    }
+
+    /// Expects the echo of `frame` next, the rest of a previous echo is dropped.
+    ///
+    /// Only the first [`MAX_BLOCK_LENGTH`] bytes of longer frames are checked.
+    pub fn transmitted(&mut self, frame: &[u8]) {
+        let length = frame.len().min(MAX_BLOCK_LENGTH);
+        self.expected[..length].copy_from_slice(&frame[..length]);
+        self.length = length;
+        self.matched = 0;
+    }
+
+    /// Returns the number of echo bytes still expected.
+    #[must_use]
+    pub const fn pending(&self) -> usize {
+        self.length - self.matched
+    }
+
+    /// Drops the pending echo, e.g. when the write failed.
+    pub const fn reset(&mut self) {
+        self.length = 0;
+        self.matched = 0;
+    }
+
+    /// Checks a received byte, returns it if it is not part of the echo.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EchoMismatch`] if the byte differs from the transmitted one, the rest of the
+    /// echo is dropped.
+    pub const fn receive(&mut self, byte: u8) -> Result<Option<u8>, EchoMismatch> {
+        if self.pending() == 0 {
+            return Ok(Some(byte));
+        }
+        let expected = self.expected[self.matched];
+        if byte != expected {
+            let position = self.matched;
+            self.reset();
+            return Err(EchoMismatch {
+                position,
+                expected,
+                received: byte,
+            });
+        }
+        self.matched += 1;
+        Ok(None)
+    }
+
+    /// Strips the pending echo from the start of `received`, returns the bytes past it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EchoMismatch`] if a byte differs from the transmitted one, the rest of the
+    /// echo is dropped.
+    pub fn strip<'a>(&mut self, received: &'a [u8]) -> Result<&'a [u8], EchoMismatch> {
+        let echoed = self.pending().min(received.len());
+        for byte in &received[..echoed] {
+            self.receive(*byte)?;
+        }
+        Ok(&received[echoed..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn echo_is_checked_byte_by_byte() {
+        let mut echo = EchoCanceller::new();
+        assert_eq!(echo.receive(7), Ok(Some(7)));
+
+        echo.transmitted(&[2, 0, 1, 254, 255]);
+        assert_eq!(echo.receive(2), Ok(None));
+        assert_eq!(echo.pending(), 4);
+        assert_eq!(
+            echo.receive(1),
+            Err(EchoMismatch {
+                position: 1,
+                expected: 0,
+                received: 1,
+            })
+        );
+        assert_eq!(echo.receive(0), Ok(Some(0)));
+
+        echo.transmitted(&[2, 0, 1, 254, 255]);
+        echo.transmitted(&[3, 0, 1, 254, 254]);
+        assert_eq!(echo.strip(&[3, 0, 1, 254, 254]), Ok(&[][..]));
+        assert_eq!(echo.pending(), 0);
+    }
+}
@@ -0,0 +1,56 @@
+/// Event buffer decrypted from a `ReadEncryptedEvents` reply, header 112.
+///
+/// The buffer holds the same event counter and 5 result pairs as `ReadBufferedCreditOrErrorCodes`
+/// and `ReadBufferedBillEvents`, [`buffer`](Self::buffer) lays them out like those replies so
+/// they parse into the usual poll results.
+///
+/// The reply is decrypted by the [`PeripheralCipher`](super::peripheral_encryption::PeripheralCipher)
+/// of the peripheral.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let events = EncryptedEvents {
+///     event_counter: 1,
+///     results: [[3, 1], [0, 0], [0, 0], [0, 0], [0, 0]],
+/// };
+///
+/// let result = CoinAcceptorPollResult::try_from((&events.buffer()[..], 0)).unwrap();
+/// assert_eq!(result.events.len(), 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncryptedEvents {
+    pub event_counter: u8,
+    /// Result A and B of the last 5 events, the most recent first.
+    pub results: [[u8; 2]; 5],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EncryptedEventsError {
+    #[error("encrypted events must be {expected} bytes, received {received}")]
+    InvalidLength { expected: usize, received: usize },
+    /// The reply does not answer the challenge sent with the request, it may be replayed.
+    #[error("challenge mismatch, expected {expected:#04x} received {received:#04x}")]
+    ChallengeMismatch { expected: u8, received: u8 },
+    /// The decrypted checksum does not match, the key is most likely wrong.
+    #[error("decrypted checksum mismatch, expected {expected:#06x} received {received:#06x}")]
+    ChecksumMismatch { expected: u16, received: u16 },
+}
+
+impl EncryptedEvents {
+    /// Returns the event counter followed by the results, the layout of a
+    /// `ReadBufferedCreditOrErrorCodes` or `ReadBufferedBillEvents` reply.
+    #[must_use]
+    pub fn buffer(&self) -> [u8; 11] {
+        let mut buffer = [0u8; 11];
+        buffer[0] = self.event_counter;
+        for (chunk, result) in buffer[1..].chunks_exact_mut(2).zip(self.results) {
+            chunk.copy_from_slice(&result);
+        }
+        buffer
+    }
+}
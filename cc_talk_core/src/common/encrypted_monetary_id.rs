@@ -0,0 +1,163 @@
+use super::currency::{CurrencyToken, CurrencyValue, Factor};
+
+/// Country or currency of a [`MonetaryId`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MonetaryCode {
+    /// ISO 3166-1 alpha-2 country code, sent as `#` and the 2 letters, the default.
+    Country([u8; 2]),
+    /// ISO 4217 currency code.
+    Currency([u8; 3]),
+}
+
+impl MonetaryCode {
+    /// Returns the code without the `#` marking country codes.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        let bytes: &[u8] = match self {
+            Self::Country(code) => code,
+            Self::Currency(code) => code,
+        };
+        // Only ASCII letters are accepted when decoding.
+        core::str::from_utf8(bytes).unwrap_or_default()
+    }
+
+    /// Decodes a code sent as `#` and 2 uppercase letters or as 3 uppercase letters.
+    #[must_use]
+    pub fn from_bytes(code: [u8; 3]) -> Option<Self> {
+        match code {
+            [b'#', c1, c2] if c1.is_ascii_uppercase() && c2.is_ascii_uppercase() => {
+                Some(Self::Country([c1, c2]))
+            }
+            code if code.iter().all(u8::is_ascii_uppercase) => Some(Self::Currency(code)),
+            _ => None,
+        }
+    }
+
+    /// Encodes the code, country codes are prefixed with `#`.
+    #[must_use]
+    pub const fn to_bytes(self) -> [u8; 3] {
+        match self {
+            Self::Country([c1, c2]) => [b'#', c1, c2],
+            Self::Currency(code) => code,
+        }
+    }
+}
+
+/// Coin or bill decrypted from a `RequestEncryptedMonetaryId` reply, header 108.
+///
+/// Replaces the coin or bill id and the country scaling factor of encrypted peripherals, see
+/// [`currency_token`](Self::currency_token). The reply is decrypted by the
+/// [`PeripheralCipher`](super::peripheral_encryption::PeripheralCipher) of the peripheral.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let id = MonetaryId {
+///     position: 3,
+///     code: MonetaryCode::Currency(*b"EUR"),
+///     scaling_factor: 2,
+///     decimal_places: 2,
+///     value: 20,
+///     issue_level: b'A',
+///     issue_number: 1,
+/// };
+///
+/// let CurrencyToken::Currency(value) = id.currency_token().unwrap() else {
+///     unreachable!()
+/// };
+/// assert_eq!((value.smallest_unit_value(), value.country_code()), (2000, "EUR"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MonetaryId {
+    pub position: u8,
+    pub code: MonetaryCode,
+    /// Power of 10 the value is multiplied by to get the minor currency unit.
+    pub scaling_factor: u8,
+    /// Decimal places used to display the value.
+    pub decimal_places: u8,
+    /// Value of 0 to 9999, sent as 4 ASCII digits.
+    pub value: u16,
+    /// Issue level, an ASCII letter from `A` to `Z`.
+    pub issue_level: u8,
+    /// Issue number, 1 to 9.
+    pub issue_number: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MonetaryIdError {
+    #[error("encrypted monetary id must be {expected} bytes, received {received}")]
+    InvalidLength { expected: usize, received: usize },
+    /// The reply does not answer the challenge sent with the request, it may be replayed.
+    #[error("challenge mismatch, expected {expected:#04x} received {received:#04x}")]
+    ChallengeMismatch { expected: u8, received: u8 },
+    /// The reply describes another position than the one requested.
+    #[error("position mismatch, expected {expected} received {received}")]
+    PositionMismatch { expected: u8, received: u8 },
+    /// The decrypted checksum does not match, the key is most likely wrong.
+    #[error("decrypted checksum mismatch, expected {expected:#06x} received {received:#06x}")]
+    ChecksumMismatch { expected: u16, received: u16 },
+    /// A field holds characters the specification does not allow.
+    #[error("invalid {0} field")]
+    InvalidField(&'static str),
+}
+
+impl MonetaryId {
+    /// Returns the value in minor currency units, `None` if it overflows.
+    #[must_use]
+    pub fn minor_units(&self) -> Option<u32> {
+        10u32
+            .checked_pow(u32::from(self.scaling_factor))?
+            .checked_mul(u32::from(self.value))
+    }
+
+    /// Converts the id into a [`CurrencyToken`], the value in minor currency units with the
+    /// decimal places of the id.
+    ///
+    /// Returns `None` if the value overflows.
+    #[must_use]
+    pub fn currency_token(&self) -> Option<CurrencyToken> {
+        CurrencyValue::new(
+            self.code.as_str(),
+            Factor::None,
+            self.decimal_places,
+            self.minor_units()?,
+        )
+        .map(CurrencyToken::Currency)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn codes_and_overflowing_values() {
+        assert_eq!(
+            MonetaryCode::from_bytes(*b"#GB"),
+            Some(MonetaryCode::Country(*b"GB"))
+        );
+        assert_eq!(
+            MonetaryCode::from_bytes(*b"EUR"),
+            Some(MonetaryCode::Currency(*b"EUR"))
+        );
+        assert_eq!(MonetaryCode::from_bytes(*b"#G1"), None);
+        assert_eq!(MonetaryCode::Country(*b"GB").to_bytes(), *b"#GB");
+        assert_eq!(MonetaryCode::Country(*b"GB").as_str(), "GB");
+
+        let overflowing = MonetaryId {
+            position: 1,
+            code: MonetaryCode::Currency(*b"JPY"),
+            scaling_factor: 9,
+            decimal_places: 0,
+            value: 9999,
+            issue_level: b'A',
+            issue_number: 1,
+        };
+        assert_eq!(overflowing.currency_token(), None);
+    }
+}
@@ -0,0 +1,329 @@
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes256,
+};
+
+use super::{checksum::crc16_bytes, device::SerialCode, manufacturers::Manufacturer};
+
+/// AES-256 key of an [`AesProductIdCipher`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ProductIdKey([u8; 32]);
+
+impl ProductIdKey {
+    #[must_use]
+    pub const fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    fn cipher(self) -> Aes256 {
+        Aes256::new(&GenericArray::from(self.0))
+    }
+
+    /// Encrypts a single 16 byte block.
+    #[must_use]
+    pub fn encrypt_block(&self, block: [u8; 16]) -> [u8; 16] {
+        let mut block = GenericArray::from(block);
+        self.cipher().encrypt_block(&mut block);
+        block.into()
+    }
+
+    /// Decrypts a single 16 byte block.
+    #[must_use]
+    pub fn decrypt_block(&self, block: [u8; 16]) -> [u8; 16] {
+        let mut block = GenericArray::from(block);
+        self.cipher().decrypt_block(&mut block);
+        block.into()
+    }
+}
+
+impl core::fmt::Debug for ProductIdKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "ProductIdKey(******)")
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ProductIdKey {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "ProductIdKey(******)");
+    }
+}
+
+/// Product identification decrypted from a `RequestEncryptedProductId` reply, header 224.
+///
+/// The text fields are stored without their space padding. The reply is decrypted by the
+/// [`ProductIdCipher`] of the peripheral.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let id = EncryptedProductId {
+///     manufacturer: "MCI".try_into().unwrap(),
+///     product: "SH4".try_into().unwrap(),
+///     serial: SerialCode::new_extended(0, 1, 2, 3),
+///     revision: "HOPPER-V1.23".try_into().unwrap(),
+/// };
+///
+/// assert_eq!(id.known_manufacturer(), Some(Manufacturer::MoneyControlsInternational));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedProductId {
+    /// Abbreviated manufacturer name, see [`Manufacturer::abbreviated_name`].
+    pub manufacturer: heapless::String<3>,
+    pub product: heapless::String<8>,
+    /// Always a 4 byte serial code.
+    pub serial: SerialCode,
+    /// Firmware revision, e.g. `HOPPER-V1.23`.
+    pub revision: heapless::String<12>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EncryptedProductIdError {
+    #[error("encrypted product id must be {expected} bytes, received {received}")]
+    InvalidLength { expected: usize, received: usize },
+    /// The reply does not answer the challenge sent with the request, it may be replayed.
+    #[error("challenge mismatch, expected {expected:?} received {received:?}")]
+    ChallengeMismatch {
+        expected: [u8; 2],
+        received: [u8; 2],
+    },
+    /// The decrypted checksum does not match, the key is most likely wrong.
+    #[error("decrypted checksum mismatch, expected {expected:#06x} received {received:#06x}")]
+    ChecksumMismatch { expected: u16, received: u16 },
+    /// A text field is not printable ASCII.
+    #[error("invalid {0} field")]
+    InvalidField(&'static str),
+}
+
+impl EncryptedProductId {
+    /// Returns the manufacturer if its abbreviation is a registered one.
+    #[must_use]
+    pub fn known_manufacturer(&self) -> Option<Manufacturer> {
+        Manufacturer::from_abbreviated_name(&self.manufacturer)
+    }
+}
+
+/// Algorithm of the `RequestEncryptedProductId` reply of a peripheral.
+///
+/// The encryption of the product id is licensed separately from the ccTalk specification and is
+/// not part of this crate. Implement this trait with the standard of the peripheral.
+///
+/// Implementations should hide their keys from the `Debug` output.
+pub trait ProductIdCipher: core::fmt::Debug {
+    /// Decrypts a `RequestEncryptedProductId` reply and checks it answers `challenge`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the reply does not decrypt into a product id answering `challenge`.
+    fn decrypt_product_id(
+        &self,
+        challenge: [u8; 2],
+        reply: &[u8],
+    ) -> Result<EncryptedProductId, EncryptedProductIdError>;
+}
+
+/// AES-256 based [`ProductIdCipher`] exercising encrypted peripherals in tests and emulators.
+///
+/// This is **not** the encryption of any peripheral, it only mirrors the shape of the reply. The
+/// reply is 2 AES blocks holding a CRC-16, the challenge bytes and the space padded fields,
+/// [`encrypt_product_id`](Self::encrypt_product_id) builds the reply of an emulated peripheral.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let cipher = AesProductIdCipher::new(ProductIdKey::new([7; 32]));
+/// let id = EncryptedProductId {
+///     manufacturer: "MCI".try_into().unwrap(),
+///     product: "SH4".try_into().unwrap(),
+///     serial: SerialCode::new_extended(0, 1, 2, 3),
+///     revision: "HOPPER-V1.23".try_into().unwrap(),
+/// };
+/// let reply = cipher.encrypt_product_id(&id, [0x12, 0x34], 0x99);
+///
+/// assert_eq!(cipher.decrypt_product_id([0x12, 0x34], &reply), Ok(id));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AesProductIdCipher {
+    key: ProductIdKey,
+}
+
+impl AesProductIdCipher {
+    /// Length of an encrypted reply, 2 AES blocks.
+    pub const REPLY_LENGTH: usize = 32;
+
+    #[must_use]
+    pub const fn new(key: ProductIdKey) -> Self {
+        Self { key }
+    }
+
+    /// Encrypts `id` like a peripheral answering `challenge`, `random` is the byte mixed into the
+    /// plaintext.
+    #[must_use]
+    pub fn encrypt_product_id(
+        &self,
+        id: &EncryptedProductId,
+        challenge: [u8; 2],
+        random: u8,
+    ) -> [u8; Self::REPLY_LENGTH] {
+        let mut plain = [b' '; Self::REPLY_LENGTH];
+        pad(&mut plain[1..4], &id.manufacturer);
+        pad(&mut plain[4..12], &id.product);
+        plain[12] = id.serial.fix();
+        plain[13] = id.serial.minor();
+        plain[14] = id.serial.major();
+        plain[15] = challenge[0];
+        pad(&mut plain[16..28], &id.revision);
+        plain[28] = id.serial.extension().unwrap_or_default();
+        plain[29] = random;
+        plain[30] = challenge[1];
+        let [lsb, msb] = crc16_bytes(&plain[1..31]).to_le_bytes();
+        plain[0] = lsb;
+        plain[31] = msb;
+        apply_blocks(plain, |block| self.key.encrypt_block(block))
+    }
+}
+
+impl ProductIdCipher for AesProductIdCipher {
+    fn decrypt_product_id(
+        &self,
+        challenge: [u8; 2],
+        reply: &[u8],
+    ) -> Result<EncryptedProductId, EncryptedProductIdError> {
+        let Ok(reply) = <[u8; Self::REPLY_LENGTH]>::try_from(reply) else {
+            return Err(EncryptedProductIdError::InvalidLength {
+                expected: Self::REPLY_LENGTH,
+                received: reply.len(),
+            });
+        };
+        let plain = apply_blocks(reply, |block| self.key.decrypt_block(block));
+
+        let expected = crc16_bytes(&plain[1..31]);
+        let received = u16::from_le_bytes([plain[0], plain[31]]);
+        if expected != received {
+            return Err(EncryptedProductIdError::ChecksumMismatch { expected, received });
+        }
+        let received = [plain[15], plain[30]];
+        if received != challenge {
+            return Err(EncryptedProductIdError::ChallengeMismatch {
+                expected: challenge,
+                received,
+            });
+        }
+
+        let serial = SerialCode::new_extended(plain[28], plain[14], plain[13], plain[12]);
+        Ok(EncryptedProductId {
+            manufacturer: text(&plain[1..4])
+                .ok_or(EncryptedProductIdError::InvalidField("manufacturer"))?,
+            product: text(&plain[4..12]).ok_or(EncryptedProductIdError::InvalidField("product"))?,
+            serial,
+            revision: text(&plain[16..28])
+                .ok_or(EncryptedProductIdError::InvalidField("revision"))?,
+        })
+    }
+}
+
+/// Decodes a space padded ASCII field, `None` if it holds non printable characters.
+fn text<const N: usize>(bytes: &[u8]) -> Option<heapless::String<N>> {
+    if !bytes
+        .iter()
+        .all(|byte| byte.is_ascii_graphic() || *byte == b' ')
+    {
+        return None;
+    }
+    let text = core::str::from_utf8(bytes).ok()?.trim_end_matches(' ');
+    heapless::String::try_from(text).ok()
+}
+
+/// Writes `text` left justified in a field already filled with spaces.
+fn pad(field: &mut [u8], text: &str) {
+    field[..text.len()].copy_from_slice(text.as_bytes());
+}
+
+/// Runs `cipher` on both blocks of an encrypted reply.
+fn apply_blocks(
+    input: [u8; AesProductIdCipher::REPLY_LENGTH],
+    cipher: impl Fn([u8; 16]) -> [u8; 16],
+) -> [u8; AesProductIdCipher::REPLY_LENGTH] {
+    let mut output = [0u8; AesProductIdCipher::REPLY_LENGTH];
+    for (output, block) in output.chunks_exact_mut(16).zip(input.chunks_exact(16)) {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(block);
+        output.copy_from_slice(&cipher(bytes));
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CIPHER: AesProductIdCipher = AesProductIdCipher::new(ProductIdKey::new([
+        0x60, 0x3D, 0xEB, 0x10, 0x15, 0xCA, 0x71, 0xBE, 0x2B, 0x73, 0xAE, 0xF0, 0x85, 0x7D, 0x77,
+        0x81, 0x1F, 0x35, 0x2C, 0x07, 0x3B, 0x61, 0x08, 0xD7, 0x2D, 0x98, 0x10, 0xA3, 0x09, 0x14,
+        0xDF, 0xF4,
+    ]));
+
+    #[test]
+    fn aes_256_matches_the_fips_197_vector() {
+        let key = ProductIdKey::new([
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+            0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x1B,
+            0x1C, 0x1D, 0x1E, 0x1F,
+        ]);
+        let plain = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD,
+            0xEE, 0xFF,
+        ];
+        let cipher = [
+            0x8E, 0xA2, 0xB7, 0xCA, 0x51, 0x67, 0x45, 0xBF, 0xEA, 0xFC, 0x49, 0x90, 0x4B, 0x49,
+            0x60, 0x89,
+        ];
+        assert_eq!(key.encrypt_block(plain), cipher);
+        assert_eq!(key.decrypt_block(cipher), plain);
+    }
+
+    #[test]
+    fn product_ids_are_decrypted_and_verified() {
+        let id = EncryptedProductId {
+            manufacturer: heapless::String::try_from("AZK").unwrap_or_default(),
+            product: heapless::String::try_from("HopperX").unwrap_or_default(),
+            serial: SerialCode::from_le_bytes(&[0x78, 0x56, 0x34, 0x12])
+                .unwrap_or(SerialCode::new(0, 0, 0)),
+            revision: heapless::String::try_from("V2.0").unwrap_or_default(),
+        };
+        let mut reply = CIPHER.encrypt_product_id(&id, [0xA5, 0x5A], 0x42);
+        assert_eq!(
+            CIPHER.decrypt_product_id([0xA5, 0x5A], &reply),
+            Ok(id.clone())
+        );
+        assert_eq!(id.known_manufacturer(), Some(Manufacturer::Azkoyen));
+
+        assert_eq!(
+            CIPHER.decrypt_product_id([0xA5, 0x5B], &reply),
+            Err(EncryptedProductIdError::ChallengeMismatch {
+                expected: [0xA5, 0x5B],
+                received: [0xA5, 0x5A],
+            })
+        );
+        assert!(matches!(
+            AesProductIdCipher::new(ProductIdKey::new([0; 32]))
+                .decrypt_product_id([0xA5, 0x5A], &reply),
+            Err(EncryptedProductIdError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            CIPHER.decrypt_product_id([0xA5, 0x5A], &reply[..16]),
+            Err(EncryptedProductIdError::InvalidLength {
+                expected: 32,
+                received: 16,
+            })
+        );
+        reply[20] ^= 1;
+        assert!(CIPHER.decrypt_product_id([0xA5, 0x5A], &reply).is_err());
+    }
+}
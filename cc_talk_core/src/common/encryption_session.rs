@@ -0,0 +1,252 @@
+/// Source of random bytes used to build encryption challenges.
+///
+/// The crate does not depend on any RNG implementation so it can be used on `no_std`
+/// targets, implement this on top of whatever entropy source the platform provides
+/// (hardware RNG peripheral, `rand`, `getrandom`, ...).
+pub trait ChallengeRng {
+    /// Fills `dest` with random bytes.
+    fn fill_bytes(&mut self, dest: &mut [u8]);
+}
+
+impl<R: ChallengeRng + ?Sized> ChallengeRng for &mut R {
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        (**self).fill_bytes(dest);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EncryptionSessionError {
+    #[error("no challenge is pending for this device")]
+    NoPendingChallenge,
+    #[error("challenge mismatch, expected {expected:?} received {received:?}")]
+    ChallengeMismatch {
+        expected: [u8; 2],
+        received: [u8; 2],
+    },
+    /// The peripheral reported a DH counter which does not match the number of key
+    /// exchanges performed by the host, a third party may have exchanged keys with it.
+    #[error("unexpected DH counter, expected {expected} reported {reported}")]
+    UnexpectedDhCounter { expected: u16, reported: u16 },
+}
+
+/// Anti-replay state kept by the host for a single encrypted peripheral.
+///
+/// Every encrypted command sends 2 plain-text challenge bytes which the device returns
+/// inside the encrypted reply, a challenge can only be verified once so a recorded reply
+/// cannot be replayed.
+///
+/// The session also tracks the DH counter reported by header 200, the peripheral
+/// increments it on every key exchange so a jump the host did not cause suggests an
+/// illegal third party key exchange.
+///
+/// # Example
+///
+/// ```
+/// use cc_talk_core::cc_talk::{ChallengeRng, EncryptionSession, EncryptionSessionError};
+///
+/// struct Counter(u8);
+///
+/// impl ChallengeRng for Counter {
+///     fn fill_bytes(&mut self, dest: &mut [u8]) {
+///         for byte in dest {
+///             self.0 = self.0.wrapping_add(1);
+///             *byte = self.0;
+///         }
+///     }
+/// }
+///
+/// let mut session = EncryptionSession::new();
+/// let challenge = session.new_challenge(&mut Counter(0));
+/// assert_eq!(session.verify_challenge(challenge), Ok(()));
+/// assert_eq!(
+///     session.verify_challenge(challenge),
+///     Err(EncryptionSessionError::NoPendingChallenge)
+/// );
+///
+/// session.check_dh_counter(4).unwrap();
+/// session.record_key_exchange();
+/// assert!(session.check_dh_counter(6).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncryptionSession {
+    dh_counter: Option<u16>,
+    pending_challenge: Option<[u8; 2]>,
+}
+
+impl EncryptionSession {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            dh_counter: None,
+            pending_challenge: None,
+        }
+    }
+
+    /// Creates a session which already knows the DH counter, e.g. restored from
+    /// persistent storage.
+    #[must_use]
+    pub const fn with_dh_counter(dh_counter: u16) -> Self {
+        Self {
+            dh_counter: Some(dh_counter),
+            pending_challenge: None,
+        }
+    }
+
+    /// The DH counter the host expects the peripheral to report, if known.
+    #[must_use]
+    pub const fn dh_counter(&self) -> Option<u16> {
+        self.dh_counter
+    }
+
+    /// The challenge waiting for an encrypted reply, if any.
+    #[must_use]
+    pub const fn pending_challenge(&self) -> Option<[u8; 2]> {
+        self.pending_challenge
+    }
+
+    /// Generates a new challenge for the next encrypted command.
+    ///
+    /// Any previously pending challenge is discarded.
+    pub fn new_challenge<R: ChallengeRng>(&mut self, mut rng: R) -> [u8; 2] {
+        let mut challenge = [0u8; 2];
+        rng.fill_bytes(&mut challenge);
+        self.pending_challenge = Some(challenge);
+        challenge
+    }
+
+    /// Verifies the challenge bytes returned in a decrypted reply.
+    ///
+    /// The pending challenge is consumed whether it matches or not.
+    ///
+    /// # Errors
+    ///
+    /// Errors if no challenge was pending or if the received bytes do not match.
+    pub const fn verify_challenge(
+        &mut self,
+        received: [u8; 2],
+    ) -> Result<(), EncryptionSessionError> {
+        match self.pending_challenge.take() {
+            None => Err(EncryptionSessionError::NoPendingChallenge),
+            Some(expected) if expected[0] == received[0] && expected[1] == received[1] => Ok(()),
+            Some(expected) => Err(EncryptionSessionError::ChallengeMismatch { expected, received }),
+        }
+    }
+
+    /// Records a key exchange performed by the host, the peripheral increments its DH
+    /// counter by one (wrapping) on every exchange.
+    ///
+    /// The challenge pending before the exchange is dropped as it was issued under the
+    /// previous key.
+    pub const fn record_key_exchange(&mut self) {
+        if let Some(counter) = self.dh_counter {
+            self.dh_counter = Some(counter.wrapping_add(1));
+        }
+        self.pending_challenge = None;
+    }
+
+    /// Checks the DH counter reported by the peripheral.
+    ///
+    /// The first reported value is learned, after that any difference with the expected
+    /// value is reported. The expected value is left untouched on error, use
+    /// [`EncryptionSession::accept_dh_counter`] once the jump has been dealt with.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the reported counter does not match the expected one.
+    pub const fn check_dh_counter(&mut self, reported: u16) -> Result<(), EncryptionSessionError> {
+        match self.dh_counter {
+            None => {
+                self.dh_counter = Some(reported);
+                Ok(())
+            }
+            Some(expected) if expected == reported => Ok(()),
+            Some(expected) => {
+                Err(EncryptionSessionError::UnexpectedDhCounter { expected, reported })
+            }
+        }
+    }
+
+    /// Accepts the reported DH counter as the new expected value.
+    pub const fn accept_dh_counter(&mut self, reported: u16) {
+        self.dh_counter = Some(reported);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct FixedRng([u8; 2]);
+
+    impl ChallengeRng for FixedRng {
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.copy_from_slice(&self.0);
+        }
+    }
+
+    #[test]
+    fn challenge_can_only_be_verified_once() {
+        let mut session = EncryptionSession::new();
+        let challenge = session.new_challenge(FixedRng([0x12, 0x34]));
+        assert_eq!(challenge, [0x12, 0x34]);
+        assert_eq!(session.pending_challenge(), Some(challenge));
+
+        assert_eq!(session.verify_challenge([0x12, 0x34]), Ok(()));
+        assert_eq!(
+            session.verify_challenge([0x12, 0x34]),
+            Err(EncryptionSessionError::NoPendingChallenge)
+        );
+    }
+
+    #[test]
+    fn mismatched_challenge_is_consumed() {
+        let mut session = EncryptionSession::new();
+        session.new_challenge(FixedRng([1, 2]));
+
+        assert_eq!(
+            session.verify_challenge([2, 1]),
+            Err(EncryptionSessionError::ChallengeMismatch {
+                expected: [1, 2],
+                received: [2, 1],
+            })
+        );
+        assert_eq!(session.pending_challenge(), None);
+    }
+
+    #[test]
+    fn dh_counter_jump_is_detected() {
+        let mut session = EncryptionSession::new();
+        assert_eq!(session.check_dh_counter(u16::MAX), Ok(()));
+
+        session.record_key_exchange();
+        assert_eq!(session.dh_counter(), Some(0));
+        assert_eq!(session.check_dh_counter(0), Ok(()));
+
+        assert_eq!(
+            session.check_dh_counter(2),
+            Err(EncryptionSessionError::UnexpectedDhCounter {
+                expected: 0,
+                reported: 2,
+            })
+        );
+        assert_eq!(session.dh_counter(), Some(0));
+
+        session.accept_dh_counter(2);
+        assert_eq!(session.check_dh_counter(2), Ok(()));
+    }
+
+    #[test]
+    fn key_exchange_drops_pending_challenge() {
+        let mut session = EncryptionSession::with_dh_counter(7);
+        session.new_challenge(FixedRng([9, 9]));
+        session.record_key_exchange();
+
+        assert_eq!(session.dh_counter(), Some(8));
+        assert_eq!(
+            session.verify_challenge([9, 9]),
+            Err(EncryptionSessionError::NoPendingChallenge)
+        );
+    }
+}
@@ -114,3 +114,54 @@ impl TryFrom<u8> for EscrowServiceStatus {
         }
     }
 }
+
+/// Status of a coin escrow as reported by `RequestEscrowStatus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EscrowStatus {
+    pub operating: EscrowOperatingStatus,
+    pub level: EscrowLevelStatus,
+    pub fault: EscrowFaultCode,
+}
+
+impl EscrowStatus {
+    #[must_use]
+    pub const fn new(
+        operating: EscrowOperatingStatus,
+        level: EscrowLevelStatus,
+        fault: EscrowFaultCode,
+    ) -> Self {
+        Self {
+            operating,
+            level,
+            fault,
+        }
+    }
+
+    /// Returns `true` if the escrow is not moving its flaps.
+    #[must_use]
+    pub const fn is_idle(&self) -> bool {
+        matches!(self.operating, EscrowOperatingStatus::Idle)
+    }
+
+    /// Returns `true` if the escrow cannot hold another coin.
+    #[must_use]
+    pub const fn is_full(&self) -> bool {
+        matches!(self.level, EscrowLevelStatus::Full)
+    }
+
+    /// Returns `true` if the escrow reports a fault condition or a fault code.
+    #[must_use]
+    pub const fn has_fault(&self) -> bool {
+        matches!(self.operating, EscrowOperatingStatus::FaultCondition)
+            || !matches!(self.fault, EscrowFaultCode::NoFault)
+    }
+}
+
+impl From<(EscrowOperatingStatus, EscrowLevelStatus, EscrowFaultCode)> for EscrowStatus {
+    fn from(
+        (operating, level, fault): (EscrowOperatingStatus, EscrowLevelStatus, EscrowFaultCode),
+    ) -> Self {
+        Self::new(operating, level, fault)
+    }
+}
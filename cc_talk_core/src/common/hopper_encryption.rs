@@ -0,0 +1,297 @@
+use des::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Des,
+};
+
+use super::{
+    checksum::crc16_bytes,
+    hopper_status::{HopperDispenseStatus, HopperStatus},
+};
+
+/// 64 bit DES key shared by the host and an encrypted hopper.
+///
+/// The key is hidden from the `Debug` output.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct HopperDesKey([u8; 8]);
+
+impl HopperDesKey {
+    #[must_use]
+    pub const fn new(key: [u8; 8]) -> Self {
+        Self(key)
+    }
+
+    fn cipher(self) -> Des {
+        Des::new(&GenericArray::from(self.0))
+    }
+
+    /// Encrypts a single 8 byte block.
+    #[must_use]
+    pub fn encrypt_block(&self, block: [u8; 8]) -> [u8; 8] {
+        let mut block = GenericArray::from(block);
+        self.cipher().encrypt_block(&mut block);
+        block.into()
+    }
+
+    /// Decrypts a single 8 byte block.
+    #[must_use]
+    pub fn decrypt_block(&self, block: [u8; 8]) -> [u8; 8] {
+        let mut block = GenericArray::from(block);
+        self.cipher().decrypt_block(&mut block);
+        block.into()
+    }
+}
+
+impl core::fmt::Debug for HopperDesKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "HopperDesKey(******)")
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for HopperDesKey {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "HopperDesKey(******)");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HopperEncryptionError {
+    #[error("encrypted reply must be {expected} bytes, received {received}")]
+    InvalidLength { expected: usize, received: usize },
+    /// The reply does not answer the challenge sent with the request, it may be replayed.
+    #[error("challenge mismatch, expected {expected:?} received {received:?}")]
+    ChallengeMismatch {
+        expected: [u8; 3],
+        received: [u8; 3],
+    },
+    /// The decrypted CRC does not match, the DES key is most likely wrong.
+    #[error("decrypted checksum mismatch, expected {expected:#06x} received {received:#06x}")]
+    ChecksumMismatch { expected: u16, received: u16 },
+}
+
+/// Hopper status decrypted from a `RequestEncryptedHopperStatus` reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncryptedHopperStatus {
+    /// Same data as `RequestHopperStatus`.
+    pub dispense: HopperDispenseStatus,
+    /// Same data as `TestHopper`.
+    pub registers: [u8; 3],
+    /// Same data as `RequestPayoutHighLowStatus`.
+    pub level: HopperStatus,
+}
+
+/// Algorithm of an encrypted hopper: the dispense security codes and the encrypted status.
+///
+/// The Serial Hopper Encryption Standards reported by `RequestEncryptionSupport`, CMF1-1 to
+/// CMF1-3, are licensed separately from the ccTalk specification and are not part of this
+/// crate. Implement this trait with the standard of the hopper.
+///
+/// Implementations should hide their keys from the `Debug` output.
+pub trait HopperCipher: core::fmt::Debug {
+    /// Computes the 8 byte security code sent before the number of coins with
+    /// `DispenseHopperCoins`.
+    ///
+    /// `pumped` are the bytes last sent with `PumpRNG` and `cipher_key` the reply of the
+    /// following `RequestCipherKey`.
+    fn security_code(&self, pumped: &[u8; 8], cipher_key: &[u8; 8], coins: u8) -> [u8; 8];
+
+    /// Decrypts a `RequestEncryptedHopperStatus` reply and checks it answers `challenge`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the reply does not decrypt into a status answering `challenge`.
+    fn decrypt_status(
+        &self,
+        challenge: [u8; 3],
+        reply: &[u8],
+    ) -> Result<EncryptedHopperStatus, HopperEncryptionError>;
+}
+
+/// DES based [`HopperCipher`] exercising encrypted hoppers in tests and emulators.
+///
+/// This is **not** a Serial Hopper Encryption Standard, no hopper accepts its security codes.
+///
+/// The security code is the DES encryption of the cipher key combined by XOR with the pumped
+/// bytes, the number of coins is combined into the last byte. The status is 2 DES blocks holding
+/// a CRC-16, the challenge bytes and the status data.
+///
+/// # Example
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let cipher = DesHopperCipher::new(HopperDesKey::new([1, 2, 3, 4, 5, 6, 7, 8]));
+/// let pumped = [9, 8, 7, 6, 5, 4, 3, 2];
+/// let cipher_key = [0xA5; 8];
+///
+/// let code = cipher.security_code(&pumped, &cipher_key, 10);
+/// assert_ne!(code, cipher.security_code(&pumped, &cipher_key, 11));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DesHopperCipher {
+    key: HopperDesKey,
+}
+
+impl DesHopperCipher {
+    /// Length of an encrypted hopper status reply, 2 DES blocks.
+    pub const STATUS_LENGTH: usize = 16;
+
+    #[must_use]
+    pub const fn new(key: HopperDesKey) -> Self {
+        Self { key }
+    }
+}
+
+impl HopperCipher for DesHopperCipher {
+    fn security_code(&self, pumped: &[u8; 8], cipher_key: &[u8; 8], coins: u8) -> [u8; 8] {
+        let mut block = *cipher_key;
+        for (byte, pumped) in block.iter_mut().zip(pumped) {
+            *byte ^= pumped;
+        }
+        block[7] ^= coins;
+        self.key.encrypt_block(block)
+    }
+
+    fn decrypt_status(
+        &self,
+        challenge: [u8; 3],
+        reply: &[u8],
+    ) -> Result<EncryptedHopperStatus, HopperEncryptionError> {
+        let Ok(reply) = <[u8; Self::STATUS_LENGTH]>::try_from(reply) else {
+            return Err(HopperEncryptionError::InvalidLength {
+                expected: Self::STATUS_LENGTH,
+                received: reply.len(),
+            });
+        };
+
+        let mut plain = [0u8; Self::STATUS_LENGTH];
+        for (plain, block) in plain.chunks_exact_mut(8).zip(reply.chunks_exact(8)) {
+            let mut encrypted = [0u8; 8];
+            encrypted.copy_from_slice(block);
+            plain.copy_from_slice(&self.key.decrypt_block(encrypted));
+        }
+
+        let expected = crc16_bytes(&plain[1..15]);
+        let received = u16::from_le_bytes([plain[0], plain[15]]);
+        if expected != received {
+            return Err(HopperEncryptionError::ChecksumMismatch { expected, received });
+        }
+
+        let received = [plain[1], plain[7], plain[8]];
+        if received != challenge {
+            return Err(HopperEncryptionError::ChallengeMismatch {
+                expected: challenge,
+                received,
+            });
+        }
+
+        Ok(EncryptedHopperStatus {
+            dispense: HopperDispenseStatus::new(plain[2], plain[3], plain[4], plain[5]),
+            registers: [plain[9], plain[10], plain[11]],
+            level: HopperStatus::from(plain[12]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: HopperDesKey = HopperDesKey::new([0x13, 0x34, 0x57, 0x79, 0x9B, 0xBC, 0xDF, 0xF1]);
+
+    /// Builds the reply of a hopper, see [`DesHopperCipher::decrypt_status`].
+    fn encrypted_reply(challenge: [u8; 3]) -> [u8; 16] {
+        let mut plain = [
+            0,
+            challenge[0],
+            7,
+            3,
+            10,
+            2,
+            0x55,
+            challenge[1],
+            challenge[2],
+            0b1000_0000,
+            0,
+            0,
+            0b0001_0000,
+            0x66,
+            0x77,
+            0,
+        ];
+        let [lsb, msb] = crc16_bytes(&plain[1..15]).to_le_bytes();
+        plain[0] = lsb;
+        plain[15] = msb;
+
+        let mut reply = [0u8; 16];
+        for (reply, block) in reply.chunks_exact_mut(8).zip(plain.chunks_exact(8)) {
+            let mut block_bytes = [0u8; 8];
+            block_bytes.copy_from_slice(block);
+            reply.copy_from_slice(&KEY.encrypt_block(block_bytes));
+        }
+        reply
+    }
+
+    #[test]
+    fn des_matches_the_reference_vector() {
+        let block = KEY.encrypt_block([0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+        assert_eq!(block, [0x85, 0xE8, 0x13, 0x54, 0x0F, 0x0A, 0xB4, 0x05]);
+        assert_eq!(
+            KEY.decrypt_block(block),
+            [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]
+        );
+        assert_eq!(std::format!("{KEY:?}"), "HopperDesKey(******)");
+    }
+
+    #[test]
+    fn security_codes_mix_the_pumped_bytes_and_the_coins() {
+        let pumped = [1, 2, 3, 4, 5, 6, 7, 8];
+        let cipher_key = [0x10; 8];
+        let cipher = DesHopperCipher::new(KEY);
+
+        assert_eq!(
+            cipher.security_code(&pumped, &cipher_key, 1),
+            KEY.encrypt_block([0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x19])
+        );
+        assert_ne!(
+            cipher.security_code(&pumped, &cipher_key, 1),
+            cipher.security_code(&pumped, &cipher_key, 2)
+        );
+    }
+
+    #[test]
+    fn encrypted_status_is_decrypted_and_verified() {
+        let cipher = DesHopperCipher::new(KEY);
+        let reply = encrypted_reply([0xC1, 0xC2, 0xC3]);
+
+        let status = cipher
+            .decrypt_status([0xC1, 0xC2, 0xC3], &reply)
+            .expect("should decrypt");
+        assert_eq!(status.dispense, HopperDispenseStatus::new(7, 3, 10, 2));
+        assert_eq!(status.registers, [0b1000_0000, 0, 0]);
+        assert!(status.level.low_level_supported);
+
+        assert_eq!(
+            cipher.decrypt_status([0xC1, 0xC2, 0xC4], &reply),
+            Err(HopperEncryptionError::ChallengeMismatch {
+                expected: [0xC1, 0xC2, 0xC4],
+                received: [0xC1, 0xC2, 0xC3],
+            })
+        );
+        assert!(matches!(
+            DesHopperCipher::new(HopperDesKey::new([0; 8]))
+                .decrypt_status([0xC1, 0xC2, 0xC3], &reply),
+            Err(HopperEncryptionError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            cipher.decrypt_status([0xC1, 0xC2, 0xC3], &reply[..8]),
+            Err(HopperEncryptionError::InvalidLength {
+                expected: 16,
+                received: 8
+            })
+        );
+    }
+}
@@ -478,7 +478,7 @@ mod tests {
     #[should_panic(expected = "registers must be of length 0, 1, 2, or 3")]
     fn test_parse_invalid_length() {
         let registers = &[0, 1, 2, 3, 4]; // Invalid length > 3
-        HopperFlag::parse_hopper_flags_array(registers);
+        let _ = HopperFlag::parse_hopper_flags_array(registers);
     }
 
     #[test]
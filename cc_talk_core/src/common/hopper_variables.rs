@@ -0,0 +1,133 @@
+/// Variable set of Serial Compact Hopper (SCH) payouts, see `RequestVariableSet` and
+/// `ModifyVariableSet`.
+///
+/// The variable set is sent as `[current limit] [motor stop delay] [payout timeout] [flags]`,
+/// undocumented flag bits are kept as read so a read-modify-write does not clear them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HopperVariables {
+    pub current_limit: u8,
+    pub motor_stop_delay: u8,
+    pub payout_timeout: u8,
+    /// Pays coins one at a time, the motor stops after every coin.
+    pub single_coin_mode: bool,
+    /// Lets the hopper hand over the payout to another hopper when it runs empty.
+    pub use_other_hopper: bool,
+    other_flags: u8,
+}
+
+impl HopperVariables {
+    /// Number of bytes of the variable set.
+    pub const LEN: usize = 4;
+
+    const SINGLE_COIN_MODE: u8 = 0b0000_0001;
+    const USE_OTHER_HOPPER: u8 = 0b0000_0010;
+
+    #[must_use]
+    pub const fn new(current_limit: u8, motor_stop_delay: u8, payout_timeout: u8) -> Self {
+        Self {
+            current_limit,
+            motor_stop_delay,
+            payout_timeout,
+            single_coin_mode: false,
+            use_other_hopper: false,
+            other_flags: 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_single_coin_mode(mut self, enabled: bool) -> Self {
+        self.single_coin_mode = enabled;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_use_other_hopper(mut self, enabled: bool) -> Self {
+        self.use_other_hopper = enabled;
+        self
+    }
+
+    /// Parses the variable set returned by `RequestVariableSet`.
+    ///
+    /// Returns `None` if less than [`Self::LEN`] bytes are provided, extra bytes are ignored.
+    #[must_use]
+    pub const fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let [current_limit, motor_stop_delay, payout_timeout, flags, ..] = *bytes else {
+            return None;
+        };
+        Some(Self {
+            current_limit,
+            motor_stop_delay,
+            payout_timeout,
+            single_coin_mode: flags & Self::SINGLE_COIN_MODE != 0,
+            use_other_hopper: flags & Self::USE_OTHER_HOPPER != 0,
+            other_flags: flags & !(Self::SINGLE_COIN_MODE | Self::USE_OTHER_HOPPER),
+        })
+    }
+
+    /// Returns the variable set as sent with `ModifyVariableSet`.
+    #[must_use]
+    pub const fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut flags = self.other_flags;
+        if self.single_coin_mode {
+            flags |= Self::SINGLE_COIN_MODE;
+        }
+        if self.use_other_hopper {
+            flags |= Self::USE_OTHER_HOPPER;
+        }
+        [
+            self.current_limit,
+            self.motor_stop_delay,
+            self.payout_timeout,
+            flags,
+        ]
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for HopperVariables {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            " Hopper Variables\n\
+            ├─  Current limit: {}\n\
+            ├─  Motor stop delay: {}\n\
+            ├─  Payout timeout: {}\n\
+            ├─  Single coin mode: {}\n\
+            └─  Use other hopper: {}",
+            self.current_limit,
+            self.motor_stop_delay,
+            self.payout_timeout,
+            self.single_coin_mode,
+            self.use_other_hopper
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_round_trip_and_keep_unknown_bits() {
+        let variables =
+            HopperVariables::from_bytes(&[10, 20, 30, 0b1000_0001, 99]).expect("should parse");
+        assert_eq!(variables.current_limit, 10);
+        assert_eq!(variables.motor_stop_delay, 20);
+        assert_eq!(variables.payout_timeout, 30);
+        assert!(variables.single_coin_mode);
+        assert!(!variables.use_other_hopper);
+
+        let modified = variables
+            .with_single_coin_mode(false)
+            .with_use_other_hopper(true);
+        assert_eq!(modified.to_bytes(), [10, 20, 30, 0b1000_0010]);
+        assert_eq!(HopperVariables::from_bytes(&[1, 2, 3]), None);
+        assert_eq!(
+            HopperVariables::new(1, 2, 3)
+                .with_single_coin_mode(true)
+                .to_bytes(),
+            [1, 2, 3, 1]
+        );
+    }
+}
@@ -88,35 +88,45 @@ impl BillValidatorOptionFlags {
         }
     }
 
-    const fn stacker(&self) -> bool {
+    /// The validator has a stacker.
+    #[must_use]
+    pub const fn stacker(&self) -> bool {
         self.stacker
     }
 
-    const fn escrow(&self) -> bool {
+    /// The validator can hold a bill in escrow until it is routed.
+    #[must_use]
+    pub const fn escrow(&self) -> bool {
         self.escrow
     }
 
-    const fn individual_bill_accept_counter(&self) -> bool {
+    #[must_use]
+    pub const fn individual_bill_accept_counter(&self) -> bool {
         self.individual_bill_accept_counter
     }
 
-    const fn individual_error_counter(&self) -> bool {
+    #[must_use]
+    pub const fn individual_error_counter(&self) -> bool {
         self.individual_error_counter
     }
 
-    const fn non_volatile_counter(&self) -> bool {
+    #[must_use]
+    pub const fn non_volatile_counter(&self) -> bool {
         self.non_volatile_counter
     }
 
-    const fn bill_teach(&self) -> bool {
+    #[must_use]
+    pub const fn bill_teach(&self) -> bool {
         self.bill_teach
     }
 
-    const fn bill_security_tuning(&self) -> bool {
+    #[must_use]
+    pub const fn bill_security_tuning(&self) -> bool {
         self.bill_security_tuning
     }
 
-    const fn remote_bill_programming(&self) -> bool {
+    #[must_use]
+    pub const fn remote_bill_programming(&self) -> bool {
         self.remote_bill_programming
     }
 }
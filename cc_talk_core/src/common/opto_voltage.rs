@@ -0,0 +1,134 @@
+/// Width of a single opto voltage reading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OptoResolution {
+    #[default]
+    Bits8,
+    /// Readings are sent LSB first.
+    Bits16,
+}
+
+impl OptoResolution {
+    #[must_use]
+    pub const fn bytes_per_reading(&self) -> usize {
+        match self {
+            Self::Bits8 => 1,
+            Self::Bits16 => 2,
+        }
+    }
+
+    #[must_use]
+    pub const fn full_scale(&self) -> u16 {
+        match self {
+            Self::Bits8 => u8::MAX as u16,
+            Self::Bits16 => u16::MAX,
+        }
+    }
+}
+
+/// Voltage a full scale reading corresponds to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OptoReference {
+    V3_3,
+    #[default]
+    V5_0,
+}
+
+impl OptoReference {
+    #[must_use]
+    pub const fn millivolts(&self) -> u16 {
+        match self {
+            Self::V3_3 => 3300,
+            Self::V5_0 => 5000,
+        }
+    }
+}
+
+/// Format of the data returned by header 148, read opto voltages.
+///
+/// The specification leaves the format to the product manual, it is typically 8 or 16 bits
+/// scaled to 3.3V or 5.0V.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OptoScaling {
+    pub resolution: OptoResolution,
+    pub reference: OptoReference,
+}
+
+impl OptoScaling {
+    #[must_use]
+    pub const fn new(resolution: OptoResolution, reference: OptoReference) -> Self {
+        Self {
+            resolution,
+            reference,
+        }
+    }
+
+    /// Converts a raw reading into millivolts.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn to_millivolts(&self, raw: u16) -> u16 {
+        let full_scale = self.resolution.full_scale() as u32;
+        let raw = if raw as u32 > full_scale {
+            full_scale
+        } else {
+            raw as u32
+        };
+        // Bounded by the reference voltage, which fits in a u16.
+        ((raw * self.reference.millivolts() as u32 + full_scale / 2) / full_scale) as u16
+    }
+
+    /// Builds a reading from its raw value.
+    #[must_use]
+    pub const fn reading(&self, raw: u16) -> OptoReading {
+        OptoReading {
+            raw,
+            millivolts: self.to_millivolts(raw),
+        }
+    }
+}
+
+/// A single opto voltage, as read and once scaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OptoReading {
+    pub raw: u16,
+    pub millivolts: u16,
+}
+
+impl OptoReading {
+    /// Returns true if the reading is under the given threshold, which usually means
+    /// the opto is dirty or degrading.
+    #[must_use]
+    pub const fn is_below(&self, millivolts: u16) -> bool {
+        self.millivolts < millivolts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn readings_are_scaled_to_the_reference() {
+        let scaling = OptoScaling::default();
+        assert_eq!(scaling.to_millivolts(0), 0);
+        assert_eq!(scaling.to_millivolts(255), 5000);
+        assert_eq!(scaling.to_millivolts(128), 2510);
+        // Out of range values are clamped to full scale.
+        assert_eq!(scaling.to_millivolts(1024), 5000);
+
+        let scaling = OptoScaling::new(OptoResolution::Bits16, OptoReference::V3_3);
+        assert_eq!(scaling.to_millivolts(u16::MAX), 3300);
+        assert_eq!(scaling.to_millivolts(32768), 1650);
+    }
+
+    #[test]
+    fn reading_threshold() {
+        let reading = OptoScaling::default().reading(51);
+        assert_eq!(reading.millivolts, 1000);
+        assert!(reading.is_below(1001));
+        assert!(!reading.is_below(1000));
+    }
+}
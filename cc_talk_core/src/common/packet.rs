@@ -1,3 +1,8 @@
+use super::{
+    checksum::{ChecksumError, ChecksumKind, ChecksumType},
+    packet_display::{PacketDisplay, PacketHexdump},
+};
+
 /// Maximum block length
 /// Destination + Data length + source + header + 255 bytes of data
 /// Total maximum size = 259 bytes
@@ -16,10 +21,7 @@ pub struct Packet<B> {
     buffer: B,
 }
 
-impl<B> Packet<B>
-where
-    B: AsMut<[u8]> + AsRef<[u8]>,
-{
+impl<B> Packet<B> {
     /// Creates a new `Packet` with the given buffer.
     ///
     /// # Examples
@@ -43,7 +45,57 @@ where
     pub const fn new(buffer: B) -> Self {
         Self { buffer }
     }
+}
+
+impl<B> Packet<B>
+where
+    B: AsRef<[u8]>,
+{
+    /// Returns a one line rendering of the frame fields, e.g.
+    /// `dst=1 src=2 header=Reply(0) len=2 data=[AB CD] checksum=83 crc8 ok`.
+    ///
+    /// The checksum type is detected from the frame, CRC8 first then CRC16. Frames shorter than
+    /// their length byte are marked as incomplete and frames without a header as truncated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cc_talk_core::cc_talk::*;
+    ///
+    /// let packet = Packet::new(&[2u8, 0, 1, 254, 255][..]);
+    /// assert_eq!(
+    ///     packet.display().to_string(),
+    ///     "dst=2 src=1 header=SimplePoll(254) len=0 data=[] checksum=FF crc8 ok"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn display(&self) -> PacketDisplay<'_> {
+        PacketDisplay::new(self.buffer.as_ref())
+    }
+
+    /// Returns a multi-line hexdump of the frame, one annotated line per field and up to 16
+    /// data bytes per line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cc_talk_core::cc_talk::*;
+    ///
+    /// let packet = Packet::new(&[2u8, 0, 1, 254, 255][..]);
+    /// let dump = packet.hexdump().to_string();
+    /// assert!(dump.starts_with("0000  02"));
+    /// assert!(dump.ends_with("checksum crc8 ok"));
+    /// ```
+    #[must_use]
+    pub fn hexdump(&self) -> PacketHexdump<'_> {
+        PacketHexdump::new(self.buffer.as_ref())
+    }
+}
 
+impl<B> Packet<B>
+where
+    B: AsMut<[u8]> + AsRef<[u8]>,
+{
     pub fn as_slice(&self) -> &[u8] {
         self.buffer.as_ref()
     }
@@ -271,6 +323,110 @@ where
     }
 }
 
+impl<'a> Packet<&'a [u8]> {
+    /// Validates a received frame and returns its fields.
+    ///
+    /// The frame must hold exactly one block: the length byte has to match the buffer length,
+    /// the header has to be known and the checksum has to match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cc_talk_core::cc_talk::*;
+    ///
+    /// let packet = Packet::parse(&[1, 0, 2, 0, 253], ChecksumType::Crc8).unwrap();
+    /// assert_eq!(packet.source(), Some(2));
+    /// assert_eq!(packet.header(), Header::Reply);
+    /// assert!(packet.data().is_empty());
+    ///
+    /// assert!(Packet::parse(&[1, 0, 2, 0, 254], ChecksumType::Crc8).is_err());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`PacketError::DataLengthMismatch`] if the length byte does not match the frame length.
+    /// - [`PacketError::InvalidHeader`] if the header is unknown.
+    /// - [`PacketError::ChecksumMismatch`] if the checksum does not match.
+    pub fn parse(
+        frame: &'a [u8],
+        checksum_type: ChecksumType,
+    ) -> Result<ValidatedPacket<'a>, PacketError> {
+        let [destination, data_length, _, header, ..] = *frame else {
+            return Err(PacketError::DataLengthMismatch);
+        };
+        let checksum_offset = DATA_OFFSET + usize::from(data_length);
+        if frame.len() != checksum_offset + 1 {
+            return Err(PacketError::DataLengthMismatch);
+        }
+        let header = Header::try_from(header)?;
+
+        // The source byte of CRC16 frames holds part of the checksum.
+        let source = checksum_type.verify(frame).map_err(|error| match error {
+            ChecksumError::FrameTooShort => PacketError::DataLengthMismatch,
+            ChecksumError::Mismatch { expected, actual } => {
+                PacketError::ChecksumMismatch { expected, actual }
+            }
+        })?;
+
+        Ok(ValidatedPacket {
+            frame,
+            destination,
+            source,
+            header,
+            data: &frame[DATA_OFFSET..checksum_offset],
+            checksum_type,
+        })
+    }
+}
+
+/// A frame checked by [`Packet::parse`], its fields borrow the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidatedPacket<'a> {
+    frame: &'a [u8],
+    destination: u8,
+    source: Option<u8>,
+    header: Header,
+    data: &'a [u8],
+    checksum_type: ChecksumType,
+}
+
+impl<'a> ValidatedPacket<'a> {
+    #[must_use]
+    pub const fn destination(&self) -> u8 {
+        self.destination
+    }
+
+    /// Returns the source address, `None` for CRC16 frames.
+    ///
+    /// The source byte of a CRC16 frame holds the checksum LSB, the source is implied by the
+    /// transaction: the host for a request, the addressed device for its reply.
+    #[must_use]
+    pub const fn source(&self) -> Option<u8> {
+        self.source
+    }
+
+    #[must_use]
+    pub const fn header(&self) -> Header {
+        self.header
+    }
+
+    #[must_use]
+    pub const fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    #[must_use]
+    pub const fn checksum_type(&self) -> ChecksumType {
+        self.checksum_type
+    }
+
+    /// Returns the whole frame, checksum included.
+    #[must_use]
+    pub const fn as_slice(&self) -> &'a [u8] {
+        self.frame
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PacketError {
@@ -282,6 +438,8 @@ pub enum PacketError {
     InvalidHeader(u8),
     #[error("The packet couldnt be validated")]
     InvalidPacket,
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: u16, actual: u16 },
 }
 
 /// ccTalk headers enum
@@ -2679,168 +2837,205 @@ pub enum Header {
     Reply = 0,
 }
 
+/// Declares [`Header::ALL`] from the header names.
+///
+/// The list fails to compile if a header is missing, and [`HEADER_LOOKUP`] if one is repeated.
+macro_rules! all_headers {
+    ($($variant:ident),* $(,)?) => {
+        impl Header {
+            /// Every header, in declaration order.
+            pub const ALL: &'static [Self] = &[$(Self::$variant),*];
+        }
+
+        const _: () = {
+            const fn exhaustive(header: Header) {
+                match header {
+                    $(Header::$variant)|* => {}
+                }
+            }
+            exhaustive(Header::Reply);
+        };
+    };
+}
+
+all_headers! {
+    SimplePoll, AddressPoll, AddressClash, AddressChange, AddressRandom, RequestPollingPriority,
+    RequestStatus, RequestVariableSet, RequestManufacturerId, RequestEquipementCategoryId,
+    RequestProductCode, RequestDatabaseVersion, RequestSerialNumber, RequestSoftwareRevision,
+    TestSolenoids, OperateMotors, TestOutputLines, ReadInputLines, ReadOptoStates, ReadDHPubKey,
+    SendDHPubKey, LatchOutputLines, PerformSelfCheck, ModifyInhibitStatus, RequestInhibitStatus,
+    ReadBufferedCreditOrErrorCodes, ModifyMasterInhibitStatus, RequestMasterInhibitStatus,
+    RequestInsertionCounter, RequestAcceptCounter, RequestEncryptedProductId,
+    ModifyEncryptedInhibitAndOverrideRegisters, ModifySorterOverrideStatus,
+    RequestSorterOverrideStatus, ACMIEncryptedData, EnterNewPinNumber, EnterPinNumber,
+    RequestPayoutStatus, RequestDataStorageAvailability, ReadDataBlock, WriteDataBlock,
+    RequestOptionFlags, RequestCoinPosition, PowerManagementControl, ModifySorterPaths,
+    RequestSorterPaths, ModifyPayoutAbsoluteCount, RequestPayoutAbsoluteCount, MeterControl,
+    DisplayControl, TeachModeControl, RequestTeachStatus, ACMIUnencryptedProductId,
+    ConfigurationToEEPROM, CountersToEEPROM, CalculateROMChecksum, RequestCreationDate,
+    RequestLastModificationDate, RequestRejectCounter, RequestFraudCounter, RequestBuildCode,
+    KeypadControl, ModifyDefaultSorterPath, RequestDefaultSorterPath, ModifyPayoutCapacity,
+    RequestPayoutCapacity, ModifyCoinId, RequestCoinId, UploadWindowData, DownloadCalibrationInfo,
+    ModifySecuritySetting, RequestSecuritySetting, ModifyBankSelect, RequestBankSelect,
+    HandheldFunction, RequestAlarmCounter, ModifyPayoutFloat, RequestPayoutFloat,
+    RequestThermistorReading, EmergencyStop, RequestHopperCoin, RequestBaseYear, RequestAddressMode,
+    RequestHopperDispenseCount, DispenseHopperCoins, RequestHopperStatus, ModifyVariableSet,
+    EnableHopper, TestHopper, ModifyInhibitAndOverrideRegisters, PumpRNG, RequestCipherKey,
+    ReadBufferedBillEvents, ModifyBillId, RequestBillId, RequestCountryScalingFactor,
+    RequestBillPosition, RouteBill, ModifyBillOperatingMode, RequestBillOperatingMode, TestLamps,
+    RequestIndividualAcceptCounter, RequestIndividualErrorCounter, ReadOptoVoltages,
+    PerformStackerCycle, OperateBiDirectionalMotors, RequestCurrencyRevision, UploadBillTables,
+    BeginBillTableUpgrade, FinishBillTableUpgrade, RequestFirmwareUpgradeCapability, UploadFirmware,
+    BeginFirmwareUpgrade, FinishFirmwareUpgrade, SwitchEncryptionMode, StoreEncryptionMode,
+    SetAcceptLimit, DispenseHopperValue, RequestHopperPollingValue, EmergencyStopValue,
+    RequestHopperCoinValue, RequestIndexedHopperDispenseCount, ReadBarCodeData, RequestMoneyIn,
+    RequestMoneyOut, ClearMoneyCounters, PayMoneyOut, VerifyMoneyOut, RequestActivityRegister,
+    RequestErrorStatus, PurgeHopper, ModifyHopperBalance, RequestHopperBalance, ModifyCashBoxValue,
+    RequestCashBoxValue, ModifyRealTimeClock, RequestRealTimeClock, RequestUsbId, SwitchBaudRate,
+    ReadEncryptedEvents, RequestEncryptionSupport, SwitchEncryptionKey,
+    RequestEncryptedHopperStatus, RequestEncryptedMonetaryId, OperateEscrow, RequestEscrowStatus,
+    DataStream, RequestServiceStatus, Busy, NACK, RequestCommsRevision, ClearCommsStatusVariable,
+    RequestCommsStatusVariables, ResetDevice, Reply,
+}
+
+/// Maps every byte to its header, built at compile time from [`Header::ALL`].
+///
+/// Building the table fails to compile if two headers share a value.
+const HEADER_LOOKUP: [Option<Header>; 256] = {
+    let mut table = [None; 256];
+    let mut i = 0;
+    while i < Header::ALL.len() {
+        let header = Header::ALL[i];
+        assert!(
+            table[header as usize].is_none(),
+            "two headers share the same value"
+        );
+        table[header as usize] = Some(header);
+        i += 1;
+    }
+    table
+};
+
+impl Header {
+    /// Reply timeout for headers which make the device perform a long operation before
+    /// replying, `None` for every other header.
+    ///
+    /// The time to execute these commands should be in the product manual, the returned values
+    /// are conservative defaults.
+    #[must_use]
+    pub const fn long_operation_timeout(&self) -> Option<core::time::Duration> {
+        match self {
+            Self::PerformSelfCheck => Some(core::time::Duration::from_secs(2)),
+            Self::PerformStackerCycle => Some(core::time::Duration::from_secs(5)),
+            Self::ConfigurationToEEPROM | Self::CountersToEEPROM | Self::WriteDataBlock => {
+                Some(core::time::Duration::from_secs(1))
+            }
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn is_long_operation(&self) -> bool {
+        self.long_operation_timeout().is_some()
+    }
+}
+
+impl Header {
+    /// Returns the header with the given value, `None` if no header has it.
+    #[must_use]
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        HEADER_LOOKUP[value as usize]
+    }
+}
+
 impl TryFrom<u8> for Header {
     type Error = PacketError;
 
-    #[allow(clippy::too_many_lines)]
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            254 => Ok(Self::SimplePoll),
-            253 => Ok(Self::AddressPoll),
-            252 => Ok(Self::AddressClash),
-            251 => Ok(Self::AddressChange),
-            250 => Ok(Self::AddressRandom),
-            249 => Ok(Self::RequestPollingPriority),
-            248 => Ok(Self::RequestStatus),
-            247 => Ok(Self::RequestVariableSet),
-            246 => Ok(Self::RequestManufacturerId),
-            245 => Ok(Self::RequestEquipementCategoryId),
-            244 => Ok(Self::RequestProductCode),
-            243 => Ok(Self::RequestDatabaseVersion),
-            242 => Ok(Self::RequestSerialNumber),
-            241 => Ok(Self::RequestSoftwareRevision),
-            240 => Ok(Self::TestSolenoids),
-            239 => Ok(Self::OperateMotors),
-            238 => Ok(Self::TestOutputLines),
-            237 => Ok(Self::ReadInputLines),
-            236 => Ok(Self::ReadOptoStates),
-            235 => Ok(Self::ReadDHPubKey),
-            234 => Ok(Self::SendDHPubKey),
-            233 => Ok(Self::LatchOutputLines),
-            232 => Ok(Self::PerformSelfCheck),
-            231 => Ok(Self::ModifyInhibitStatus),
-            230 => Ok(Self::RequestInhibitStatus),
-            229 => Ok(Self::ReadBufferedCreditOrErrorCodes),
-            228 => Ok(Self::ModifyMasterInhibitStatus),
-            227 => Ok(Self::RequestMasterInhibitStatus),
-            226 => Ok(Self::RequestInsertionCounter),
-            225 => Ok(Self::RequestAcceptCounter),
-            224 => Ok(Self::RequestEncryptedProductId),
-            223 => Ok(Self::ModifyEncryptedInhibitAndOverrideRegisters),
-            222 => Ok(Self::ModifySorterOverrideStatus),
-            221 => Ok(Self::RequestSorterOverrideStatus),
-            220 => Ok(Self::ACMIEncryptedData),
-            219 => Ok(Self::EnterNewPinNumber),
-            218 => Ok(Self::EnterPinNumber),
-            217 => Ok(Self::RequestPayoutStatus),
-            216 => Ok(Self::RequestDataStorageAvailability),
-            215 => Ok(Self::ReadDataBlock),
-            214 => Ok(Self::WriteDataBlock),
-            213 => Ok(Self::RequestOptionFlags),
-            212 => Ok(Self::RequestCoinPosition),
-            211 => Ok(Self::PowerManagementControl),
-            210 => Ok(Self::ModifySorterPaths),
-            209 => Ok(Self::RequestSorterPaths),
-            208 => Ok(Self::ModifyPayoutAbsoluteCount),
-            207 => Ok(Self::RequestPayoutAbsoluteCount),
-            204 => Ok(Self::MeterControl),
-            203 => Ok(Self::DisplayControl),
-            202 => Ok(Self::TeachModeControl),
-            201 => Ok(Self::RequestTeachStatus),
-            200 => Ok(Self::ACMIUnencryptedProductId),
-            199 => Ok(Self::ConfigurationToEEPROM),
-            198 => Ok(Self::CountersToEEPROM),
-            197 => Ok(Self::CalculateROMChecksum),
-            196 => Ok(Self::RequestCreationDate),
-            195 => Ok(Self::RequestLastModificationDate),
-            194 => Ok(Self::RequestRejectCounter),
-            193 => Ok(Self::RequestFraudCounter),
-            192 => Ok(Self::RequestBuildCode),
-            191 => Ok(Self::KeypadControl),
-            189 => Ok(Self::ModifyDefaultSorterPath),
-            188 => Ok(Self::RequestDefaultSorterPath),
-            187 => Ok(Self::ModifyPayoutCapacity),
-            186 => Ok(Self::RequestPayoutCapacity),
-            185 => Ok(Self::ModifyCoinId),
-            184 => Ok(Self::RequestCoinId),
-            183 => Ok(Self::UploadWindowData),
-            182 => Ok(Self::DownloadCalibrationInfo),
-            181 => Ok(Self::ModifySecuritySetting),
-            180 => Ok(Self::RequestSecuritySetting),
-            179 => Ok(Self::ModifyBankSelect),
-            178 => Ok(Self::RequestBankSelect),
-            177 => Ok(Self::HandheldFunction),
-            176 => Ok(Self::RequestAlarmCounter),
-            175 => Ok(Self::ModifyPayoutFloat),
-            174 => Ok(Self::RequestPayoutFloat),
-            173 => Ok(Self::RequestThermistorReading),
-            172 => Ok(Self::EmergencyStop),
-            171 => Ok(Self::RequestHopperCoin),
-            170 => Ok(Self::RequestBaseYear),
-            169 => Ok(Self::RequestAddressMode),
-            168 => Ok(Self::RequestHopperDispenseCount),
-            167 => Ok(Self::DispenseHopperCoins),
-            166 => Ok(Self::RequestHopperStatus),
-            165 => Ok(Self::ModifyVariableSet),
-            164 => Ok(Self::EnableHopper),
-            163 => Ok(Self::TestHopper),
-            162 => Ok(Self::ModifyInhibitAndOverrideRegisters),
-            161 => Ok(Self::PumpRNG),
-            160 => Ok(Self::RequestCipherKey),
-            159 => Ok(Self::ReadBufferedBillEvents),
-            158 => Ok(Self::ModifyBillId),
-            157 => Ok(Self::RequestBillId),
-            156 => Ok(Self::RequestCountryScalingFactor),
-            155 => Ok(Self::RequestBillPosition),
-            154 => Ok(Self::RouteBill),
-            153 => Ok(Self::ModifyBillOperatingMode),
-            152 => Ok(Self::RequestBillOperatingMode),
-            151 => Ok(Self::TestLamps),
-            150 => Ok(Self::RequestIndividualAcceptCounter),
-            149 => Ok(Self::RequestIndividualErrorCounter),
-            148 => Ok(Self::ReadOptoVoltages),
-            147 => Ok(Self::PerformStackerCycle),
-            146 => Ok(Self::OperateBiDirectionalMotors),
-            145 => Ok(Self::RequestCurrencyRevision),
-            144 => Ok(Self::UploadBillTables),
-            143 => Ok(Self::BeginBillTableUpgrade),
-            142 => Ok(Self::FinishBillTableUpgrade),
-            141 => Ok(Self::RequestFirmwareUpgradeCapability),
-            140 => Ok(Self::UploadFirmware),
-            139 => Ok(Self::BeginFirmwareUpgrade),
-            138 => Ok(Self::FinishFirmwareUpgrade),
-            137 => Ok(Self::SwitchEncryptionMode),
-            136 => Ok(Self::StoreEncryptionMode),
-            135 => Ok(Self::SetAcceptLimit),
-            134 => Ok(Self::DispenseHopperValue),
-            133 => Ok(Self::RequestHopperPollingValue),
-            132 => Ok(Self::EmergencyStopValue),
-            131 => Ok(Self::RequestHopperCoinValue),
-            130 => Ok(Self::RequestIndexedHopperDispenseCount),
-            129 => Ok(Self::ReadBarCodeData),
-            128 => Ok(Self::RequestMoneyIn),
-            127 => Ok(Self::RequestMoneyOut),
-            126 => Ok(Self::ClearMoneyCounters),
-            125 => Ok(Self::PayMoneyOut),
-            124 => Ok(Self::VerifyMoneyOut),
-            123 => Ok(Self::RequestActivityRegister),
-            122 => Ok(Self::RequestErrorStatus),
-            121 => Ok(Self::PurgeHopper),
-            120 => Ok(Self::ModifyHopperBalance),
-            119 => Ok(Self::RequestHopperBalance),
-            118 => Ok(Self::ModifyCashBoxValue),
-            117 => Ok(Self::RequestCashBoxValue),
-            116 => Ok(Self::ModifyRealTimeClock),
-            115 => Ok(Self::RequestRealTimeClock),
-            114 => Ok(Self::RequestUsbId),
-            113 => Ok(Self::SwitchBaudRate),
-            112 => Ok(Self::ReadEncryptedEvents),
-            111 => Ok(Self::RequestEncryptionSupport),
-            110 => Ok(Self::SwitchEncryptionKey),
-            109 => Ok(Self::RequestEncryptedHopperStatus),
-            108 => Ok(Self::RequestEncryptedMonetaryId),
-            107 => Ok(Self::OperateEscrow),
-            106 => Ok(Self::RequestEscrowStatus),
-            105 => Ok(Self::DataStream),
-            104 => Ok(Self::RequestServiceStatus),
-            6 => Ok(Self::Busy),
-            5 => Ok(Self::NACK),
-            4 => Ok(Self::RequestCommsRevision),
-            3 => Ok(Self::ClearCommsStatusVariable),
-            2 => Ok(Self::RequestCommsStatusVariables),
-            1 => Ok(Self::ResetDevice),
-            0 => Ok(Self::Reply),
-            _ => Err(PacketError::InvalidHeader(value)),
+        HEADER_LOOKUP[usize::from(value)].ok_or(PacketError::InvalidHeader(value))
+    }
+}
+
+impl From<Header> for u8 {
+    fn from(header: Header) -> Self {
+        header as Self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::checksum::crc16;
+
+    #[test]
+    fn parse_checks_length_header_and_checksum() {
+        let packet =
+            Packet::parse(&[1, 2, 40, 0, 7, 9, 197], ChecksumType::Crc8).expect("should be valid");
+        assert_eq!(packet.destination(), 1);
+        assert_eq!(packet.source(), Some(40));
+        assert_eq!(packet.header(), Header::Reply);
+        assert_eq!(packet.data(), &[7, 9]);
+
+        assert_eq!(
+            Packet::parse(&[1, 2, 40, 0, 7, 9], ChecksumType::Crc8),
+            Err(PacketError::DataLengthMismatch)
+        );
+        assert_eq!(
+            Packet::parse(&[1, 0, 2], ChecksumType::Crc8),
+            Err(PacketError::DataLengthMismatch)
+        );
+        assert_eq!(
+            Packet::parse(&[1, 0, 2, 7, 246], ChecksumType::Crc8),
+            Err(PacketError::InvalidHeader(7))
+        );
+        assert_eq!(
+            Packet::parse(&[1, 0, 2, 0, 252], ChecksumType::Crc8),
+            Err(PacketError::ChecksumMismatch {
+                expected: 253,
+                actual: 252
+            })
+        );
+    }
+
+    #[test]
+    fn header_lookup_matches_the_enum_values() {
+        let mut known = 0;
+        for value in 0..=u8::MAX {
+            if let Ok(header) = Header::try_from(value) {
+                assert_eq!(u8::from(header), value);
+                known += 1;
+            } else {
+                assert_eq!(
+                    Header::try_from(value),
+                    Err(PacketError::InvalidHeader(value))
+                );
+            }
+        }
+        assert_eq!(known, Header::ALL.len());
+        for &header in Header::ALL {
+            assert_eq!(Header::try_from(u8::from(header)), Ok(header));
         }
+        assert_eq!(Header::try_from(254), Ok(Header::SimplePoll));
+    }
+
+    #[test]
+    fn parse_crc16_frame() {
+        // The checksum LSB is sent in place of the source address.
+        let mut frame = [40, 0, 0, 1, 0];
+        let [lsb, msb] = crc16(&frame).to_le_bytes();
+        frame[SOURCE_OFFSET] = lsb;
+        frame[DATA_OFFSET] = msb;
+
+        let packet = Packet::parse(&frame, ChecksumType::Crc16).expect("should be valid");
+        assert_eq!(packet.destination(), 40);
+        assert_eq!(packet.source(), None);
+        assert_eq!(packet.header(), Header::ResetDevice);
+        assert!(packet.data().is_empty());
+
+        frame[DATA_OFFSET] = msb.wrapping_add(1);
+        assert!(matches!(
+            Packet::parse(&frame, ChecksumType::Crc16),
+            Err(PacketError::ChecksumMismatch { .. })
+        ));
     }
 }
@@ -0,0 +1,149 @@
+use super::{
+    bus_address::BusAddress,
+    checksum::{ChecksumKind, ChecksumType},
+    packet::{
+        Header, PacketError, DATA_LENGTH_OFFSET, DATA_OFFSET, DESTINATION_OFFSET, HEADER_OFFSET,
+        SOURCE_OFFSET,
+    },
+};
+
+/// Writes complete, checksummed frames into caller buffers.
+///
+/// The source defaults to the host address and the checksum to CRC8, the CRC16 checksum
+/// overwrites the source byte.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let mut buffer = [0u8; MAX_BLOCK_LENGTH];
+/// let length = PacketBuilder::new(2, Header::ModifyInhibitStatus)
+///     .data(&[0xFF, 0x00])
+///     .write_to(&mut buffer)
+///     .unwrap();
+/// assert_eq!(&buffer[..length], [2, 2, 1, 231, 0xFF, 0x00, 21]);
+///
+/// let packet = Packet::parse(&buffer[..length], ChecksumType::Crc8).unwrap();
+/// assert_eq!(packet.data(), [0xFF, 0x00]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use]
+pub struct PacketBuilder<'a> {
+    destination: u8,
+    source: u8,
+    header: Header,
+    data: &'a [u8],
+    checksum_type: ChecksumType,
+}
+
+impl<'a> PacketBuilder<'a> {
+    /// Starts a frame without data from the host to `destination`.
+    pub const fn new(destination: u8, header: Header) -> Self {
+        Self {
+            destination,
+            source: BusAddress::HOST.get(),
+            header,
+            data: &[],
+            checksum_type: ChecksumType::Crc8,
+        }
+    }
+
+    /// Sets the source address, e.g. for a device replying to the host.
+    pub const fn source(mut self, source: u8) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub const fn data(mut self, data: &'a [u8]) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub const fn checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+        self.checksum_type = checksum_type;
+        self
+    }
+
+    /// Returns the length of the frame, its checksum included.
+    #[must_use]
+    pub const fn frame_length(&self) -> usize {
+        DATA_OFFSET + self.data.len() + 1
+    }
+
+    /// Writes the frame at the start of `buffer`, returns the written length.
+    ///
+    /// The bytes of `buffer` past the frame are left untouched.
+    ///
+    /// # Errors
+    ///
+    /// - [`PacketError::DataLengthMismatch`] if the data is longer than 255 bytes.
+    /// - [`PacketError::OutOfBounds`] if the frame does not fit in `buffer`.
+    pub fn write_to<B>(&self, buffer: &mut B) -> Result<usize, PacketError>
+    where
+        B: AsMut<[u8]> + ?Sized,
+    {
+        let data_length =
+            u8::try_from(self.data.len()).map_err(|_| PacketError::DataLengthMismatch)?;
+        let length = self.frame_length();
+        let frame = buffer
+            .as_mut()
+            .get_mut(..length)
+            .ok_or(PacketError::OutOfBounds)?;
+        frame[DESTINATION_OFFSET] = self.destination;
+        frame[DATA_LENGTH_OFFSET] = data_length;
+        frame[SOURCE_OFFSET] = self.source;
+        frame[HEADER_OFFSET] = self.header as u8;
+        frame[DATA_OFFSET..length - 1].copy_from_slice(self.data);
+        self.checksum_type
+            .sign(frame)
+            .map_err(|_| PacketError::OutOfBounds)?;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cc_talk::{Packet, MAX_BLOCK_LENGTH};
+
+    use super::*;
+
+    #[test]
+    fn frames_are_written_and_signed() {
+        let mut buffer = [0xAAu8; 8];
+        let length = PacketBuilder::new(2, Header::SimplePoll)
+            .write_to(&mut buffer)
+            .expect("should fit");
+        assert_eq!(buffer[..length], [2, 0, 1, 254, 255]);
+        assert_eq!(buffer[length..], [0xAA; 3]);
+
+        let mut buffer = std::vec![0u8; MAX_BLOCK_LENGTH];
+        let length = PacketBuilder::new(1, Header::Reply)
+            .source(3)
+            .data(&[7, 8])
+            .checksum_type(ChecksumType::Crc16)
+            .write_to(&mut buffer)
+            .expect("should fit");
+        let packet = Packet::parse(&buffer[..length], ChecksumType::Crc16).expect("should parse");
+        assert_eq!((packet.destination(), packet.data()), (1, &[7, 8][..]));
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let mut buffer = [0u8; 5];
+        assert_eq!(
+            PacketBuilder::new(2, Header::SimplePoll)
+                .data(&[1])
+                .write_to(&mut buffer),
+            Err(PacketError::OutOfBounds)
+        );
+
+        let mut buffer = [0u8; 512];
+        assert_eq!(
+            PacketBuilder::new(2, Header::SimplePoll)
+                .data(&[0; 256])
+                .write_to(&mut buffer),
+            Err(PacketError::DataLengthMismatch)
+        );
+    }
+}
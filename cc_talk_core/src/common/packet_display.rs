@@ -0,0 +1,302 @@
+use core::fmt;
+
+use super::{
+    checksum::ChecksumType,
+    packet::{Header, DATA_OFFSET},
+};
+
+/// Number of data bytes per line of a [`PacketHexdump`].
+const HEXDUMP_ROW_LENGTH: usize = 16;
+
+/// Checksum state of a complete frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChecksumCheck {
+    Valid(ChecksumType),
+    Invalid,
+}
+
+impl ChecksumCheck {
+    fn of(frame: &[u8]) -> Self {
+        ChecksumType::detect(frame).map_or(Self::Invalid, Self::Valid)
+    }
+}
+
+impl fmt::Display for ChecksumCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Valid(ChecksumType::Crc8) => write!(f, "crc8 ok"),
+            Self::Valid(ChecksumType::Crc16) => write!(f, "crc16 ok"),
+            Self::Invalid => write!(f, "bad"),
+        }
+    }
+}
+
+/// Fields of a frame which holds at least a header.
+struct Fields<'a> {
+    destination: u8,
+    length: u8,
+    source: u8,
+    header: u8,
+    data: &'a [u8],
+    /// `None` when the frame is shorter than its length byte.
+    checksum: Option<(u8, ChecksumCheck)>,
+}
+
+impl<'a> Fields<'a> {
+    fn of(frame: &'a [u8]) -> Option<Self> {
+        let [destination, length, source, header, ..] = *frame else {
+            return None;
+        };
+        let checksum_offset = DATA_OFFSET + usize::from(length);
+        let checksum = frame
+            .get(checksum_offset)
+            .map(|&checksum| (checksum, ChecksumCheck::of(&frame[..=checksum_offset])));
+        let source = match checksum {
+            // The source byte holds part of the checksum, CRC16 frames come from the host.
+            Some((_, ChecksumCheck::Valid(ChecksumType::Crc16))) => 1,
+            _ => source,
+        };
+        Some(Self {
+            destination,
+            length,
+            source,
+            header,
+            data: &frame[DATA_OFFSET..checksum_offset.min(frame.len())],
+            checksum,
+        })
+    }
+}
+
+/// Header name followed by its value, `unknown` for unknown headers.
+struct HeaderName(u8);
+
+impl fmt::Display for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Header::try_from(self.0) {
+            Ok(header) => write!(f, "{header:?}({})", self.0),
+            Err(_) => write!(f, "unknown({})", self.0),
+        }
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{byte:02X}")?;
+    }
+    Ok(())
+}
+
+/// One line rendering of a frame, see [`Packet::display`](super::packet::Packet::display).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketDisplay<'a> {
+    frame: &'a [u8],
+}
+
+impl<'a> PacketDisplay<'a> {
+    /// Renders `frame`, which may be incomplete or hold an invalid checksum.
+    #[must_use]
+    pub const fn new(frame: &'a [u8]) -> Self {
+        Self { frame }
+    }
+}
+
+impl fmt::Display for PacketDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(fields) = Fields::of(self.frame) else {
+            write!(f, "truncated [")?;
+            write_hex(f, self.frame)?;
+            return write!(f, "]");
+        };
+        write!(
+            f,
+            "dst={} src={} header={} len={} data=[",
+            fields.destination,
+            fields.source,
+            HeaderName(fields.header),
+            fields.length
+        )?;
+        write_hex(f, fields.data)?;
+        write!(f, "]")?;
+        match fields.checksum {
+            Some((checksum, check)) => write!(f, " checksum={checksum:02X} {check}"),
+            None => write!(f, " (incomplete)"),
+        }
+    }
+}
+
+/// Multi-line annotated hexdump of a frame, see
+/// [`Packet::hexdump`](super::packet::Packet::hexdump).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHexdump<'a> {
+    frame: &'a [u8],
+}
+
+impl<'a> PacketHexdump<'a> {
+    /// Dumps `frame`, which may be incomplete or hold an invalid checksum.
+    #[must_use]
+    pub const fn new(frame: &'a [u8]) -> Self {
+        Self { frame }
+    }
+
+    fn write_line(
+        f: &mut fmt::Formatter<'_>,
+        offset: usize,
+        bytes: &[u8],
+        annotation: fmt::Arguments<'_>,
+    ) -> fmt::Result {
+        if offset > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{offset:04X}  ")?;
+        write_hex(f, bytes)?;
+        let width = HEXDUMP_ROW_LENGTH * 3 - 1;
+        let padding = width.saturating_sub((bytes.len() * 3).saturating_sub(1));
+        write!(f, "{:padding$}  {annotation}", "")
+    }
+}
+
+impl fmt::Display for PacketHexdump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(fields) = Fields::of(self.frame) else {
+            for (offset, byte) in self.frame.iter().enumerate() {
+                Self::write_line(f, offset, &[*byte], format_args!("truncated"))?;
+            }
+            return Ok(());
+        };
+        let crc16 = matches!(
+            fields.checksum,
+            Some((_, ChecksumCheck::Valid(ChecksumType::Crc16)))
+        );
+        Self::write_line(
+            f,
+            0,
+            &self.frame[..1],
+            format_args!("destination {}", fields.destination),
+        )?;
+        Self::write_line(
+            f,
+            1,
+            &self.frame[1..2],
+            format_args!("data length {}", fields.length),
+        )?;
+        if crc16 {
+            Self::write_line(f, 2, &self.frame[2..3], format_args!("checksum lsb"))?;
+        } else {
+            Self::write_line(
+                f,
+                2,
+                &self.frame[2..3],
+                format_args!("source {}", fields.source),
+            )?;
+        }
+        Self::write_line(
+            f,
+            3,
+            &self.frame[3..4],
+            format_args!("header {}", HeaderName(fields.header)),
+        )?;
+        for (row, chunk) in fields.data.chunks(HEXDUMP_ROW_LENGTH).enumerate() {
+            Self::write_line(
+                f,
+                DATA_OFFSET + row * HEXDUMP_ROW_LENGTH,
+                chunk,
+                format_args!("data"),
+            )?;
+        }
+        let checksum_offset = DATA_OFFSET + fields.data.len();
+        let Some((checksum, check)) = fields.checksum else {
+            let missing = usize::from(fields.length) + DATA_OFFSET + 1 - self.frame.len();
+            return Self::write_line(
+                f,
+                checksum_offset,
+                &[],
+                format_args!("incomplete, {missing} bytes missing"),
+            );
+        };
+        let label = if crc16 { "checksum msb" } else { "checksum" };
+        Self::write_line(
+            f,
+            checksum_offset,
+            &[checksum],
+            format_args!("{label} {check}"),
+        )?;
+        for (i, byte) in self.frame[checksum_offset + 1..].iter().enumerate() {
+            Self::write_line(
+                f,
+                checksum_offset + 1 + i,
+                &[*byte],
+                format_args!("trailing"),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::string::ToString;
+
+    use super::*;
+    use crate::common::{
+        checksum::{crc16, crc8},
+        packet::{Packet, SOURCE_OFFSET},
+    };
+
+    #[test]
+    fn display_renders_the_fields_and_checksum() {
+        assert_eq!(
+            Packet::new([1u8, 2, 2, 0, 0xAB, 0xCD, 0x83])
+                .display()
+                .to_string(),
+            "dst=1 src=2 header=Reply(0) len=2 data=[AB CD] checksum=83 crc8 ok"
+        );
+        assert_eq!(
+            Packet::new([1u8, 2, 2, 0, 0xAB, 0xCD, 0x10])
+                .display()
+                .to_string(),
+            "dst=1 src=2 header=Reply(0) len=2 data=[AB CD] checksum=10 bad"
+        );
+        assert_eq!(
+            Packet::new([1u8, 4, 2, 7, 1]).display().to_string(),
+            "dst=1 src=2 header=unknown(7) len=4 data=[01] (incomplete)"
+        );
+        assert_eq!(
+            Packet::new([1u8, 4]).display().to_string(),
+            "truncated [01 04]"
+        );
+    }
+
+    #[test]
+    fn display_detects_crc16_frames() {
+        let mut frame = [40u8, 0, 0, 1, 0];
+        let [lsb, msb] = crc16(&frame).to_le_bytes();
+        frame[SOURCE_OFFSET] = lsb;
+        frame[DATA_OFFSET] = msb;
+        let rendered = Packet::new(frame).display().to_string();
+        assert!(rendered.starts_with("dst=40 src=1 header=ResetDevice(1)"));
+        assert!(rendered.ends_with("crc16 ok"));
+    }
+
+    #[test]
+    fn hexdump_annotates_every_field() {
+        let mut frame = std::vec![1u8, 18, 2, 0];
+        frame.extend(0..18);
+        frame.push(crc8(&frame));
+        let dump = Packet::new(frame).hexdump().to_string();
+        let lines: std::vec::Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], std::format!("0000  01{:47}destination 1", ""));
+        assert_eq!(lines[3], std::format!("0003  00{:47}header Reply(0)", ""));
+        assert!(lines[4].starts_with("0004  00 01 02"));
+        assert!(lines[4].ends_with("0F  data"));
+        assert!(lines[5].starts_with("0014  10 11 "));
+        assert!(lines[6].starts_with("0016  "));
+        assert!(lines[6].ends_with("checksum crc8 ok"));
+
+        let dump = Packet::new([1u8, 4, 2, 0, 1]).hexdump().to_string();
+        assert!(dump.ends_with("incomplete, 4 bytes missing"));
+    }
+}
@@ -0,0 +1,206 @@
+use core::time::Duration;
+
+use super::{
+    checksum::ChecksumType,
+    packet::{Packet, ValidatedPacket, DATA_LENGTH_OFFSET, DATA_OFFSET},
+};
+
+/// Longest frame, 255 bytes of data.
+const MAX_FRAME_LENGTH: usize = DATA_OFFSET + u8::MAX as usize + 1;
+
+/// Assembles frames from bytes received one at a time, e.g. from a UART interrupt or from the
+/// chunks of a socket read.
+///
+/// A frame is emitted once its checksum matches. Bytes which do not start a valid frame are
+/// dropped one at a time until the buffered bytes line up with a frame again, a partial frame is
+/// dropped when the gap since its last byte exceeds the inter-byte timeout. After garbage, the
+/// bytes buffered past a frame are kept as the start of the next one.
+///
+/// Timestamps are read from any monotonic clock, only their differences are used. Encrypted
+/// frames have to be decrypted before their checksum can be verified, the framer only handles
+/// unencrypted lines.
+///
+/// # Examples
+///
+/// ```
+/// use core::time::Duration;
+///
+/// use cc_talk_core::cc_talk::*;
+///
+/// let mut framer = PacketFramer::new(ChecksumType::Crc8);
+/// let mut frames = 0;
+/// for (index, byte) in [0xFF, 1, 0, 2, 0, 253].into_iter().enumerate() {
+///     if let Some(packet) = framer.push(byte, Duration::from_millis(index as u64)) {
+///         assert_eq!(packet.source(), Some(2));
+///         frames += 1;
+///     }
+/// }
+/// assert_eq!((frames, framer.discarded()), (1, 1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PacketFramer {
+    buffer: [u8; MAX_FRAME_LENGTH],
+    length: usize,
+    /// Length of the frame returned by the last push, at the start of the buffer.
+    emitted: usize,
+    checksum_type: ChecksumType,
+    inter_byte_timeout: Option<Duration>,
+    last_byte_at: Option<Duration>,
+    discarded: usize,
+}
+
+impl PacketFramer {
+    /// Creates a framer for frames signed with `checksum_type`, without inter-byte timeout.
+    #[must_use]
+    pub const fn new(checksum_type: ChecksumType) -> Self {
+        Self {
+            buffer: [0; MAX_FRAME_LENGTH],
+            length: 0,
+            emitted: 0,
+            checksum_type,
+            inter_byte_timeout: None,
+            last_byte_at: None,
+            discarded: 0,
+        }
+    }
+
+    /// Drops a partial frame when no byte was pushed for longer than `timeout`.
+    #[must_use]
+    pub const fn with_inter_byte_timeout(mut self, timeout: Duration) -> Self {
+        self.inter_byte_timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the number of bytes buffered for the next frame.
+    #[must_use]
+    pub const fn pending(&self) -> usize {
+        self.length - self.emitted
+    }
+
+    /// Returns the number of bytes dropped since the framer was created, garbage and timed out
+    /// partial frames included.
+    #[must_use]
+    pub const fn discarded(&self) -> usize {
+        self.discarded
+    }
+
+    /// Drops the partial frame, e.g. after writing a request on a half-duplex line.
+    pub const fn reset(&mut self) {
+        self.length = 0;
+        self.emitted = 0;
+        self.last_byte_at = None;
+    }
+
+    /// Adds a byte received at `now`, returns the frame it completes.
+    ///
+    /// The returned frame borrows the framer, it is dropped by the next push.
+    pub fn push(&mut self, byte: u8, now: Duration) -> Option<ValidatedPacket<'_>> {
+        if self.emitted > 0 {
+            self.buffer.copy_within(self.emitted..self.length, 0);
+            self.length -= self.emitted;
+            self.emitted = 0;
+        }
+        let stale = match (self.inter_byte_timeout, self.last_byte_at) {
+            (Some(timeout), Some(last_byte_at)) => now.saturating_sub(last_byte_at) > timeout,
+            _ => false,
+        };
+        if stale {
+            self.discarded += self.length;
+            self.length = 0;
+        }
+        self.last_byte_at = Some(now);
+
+        self.buffer[self.length] = byte;
+        self.length += 1;
+        self.emitted = self.resync()?;
+        Packet::parse(&self.buffer[..self.emitted], self.checksum_type).ok()
+    }
+
+    /// Drops leading bytes until the buffer starts with a partial frame or a valid one, returns
+    /// the length of the valid frame.
+    ///
+    /// A garbage byte read as a long data length would hold back the frames behind it, a valid
+    /// frame ending with the last byte takes precedence over a partial frame.
+    fn resync(&mut self) -> Option<usize> {
+        loop {
+            if self.length <= DATA_LENGTH_OFFSET {
+                return None;
+            }
+            let frame_length = DATA_OFFSET + usize::from(self.buffer[DATA_LENGTH_OFFSET]) + 1;
+            if self.length < frame_length {
+                let start = (1..self.length).find(|&start| self.ends_frame(start))?;
+                self.buffer.copy_within(start..self.length, 0);
+                self.length -= start;
+                self.discarded += start;
+                return Some(self.length);
+            }
+            if Packet::parse(&self.buffer[..frame_length], self.checksum_type).is_ok() {
+                return Some(frame_length);
+            }
+            self.buffer.copy_within(1..self.length, 0);
+            self.length -= 1;
+            self.discarded += 1;
+        }
+    }
+
+    /// Returns `true` when the buffered bytes from `start` are exactly one valid frame.
+    fn ends_frame(&self, start: usize) -> bool {
+        let candidate = &self.buffer[start..self.length];
+        candidate.len() > DATA_OFFSET
+            && usize::from(candidate[DATA_LENGTH_OFFSET]) + DATA_OFFSET + 1 == candidate.len()
+            && Packet::parse(candidate, self.checksum_type).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed(framer: &mut PacketFramer, bytes: &[u8], start: u64) -> usize {
+        let mut count = 0;
+        for (offset, byte) in bytes.iter().enumerate() {
+            let now = Duration::from_millis(start + offset as u64);
+            if framer.push(*byte, now).is_some() {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn frames_split_across_reads_are_assembled() {
+        let mut framer = PacketFramer::new(ChecksumType::Crc8);
+        assert_eq!(feed(&mut framer, &[1, 2, 2], 0), 0);
+        assert_eq!(framer.pending(), 3);
+        let mut packet = None;
+        framer.push(0, Duration::ZERO);
+        for byte in [0xAB, 0xCD, 0x83] {
+            packet = framer
+                .push(byte, Duration::ZERO)
+                .map(|packet| packet.data()[0]);
+        }
+        assert_eq!(packet, Some(0xAB));
+        assert_eq!(framer.pending(), 0);
+    }
+
+    #[test]
+    fn garbage_is_skipped() {
+        let mut framer = PacketFramer::new(ChecksumType::Crc8);
+        let count = feed(
+            &mut framer,
+            &[0x00, 0x03, 0x42, 1, 0, 2, 0, 253, 1, 0, 3, 0, 252],
+            0,
+        );
+        assert_eq!(count, 2);
+        assert_eq!(framer.discarded(), 3);
+    }
+
+    #[test]
+    fn stale_partial_frames_are_dropped() {
+        let mut framer = PacketFramer::new(ChecksumType::Crc8)
+            .with_inter_byte_timeout(Duration::from_millis(50));
+        assert_eq!(feed(&mut framer, &[1, 0, 2], 0), 0);
+        assert_eq!(feed(&mut framer, &[1, 0, 2, 0, 253], 100), 1);
+        assert_eq!(framer.discarded(), 3);
+    }
+}
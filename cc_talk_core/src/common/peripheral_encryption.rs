@@ -0,0 +1,370 @@
+use super::{
+    checksum::crc16_bytes,
+    encrypted_events::{EncryptedEvents, EncryptedEventsError},
+    encrypted_monetary_id::{MonetaryCode, MonetaryId, MonetaryIdError},
+    hopper_encryption::HopperDesKey,
+};
+
+/// Algorithm of the encrypted replies of a coin acceptor or bill validator, `ReadEncryptedEvents`
+/// and `RequestEncryptedMonetaryId`.
+///
+/// The encryption of these replies is licensed separately from the ccTalk specification and is
+/// not part of this crate. Implement this trait with the standard of the peripheral.
+///
+/// Implementations should hide their keys from the `Debug` output.
+pub trait PeripheralCipher: core::fmt::Debug {
+    /// Decrypts a `ReadEncryptedEvents` reply and checks it answers `challenge`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the reply does not decrypt into events answering `challenge`.
+    fn decrypt_events(
+        &self,
+        challenge: u8,
+        reply: &[u8],
+    ) -> Result<EncryptedEvents, EncryptedEventsError>;
+
+    /// Decrypts a `RequestEncryptedMonetaryId` reply and checks it answers `position` and
+    /// `challenge`, returns `None` if nothing is programmed at the position.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the reply does not decrypt into an id answering `position` and `challenge`.
+    fn decrypt_monetary_id(
+        &self,
+        position: u8,
+        challenge: u8,
+        reply: &[u8],
+    ) -> Result<Option<MonetaryId>, MonetaryIdError>;
+}
+
+/// DES based [`PeripheralCipher`] exercising encrypted peripherals in tests and emulators.
+///
+/// This is **not** the encryption of any peripheral, it only mirrors the shape of the replies.
+/// Every reply is 2 DES blocks holding a CRC-16, the challenge byte and the fields, the
+/// [`encrypt_events`](Self::encrypt_events) and
+/// [`encrypt_monetary_id`](Self::encrypt_monetary_id) methods build the replies of an emulated
+/// peripheral.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let cipher = DesPeripheralCipher::new(HopperDesKey::new([1, 2, 3, 4, 5, 6, 7, 8]));
+/// let events = EncryptedEvents {
+///     event_counter: 1,
+///     results: [[3, 1], [0, 0], [0, 0], [0, 0], [0, 0]],
+/// };
+/// let reply = cipher.encrypt_events(&events, 0x5A, [0x11, 0x22]);
+///
+/// assert_eq!(cipher.decrypt_events(0x5A, &reply), Ok(events));
+/// let result = CoinAcceptorPollResult::try_from((&events.buffer()[..], 0)).unwrap();
+/// assert_eq!(result.events.len(), 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DesPeripheralCipher {
+    key: HopperDesKey,
+}
+
+impl DesPeripheralCipher {
+    /// Length of an encrypted reply, 2 DES blocks.
+    pub const REPLY_LENGTH: usize = 16;
+
+    #[must_use]
+    pub const fn new(key: HopperDesKey) -> Self {
+        Self { key }
+    }
+
+    /// Encrypts `events` like a peripheral answering `challenge`, `random` are the 2 bytes mixed
+    /// into the plaintext.
+    #[must_use]
+    pub fn encrypt_events(
+        &self,
+        events: &EncryptedEvents,
+        challenge: u8,
+        random: [u8; 2],
+    ) -> [u8; Self::REPLY_LENGTH] {
+        let [r1, r2, r3, r4, r5] = events.results;
+        self.seal([
+            0,
+            random[0],
+            events.event_counter,
+            r1[0],
+            r1[1],
+            r2[0],
+            r2[1],
+            challenge,
+            random[1],
+            r3[0],
+            r3[1],
+            r4[0],
+            r4[1],
+            r5[0],
+            r5[1],
+            0,
+        ])
+    }
+
+    /// Encrypts `id` like a peripheral answering `challenge`, `random` is the byte mixed into the
+    /// plaintext.
+    #[must_use]
+    pub fn encrypt_monetary_id(
+        &self,
+        id: &MonetaryId,
+        challenge: u8,
+        random: u8,
+    ) -> [u8; Self::REPLY_LENGTH] {
+        let [c1, c2, c3] = id.code.to_bytes();
+        let mut digits = [b'0'; 4];
+        let mut value = id.value;
+        for digit in digits.iter_mut().rev() {
+            // The remainder is a single digit.
+            #[allow(clippy::cast_possible_truncation)]
+            let remainder = (value % 10) as u8;
+            *digit = b'0' + remainder;
+            value /= 10;
+        }
+        self.seal([
+            0,
+            id.position,
+            c1,
+            c2,
+            c3,
+            id.scaling_factor,
+            id.decimal_places,
+            challenge,
+            random,
+            digits[0],
+            digits[1],
+            digits[2],
+            digits[3],
+            id.issue_level,
+            b'0' + id.issue_number,
+            0,
+        ])
+    }
+
+    /// Decrypts `reply` and checks its CRC, returns the plaintext and the CRC mismatch if any.
+    fn open(
+        self,
+        reply: [u8; Self::REPLY_LENGTH],
+    ) -> ([u8; Self::REPLY_LENGTH], Option<(u16, u16)>) {
+        let plain = apply_blocks(reply, |block| self.key.decrypt_block(block));
+        let expected = crc16_bytes(&plain[1..15]);
+        let received = u16::from_le_bytes([plain[0], plain[15]]);
+        let mismatch = (expected != received).then_some((expected, received));
+        (plain, mismatch)
+    }
+
+    /// Stores the CRC of `plain` in its first and last byte and encrypts it.
+    fn seal(self, mut plain: [u8; Self::REPLY_LENGTH]) -> [u8; Self::REPLY_LENGTH] {
+        let [lsb, msb] = crc16_bytes(&plain[1..15]).to_le_bytes();
+        plain[0] = lsb;
+        plain[15] = msb;
+        apply_blocks(plain, |block| self.key.encrypt_block(block))
+    }
+}
+
+impl PeripheralCipher for DesPeripheralCipher {
+    fn decrypt_events(
+        &self,
+        challenge: u8,
+        reply: &[u8],
+    ) -> Result<EncryptedEvents, EncryptedEventsError> {
+        let Ok(reply) = <[u8; Self::REPLY_LENGTH]>::try_from(reply) else {
+            return Err(EncryptedEventsError::InvalidLength {
+                expected: Self::REPLY_LENGTH,
+                received: reply.len(),
+            });
+        };
+        let (plain, mismatch) = self.open(reply);
+        if let Some((expected, received)) = mismatch {
+            return Err(EncryptedEventsError::ChecksumMismatch { expected, received });
+        }
+        if plain[7] != challenge {
+            return Err(EncryptedEventsError::ChallengeMismatch {
+                expected: challenge,
+                received: plain[7],
+            });
+        }
+
+        Ok(EncryptedEvents {
+            event_counter: plain[2],
+            results: [
+                [plain[3], plain[4]],
+                [plain[5], plain[6]],
+                [plain[9], plain[10]],
+                [plain[11], plain[12]],
+                [plain[13], plain[14]],
+            ],
+        })
+    }
+
+    fn decrypt_monetary_id(
+        &self,
+        position: u8,
+        challenge: u8,
+        reply: &[u8],
+    ) -> Result<Option<MonetaryId>, MonetaryIdError> {
+        let Ok(reply) = <[u8; Self::REPLY_LENGTH]>::try_from(reply) else {
+            return Err(MonetaryIdError::InvalidLength {
+                expected: Self::REPLY_LENGTH,
+                received: reply.len(),
+            });
+        };
+        let (plain, mismatch) = self.open(reply);
+        if let Some((expected, received)) = mismatch {
+            return Err(MonetaryIdError::ChecksumMismatch { expected, received });
+        }
+        if plain[7] != challenge {
+            return Err(MonetaryIdError::ChallengeMismatch {
+                expected: challenge,
+                received: plain[7],
+            });
+        }
+        if plain[1] != position {
+            return Err(MonetaryIdError::PositionMismatch {
+                expected: position,
+                received: plain[1],
+            });
+        }
+
+        if plain[2..5] == *b"..." {
+            return Ok(None);
+        }
+        let code = MonetaryCode::from_bytes([plain[2], plain[3], plain[4]])
+            .ok_or(MonetaryIdError::InvalidField("country code"))?;
+        let mut value = 0u16;
+        for digit in &plain[9..13] {
+            if !digit.is_ascii_digit() {
+                return Err(MonetaryIdError::InvalidField("value"));
+            }
+            value = value * 10 + u16::from(digit - b'0');
+        }
+        if !plain[13].is_ascii_uppercase() {
+            return Err(MonetaryIdError::InvalidField("issue level"));
+        }
+        if !(b'1'..=b'9').contains(&plain[14]) {
+            return Err(MonetaryIdError::InvalidField("issue number"));
+        }
+
+        Ok(Some(MonetaryId {
+            position,
+            code,
+            scaling_factor: plain[5],
+            decimal_places: plain[6],
+            value,
+            issue_level: plain[13],
+            issue_number: plain[14] - b'0',
+        }))
+    }
+}
+
+/// Runs `cipher` on both blocks of an encrypted reply.
+fn apply_blocks(
+    input: [u8; DesPeripheralCipher::REPLY_LENGTH],
+    cipher: impl Fn([u8; 8]) -> [u8; 8],
+) -> [u8; DesPeripheralCipher::REPLY_LENGTH] {
+    let mut output = [0u8; DesPeripheralCipher::REPLY_LENGTH];
+    for (output, block) in output.chunks_exact_mut(8).zip(input.chunks_exact(8)) {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(block);
+        output.copy_from_slice(&cipher(bytes));
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::currency::{CurrencyToken, CurrencyValue, Factor};
+
+    const CIPHER: DesPeripheralCipher = DesPeripheralCipher::new(HopperDesKey::new([
+        0x13, 0x34, 0x57, 0x79, 0x9B, 0xBC, 0xDF, 0xF1,
+    ]));
+    const WRONG_KEY: DesPeripheralCipher = DesPeripheralCipher::new(HopperDesKey::new([0; 8]));
+
+    #[test]
+    fn events_are_decrypted_and_verified() {
+        let events = EncryptedEvents {
+            event_counter: 42,
+            results: [[1, 0], [0, 254], [2, 1], [0, 0], [3, 2]],
+        };
+        let mut reply = CIPHER.encrypt_events(&events, 0x77, [0xAB, 0xCD]);
+        assert_eq!(CIPHER.decrypt_events(0x77, &reply), Ok(events));
+        assert_eq!(events.buffer(), [42, 1, 0, 0, 254, 2, 1, 0, 0, 3, 2]);
+
+        assert_eq!(
+            CIPHER.decrypt_events(0x78, &reply),
+            Err(EncryptedEventsError::ChallengeMismatch {
+                expected: 0x78,
+                received: 0x77,
+            })
+        );
+        assert!(matches!(
+            WRONG_KEY.decrypt_events(0x77, &reply),
+            Err(EncryptedEventsError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            CIPHER.decrypt_events(0x77, &reply[..11]),
+            Err(EncryptedEventsError::InvalidLength {
+                expected: 16,
+                received: 11,
+            })
+        );
+        reply[3] ^= 1;
+        assert!(CIPHER.decrypt_events(0x77, &reply).is_err());
+    }
+
+    #[test]
+    fn monetary_ids_are_decrypted_and_verified() {
+        let id = MonetaryId {
+            position: 5,
+            code: MonetaryCode::Country(*b"GB"),
+            scaling_factor: 0,
+            decimal_places: 2,
+            value: 50,
+            issue_level: b'B',
+            issue_number: 2,
+        };
+        let reply = CIPHER.encrypt_monetary_id(&id, 0x42, 0x99);
+        assert_eq!(CIPHER.decrypt_monetary_id(5, 0x42, &reply), Ok(Some(id)));
+        assert_eq!(
+            id.currency_token(),
+            CurrencyValue::new("GB", Factor::None, 2, 50).map(CurrencyToken::Currency)
+        );
+
+        assert_eq!(
+            CIPHER.decrypt_monetary_id(6, 0x42, &reply),
+            Err(MonetaryIdError::PositionMismatch {
+                expected: 6,
+                received: 5,
+            })
+        );
+        assert_eq!(
+            CIPHER.decrypt_monetary_id(5, 0x43, &reply),
+            Err(MonetaryIdError::ChallengeMismatch {
+                expected: 0x43,
+                received: 0x42,
+            })
+        );
+        assert!(matches!(
+            WRONG_KEY.decrypt_monetary_id(5, 0x42, &reply),
+            Err(MonetaryIdError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn blank_positions_and_malformed_fields() {
+        let blank = CIPHER.seal(*b"\0\x07...\0\0\x10\0......\0");
+        assert_eq!(CIPHER.decrypt_monetary_id(7, 0x10, &blank), Ok(None));
+
+        let malformed = CIPHER.seal(*b"\0\x07EUR\x02\x02\x10\x000a20A1\0");
+        assert_eq!(
+            CIPHER.decrypt_monetary_id(7, 0x10, &malformed),
+            Err(MonetaryIdError::InvalidField("value"))
+        );
+    }
+}
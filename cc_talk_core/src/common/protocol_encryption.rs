@@ -0,0 +1,200 @@
+use super::packet::SOURCE_OFFSET;
+
+/// 6 digit protocol encryption key, also called BNV key.
+///
+/// The key is stored by the device and changed with `SwitchEncryptionCode`, the frames are
+/// encrypted with it by a [`FrameCipher`].
+///
+/// The key is hidden from the `Debug` output.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let key = BnvKey::new(123_456).expect("valid key");
+/// assert_eq!(key.to_bytes(), [0x21, 0x43, 0x65]);
+/// assert_eq!(BnvKey::from_bytes([0x21, 0x43, 0x65]), Some(key));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct BnvKey([u8; 6]);
+
+impl BnvKey {
+    /// Creates a key from its decimal code, e.g. `123456`.
+    ///
+    /// Returns `None` if the code has more than 6 digits.
+    #[must_use]
+    pub const fn new(code: u32) -> Option<Self> {
+        if code > 999_999 {
+            return None;
+        }
+        let mut digits = [0u8; 6];
+        let mut remaining = code;
+        let mut i = 6;
+        while i > 0 {
+            i -= 1;
+            #[allow(clippy::cast_possible_truncation)]
+            {
+                digits[i] = (remaining % 10) as u8;
+            }
+            remaining /= 10;
+        }
+        Some(Self(digits))
+    }
+
+    /// Creates a key from the 3 bytes sent with `SwitchEncryptionCode`, each byte holds two
+    /// digits, the first one in the low nibble.
+    ///
+    /// Returns `None` if a nibble is not a decimal digit.
+    #[must_use]
+    pub const fn from_bytes(bytes: [u8; 3]) -> Option<Self> {
+        let mut digits = [0u8; 6];
+        let mut i = 0;
+        while i < 3 {
+            let low = bytes[i] & 0x0F;
+            let high = bytes[i] >> 4;
+            if low > 9 || high > 9 {
+                return None;
+            }
+            digits[2 * i] = low;
+            digits[2 * i + 1] = high;
+            i += 1;
+        }
+        Some(Self(digits))
+    }
+
+    /// Returns the key as sent with `SwitchEncryptionCode`, see [`BnvKey::from_bytes`].
+    #[must_use]
+    pub const fn to_bytes(&self) -> [u8; 3] {
+        let d = self.0;
+        [d[1] << 4 | d[0], d[3] << 4 | d[2], d[5] << 4 | d[4]]
+    }
+
+    /// Returns the decimal code of the key.
+    #[must_use]
+    pub const fn code(&self) -> u32 {
+        let mut code = 0;
+        let mut i = 0;
+        while i < 6 {
+            code = code * 10 + self.0[i] as u32;
+            i += 1;
+        }
+        code
+    }
+}
+
+/// Algorithm encrypting the frames exchanged with a device using protocol level encryption.
+///
+/// The ccTalk Serial Protocol Encryption Standard is licensed separately from the ccTalk
+/// specification and is not part of this crate, implement this trait with it to talk to devices
+/// using protocol level encryption.
+///
+/// The destination and data length bytes have to be left in plain text so every device on the
+/// bus can still delimit the frames, everything after them, checksum included, is handed to the
+/// cipher.
+pub trait FrameCipher {
+    /// Encrypts a serialized frame in place.
+    fn encrypt(&self, key: &BnvKey, frame: &mut [u8]);
+
+    /// Decrypts a received frame in place.
+    fn decrypt(&self, key: &BnvKey, frame: &mut [u8]);
+}
+
+/// Keyed XOR chain exercising encrypted transports in tests and emulators.
+///
+/// This is **not** the ccTalk Serial Protocol Encryption Standard, no device understands it.
+///
+/// Each byte after the data length is combined by XOR with a key byte and the previous
+/// encrypted byte, then rotated by a key dependent number of bits.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let key = BnvKey::new(123_456).expect("valid key");
+/// let mut frame = [2, 0, 1, 254, 255];
+/// XorChainCipher.encrypt(&key, &mut frame);
+/// assert_eq!(frame[..2], [2, 0]);
+/// XorChainCipher.decrypt(&key, &mut frame);
+/// assert_eq!(frame, [2, 0, 1, 254, 255]);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XorChainCipher;
+
+impl XorChainCipher {
+    /// Returns the XOR mask, the rotation and the initial feedback derived from the key.
+    fn schedule(key: BnvKey) -> (u8, u32, u8) {
+        let [d0, d1, d2, d3, d4, d5] = key.0;
+        let rotation = u32::from(d1 + d4) % 7 + 1;
+        (d0 << 4 | d5, rotation, d2 << 4 | d3)
+    }
+}
+
+impl FrameCipher for XorChainCipher {
+    fn encrypt(&self, key: &BnvKey, frame: &mut [u8]) {
+        let (mask, rotation, mut feedback) = Self::schedule(*key);
+        for byte in frame.iter_mut().skip(SOURCE_OFFSET) {
+            *byte = (*byte ^ mask ^ feedback).rotate_left(rotation);
+            feedback = *byte;
+        }
+    }
+
+    fn decrypt(&self, key: &BnvKey, frame: &mut [u8]) {
+        let (mask, rotation, mut feedback) = Self::schedule(*key);
+        for byte in frame.iter_mut().skip(SOURCE_OFFSET) {
+            let encrypted = *byte;
+            *byte = encrypted.rotate_right(rotation) ^ mask ^ feedback;
+            feedback = encrypted;
+        }
+    }
+}
+
+impl core::fmt::Debug for BnvKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "BnvKey(******)")
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for BnvKey {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "BnvKey(******)");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_codes_round_trip() {
+        let key = BnvKey::new(123_456).expect("valid key");
+        assert_eq!(key.code(), 123_456);
+        assert_eq!(BnvKey::from_bytes(key.to_bytes()), Some(key));
+        assert_eq!(
+            BnvKey::new(42).expect("valid key").to_bytes(),
+            [0x00, 0x00, 0x24]
+        );
+        assert_eq!(BnvKey::new(1_000_000), None);
+        assert_eq!(BnvKey::from_bytes([0x1A, 0, 0]), None);
+        assert_eq!(std::format!("{key:?}"), "BnvKey(******)");
+    }
+
+    #[test]
+    fn frames_are_encrypted_after_the_length_byte() {
+        let key = BnvKey::new(654_321).expect("valid key");
+        let plain = [2, 3, 1, 231, 0xFF, 0xFF, 0x00, 0x9A];
+        let mut frame = plain;
+        XorChainCipher.encrypt(&key, &mut frame);
+        assert_eq!(frame[..2], plain[..2]);
+        assert_ne!(frame[2..], plain[2..]);
+
+        let mut other = plain;
+        XorChainCipher.encrypt(&BnvKey::new(654_322).expect("valid key"), &mut other);
+        assert_ne!(frame, other);
+
+        XorChainCipher.decrypt(&key, &mut frame);
+        assert_eq!(frame, plain);
+    }
+}
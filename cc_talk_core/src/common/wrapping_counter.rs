@@ -0,0 +1,153 @@
+/// Tracks a device counter of `BITS` bits across reads and totals it on the host.
+///
+/// Device counters, e.g. the insertion or dispense counters, roll over to zero past their
+/// maximum and restart from zero when the device loses its RAM. A reading lower than the
+/// previous one is taken as a wrap when the previous reading was in the last quarter of the
+/// range, as a reset otherwise. The host total only ever grows.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::*;
+///
+/// let mut counter = WrappingCounter24::new();
+/// assert_eq!(counter.update(16_777_210), CounterStep::First);
+/// assert_eq!(counter.update(4), CounterStep::Wrapped(10));
+/// assert_eq!(counter.update(2), CounterStep::Reset(2));
+/// assert_eq!(counter.total(), 12);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WrappingCounter<const BITS: u32> {
+    last: Option<u32>,
+    total: u64,
+    wraps: u32,
+    resets: u32,
+}
+
+/// 16 bit counter, e.g. the payout absolute count of a hopper.
+pub type WrappingCounter16 = WrappingCounter<16>;
+/// 24 bit counter, e.g. the insertion, accept or dispense counters.
+pub type WrappingCounter24 = WrappingCounter<24>;
+/// 32 bit counter, e.g. the money in and money out counters.
+pub type WrappingCounter32 = WrappingCounter<32>;
+
+/// How a reading moved a [`WrappingCounter`], with the count added to the total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CounterStep {
+    /// First reading, nothing is counted.
+    First,
+    /// The counter moved forward or stayed put.
+    Advanced(u32),
+    /// The counter rolled over past its maximum.
+    Wrapped(u32),
+    /// The counter restarted from zero, its reading is counted.
+    Reset(u32),
+}
+
+impl CounterStep {
+    /// Returns the count added to the total.
+    #[must_use]
+    pub const fn delta(&self) -> u32 {
+        match self {
+            Self::First => 0,
+            Self::Advanced(delta) | Self::Wrapped(delta) | Self::Reset(delta) => *delta,
+        }
+    }
+}
+
+impl<const BITS: u32> WrappingCounter<BITS> {
+    /// Highest value of the counter, e.g. 16,777,215 for 24 bits.
+    pub const MAX: u32 = {
+        assert!(BITS > 0 && BITS <= 32, "counters have 1 to 32 bits");
+        u32::MAX >> (32 - BITS)
+    };
+
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            total: 0,
+            wraps: 0,
+            resets: 0,
+        }
+    }
+
+    /// Resumes tracking from a reading stored earlier, the total restarts from zero.
+    #[must_use]
+    pub const fn resume(last: u32) -> Self {
+        Self {
+            last: Some(last & Self::MAX),
+            ..Self::new()
+        }
+    }
+
+    /// Returns the last reading, `None` until the counter is first read.
+    #[must_use]
+    pub const fn last(&self) -> Option<u32> {
+        self.last
+    }
+
+    /// Returns the count since the first reading, across wraps and resets.
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.total
+    }
+
+    #[must_use]
+    pub const fn wraps(&self) -> u32 {
+        self.wraps
+    }
+
+    #[must_use]
+    pub const fn resets(&self) -> u32 {
+        self.resets
+    }
+
+    /// Records a reading, the bits above `BITS` are ignored.
+    pub const fn update(&mut self, reading: u32) -> CounterStep {
+        let reading = reading & Self::MAX;
+        let step = match self.last {
+            None => CounterStep::First,
+            Some(previous) if reading >= previous => CounterStep::Advanced(reading - previous),
+            Some(previous) if previous >= Self::MAX - Self::MAX / 4 => {
+                self.wraps += 1;
+                CounterStep::Wrapped(Self::MAX - previous + reading + 1)
+            }
+            Some(_) => {
+                self.resets += 1;
+                CounterStep::Reset(reading)
+            }
+        };
+        self.last = Some(reading);
+        self.total += step.delta() as u64;
+        step
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wraps_are_told_apart_from_resets() {
+        let mut counter = WrappingCounter16::new();
+        assert_eq!(counter.update(65_530), CounterStep::First);
+        assert_eq!(counter.update(65_535), CounterStep::Advanced(5));
+        assert_eq!(counter.update(4), CounterStep::Wrapped(5));
+        assert_eq!(counter.update(0x1_0008), CounterStep::Advanced(4));
+        assert_eq!(counter.update(3), CounterStep::Reset(3));
+        assert_eq!(
+            (counter.total(), counter.wraps(), counter.resets()),
+            (17, 1, 1)
+        );
+
+        let mut counter = WrappingCounter32::resume(u32::MAX);
+        assert_eq!(counter.update(0), CounterStep::Wrapped(1));
+        assert_eq!(counter.update(u32::MAX), CounterStep::Advanced(u32::MAX));
+        assert_eq!(counter.update(u32::MAX - 1), CounterStep::Wrapped(u32::MAX));
+        assert_eq!(counter.total(), 2 * u64::from(u32::MAX) + 1);
+        assert_eq!(WrappingCounter24::MAX, 16_777_215);
+    }
+}
@@ -4,13 +4,15 @@
 extern crate std;
 
 mod common;
-mod log;
+pub mod log;
 mod serde;
 
 pub mod cc_talk {
+    pub use crate::common::accept_limit::*;
     pub use crate::common::bill_event_types::*;
     pub use crate::common::bill_routing::*;
     pub use crate::common::bit_mask::*;
+    pub use crate::common::bus_address::*;
     pub use crate::common::category::*;
     pub use crate::common::changer_device::*;
     pub use crate::common::changer_error::*;
@@ -24,22 +26,44 @@ pub mod cc_talk {
     pub use crate::common::currency::*;
     pub use crate::common::data_storage::*;
     pub use crate::common::date::*;
+    #[cfg(feature = "descriptions")]
+    pub use crate::common::descriptions::*;
     pub use crate::common::device::*;
+    pub use crate::common::echo_canceller::*;
+    #[cfg(feature = "hopper-encryption")]
+    pub use crate::common::encrypted_events::*;
+    #[cfg(feature = "hopper-encryption")]
+    pub use crate::common::encrypted_monetary_id::*;
+    #[cfg(feature = "aes-encryption")]
+    pub use crate::common::encrypted_product_id::*;
+    pub use crate::common::encryption_session::*;
     pub use crate::common::escrow_status::*;
     pub use crate::common::fault_code::*;
+    #[cfg(feature = "hopper-encryption")]
+    pub use crate::common::hopper_encryption::*;
     pub use crate::common::hopper_flags::*;
     pub use crate::common::hopper_status::*;
+    pub use crate::common::hopper_variables::*;
     pub use crate::common::lamp_control::*;
     pub use crate::common::manufacturers::*;
     pub use crate::common::option_flags::*;
+    pub use crate::common::opto_voltage::*;
     pub use crate::common::packet::*;
+    pub use crate::common::packet_builder::*;
+    pub use crate::common::packet_display::*;
+    pub use crate::common::packet_framer::*;
+    #[cfg(feature = "hopper-encryption")]
+    pub use crate::common::peripheral_encryption::*;
     pub use crate::common::power_option::*;
+    pub use crate::common::protocol_encryption::*;
     pub use crate::common::teach_mode_status::*;
+    pub use crate::common::wrapping_counter::*;
 
     pub use crate::serde::*;
 }
 
 #[cfg(test)]
+#[allow(dead_code)]
 #[cfg_attr(feature = "defmt", defmt::panic_handler)]
 fn panic() -> ! {
     core::panic!("panic via `defmt::panic!`")
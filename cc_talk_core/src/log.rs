@@ -1,239 +1,162 @@
-#![allow(unused)]
-
-macro_rules! assert {
-    ($($x:tt)*) => {
-        {
-            #[cfg(not(feature = "defmt"))]
-            ::core::assert!($($x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::assert!($($x)*);
-        }
-    };
-}
-
-macro_rules! assert_eq {
-    ($($x:tt)*) => {
-        {
-            #[cfg(not(feature = "defmt"))]
-            ::core::assert_eq!($($x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::assert_eq!($($x)*);
-        }
-    };
-}
-
-macro_rules! assert_ne {
-    ($($x:tt)*) => {
-        {
-            #[cfg(not(feature = "defmt"))]
-            ::core::assert_ne!($($x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::assert_ne!($($x)*);
-        }
-    };
-}
-
-macro_rules! debug_assert {
-    ($($x:tt)*) => {
-        {
-            #[cfg(not(feature = "defmt"))]
-            ::core::debug_assert!($($x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::debug_assert!($($x)*);
-        }
-    };
-}
-
-macro_rules! debug_assert_eq {
-    ($($x:tt)*) => {
-        {
-            #[cfg(not(feature = "defmt"))]
-            ::core::debug_assert_eq!($($x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::debug_assert_eq!($($x)*);
-        }
-    };
-}
-
-macro_rules! debug_assert_ne {
-    ($($x:tt)*) => {
-        {
-            #[cfg(not(feature = "defmt"))]
-            ::core::debug_assert_ne!($($x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::debug_assert_ne!($($x)*);
-        }
-    };
-}
-
-macro_rules! todo {
-    ($($x:tt)*) => {
-        {
-            #[cfg(not(feature = "defmt"))]
-            ::core::todo!($($x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::todo!($($x)*);
-        }
-    };
-}
-
-#[cfg(not(feature = "defmt"))]
-macro_rules! unreachable {
-    ($($x:tt)*) => {
-        ::core::unreachable!($($x)*)
-    };
-}
-
-#[cfg(feature = "defmt")]
-macro_rules! unreachable {
-    ($($x:tt)*) => {
-        ::defmt::unreachable!($($x)*)
-    };
-}
-
-macro_rules! panic {
-    ($($x:tt)*) => {
-        {
-            #[cfg(not(feature = "defmt"))]
-            ::core::panic!($($x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::panic!($($x)*);
-        }
-    };
-}
-
-macro_rules! trace {
-    ($s:literal $(, $x:expr)* $(,)?) => {
-        {
-            #[cfg(feature = "log")]
-            ::log::trace!($s $(, $x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::trace!($s $(, $x)*);
-            #[cfg(feature="defmt")]
-            let _ = ($( & $x ),*);
-        }
-    };
+//! Logging facade shared by the ccTalk crates.
+//!
+//! The macros take a format string literal followed by positional arguments, the subset of
+//! syntax understood by both backends:
+//!
+//! - with the `defmt` feature they log through `defmt`, the calling crate must depend on it,
+//! - otherwise with the `tracing` feature they log through `tracing`,
+//! - otherwise they only evaluate their arguments.
+//!
+//! Levels below the one selected with a `max-level-*` feature are compiled out, e.g.
+//! `max-level-info` removes the `trace!` and `debug!` statements.
+//!
+//! ```ignore
+//! cc_talk_core::log::debug!("polled device {}, {} events", address, events);
+//! ```
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "tracing")]
+    pub use tracing;
 }
 
-macro_rules! debug {
-    ($s:literal $(, $x:expr)* $(,)?) => {
-        {
-            #[cfg(feature = "log")]
-            ::log::debug!($s $(, $x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::debug!($s $(, $x)*);
-            #[cfg(not(feature="defmt"))]
-            let _ = ($( & $x ),*);
-        }
-    };
-}
-
-macro_rules! info {
-    ($s:literal $(, $x:expr)* $(,)?) => {
-        {
-            #[cfg(feature = "log")]
-            ::log::info!($s $(, $x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::info!($s $(, $x)*);
-            #[cfg(not(feature="defmt"))]
-            let _ = ($( & $x ),*);
-        }
-    };
-}
-
-macro_rules! _warn {
-    ($s:literal $(, $x:expr)* $(,)?) => {
-        {
-            #[cfg(feature = "log")]
-            ::log::warn!($s $(, $x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::warn!($s $(, $x)*);
-            #[cfg(not(feature="defmt"))]
-            let _ = ($( & $x ),*);
-        }
-    };
-}
-
-macro_rules! error {
-    ($s:literal $(, $x:expr)* $(,)?) => {
-        {
-            #[cfg(feature = "log")]
-            ::log::error!($s $(, $x)*);
-            #[cfg(feature = "defmt")]
-            ::defmt::error!($s $(, $x)*);
-            #[cfg(not(feature="defmt"))]
-            let _ = ($( & $x ),*);
-        }
-    };
-}
-
-#[cfg(feature = "defmt")]
-macro_rules! unwrap {
-    ($($x:tt)*) => {
-        ::defmt::unwrap!($($x)*)
-    };
-}
-
-#[cfg(not(feature = "defmt"))]
-macro_rules! unwrap {
-    ($arg:expr) => {
-        match $crate::fmt::Try::into_result($arg) {
-            ::core::result::Result::Ok(t) => t,
-            ::core::result::Result::Err(_) => {
-                ::core::panic!();
-            }
-        }
-    };
-    ($arg:expr, $($msg:expr),+ $(,)? ) => {
-        match $crate::fmt::Try::into_result($arg) {
-            ::core::result::Result::Ok(t) => t,
-            ::core::result::Result::Err(_) => {
-                ::core::panic!();
-            }
-        }
-    };
-}
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub struct NoneError;
-
-pub trait Try {
-    type Ok;
-    type Error;
-    fn into_result(self) -> Result<Self::Ok, Self::Error>;
-}
-
-impl<T> Try for Option<T> {
-    type Ok = T;
-    type Error = NoneError;
-
-    #[inline]
-    fn into_result(self) -> Result<T, NoneError> {
-        self.ok_or(NoneError)
-    }
-}
-
-impl<T, E> Try for Result<T, E> {
-    type Ok = T;
-    type Error = E;
-
-    #[inline]
-    fn into_result(self) -> Self {
-        self
-    }
-}
-
-#[allow(clippy::redundant_pub_crate)]
-pub(crate) struct Bytes<'a>(pub &'a [u8]);
-
 #[cfg(feature = "defmt")]
-#[allow(clippy::elidable_lifetime_names)]
-impl<'a> defmt::Format for Bytes<'a> {
-    fn format(&self, fmt: defmt::Formatter) {
-        defmt::write!(fmt, "{:02x}", self.0);
-    }
-}
-
-pub(crate) use debug;
-pub(crate) use error;
-pub(crate) use info;
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_log {
+    ($level:ident, $s:literal $(, $x:expr)* $(,)?) => {
+        ::defmt::$level!($s $(, $x)*)
+    };
+}
+
+#[cfg(all(feature = "tracing", not(feature = "defmt")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_log {
+    ($level:ident, $s:literal $(, $x:expr)* $(,)?) => {
+        $crate::log::__private::tracing::$level!($s $(, $x)*)
+    };
+}
+
+#[cfg(not(any(feature = "tracing", feature = "defmt")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_log {
+    ($level:ident, $s:literal $(, $x:expr)* $(,)?) => {{
+        $(let _ = &$x;)*
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_log_disabled {
+    ($s:literal $(, $x:expr)* $(,)?) => {{
+        $(let _ = &$x;)*
+    }};
+}
+
+#[cfg(not(any(
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn",
+    feature = "max-level-info",
+    feature = "max-level-debug"
+)))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_trace {
+    ($($arg:tt)*) => { $crate::__cc_talk_log!(trace, $($arg)*) };
+}
+
+#[cfg(any(
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn",
+    feature = "max-level-info",
+    feature = "max-level-debug"
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_trace {
+    ($($arg:tt)*) => { $crate::__cc_talk_log_disabled!($($arg)*) };
+}
+
+#[cfg(not(any(
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn",
+    feature = "max-level-info"
+)))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_debug {
+    ($($arg:tt)*) => { $crate::__cc_talk_log!(debug, $($arg)*) };
+}
+
+#[cfg(any(
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn",
+    feature = "max-level-info"
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_debug {
+    ($($arg:tt)*) => { $crate::__cc_talk_log_disabled!($($arg)*) };
+}
+
+#[cfg(not(any(
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn"
+)))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_info {
+    ($($arg:tt)*) => { $crate::__cc_talk_log!(info, $($arg)*) };
+}
+
+#[cfg(any(
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn"
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_info {
+    ($($arg:tt)*) => { $crate::__cc_talk_log_disabled!($($arg)*) };
+}
+
+#[cfg(not(any(feature = "max-level-off", feature = "max-level-error")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_warn {
+    ($($arg:tt)*) => { $crate::__cc_talk_log!(warn, $($arg)*) };
+}
+
+#[cfg(any(feature = "max-level-off", feature = "max-level-error"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_warn {
+    ($($arg:tt)*) => { $crate::__cc_talk_log_disabled!($($arg)*) };
+}
+
+#[cfg(not(feature = "max-level-off"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_error {
+    ($($arg:tt)*) => { $crate::__cc_talk_log!(error, $($arg)*) };
+}
+
+#[cfg(feature = "max-level-off")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cc_talk_error {
+    ($($arg:tt)*) => { $crate::__cc_talk_log_disabled!($($arg)*) };
+}
+
+pub use crate::__cc_talk_debug as debug;
+pub use crate::__cc_talk_error as error;
+pub use crate::__cc_talk_info as info;
+pub use crate::__cc_talk_trace as trace;
+pub use crate::__cc_talk_warn as warn;
@@ -1,16 +1,18 @@
-use crate::{
-    cc_talk::{ChecksumType, Packet},
-    common::checksum,
-};
+use crate::cc_talk::{BusAddress, ChecksumType, Packet, PacketError, DATA_OFFSET};
 
 /// Deserializes a ccTalk packet and verifies its checksum.
 /// Returns the reply to address if successful, or an error if the checksum is invalid or the
 /// packet is malformed.
 ///
+/// CRC16 frames carry no source address, the host address is returned as they are requests from
+/// the host. The frame is validated like with [`Packet::parse`], which also gives access to its
+/// fields.
+///
 /// # Errors
 ///
 /// Returns a `DeserializationError` if:
 /// - The buffer is too small to contain a valid packet.
+/// - The length byte does not match the buffer length or the header is unknown.
 /// - The checksum does not match the expected value.
 pub fn deserialize<B>(
     packet: &mut Packet<B>,
@@ -19,46 +21,18 @@ pub fn deserialize<B>(
 where
     B: AsRef<[u8]> + AsMut<[u8]>,
 {
-    match checksum_type {
-        ChecksumType::Crc8 => {
-            let checksum = packet
-                .get_checksum()
-                .map_err(|_| DeserializationError::BufferTooSmall)?;
-            let expected_checksum = checksum::crc8(packet.as_slice());
-
-            if checksum != expected_checksum {
-                return Err(DeserializationError::ChecksumMismatch(
-                    u16::from(expected_checksum),
-                    u16::from(checksum),
-                ));
-            }
-
-            Ok(packet
-                .get_source()
-                .map_err(|_| DeserializationError::InvalidPacket)?)
-        }
-        ChecksumType::Crc16 => {
-            let most_significant_bits = packet
-                .get_checksum()
-                .map(u16::from)
-                .map_err(|_| DeserializationError::BufferTooSmall)?;
-            let least_significant_bits = packet
-                .get_source()
-                .map(u16::from)
-                .map_err(|_| DeserializationError::BufferTooSmall)?;
-
-            let checksum = most_significant_bits << 8 | least_significant_bits;
-            let expected_checksum = checksum::crc16(packet.as_slice());
-            if checksum != expected_checksum {
-                return Err(DeserializationError::ChecksumMismatch(
-                    expected_checksum,
-                    checksum,
-                ));
-            }
-
-            Ok(1u8) // Default return address for CRC16
-        }
+    let frame = packet.as_slice();
+    if frame.len() <= DATA_OFFSET {
+        return Err(DeserializationError::BufferTooSmall);
     }
+    Packet::parse(frame, checksum_type)
+        .map(|packet| packet.source().unwrap_or(BusAddress::HOST.get()))
+        .map_err(|error| match error {
+            PacketError::ChecksumMismatch { expected, actual } => {
+                DeserializationError::ChecksumMismatch(expected, actual)
+            }
+            _ => DeserializationError::InvalidPacket,
+        })
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
@@ -89,4 +63,20 @@ mod test {
         assert!(result.is_ok());
         assert_eq!(result.expect("is_ok"), 2);
     }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        assert_eq!(
+            deserialize(&mut Packet::new([1u8, 0, 2]), ChecksumType::Crc8),
+            Err(DeserializationError::BufferTooSmall)
+        );
+        assert_eq!(
+            deserialize(&mut Packet::new([1u8, 1, 2, 0, 253]), ChecksumType::Crc8),
+            Err(DeserializationError::InvalidPacket)
+        );
+        assert_eq!(
+            deserialize(&mut Packet::new([1u8, 0, 2, 0, 254]), ChecksumType::Crc8),
+            Err(DeserializationError::ChecksumMismatch(253, 254))
+        );
+    }
 }
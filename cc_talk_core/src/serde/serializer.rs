@@ -1,4 +1,4 @@
-use crate::cc_talk::{ChecksumType, Device, Packet, SOURCE_OFFSET};
+use crate::cc_talk::{ChecksumKind, Device, FrameCipher, Packet};
 
 /// Serializes a ccTalk packet by calculating and inserting the appropriate checksum.
 ///
@@ -6,52 +6,53 @@ use crate::cc_talk::{ChecksumType, Device, Packet, SOURCE_OFFSET};
 ///
 /// Other errors will return a `SerializationError` if:
 /// - Buffer is too small to write the checksum.
-///
-/// # Panics
-///
-/// Panics if the device is encrypted, as encrypted devices are not supported.
+/// - The device has an encryption key, see [`serialize_encrypted`].
 pub fn serialize<B>(device: &Device, packet: &mut Packet<B>) -> Result<(), SerializationError>
 where
     B: AsMut<[u8]> + AsRef<[u8]>,
 {
-    assert!(
-        !device.encrypted(),
-        "encrypted devices are currently not supported."
-    );
-
-    match device.checksum_type() {
-        ChecksumType::Crc8 => {
-            let checksum = crate::common::checksum::crc8(packet.as_slice());
-            let checksum_index = packet
-                .get_checksum_offset()
-                .map_err(|_| SerializationError::BufferTooSmall)?;
-
-            packet
-                .write_byte(checksum_index as usize, checksum)
-                .map_err(|_| SerializationError::BufferTooSmall)?;
-
-            Ok(())
-        }
-        ChecksumType::Crc16 => {
-            let checksum = crate::common::checksum::crc16(packet.as_slice());
-            let checksum_index = packet
-                .get_checksum_offset()
-                .map_err(|_| SerializationError::BufferTooSmall)?;
-
-            let least_significant_bits = (checksum & 0xFF) as u8;
-            let most_significant_bits = ((checksum >> 8) & 0xFF) as u8;
-
-            packet
-                .write_byte(SOURCE_OFFSET, least_significant_bits)
-                .map_err(|_| SerializationError::BufferTooSmall)?;
-
-            packet
-                .write_byte(checksum_index as usize, most_significant_bits)
-                .map_err(|_| SerializationError::BufferTooSmall)?;
+    if device.encrypted() {
+        return Err(SerializationError::MissingCipher);
+    }
+    write_checksum(device, packet)
+}
 
-            Ok(())
-        }
+/// Serializes a ccTalk packet like [`serialize`], frames sent to a device with an encryption
+/// key are then encrypted with `cipher`.
+///
+/// # Errors
+///
+/// Other errors will return a `SerializationError` if:
+/// - Buffer is too small to write the checksum.
+pub fn serialize_encrypted<B, C>(
+    device: &Device,
+    packet: &mut Packet<B>,
+    cipher: &C,
+) -> Result<(), SerializationError>
+where
+    B: AsMut<[u8]> + AsRef<[u8]>,
+    C: FrameCipher + ?Sized,
+{
+    write_checksum(device, packet)?;
+    if let Some(key) = device.encryption_key() {
+        let frame_length = packet.get_logical_size();
+        let frame = packet
+            .as_mut_slice()
+            .get_mut(..frame_length)
+            .ok_or(SerializationError::BufferTooSmall)?;
+        cipher.encrypt(key, frame);
     }
+    Ok(())
+}
+
+fn write_checksum<B>(device: &Device, packet: &mut Packet<B>) -> Result<(), SerializationError>
+where
+    B: AsMut<[u8]> + AsRef<[u8]>,
+{
+    device
+        .checksum_type()
+        .sign(packet.as_mut_slice())
+        .map_err(|_| SerializationError::BufferTooSmall)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
@@ -59,11 +60,14 @@ where
 pub enum SerializationError {
     #[error("buffer too small for serialization")]
     BufferTooSmall,
+    /// The device has an encryption key but no [`FrameCipher`] was given.
+    #[error("encrypted device serialized without a frame cipher")]
+    MissingCipher,
 }
 
 #[cfg(test)]
 mod test {
-    use crate::cc_talk::{Category, Device};
+    use crate::cc_talk::{BnvKey, Category, ChecksumType, Device, Header, XorChainCipher};
 
     use super::*;
 
@@ -78,4 +82,26 @@ mod test {
         assert!(packet.get_checksum().is_ok());
         assert_eq!(packet.get_checksum().expect("is_ok"), 253);
     }
+
+    #[test]
+    fn encrypted_frames_decrypt_to_valid_frames() {
+        let key = BnvKey::new(123_456).expect("valid key");
+        for checksum_type in [ChecksumType::Crc8, ChecksumType::Crc16] {
+            let mut packet = Packet::new([3, 2, 1, 167, 5, 9, 0]);
+            let device = Device::new(3, Category::Payout, checksum_type).with_encryption_key(key);
+            assert_eq!(
+                serialize(&device, &mut packet),
+                Err(SerializationError::MissingCipher)
+            );
+            serialize_encrypted(&device, &mut packet, &XorChainCipher).expect("should serialize");
+            assert!(Packet::parse(packet.as_slice(), checksum_type).is_err());
+
+            let mut frame = [0u8; 7];
+            frame.copy_from_slice(packet.as_slice());
+            XorChainCipher.decrypt(&key, &mut frame);
+            let parsed = Packet::parse(&frame, checksum_type).expect("should be valid");
+            assert_eq!(parsed.header(), Header::DispenseHopperCoins);
+            assert_eq!(parsed.data(), [5, 9]);
+        }
+    }
 }
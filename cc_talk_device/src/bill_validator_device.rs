@@ -0,0 +1,194 @@
+use cc_talk_core::cc_talk::{
+    serializer::serialize, BusAddress, Header, Packet, PacketError, ValidatedPacket,
+    DESTINATION_OFFSET,
+};
+
+use crate::{
+    device_impl::{DeviceImpl, SimpleBillValidator},
+    log::error,
+    payout_device::FrameError,
+};
+
+pub struct BillValidatorDevice<T>
+where
+    T: DeviceImpl + SimpleBillValidator,
+{
+    implementation: T,
+}
+
+impl<T> BillValidatorDevice<T>
+where
+    T: DeviceImpl + SimpleBillValidator,
+{
+    pub fn new(implementation: T) -> Self {
+        Self { implementation }
+    }
+
+    pub fn implementation(&self) -> &T {
+        &self.implementation
+    }
+
+    /// Process a ccTalk frame.
+    ///
+    /// See [`PayoutDevice::on_frame`](crate::payout_device::PayoutDevice::on_frame).
+    pub async fn on_frame(
+        &self,
+        frame: &mut [u8],
+        reply_buffer: &mut [u8],
+    ) -> Result<usize, FrameError> {
+        match self.validate(frame) {
+            Some(packet) => {
+                let mut reply_packet = Packet::new(reply_buffer);
+
+                reply_packet.set_source(self.implementation.address())?;
+                // CRC16 requests carry no source, they come from the host.
+                reply_packet.set_destination(packet.source().unwrap_or(BusAddress::HOST.get()))?;
+                self.process_packet(packet.header(), packet.data(), &mut reply_packet)
+                    .await?;
+
+                match serialize(&self.implementation.device(), &mut reply_packet) {
+                    Ok(()) => Ok(reply_packet.get_logical_size()),
+                    Err(error) => {
+                        error!("failed to serialize reply packet: {:?}", error);
+                        Err(FrameError::SerializationError)
+                    }
+                }
+            }
+            None => Err(FrameError::FrameNotValid),
+        }
+    }
+
+    fn validate<'a>(&self, frame: &'a [u8]) -> Option<ValidatedPacket<'a>> {
+        let destination = frame.get(DESTINATION_OFFSET).copied().unwrap_or(0u8);
+        if !self.implementation.is_for_me(destination) {
+            return None;
+        }
+
+        match Packet::parse(frame, self.implementation.checksum_type()) {
+            Ok(packet) => Some(packet),
+            Err(error) => {
+                error!("failed to validate packet: {:?}", error);
+                None
+            }
+        }
+    }
+
+    async fn process_packet(
+        &self,
+        header: Header,
+        payload: &[u8],
+        packet: &mut Packet<&mut [u8]>,
+    ) -> Result<(), PacketError> {
+        packet.set_header(Header::Reply)?;
+
+        match header {
+            Header::SimplePoll => packet.set_data(&[]),
+            Header::RequestManufacturerId => packet.set_data(
+                self.implementation
+                    .manufacturer()
+                    .abbreviated_name()
+                    .as_bytes(),
+            ),
+            Header::RequestEquipementCategoryId => packet.set_data("Bill Validator".as_bytes()),
+            Header::RequestProductCode => {
+                packet.set_data(self.implementation.product_code().as_bytes())
+            }
+            Header::RequestSerialNumber => {
+                let serial_number = self.implementation.serial_number();
+                packet.set_data(
+                    [
+                        serial_number.fix(),
+                        serial_number.minor(),
+                        serial_number.major(),
+                    ]
+                    .as_ref(),
+                )
+            }
+            Header::RequestSoftwareRevision => {
+                packet.set_data(self.implementation.software_revision().as_bytes())
+            }
+            Header::RequestBuildCode => {
+                packet.set_data(self.implementation.build_code().as_bytes())
+            }
+            Header::RequestDataStorageAvailability => {
+                let data_storage = self.implementation.data_storage_availability();
+                let data_storage_bytes: [u8; 5] = data_storage.into();
+                packet.set_data(&data_storage_bytes)
+            }
+            Header::RequestCommsRevision => {
+                let (major, minor, patch) = self.implementation.comms_revision();
+                packet.set_data(&[major, minor, patch])
+            }
+            Header::RequestBillId => match payload.first() {
+                Some(bill_type) => {
+                    packet.set_data(self.implementation.bill_id(*bill_type).as_bytes())
+                }
+                None => {
+                    packet.set_header(Header::NACK)?;
+                    packet.set_data(&[])
+                }
+            },
+            Header::ReadBufferedBillEvents => {
+                let (event_counter, events) = self.implementation.read_buffered_bill_events().await;
+                let mut data = [0u8; 11];
+                data[0] = event_counter;
+                data[1..].copy_from_slice(&events);
+                packet.set_data(&data)
+            }
+            Header::RouteBill => {
+                let Some(route_code) = payload.first() else {
+                    packet.set_header(Header::NACK)?;
+                    return packet.set_data(&[]);
+                };
+                match self.implementation.route_bill(*route_code).await {
+                    Some(error) => packet.set_data(&[error]),
+                    None => packet.set_data(&[]),
+                }
+            }
+            Header::ModifyInhibitStatus => {
+                if payload.len() < 2 {
+                    packet.set_header(Header::NACK)?;
+                    return packet.set_data(&[]);
+                }
+                self.implementation
+                    .set_inhibits([payload[0], payload[1]])
+                    .await;
+                packet.set_data(&[])
+            }
+            Header::RequestInhibitStatus => packet.set_data(&self.implementation.inhibits()),
+            Header::ModifyMasterInhibitStatus => {
+                if payload.is_empty() {
+                    packet.set_header(Header::NACK)?;
+                    return packet.set_data(&[]);
+                }
+                self.implementation
+                    .set_master_inhibit(payload[0] & 1 == 0)
+                    .await;
+                packet.set_data(&[])
+            }
+            Header::RequestMasterInhibitStatus => {
+                let status = u8::from(!self.implementation.master_inhibit());
+                packet.set_data(&[status])
+            }
+            Header::ModifyBillOperatingMode => {
+                if payload.is_empty() {
+                    packet.set_header(Header::NACK)?;
+                    return packet.set_data(&[]);
+                }
+                self.implementation.set_operating_mode(payload[0]).await;
+                packet.set_data(&[])
+            }
+            Header::RequestBillOperatingMode => {
+                packet.set_data(&[self.implementation.operating_mode()])
+            }
+            Header::ResetDevice => {
+                self.implementation.reset().await;
+                packet.set_data(&[])
+            }
+            _ => {
+                packet.set_header(Header::NACK)?;
+                packet.set_data(&[])
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use cc_talk_core::cc_talk::{
+    serializer::serialize, BusAddress, Header, Packet, PacketError, ValidatedPacket,
+    DESTINATION_OFFSET,
+};
+
+use crate::{
+    device_impl::{DeviceImpl, SimpleCoinAcceptor},
+    log::error,
+    payout_device::FrameError,
+};
+
+pub struct CoinAcceptorDevice<T>
+where
+    T: DeviceImpl + SimpleCoinAcceptor,
+{
+    implementation: T,
+}
+
+impl<T> CoinAcceptorDevice<T>
+where
+    T: DeviceImpl + SimpleCoinAcceptor,
+{
+    pub fn new(implementation: T) -> Self {
+        Self { implementation }
+    }
+
+    pub fn implementation(&self) -> &T {
+        &self.implementation
+    }
+
+    /// Process a ccTalk frame.
+    ///
+    /// See [`PayoutDevice::on_frame`](crate::payout_device::PayoutDevice::on_frame).
+    pub async fn on_frame(
+        &self,
+        frame: &mut [u8],
+        reply_buffer: &mut [u8],
+    ) -> Result<usize, FrameError> {
+        match self.validate(frame) {
+            Some(packet) => {
+                let mut reply_packet = Packet::new(reply_buffer);
+
+                reply_packet.set_source(self.implementation.address())?;
+                // CRC16 requests carry no source, they come from the host.
+                reply_packet.set_destination(packet.source().unwrap_or(BusAddress::HOST.get()))?;
+                self.process_packet(packet.header(), packet.data(), &mut reply_packet)
+                    .await?;
+
+                match serialize(&self.implementation.device(), &mut reply_packet) {
+                    Ok(()) => Ok(reply_packet.get_logical_size()),
+                    Err(error) => {
+                        error!("failed to serialize reply packet: {:?}", error);
+                        Err(FrameError::SerializationError)
+                    }
+                }
+            }
+            None => Err(FrameError::FrameNotValid),
+        }
+    }
+
+    fn validate<'a>(&self, frame: &'a [u8]) -> Option<ValidatedPacket<'a>> {
+        let destination = frame.get(DESTINATION_OFFSET).copied().unwrap_or(0u8);
+        if !self.implementation.is_for_me(destination) {
+            return None;
+        }
+
+        match Packet::parse(frame, self.implementation.checksum_type()) {
+            Ok(packet) => Some(packet),
+            Err(error) => {
+                error!("failed to validate packet: {:?}", error);
+                None
+            }
+        }
+    }
+
+    async fn process_packet(
+        &self,
+        header: Header,
+        payload: &[u8],
+        packet: &mut Packet<&mut [u8]>,
+    ) -> Result<(), PacketError> {
+        packet.set_header(Header::Reply)?;
+
+        match header {
+            Header::SimplePoll => packet.set_data(&[]),
+            Header::RequestManufacturerId => packet.set_data(
+                self.implementation
+                    .manufacturer()
+                    .abbreviated_name()
+                    .as_bytes(),
+            ),
+            Header::RequestEquipementCategoryId => packet.set_data("Coin Acceptor".as_bytes()),
+            Header::RequestProductCode => {
+                packet.set_data(self.implementation.product_code().as_bytes())
+            }
+            Header::RequestSerialNumber => {
+                let serial_number = self.implementation.serial_number();
+                packet.set_data(
+                    [
+                        serial_number.fix(),
+                        serial_number.minor(),
+                        serial_number.major(),
+                    ]
+                    .as_ref(),
+                )
+            }
+            Header::RequestSoftwareRevision => {
+                packet.set_data(self.implementation.software_revision().as_bytes())
+            }
+            Header::RequestBuildCode => {
+                packet.set_data(self.implementation.build_code().as_bytes())
+            }
+            Header::RequestDataStorageAvailability => {
+                let data_storage = self.implementation.data_storage_availability();
+                let data_storage_bytes: [u8; 5] = data_storage.into();
+                packet.set_data(&data_storage_bytes)
+            }
+            Header::RequestCommsRevision => {
+                let (major, minor, patch) = self.implementation.comms_revision();
+                packet.set_data(&[major, minor, patch])
+            }
+            Header::RequestPollingPriority => {
+                let (unit, value) = self.implementation.polling_priority();
+                packet.set_data(&[unit, value])
+            }
+            Header::RequestCoinId => match payload.first() {
+                Some(position) => {
+                    packet.set_data(self.implementation.coin_id(*position).as_bytes())
+                }
+                None => {
+                    packet.set_header(Header::NACK)?;
+                    packet.set_data(&[])
+                }
+            },
+            Header::RequestSorterPaths => match payload.first() {
+                Some(position) => packet.set_data(&[self.implementation.sorter_path(*position)]),
+                None => {
+                    packet.set_header(Header::NACK)?;
+                    packet.set_data(&[])
+                }
+            },
+            Header::ReadBufferedCreditOrErrorCodes => {
+                let (event_counter, results) = self.implementation.read_buffered_credits().await;
+                let mut data = [0u8; 11];
+                data[0] = event_counter;
+                data[1..].copy_from_slice(&results);
+                packet.set_data(&data)
+            }
+            Header::ModifyInhibitStatus => {
+                if payload.len() < 2 {
+                    packet.set_header(Header::NACK)?;
+                    return packet.set_data(&[]);
+                }
+                self.implementation
+                    .set_inhibits([payload[0], payload[1]])
+                    .await;
+                packet.set_data(&[])
+            }
+            Header::RequestInhibitStatus => packet.set_data(&self.implementation.inhibits()),
+            Header::ModifyMasterInhibitStatus => {
+                if payload.is_empty() {
+                    packet.set_header(Header::NACK)?;
+                    return packet.set_data(&[]);
+                }
+                self.implementation
+                    .set_master_inhibit(payload[0] & 1 == 0)
+                    .await;
+                packet.set_data(&[])
+            }
+            Header::RequestMasterInhibitStatus => {
+                let status = u8::from(!self.implementation.master_inhibit());
+                packet.set_data(&[status])
+            }
+            Header::ResetDevice => {
+                self.implementation.reset().await;
+                packet.set_data(&[])
+            }
+            _ => {
+                packet.set_header(Header::NACK)?;
+                packet.set_data(&[])
+            }
+        }
+    }
+}
@@ -32,3 +32,35 @@ pub trait SimplePayoutDevice {
     fn enable_payout(&self, enable: bool) -> impl Future<Output = ()> + '_;
     fn test(&self) -> impl Future<Output = (u8, u8, u8)> + '_;
 }
+
+pub trait SimpleCoinAcceptor {
+    /// Returns the 6 character coin id at the given position, `......` if unused.
+    fn coin_id(&self, position: u8) -> &str;
+    fn sorter_path(&self, position: u8) -> u8;
+    /// Returns the polling priority as `(unit, value)`.
+    fn polling_priority(&self) -> (u8, u8);
+    /// Returns the event counter and the 5 last results, newest first.
+    fn read_buffered_credits(&self) -> impl Future<Output = (u8, [u8; 10])> + '_;
+    fn inhibits(&self) -> [u8; 2];
+    fn set_inhibits(&self, inhibits: [u8; 2]) -> impl Future<Output = ()> + '_;
+    /// Returns true when the master inhibit is active, i.e. all coins are rejected.
+    fn master_inhibit(&self) -> bool;
+    fn set_master_inhibit(&self, inhibit: bool) -> impl Future<Output = ()> + '_;
+}
+
+pub trait SimpleBillValidator {
+    /// Returns the 7 character bill id of the given bill type, `.......` if unused.
+    fn bill_id(&self, bill_type: u8) -> &str;
+    /// Returns the event counter and the 5 last events, newest first.
+    fn read_buffered_bill_events(&self) -> impl Future<Output = (u8, [u8; 10])> + '_;
+    /// Routes the bill held in escrow, returns the routing error code if it failed.
+    fn route_bill(&self, route_code: u8) -> impl Future<Output = Option<u8>> + '_;
+    fn inhibits(&self) -> [u8; 2];
+    fn set_inhibits(&self, inhibits: [u8; 2]) -> impl Future<Output = ()> + '_;
+    /// Returns true when the master inhibit is active, i.e. all bills are rejected.
+    fn master_inhibit(&self) -> bool;
+    fn set_master_inhibit(&self, inhibit: bool) -> impl Future<Output = ()> + '_;
+    /// Returns the operating mode mask, bit 0 enables the stacker and bit 1 the escrow.
+    fn operating_mode(&self) -> u8;
+    fn set_operating_mode(&self, mode: u8) -> impl Future<Output = ()> + '_;
+}
@@ -1,5 +1,7 @@
 #![no_std]
 
+pub mod bill_validator_device;
+pub mod coin_acceptor_device;
 pub mod device_impl;
 pub mod payout_device;
 
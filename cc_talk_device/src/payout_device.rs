@@ -1,5 +1,6 @@
 use cc_talk_core::cc_talk::{
-    deserializer::deserialize, serializer::serialize, Header, Packet, PacketError,
+    serializer::serialize, BusAddress, Header, Packet, PacketError, ValidatedPacket,
+    DESTINATION_OFFSET,
 };
 
 use crate::{
@@ -22,6 +23,7 @@ impl From<PacketError> for FrameError {
             PacketError::DataLengthMismatch => FrameError::FrameNotValid,
             PacketError::InvalidHeader(_) => FrameError::FrameNotValid,
             PacketError::InvalidPacket => FrameError::FrameNotValid,
+            PacketError::ChecksumMismatch { .. } => FrameError::FrameNotValid,
         }
     }
 }
@@ -41,6 +43,10 @@ where
         Self { implementation }
     }
 
+    pub fn implementation(&self) -> &T {
+        &self.implementation
+    }
+
     /// Process a ccTalk frame.
     ///
     /// `frame` has to be a valid ccTalk frame, which means it has to be at least 5 bytes long.
@@ -55,14 +61,13 @@ where
         reply_buffer: &mut [u8],
     ) -> Result<usize, FrameError> {
         match self.validate(frame) {
-            Some((packet, reply_address)) => {
-                let header = packet.get_header()?;
-                let payload = packet.get_data()?;
+            Some(packet) => {
                 let mut reply_packet = Packet::new(reply_buffer);
 
                 reply_packet.set_source(self.implementation.address())?;
-                reply_packet.set_destination(reply_address)?;
-                self.process_packet(header, payload, &mut reply_packet)
+                // CRC16 requests carry no source, they come from the host.
+                reply_packet.set_destination(packet.source().unwrap_or(BusAddress::HOST.get()))?;
+                self.process_packet(packet.header(), packet.data(), &mut reply_packet)
                     .await?;
 
                 match serialize(&self.implementation.device(), &mut reply_packet) {
@@ -77,19 +82,17 @@ where
         }
     }
 
-    fn validate<'a>(&self, buffer: &'a mut [u8]) -> Option<(Packet<&'a mut [u8]>, u8)> {
-        let mut p = Packet::new(&mut buffer[..]);
-
-        let destination = p.get_destination().unwrap_or(0u8);
+    fn validate<'a>(&self, frame: &'a [u8]) -> Option<ValidatedPacket<'a>> {
+        let destination = frame.get(DESTINATION_OFFSET).copied().unwrap_or(0u8);
         if !self.implementation.is_for_me(destination) {
             return None;
         }
 
-        match deserialize(&mut p, self.implementation.checksum_type()) {
-            Ok(reply_addr) => Some((p, reply_addr)),
+        match Packet::parse(frame, self.implementation.checksum_type()) {
+            Ok(packet) => Some(packet),
             Err(error) => {
                 // If we have a checksom error, or something similar, its better to not reply.
-                error!("failed to deserialize packet: {:?}", error);
+                error!("failed to validate packet: {:?}", error);
                 None
             }
         }
@@ -183,6 +186,8 @@ where
                 self.implementation.enable_payout(enable).await;
                 packet.set_data(&[])
             }
+            Header::PumpRNG => packet.set_data(&[]),
+            Header::RequestCipherKey => packet.set_data(&[0u8; 8]),
             Header::TestHopper => {
                 let (register_1, register_2, register_3) = self.implementation.test().await;
                 packet.set_data(&[register_1, register_2, register_3])
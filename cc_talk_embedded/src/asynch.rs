@@ -0,0 +1,277 @@
+use cc_talk_core::cc_talk::{
+    BusAddress, DATA_LENGTH_OFFSET, DATA_OFFSET, Device, EchoCanceller, FrameCipher, Header,
+    MAX_BLOCK_LENGTH,
+};
+use cc_talk_host::command::Command;
+use embassy_futures::select::{Either, select};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::{
+    config::TransportConfig,
+    error::TransportError,
+    frame::{self, HEADER_LENGTH},
+    log::{debug, trace},
+};
+
+/// ccTalk host transport over an async UART, e.g. an embassy `BufferedUart`.
+///
+/// Replies are timed by racing the reads against the `delay`, the UART read has to be cancel
+/// safe which buffered UARTs are.
+pub struct AsyncTransport<U, D> {
+    uart: U,
+    delay: D,
+    config: TransportConfig,
+    buffer: [u8; MAX_BLOCK_LENGTH],
+    echo: EchoCanceller,
+    cipher: Option<&'static dyn FrameCipher>,
+}
+
+// Embedded executors such as embassy run their tasks on a single thread.
+#[allow(clippy::future_not_send)]
+impl<U, D> AsyncTransport<U, D>
+where
+    U: Read + Write,
+    D: DelayNs,
+{
+    pub const fn new(uart: U, delay: D, config: TransportConfig) -> Self {
+        Self {
+            uart,
+            delay,
+            config,
+            buffer: [0; MAX_BLOCK_LENGTH],
+            echo: EchoCanceller::new(),
+            cipher: None,
+        }
+    }
+
+    /// Encrypts the frames exchanged with the devices which have an encryption key with
+    /// `cipher`, see [`Device::with_encryption_key`].
+    #[must_use]
+    pub const fn with_frame_cipher(mut self, cipher: &'static dyn FrameCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    pub const fn config(&self) -> &TransportConfig {
+        &self.config
+    }
+
+    /// Returns the UART and the delay.
+    pub fn release(self) -> (U, D) {
+        (self.uart, self.delay)
+    }
+
+    /// Sends the command to the device and parses its reply.
+    ///
+    /// Busy replies are retried with the [`Command::busy_policy`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the exchange fails after the retries, see [`Self::send`], or if the reply
+    /// can't be parsed.
+    pub async fn execute<C: Command>(
+        &mut self,
+        device: &Device,
+        command: &C,
+    ) -> Result<C::Response, TransportError<U::Error>> {
+        let policy = command.busy_policy();
+        let mut retry = 0;
+        loop {
+            match self.send(device, command.header(), command.data()).await {
+                Ok(frame) => {
+                    return Ok(command.parse_response(&frame[DATA_OFFSET..frame.len() - 1])?);
+                }
+                Err(TransportError::Busy) => {
+                    retry += 1;
+                    let Some(delay) = policy.delay(retry) else {
+                        return Err(TransportError::Busy);
+                    };
+                    debug!("{} busy, retry {}", device.address(), retry);
+                    self.delay
+                        .delay_us(u32::try_from(delay.as_micros()).unwrap_or(u32::MAX))
+                        .await;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Sends a frame to the device and returns its validated reply frame.
+    ///
+    /// Timeouts, corrupted replies and echo mismatches are retried, see
+    /// [`TransportConfig::retries`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the UART fails, if the device does not reply in time, NAKs or is busy.
+    pub async fn send(
+        &mut self,
+        device: &Device,
+        header: Header,
+        data: &[u8],
+    ) -> Result<&[u8], TransportError<U::Error>> {
+        let mut attempt = 0;
+        loop {
+            match self.exchange(device, header, data).await {
+                Ok(length) => return Ok(&self.buffer[..length]),
+                Err(error) if error.is_retryable() && attempt < self.config.retries => {
+                    attempt += 1;
+                    debug!(
+                        "retrying header {} to {}, attempt {}",
+                        header as u8,
+                        device.address(),
+                        attempt
+                    );
+                    self.flush_input().await?;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    async fn exchange(
+        &mut self,
+        device: &Device,
+        header: Header,
+        data: &[u8],
+    ) -> Result<usize, TransportError<U::Error>> {
+        let device = &self.config.device(device);
+        let length = frame::encode(device, self.cipher, header, data, &mut self.buffer)?;
+        trace!("sending {} bytes to {}", length, device.address());
+        self.uart
+            .write_all(&self.buffer[..length])
+            .await
+            .map_err(TransportError::Io)?;
+        self.uart.flush().await.map_err(TransportError::Io)?;
+
+        if self.config.echo {
+            self.echo.transmitted(&self.buffer[..length]);
+            while self.echo.pending() > 0 {
+                let byte = self.read_byte(self.echo.pending() == length).await?;
+                if let Err(mismatch) = self.echo.receive(byte) {
+                    debug!("{}", mismatch);
+                    return Err(TransportError::EchoMismatch);
+                }
+            }
+        }
+
+        let limit = self.config.response_limit(header);
+        // Frames addressed to another device are skipped.
+        let length = loop {
+            for index in 0..HEADER_LENGTH {
+                self.buffer[index] = self.read_byte(index == 0).await?;
+            }
+            let mut header = [0; HEADER_LENGTH];
+            header.copy_from_slice(&self.buffer[..HEADER_LENGTH]);
+            let length = frame::frame_length(header);
+            if self.buffer[0] == BusAddress::HOST.get() && header[DATA_LENGTH_OFFSET] > limit {
+                // Drop the rest of the frame to stay in sync with the line.
+                for _ in HEADER_LENGTH..length {
+                    self.read_byte(false).await?;
+                }
+                return Err(TransportError::ReplyTooLong {
+                    length: header[DATA_LENGTH_OFFSET],
+                    limit,
+                });
+            }
+            for index in HEADER_LENGTH..length {
+                self.buffer[index] = self.read_byte(false).await?;
+            }
+            if self.buffer[0] == BusAddress::HOST.get() {
+                break length;
+            }
+            trace!("skipping frame for {}", self.buffer[0]);
+        };
+        frame::check_reply(device, self.cipher, &mut self.buffer[..length])?;
+        Ok(length)
+    }
+
+    /// Reads a byte, waiting up to the reply timeout for the first byte of a frame and up to
+    /// the inter-byte timeout otherwise.
+    async fn read_byte(&mut self, first: bool) -> Result<u8, TransportError<U::Error>> {
+        let timeout_ms = if first {
+            self.config.reply_timeout_ms
+        } else {
+            self.config.inter_byte_timeout_ms
+        };
+        let mut byte = [0];
+        match select(self.uart.read(&mut byte), self.delay.delay_ms(timeout_ms)).await {
+            Either::First(Ok(0)) | Either::Second(()) => Err(TransportError::Timeout),
+            Either::First(Ok(_)) => Ok(byte[0]),
+            Either::First(Err(error)) => Err(TransportError::Io(error)),
+        }
+    }
+
+    /// Drops the bytes received until the line stays quiet for the inter-byte timeout.
+    async fn flush_input(&mut self) -> Result<(), TransportError<U::Error>> {
+        loop {
+            match self.read_byte(false).await {
+                Ok(_) => {}
+                Err(TransportError::Timeout) => return Ok(()),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use cc_talk_core::cc_talk::{Category, ChecksumType};
+    use cc_talk_host::core::core_commands::SimplePollCommand;
+    use embassy_futures::block_on;
+    use embedded_io_async::{ErrorKind, ErrorType};
+
+    use super::*;
+
+    /// A bus with echo on which a device NAKs every command.
+    #[derive(Default)]
+    struct NakingBus {
+        received: VecDeque<u8>,
+    }
+
+    impl ErrorType for NakingBus {
+        type Error = ErrorKind;
+    }
+
+    impl Read for NakingBus {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let Some(byte) = self.received.pop_front() else {
+                // Nothing more is sent, let the timeout win.
+                return core::future::pending().await;
+            };
+            buf[0] = byte;
+            Ok(1)
+        }
+    }
+
+    impl Write for NakingBus {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.received.extend(buf);
+            // NAK header with a CRC8 checksum.
+            self.received
+                .extend([1, 0, buf[0], 5, 250u8.wrapping_sub(buf[0])]);
+            Ok(buf.len())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn naks_are_reported() {
+        let mut transport =
+            AsyncTransport::new(NakingBus::default(), NoDelay, TransportConfig::default());
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+
+        let result = block_on(transport.execute(&device, &SimplePollCommand));
+        assert_eq!(result, Err(TransportError::Nack));
+        let (bus, _) = transport.release();
+        assert_eq!(bus.received, VecDeque::<u8>::new());
+    }
+}
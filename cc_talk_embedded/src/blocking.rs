@@ -0,0 +1,425 @@
+use cc_talk_core::cc_talk::{
+    BusAddress, DATA_LENGTH_OFFSET, DATA_OFFSET, Device, EchoCanceller, FrameCipher, Header,
+    MAX_BLOCK_LENGTH,
+};
+use cc_talk_host::command::Command;
+use embedded_hal::delay::DelayNs;
+use embedded_io::{Read, ReadReady, Write};
+
+use crate::{
+    config::TransportConfig,
+    error::TransportError,
+    frame::{self, HEADER_LENGTH},
+    log::{debug, trace},
+};
+
+/// Interval at which the UART is checked while waiting for a byte, in microseconds.
+const POLL_INTERVAL_US: u32 = 100;
+
+/// ccTalk host transport over a blocking UART.
+///
+/// Replies are timed by polling [`ReadReady`] and waiting on the `delay` in between, the
+/// transport needs no clock.
+pub struct BlockingTransport<U, D> {
+    uart: U,
+    delay: D,
+    config: TransportConfig,
+    buffer: [u8; MAX_BLOCK_LENGTH],
+    echo: EchoCanceller,
+    cipher: Option<&'static dyn FrameCipher>,
+}
+
+impl<U, D> BlockingTransport<U, D>
+where
+    U: Read + Write + ReadReady,
+    D: DelayNs,
+{
+    pub const fn new(uart: U, delay: D, config: TransportConfig) -> Self {
+        Self {
+            uart,
+            delay,
+            config,
+            buffer: [0; MAX_BLOCK_LENGTH],
+            echo: EchoCanceller::new(),
+            cipher: None,
+        }
+    }
+
+    /// Encrypts the frames exchanged with the devices which have an encryption key with
+    /// `cipher`, see [`Device::with_encryption_key`].
+    #[must_use]
+    pub const fn with_frame_cipher(mut self, cipher: &'static dyn FrameCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    pub const fn config(&self) -> &TransportConfig {
+        &self.config
+    }
+
+    /// Returns the UART and the delay.
+    pub fn release(self) -> (U, D) {
+        (self.uart, self.delay)
+    }
+
+    /// Sends the command to the device and parses its reply.
+    ///
+    /// Busy replies are retried with the [`Command::busy_policy`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the exchange fails after the retries, see [`Self::send`], or if the reply
+    /// can't be parsed.
+    pub fn execute<C: Command>(
+        &mut self,
+        device: &Device,
+        command: &C,
+    ) -> Result<C::Response, TransportError<U::Error>> {
+        let policy = command.busy_policy();
+        let mut retry = 0;
+        loop {
+            match self.send(device, command.header(), command.data()) {
+                Ok(frame) => {
+                    return Ok(command.parse_response(&frame[DATA_OFFSET..frame.len() - 1])?);
+                }
+                Err(TransportError::Busy) => {
+                    retry += 1;
+                    let Some(delay) = policy.delay(retry) else {
+                        return Err(TransportError::Busy);
+                    };
+                    debug!("{} busy, retry {}", device.address(), retry);
+                    self.delay
+                        .delay_us(u32::try_from(delay.as_micros()).unwrap_or(u32::MAX));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Sends a frame to the device and returns its validated reply frame.
+    ///
+    /// Timeouts, corrupted replies and echo mismatches are retried, see
+    /// [`TransportConfig::retries`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the UART fails, if the device does not reply in time, NAKs or is busy.
+    pub fn send(
+        &mut self,
+        device: &Device,
+        header: Header,
+        data: &[u8],
+    ) -> Result<&[u8], TransportError<U::Error>> {
+        let mut attempt = 0;
+        loop {
+            match self.exchange(device, header, data) {
+                Ok(length) => return Ok(&self.buffer[..length]),
+                Err(error) if error.is_retryable() && attempt < self.config.retries => {
+                    attempt += 1;
+                    debug!(
+                        "retrying header {} to {}, attempt {}",
+                        header as u8,
+                        device.address(),
+                        attempt
+                    );
+                    self.flush_input()?;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn exchange(
+        &mut self,
+        device: &Device,
+        header: Header,
+        data: &[u8],
+    ) -> Result<usize, TransportError<U::Error>> {
+        let device = &self.config.device(device);
+        let length = frame::encode(device, self.cipher, header, data, &mut self.buffer)?;
+        trace!("sending {} bytes to {}", length, device.address());
+        self.uart
+            .write_all(&self.buffer[..length])
+            .map_err(TransportError::Io)?;
+        self.uart.flush().map_err(TransportError::Io)?;
+
+        if self.config.echo {
+            self.echo.transmitted(&self.buffer[..length]);
+            while self.echo.pending() > 0 {
+                let byte = self.read_byte(self.echo.pending() == length)?;
+                if let Err(mismatch) = self.echo.receive(byte) {
+                    debug!("{}", mismatch);
+                    return Err(TransportError::EchoMismatch);
+                }
+            }
+        }
+
+        let limit = self.config.response_limit(header);
+        // Frames addressed to another device are skipped.
+        let length = loop {
+            for index in 0..HEADER_LENGTH {
+                self.buffer[index] = self.read_byte(index == 0)?;
+            }
+            let mut header = [0; HEADER_LENGTH];
+            header.copy_from_slice(&self.buffer[..HEADER_LENGTH]);
+            let length = frame::frame_length(header);
+            if self.buffer[0] == BusAddress::HOST.get() && header[DATA_LENGTH_OFFSET] > limit {
+                // Drop the rest of the frame to stay in sync with the line.
+                for _ in HEADER_LENGTH..length {
+                    self.read_byte(false)?;
+                }
+                return Err(TransportError::ReplyTooLong {
+                    length: header[DATA_LENGTH_OFFSET],
+                    limit,
+                });
+            }
+            for index in HEADER_LENGTH..length {
+                self.buffer[index] = self.read_byte(false)?;
+            }
+            if self.buffer[0] == BusAddress::HOST.get() {
+                break length;
+            }
+            trace!("skipping frame for {}", self.buffer[0]);
+        };
+        frame::check_reply(device, self.cipher, &mut self.buffer[..length])?;
+        Ok(length)
+    }
+
+    /// Reads a byte, waiting up to the reply timeout for the first byte of a frame and up to
+    /// the inter-byte timeout otherwise.
+    fn read_byte(&mut self, first: bool) -> Result<u8, TransportError<U::Error>> {
+        let timeout_ms = if first {
+            self.config.reply_timeout_ms
+        } else {
+            self.config.inter_byte_timeout_ms
+        };
+        let limit_us = timeout_ms.saturating_mul(1000);
+        let mut waited_us = 0;
+        while !self.uart.read_ready().map_err(TransportError::Io)? {
+            if waited_us >= limit_us {
+                return Err(TransportError::Timeout);
+            }
+            self.delay.delay_us(POLL_INTERVAL_US);
+            waited_us += POLL_INTERVAL_US;
+        }
+        let mut byte = [0];
+        match self.uart.read(&mut byte).map_err(TransportError::Io)? {
+            0 => Err(TransportError::Timeout),
+            _ => Ok(byte[0]),
+        }
+    }
+
+    /// Drops the bytes already received, e.g. the tail of a corrupted reply.
+    fn flush_input(&mut self) -> Result<(), TransportError<U::Error>> {
+        let mut scratch = [0; 16];
+        while self.uart.read_ready().map_err(TransportError::Io)? {
+            if self.uart.read(&mut scratch).map_err(TransportError::Io)? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, vec::Vec};
+
+    use cc_talk_core::cc_talk::{Category, ChecksumKind, ChecksumType, Manufacturer, crc8};
+    use cc_talk_host::core::core_commands::{RequestManufacturerIdCommand, SimplePollCommand};
+    use embedded_io::{ErrorKind, ErrorType};
+
+    use super::*;
+
+    /// A bus with echo on which the queued frames are received after the next write.
+    #[derive(Default)]
+    struct Bus {
+        received: VecDeque<u8>,
+        replies: VecDeque<Vec<u8>>,
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl ErrorType for Bus {
+        type Error = ErrorKind;
+    }
+
+    impl Read for Bus {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let mut read = 0;
+            while read < buf.len() {
+                let Some(byte) = self.received.pop_front() else {
+                    break;
+                };
+                buf[read] = byte;
+                read += 1;
+            }
+            Ok(read)
+        }
+    }
+
+    impl ReadReady for Bus {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.received.is_empty())
+        }
+    }
+
+    impl Write for Bus {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.sent.push(buf.to_vec());
+            self.received.extend(buf);
+            for reply in self.replies.drain(..) {
+                self.received.extend(reply);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn reply(mut frame: Vec<u8>) -> Vec<u8> {
+        frame.push(0);
+        let checksum_offset = frame.len() - 1;
+        frame[checksum_offset] = crc8(&frame);
+        frame
+    }
+
+    #[test]
+    fn commands_are_exchanged_and_parsed() {
+        let bus = Bus {
+            // A reply to another device, then the manufacturer.
+            replies: VecDeque::from([
+                reply(std::vec![5, 0, 2, 0]),
+                reply(std::vec![1, 3, 2, 0, b'W', b'H', b'M']),
+            ]),
+            ..Bus::default()
+        };
+        let mut transport = BlockingTransport::new(bus, NoDelay, TransportConfig::default());
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+
+        assert_eq!(
+            transport.execute(&device, &RequestManufacturerIdCommand),
+            Ok(Manufacturer::WHMunzprufer)
+        );
+        assert_eq!(transport.uart.sent, [std::vec![2, 0, 1, 246, 7]]);
+    }
+
+    #[test]
+    fn silent_device_times_out_after_the_retries() {
+        let mut transport = BlockingTransport::new(
+            Bus::default(),
+            NoDelay,
+            TransportConfig::default().with_retries(1),
+        );
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+
+        assert_eq!(
+            transport.execute(&device, &SimplePollCommand),
+            Err(TransportError::Timeout)
+        );
+        assert_eq!(transport.uart.sent.len(), 2);
+    }
+
+    #[test]
+    fn oversized_replies_are_dropped() {
+        let bus = Bus {
+            // A simple poll is only acknowledged.
+            replies: VecDeque::from([reply(std::vec![1, 2, 2, 0, 0, 0])]),
+            ..Bus::default()
+        };
+        let mut transport = BlockingTransport::new(bus, NoDelay, TransportConfig::default());
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+
+        assert_eq!(
+            transport.execute(&device, &SimplePollCommand),
+            Err(TransportError::ReplyTooLong {
+                length: 2,
+                limit: 0
+            })
+        );
+        assert!(transport.uart.received.is_empty());
+
+        let mut transport = BlockingTransport::new(
+            transport.release().0,
+            NoDelay,
+            TransportConfig::default().with_max_response_length(2),
+        );
+        transport
+            .uart
+            .replies
+            .push_back(reply(std::vec![1, 3, 2, 0, b'W', b'H', b'M']));
+        assert_eq!(
+            transport.execute(&device, &RequestManufacturerIdCommand),
+            Err(TransportError::ReplyTooLong {
+                length: 3,
+                limit: 2
+            })
+        );
+    }
+
+    #[test]
+    fn configured_crc16_devices_use_crc16() {
+        let mut manufacturer = std::vec![1, 3, 0, 0, b'J', b'C', b'M', 0];
+        ChecksumType::Crc16
+            .sign(&mut manufacturer)
+            .expect("should fit");
+        let bus = Bus {
+            replies: VecDeque::from([manufacturer]),
+            ..Bus::default()
+        };
+        let mut transport = BlockingTransport::new(
+            bus,
+            NoDelay,
+            TransportConfig::default().with_crc16_device(40),
+        );
+        let device = Device::new(40, Category::BillValidator, ChecksumType::Crc8);
+
+        assert_eq!(
+            transport.execute(&device, &RequestManufacturerIdCommand),
+            Ok(Manufacturer::JapanCashMachine)
+        );
+        assert_eq!(
+            ChecksumType::detect(&transport.uart.sent[0]),
+            Some(ChecksumType::Crc16)
+        );
+    }
+
+    #[test]
+    fn encrypted_devices_need_a_frame_cipher() {
+        use cc_talk_core::cc_talk::{BnvKey, XorChainCipher};
+
+        let key = BnvKey::new(123_456).expect("valid key");
+        let mut manufacturer = reply(std::vec![1, 3, 2, 0, b'W', b'H', b'M']);
+        XorChainCipher.encrypt(&key, &mut manufacturer);
+        let bus = Bus {
+            replies: VecDeque::from([manufacturer]),
+            ..Bus::default()
+        };
+        let device =
+            Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8).with_encryption_key(key);
+
+        let mut transport = BlockingTransport::new(bus, NoDelay, TransportConfig::default());
+        assert_eq!(
+            transport.execute(&device, &RequestManufacturerIdCommand),
+            Err(TransportError::MissingCipher)
+        );
+
+        let mut transport =
+            BlockingTransport::new(transport.release().0, NoDelay, TransportConfig::default())
+                .with_frame_cipher(&XorChainCipher);
+        assert_eq!(
+            transport.execute(&device, &RequestManufacturerIdCommand),
+            Ok(Manufacturer::WHMunzprufer)
+        );
+        let mut sent = transport.uart.sent[0].clone();
+        XorChainCipher.decrypt(&key, &mut sent);
+        assert_eq!(sent, [2, 0, 1, 246, 7]);
+    }
+}
@@ -0,0 +1,96 @@
+use cc_talk_core::cc_talk::{ChecksumType, Device, Header};
+use cc_talk_host::command_table::max_response_length;
+
+/// Timing and retry settings shared by the transports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransportConfig {
+    /// Time allowed to the device to start its reply, in milliseconds.
+    pub reply_timeout_ms: u32,
+    /// Maximum gap between two bytes of a frame, in milliseconds.
+    pub inter_byte_timeout_ms: u32,
+    /// Every byte sent on a two-wire bus is received back, the echo is read and checked
+    /// against the sent frame before waiting for the reply.
+    pub echo: bool,
+    /// Attempts made after a timeout, a corrupted reply or an echo mismatch.
+    pub retries: u8,
+    /// Longest reply payload read, replies are also capped to the longest reply of their
+    /// command in the command table. Longer replies are dropped before they are buffered.
+    pub max_response_length: u8,
+    /// Devices using CRC-16 checksums whatever the checksum type they are given, one bit per
+    /// address.
+    pub crc16_devices: [u8; 32],
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            reply_timeout_ms: 100,
+            inter_byte_timeout_ms: 50,
+            echo: true,
+            retries: 2,
+            max_response_length: u8::MAX,
+            crc16_devices: [0; 32],
+        }
+    }
+}
+
+impl TransportConfig {
+    #[must_use]
+    pub const fn with_reply_timeout_ms(mut self, reply_timeout_ms: u32) -> Self {
+        self.reply_timeout_ms = reply_timeout_ms;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_inter_byte_timeout_ms(mut self, inter_byte_timeout_ms: u32) -> Self {
+        self.inter_byte_timeout_ms = inter_byte_timeout_ms;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_max_response_length(mut self, max_response_length: u8) -> Self {
+        self.max_response_length = max_response_length;
+        self
+    }
+
+    /// Builds and verifies the frames exchanged with the device at `address` with CRC-16
+    /// checksums, e.g. a bill validator next to coin acceptors using the simple checksum.
+    #[must_use]
+    pub const fn with_crc16_device(mut self, address: u8) -> Self {
+        self.crc16_devices[address as usize / 8] |= 1 << (address % 8);
+        self
+    }
+
+    /// Returns `device` with the checksum type configured for its address.
+    pub(crate) fn device(&self, device: &Device) -> Device {
+        let address = device.address();
+        if self.crc16_devices[usize::from(address / 8)] & (1 << (address % 8)) == 0 {
+            device.clone()
+        } else {
+            device.clone().with_checksum_type(ChecksumType::Crc16)
+        }
+    }
+
+    /// Returns the longest reply payload accepted for `header`.
+    pub(crate) const fn response_limit(&self, header: Header) -> u8 {
+        let table_limit = max_response_length(header);
+        if table_limit < self.max_response_length {
+            table_limit
+        } else {
+            self.max_response_length
+        }
+    }
+}
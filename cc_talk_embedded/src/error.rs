@@ -0,0 +1,47 @@
+use cc_talk_core::cc_talk::PacketError;
+use cc_talk_host::command::ParseResponseError;
+
+/// Errors of an exchange with a device, `E` is the error of the UART.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransportError<E> {
+    #[error("uart error: {0:?}")]
+    Io(E),
+    #[error("timeout")]
+    Timeout,
+    #[error("echo does not match the sent frame")]
+    EchoMismatch,
+    #[error("invalid reply: {0}")]
+    InvalidReply(PacketError),
+    #[error("NACK")]
+    Nack,
+    #[error("device busy")]
+    Busy,
+    #[error("unable to build the frame")]
+    FrameTooLong,
+    /// The device has an encryption key but the transport has no frame cipher.
+    #[error("encrypted device without a frame cipher")]
+    MissingCipher,
+    /// The reply is longer than the command allows, see
+    /// [`TransportConfig::max_response_length`](crate::TransportConfig::max_response_length).
+    #[error("reply of {length} bytes, at most {limit} expected")]
+    ReplyTooLong { length: u8, limit: u8 },
+    #[error("unable to parse response: {0}")]
+    Parse(ParseResponseError),
+}
+
+impl<E> TransportError<E> {
+    /// Returns `true` for the errors worth sending the command again for.
+    pub(crate) const fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout | Self::EchoMismatch | Self::InvalidReply(_)
+        )
+    }
+}
+
+impl<E> From<ParseResponseError> for TransportError<E> {
+    fn from(error: ParseResponseError) -> Self {
+        Self::Parse(error)
+    }
+}
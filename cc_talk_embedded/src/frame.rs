@@ -0,0 +1,55 @@
+use cc_talk_core::cc_talk::{
+    DATA_LENGTH_OFFSET, Device, FrameCipher, Header, MAX_BLOCK_LENGTH, Packet, PacketBuilder,
+};
+
+use crate::error::TransportError;
+
+/// Destination, data length, source and header.
+pub const HEADER_LENGTH: usize = 4;
+
+/// Writes the frame of a command to `buffer`, encrypted with `cipher` if the device has an
+/// encryption key, returns its length.
+pub fn encode<E>(
+    device: &Device,
+    cipher: Option<&dyn FrameCipher>,
+    header: Header,
+    data: &[u8],
+    buffer: &mut [u8; MAX_BLOCK_LENGTH],
+) -> Result<usize, TransportError<E>> {
+    let length = PacketBuilder::new(device.address(), header)
+        .data(data)
+        .checksum_type(*device.checksum_type())
+        .write_to(buffer)
+        .map_err(|_| TransportError::FrameTooLong)?;
+    if let Some(key) = device.encryption_key() {
+        cipher
+            .ok_or(TransportError::MissingCipher)?
+            .encrypt(key, &mut buffer[..length]);
+    }
+    Ok(length)
+}
+
+/// Returns the length of the frame starting with `header`, its checksum included.
+pub fn frame_length(header: [u8; HEADER_LENGTH]) -> usize {
+    HEADER_LENGTH + usize::from(header[DATA_LENGTH_OFFSET]) + 1
+}
+
+/// Decrypts and validates a reply frame.
+pub fn check_reply<E>(
+    device: &Device,
+    cipher: Option<&dyn FrameCipher>,
+    frame: &mut [u8],
+) -> Result<(), TransportError<E>> {
+    if let Some(key) = device.encryption_key() {
+        cipher
+            .ok_or(TransportError::MissingCipher)?
+            .decrypt(key, frame);
+    }
+    let packet =
+        Packet::parse(frame, *device.checksum_type()).map_err(TransportError::InvalidReply)?;
+    match packet.header() {
+        Header::NACK => Err(TransportError::Nack),
+        Header::Busy => Err(TransportError::Busy),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,32 @@
+//! ccTalk host transports for `no_std` targets.
+//!
+//! The transports drive a UART through the [`embedded_io`] traits, [`BlockingTransport`] with
+//! [`embedded_hal::delay::DelayNs`] to time the replies, [`AsyncTransport`] with
+//! [`embedded_io_async`] and [`embedded_hal_async::delay::DelayNs`], e.g. an embassy UART and
+//! `embassy_time::Delay`. Both send the `Command` types of `cc_talk_host`:
+//!
+//! ```ignore
+//! use cc_talk_core::cc_talk::{Category, ChecksumType, Device};
+//! use cc_talk_embedded::{BlockingTransport, TransportConfig};
+//! use cc_talk_host::core::core_commands::RequestManufacturerIdCommand;
+//!
+//! let mut transport = BlockingTransport::new(uart, delay, TransportConfig::default());
+//! let hopper = Device::new(3, Category::Payout, ChecksumType::Crc8);
+//! let manufacturer = transport.execute(&hopper, &RequestManufacturerIdCommand)?;
+//! ```
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+mod asynch;
+mod blocking;
+mod config;
+mod error;
+mod frame;
+mod log;
+
+pub use asynch::AsyncTransport;
+pub use blocking::BlockingTransport;
+pub use config::TransportConfig;
+pub use error::TransportError;
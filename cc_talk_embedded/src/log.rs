@@ -0,0 +1,4 @@
+//! Logging through the facade of `cc_talk_core`, see [`cc_talk_core::log`].
+
+#[allow(unused_imports)]
+pub(crate) use cc_talk_core::log::{debug, error, info, trace, warn};
@@ -0,0 +1,1798 @@
+//! In-process emulated ccTalk bus.
+//!
+//! It backs the `--mock` option of `cc_talk_cli` and the end-to-end tests of the host drivers.
+//!
+//! A scenario file describes the devices present on the bus:
+//!
+//! ```toml
+//! [[hopper]]
+//! address = 3
+//! coin = "EU200A"
+//! coins = 50
+//!
+//! [[selector]]
+//! address = 2
+//! coins = ["EU010A", "EU020A", "EU050A", "EU100A", "EU200A"]
+//! # Coin positions inserted while the selector is accepting, one per poll.
+//! credits = [5, 1, 3]
+//!
+//! [[validator]]
+//! address = 40
+//! bills = ["EU0005A", "EU0010A"]
+//! # Bill types inserted while the validator is accepting, one per poll.
+//! credits = [2, 1]
+//! ```
+//!
+//! Hoppers are [`SimulatedHopper`]s, they track their inventory and event counters and can
+//! pay out at the pace of a real hopper and suffer opto faults:
+//!
+//! ```toml
+//! [[hopper]]
+//! address = 4
+//! coins = 20
+//! # One coin every 200ms, coins are paid out instantly by default.
+//! coin_interval_ms = 200
+//! # The exit opto gets blocked after 5 coins.
+//! opto_fault = "blocked_during_payout"
+//! opto_fault_after = 5
+//! ```
+//!
+//! The line itself can be noisy, each fault is drawn with its own probability for every frame:
+//!
+//! ```toml
+//! [noise]
+//! corrupt_checksum = 0.05
+//! truncate = 0.01
+//! duplicate_echo = 0.01
+//! insert_byte = 0.01
+//! seed = 42
+//! ```
+//!
+//! The bus is served on a Unix socket with [`MockBus::serve`], for the transport, or in memory
+//! with [`MockBus::serve_channel`], in place of the transport.
+//!
+//! Tests drive the devices while the bus runs through a [`MockControl`], either directly or
+//! through a control socket served by [`MockControl::serve`].
+
+use std::{
+    collections::VecDeque,
+    future::{Future, ready},
+    io,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cc_talk_core::cc_talk::{
+    BusAddress, Category, ChecksumType, DataStorage, Device, Header, HopperDispenseStatus,
+    HopperStatus, MAX_BLOCK_LENGTH, Manufacturer, Packet, SerialCode, serializer::serialize,
+};
+use cc_talk_device::{
+    bill_validator_device::BillValidatorDevice,
+    coin_acceptor_device::CoinAcceptorDevice,
+    device_impl::{DeviceImpl, SimpleBillValidator, SimpleCoinAcceptor, SimplePayoutDevice},
+    payout_device::PayoutDevice,
+};
+use cc_talk_tokio_host::transport::tokio_transport::{TransportError, TransportMessage};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::mpsc,
+};
+use tracing::{debug, info, warn};
+
+mod noise;
+
+use noise::Noise;
+pub use noise::{InjectedFaults, LineNoise};
+
+const UNUSED_COIN: &str = "......";
+const UNUSED_BILL: &str = ".......";
+
+/// Operating mode bit enabling the escrow, see `ModifyBillOperatingMode`.
+const ESCROW_MODE: u8 = 0b10;
+
+const IDLE_HOPPER: HopperDispenseStatus = HopperDispenseStatus {
+    event_counter: 0,
+    coins_remaining: 0,
+    paid: 0,
+    unpaid: 0,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScenarioError {
+    #[error("unable to read scenario: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid scenario: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("unknown manufacturer '{0}'")]
+    UnknownManufacturer(String),
+    #[error("address {0} is used by more than one device")]
+    DuplicateAddress(u8),
+    #[error("noise probability '{0}' is not between 0 and 1")]
+    InvalidProbability(&'static str),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ControlError {
+    #[error("no emulated device at address {0}")]
+    UnknownAddress(u8),
+    #[error("device {address} cannot {action}")]
+    Unsupported { address: u8, action: &'static str },
+    #[error("invalid control command '{0}'")]
+    InvalidCommand(String),
+}
+
+/// Devices present on the emulated bus.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default, rename = "hopper")]
+    pub hoppers: Vec<HopperScenario>,
+    #[serde(default, rename = "selector")]
+    pub selectors: Vec<SelectorScenario>,
+    #[serde(default, rename = "validator")]
+    pub validators: Vec<ValidatorScenario>,
+    /// Electrical faults of the line, none by default.
+    #[serde(default)]
+    pub noise: LineNoise,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityScenario {
+    #[serde(default = "default_manufacturer")]
+    pub manufacturer: String,
+    #[serde(default = "default_product_code")]
+    pub product_code: String,
+    #[serde(default)]
+    pub serial_number: u32,
+    #[serde(default = "default_software_revision")]
+    pub software_revision: String,
+}
+
+impl Default for IdentityScenario {
+    fn default() -> Self {
+        Self {
+            manufacturer: default_manufacturer(),
+            product_code: default_product_code(),
+            serial_number: 0,
+            software_revision: default_software_revision(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HopperScenario {
+    pub address: u8,
+    /// Name of the device, offered by shell completion.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default, flatten)]
+    pub identity: IdentityScenario,
+    /// Coin id dispensed by the hopper.
+    #[serde(default = "default_hopper_coin")]
+    pub coin: String,
+    /// Number of coins currently in the hopper.
+    #[serde(default = "default_hopper_coins")]
+    pub coins: u32,
+    #[serde(default = "default_low_level")]
+    pub low_level: u32,
+    #[serde(default = "default_high_level")]
+    pub high_level: u32,
+    #[serde(default)]
+    pub dispense_count: u32,
+    /// Registers returned by the self test.
+    #[serde(default)]
+    pub test_registers: [u8; 3],
+    /// Coins paid before the hopper jams, a jammed hopper pays nothing and reports
+    /// `AbsoluteMaximumCurrentExceeded` in its self test.
+    #[serde(default)]
+    pub jam_after: Option<u32>,
+    /// Time taken to pay out each coin, coins are paid out instantly if 0.
+    #[serde(default)]
+    pub coin_interval_ms: u64,
+    /// Fault of the exit opto. Idle faults are present from power up, payout faults are raised
+    /// once `opto_fault_after` coins were paid. A faulty hopper pays nothing until it is reset.
+    #[serde(default)]
+    pub opto_fault: Option<OptoFault>,
+    #[serde(default)]
+    pub opto_fault_after: u32,
+}
+
+/// Fault of the exit opto of a [`SimulatedHopper`], reported by its self test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptoFault {
+    PathBlockedDuringIdle,
+    ShortCircuitDuringIdle,
+    BlockedDuringPayout,
+    ShortCircuitDuringPayout,
+}
+
+impl OptoFault {
+    const fn is_idle(self) -> bool {
+        matches!(
+            self,
+            Self::PathBlockedDuringIdle | Self::ShortCircuitDuringIdle
+        )
+    }
+
+    /// Returns the self test registers 1 and 2 flagging the fault.
+    const fn registers(self) -> (u8, u8) {
+        match self {
+            Self::PathBlockedDuringIdle => (1 << 3, 0),
+            Self::ShortCircuitDuringIdle => (1 << 4, 0),
+            Self::BlockedDuringPayout => (1 << 5, 0),
+            Self::ShortCircuitDuringPayout => (0, 1 << 0),
+        }
+    }
+}
+
+impl FromStr for OptoFault {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "path_blocked_during_idle" => Ok(Self::PathBlockedDuringIdle),
+            "short_circuit_during_idle" => Ok(Self::ShortCircuitDuringIdle),
+            "blocked_during_payout" => Ok(Self::BlockedDuringPayout),
+            "short_circuit_during_payout" => Ok(Self::ShortCircuitDuringPayout),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SelectorScenario {
+    pub address: u8,
+    /// Name of the device, offered by shell completion.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default, flatten)]
+    pub identity: IdentityScenario,
+    /// Coin ids, the first entry is position 1.
+    #[serde(default)]
+    pub coins: Vec<String>,
+    /// Sorter path of every coin.
+    #[serde(default = "default_sorter_path")]
+    pub sorter_path: u8,
+    /// Coin positions inserted while the selector accepts coins, one per poll.
+    #[serde(default)]
+    pub credits: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidatorScenario {
+    pub address: u8,
+    /// Name of the device, offered by shell completion.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default, flatten)]
+    pub identity: IdentityScenario,
+    /// Bill ids, the first entry is bill type 1.
+    #[serde(default)]
+    pub bills: Vec<String>,
+    /// Bill types inserted while the validator accepts bills, one per poll.
+    ///
+    /// With the escrow enabled the next bill is only inserted once the held bill is routed.
+    #[serde(default)]
+    pub credits: Vec<u8>,
+}
+
+fn default_manufacturer() -> String {
+    Manufacturer::InnovativeTechnology
+        .abbreviated_name()
+        .to_string()
+}
+
+fn default_product_code() -> String {
+    "MOCK".to_string()
+}
+
+fn default_software_revision() -> String {
+    "MOCK-1.0".to_string()
+}
+
+fn default_hopper_coin() -> String {
+    "EU100A".to_string()
+}
+
+const fn default_hopper_coins() -> u32 {
+    100
+}
+
+const fn default_low_level() -> u32 {
+    10
+}
+
+const fn default_high_level() -> u32 {
+    400
+}
+
+const fn default_sorter_path() -> u8 {
+    1
+}
+
+impl Scenario {
+    /// Loads and validates a scenario file.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file cannot be read or does not describe a valid bus.
+    pub fn load(path: &Path) -> Result<Self, ScenarioError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses and validates a scenario.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the content does not describe a valid bus.
+    pub fn parse(content: &str) -> Result<Self, ScenarioError> {
+        let scenario: Self = toml::from_str(content)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> Result<(), ScenarioError> {
+        let mut addresses = vec![];
+        let identities = self
+            .hoppers
+            .iter()
+            .map(|hopper| (hopper.address, &hopper.identity))
+            .chain(
+                self.selectors
+                    .iter()
+                    .map(|selector| (selector.address, &selector.identity)),
+            )
+            .chain(
+                self.validators
+                    .iter()
+                    .map(|validator| (validator.address, &validator.identity)),
+            );
+        for (address, identity) in identities {
+            if addresses.contains(&address) {
+                return Err(ScenarioError::DuplicateAddress(address));
+            }
+            addresses.push(address);
+            identity.manufacturer()?;
+        }
+        if let Some(name) = self.noise.invalid_probability() {
+            return Err(ScenarioError::InvalidProbability(name));
+        }
+        Ok(())
+    }
+}
+
+impl IdentityScenario {
+    /// Returns a short description of the device, e.g. `MCI SCH2`.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        format!("{} {}", self.manufacturer, self.product_code)
+    }
+
+    fn manufacturer(&self) -> Result<Manufacturer, ScenarioError> {
+        Manufacturer::from_name(&self.manufacturer)
+            .ok_or_else(|| ScenarioError::UnknownManufacturer(self.manufacturer.clone()))
+    }
+
+    const fn serial_number(&self) -> SerialCode {
+        let [fix, minor, major, _] = self.serial_number.to_le_bytes();
+        SerialCode::new(major, minor, fix)
+    }
+}
+
+#[derive(Debug)]
+struct MockIdentity {
+    device: Device,
+    manufacturer: Manufacturer,
+    identity: IdentityScenario,
+}
+
+impl MockIdentity {
+    fn new(
+        address: u8,
+        category: Category,
+        identity: &IdentityScenario,
+    ) -> Result<Self, ScenarioError> {
+        Ok(Self {
+            device: Device::new(address, category, ChecksumType::Crc8),
+            manufacturer: identity.manufacturer()?,
+            identity: identity.clone(),
+        })
+    }
+}
+
+/// Implements [`DeviceImpl`] by delegating to the `identity` field.
+macro_rules! impl_device {
+    ($device:ty) => {
+        impl DeviceImpl for $device {
+            fn manufacturer(&self) -> Manufacturer {
+                self.identity.manufacturer
+            }
+
+            fn category(&self) -> Category {
+                self.identity.device.category().clone()
+            }
+
+            fn checksum_type(&self) -> ChecksumType {
+                *self.identity.device.checksum_type()
+            }
+
+            fn product_code(&self) -> &str {
+                &self.identity.identity.product_code
+            }
+
+            fn serial_number(&self) -> SerialCode {
+                self.identity.identity.serial_number()
+            }
+
+            fn software_revision(&self) -> &str {
+                &self.identity.identity.software_revision
+            }
+
+            fn build_code(&self) -> &str {
+                "MOCK"
+            }
+
+            fn data_storage_availability(&self) -> DataStorage {
+                DataStorage::from([0u8; 5])
+            }
+
+            fn comms_revision(&self) -> (u8, u8, u8) {
+                (1, 4, 7)
+            }
+
+            fn reset(&self) -> impl Future<Output = ()> + '_ {
+                self.reset_state();
+                ready(())
+            }
+
+            fn is_for_me(&self, destination_address: u8) -> bool {
+                destination_address == self.identity.device.address()
+            }
+
+            fn address(&self) -> u8 {
+                self.identity.device.address()
+            }
+
+            fn device(&self) -> Device {
+                self.identity.device.clone()
+            }
+        }
+    };
+}
+
+#[derive(Debug)]
+struct HopperState {
+    enabled: bool,
+    coins: u32,
+    dispense_count: u32,
+    status: HopperDispenseStatus,
+    /// Coins left before the hopper jams.
+    jam_budget: Option<u32>,
+    /// Coins left before the exit opto faults during a payout.
+    opto_budget: Option<u32>,
+    opto_fault: Option<OptoFault>,
+    /// Start of the payout in progress.
+    payout_started: Option<Instant>,
+}
+
+/// Emulated hopper, configured by a [`HopperScenario`].
+///
+/// The hopper tracks the coins it holds, its dispense count and its event counter. A payout
+/// progresses with time, one coin per `coin_interval_ms`, and stops early if the hopper runs
+/// dry, jams or its exit opto faults.
+#[derive(Debug)]
+pub struct SimulatedHopper {
+    identity: MockIdentity,
+    scenario: HopperScenario,
+    state: Mutex<HopperState>,
+}
+
+impl SimulatedHopper {
+    /// Creates the hopper in its power up state.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the manufacturer of the scenario is unknown.
+    pub fn new(scenario: &HopperScenario) -> Result<Self, ScenarioError> {
+        let hopper = Self {
+            identity: MockIdentity::new(scenario.address, Category::Payout, &scenario.identity)?,
+            scenario: scenario.clone(),
+            state: Mutex::new(HopperState {
+                enabled: false,
+                coins: scenario.coins,
+                dispense_count: scenario.dispense_count,
+                status: IDLE_HOPPER,
+                jam_budget: None,
+                opto_budget: None,
+                opto_fault: None,
+                payout_started: None,
+            }),
+        };
+        hopper.reset_state();
+        Ok(hopper)
+    }
+
+    /// Returns the number of coins the hopper holds.
+    pub fn coins(&self) -> u32 {
+        self.advanced_state().coins
+    }
+
+    /// Returns `true` while a payout is in progress.
+    pub fn is_dispensing(&self) -> bool {
+        self.advanced_state().status.coins_remaining > 0
+    }
+
+    /// Returns the number of coins the hopper paid over its lifetime, like the dispense count
+    /// it reports to the host.
+    pub fn dispense_count(&self) -> u32 {
+        self.advanced_state().dispense_count
+    }
+
+    /// Adds coins to the hopper.
+    pub fn refill(&self, coins: u32) {
+        let mut state = self.advanced_state();
+        state.coins = state.coins.saturating_add(coins);
+    }
+
+    /// Jams the hopper until the next reset, whatever the `jam_after` of the scenario.
+    pub fn jam(&self) {
+        self.advanced_state().jam_budget = Some(0);
+    }
+
+    /// Raises an exit opto fault until the next reset, a payout in progress stops.
+    pub fn inject_opto_fault(&self, fault: OptoFault) {
+        let mut state = self.advanced_state();
+        state.opto_fault = Some(fault);
+        stop_payout(&mut state);
+        drop(state);
+    }
+
+    /// Returns to the power up state, the coins paid so far leave the hopper and the coins left
+    /// and the dispense count survive the reset.
+    fn reset_state(&self) {
+        let mut state = self.advanced_state();
+        let opto_fault = self.scenario.opto_fault;
+        *state = HopperState {
+            enabled: false,
+            coins: state.coins,
+            dispense_count: state.dispense_count,
+            status: IDLE_HOPPER,
+            jam_budget: self.scenario.jam_after,
+            opto_budget: opto_fault
+                .filter(|fault| !fault.is_idle())
+                .map(|_| self.scenario.opto_fault_after),
+            opto_fault: opto_fault.filter(|fault| fault.is_idle()),
+            payout_started: None,
+        };
+    }
+
+    fn is_jammed(&self) -> bool {
+        self.advanced_state().jam_budget == Some(0)
+    }
+
+    /// Locks the state once the coins due by now are paid.
+    fn advanced_state(&self) -> std::sync::MutexGuard<'_, HopperState> {
+        let mut state = self.state.lock().expect("should not be poisoned");
+        self.advance(&mut state);
+        state
+    }
+
+    /// Pays the coins of the payout in progress due by now.
+    fn advance(&self, state: &mut HopperState) {
+        let Some(started) = state.payout_started else {
+            return;
+        };
+        let interval = Duration::from_millis(self.scenario.coin_interval_ms);
+        let due = if interval.is_zero() {
+            u32::MAX
+        } else {
+            u32::try_from(started.elapsed().as_nanos() / interval.as_nanos()).unwrap_or(u32::MAX)
+        };
+        while state.status.coins_remaining > 0 && u32::from(state.status.paid) < due {
+            if state.coins == 0 || state.jam_budget == Some(0) {
+                stop_payout(state);
+                break;
+            }
+            if state.opto_budget == Some(0) {
+                state.opto_budget = None;
+                state.opto_fault = self.scenario.opto_fault;
+                stop_payout(state);
+                break;
+            }
+            state.coins -= 1;
+            for budget in [&mut state.jam_budget, &mut state.opto_budget]
+                .into_iter()
+                .flatten()
+            {
+                *budget -= 1;
+            }
+            state.dispense_count = state.dispense_count.wrapping_add(1);
+            state.status.coins_remaining -= 1;
+            state.status.paid += 1;
+        }
+        if state.status.coins_remaining == 0 {
+            state.payout_started = None;
+        }
+    }
+}
+
+/// Stops the payout in progress, the coins left are unpaid.
+const fn stop_payout(state: &mut HopperState) {
+    state.status.unpaid += state.status.coins_remaining;
+    state.status.coins_remaining = 0;
+    state.payout_started = None;
+}
+
+impl_device!(SimulatedHopper);
+
+impl SimplePayoutDevice for SimulatedHopper {
+    fn request_sensor_status(&self) -> impl Future<Output = HopperStatus> + '_ {
+        let coins = self.coins();
+        ready(HopperStatus {
+            low_level_supported: true,
+            higher_than_low_level: coins >= self.scenario.low_level,
+            high_level_supported: true,
+            higher_than_high_level: coins >= self.scenario.high_level,
+        })
+    }
+
+    fn emergency_stop(&self) -> impl Future<Output = ()> + '_ {
+        let mut state = self.advanced_state();
+        stop_payout(&mut state);
+        state.enabled = false;
+        drop(state);
+        ready(())
+    }
+
+    fn request_hopper_coin(&self) -> &str {
+        &self.scenario.coin
+    }
+
+    fn request_hopper_dispense_count(&self) -> impl Future<Output = u32> + '_ {
+        ready(self.advanced_state().dispense_count)
+    }
+
+    fn dispense_hopper_coins(&self, count: u8) -> impl Future<Output = ()> + '_ {
+        let mut state = self.advanced_state();
+        if !state.enabled {
+            drop(state);
+            warn!(address = self.address(), "mock hopper is disabled");
+            return ready(());
+        }
+        if state.status.coins_remaining > 0 {
+            drop(state);
+            warn!(
+                address = self.address(),
+                "mock hopper is already dispensing"
+            );
+            return ready(());
+        }
+
+        let event_counter = next_event_counter(state.status.event_counter);
+        if let Some(fault) = state.opto_fault {
+            state.status = HopperDispenseStatus {
+                event_counter,
+                coins_remaining: 0,
+                paid: 0,
+                unpaid: count,
+            };
+            drop(state);
+            warn!(address = self.address(), ?fault, "mock hopper opto fault");
+            return ready(());
+        }
+        state.status = HopperDispenseStatus {
+            event_counter,
+            coins_remaining: count,
+            paid: 0,
+            unpaid: 0,
+        };
+        state.payout_started = Some(Instant::now());
+        self.advance(&mut state);
+        drop(state);
+        debug!(address = self.address(), count, "mock hopper dispensing");
+        ready(())
+    }
+
+    fn request_payout_status(&self) -> impl Future<Output = HopperDispenseStatus> + '_ {
+        ready(self.advanced_state().status)
+    }
+
+    fn enable_payout(&self, enable: bool) -> impl Future<Output = ()> + '_ {
+        self.advanced_state().enabled = enable;
+        ready(())
+    }
+
+    fn test(&self) -> impl Future<Output = (u8, u8, u8)> + '_ {
+        let [mut register_1, mut register_2, register_3] = self.scenario.test_registers;
+        if self.is_jammed() {
+            // Absolute maximum current exceeded
+            register_1 |= 1;
+        }
+        let opto_fault = self.advanced_state().opto_fault;
+        if let Some(fault) = opto_fault {
+            let (fault_1, fault_2) = fault.registers();
+            register_1 |= fault_1;
+            register_2 |= fault_2;
+        }
+        ready((register_1, register_2, register_3))
+    }
+}
+
+#[derive(Debug, Default)]
+struct SelectorState {
+    master_inhibit: bool,
+    inhibits: [u8; 2],
+    event_counter: u8,
+    results: [u8; 10],
+    credits: VecDeque<u8>,
+    /// Positions of the coins accepted so far, kept across resets.
+    accepted: Vec<u8>,
+}
+
+impl SelectorState {
+    fn push_result(&mut self, result_a: u8, result_b: u8) {
+        self.results.copy_within(0..8, 2);
+        self.results[0] = result_a;
+        self.results[1] = result_b;
+        self.event_counter = next_event_counter(self.event_counter);
+    }
+}
+
+#[derive(Debug)]
+struct MockSelector {
+    identity: MockIdentity,
+    scenario: SelectorScenario,
+    state: Mutex<SelectorState>,
+}
+
+impl MockSelector {
+    fn new(scenario: &SelectorScenario) -> Result<Self, ScenarioError> {
+        let selector = Self {
+            identity: MockIdentity::new(
+                scenario.address,
+                Category::CoinAcceptor,
+                &scenario.identity,
+            )?,
+            scenario: scenario.clone(),
+            state: Mutex::default(),
+        };
+        selector.reset_state();
+        Ok(selector)
+    }
+
+    fn reset_state(&self) {
+        let mut state = self.state.lock().expect("should not be poisoned");
+        *state = SelectorState {
+            master_inhibit: true,
+            inhibits: [0, 0],
+            event_counter: 0,
+            results: [0; 10],
+            credits: self.scenario.credits.iter().copied().collect(),
+            accepted: std::mem::take(&mut state.accepted),
+        };
+    }
+
+    /// Queues a coin, it is inserted once the queued credits before it are.
+    fn insert_coin(&self, position: u8) {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .credits
+            .push_back(position);
+    }
+
+    fn accepted(&self) -> Vec<u8> {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .accepted
+            .clone()
+    }
+
+    fn jam(&self) {
+        // Credit sensor timeout, possible coin jam
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .push_result(0, 6);
+    }
+
+    const fn is_inhibited(inhibits: [u8; 2], position: u8) -> bool {
+        if position == 0 || position > 16 {
+            return true;
+        }
+        let bit = position - 1;
+        inhibits[(bit / 8) as usize] & (1 << (bit % 8)) == 0
+    }
+}
+
+impl_device!(MockSelector);
+
+impl SimpleCoinAcceptor for MockSelector {
+    fn coin_id(&self, position: u8) -> &str {
+        position
+            .checked_sub(1)
+            .and_then(|index| self.scenario.coins.get(usize::from(index)))
+            .map_or(UNUSED_COIN, String::as_str)
+    }
+
+    fn sorter_path(&self, _position: u8) -> u8 {
+        self.scenario.sorter_path
+    }
+
+    fn polling_priority(&self) -> (u8, u8) {
+        // 20 x 10ms
+        (2, 20)
+    }
+
+    fn read_buffered_credits(&self) -> impl Future<Output = (u8, [u8; 10])> + '_ {
+        let mut state = self.state.lock().expect("should not be poisoned");
+        let inhibits = state.inhibits;
+        if !state.master_inhibit
+            && let Some(position) = state.credits.pop_front()
+        {
+            if Self::is_inhibited(inhibits, position) {
+                // Inhibited coin rejected
+                state.push_result(0, 2);
+            } else {
+                state.push_result(position, self.scenario.sorter_path);
+                state.accepted.push(position);
+            }
+        }
+        ready((state.event_counter, state.results))
+    }
+
+    fn inhibits(&self) -> [u8; 2] {
+        self.state.lock().expect("should not be poisoned").inhibits
+    }
+
+    fn set_inhibits(&self, inhibits: [u8; 2]) -> impl Future<Output = ()> + '_ {
+        self.state.lock().expect("should not be poisoned").inhibits = inhibits;
+        ready(())
+    }
+
+    fn master_inhibit(&self) -> bool {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .master_inhibit
+    }
+
+    fn set_master_inhibit(&self, inhibit: bool) -> impl Future<Output = ()> + '_ {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .master_inhibit = inhibit;
+        ready(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct ValidatorState {
+    master_inhibit: bool,
+    inhibits: [u8; 2],
+    operating_mode: u8,
+    event_counter: u8,
+    events: [u8; 10],
+    credits: VecDeque<u8>,
+    escrow: Option<u8>,
+}
+
+impl ValidatorState {
+    fn push_event(&mut self, result_a: u8, result_b: u8) {
+        self.events.copy_within(0..8, 2);
+        self.events[0] = result_a;
+        self.events[1] = result_b;
+        self.event_counter = next_event_counter(self.event_counter);
+    }
+}
+
+#[derive(Debug)]
+struct MockValidator {
+    identity: MockIdentity,
+    scenario: ValidatorScenario,
+    state: Mutex<ValidatorState>,
+}
+
+impl MockValidator {
+    fn new(scenario: &ValidatorScenario) -> Result<Self, ScenarioError> {
+        let validator = Self {
+            identity: MockIdentity::new(
+                scenario.address,
+                Category::BillValidator,
+                &scenario.identity,
+            )?,
+            scenario: scenario.clone(),
+            state: Mutex::default(),
+        };
+        validator.reset_state();
+        Ok(validator)
+    }
+
+    fn reset_state(&self) {
+        let mut state = self.state.lock().expect("should not be poisoned");
+        *state = ValidatorState {
+            master_inhibit: true,
+            inhibits: [0, 0],
+            operating_mode: 0,
+            event_counter: 0,
+            events: [0; 10],
+            credits: self.scenario.credits.iter().copied().collect(),
+            escrow: None,
+        };
+    }
+
+    /// Queues a bill, it is inserted once the queued credits before it are.
+    fn insert_bill(&self, bill_type: u8) {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .credits
+            .push_back(bill_type);
+    }
+
+    fn jam(&self) {
+        // Bill jammed in transport (safe mode)
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .push_event(0, 16);
+    }
+}
+
+impl_device!(MockValidator);
+
+impl SimpleBillValidator for MockValidator {
+    fn bill_id(&self, bill_type: u8) -> &str {
+        bill_type
+            .checked_sub(1)
+            .and_then(|index| self.scenario.bills.get(usize::from(index)))
+            .map_or(UNUSED_BILL, String::as_str)
+    }
+
+    fn read_buffered_bill_events(&self) -> impl Future<Output = (u8, [u8; 10])> + '_ {
+        let mut state = self.state.lock().expect("should not be poisoned");
+        if !state.master_inhibit
+            && state.escrow.is_none()
+            && let Some(bill_type) = state.credits.pop_front()
+        {
+            if MockSelector::is_inhibited(state.inhibits, bill_type) {
+                // Inhibited bill via serial
+                state.push_event(0, 4);
+            } else if state.operating_mode & ESCROW_MODE != 0 {
+                state.escrow = Some(bill_type);
+                state.push_event(bill_type, 1);
+            } else {
+                state.push_event(bill_type, 0);
+            }
+        }
+        ready((state.event_counter, state.events))
+    }
+
+    fn route_bill(&self, route_code: u8) -> impl Future<Output = Option<u8>> + '_ {
+        let mut state = self.state.lock().expect("should not be poisoned");
+        let result = match (state.escrow.take(), route_code) {
+            // Escrow empty
+            (None, _) => Some(254),
+            (Some(bill_type), 0) => {
+                debug!(
+                    address = self.address(),
+                    bill_type, "mock validator returned bill"
+                );
+                // Bill returned from escrow
+                state.push_event(0, 1);
+                None
+            }
+            (Some(bill_type), 1) => {
+                debug!(
+                    address = self.address(),
+                    bill_type, "mock validator stacked bill"
+                );
+                state.push_event(bill_type, 0);
+                None
+            }
+            (Some(bill_type), 255) => {
+                state.escrow = Some(bill_type);
+                None
+            }
+            // Failed to route
+            (Some(bill_type), _) => {
+                state.escrow = Some(bill_type);
+                Some(255)
+            }
+        };
+        drop(state);
+        ready(result)
+    }
+
+    fn inhibits(&self) -> [u8; 2] {
+        self.state.lock().expect("should not be poisoned").inhibits
+    }
+
+    fn set_inhibits(&self, inhibits: [u8; 2]) -> impl Future<Output = ()> + '_ {
+        self.state.lock().expect("should not be poisoned").inhibits = inhibits;
+        ready(())
+    }
+
+    fn master_inhibit(&self) -> bool {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .master_inhibit
+    }
+
+    fn set_master_inhibit(&self, inhibit: bool) -> impl Future<Output = ()> + '_ {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .master_inhibit = inhibit;
+        ready(())
+    }
+
+    fn operating_mode(&self) -> u8 {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .operating_mode
+    }
+
+    fn set_operating_mode(&self, mode: u8) -> impl Future<Output = ()> + '_ {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .operating_mode = mode;
+        ready(())
+    }
+}
+
+/// Event counters wrap from 255 to 1, 0 is reserved for power up and reset.
+const fn next_event_counter(counter: u8) -> u8 {
+    match counter {
+        u8::MAX => 1,
+        counter => counter + 1,
+    }
+}
+
+/// Emulated devices, shared by the bus and its controls.
+struct Devices {
+    hoppers: Vec<PayoutDevice<SimulatedHopper>>,
+    selectors: Vec<CoinAcceptorDevice<MockSelector>>,
+    validators: Vec<BillValidatorDevice<MockValidator>>,
+}
+
+impl Devices {
+    /// Addresses of every device, in ascending order.
+    fn addresses(&self) -> Vec<u8> {
+        let mut addresses: Vec<u8> = self
+            .hoppers
+            .iter()
+            .map(|hopper| hopper.implementation().address())
+            .chain(
+                self.selectors
+                    .iter()
+                    .map(|selector| selector.implementation().address()),
+            )
+            .chain(
+                self.validators
+                    .iter()
+                    .map(|validator| validator.implementation().address()),
+            )
+            .collect();
+        addresses.sort_unstable();
+        addresses
+    }
+}
+
+/// Emulated bus answering frames for every device of a [`Scenario`].
+pub struct MockBus {
+    devices: Arc<Devices>,
+    noise: Arc<Mutex<Noise>>,
+    echo: bool,
+}
+
+impl MockBus {
+    /// Creates the emulated devices described by the scenario.
+    ///
+    /// `echo` mirrors every received frame before replying, like a single wire bus does.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a device of the scenario is invalid.
+    pub fn new(scenario: &Scenario, echo: bool) -> Result<Self, ScenarioError> {
+        let devices = Devices {
+            hoppers: scenario
+                .hoppers
+                .iter()
+                .map(|hopper| SimulatedHopper::new(hopper).map(PayoutDevice::new))
+                .collect::<Result<_, _>>()?,
+            selectors: scenario
+                .selectors
+                .iter()
+                .map(|selector| MockSelector::new(selector).map(CoinAcceptorDevice::new))
+                .collect::<Result<_, _>>()?,
+            validators: scenario
+                .validators
+                .iter()
+                .map(|validator| MockValidator::new(validator).map(BillValidatorDevice::new))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Self {
+            devices: Arc::new(devices),
+            noise: Arc::new(Mutex::new(Noise::new(scenario.noise.clone()))),
+            echo,
+        })
+    }
+
+    /// Handle driving the devices of the bus, it stays valid while the bus is served.
+    #[must_use]
+    pub fn control(&self) -> MockControl {
+        MockControl {
+            devices: Arc::clone(&self.devices),
+            noise: Arc::clone(&self.noise),
+        }
+    }
+
+    /// Accepts connections on the listener and serves them one at a time until the task
+    /// is aborted.
+    pub async fn serve(self, listener: UnixListener) {
+        info!(
+            hoppers = self.devices.hoppers.len(),
+            selectors = self.devices.selectors.len(),
+            validators = self.devices.validators.len(),
+            "mock bus ready"
+        );
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    if let Err(error) = self.handle(stream).await {
+                        debug!("mock bus connection closed: {}", error);
+                    }
+                }
+                Err(error) => {
+                    warn!("mock bus unable to accept connection: {}", error);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn handle(&self, mut stream: UnixStream) -> io::Result<()> {
+        let mut frame = [0u8; MAX_BLOCK_LENGTH];
+        let mut reply = [0u8; MAX_BLOCK_LENGTH];
+        let mut line = Vec::with_capacity(3 * MAX_BLOCK_LENGTH);
+        loop {
+            stream.read_exact(&mut frame[..2]).await?;
+            let frame_length = usize::from(frame[1]) + 5;
+            stream.read_exact(&mut frame[2..frame_length]).await?;
+
+            line.clear();
+            let reply_length = self.reply(&frame[..frame_length], &mut reply).await;
+            {
+                let mut noise = self.noise.lock().expect("should not be poisoned");
+                if self.echo {
+                    noise.echo(&frame[..frame_length], &mut line);
+                }
+                match reply_length {
+                    Some(reply_length) if frame[3] != Header::AddressPoll as u8 => {
+                        noise.reply(&reply[..reply_length], &mut line);
+                    }
+                    Some(reply_length) => line.extend_from_slice(&reply[..reply_length]),
+                    None => {}
+                }
+            }
+            stream.write_all(&line).await?;
+            stream.flush().await?;
+        }
+    }
+
+    /// Answers the messages received on the channel until it is closed, in place of a
+    /// transport and its socket.
+    ///
+    /// Replies are checked like the transport does, a frame nobody answers fails with
+    /// [`TransportError::Timeout`] and a NAK with [`TransportError::Nack`]. Noisy replies fail
+    /// with [`TransportError::Timeout`] when truncated and [`TransportError::ChecksumError`]
+    /// otherwise, duplicated echoes have no effect without a line.
+    pub async fn serve_channel(self, mut receiver: mpsc::Receiver<TransportMessage>) {
+        info!(
+            hoppers = self.devices.hoppers.len(),
+            selectors = self.devices.selectors.len(),
+            validators = self.devices.validators.len(),
+            "in-memory mock bus ready"
+        );
+        let mut frame = [0u8; MAX_BLOCK_LENGTH];
+        let mut reply = [0u8; MAX_BLOCK_LENGTH];
+        while let Some(message) = receiver.recv().await {
+            let result = match encode(&message, &mut frame) {
+                Ok(length) => match self.reply(&frame[..length], &mut reply).await {
+                    Some(reply_length) if message.header != Header::AddressPoll => {
+                        self.noisy_reply(&reply[..reply_length], message.checksum_type)
+                    }
+                    Some(reply_length) => Ok(reply[..reply_length].to_vec()),
+                    None => Err(TransportError::Timeout),
+                },
+                Err(error) => Err(error),
+            };
+            let _ = message.respond_to.send(result);
+        }
+    }
+
+    /// Passes a reply through the noise of the line and checks it like the transport does.
+    fn noisy_reply(
+        &self,
+        reply: &[u8],
+        checksum_type: ChecksumType,
+    ) -> Result<Vec<u8>, TransportError> {
+        let mut line = Vec::with_capacity(MAX_BLOCK_LENGTH);
+        self.noise
+            .lock()
+            .expect("should not be poisoned")
+            .reply(reply, &mut line);
+        let frame_length = usize::from(line[1]) + 5;
+        let Some(frame) = line.get(..frame_length) else {
+            return Err(TransportError::Timeout);
+        };
+        match Packet::parse(frame, checksum_type) {
+            Ok(packet) if packet.header() == Header::NACK => Err(TransportError::Nack),
+            Ok(_) => Ok(frame.to_vec()),
+            Err(_) => Err(TransportError::ChecksumError),
+        }
+    }
+
+    /// Offers the frame to every device, returns the size of the first reply.
+    ///
+    /// Devices answer an `AddressPoll` with their address byte, without the 4ms per address
+    /// delay of real devices.
+    async fn reply(&self, frame: &[u8], reply: &mut [u8]) -> Option<usize> {
+        if frame[3] == Header::AddressPoll as u8 {
+            let addresses = self.devices.addresses();
+            reply[..addresses.len()].copy_from_slice(&addresses);
+            return Some(addresses.len());
+        }
+        let mut buffer = [0u8; MAX_BLOCK_LENGTH];
+        for hopper in &self.devices.hoppers {
+            buffer[..frame.len()].copy_from_slice(frame);
+            if let Ok(size) = hopper.on_frame(&mut buffer[..frame.len()], reply).await {
+                return Some(size);
+            }
+        }
+        for selector in &self.devices.selectors {
+            buffer[..frame.len()].copy_from_slice(frame);
+            if let Ok(size) = selector.on_frame(&mut buffer[..frame.len()], reply).await {
+                return Some(size);
+            }
+        }
+        for validator in &self.devices.validators {
+            buffer[..frame.len()].copy_from_slice(frame);
+            if let Ok(size) = validator.on_frame(&mut buffer[..frame.len()], reply).await {
+                return Some(size);
+            }
+        }
+        None
+    }
+}
+
+/// Builds the frame the transport would send for the message, returns its length.
+fn encode(message: &TransportMessage, frame: &mut [u8]) -> Result<usize, TransportError> {
+    let mut packet = Packet::new(frame);
+    packet
+        .set_destination(message.address)
+        .and_then(|()| packet.set_source(BusAddress::HOST.get()))
+        .and_then(|()| packet.set_header(message.header))
+        .and_then(|()| packet.set_data(&message.data))
+        .map_err(|_| TransportError::BufferOverflow)?;
+    let device = Device::new(message.address, Category::Unknown, message.checksum_type);
+    serialize(&device, &mut packet).map_err(|_| TransportError::PacketCreationError)?;
+    Ok(packet.get_logical_size())
+}
+
+/// Injects physical events into the devices of a running [`MockBus`].
+///
+/// Inserted coins and bills are queued after the credits of the scenario and follow the same
+/// rules, one is inserted per poll while the device accepts.
+#[derive(Clone)]
+pub struct MockControl {
+    devices: Arc<Devices>,
+    noise: Arc<Mutex<Noise>>,
+}
+
+impl MockControl {
+    /// Inserts the coin at `position` into the coin acceptor at `address`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no coin acceptor at `address`.
+    pub fn insert_coin(&self, address: u8, position: u8) -> Result<(), ControlError> {
+        self.selector(address, "accept coins")?
+            .insert_coin(position);
+        debug!(address, position, "coin inserted");
+        Ok(())
+    }
+
+    /// Inserts a bill of `bill_type` into the bill validator at `address`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no bill validator at `address`.
+    pub fn insert_bill(&self, address: u8, bill_type: u8) -> Result<(), ControlError> {
+        self.validator(address, "accept bills")?
+            .insert_bill(bill_type);
+        debug!(address, bill_type, "bill inserted");
+        Ok(())
+    }
+
+    /// Returns the positions of the coins the coin acceptor at `address` accepted since the bus
+    /// was created, in the order they were accepted.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no coin acceptor at `address`.
+    pub fn accepted_coins(&self, address: u8) -> Result<Vec<u8>, ControlError> {
+        Ok(self.selector(address, "accept coins")?.accepted())
+    }
+
+    /// Returns the hopper at `address`, e.g. to check its inventory.
+    #[must_use]
+    pub fn hopper(&self, address: u8) -> Option<&SimulatedHopper> {
+        self.find_hopper(address)
+    }
+
+    /// Adds coins to the hopper at `address`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no hopper at `address`.
+    pub fn refill(&self, address: u8, coins: u32) -> Result<(), ControlError> {
+        self.payout(address, "be refilled")?.refill(coins);
+        debug!(address, coins, "hopper refilled");
+        Ok(())
+    }
+
+    /// Raises an exit opto fault on the hopper at `address`, it pays nothing until it is reset.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no hopper at `address`.
+    pub fn opto_fault(&self, address: u8, fault: OptoFault) -> Result<(), ControlError> {
+        self.payout(address, "have an opto fault")?
+            .inject_opto_fault(fault);
+        debug!(address, ?fault, "hopper opto fault raised");
+        Ok(())
+    }
+
+    /// Jams the device at `address`.
+    ///
+    /// A hopper stops paying and fails its self test until it is reset, acceptors report a
+    /// jam event on their next poll.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no device at `address`.
+    pub fn jam(&self, address: u8) -> Result<(), ControlError> {
+        if let Some(hopper) = self.find_hopper(address) {
+            hopper.jam();
+        } else if let Some(selector) = self.find_selector(address) {
+            selector.jam();
+        } else if let Some(validator) = self.find_validator(address) {
+            validator.jam();
+        } else {
+            return Err(ControlError::UnknownAddress(address));
+        }
+        debug!(address, "device jammed");
+        Ok(())
+    }
+
+    /// Switches the device at `address` off and on, it comes back in its power up state like
+    /// after a `ResetDevice`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is no device at `address`.
+    pub fn power_cycle(&self, address: u8) -> Result<(), ControlError> {
+        if let Some(hopper) = self.find_hopper(address) {
+            hopper.reset_state();
+        } else if let Some(selector) = self.find_selector(address) {
+            selector.reset_state();
+        } else if let Some(validator) = self.find_validator(address) {
+            validator.reset_state();
+        } else {
+            return Err(ControlError::UnknownAddress(address));
+        }
+        debug!(address, "device power cycled");
+        Ok(())
+    }
+
+    /// Replaces the noise of the line, the faults injected so far are kept.
+    pub fn set_noise(&self, profile: LineNoise) {
+        let mut noise = self.noise();
+        *noise = Noise::new(profile).with_injected(noise.injected());
+        drop(noise);
+        debug!("line noise changed");
+    }
+
+    /// Returns the faults injected on the line since the bus was created, e.g. to compare
+    /// them with the transport statistics.
+    #[must_use]
+    pub fn injected_faults(&self) -> InjectedFaults {
+        self.noise().injected()
+    }
+
+    /// Runs one line of the control protocol.
+    ///
+    /// Lines are `insert_coin <address> <position>`, `insert_bill <address> <bill type>`,
+    /// `refill <address> <coins>`, `opto_fault <address> <fault>`, `jam <address>` and
+    /// `power_cycle <address>`, faults are named like in the scenario, e.g.
+    /// `blocked_during_payout`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the line is not a valid command or the command fails.
+    pub fn execute(&self, line: &str) -> Result<(), ControlError> {
+        let invalid = || ControlError::InvalidCommand(line.trim().to_string());
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let ["opto_fault", address, fault] = words.as_slice() {
+            let address = address.parse().map_err(|_| invalid())?;
+            let fault = fault.parse().map_err(|()| invalid())?;
+            return self.opto_fault(address, fault);
+        }
+        let numbers = words
+            .iter()
+            .skip(1)
+            .map(|word| word.parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match (words.first().copied(), numbers.as_slice()) {
+            (Some("insert_coin"), &[address, position]) => self.insert_coin(address, position),
+            (Some("insert_bill"), &[address, bill_type]) => self.insert_bill(address, bill_type),
+            (Some("refill"), &[address, coins]) => self.refill(address, u32::from(coins)),
+            (Some("jam"), &[address]) => self.jam(address),
+            (Some("power_cycle"), &[address]) => self.power_cycle(address),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Accepts control connections on the listener until the task is aborted.
+    ///
+    /// Every line received is run with [`MockControl::execute`] and answered with `ok` or
+    /// `error: <reason>`.
+    pub async fn serve(self, listener: UnixListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    if let Err(error) = self.handle(stream).await {
+                        debug!("mock control connection closed: {}", error);
+                    }
+                }
+                Err(error) => {
+                    warn!("mock control unable to accept connection: {}", error);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn handle(&self, stream: UnixStream) -> io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let answer = match self.execute(&line) {
+                Ok(()) => "ok\n".to_string(),
+                Err(error) => format!("error: {error}\n"),
+            };
+            writer.write_all(answer.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    fn noise(&self) -> std::sync::MutexGuard<'_, Noise> {
+        self.noise.lock().expect("should not be poisoned")
+    }
+
+    fn find_hopper(&self, address: u8) -> Option<&SimulatedHopper> {
+        self.devices
+            .hoppers
+            .iter()
+            .map(PayoutDevice::implementation)
+            .find(|hopper| hopper.address() == address)
+    }
+
+    fn find_selector(&self, address: u8) -> Option<&MockSelector> {
+        self.devices
+            .selectors
+            .iter()
+            .map(CoinAcceptorDevice::implementation)
+            .find(|selector| selector.address() == address)
+    }
+
+    fn find_validator(&self, address: u8) -> Option<&MockValidator> {
+        self.devices
+            .validators
+            .iter()
+            .map(BillValidatorDevice::implementation)
+            .find(|validator| validator.address() == address)
+    }
+
+    fn payout(&self, address: u8, action: &'static str) -> Result<&SimulatedHopper, ControlError> {
+        self.find_hopper(address)
+            .ok_or_else(|| self.unsupported(address, action))
+    }
+
+    fn selector(&self, address: u8, action: &'static str) -> Result<&MockSelector, ControlError> {
+        self.find_selector(address)
+            .ok_or_else(|| self.unsupported(address, action))
+    }
+
+    fn validator(&self, address: u8, action: &'static str) -> Result<&MockValidator, ControlError> {
+        self.find_validator(address)
+            .ok_or_else(|| self.unsupported(address, action))
+    }
+
+    /// Error for an action the device at `address` does not support, if there is one.
+    fn unsupported(&self, address: u8, action: &'static str) -> ControlError {
+        if self.find_hopper(address).is_some()
+            || self.find_selector(address).is_some()
+            || self.find_validator(address).is_some()
+        {
+            ControlError::Unsupported { address, action }
+        } else {
+            ControlError::UnknownAddress(address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCENARIO: &str = r#"
+        [[hopper]]
+        address = 3
+        coin = "EU200A"
+        coins = 5
+
+        [[selector]]
+        address = 2
+        product_code = "SR5"
+        coins = ["EU010A", "EU020A"]
+        credits = [2, 1]
+
+        [[validator]]
+        address = 40
+        bills = ["EU0005A", "EU0010A"]
+        credits = [2, 1, 2]
+    "#;
+
+    #[test]
+    fn scenario_is_parsed_with_defaults() {
+        let scenario: Scenario = toml::from_str(SCENARIO).expect("should parse");
+        scenario.validate().expect("should be valid");
+
+        assert_eq!(scenario.hoppers[0].coins, 5);
+        assert_eq!(scenario.hoppers[0].identity.product_code, "MOCK");
+        assert_eq!(scenario.selectors[0].identity.product_code, "SR5");
+        assert_eq!(scenario.selectors[0].credits, vec![2, 1]);
+    }
+
+    #[test]
+    fn duplicate_addresses_are_rejected() {
+        let scenario: Scenario = toml::from_str(
+            r"
+            [[hopper]]
+            address = 3
+            [[selector]]
+            address = 3
+            ",
+        )
+        .expect("should parse");
+        assert!(matches!(
+            scenario.validate(),
+            Err(ScenarioError::DuplicateAddress(3))
+        ));
+    }
+
+    #[tokio::test]
+    async fn selector_credits_only_while_accepting() {
+        let scenario: Scenario = toml::from_str(SCENARIO).expect("should parse");
+        let selector = MockSelector::new(&scenario.selectors[0]).expect("should be valid");
+
+        assert_eq!(selector.coin_id(1), "EU010A");
+        assert_eq!(selector.coin_id(3), UNUSED_COIN);
+        assert_eq!(selector.read_buffered_credits().await.0, 0);
+
+        selector.set_master_inhibit(false).await;
+        selector.set_inhibits([0xFF, 0xFF]).await;
+        let (counter, results) = selector.read_buffered_credits().await;
+        assert_eq!(counter, 1);
+        assert_eq!(results[..2], [2, 1]);
+
+        let (counter, results) = selector.read_buffered_credits().await;
+        assert_eq!(counter, 2);
+        assert_eq!(results[..4], [1, 1, 2, 1]);
+
+        selector.reset_state();
+        assert_eq!(selector.accepted(), [2, 1]);
+    }
+
+    #[tokio::test]
+    async fn hopper_pays_what_it_holds() {
+        let scenario: Scenario = toml::from_str(SCENARIO).expect("should parse");
+        let hopper = SimulatedHopper::new(&scenario.hoppers[0]).expect("should be valid");
+
+        hopper.dispense_hopper_coins(2).await;
+        assert_eq!(hopper.request_payout_status().await.event_counter, 0);
+
+        hopper.enable_payout(true).await;
+        hopper.dispense_hopper_coins(8).await;
+        let status = hopper.request_payout_status().await;
+        assert_eq!((status.paid, status.unpaid), (5, 3));
+        assert_eq!(hopper.request_hopper_dispense_count().await, 5);
+        assert!(!hopper.request_sensor_status().await.higher_than_low_level);
+    }
+
+    #[tokio::test]
+    async fn jammed_hopper_stops_paying() {
+        let mut scenario: Scenario = toml::from_str(SCENARIO).expect("should parse");
+        scenario.hoppers[0].jam_after = Some(2);
+        let hopper = SimulatedHopper::new(&scenario.hoppers[0]).expect("should be valid");
+
+        hopper.enable_payout(true).await;
+        hopper.dispense_hopper_coins(3).await;
+        let status = hopper.request_payout_status().await;
+        assert_eq!((status.paid, status.unpaid), (2, 1));
+        assert_eq!(hopper.test().await.0 & 1, 1);
+
+        hopper.reset().await;
+        assert_eq!(hopper.test().await.0 & 1, 0);
+    }
+
+    #[tokio::test]
+    async fn timed_hopper_pays_one_coin_per_interval() {
+        let mut scenario: Scenario = toml::from_str(SCENARIO).expect("should parse");
+        scenario.hoppers[0].coin_interval_ms = 50;
+        let hopper = SimulatedHopper::new(&scenario.hoppers[0]).expect("should be valid");
+
+        hopper.enable_payout(true).await;
+        hopper.dispense_hopper_coins(3).await;
+        let status = hopper.request_payout_status().await;
+        assert_eq!((status.event_counter, status.coins_remaining), (1, 3));
+        assert!(hopper.is_dispensing());
+
+        tokio::time::sleep(Duration::from_millis(175)).await;
+        let status = hopper.request_payout_status().await;
+        assert_eq!((status.coins_remaining, status.paid), (0, 3));
+        assert_eq!(hopper.coins(), 2);
+    }
+
+    #[tokio::test]
+    async fn validator_holds_bills_in_escrow_until_routed() {
+        let scenario: Scenario = toml::from_str(SCENARIO).expect("should parse");
+        let validator = MockValidator::new(&scenario.validators[0]).expect("should be valid");
+
+        assert_eq!(validator.bill_id(2), "EU0010A");
+        assert_eq!(validator.bill_id(3), UNUSED_BILL);
+
+        validator.set_master_inhibit(false).await;
+        validator.set_inhibits([0b11, 0]).await;
+        validator.set_operating_mode(0b11).await;
+        let (counter, events) = validator.read_buffered_bill_events().await;
+        assert_eq!((counter, events[..2].to_vec()), (1, vec![2, 1]));
+        // Nothing is inserted while a bill is held
+        assert_eq!(validator.read_buffered_bill_events().await.0, 1);
+
+        assert_eq!(validator.route_bill(1).await, None);
+        let (counter, events) = validator.read_buffered_bill_events().await;
+        assert_eq!((counter, events[..6].to_vec()), (3, vec![1, 1, 2, 0, 2, 1]));
+
+        assert_eq!(validator.route_bill(0).await, None);
+        assert_eq!(validator.route_bill(0).await, Some(254));
+        let (counter, events) = validator.read_buffered_bill_events().await;
+        assert_eq!((counter, events[..4].to_vec()), (5, vec![2, 1, 0, 1]));
+    }
+
+    #[tokio::test]
+    async fn noisy_replies_fail_like_on_the_line() {
+        let mut scenario: Scenario = toml::from_str(SCENARIO).expect("should parse");
+        scenario.noise.corrupt_checksum = 1.0;
+        let bus = MockBus::new(&scenario, false).expect("should be valid");
+        let control = bus.control();
+        let (sender, receiver) = mpsc::channel(1);
+        let served = tokio::spawn(bus.serve_channel(receiver));
+
+        let (respond_to, reply) = tokio::sync::oneshot::channel();
+        let device = Device::new(3, Category::Payout, ChecksumType::Crc8);
+        sender
+            .send(TransportMessage::new(
+                &device,
+                cc_talk_host::core::core_commands::SimplePollCommand,
+                respond_to,
+            ))
+            .await
+            .expect("should be served");
+        assert_eq!(
+            reply.await.expect("should answer"),
+            Err(TransportError::ChecksumError)
+        );
+        assert_eq!(control.injected_faults().corrupted_checksums, 1);
+
+        control.set_noise(LineNoise::default());
+        let (respond_to, reply) = tokio::sync::oneshot::channel();
+        sender
+            .send(TransportMessage::new(
+                &device,
+                cc_talk_host::core::core_commands::SimplePollCommand,
+                respond_to,
+            ))
+            .await
+            .expect("should be served");
+        assert!(reply.await.expect("should answer").is_ok());
+        assert_eq!(control.injected_faults().corrupted_replies(), 1);
+
+        drop(sender);
+        served.await.expect("should stop");
+
+        scenario.noise.truncate = 1.5;
+        assert!(matches!(
+            scenario.validate(),
+            Err(ScenarioError::InvalidProbability("truncate"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn control_commands_reach_the_devices() {
+        let scenario: Scenario = toml::from_str(SCENARIO).expect("should parse");
+        let bus = MockBus::new(&scenario, false).expect("should be valid");
+        let control = bus.control();
+        let selector = bus.devices.selectors[0].implementation();
+        let hopper = bus.devices.hoppers[0].implementation();
+
+        control.execute("insert_coin 2 1").expect("should insert");
+        assert_eq!(
+            selector
+                .state
+                .lock()
+                .expect("should not be poisoned")
+                .credits,
+            [2, 1, 1]
+        );
+        control.execute("jam 3").expect("should jam");
+        assert_eq!(hopper.test().await.0 & 1, 1);
+        control
+            .execute("power_cycle 3")
+            .expect("should power cycle");
+        assert_eq!(hopper.test().await.0 & 1, 0);
+        control.execute("refill 3 10").expect("should refill");
+        assert_eq!(hopper.coins(), 15);
+        control
+            .execute("opto_fault 3 short_circuit_during_payout")
+            .expect("should raise the fault");
+        assert_eq!(hopper.test().await.1 & 1, 1);
+
+        assert!(matches!(
+            control.execute("insert_bill 3 1"),
+            Err(ControlError::Unsupported { address: 3, .. })
+        ));
+        assert!(matches!(
+            control.execute("jam 9"),
+            Err(ControlError::UnknownAddress(9))
+        ));
+        assert!(matches!(
+            control.execute("insert_coin 2"),
+            Err(ControlError::InvalidCommand(_))
+        ));
+        assert!(matches!(
+            control.execute("opto_fault 3 dusty"),
+            Err(ControlError::InvalidCommand(_))
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+use serde::Deserialize;
+
+use cc_talk_core::cc_talk::{DATA_LENGTH_OFFSET, DATA_OFFSET};
+
+/// Electrical faults of the emulated line, each drawn with its own probability.
+///
+/// Faults only apply to framed replies and echoes, the address bytes answering an
+/// `AddressPoll` are left untouched.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LineNoise {
+    /// Probability of a reply with a wrong checksum.
+    #[serde(default)]
+    pub corrupt_checksum: f64,
+    /// Probability of a reply cut short, the device stops sending mid-frame.
+    #[serde(default)]
+    pub truncate: f64,
+    /// Probability of a request echoed twice.
+    #[serde(default)]
+    pub duplicate_echo: f64,
+    /// Probability of a stray byte inserted in a reply.
+    #[serde(default)]
+    pub insert_byte: f64,
+    /// Seed of the random draws, runs with the same seed and traffic inject the same faults.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl LineNoise {
+    /// Returns the name of the first probability outside of `0..=1`.
+    pub(super) fn invalid_probability(&self) -> Option<&'static str> {
+        [
+            ("corrupt_checksum", self.corrupt_checksum),
+            ("truncate", self.truncate),
+            ("duplicate_echo", self.duplicate_echo),
+            ("insert_byte", self.insert_byte),
+        ]
+        .into_iter()
+        .find(|(_, probability)| !(0.0..=1.0).contains(probability))
+        .map(|(name, _)| name)
+    }
+}
+
+/// Faults injected since the bus was created, see [`MockControl::injected_faults`].
+///
+/// [`MockControl::injected_faults`]: super::MockControl::injected_faults
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InjectedFaults {
+    pub corrupted_checksums: u64,
+    pub truncated_replies: u64,
+    pub duplicated_echoes: u64,
+    pub inserted_bytes: u64,
+}
+
+impl InjectedFaults {
+    /// Returns the number of replies the host cannot validate.
+    #[must_use]
+    pub const fn corrupted_replies(&self) -> u64 {
+        self.corrupted_checksums + self.truncated_replies + self.inserted_bytes
+    }
+}
+
+/// Draws the faults of a [`LineNoise`] profile with a xorshift generator.
+#[derive(Debug)]
+pub struct Noise {
+    profile: LineNoise,
+    state: u64,
+    injected: InjectedFaults,
+}
+
+impl Noise {
+    pub(super) fn new(profile: LineNoise) -> Self {
+        // A zero state would only ever produce zeroes.
+        let state = (profile.seed ^ 0x9E37_79B9_7F4A_7C15).max(1);
+        Self {
+            profile,
+            state,
+            injected: InjectedFaults::default(),
+        }
+    }
+
+    /// Keeps counting from `injected`, e.g. when the profile changes.
+    pub(super) const fn with_injected(mut self, injected: InjectedFaults) -> Self {
+        self.injected = injected;
+        self
+    }
+
+    pub(super) const fn injected(&self) -> InjectedFaults {
+        self.injected
+    }
+
+    /// Appends the echo of `frame` to `line`, sometimes twice.
+    pub(super) fn echo(&mut self, frame: &[u8], line: &mut Vec<u8>) {
+        line.extend_from_slice(frame);
+        if self.chance(self.profile.duplicate_echo) {
+            self.injected.duplicated_echoes += 1;
+            line.extend_from_slice(frame);
+        }
+    }
+
+    /// Appends `reply` to `line` with the faults drawn for it.
+    pub(super) fn reply(&mut self, reply: &[u8], line: &mut Vec<u8>) {
+        let start = line.len();
+        line.extend_from_slice(reply);
+        if reply.len() <= DATA_OFFSET {
+            return;
+        }
+        if self.chance(self.profile.corrupt_checksum) {
+            self.injected.corrupted_checksums += 1;
+            if let Some(checksum) = line.last_mut() {
+                *checksum ^= 1;
+            }
+        }
+        if self.chance(self.profile.insert_byte) {
+            self.injected.inserted_bytes += 1;
+            let position = start + 1 + self.below(reply.len() - 1);
+            let byte = self.byte();
+            line.insert(position, byte);
+        }
+        if self.chance(self.profile.truncate) {
+            self.injected.truncated_replies += 1;
+            // At least the data length byte is sent, the host waits for the rest.
+            let kept = DATA_LENGTH_OFFSET + 1 + self.below(line.len() - start - 2);
+            line.truncate(start + kept);
+        }
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        let draw = u32::try_from(self.next() >> 32).unwrap_or(u32::MAX);
+        f64::from(draw) < probability * f64::from(u32::MAX)
+    }
+
+    const fn byte(&mut self) -> u8 {
+        self.next().to_be_bytes()[0]
+    }
+
+    /// Returns a number in `0..bound`, `bound` is at most a frame length.
+    fn below(&mut self, bound: usize) -> usize {
+        let draw = usize::from(u16::from_be_bytes([self.byte(), self.byte()]));
+        draw % bound.max(1)
+    }
+
+    const fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const REPLY: [u8; 7] = [1, 2, 3, 0, 0xAB, 0xCD, 0x80];
+
+    #[test]
+    fn faults_follow_the_profile() {
+        let mut noise = Noise::new(LineNoise::default());
+        let mut line = vec![];
+        noise.echo(&REPLY, &mut line);
+        noise.reply(&REPLY, &mut line);
+        assert_eq!(line.len(), 2 * REPLY.len());
+        assert_eq!(noise.injected(), InjectedFaults::default());
+
+        let mut noise = Noise::new(LineNoise {
+            corrupt_checksum: 1.0,
+            duplicate_echo: 1.0,
+            ..LineNoise::default()
+        });
+        let mut line = vec![];
+        noise.echo(&REPLY, &mut line);
+        noise.reply(&REPLY, &mut line);
+        assert_eq!(line[..14], [REPLY, REPLY].concat());
+        assert_eq!(line[20], 0x81);
+
+        let mut noise = Noise::new(LineNoise {
+            truncate: 1.0,
+            insert_byte: 1.0,
+            ..LineNoise::default()
+        });
+        for _ in 0..50 {
+            let mut line = vec![];
+            noise.reply(&REPLY, &mut line);
+            assert!((2..=REPLY.len()).contains(&line.len()));
+        }
+        assert_eq!(noise.injected().corrupted_replies(), 100);
+    }
+
+    #[test]
+    fn draws_are_seeded() {
+        let profile = LineNoise {
+            corrupt_checksum: 0.5,
+            seed: 7,
+            ..LineNoise::default()
+        };
+        let draw = |profile: &LineNoise| {
+            let mut noise = Noise::new(profile.clone());
+            (0..100)
+                .map(|_| noise.chance(profile.corrupt_checksum))
+                .collect::<Vec<_>>()
+        };
+        let draws = draw(&profile);
+        assert_eq!(draws, draw(&profile));
+        let hits = draws.iter().filter(|hit| **hit).count();
+        assert!((30..70).contains(&hits), "{hits} hits");
+    }
+}
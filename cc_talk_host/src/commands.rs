@@ -1,4 +1,6 @@
+pub mod ascii;
 pub mod command;
+pub mod command_table;
 pub mod core;
 pub mod core_plus;
 pub mod device;
@@ -0,0 +1,85 @@
+use cc_talk_core::cc_talk::Header;
+
+use super::command::{Command, ParseResponseError};
+
+/// Longest ASCII reply kept by an [`AsciiCommand`].
+pub const MAX_ASCII_LENGTH: usize = 64;
+
+/// ASCII reply of an [`AsciiCommand`].
+pub type AsciiString = heapless::String<MAX_ASCII_LENGTH>;
+
+/// Command without data whose reply is an ASCII string, e.g. the product code.
+///
+/// `HEADER` is the value of the command header, a value which is not a known header fails to
+/// compile.
+///
+/// # Examples
+///
+/// ```
+/// use cc_talk_core::cc_talk::Header;
+/// use cc_talk_host::{ascii::AsciiCommand, command::Command};
+///
+/// let command = AsciiCommand::<{ Header::RequestProductCode as u8 }>;
+/// assert_eq!(command.header(), Header::RequestProductCode);
+/// assert_eq!(command.parse_response(b"SCH2").unwrap(), "SCH2");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AsciiCommand<const HEADER: u8>;
+
+impl<const HEADER: u8> AsciiCommand<HEADER> {
+    const HEADER: Header = match Header::from_u8(HEADER) {
+        Some(header) => header,
+        None => panic!("AsciiCommand header is not a ccTalk header"),
+    };
+}
+
+impl<const HEADER: u8> Command for AsciiCommand<HEADER> {
+    type Response = AsciiString;
+
+    fn header(&self) -> Header {
+        Self::HEADER
+    }
+
+    fn data(&self) -> &[u8] {
+        &[]
+    }
+
+    /// Checks that the response is ASCII, the characters are kept as sent, padding included.
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        if !response_payload.is_ascii() {
+            return Err(ParseResponseError::ParseError("Invalid ASCII response"));
+        }
+        let mut response = AsciiString::new();
+        for byte in response_payload {
+            response.push(char::from(*byte)).map_err(|_| {
+                ParseResponseError::DataLengthMismatch(MAX_ASCII_LENGTH, response_payload.len())
+            })?;
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ascii_replies_are_checked() {
+        let command = AsciiCommand::<{ Header::RequestBuildCode as u8 }>;
+        assert_eq!(command.header(), Header::RequestBuildCode);
+        assert!(command.data().is_empty());
+        assert_eq!(command.parse_response(b"ABC ").unwrap(), "ABC ");
+        assert_eq!(command.parse_response(&[]).unwrap(), "");
+        assert!(matches!(
+            command.parse_response(&[0xFF, 0xFE]),
+            Err(ParseResponseError::ParseError(_))
+        ));
+        assert_eq!(
+            command.parse_response(&[b'A'; 65]),
+            Err(ParseResponseError::DataLengthMismatch(64, 65))
+        );
+    }
+}
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use core::time::Duration;
+
 use cc_talk_core::cc_talk::Header;
 
 /// Base command trait that all commands must implement.
@@ -15,10 +17,70 @@ pub trait Command {
     /// Parses the payload of the response.
     fn parse_response(&self, response_payload: &[u8])
     -> Result<Self::Response, ParseResponseError>;
+
+    /// What the host does when the device replies with [`ResponseStatus::Busy`].
+    fn busy_policy(&self) -> BusyPolicy {
+        BusyPolicy::DEFAULT
+    }
+}
+
+/// Status of a reply, given by its header.
+///
+/// Only acknowledged replies carry the response of the command, NAK and Busy replies have no
+/// payload to parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResponseStatus {
+    /// The device executed the command.
+    Ack,
+    /// The device refused the command, header 5.
+    Nak,
+    /// The device can't execute the command now, header 6.
+    Busy,
+}
+
+impl From<Header> for ResponseStatus {
+    fn from(header: Header) -> Self {
+        match header {
+            Header::NACK => Self::Nak,
+            Header::Busy => Self::Busy,
+            _ => Self::Ack,
+        }
+    }
+}
+
+/// How a command is retried when the device replies [`ResponseStatus::Busy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusyPolicy {
+    /// The busy reply is reported right away.
+    Fail,
+    /// The command is sent again up to `attempts` times, waiting `delay` before each attempt.
+    Retry { attempts: u8, delay: Duration },
+}
+
+impl BusyPolicy {
+    /// Policy of the commands which do not override [`Command::busy_policy`].
+    pub const DEFAULT: Self = Self::Retry {
+        attempts: 3,
+        delay: Duration::from_millis(50),
+    };
+
+    /// Returns the delay before the given retry, `None` once the retries are exhausted.
+    ///
+    /// `retry` counts from 1.
+    #[must_use]
+    pub const fn delay(&self, retry: u8) -> Option<Duration> {
+        match self {
+            Self::Retry { attempts, delay } if retry <= *attempts => Some(*delay),
+            _ => None,
+        }
+    }
 }
 
 /// Errors that can occur during command execution
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ParseResponseError {
     /// The response data length does not match the expected length.
     /// .0 is expected length, .1 is actual length.
@@ -30,3 +92,27 @@ pub enum ParseResponseError {
     #[error("buffer too small to hold response data")]
     BufferTooSmall,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reply_headers_give_the_response_status() {
+        assert_eq!(ResponseStatus::from(Header::Reply), ResponseStatus::Ack);
+        assert_eq!(ResponseStatus::from(Header::NACK), ResponseStatus::Nak);
+        assert_eq!(ResponseStatus::from(Header::Busy), ResponseStatus::Busy);
+    }
+
+    #[test]
+    fn busy_policy_bounds_the_retries() {
+        let policy = BusyPolicy::Retry {
+            attempts: 2,
+            delay: Duration::from_millis(10),
+        };
+        assert_eq!(policy.delay(1), Some(Duration::from_millis(10)));
+        assert_eq!(policy.delay(2), Some(Duration::from_millis(10)));
+        assert_eq!(policy.delay(3), None);
+        assert_eq!(BusyPolicy::Fail.delay(1), None);
+    }
+}
@@ -0,0 +1,330 @@
+//! Compile-time table of the commands supported by this crate.
+//!
+//! Small hosts which cannot afford every command type can validate replies generically, a
+//! [`CommandSpec`] only knows the length of the reply payload. Parsing the payload is left to
+//! the caller, the typed [`Command`](super::command::Command)s remain the way to get checked
+//! values.
+
+use cc_talk_core::cc_talk::Header;
+
+use super::command::ParseResponseError;
+
+/// Longest payload a reply can hold, the length byte of a frame is a `u8`.
+const VARIABLE: u8 = u8::MAX;
+
+/// Expected reply of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub header: Header,
+    pub min_response_length: u8,
+    pub max_response_length: u8,
+    /// The device only acknowledges the command, the reply has no payload.
+    pub ack_only: bool,
+}
+
+impl CommandSpec {
+    const fn ack(header: Header) -> Self {
+        Self::ranged(header, 0, 0)
+    }
+
+    const fn fixed(header: Header, length: u8) -> Self {
+        Self::ranged(header, length, length)
+    }
+
+    const fn ranged(header: Header, min_response_length: u8, max_response_length: u8) -> Self {
+        Self {
+            header,
+            min_response_length,
+            max_response_length,
+            ack_only: max_response_length == 0,
+        }
+    }
+
+    /// Checks the length of a reply payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseResponseError::DataLengthMismatch`] with the closest accepted length if
+    /// the payload is too short or too long.
+    pub const fn validate(&self, response_payload: &[u8]) -> Result<(), ParseResponseError> {
+        let length = response_payload.len();
+        if length < self.min_response_length as usize {
+            Err(ParseResponseError::DataLengthMismatch(
+                self.min_response_length as usize,
+                length,
+            ))
+        } else if length > self.max_response_length as usize {
+            Err(ParseResponseError::DataLengthMismatch(
+                self.max_response_length as usize,
+                length,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Finds the reply expected for `header`, `None` if this crate does not support it.
+pub const fn lookup(header: Header) -> Option<&'static CommandSpec> {
+    let mut i = 0;
+    while i < COMMAND_TABLE.len() {
+        if COMMAND_TABLE[i].header as u8 == header as u8 {
+            return Some(&COMMAND_TABLE[i]);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Longest reply payload accepted for `header`, 255 if this crate does not support it.
+///
+/// Transports use it to reject oversized replies before reading their payload.
+pub const fn max_response_length(header: Header) -> u8 {
+    match lookup(header) {
+        Some(spec) => spec.max_response_length,
+        None => VARIABLE,
+    }
+}
+
+/// Replies of the supported commands, ordered by header.
+///
+/// The lengths match the parsers of the command types, variable length replies accept up to
+/// 255 bytes. Headers shared by several layouts, like `RequestVariableSet`, use the widest one.
+pub const COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec::ack(Header::ResetDevice),
+    CommandSpec::fixed(Header::RequestCommsStatusVariables, 3),
+    CommandSpec::ack(Header::ClearCommsStatusVariable),
+    CommandSpec::fixed(Header::RequestCommsRevision, 3),
+    CommandSpec::ranged(Header::RequestServiceStatus, 0, 1),
+    CommandSpec::ranged(Header::DataStream, 0, VARIABLE),
+    CommandSpec::fixed(Header::RequestEscrowStatus, 3),
+    CommandSpec::ack(Header::OperateEscrow),
+    CommandSpec::fixed(Header::RequestEncryptedMonetaryId, 16),
+    CommandSpec::fixed(Header::RequestEncryptedHopperStatus, 16),
+    CommandSpec::fixed(Header::ReadEncryptedEvents, 16),
+    CommandSpec::ranged(Header::SwitchBaudRate, 0, 1),
+    CommandSpec::fixed(Header::RequestUsbId, 4),
+    CommandSpec::fixed(Header::RequestRealTimeClock, 4),
+    CommandSpec::ack(Header::ModifyRealTimeClock),
+    CommandSpec::fixed(Header::RequestCashBoxValue, 4),
+    CommandSpec::ack(Header::ModifyCashBoxValue),
+    CommandSpec::fixed(Header::RequestHopperBalance, 8),
+    CommandSpec::ack(Header::ModifyHopperBalance),
+    CommandSpec::ack(Header::PurgeHopper),
+    CommandSpec::fixed(Header::RequestErrorStatus, 2),
+    CommandSpec::fixed(Header::RequestActivityRegister, 2),
+    CommandSpec::fixed(Header::VerifyMoneyOut, 9),
+    CommandSpec::ack(Header::PayMoneyOut),
+    CommandSpec::ack(Header::ClearMoneyCounters),
+    CommandSpec::fixed(Header::RequestMoneyOut, 4),
+    CommandSpec::fixed(Header::RequestMoneyIn, 4),
+    CommandSpec::ranged(Header::ReadBarCodeData, 0, VARIABLE),
+    CommandSpec::fixed(Header::RequestIndexedHopperDispenseCount, 3),
+    CommandSpec::fixed(Header::RequestHopperCoinValue, 8),
+    CommandSpec::fixed(Header::EmergencyStopValue, 2),
+    CommandSpec::fixed(Header::RequestHopperPollingValue, 7),
+    CommandSpec::ranged(Header::DispenseHopperValue, 0, 1),
+    CommandSpec::ack(Header::SetAcceptLimit),
+    CommandSpec::ack(Header::StoreEncryptionMode),
+    CommandSpec::ack(Header::SwitchEncryptionMode),
+    CommandSpec::ack(Header::FinishFirmwareUpgrade),
+    CommandSpec::ack(Header::BeginFirmwareUpgrade),
+    CommandSpec::fixed(Header::RequestFirmwareUpgradeCapability, 1),
+    CommandSpec::ack(Header::FinishBillTableUpgrade),
+    CommandSpec::ack(Header::BeginBillTableUpgrade),
+    CommandSpec::ack(Header::UploadBillTables),
+    CommandSpec::ranged(Header::RequestCurrencyRevision, 0, VARIABLE),
+    CommandSpec::ack(Header::OperateBiDirectionalMotors),
+    CommandSpec::ranged(Header::PerformStackerCycle, 0, 1),
+    CommandSpec::ranged(Header::ReadOptoVoltages, 1, VARIABLE),
+    CommandSpec::fixed(Header::RequestIndividualAcceptCounter, 3),
+    CommandSpec::ack(Header::TestLamps),
+    CommandSpec::fixed(Header::RequestBillOperatingMode, 1),
+    CommandSpec::ack(Header::ModifyBillOperatingMode),
+    CommandSpec::ranged(Header::RouteBill, 0, 1),
+    CommandSpec::ranged(Header::RequestBillPosition, 1, VARIABLE),
+    CommandSpec::fixed(Header::RequestCountryScalingFactor, 3),
+    CommandSpec::fixed(Header::RequestBillId, 7),
+    CommandSpec::ack(Header::ModifyBillId),
+    CommandSpec::ranged(Header::ReadBufferedBillEvents, 1, 11),
+    CommandSpec::fixed(Header::RequestCipherKey, 8),
+    CommandSpec::ack(Header::PumpRNG),
+    CommandSpec::ack(Header::ModifyInhibitAndOverrideRegisters),
+    CommandSpec::ranged(Header::TestHopper, 0, 3),
+    CommandSpec::ack(Header::EnableHopper),
+    CommandSpec::ack(Header::ModifyVariableSet),
+    CommandSpec::fixed(Header::RequestHopperStatus, 4),
+    CommandSpec::ranged(Header::DispenseHopperCoins, 0, 1),
+    CommandSpec::fixed(Header::RequestHopperDispenseCount, 3),
+    CommandSpec::fixed(Header::RequestAddressMode, 1),
+    CommandSpec::fixed(Header::RequestBaseYear, 4),
+    CommandSpec::fixed(Header::RequestHopperCoin, 6),
+    CommandSpec::fixed(Header::EmergencyStop, 1),
+    CommandSpec::fixed(Header::RequestThermistorReading, 1),
+    CommandSpec::fixed(Header::RequestPayoutFloat, 2),
+    CommandSpec::ack(Header::ModifyPayoutFloat),
+    CommandSpec::fixed(Header::RequestAlarmCounter, 1),
+    CommandSpec::fixed(Header::RequestBankSelect, 1),
+    CommandSpec::ack(Header::ModifyBankSelect),
+    CommandSpec::fixed(Header::RequestSecuritySetting, 1),
+    CommandSpec::ack(Header::ModifySecuritySetting),
+    CommandSpec::ranged(Header::DownloadCalibrationInfo, 0, VARIABLE),
+    CommandSpec::ack(Header::UploadWindowData),
+    CommandSpec::fixed(Header::RequestCoinId, 6),
+    CommandSpec::ack(Header::ModifyCoinId),
+    CommandSpec::fixed(Header::RequestPayoutCapacity, 2),
+    CommandSpec::ack(Header::ModifyPayoutCapacity),
+    CommandSpec::fixed(Header::RequestDefaultSorterPath, 1),
+    CommandSpec::ack(Header::ModifyDefaultSorterPath),
+    CommandSpec::ranged(Header::RequestBuildCode, 0, VARIABLE),
+    CommandSpec::fixed(Header::RequestFraudCounter, 3),
+    CommandSpec::fixed(Header::RequestRejectCounter, 3),
+    CommandSpec::fixed(Header::RequestLastModificationDate, 2),
+    CommandSpec::fixed(Header::RequestCreationDate, 2),
+    CommandSpec::fixed(Header::CalculateROMChecksum, 4),
+    CommandSpec::ack(Header::CountersToEEPROM),
+    CommandSpec::ack(Header::ConfigurationToEEPROM),
+    CommandSpec::fixed(Header::RequestTeachStatus, 2),
+    CommandSpec::ack(Header::TeachModeControl),
+    CommandSpec::fixed(Header::RequestPayoutAbsoluteCount, 2),
+    CommandSpec::ack(Header::ModifyPayoutAbsoluteCount),
+    CommandSpec::ranged(Header::RequestSorterPaths, 1, VARIABLE),
+    CommandSpec::ack(Header::ModifySorterPaths),
+    CommandSpec::ack(Header::PowerManagementControl),
+    CommandSpec::fixed(Header::RequestCoinPosition, 2),
+    CommandSpec::fixed(Header::RequestOptionFlags, 1),
+    CommandSpec::ack(Header::WriteDataBlock),
+    CommandSpec::ranged(Header::ReadDataBlock, 1, VARIABLE),
+    CommandSpec::fixed(Header::RequestDataStorageAvailability, 5),
+    CommandSpec::ranged(Header::RequestPayoutStatus, 1, 2),
+    CommandSpec::ack(Header::EnterPinNumber),
+    CommandSpec::ack(Header::EnterNewPinNumber),
+    CommandSpec::fixed(Header::RequestSorterOverrideStatus, 1),
+    CommandSpec::ack(Header::ModifySorterOverrideStatus),
+    CommandSpec::ack(Header::ModifyEncryptedInhibitAndOverrideRegisters),
+    CommandSpec::fixed(Header::RequestEncryptedProductId, 32),
+    CommandSpec::fixed(Header::RequestAcceptCounter, 3),
+    CommandSpec::fixed(Header::RequestInsertionCounter, 3),
+    CommandSpec::ranged(Header::RequestMasterInhibitStatus, 1, VARIABLE),
+    CommandSpec::ack(Header::ModifyMasterInhibitStatus),
+    CommandSpec::ranged(Header::ReadBufferedCreditOrErrorCodes, 1, 11),
+    CommandSpec::ranged(Header::RequestInhibitStatus, 1, VARIABLE),
+    CommandSpec::ack(Header::ModifyInhibitStatus),
+    CommandSpec::ranged(Header::PerformSelfCheck, 1, 2),
+    CommandSpec::ack(Header::LatchOutputLines),
+    CommandSpec::ack(Header::SendDHPubKey),
+    CommandSpec::ranged(Header::ReadOptoStates, 1, VARIABLE),
+    CommandSpec::ranged(Header::ReadInputLines, 0, VARIABLE),
+    CommandSpec::ack(Header::TestOutputLines),
+    CommandSpec::ack(Header::OperateMotors),
+    CommandSpec::ack(Header::TestSolenoids),
+    CommandSpec::ranged(Header::RequestSoftwareRevision, 0, VARIABLE),
+    CommandSpec::ranged(Header::RequestSerialNumber, 3, 4),
+    CommandSpec::fixed(Header::RequestDatabaseVersion, 1),
+    CommandSpec::ranged(Header::RequestProductCode, 0, VARIABLE),
+    CommandSpec::ranged(Header::RequestEquipementCategoryId, 0, VARIABLE),
+    CommandSpec::ranged(Header::RequestManufacturerId, 0, VARIABLE),
+    CommandSpec::ranged(Header::RequestVariableSet, 0, VARIABLE),
+    CommandSpec::fixed(Header::RequestStatus, 1),
+    CommandSpec::fixed(Header::RequestPollingPriority, 2),
+    CommandSpec::ack(Header::AddressRandom),
+    CommandSpec::ack(Header::AddressChange),
+    CommandSpec::fixed(Header::AddressClash, 1),
+    CommandSpec::fixed(Header::AddressPoll, 1),
+    CommandSpec::ack(Header::SimplePoll),
+];
+
+#[cfg(test)]
+mod test {
+    use cc_talk_core::cc_talk::BillRouteCode;
+
+    use super::*;
+    use crate::{
+        command::Command,
+        core_plus::core_plus_commands::{RequestCommsRevisionCommand, RequestSerialNumberCommand},
+        device::device_commands::{RequestHopperStatusCommand, RouteBillCommand},
+    };
+
+    #[test]
+    fn table_is_ordered_by_header_without_duplicates() {
+        for pair in COMMAND_TABLE.windows(2) {
+            assert!(
+                (pair[0].header as u8) < (pair[1].header as u8),
+                "{:?} before {:?}",
+                pair[0].header,
+                pair[1].header
+            );
+        }
+        for spec in COMMAND_TABLE {
+            assert!(spec.min_response_length <= spec.max_response_length);
+            assert_eq!(spec.ack_only, spec.max_response_length == 0);
+        }
+    }
+
+    #[test]
+    fn lookup_finds_supported_headers_only() {
+        const SIMPLE_POLL: Option<&CommandSpec> = lookup(Header::SimplePoll);
+        assert!(SIMPLE_POLL.is_some_and(|spec| spec.ack_only));
+        assert_eq!(
+            lookup(Header::RequestSerialNumber).map(|spec| spec.max_response_length),
+            Some(4)
+        );
+        assert_eq!(lookup(Header::Reply), None);
+        assert_eq!(lookup(Header::NACK), None);
+        assert_eq!(max_response_length(Header::RequestHopperStatus), 4);
+        assert_eq!(max_response_length(Header::Reply), u8::MAX);
+    }
+
+    #[test]
+    fn validation_agrees_with_the_command_types() {
+        let check = |header: Header, payload: &[u8], typed: bool| {
+            let spec = lookup(header).expect("should be supported");
+            assert_eq!(
+                spec.validate(payload).is_ok(),
+                typed,
+                "{header:?} {payload:?}"
+            );
+        };
+
+        for payload in [&[1u8, 2][..], &[1, 2, 3], &[1, 2, 3, 4], &[1, 2, 3, 4, 5]] {
+            check(
+                Header::RequestSerialNumber,
+                payload,
+                RequestSerialNumberCommand.parse_response(payload).is_ok(),
+            );
+            check(
+                Header::RequestCommsRevision,
+                payload,
+                RequestCommsRevisionCommand.parse_response(payload).is_ok(),
+            );
+            check(
+                Header::RequestHopperStatus,
+                payload,
+                RequestHopperStatusCommand.parse_response(payload).is_ok(),
+            );
+        }
+        for payload in [&[][..], &[254], &[254, 0]] {
+            check(
+                Header::RouteBill,
+                payload,
+                RouteBillCommand::new(BillRouteCode::Stack)
+                    .parse_response(payload)
+                    .is_ok(),
+            );
+        }
+    }
+
+    #[test]
+    fn mismatches_report_the_closest_accepted_length() {
+        let spec = lookup(Header::PerformSelfCheck).expect("should be supported");
+        assert_eq!(
+            spec.validate(&[]),
+            Err(ParseResponseError::DataLengthMismatch(1, 0))
+        );
+        assert_eq!(
+            spec.validate(&[0, 0, 0]),
+            Err(ParseResponseError::DataLengthMismatch(2, 3))
+        );
+        assert_eq!(spec.validate(&[0, 0]), Ok(()));
+    }
+}
@@ -1,6 +1,9 @@
 use cc_talk_core::cc_talk::{Category, Header, Manufacturer};
 
-use super::super::command::{Command, ParseResponseError};
+use super::super::{
+    ascii::AsciiCommand,
+    command::{Command, ParseResponseError},
+};
 
 #[derive(Debug)]
 pub struct SimplePollCommand;
@@ -81,65 +84,11 @@ impl Command for RequestEquipementCategoryIdCommand {
     }
 }
 
-#[derive(Debug)]
-pub struct RequestProductCodeCommand;
-impl Command for RequestProductCodeCommand {
-    type Response = heapless::String<64>;
+/// Requests the product code, e.g. `SCH2`.
+pub type RequestProductCodeCommand = AsciiCommand<{ Header::RequestProductCode as u8 }>;
 
-    fn header(&self) -> Header {
-        Header::RequestProductCode
-    }
-
-    fn data(&self) -> &[u8] {
-        &[]
-    }
-
-    /// The answer to this command is a string, currently the `parse_response` will only check if
-    /// the response is valid UTF-8.
-    ///
-    /// The cast to a valid data type depending on the enviornment (std, heapless, etc.) is left to
-    /// the user.
-    fn parse_response(
-        &self,
-        response_payload: &[u8],
-    ) -> Result<Self::Response, ParseResponseError> {
-        if !response_payload.iter().all(|&b| b.is_ascii()) {
-            return Err(ParseResponseError::ParseError("Invalid ASCII response"));
-        }
-        Ok(heapless::String::from_iter(
-            response_payload.iter().map(|b| *b as char),
-        ))
-    }
-}
-
-#[derive(Debug)]
-pub struct RequestBuildCodeCommand;
-impl Command for RequestBuildCodeCommand {
-    type Response = ();
-
-    fn header(&self) -> Header {
-        Header::RequestBuildCode
-    }
-
-    fn data(&self) -> &[u8] {
-        &[]
-    }
-
-    /// The answer to this command is a string, currently the `parse_response` will only check if
-    /// the response is valid UTF-8.
-    ///
-    /// The cast to a valid data type depending on the enviornment (std, heapless, etc.) is left to
-    /// the user.
-    fn parse_response(
-        &self,
-        response_payload: &[u8],
-    ) -> Result<Self::Response, ParseResponseError> {
-        if !response_payload.iter().all(|&b| b.is_ascii()) {
-            return Err(ParseResponseError::ParseError("Invalid UTF-8 response"));
-        }
-        Ok(())
-    }
-}
+/// Requests the build code, the hardware or software variant of the product.
+pub type RequestBuildCodeCommand = AsciiCommand<{ Header::RequestBuildCode as u8 }>;
 
 #[deprecated(note = "This command is not implemented yet.")]
 #[derive(Debug)]
@@ -206,7 +155,7 @@ mod test {
 
     #[test]
     fn product_code() {
-        let cmd = RequestProductCodeCommand;
+        let cmd = RequestProductCodeCommand::default();
         assert_eq!(cmd.header(), Header::RequestProductCode);
         assert!(cmd.data().is_empty());
 
@@ -221,7 +170,7 @@ mod test {
 
     #[test]
     fn request_build_code() {
-        let cmd = RequestBuildCodeCommand;
+        let cmd = RequestBuildCodeCommand::default();
         assert_eq!(cmd.header(), Header::RequestBuildCode);
         assert!(cmd.data().is_empty());
 
@@ -1,6 +1,13 @@
-use cc_talk_core::cc_talk::{DataStorage, Header, RTBYDate, SerialCode};
+use cc_talk_core::cc_talk::{BnvKey, DataStorageAvailability, Header, RTBYDate, SerialCode};
+#[cfg(feature = "aes-encryption")]
+use cc_talk_core::cc_talk::{
+    ChallengeRng, EncryptedProductId, EncryptedProductIdError, ProductIdCipher,
+};
 
-use super::super::command::{Command, ParseResponseError};
+use super::super::{
+    ascii::AsciiCommand,
+    command::{Command, ParseResponseError},
+};
 
 #[derive(Debug)]
 pub struct RequestSerialNumberCommand;
@@ -15,56 +22,23 @@ impl Command for RequestSerialNumberCommand {
         &[]
     }
 
-    /// Parses the response payload as a serial code.
-    fn parse_response(
-        &self,
-        response_payload: &[u8],
-    ) -> Result<Self::Response, ParseResponseError> {
-        if response_payload.len() != 3 {
-            return Err(ParseResponseError::DataLengthMismatch(
-                3,
-                response_payload.len(),
-            ));
-        }
-        Ok(SerialCode::new(
-            response_payload[2],
-            response_payload[1],
-            response_payload[0], // Byte 0 is LSB
-        ))
-    }
-}
-
-#[derive(Debug)]
-pub struct RequestSoftwareRevisionCommand;
-impl Command for RequestSoftwareRevisionCommand {
-    type Response = heapless::String<64>;
-
-    fn header(&self) -> Header {
-        Header::RequestSoftwareRevision
-    }
-
-    fn data(&self) -> &[u8] {
-        &[]
-    }
-
-    /// The answer to this command is a string, currently the `parse_response` will only check if
-    /// the response is valid UTF-8.
+    /// Parses the response payload as a serial code, byte 0 is LSB.
     ///
-    /// The cast to a valid data type depending on the enviornment (std, heapless, etc.) is left to
-    /// the user.
+    /// Both the standard 3 byte and the extended 4 byte serial numbers are accepted.
     fn parse_response(
         &self,
         response_payload: &[u8],
     ) -> Result<Self::Response, ParseResponseError> {
-        if !response_payload.iter().all(|&b| b.is_ascii()) {
-            return Err(ParseResponseError::ParseError("Invalid ASCII response"));
-        }
-        Ok(heapless::String::from_iter(
-            response_payload.iter().map(|b| *b as char),
+        SerialCode::from_le_bytes(response_payload).ok_or(ParseResponseError::DataLengthMismatch(
+            3,
+            response_payload.len(),
         ))
     }
 }
 
+/// Requests the software revision, its format is up to the manufacturer.
+pub type RequestSoftwareRevisionCommand = AsciiCommand<{ Header::RequestSoftwareRevision as u8 }>;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ReadDHPublicKeyMode {
     RequestStatus,
@@ -115,9 +89,66 @@ impl Command for SendDHPublicKeyCommand<'_> {
     }
 }
 
-#[deprecated(note = "encryption is not supported yet, so this command is not implemented")]
+/// Requests the product identification encrypted by the [`ProductIdCipher`] of the peripheral.
+///
+/// The reply must answer both challenge bytes, a replayed reply is rejected.
+#[cfg(feature = "aes-encryption")]
 #[derive(Debug)]
-pub struct RequestEncryptedProductIdCommand;
+pub struct RequestEncryptedProductIdCommand<'a, C: ProductIdCipher + ?Sized> {
+    cipher: &'a C,
+    challenge: [u8; 2],
+}
+#[cfg(feature = "aes-encryption")]
+impl<'a, C: ProductIdCipher + ?Sized> RequestEncryptedProductIdCommand<'a, C> {
+    /// Draws the challenge bytes from `rng`.
+    pub fn new(cipher: &'a C, mut rng: impl ChallengeRng) -> Self {
+        let mut challenge = [0u8; 2];
+        rng.fill_bytes(&mut challenge);
+        Self::with_challenge(cipher, challenge)
+    }
+
+    pub const fn with_challenge(cipher: &'a C, challenge: [u8; 2]) -> Self {
+        RequestEncryptedProductIdCommand { cipher, challenge }
+    }
+
+    pub const fn challenge(&self) -> [u8; 2] {
+        self.challenge
+    }
+}
+#[cfg(feature = "aes-encryption")]
+impl<C: ProductIdCipher + ?Sized> Command for RequestEncryptedProductIdCommand<'_, C> {
+    type Response = EncryptedProductId;
+
+    fn header(&self) -> Header {
+        Header::RequestEncryptedProductId
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.challenge
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        self.cipher
+            .decrypt_product_id(self.challenge, response_payload)
+            .map_err(|error| match error {
+                EncryptedProductIdError::InvalidLength { expected, received } => {
+                    ParseResponseError::DataLengthMismatch(expected, received)
+                }
+                EncryptedProductIdError::ChallengeMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted product id challenge mismatch")
+                }
+                EncryptedProductIdError::ChecksumMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted product id checksum mismatch")
+                }
+                EncryptedProductIdError::InvalidField(_) => {
+                    ParseResponseError::ParseError("invalid encrypted product id")
+                }
+            })
+    }
+}
 
 #[deprecated(note = "encryption is not supported yet, so this command is not implemented")]
 #[derive(Debug)]
@@ -126,7 +157,7 @@ pub struct RequestACMIEncryptedDataCommand;
 #[derive(Debug)]
 pub struct RequestDataStorageAvailabilityCommand;
 impl Command for RequestDataStorageAvailabilityCommand {
-    type Response = DataStorage;
+    type Response = DataStorageAvailability;
 
     fn header(&self) -> Header {
         Header::RequestDataStorageAvailability
@@ -138,8 +169,8 @@ impl Command for RequestDataStorageAvailabilityCommand {
 
     /// Parses the data storage availability response.
     ///
-    /// If read or write is not available, the corresponding blocks and bytes per block will be set
-    /// to 0.
+    /// A block count of 0 is resolved to 256 blocks, an area with 0 bytes per block is not
+    /// available.
     fn parse_response(
         &self,
         response_payload: &[u8],
@@ -150,13 +181,14 @@ impl Command for RequestDataStorageAvailabilityCommand {
                 response_payload.len(),
             ));
         }
-        Ok(DataStorage::from([
+        DataStorageAvailability::try_from([
             response_payload[0],
             response_payload[1],
             response_payload[2],
             response_payload[3],
             response_payload[4],
-        ]))
+        ])
+        .map_err(|_| ParseResponseError::ParseError("invalid memory type"))
     }
 }
 
@@ -322,13 +354,75 @@ impl Command for RequestAddressModeCommand {
     }
 }
 
-#[deprecated(note = "encryption is not supported yet, so this command is not implemented")]
+/// Switches the protocol encryption key of the device.
+///
+/// The change is volatile, see [`StoreEncryptionCodeCommand`]. The ACK is still encrypted with
+/// the previous key, the new key is used from the next command on. The tokio transport switches
+/// to the new key itself once the ACK is received.
 #[derive(Debug)]
-pub struct SwitchEncryptionCodeCommand;
+pub struct SwitchEncryptionCodeCommand {
+    buffer: [u8; 3],
+}
+impl SwitchEncryptionCodeCommand {
+    #[must_use]
+    pub const fn new(key: BnvKey) -> Self {
+        Self {
+            buffer: key.to_bytes(),
+        }
+    }
+}
+impl Command for SwitchEncryptionCodeCommand {
+    type Response = ();
 
-#[deprecated(note = "encryption is not supported yet, so this command is not implemented")]
+    fn header(&self) -> Header {
+        Header::SwitchEncryptionMode
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        if !response_payload.is_empty() {
+            return Err(ParseResponseError::DataLengthMismatch(
+                0,
+                response_payload.len(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Stores the current protocol encryption key in NV memory, it is used after the next power up.
 #[derive(Debug)]
 pub struct StoreEncryptionCodeCommand;
+impl Command for StoreEncryptionCodeCommand {
+    type Response = ();
+
+    fn header(&self) -> Header {
+        Header::StoreEncryptionMode
+    }
+
+    fn data(&self) -> &[u8] {
+        &[]
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        if !response_payload.is_empty() {
+            return Err(ParseResponseError::DataLengthMismatch(
+                0,
+                response_payload.len(),
+            ));
+        }
+        Ok(())
+    }
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct UsbInfo {
@@ -581,7 +675,7 @@ impl Command for ResetDeviceCommand {
 
 #[cfg(test)]
 mod test {
-    use cc_talk_core::cc_talk::MemoryType;
+    use cc_talk_core::cc_talk::{DataBlocks, MemoryType};
     use heapless::format;
 
     use super::*;
@@ -597,9 +691,52 @@ mod test {
         assert_eq!(response.as_number(), 65536);
     }
 
+    #[test]
+    fn request_extended_serial_number() {
+        let command = RequestSerialNumberCommand;
+        let response = command.parse_response(&[0, 0, 0, 1]).unwrap();
+        assert!(response.is_extended());
+        assert_eq!(response.as_number(), 1 << 24);
+
+        assert_eq!(
+            command.parse_response(&[0, 1]),
+            Err(ParseResponseError::DataLengthMismatch(3, 2))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "aes-encryption")]
+    fn encrypted_product_id_answers_the_challenge() {
+        use cc_talk_core::cc_talk::{AesProductIdCipher, ProductIdKey};
+
+        let cipher = AesProductIdCipher::new(ProductIdKey::new([0x2B; 32]));
+        let id = EncryptedProductId {
+            manufacturer: format!("INO").unwrap(),
+            product: format!("NV11").unwrap(),
+            serial: SerialCode::new_extended(1, 2, 3, 4),
+            revision: format!("NV-V3.4").unwrap(),
+        };
+        let reply = cipher.encrypt_product_id(&id, [0xC0, 0xDE], 0x17);
+
+        let command = RequestEncryptedProductIdCommand::with_challenge(&cipher, [0xC0, 0xDE]);
+        assert_eq!(command.data(), &[0xC0, 0xDE]);
+        assert_eq!(command.parse_response(&reply), Ok(id));
+        assert_eq!(
+            RequestEncryptedProductIdCommand::with_challenge(&cipher, [0xC0, 0xDF])
+                .parse_response(&reply),
+            Err(ParseResponseError::ParseError(
+                "encrypted product id challenge mismatch"
+            ))
+        );
+        assert_eq!(
+            command.parse_response(&reply[..31]),
+            Err(ParseResponseError::DataLengthMismatch(32, 31))
+        );
+    }
+
     #[test]
     fn request_software_revision() {
-        let command = RequestSoftwareRevisionCommand;
+        let command = RequestSoftwareRevisionCommand::default();
         let response = command.parse_response(b"v1.0.0");
         assert!(response.is_ok());
     }
@@ -607,17 +744,26 @@ mod test {
     #[test]
     fn data_storage_availability() {
         let command = RequestDataStorageAvailabilityCommand;
-        let response = command.parse_response(&[0, 1, 2, 3, 4]).unwrap();
+        let response = command.parse_response(&[0, 1, 2, 0, 4]).unwrap();
         assert_eq!(
             response,
-            DataStorage {
+            DataStorageAvailability {
                 memory_type: MemoryType::VolatileOnReset,
-                read_blocks: 1,
-                read_bytes_per_block: 2,
-                write_blocks: 3,
-                write_bytes_per_block: 4
+                read: Some(DataBlocks {
+                    blocks: 1,
+                    bytes_per_block: 2
+                }),
+                write: Some(DataBlocks {
+                    blocks: 256,
+                    bytes_per_block: 4
+                }),
             }
         );
+        assert_eq!(response.write.map(|write| write.capacity()), Some(1024));
+        assert_eq!(
+            command.parse_response(&[4, 1, 2, 3, 4]),
+            Err(ParseResponseError::ParseError("invalid memory type"))
+        );
     }
 
     #[test]
@@ -626,12 +772,10 @@ mod test {
         let response = command.parse_response(&[3, 255, 0, 50, 0]).unwrap();
         assert_eq!(
             response,
-            DataStorage {
+            DataStorageAvailability {
                 memory_type: MemoryType::PermanentUnlimitedUse,
-                read_blocks: 255,
-                read_bytes_per_block: 0,
-                write_blocks: 50,
-                write_bytes_per_block: 0
+                read: None,
+                write: None,
             }
         );
     }
@@ -3,14 +3,21 @@
 use core::time::Duration;
 
 use cc_talk_core::cc_talk::{
-    BillRouteCode, BillRoutingError, BillValidatorPollResult, BillValidatorPollResultError,
-    BitMask, BitMaskError, ChangerDevice, ChangerError, ChangerFlags, ChangerPollResult,
-    CoinAcceptorPollResult, CurrencyToken, CurrencyTokenError, EscrowFaultCode, EscrowLevelStatus,
-    EscrowOperatingStatus, EscrowServiceStatus, Fault, FaultCode, FirmwareStorageType, Header,
-    HopperDispenseStatus, HopperDispenseValueStatus, HopperFlag, HopperStatus, LampControl,
-    PowerOption, RequestOptionFlags, SorterPath, StackerCycleError, TeachModeStatus,
+    AcceptLimitError, AcceptLimitFormat, BillRouteCode, BillRoutingError, BillValidatorPollResult,
+    BillValidatorPollResultError, BitMask, BitMaskError, ChangerDevice, ChangerError, ChangerFlags,
+    ChangerPollResult, CoinAcceptorPollResult, CountryScalingFactor, CurrencyToken,
+    CurrencyTokenError, DataBlockError, DataStorageAvailability, EscrowFaultCode,
+    EscrowLevelStatus, EscrowOperatingStatus, EscrowServiceStatus, Fault, FaultCode,
+    FirmwareStorageType, Header, HopperDispenseStatus, HopperDispenseValueStatus, HopperFlag,
+    HopperStatus, HopperVariables, LampControl, OptoReading, OptoScaling, PowerOption,
+    RequestOptionFlags, SorterPath, StackerCycleError, TeachModeStatus, encode_accept_limit,
     parse_changer_flags_heapless,
 };
+#[cfg(feature = "hopper-encryption")]
+use cc_talk_core::cc_talk::{
+    ChallengeRng, EncryptedEvents, EncryptedEventsError, EncryptedHopperStatus, HopperCipher,
+    HopperEncryptionError, MonetaryId, MonetaryIdError, PeripheralCipher,
+};
 
 use crate::commands::command::{Command, ParseResponseError};
 
@@ -158,6 +165,31 @@ impl Command for RequestVariableSetCommand {
     }
 }
 
+/// `RequestVariableSet` for hoppers using the [`HopperVariables`] layout.
+#[derive(Debug)]
+pub struct RequestHopperVariableSetCommand;
+impl Command for RequestHopperVariableSetCommand {
+    type Response = HopperVariables;
+
+    fn header(&self) -> Header {
+        Header::RequestVariableSet
+    }
+
+    fn data(&self) -> &[u8] {
+        &[]
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        HopperVariables::from_bytes(response_payload).ok_or(ParseResponseError::DataLengthMismatch(
+            HopperVariables::LEN,
+            response_payload.len(),
+        ))
+    }
+}
+
 #[derive(Debug)]
 pub struct RequestDatabaseVersionCommand;
 impl Command for RequestDatabaseVersionCommand {
@@ -303,7 +335,7 @@ impl Command for ReadOptoStatesCommand {
         match payload.len() {
             1 => Ok(payload[0]),
             2..=usize::MAX => {
-                crate::log::warning!(
+                crate::log::warn!(
                     "expected size of 1, but got {} instead. Maybe some information got lost.",
                     payload.len()
                 );
@@ -593,9 +625,100 @@ impl Command for RequestCreditCounterCommand {
     }
 }
 
-// TODO: Implement this once encryption is supported
+/// Sets the inhibit and sorter override registers in one operation, for both the 'current'
+/// coin and the 'next' coin.
+///
+/// The 'next' registers are applied by the coin acceptor after each accept sequence, which
+/// allows coin-by-coin control of acceptance and routing.
+#[derive(Debug)]
+pub struct ModifyInhibitAndOverrideRegistersCommand {
+    buffer: [u8; 6],
+}
+impl ModifyInhibitAndOverrideRegistersCommand {
+    pub fn build(
+        current_inhibits: BitMask<2>,
+        current_overrides: BitMask<1>,
+        next_inhibits: BitMask<2>,
+        next_overrides: BitMask<1>,
+    ) -> Result<Self, BitMaskError> {
+        let [current_1, current_2] = current_inhibits.to_le_bytes::<2>()?;
+        let [current_override] = current_overrides.to_le_bytes::<1>()?;
+        let [next_1, next_2] = next_inhibits.to_le_bytes::<2>()?;
+        let [next_override] = next_overrides.to_le_bytes::<1>()?;
+        Ok(ModifyInhibitAndOverrideRegistersCommand {
+            buffer: [
+                current_1,
+                current_2,
+                current_override,
+                next_1,
+                next_2,
+                next_override,
+            ],
+        })
+    }
+}
+impl Command for ModifyInhibitAndOverrideRegistersCommand {
+    type Response = ();
+
+    fn header(&self) -> Header {
+        Header::ModifyInhibitAndOverrideRegisters
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    fn parse_response(&self, payload: &[u8]) -> Result<Self::Response, ParseResponseError> {
+        if payload.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseResponseError::DataLengthMismatch(0, payload.len()))
+        }
+    }
+}
+
+/// Sets the inhibits and sorter overrides together with a cash value and a coin count limit.
+///
+/// The coin acceptor rejects coins which would take the cumulative value or count above the
+/// limits, 0 disables a limit. Every time the command is sent the cumulative totals are reset.
 #[derive(Debug)]
-pub struct ModifyEncryptedInhibitAndOverrideRegistersCommand;
+pub struct ModifyEncryptedInhibitAndOverrideRegistersCommand {
+    buffer: [u8; 13],
+}
+impl ModifyEncryptedInhibitAndOverrideRegistersCommand {
+    pub fn build(
+        inhibits: BitMask<4>,
+        cash_value: u32,
+        coin_count: u32,
+        sorter_overrides: BitMask<1>,
+    ) -> Result<Self, BitMaskError> {
+        let mut buffer = [0u8; 13];
+        buffer[..4].copy_from_slice(&inhibits.to_le_bytes::<4>()?);
+        buffer[4..8].copy_from_slice(&cash_value.to_le_bytes());
+        buffer[8..12].copy_from_slice(&coin_count.to_le_bytes());
+        [buffer[12]] = sorter_overrides.to_le_bytes::<1>()?;
+        Ok(ModifyEncryptedInhibitAndOverrideRegistersCommand { buffer })
+    }
+}
+impl Command for ModifyEncryptedInhibitAndOverrideRegistersCommand {
+    type Response = ();
+
+    fn header(&self) -> Header {
+        Header::ModifyEncryptedInhibitAndOverrideRegisters
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    fn parse_response(&self, payload: &[u8]) -> Result<Self::Response, ParseResponseError> {
+        if payload.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseResponseError::DataLengthMismatch(0, payload.len()))
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct ModifySorterOverrideStatusCommand {
@@ -737,13 +860,33 @@ impl Command for RequestpayoutHighLowStatusCommand {
     }
 }
 
-/// The size `N` should be retrieved from [Header::DataStorageAvailability]
+/// Largest data block, the block size is a single byte of [`DataStorageAvailability`].
+pub const MAX_DATA_BLOCK_SIZE: usize = u8::MAX as usize;
+
+/// Reads a data block, its size is negotiated with `RequestDataStorageAvailability`.
 #[derive(Debug)]
-pub struct ReadDataBlockCommand<const N: usize> {
-    pub block_number: u8,
+pub struct ReadDataBlockCommand {
+    block_number: u8,
+    block_size: usize,
+}
+impl ReadDataBlockCommand {
+    pub fn new(
+        storage: &DataStorageAvailability,
+        block_number: u8,
+    ) -> Result<Self, DataBlockError> {
+        let block_size = storage.read_block_size(block_number)?;
+        Ok(ReadDataBlockCommand {
+            block_number,
+            block_size: usize::from(block_size),
+        })
+    }
+
+    pub fn block_number(&self) -> u8 {
+        self.block_number
+    }
 }
-impl<const N: usize> Command for ReadDataBlockCommand<N> {
-    type Response = [u8; N];
+impl Command for ReadDataBlockCommand {
+    type Response = heapless::Vec<u8, MAX_DATA_BLOCK_SIZE>;
 
     fn header(&self) -> Header {
         Header::ReadDataBlock
@@ -757,39 +900,51 @@ impl<const N: usize> Command for ReadDataBlockCommand<N> {
         &self,
         response_payload: &[u8],
     ) -> Result<Self::Response, ParseResponseError> {
+        let block_size = self.block_size;
         match response_payload.len() {
-            len if len == N => Ok(response_payload.try_into().unwrap()),
-            len if len > N => {
-                crate::log::info!("unexpected response length: expected {}, got {}", N, len);
-                Ok(response_payload[0..N].try_into().unwrap())
-            }
-            _ => Err(ParseResponseError::DataLengthMismatch(
-                N,
+            len if len < block_size => Err(ParseResponseError::DataLengthMismatch(
+                block_size,
                 response_payload.len(),
             )),
+            len => {
+                if len > block_size {
+                    crate::log::info!(
+                        "unexpected response length: expected {}, got {}",
+                        block_size,
+                        len
+                    );
+                }
+                heapless::Vec::from_slice(&response_payload[..block_size])
+                    .map_err(|_| ParseResponseError::BufferTooSmall)
+            }
         }
     }
 }
 
-/// The size `N` should be retrieved from [Header::DataStorageAvailability]
+/// Writes a data block, its size is negotiated with `RequestDataStorageAvailability`.
+///
+/// Buffers shorter than the block are written as is.
 #[derive(Debug)]
-pub struct WriteDataBlockCommand<const N: usize> {
-    data: heapless::Vec<u8, 256>,
+pub struct WriteDataBlockCommand {
+    data: heapless::Vec<u8, { MAX_DATA_BLOCK_SIZE + 1 }>,
 }
-impl<const N: usize> WriteDataBlockCommand<N> {
-    pub fn new(block_number: u8, buffer: &[u8]) -> Result<Self, ()> {
-        if buffer.len() > N {
-            return Err(());
-        }
+impl WriteDataBlockCommand {
+    pub fn new(
+        storage: &DataStorageAvailability,
+        block_number: u8,
+        buffer: &[u8],
+    ) -> Result<Self, DataBlockError> {
+        storage.write_block_size(block_number, buffer.len())?;
 
         let mut data = heapless::Vec::new();
-        data.push(block_number).map_err(|_| ())?;
-        data.extend_from_slice(buffer).map_err(|_| ())?;
+        // Cannot fail, the block size is at most MAX_DATA_BLOCK_SIZE.
+        let _ = data.push(block_number);
+        let _ = data.extend_from_slice(buffer);
 
         Ok(WriteDataBlockCommand { data })
     }
 }
-impl<const N: usize> Command for WriteDataBlockCommand<N> {
+impl Command for WriteDataBlockCommand {
     type Response = ();
 
     fn header(&self) -> Header {
@@ -2069,6 +2224,11 @@ impl DispenseHopperCoinsCommand {
         DispenseHopperCoinsCommand { buffer, length: 1 }
     }
 
+    /// Dispenses from an encrypted hopper, see [`HopperCipher::security_code`].
+    pub fn new_encrypted(coins: u8, security_code: [u8; 8]) -> Self {
+        Self::new_with_data(coins, &security_code)
+    }
+
     pub fn new_with_data(coins: u8, additional_data: &[u8]) -> Self {
         const MAX_BUFFER_SIZE: usize = 32;
 
@@ -2175,6 +2335,44 @@ impl<const N: usize> Command for ModifyVariableSetCommand<N> {
     }
 }
 
+/// `ModifyVariableSet` for hoppers using the [`HopperVariables`] layout.
+#[derive(Debug)]
+pub struct ModifyHopperVariableSetCommand {
+    buffer: [u8; HopperVariables::LEN],
+}
+impl ModifyHopperVariableSetCommand {
+    pub fn new(variables: HopperVariables) -> Self {
+        ModifyHopperVariableSetCommand {
+            buffer: variables.to_bytes(),
+        }
+    }
+}
+impl Command for ModifyHopperVariableSetCommand {
+    type Response = ();
+
+    fn header(&self) -> Header {
+        Header::ModifyVariableSet
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        if response_payload.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseResponseError::DataLengthMismatch(
+                0,
+                response_payload.len(),
+            ))
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct EnableHopperCommand {
     buffer: [u8; 1],
@@ -2273,10 +2471,12 @@ impl<const N: usize> Command for PumpRngCommand<N> {
     }
 }
 
+/// Requests the 8 byte cipher key used to compute the next dispense security code, see
+/// [`HopperCipher::security_code`].
 #[derive(Debug)]
 pub struct RequestCipherKeyCommand;
 impl Command for RequestCipherKeyCommand {
-    type Response = ();
+    type Response = [u8; 8];
 
     fn header(&self) -> Header {
         Header::RequestCipherKey
@@ -2286,9 +2486,13 @@ impl Command for RequestCipherKeyCommand {
         &[]
     }
 
-    /// Device specific command, no validation/parsing is provided.
-    fn parse_response(&self, _: &[u8]) -> Result<Self::Response, ParseResponseError> {
-        Ok(())
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        response_payload
+            .try_into()
+            .map_err(|_| ParseResponseError::DataLengthMismatch(8, response_payload.len()))
     }
 }
 
@@ -2419,23 +2623,63 @@ impl Command for RequestBillIdCommand {
     }
 }
 
-// TODO: Implement this, however the scaling factor is hardcoded for now
 #[derive(Debug)]
-pub struct RequestCountryScalingFactorCommand;
+pub struct RequestCountryScalingFactorCommand {
+    buffer: [u8; 2],
+}
+impl RequestCountryScalingFactorCommand {
+    pub fn new(country_code: &str) -> Self {
+        RequestCountryScalingFactorCommand {
+            buffer: [country_code.as_bytes()[0], country_code.as_bytes()[1]],
+        }
+    }
+}
+impl Command for RequestCountryScalingFactorCommand {
+    type Response = CountryScalingFactor;
+
+    fn header(&self) -> Header {
+        Header::RequestCountryScalingFactor
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buffer
+    }
 
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        match response_payload.len() {
+            3 => Ok(CountryScalingFactor::new(
+                u16::from_le_bytes([response_payload[0], response_payload[1]]),
+                response_payload[2],
+            )),
+            _ => Err(ParseResponseError::DataLengthMismatch(
+                3,
+                response_payload.len(),
+            )),
+        }
+    }
+}
+
+/// Requests the bill positions of a country, one bit per position.
+///
+/// `N` is the number of mask bytes the caller can hold, the mask has a bit for every byte
+/// received. Used as is as the new inhibit mask it enables the country and inhibits every other
+/// bill.
 #[derive(Debug)]
-pub struct RequestBillPositionCommand {
+pub struct RequestBillPositionCommand<const N: usize> {
     buffer: [u8; 2],
 }
-impl RequestBillPositionCommand {
+impl<const N: usize> RequestBillPositionCommand<N> {
     pub fn new(country_code: &str) -> Self {
         RequestBillPositionCommand {
             buffer: [country_code.as_bytes()[0], country_code.as_bytes()[1]],
         }
     }
 }
-impl Command for RequestBillPositionCommand {
-    type Response = ();
+impl<const N: usize> Command for RequestBillPositionCommand<N> {
+    type Response = BitMask<N>;
 
     fn header(&self) -> Header {
         Header::RequestBillPosition
@@ -2450,11 +2694,9 @@ impl Command for RequestBillPositionCommand {
         response_payload: &[u8],
     ) -> Result<Self::Response, ParseResponseError> {
         match response_payload.len() {
-            1..=255 => Ok(()),
-            _ => Err(ParseResponseError::DataLengthMismatch(
-                1,
-                response_payload.len(),
-            )),
+            len @ 1.. if len <= N => BitMask::from_le_bytes(response_payload, len * 8)
+                .map_err(|_| ParseResponseError::ParseError("invalid bill position mask")),
+            len => Err(ParseResponseError::DataLengthMismatch(N, len)),
         }
     }
 }
@@ -2650,10 +2892,21 @@ impl Command for RequestIndividualAcceptCounterCommand {
     }
 }
 
-#[derive(Debug)]
-pub struct ReadOptoVoltagesCommand;
+/// Maximum number of readings a single reply can hold, one per byte in 8-bit mode.
+pub const MAX_OPTO_READINGS: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadOptoVoltagesCommand {
+    scaling: OptoScaling,
+}
+impl ReadOptoVoltagesCommand {
+    /// The reply format is device specific, look at your device manual for the scaling.
+    pub fn new(scaling: OptoScaling) -> Self {
+        ReadOptoVoltagesCommand { scaling }
+    }
+}
 impl Command for ReadOptoVoltagesCommand {
-    type Response = ();
+    type Response = heapless::Vec<OptoReading, MAX_OPTO_READINGS>;
 
     fn header(&self) -> Header {
         Header::ReadOptoVoltages
@@ -2663,18 +2916,30 @@ impl Command for ReadOptoVoltagesCommand {
         &[]
     }
 
-    // Device specific, look at your device manual
     fn parse_response(
         &self,
         response_payload: &[u8],
     ) -> Result<Self::Response, ParseResponseError> {
-        match response_payload.len() {
-            1..=2 => Ok(()),
-            _ => Err(ParseResponseError::DataLengthMismatch(
-                1,
+        let size = self.scaling.resolution.bytes_per_reading();
+        if response_payload.is_empty() || !response_payload.len().is_multiple_of(size) {
+            return Err(ParseResponseError::DataLengthMismatch(
+                size,
                 response_payload.len(),
-            )),
+            ));
+        }
+
+        let mut readings = heapless::Vec::new();
+        for chunk in response_payload.chunks_exact(size) {
+            let raw = match chunk {
+                [value] => u16::from(*value),
+                [lsb, msb] => u16::from_le_bytes([*lsb, *msb]),
+                _ => unreachable!("chunks are 1 or 2 bytes"),
+            };
+            readings
+                .push(self.scaling.reading(raw))
+                .map_err(|_| ParseResponseError::BufferTooSmall)?;
         }
+        Ok(readings)
     }
 }
 
@@ -2754,6 +3019,7 @@ impl RequestCurrencyRevisionCommand {
         }
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn build_with_country(country_code: &str) -> Result<Self, ()> {
         let bytes = country_code.as_bytes();
         if bytes.len() != 2 {
@@ -2799,6 +3065,7 @@ pub struct UploadBillTablesCommand {
     data_len: u8,
 }
 impl UploadBillTablesCommand {
+    #[allow(clippy::result_unit_err)]
     pub fn new(block: u8, line: u8, data: &[u8]) -> Result<Self, ()> {
         const MAX_PAYLOAD_SIZE: usize = 128;
         const COMMAND_BUFFER_SIZE: usize = 130;
@@ -2962,6 +3229,7 @@ pub struct UploadFirmwareCommand {
     data_len: u8,
 }
 impl UploadFirmwareCommand {
+    #[allow(clippy::result_unit_err)]
     pub fn new(block: u8, line: u8, data: &[u8]) -> Result<Self, ()> {
         const MAX_PAYLOAD_SIZE: usize = 128;
         const COMMAND_BUFFER_SIZE: usize = 130;
@@ -3087,11 +3355,25 @@ impl Command for FinishFirmwareUpgradeCommand {
 
 #[derive(Debug)]
 pub struct SetAcceptLimitCommand {
-    buffer: [u8; 1],
+    buffer: [u8; 2],
+    len: usize,
 }
 impl SetAcceptLimitCommand {
+    /// Single byte accept limit, as defined by the specification.
     pub fn new(limit: u8) -> Self {
-        SetAcceptLimitCommand { buffer: [limit] }
+        SetAcceptLimitCommand {
+            buffer: [limit, 0],
+            len: 1,
+        }
+    }
+
+    /// Accept limit in the payload format supported by the device.
+    ///
+    /// Fails if the limit does not fit the format, use [`AcceptLimitFormat::TwoBytes`] for
+    /// devices accepting a 16-bit limit.
+    pub fn build(limit: u16, format: AcceptLimitFormat) -> Result<Self, AcceptLimitError> {
+        let (buffer, len) = encode_accept_limit(limit, format)?;
+        Ok(SetAcceptLimitCommand { buffer, len })
     }
 }
 impl Command for SetAcceptLimitCommand {
@@ -3102,7 +3384,7 @@ impl Command for SetAcceptLimitCommand {
     }
 
     fn data(&self) -> &[u8] {
-        &self.buffer
+        &self.buffer[..self.len]
     }
 
     fn parse_response(
@@ -3841,13 +4123,221 @@ impl Command for RequestRtcCommand {
     }
 }
 
-// TODO: implement when encryption is supported
+/// Reads the event buffer encrypted by the [`PeripheralCipher`] of the peripheral, replaces
+/// `ReadBufferedCreditOrErrorCodes` and `ReadBufferedBillEvents` on encrypted devices.
+///
+/// The reply must answer the random `challenge` byte, a recorded reply cannot be replayed. It
+/// parses into a [`CoinAcceptorPollResult`] or a [`BillValidatorPollResult`], chosen with `R`.
+#[cfg(feature = "hopper-encryption")]
 #[derive(Debug)]
-pub struct ReadEncryptedEventsCommand;
+pub struct ReadEncryptedEventsCommand<'a, C: PeripheralCipher + ?Sized, R = CoinAcceptorPollResult>
+{
+    cipher: &'a C,
+    challenge: [u8; 1],
+    last_event_counter: u8,
+    result: core::marker::PhantomData<R>,
+}
+#[cfg(feature = "hopper-encryption")]
+impl<'a, C: PeripheralCipher + ?Sized, R> ReadEncryptedEventsCommand<'a, C, R> {
+    /// Draws the challenge from `rng`.
+    pub fn new(cipher: &'a C, last_event_counter: u8, mut rng: impl ChallengeRng) -> Self {
+        let mut challenge = [0u8; 1];
+        rng.fill_bytes(&mut challenge);
+        Self::with_challenge(cipher, challenge[0], last_event_counter)
+    }
+
+    pub const fn with_challenge(cipher: &'a C, challenge: u8, last_event_counter: u8) -> Self {
+        ReadEncryptedEventsCommand {
+            cipher,
+            challenge: [challenge],
+            last_event_counter,
+            result: core::marker::PhantomData,
+        }
+    }
+
+    pub const fn challenge(&self) -> u8 {
+        self.challenge[0]
+    }
+
+    /// Decrypts the reply and checks it answers the challenge, without parsing the events.
+    pub fn decrypt(&self, response_payload: &[u8]) -> Result<EncryptedEvents, ParseResponseError> {
+        self.cipher
+            .decrypt_events(self.challenge[0], response_payload)
+            .map_err(|error| match error {
+                EncryptedEventsError::InvalidLength { expected, received } => {
+                    ParseResponseError::DataLengthMismatch(expected, received)
+                }
+                EncryptedEventsError::ChallengeMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted events challenge mismatch")
+                }
+                EncryptedEventsError::ChecksumMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted events checksum mismatch")
+                }
+            })
+    }
+}
+#[cfg(feature = "hopper-encryption")]
+impl<C: PeripheralCipher + ?Sized> Command
+    for ReadEncryptedEventsCommand<'_, C, CoinAcceptorPollResult>
+{
+    type Response = CoinAcceptorPollResult;
+
+    fn header(&self) -> Header {
+        Header::ReadEncryptedEvents
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.challenge
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        let events = self.decrypt(response_payload)?;
+        ReadBufferedCreditOrErrorCodeCommand::new(self.last_event_counter)
+            .parse_response(&events.buffer())
+    }
+}
+#[cfg(feature = "hopper-encryption")]
+impl<C: PeripheralCipher + ?Sized> Command
+    for ReadEncryptedEventsCommand<'_, C, BillValidatorPollResult>
+{
+    type Response = BillValidatorPollResult;
+
+    fn header(&self) -> Header {
+        Header::ReadEncryptedEvents
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.challenge
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        let events = self.decrypt(response_payload)?;
+        ReadBufferedBillEventsCommand::new(self.last_event_counter).parse_response(&events.buffer())
+    }
+}
+
+/// Requests the hopper status encrypted by the [`HopperCipher`] of the hopper.
+///
+/// The reply must answer the 3 random `challenge` bytes, a recorded reply cannot be replayed.
+#[cfg(feature = "hopper-encryption")]
 #[derive(Debug)]
-pub struct RequestEncryptedHopperStatusCommand;
+pub struct RequestEncryptedHopperStatusCommand<'a, C: HopperCipher + ?Sized> {
+    cipher: &'a C,
+    challenge: [u8; 3],
+}
+#[cfg(feature = "hopper-encryption")]
+impl<'a, C: HopperCipher + ?Sized> RequestEncryptedHopperStatusCommand<'a, C> {
+    pub fn new(cipher: &'a C, challenge: [u8; 3]) -> Self {
+        RequestEncryptedHopperStatusCommand { cipher, challenge }
+    }
+}
+#[cfg(feature = "hopper-encryption")]
+impl<C: HopperCipher + ?Sized> Command for RequestEncryptedHopperStatusCommand<'_, C> {
+    type Response = EncryptedHopperStatus;
+
+    fn header(&self) -> Header {
+        Header::RequestEncryptedHopperStatus
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.challenge
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        self.cipher
+            .decrypt_status(self.challenge, response_payload)
+            .map_err(|error| match error {
+                HopperEncryptionError::InvalidLength { expected, received } => {
+                    ParseResponseError::DataLengthMismatch(expected, received)
+                }
+                HopperEncryptionError::ChallengeMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted hopper status challenge mismatch")
+                }
+                HopperEncryptionError::ChecksumMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted hopper status checksum mismatch")
+                }
+            })
+    }
+}
+/// Requests the coin or bill at `position` encrypted by the [`PeripheralCipher`] of the
+/// peripheral, replaces `RequestCoinId`, `RequestBillId` and `RequestCountryScalingFactor` on
+/// encrypted devices.
+///
+/// The reply must answer the position and the random challenge byte, `None` if nothing is
+/// programmed at the position.
+#[cfg(feature = "hopper-encryption")]
 #[derive(Debug)]
-pub struct RequestEncryptedMonetaryIdCommand;
+pub struct RequestEncryptedMonetaryIdCommand<'a, C: PeripheralCipher + ?Sized> {
+    cipher: &'a C,
+    buffer: [u8; 2],
+}
+#[cfg(feature = "hopper-encryption")]
+impl<'a, C: PeripheralCipher + ?Sized> RequestEncryptedMonetaryIdCommand<'a, C> {
+    /// Draws the challenge from `rng`.
+    pub fn new(cipher: &'a C, position: u8, mut rng: impl ChallengeRng) -> Self {
+        let mut challenge = [0u8; 1];
+        rng.fill_bytes(&mut challenge);
+        Self::with_challenge(cipher, position, challenge[0])
+    }
+
+    pub const fn with_challenge(cipher: &'a C, position: u8, challenge: u8) -> Self {
+        RequestEncryptedMonetaryIdCommand {
+            cipher,
+            buffer: [position, challenge],
+        }
+    }
+
+    pub const fn challenge(&self) -> u8 {
+        self.buffer[1]
+    }
+}
+#[cfg(feature = "hopper-encryption")]
+impl<C: PeripheralCipher + ?Sized> Command for RequestEncryptedMonetaryIdCommand<'_, C> {
+    type Response = Option<MonetaryId>;
+
+    fn header(&self) -> Header {
+        Header::RequestEncryptedMonetaryId
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    fn parse_response(
+        &self,
+        response_payload: &[u8],
+    ) -> Result<Self::Response, ParseResponseError> {
+        let [position, challenge] = self.buffer;
+        self.cipher
+            .decrypt_monetary_id(position, challenge, response_payload)
+            .map_err(|error| match error {
+                MonetaryIdError::InvalidLength { expected, received } => {
+                    ParseResponseError::DataLengthMismatch(expected, received)
+                }
+                MonetaryIdError::ChallengeMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted monetary id challenge mismatch")
+                }
+                MonetaryIdError::PositionMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted monetary id position mismatch")
+                }
+                MonetaryIdError::ChecksumMismatch { .. } => {
+                    ParseResponseError::ParseError("encrypted monetary id checksum mismatch")
+                }
+                MonetaryIdError::InvalidField(_) => {
+                    ParseResponseError::ParseError("invalid encrypted monetary id")
+                }
+            })
+    }
+}
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -4029,3 +4519,306 @@ impl Command for RequestCommsStatusVariablesCommand {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use cc_talk_core::cc_talk::{OptoReference, OptoResolution};
+
+    use super::*;
+
+    #[test]
+    fn hopper_variable_set_round_trips() {
+        let variables = RequestHopperVariableSetCommand
+            .parse_response(&[10, 20, 30, 0b10])
+            .unwrap();
+        assert!(variables.use_other_hopper);
+        assert!(!variables.single_coin_mode);
+        assert_eq!(
+            RequestHopperVariableSetCommand.parse_response(&[10, 20]),
+            Err(ParseResponseError::DataLengthMismatch(4, 2))
+        );
+
+        let command = ModifyHopperVariableSetCommand::new(variables.with_single_coin_mode(true));
+        assert_eq!(command.header(), Header::ModifyVariableSet);
+        assert_eq!(command.data(), &[10, 20, 30, 0b11]);
+    }
+
+    #[test]
+    fn data_blocks_use_the_negotiated_size() {
+        let storage = DataStorageAvailability::try_from([3, 4, 3, 2, 2]).unwrap();
+        let read = ReadDataBlockCommand::new(&storage, 3).unwrap();
+        assert_eq!(read.data(), &[3]);
+        assert_eq!(
+            read.parse_response(&[1, 2, 3, 4]).unwrap().as_slice(),
+            &[1, 2, 3]
+        );
+        assert_eq!(
+            read.parse_response(&[1, 2]),
+            Err(ParseResponseError::DataLengthMismatch(3, 2))
+        );
+        assert_eq!(
+            ReadDataBlockCommand::new(&storage, 4).unwrap_err(),
+            DataBlockError::BlockOutOfRange {
+                block_number: 4,
+                blocks: 4
+            }
+        );
+
+        let write = WriteDataBlockCommand::new(&storage, 1, &[7, 8]).unwrap();
+        assert_eq!(write.data(), &[1, 7, 8]);
+        assert_eq!(
+            WriteDataBlockCommand::new(&storage, 1, &[7, 8, 9]).unwrap_err(),
+            DataBlockError::TooLarge {
+                len: 3,
+                block_size: 2
+            }
+        );
+        let read_only = DataStorageAvailability::try_from([3, 4, 3, 0, 0]).unwrap();
+        assert_eq!(
+            WriteDataBlockCommand::new(&read_only, 0, &[]).unwrap_err(),
+            DataBlockError::Unavailable
+        );
+    }
+
+    #[test]
+    fn set_accept_limit_uses_the_device_format() {
+        assert_eq!(SetAcceptLimitCommand::new(7).data(), &[7]);
+        let command = SetAcceptLimitCommand::build(300, AcceptLimitFormat::TwoBytes).unwrap();
+        assert_eq!(command.data(), &[0x2C, 0x01]);
+        assert_eq!(
+            SetAcceptLimitCommand::build(300, AcceptLimitFormat::SingleByte)
+                .unwrap_err()
+                .limit,
+            300
+        );
+    }
+
+    #[test]
+    fn read_opto_voltages_applies_scaling() {
+        let command = ReadOptoVoltagesCommand::new(OptoScaling::default());
+        let readings = command.parse_response(&[0, 51, 255]).unwrap();
+        let millivolts: heapless::Vec<u16, 3> = readings.iter().map(|r| r.millivolts).collect();
+        assert_eq!(millivolts.as_slice(), &[0, 1000, 5000]);
+
+        let command = ReadOptoVoltagesCommand::new(OptoScaling::new(
+            OptoResolution::Bits16,
+            OptoReference::V3_3,
+        ));
+        let readings = command.parse_response(&[0xFF, 0xFF, 0x00, 0x80]).unwrap();
+        assert_eq!(readings[0].raw, u16::MAX);
+        assert_eq!(readings[0].millivolts, 3300);
+        assert_eq!(readings[1].millivolts, 1650);
+
+        assert_eq!(
+            command.parse_response(&[0x01, 0x02, 0x03]),
+            Err(ParseResponseError::DataLengthMismatch(2, 3))
+        );
+        assert_eq!(
+            command.parse_response(&[]),
+            Err(ParseResponseError::DataLengthMismatch(2, 0))
+        );
+    }
+
+    #[test]
+    fn modify_inhibit_and_override_registers_layout() {
+        let mut current_inhibits = BitMask::<2>::new(16).unwrap();
+        current_inhibits.set_bit(0, true).unwrap();
+        current_inhibits.set_bit(9, true).unwrap();
+        let mut current_overrides = BitMask::<1>::new(8).unwrap();
+        current_overrides.set_bit(7, true).unwrap();
+        let next_inhibits = BitMask::<2>::new(16).unwrap();
+        let next_overrides = BitMask::<1>::new_filled(8).unwrap();
+
+        let command = ModifyInhibitAndOverrideRegistersCommand::build(
+            current_inhibits,
+            current_overrides,
+            next_inhibits,
+            next_overrides,
+        )
+        .unwrap();
+
+        assert_eq!(command.header(), Header::ModifyInhibitAndOverrideRegisters);
+        assert_eq!(command.data(), &[0x01, 0x02, 0x80, 0x00, 0x00, 0xFF]);
+        assert_eq!(command.parse_response(&[]), Ok(()));
+        assert_eq!(
+            command.parse_response(&[0]),
+            Err(ParseResponseError::DataLengthMismatch(0, 1))
+        );
+    }
+
+    #[test]
+    fn modify_encrypted_inhibit_and_override_registers_layout() {
+        let mut inhibits = BitMask::<4>::new(32).unwrap();
+        inhibits.set_bit(0, true).unwrap();
+        inhibits.set_bit(31, true).unwrap();
+
+        let command = ModifyEncryptedInhibitAndOverrideRegistersCommand::build(
+            inhibits,
+            0x0001_86A0,
+            3,
+            BitMask::<1>::new_filled(8).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            command.header(),
+            Header::ModifyEncryptedInhibitAndOverrideRegisters
+        );
+        assert_eq!(
+            command.data(),
+            &[
+                0x01, 0x00, 0x00, 0x80, 0xA0, 0x86, 0x01, 0x00, 0x03, 0x00, 0x00, 0x00, 0xFF
+            ]
+        );
+        assert_eq!(command.parse_response(&[]), Ok(()));
+    }
+
+    #[test]
+    fn request_country_scaling_factor_parses_little_endian_factor() {
+        let command = RequestCountryScalingFactorCommand::new("EU");
+
+        assert_eq!(command.data(), b"EU");
+        assert_eq!(
+            command.parse_response(&[0xE8, 0x03, 0x02]),
+            Ok(CountryScalingFactor::new(1000, 2))
+        );
+        assert!(!command.parse_response(&[0, 0, 0]).unwrap().is_supported());
+        assert_eq!(
+            command.parse_response(&[100, 0]),
+            Err(ParseResponseError::DataLengthMismatch(3, 2))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hopper-encryption")]
+    fn encrypted_dispense_sends_the_security_code_before_the_coins() {
+        use cc_talk_core::cc_talk::{DesHopperCipher, HopperDesKey};
+
+        let cipher_key = RequestCipherKeyCommand
+            .parse_response(&[1, 2, 3, 4, 5, 6, 7, 8])
+            .unwrap();
+        assert_eq!(
+            RequestCipherKeyCommand.parse_response(&[1, 2]),
+            Err(ParseResponseError::DataLengthMismatch(8, 2))
+        );
+
+        let cipher = DesHopperCipher::new(HopperDesKey::new([
+            0x13, 0x34, 0x57, 0x79, 0x9B, 0xBC, 0xDF, 0xF1,
+        ]));
+        let code = cipher.security_code(&[0; 8], &cipher_key, 5);
+        let command = DispenseHopperCoinsCommand::new_encrypted(5, code);
+        assert_eq!(command.data()[..8], code);
+        assert_eq!(command.data()[8..], [5]);
+
+        let status = RequestEncryptedHopperStatusCommand::new(&cipher, [1, 2, 3]);
+        assert_eq!(status.data(), &[1, 2, 3]);
+        assert_eq!(
+            status.parse_response(&[0; 4]),
+            Err(ParseResponseError::DataLengthMismatch(16, 4))
+        );
+        assert!(matches!(
+            status.parse_response(&[0; 16]),
+            Err(ParseResponseError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "hopper-encryption")]
+    fn encrypted_events_parse_into_poll_results() {
+        use cc_talk_core::cc_talk::{BillEvent, CoinEvent, DesPeripheralCipher, HopperDesKey};
+
+        struct Fixed;
+        impl ChallengeRng for Fixed {
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                dest.fill(0x3C);
+            }
+        }
+
+        let cipher = DesPeripheralCipher::new(HopperDesKey::new([
+            0x13, 0x34, 0x57, 0x79, 0x9B, 0xBC, 0xDF, 0xF1,
+        ]));
+        let events = EncryptedEvents {
+            event_counter: 2,
+            results: [[4, 0], [1, 0], [0, 0], [0, 0], [0, 0]],
+        };
+        let reply = cipher.encrypt_events(&events, 0x3C, [9, 9]);
+
+        let coins = ReadEncryptedEventsCommand::<_, CoinAcceptorPollResult>::new(&cipher, 1, Fixed);
+        assert_eq!(coins.data(), &[0x3C]);
+        let result = coins.parse_response(&reply).unwrap();
+        assert_eq!(result.event_counter, 2);
+        assert_eq!(result.events[..], [CoinEvent::new(4, 0)]);
+
+        let bills = ReadEncryptedEventsCommand::<_, BillValidatorPollResult>::with_challenge(
+            &cipher, 0x3C, 0,
+        );
+        let result = bills.parse_response(&reply).unwrap();
+        assert_eq!(result.events.len(), 2);
+        assert!(matches!(result.events[0], BillEvent::Credit(4)));
+
+        assert_eq!(
+            ReadEncryptedEventsCommand::<_, CoinAcceptorPollResult>::with_challenge(
+                &cipher, 0x3D, 1
+            )
+            .parse_response(&reply),
+            Err(ParseResponseError::ParseError(
+                "encrypted events challenge mismatch"
+            ))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hopper-encryption")]
+    fn encrypted_monetary_id_is_checked_against_the_request() {
+        use cc_talk_core::cc_talk::{DesPeripheralCipher, HopperDesKey, MonetaryCode};
+
+        let cipher = DesPeripheralCipher::new(HopperDesKey::new([
+            0x13, 0x34, 0x57, 0x79, 0x9B, 0xBC, 0xDF, 0xF1,
+        ]));
+        let id = MonetaryId {
+            position: 4,
+            code: MonetaryCode::Country(*b"EU"),
+            scaling_factor: 0,
+            decimal_places: 2,
+            value: 200,
+            issue_level: b'A',
+            issue_number: 1,
+        };
+        let reply = cipher.encrypt_monetary_id(&id, 0x21, 0);
+
+        let command = RequestEncryptedMonetaryIdCommand::with_challenge(&cipher, 4, 0x21);
+        assert_eq!(command.data(), &[4, 0x21]);
+        assert_eq!(command.parse_response(&reply), Ok(Some(id)));
+        assert_eq!(
+            RequestEncryptedMonetaryIdCommand::with_challenge(&cipher, 5, 0x21)
+                .parse_response(&reply),
+            Err(ParseResponseError::ParseError(
+                "encrypted monetary id position mismatch"
+            ))
+        );
+    }
+
+    #[test]
+    fn bill_position_is_parsed_into_a_mask() {
+        let command = RequestBillPositionCommand::<2>::new("EU");
+        assert_eq!(command.data(), b"EU");
+
+        let mask = command.parse_response(&[0b0000_0110]).unwrap();
+        assert_eq!(mask.len(), 8);
+        assert!(mask.get_bit(1).unwrap() && mask.get_bit(2).unwrap());
+        assert_eq!(mask.count_ones(), 2);
+
+        let mask = command.parse_response(&[0, 0b1000_0000]).unwrap();
+        assert_eq!(mask.len(), 16);
+        assert!(mask.get_bit(15).unwrap());
+
+        assert_eq!(
+            command.parse_response(&[]),
+            Err(ParseResponseError::DataLengthMismatch(2, 0))
+        );
+        assert_eq!(
+            command.parse_response(&[0, 0, 1]),
+            Err(ParseResponseError::DataLengthMismatch(2, 3))
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use cc_talk_core::cc_talk::{Address, Header};
+use cc_talk_core::cc_talk::{BusAddress, BusAddressError, Header};
 
 use super::super::command::{Command, ParseResponseError};
 
@@ -6,9 +6,10 @@ use super::super::command::{Command, ParseResponseError};
 ///
 /// Your transport should be able to handle response with ~3ms space between packets.
 /// And will receive as many response as there are devices connected to the bus up to 255 devices.
+#[derive(Debug)]
 pub struct AddressPollCommand;
 impl Command for AddressPollCommand {
-    type Response = u8;
+    type Response = BusAddress;
 
     fn header(&self) -> Header {
         Header::AddressPoll
@@ -29,14 +30,15 @@ impl Command for AddressPollCommand {
                 response_payload.len(),
             ));
         }
-        Ok(response_payload[0])
+        Ok(BusAddress::new(response_payload[0]))
     }
 }
 
 /// Address clash is a MDCES command.
+#[derive(Debug)]
 pub struct AddressClashCommand;
 impl Command for AddressClashCommand {
-    type Response = u8;
+    type Response = BusAddress;
 
     fn header(&self) -> Header {
         Header::AddressClash
@@ -57,24 +59,28 @@ impl Command for AddressClashCommand {
                 response_payload.len(),
             ));
         }
-        Ok(response_payload[0])
+        Ok(BusAddress::new(response_payload[0]))
     }
 }
 
 /// Address change is a MDCES command.
+#[derive(Debug)]
 pub struct AddressChangeCommand {
     buffer: [u8; 1],
 }
 impl AddressChangeCommand {
     /// Creates a new address change command.
-    /// If a [Address::SingleAndRange] is provided, the single address will be used.
-    pub fn new(new_address: Address) -> Self {
-        AddressChangeCommand {
-            buffer: [match new_address {
-                Address::SingleAndRange(addr, _) => addr,
-                Address::Single(addr) => addr,
-            }; 1],
-        }
+    ///
+    /// Use [BusAddress::default_for] to move a device back to its category default address.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the new address is the broadcast or host address.
+    pub fn new(new_address: BusAddress) -> Result<Self, BusAddressError> {
+        let new_address = BusAddress::peripheral(new_address.get())?;
+        Ok(AddressChangeCommand {
+            buffer: [new_address.get()],
+        })
     }
 }
 impl Command for AddressChangeCommand {
@@ -104,6 +110,7 @@ impl Command for AddressChangeCommand {
 }
 
 /// Address random is a MDCES command.
+#[derive(Debug)]
 pub struct AddressRandomCommand;
 impl Command for AddressRandomCommand {
     type Response = ();
@@ -130,3 +137,32 @@ impl Command for AddressRandomCommand {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn address_poll_returns_bus_address() {
+        let cmd = AddressPollCommand;
+        assert_eq!(cmd.header(), Header::AddressPoll);
+        assert_eq!(cmd.parse_response(&[3]), Ok(BusAddress::HOPPER));
+        assert!(cmd.parse_response(&[]).is_err());
+    }
+
+    #[test]
+    fn address_change_rejects_reserved_addresses() {
+        assert_eq!(
+            AddressChangeCommand::new(BusAddress::BROADCAST).err(),
+            Some(BusAddressError::Reserved(0))
+        );
+        assert_eq!(
+            AddressChangeCommand::new(BusAddress::HOST).err(),
+            Some(BusAddressError::Reserved(1))
+        );
+
+        let cmd = AddressChangeCommand::new(BusAddress::new(12)).unwrap();
+        assert_eq!(cmd.header(), Header::AddressChange);
+        assert_eq!(cmd.data(), &[12]);
+    }
+}
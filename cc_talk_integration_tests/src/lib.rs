@@ -0,0 +1,202 @@
+//! Harness booting the emulated ccTalk bus of `cc_talk_emulator` behind the tokio transport.
+//!
+//! Every test gets its own bus and socket, tests do not share state and can run in parallel.
+//! The emulated devices answer instantly and deterministically, a flow always produces the
+//! same frames and the same results. [`Machine::in_memory_with`] skips the socket and the
+//! transport, the drivers talk to the emulated devices directly.
+
+use std::time::Duration;
+
+use cc_talk_core::cc_talk::{Category, ChecksumType, Device};
+use cc_talk_emulator::{MockBus, MockControl, Scenario};
+use cc_talk_tokio_host::{
+    device::{bill_validator::BillValidator, coin_validator::CoinValidator, payout::PayoutDevice},
+    transport::{
+        retry::RetryConfig,
+        tokio_transport::{CcTalkTokioTransport, TransportMessage},
+    },
+};
+use tempfile::TempDir;
+use tokio::{net::UnixListener, sync::mpsc, task::JoinHandle};
+
+/// Address of the coin acceptor of [`STANDARD_MACHINE`].
+pub const ACCEPTOR_ADDRESS: u8 = 2;
+/// Address of the 1 EUR hopper of [`STANDARD_MACHINE`].
+pub const EURO_HOPPER_ADDRESS: u8 = 3;
+/// Address of the 20 cent hopper of [`STANDARD_MACHINE`].
+pub const CENT_HOPPER_ADDRESS: u8 = 4;
+/// Address of the bill validator of [`STANDARD_MACHINE`].
+pub const VALIDATOR_ADDRESS: u8 = 40;
+
+/// Machine with 1 coin acceptor, 2 hoppers and 1 bill validator.
+pub const STANDARD_MACHINE: &str = r#"
+[[selector]]
+address = 2
+product_code = "SR5"
+serial_number = 1001
+coins = ["EU010A", "EU020A", "EU050A", "EU100A", "EU200A"]
+credits = [4, 3, 5]
+
+[[hopper]]
+address = 3
+product_code = "SCH2"
+serial_number = 3003
+coin = "EU100A"
+coins = 10
+
+[[hopper]]
+address = 4
+product_code = "SCH2"
+serial_number = 4004
+coin = "EU020A"
+coins = 50
+
+[[validator]]
+address = 40
+product_code = "NV9"
+serial_number = 4040
+bills = ["EU0005A", "EU0010A", "EU0020A"]
+credits = [1, 2]
+"#;
+
+const TRANSPORT_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Emulated bus and the transport talking to it, both are stopped on drop.
+pub struct Machine {
+    sender: mpsc::Sender<TransportMessage>,
+    control: MockControl,
+    bus: JoinHandle<()>,
+    transport: Option<JoinHandle<()>>,
+    _socket_dir: Option<TempDir>,
+}
+
+impl Machine {
+    /// Boots [`STANDARD_MACHINE`].
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bus cannot be started.
+    #[must_use]
+    pub fn standard() -> Self {
+        Self::boot_with(|_| {})
+    }
+
+    /// Boots [`STANDARD_MACHINE`] after `configure` adjusted it, e.g. to inject faults.
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bus cannot be started.
+    pub fn boot_with(configure: impl FnOnce(&mut Scenario)) -> Self {
+        let mut scenario = Scenario::parse(STANDARD_MACHINE).expect("should be a valid scenario");
+        configure(&mut scenario);
+        let bus = MockBus::new(&scenario, true).expect("should create the emulated devices");
+        let control = bus.control();
+
+        let socket_dir = tempfile::tempdir().expect("should create a socket directory");
+        let socket_path = socket_dir.path().join("cctalk.sock");
+        let listener = UnixListener::bind(&socket_path).expect("should bind the bus socket");
+        let bus = tokio::spawn(bus.serve(listener));
+
+        let (sender, receiver) = mpsc::channel(8);
+        let transport = CcTalkTokioTransport::new(
+            receiver,
+            socket_path.to_string_lossy().to_string(),
+            TRANSPORT_TIMEOUT,
+            Duration::ZERO,
+            RetryConfig {
+                retry_delay: Duration::ZERO,
+                ..RetryConfig::default()
+            },
+            true,
+        );
+        let transport = tokio::spawn(async move {
+            transport.run().await.expect("transport should run");
+        });
+
+        Self {
+            sender,
+            control,
+            bus,
+            transport: Some(transport),
+            _socket_dir: Some(socket_dir),
+        }
+    }
+
+    /// Boots [`STANDARD_MACHINE`] after `configure` adjusted it, on a bus answering the
+    /// drivers in memory.
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bus cannot be started.
+    pub fn in_memory_with(configure: impl FnOnce(&mut Scenario)) -> Self {
+        let mut scenario = Scenario::parse(STANDARD_MACHINE).expect("should be a valid scenario");
+        configure(&mut scenario);
+        let bus = MockBus::new(&scenario, false).expect("should create the emulated devices");
+        let control = bus.control();
+
+        let (sender, receiver) = mpsc::channel(8);
+        let bus = tokio::spawn(bus.serve_channel(receiver));
+
+        Self {
+            sender,
+            control,
+            bus,
+            transport: None,
+            _socket_dir: None,
+        }
+    }
+
+    #[must_use]
+    pub fn sender(&self) -> mpsc::Sender<TransportMessage> {
+        self.sender.clone()
+    }
+
+    /// Inserts coins and bills, jams and power cycles the emulated devices.
+    #[must_use]
+    pub const fn control(&self) -> &MockControl {
+        &self.control
+    }
+
+    #[must_use]
+    pub fn coin_acceptor(&self) -> CoinValidator {
+        CoinValidator::new(
+            Device::new(ACCEPTOR_ADDRESS, Category::CoinAcceptor, ChecksumType::Crc8),
+            self.sender(),
+        )
+    }
+
+    #[must_use]
+    pub fn hopper(&self, address: u8) -> PayoutDevice {
+        PayoutDevice::new(
+            Device::new(address, Category::Payout, ChecksumType::Crc8),
+            self.sender(),
+        )
+    }
+
+    #[must_use]
+    pub fn bill_validator(&self) -> BillValidator {
+        BillValidator::new(
+            Device::new(
+                VALIDATOR_ADDRESS,
+                Category::BillValidator,
+                ChecksumType::Crc8,
+            ),
+            self.sender(),
+        )
+    }
+}
+
+impl Drop for Machine {
+    fn drop(&mut self) {
+        if let Some(transport) = &self.transport {
+            transport.abort();
+        }
+        self.bus.abort();
+    }
+}
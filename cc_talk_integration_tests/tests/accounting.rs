@@ -0,0 +1,311 @@
+//! Randomized machine sessions checking the cash accounting invariants.
+//!
+//! Every case runs a sequence of coin insertions, payouts, refills, jams and power cycles
+//! drawn from its seed against the emulated machine. A failing case reports its seed, replay
+//! it alone with `ACCOUNTING_SEED=<seed>`.
+
+use std::time::Duration;
+
+use cc_talk_integration_tests::{
+    ACCEPTOR_ADDRESS, CENT_HOPPER_ADDRESS, EURO_HOPPER_ADDRESS, Machine,
+};
+use cc_talk_tokio_host::device::{
+    currency_acceptor_pool::CurrencyAcceptorPool,
+    payout_pool::{DispenseProgress, PayoutJournal, PayoutPool},
+};
+
+const CASES: u64 = 16;
+const STEPS: usize = 40;
+/// Values of the coin positions of the standard coin acceptor.
+const COIN_VALUES: [u32; 5] = [10, 20, 50, 100, 200];
+const HOPPERS: [(u8, u32); 2] = [(EURO_HOPPER_ADDRESS, 100), (CENT_HOPPER_ADDRESS, 20)];
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    /// Inserts a coin and polls its credit.
+    Insert {
+        position: u8,
+    },
+    /// Inserts a coin and power cycles the acceptor before its credit is polled.
+    InsertThenReset {
+        position: u8,
+    },
+    /// Pays out part of the credit.
+    Payout {
+        value: u32,
+    },
+    Refill {
+        address: u8,
+        coins: u32,
+    },
+    Jam {
+        address: u8,
+    },
+    PowerCycle {
+        address: u8,
+    },
+}
+
+/// Draws the next step, `acceptor_counted` tells whether the acceptor reported an event since
+/// its last reset.
+///
+/// The host sees a reset as the event counter of the acceptor returning to 0, a reset while
+/// the counter is still 0 goes unnoticed and the acceptor silently keeps its coins inhibited.
+/// The acceptor is only reset once it counted an event.
+fn draw(rng: &mut fastrand::Rng, balance: u32, acceptor_counted: bool) -> Step {
+    let hopper = HOPPERS[rng.usize(..HOPPERS.len())].0;
+    match rng.u8(..20) {
+        8 if acceptor_counted => Step::InsertThenReset {
+            position: rng.u8(1..=5),
+        },
+        9..=13 if balance >= 10 => Step::Payout {
+            value: rng.u32(1..=balance / 10) * 10,
+        },
+        14 | 15 => Step::Refill {
+            address: hopper,
+            coins: rng.u32(1..=20),
+        },
+        16 | 17 => Step::Jam { address: hopper },
+        18 | 19 if acceptor_counted && rng.bool() => Step::PowerCycle {
+            address: ACCEPTOR_ADDRESS,
+        },
+        18 | 19 => Step::PowerCycle { address: hopper },
+        _ => Step::Insert {
+            position: rng.u8(1..=5),
+        },
+    }
+}
+
+fn dispense_count(machine: &Machine, address: u8) -> u32 {
+    machine
+        .control()
+        .hopper(address)
+        .expect("should be a hopper")
+        .dispense_count()
+}
+
+fn float(machine: &Machine) -> u32 {
+    HOPPERS
+        .iter()
+        .map(|&(address, value)| {
+            let hopper = machine
+                .control()
+                .hopper(address)
+                .expect("should be a hopper");
+            hopper.coins() * value
+        })
+        .sum()
+}
+
+/// What the pools reported so far, checked against what the emulated devices did.
+#[derive(Default)]
+struct Books {
+    /// Credits reported by the acceptor pool.
+    credits: Vec<u32>,
+    /// Progress returned by the payout pool for every payout.
+    payouts: Vec<DispenseProgress>,
+    balance: u32,
+    /// Dispense count of every hopper when the session started.
+    initial_counts: [u32; HOPPERS.len()],
+    initial_float: u32,
+    refilled: u32,
+}
+
+impl Books {
+    fn open(machine: &Machine) -> Self {
+        Self {
+            initial_counts: HOPPERS.map(|(address, _)| dispense_count(machine, address)),
+            initial_float: float(machine),
+            ..Self::default()
+        }
+    }
+
+    fn dispensed(&self) -> u32 {
+        self.payouts.iter().map(|progress| progress.dispensed).sum()
+    }
+
+    fn check(&self, machine: &Machine, context: &str) {
+        let accepted: u32 = machine
+            .control()
+            .accepted_coins(ACCEPTOR_ADDRESS)
+            .expect("should be a coin acceptor")
+            .iter()
+            .map(|&position| COIN_VALUES[usize::from(position) - 1])
+            .sum();
+        assert_eq!(
+            self.credits.iter().sum::<u32>(),
+            accepted,
+            "{context}: every coin the acceptor accepted is credited once"
+        );
+        for (&(address, value), &initial) in HOPPERS.iter().zip(&self.initial_counts) {
+            let reported = self
+                .payouts
+                .iter()
+                .flat_map(|progress| &progress.coins_dispensed)
+                .filter(|&&coin| coin == value)
+                .count();
+            let paid = dispense_count(machine, address).wrapping_sub(initial);
+            assert_eq!(
+                u32::try_from(reported).expect("should fit"),
+                paid,
+                "{context}: the pool reports the coins hopper {address} paid"
+            );
+        }
+        assert_eq!(
+            float(machine),
+            self.initial_float + self.refilled - self.dispensed(),
+            "{context}: the hoppers hold what the pool did not report as paid"
+        );
+    }
+
+    fn check_journal(&self, journal: &PayoutJournal, seed: u64) {
+        let journaled: Vec<(u32, u32, Vec<u32>)> = journal
+            .records()
+            .into_iter()
+            .map(|record| {
+                (
+                    record.progress.requested,
+                    record.progress.dispensed,
+                    record.progress.coins_dispensed,
+                )
+            })
+            .collect();
+        let returned: Vec<(u32, u32, Vec<u32>)> = self
+            .payouts
+            .iter()
+            .map(|progress| {
+                (
+                    progress.requested,
+                    progress.dispensed,
+                    progress.coins_dispensed.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            journaled, returned,
+            "seed {seed}: the journal records the payouts the pool returned"
+        );
+    }
+}
+
+async fn pools(machine: &Machine) -> (CurrencyAcceptorPool, PayoutPool, PayoutJournal) {
+    // A power cycled acceptor inhibits all its coins.
+    let acceptor = machine.coin_acceptor().with_reset_handler(|acceptor| {
+        Box::pin(async move { acceptor.set_all_coin_inhibits(false).await })
+    });
+    let acceptors = CurrencyAcceptorPool::builder()
+        .add_coin_validator(acceptor)
+        .with_polling_interval(Duration::from_millis(1))
+        .build_and_initialize()
+        .await
+        .expect("should initialize the acceptors");
+    acceptors.enable().await.expect("should enable");
+    let journal = PayoutJournal::new(STEPS);
+    let mut builder = PayoutPool::builder()
+        .polling_interval(Duration::from_millis(1))
+        .with_journal(journal.clone());
+    for (address, value) in HOPPERS {
+        builder = builder.add_hopper(machine.hopper(address), value);
+    }
+    let payouts = builder
+        .build_and_initialize()
+        .await
+        .expect("should initialize the hoppers");
+    (acceptors, payouts, journal)
+}
+
+async fn run_case(seed: u64) {
+    let mut rng = fastrand::Rng::with_seed(seed);
+    let machine = Machine::in_memory_with(|scenario| scenario.selectors[0].credits.clear());
+    let (acceptors, payouts, journal) = pools(&machine).await;
+
+    let mut books = Books::open(&machine);
+    let mut acceptor_counted = false;
+
+    for index in 0..STEPS {
+        let step = draw(&mut rng, books.balance, acceptor_counted);
+        let context = format!("seed {seed}, step {index}: {step:?}");
+        match step {
+            Step::Insert { position } | Step::InsertThenReset { position } => {
+                machine
+                    .control()
+                    .insert_coin(ACCEPTOR_ADDRESS, position)
+                    .expect("should insert");
+                acceptor_counted = true;
+                if matches!(step, Step::InsertThenReset { .. }) {
+                    acceptor_counted = false;
+                    machine
+                        .control()
+                        .power_cycle(ACCEPTOR_ADDRESS)
+                        .expect("should power cycle");
+                }
+                let result = acceptors.poll().await;
+                for credit in result.credits {
+                    books.balance += credit.value;
+                    books.credits.push(credit.value);
+                }
+                acceptors.enable().await.expect("should enable again");
+            }
+            Step::Payout { value } => {
+                let progress = payouts.payout(value).await.expect("should pay out");
+                assert_eq!(progress.requested, value, "{context}");
+                assert_eq!(
+                    progress.dispensed + progress.remaining,
+                    value,
+                    "{context}: paid and unpaid make up the request"
+                );
+                assert_eq!(
+                    progress.coins_dispensed.iter().sum::<u32>(),
+                    progress.dispensed,
+                    "{context}"
+                );
+                books.balance = books
+                    .balance
+                    .checked_sub(progress.dispensed)
+                    .unwrap_or_else(|| panic!("{context}: the balance went negative"));
+                books.payouts.push(progress);
+            }
+            Step::Refill { address, coins } => {
+                let value = HOPPERS
+                    .iter()
+                    .find(|&&(hopper, _)| hopper == address)
+                    .map_or(0, |&(_, value)| value);
+                books.refilled += coins * value;
+                machine
+                    .control()
+                    .refill(address, coins)
+                    .expect("should refill");
+            }
+            Step::Jam { address } => machine.control().jam(address).expect("should jam"),
+            Step::PowerCycle { address } => {
+                machine
+                    .control()
+                    .power_cycle(address)
+                    .expect("should power cycle");
+                if address == ACCEPTOR_ADDRESS {
+                    acceptor_counted = false;
+                    acceptors.poll().await;
+                    acceptors.enable().await.expect("should enable again");
+                }
+            }
+        }
+
+        books.check(&machine, &context);
+    }
+
+    books.check_journal(&journal, seed);
+}
+
+#[tokio::test]
+async fn randomized_sessions_keep_the_books_balanced() {
+    if let Some(seed) = std::env::var("ACCOUNTING_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+    {
+        run_case(seed).await;
+        return;
+    }
+    for seed in 0..CASES {
+        run_case(seed).await;
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use cc_talk_integration_tests::Machine;
+use cc_talk_tokio_host::device::{
+    coin_validator::LimitEnforcement,
+    currency_acceptor_pool::{
+        BillRoutingMode, CurrencyAcceptorPool, DeviceId, SessionConfig, SessionEnd,
+    },
+};
+use tokio::sync::mpsc;
+
+async fn acceptor_pool(machine: &Machine) -> CurrencyAcceptorPool {
+    CurrencyAcceptorPool::builder()
+        .add_coin_validator(machine.coin_acceptor())
+        .add_bill_validator(machine.bill_validator())
+        .with_bill_routing_mode(BillRoutingMode::AutoStack)
+        .with_polling_interval(Duration::from_millis(10))
+        .build_and_initialize()
+        .await
+        .expect("should initialize")
+}
+
+#[tokio::test]
+async fn credits_are_valued_per_device() {
+    let machine = Machine::standard();
+    let pool = acceptor_pool(&machine).await;
+
+    assert!(
+        pool.poll().await.is_empty(),
+        "inhibited devices do not credit"
+    );
+    pool.enable().await.expect("should enable");
+
+    let mut credits = Vec::new();
+    for _ in 0..4 {
+        let result = pool.poll().await;
+        assert!(!result.has_errors(), "{:?}", result.errors);
+        credits.extend(
+            result
+                .credits
+                .iter()
+                .map(|credit| (credit.source, credit.position, credit.value)),
+        );
+    }
+
+    assert_eq!(
+        credits,
+        vec![
+            (DeviceId::CoinValidator(0), 4, 100),
+            (DeviceId::BillValidator(0), 1, 500),
+            (DeviceId::CoinValidator(0), 3, 50),
+            (DeviceId::BillValidator(0), 2, 1000),
+            (DeviceId::CoinValidator(0), 5, 200),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn payment_is_accepted_until_the_target_is_reached() {
+    let machine = Machine::standard();
+    let pool = acceptor_pool(&machine).await;
+
+    let payment = pool
+        .accept_payment(1650, Duration::from_secs(5))
+        .await
+        .expect("should reach the target");
+
+    assert!(payment.target_reached);
+    assert_eq!(payment.total_received, 1650);
+    assert_eq!(payment.credits.len(), 4);
+    assert!(
+        machine
+            .coin_acceptor()
+            .is_master_inhibit_enabled()
+            .await
+            .expect("should read the master inhibit"),
+        "the pool is disabled once the payment is done"
+    );
+}
+
+#[tokio::test]
+async fn value_limit_is_tracked_by_the_host_without_header_223() {
+    let machine = Machine::standard();
+    let acceptor = machine.coin_acceptor();
+
+    let limit = acceptor
+        .accept_up_to_value(150)
+        .await
+        .expect("should limit the value")
+        .expect("should have a limit");
+    assert_eq!(limit.enforcement, LimitEnforcement::Host);
+    assert_eq!(acceptor.is_master_inhibit_disabled().await, Ok(true));
+
+    acceptor.poll().await.expect("should credit");
+    assert_eq!(acceptor.value_limit().map(|l| l.remaining()), Some(50));
+    assert_eq!(acceptor.is_master_inhibit_disabled().await, Ok(true));
+
+    acceptor.poll().await.expect("should credit");
+    assert!(acceptor.value_limit().is_some_and(|l| l.is_reached()));
+    assert_eq!(acceptor.is_master_inhibit_enabled().await, Ok(true));
+}
+
+#[tokio::test]
+async fn tokens_are_valued_with_the_configured_values() {
+    let machine = Machine::boot_with(|scenario| {
+        let selector = &mut scenario.selectors[0];
+        selector.coins.push("TK001A".to_string());
+        selector.credits = vec![6, 4];
+    });
+    let acceptor = machine.coin_acceptor().with_token_values([(6, 150)]);
+
+    let table = acceptor.coin_table().await.expect("should read the table");
+    assert_eq!(table.token_positions().collect::<Vec<_>>(), vec![6]);
+    assert_eq!(table.coin_positions().count(), 5);
+    assert_eq!(table.value(6), Some(150));
+
+    acceptor
+        .accept_up_to_value(200)
+        .await
+        .expect("should limit the value")
+        .expect("should have a limit");
+    acceptor.poll().await.expect("should credit the token");
+    assert_eq!(acceptor.value_limit().map(|l| l.remaining()), Some(50));
+
+    acceptor.poll().await.expect("should credit");
+    assert!(acceptor.value_limit().is_some_and(|l| l.is_reached()));
+}
+
+#[tokio::test]
+async fn session_ends_on_the_target_and_inhibits_the_devices() {
+    let machine = Machine::standard();
+    let pool = acceptor_pool(&machine).await;
+    let (progress_tx, mut progress_rx) = mpsc::channel(8);
+
+    let session = pool.start_session(
+        SessionConfig::new(Duration::from_secs(5))
+            .with_target(600)
+            .with_progress(progress_tx),
+    );
+    let report = session.finish().await.expect("should run the session");
+
+    assert_eq!(report.end, SessionEnd::TargetReached);
+    assert_eq!(report.total_received, 600);
+    let progress = progress_rx.recv().await.expect("should report the credits");
+    assert_eq!((progress.total_received, progress.remaining), (100, 500));
+    assert!(
+        machine
+            .coin_acceptor()
+            .is_master_inhibit_enabled()
+            .await
+            .expect("should read the master inhibit"),
+        "the devices are inhibited once the session ends"
+    );
+}
+
+#[tokio::test]
+async fn session_without_target_runs_until_it_expires_or_is_cancelled() {
+    let machine = Machine::standard();
+    let pool = acceptor_pool(&machine).await;
+
+    let session = pool.start_session(SessionConfig::new(Duration::from_millis(200)));
+    session.extend(Duration::from_millis(100));
+    assert!(session.time_left() > Duration::from_millis(200));
+    let report = session.finish().await.expect("should run the session");
+
+    assert_eq!(report.end, SessionEnd::Expired);
+    assert!(report.duration >= Duration::from_millis(300));
+    assert_eq!(
+        report.total_received, 1850,
+        "every scripted credit is inserted"
+    );
+    assert_eq!(report.credits.len(), 5);
+
+    let session = pool.start_session(SessionConfig::new(Duration::from_secs(5)));
+    session.cancel();
+    let report = session.finish().await.expect("should run the session");
+    assert_eq!(report.end, SessionEnd::Cancelled);
+}
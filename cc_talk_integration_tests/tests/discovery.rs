@@ -0,0 +1,86 @@
+use cc_talk_core::cc_talk::{Category, ChecksumType, Manufacturer, SerialCode};
+use cc_talk_integration_tests::{
+    ACCEPTOR_ADDRESS, CENT_HOPPER_ADDRESS, EURO_HOPPER_ADDRESS, Machine, VALIDATOR_ADDRESS,
+};
+use cc_talk_tokio_host::device::{
+    base::DeviceCommon,
+    enumeration::enumerate,
+    manager::DeviceManager,
+    topology::{BusTopology, DeviceHealth},
+};
+
+#[tokio::test]
+async fn address_poll_discovers_every_device() {
+    let machine = Machine::standard();
+    let mut manager = DeviceManager::new(machine.sender());
+
+    let devices = manager.discover().await.expect("should poll the bus");
+
+    let topology: Vec<_> = devices
+        .iter()
+        .map(|device| (device.address, device.category.clone()))
+        .collect();
+    assert_eq!(
+        topology,
+        vec![
+            (ACCEPTOR_ADDRESS, Category::CoinAcceptor),
+            (EURO_HOPPER_ADDRESS, Category::Payout),
+            (CENT_HOPPER_ADDRESS, Category::Payout),
+            (VALIDATOR_ADDRESS, Category::BillValidator),
+        ]
+    );
+    let hopper = manager
+        .device(EURO_HOPPER_ADDRESS)
+        .expect("should register the hopper");
+    assert_eq!(
+        hopper.manufacturer,
+        Some(Manufacturer::InnovativeTechnology)
+    );
+    assert_eq!(
+        hopper.serial.as_ref().map(SerialCode::as_number),
+        Some(3003)
+    );
+
+    assert_eq!(manager.hoppers().count(), 2);
+    assert_eq!(manager.changers().count(), 0);
+    let acceptor = manager
+        .coin_acceptors()
+        .next()
+        .expect("should register the coin acceptor");
+    acceptor.simple_poll().await.expect("should answer");
+    let validator = manager
+        .bill_validators()
+        .next()
+        .expect("should register the bill validator");
+    assert_eq!(validator.get_device().address(), VALIDATOR_ADDRESS);
+}
+
+#[tokio::test]
+async fn enumerated_bus_is_exported() {
+    let machine = Machine::standard();
+    let addresses = [
+        ACCEPTOR_ADDRESS,
+        EURO_HOPPER_ADDRESS,
+        CENT_HOPPER_ADDRESS,
+        VALIDATOR_ADDRESS,
+    ];
+
+    let enumeration = enumerate(&machine.sender(), addresses, ChecksumType::Crc8).await;
+    let topology = BusTopology::collect(&machine.sender(), &enumeration, ChecksumType::Crc8).await;
+
+    assert_eq!(topology.devices.len(), 4);
+    assert!(topology.duplicates.is_empty());
+    let hopper = &topology.devices[1];
+    assert_eq!(hopper.address, EURO_HOPPER_ADDRESS);
+    assert_eq!(hopper.health, DeviceHealth::Ok);
+    assert_eq!(
+        hopper.manufacturer,
+        Some(Manufacturer::InnovativeTechnology)
+    );
+
+    let json = serde_json::to_value(&topology).expect("should serialize");
+    assert_eq!(json["devices"][1]["address"], EURO_HOPPER_ADDRESS);
+    assert_eq!(json["devices"][1]["category"], "Payout");
+    let dot = topology.to_dot();
+    assert!(dot.contains(&format!("host -> d{VALIDATOR_ADDRESS};")));
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use cc_talk_integration_tests::Machine;
+use cc_talk_tokio_host::device::currency_acceptor_pool::{
+    BillRoutingMode, CurrencyAcceptorPool, DeviceId, PoolPollResult,
+};
+
+async fn poll_enabled(pool: &CurrencyAcceptorPool) -> PoolPollResult {
+    let result = pool.poll().await;
+    assert!(!result.has_errors(), "{:?}", result.errors);
+    result
+}
+
+#[tokio::test]
+async fn escrowed_bills_are_credited_only_once_stacked() {
+    let machine = Machine::standard();
+    let pool = CurrencyAcceptorPool::builder()
+        .add_bill_validator(machine.bill_validator())
+        .with_bill_routing_mode(BillRoutingMode::Manual)
+        .with_polling_interval(Duration::from_millis(10))
+        .build_and_initialize()
+        .await
+        .expect("should initialize");
+    pool.enable().await.expect("should enable");
+
+    let result = poll_enabled(&pool).await;
+    assert!(result.credits.is_empty());
+    let [pending] = result.pending_bills.as_slice() else {
+        panic!("expected one pending bill, got {:?}", result.pending_bills);
+    };
+    assert_eq!(
+        (pending.source, pending.bill_type, pending.value),
+        (DeviceId::BillValidator(0), 1, 500)
+    );
+
+    // The validator holds the bill until it is routed.
+    assert!(poll_enabled(&pool).await.is_empty());
+
+    pool.route_pending_bill(pending, true)
+        .await
+        .expect("should stack the bill");
+    // The next bill is inserted as soon as the first one is stacked.
+    let result = poll_enabled(&pool).await;
+    assert_eq!(result.total_received, 500);
+    let [pending] = result.pending_bills.as_slice() else {
+        panic!("expected one pending bill, got {:?}", result.pending_bills);
+    };
+    assert_eq!((pending.bill_type, pending.value), (2, 1000));
+
+    pool.route_pending_bill(pending, false)
+        .await
+        .expect("should return the bill");
+    let result = poll_enabled(&pool).await;
+    assert_eq!(result.total_received, 0);
+    assert!(result.pending_bills.is_empty());
+}
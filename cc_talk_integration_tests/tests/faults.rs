@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use cc_talk_core::cc_talk::{Category, ChecksumType, Device, HopperFlag};
+use cc_talk_integration_tests::{CENT_HOPPER_ADDRESS, EURO_HOPPER_ADDRESS, Machine};
+use cc_talk_tokio_host::device::{
+    base::CommandError,
+    currency_acceptor_pool::{CurrencyAcceptorPool, DeviceId},
+    payout::PayoutDevice,
+    payout_pool::{PayoutEvent, PayoutJournal, PayoutOutcome, PayoutPool},
+    triage::UnresponsiveCause,
+};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn jammed_hopper_is_replaced_by_the_other_hopper() {
+    let machine = Machine::boot_with(|scenario| scenario.hoppers[0].jam_after = Some(1));
+    let journal = PayoutJournal::default();
+    let pool = PayoutPool::builder()
+        .add_hopper(machine.hopper(EURO_HOPPER_ADDRESS), 100)
+        .add_hopper(machine.hopper(CENT_HOPPER_ADDRESS), 20)
+        .polling_interval(Duration::from_millis(10))
+        .with_journal(journal.clone())
+        .build_and_initialize()
+        .await
+        .expect("should initialize");
+
+    let (event_tx, mut event_rx) = mpsc::channel(64);
+    let progress = pool
+        .payout_with_events(300, event_tx)
+        .await
+        .expect("should pay out");
+
+    assert_eq!((progress.dispensed, progress.remaining), (300, 0));
+    assert_eq!(progress.empty_hoppers, vec![EURO_HOPPER_ADDRESS]);
+    assert_eq!(progress.coins_count(), 11);
+    assert_eq!(
+        journal.last().map(|record| record.outcome),
+        Some(PayoutOutcome::Completed)
+    );
+
+    let mut rebalanced = None;
+    while let Ok(event) = event_rx.try_recv() {
+        if let PayoutEvent::PlanRebalanced {
+            exhausted_hopper,
+            remaining_value,
+            new_plan,
+        } = event
+        {
+            rebalanced = Some((exhausted_hopper, remaining_value, new_plan));
+        }
+    }
+    assert_eq!(
+        rebalanced,
+        Some((EURO_HOPPER_ADDRESS, 200, vec![(CENT_HOPPER_ADDRESS, 10)]))
+    );
+
+    let flags = machine
+        .hopper(EURO_HOPPER_ADDRESS)
+        .self_test()
+        .await
+        .expect("should run the self test");
+    assert!(flags.contains(&HopperFlag::AbsoluteMaximumCurrentExceeded));
+}
+
+#[tokio::test]
+async fn missing_validator_does_not_block_coin_credits() {
+    let machine = Machine::boot_with(|scenario| scenario.validators.clear());
+    let pool = CurrencyAcceptorPool::builder()
+        .add_coin_validator(machine.coin_acceptor())
+        .add_bill_validator(machine.bill_validator())
+        .with_polling_interval(Duration::from_millis(10))
+        .build_and_initialize()
+        .await
+        .expect("should initialize with the coin acceptor alone");
+    pool.enable()
+        .await
+        .expect("should enable the coin acceptor");
+
+    let result = pool.poll().await;
+    assert_eq!(result.total_received, 100);
+    let [error] = result.errors.as_slice() else {
+        panic!("expected one error, got {:?}", result.errors);
+    };
+    assert_eq!(error.source, DeviceId::BillValidator(0));
+    assert_eq!(error.error, CommandError::Timeout);
+}
+
+#[tokio::test]
+async fn hopper_at_the_wrong_address_is_diagnosed() {
+    let machine = Machine::boot_with(|scenario| scenario.hoppers.truncate(1));
+    let hopper = PayoutDevice::new(
+        Device::new(CENT_HOPPER_ADDRESS, Category::Payout, ChecksumType::Crc8),
+        machine.sender(),
+    )
+    .with_timeout_triage();
+
+    assert_eq!(
+        hopper.get_payout_status().await,
+        Err(CommandError::Unresponsive {
+            address: CENT_HOPPER_ADDRESS,
+            cause: UnresponsiveCause::WrongAddress {
+                answering_address: EURO_HOPPER_ADDRESS
+            },
+        })
+    );
+}
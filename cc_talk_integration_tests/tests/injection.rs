@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use cc_talk_core::cc_talk::{CoinAcceptorError, CoinEvent, HopperFlag, SorterPath};
+use cc_talk_integration_tests::{
+    ACCEPTOR_ADDRESS, EURO_HOPPER_ADDRESS, Machine, VALIDATOR_ADDRESS,
+};
+use cc_talk_tokio_host::device::currency_acceptor_pool::{
+    BillRoutingMode, CreditRoute, CurrencyAcceptorPool, DeviceId, DriftCheck, StateDrift,
+};
+
+/// Standard machine whose acceptors only credit what the test inserts.
+fn idle_machine() -> Machine {
+    Machine::boot_with(|scenario| {
+        scenario.selectors[0].credits.clear();
+        scenario.validators[0].credits.clear();
+    })
+}
+
+#[tokio::test]
+async fn inserted_coins_and_bills_are_credited() {
+    let machine = idle_machine();
+    let pool = CurrencyAcceptorPool::builder()
+        .add_coin_validator(machine.coin_acceptor())
+        .add_bill_validator(machine.bill_validator())
+        .with_bill_routing_mode(BillRoutingMode::AutoStack)
+        .with_polling_interval(Duration::from_millis(10))
+        .build_and_initialize()
+        .await
+        .expect("should initialize");
+    pool.enable().await.expect("should enable");
+    assert!(pool.poll().await.credits.is_empty());
+
+    let control = machine.control();
+    control
+        .insert_coin(ACCEPTOR_ADDRESS, 5)
+        .expect("should insert a coin");
+    control
+        .insert_bill(VALIDATOR_ADDRESS, 3)
+        .expect("should insert a bill");
+
+    let result = pool.poll().await;
+    assert!(!result.has_errors(), "{:?}", result.errors);
+    let credits: Vec<_> = result
+        .credits
+        .iter()
+        .map(|credit| (credit.source, credit.position, credit.value))
+        .collect();
+    assert_eq!(
+        credits,
+        vec![
+            (DeviceId::CoinValidator(0), 5, 200),
+            (DeviceId::BillValidator(0), 3, 2000),
+        ]
+    );
+    let routes: Vec<_> = result
+        .credit_events
+        .iter()
+        .map(|event| event.route)
+        .collect();
+    assert_eq!(
+        routes,
+        vec![
+            CreditRoute::Sorter(SorterPath::Path(1)),
+            CreditRoute::Stacked
+        ]
+    );
+}
+
+#[tokio::test]
+async fn jams_and_power_cycles_reach_the_host() {
+    let machine = idle_machine();
+    let acceptor = machine.coin_acceptor();
+    let control = machine.control();
+
+    control.jam(ACCEPTOR_ADDRESS).expect("should jam");
+    let result = acceptor.poll().await.expect("should poll");
+    assert!(
+        result
+            .events
+            .contains(&CoinEvent::Error(CoinAcceptorError::CreditSensorTimeout))
+    );
+
+    acceptor
+        .disable_master_inhibit()
+        .await
+        .expect("should accept coins");
+    control
+        .power_cycle(ACCEPTOR_ADDRESS)
+        .expect("should power cycle");
+    let result = acceptor.poll().await.expect("should poll");
+    assert!(result.events.contains(&CoinEvent::Reset));
+    assert_eq!(acceptor.is_master_inhibit_enabled().await, Ok(true));
+
+    let hopper = machine.hopper(EURO_HOPPER_ADDRESS);
+    control.jam(EURO_HOPPER_ADDRESS).expect("should jam");
+    let flags = hopper.self_test().await.expect("should run the self test");
+    assert!(flags.contains(&HopperFlag::AbsoluteMaximumCurrentExceeded));
+    control
+        .power_cycle(EURO_HOPPER_ADDRESS)
+        .expect("should power cycle");
+    let flags = hopper.self_test().await.expect("should run the self test");
+    assert!(!flags.contains(&HopperFlag::AbsoluteMaximumCurrentExceeded));
+}
+
+#[tokio::test]
+async fn power_cycled_acceptors_are_detected_and_enabled_again() {
+    let machine = idle_machine();
+    let pool = CurrencyAcceptorPool::builder()
+        .add_coin_validator(machine.coin_acceptor())
+        .add_bill_validator(machine.bill_validator())
+        .with_drift_check(DriftCheck {
+            interval: Duration::ZERO,
+            auto_correct: true,
+        })
+        .build_and_initialize()
+        .await
+        .expect("should initialize");
+    pool.enable().await.expect("should enable");
+    assert!(pool.poll().await.state_drifts.is_empty());
+
+    machine
+        .control()
+        .power_cycle(ACCEPTOR_ADDRESS)
+        .expect("should power cycle");
+    let result = pool.poll().await;
+    assert_eq!(
+        result.state_drifts,
+        vec![StateDrift::new(
+            DeviceId::CoinValidator(0),
+            ACCEPTOR_ADDRESS,
+            false,
+            true
+        )]
+    );
+    assert_eq!(
+        machine.coin_acceptor().is_master_inhibit_enabled().await,
+        Ok(false)
+    );
+    assert!(pool.poll().await.state_drifts.is_empty());
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use cc_talk_integration_tests::{CENT_HOPPER_ADDRESS, EURO_HOPPER_ADDRESS, Machine};
+use cc_talk_tokio_host::device::machine::{self, MachineError};
+
+async fn kiosk(machine: &Machine) -> machine::Machine {
+    machine::Machine::builder()
+        .coin_acceptor(machine.coin_acceptor())
+        .bill_validator(machine.bill_validator())
+        .add_hopper(machine.hopper(EURO_HOPPER_ADDRESS), 100)
+        .add_hopper(machine.hopper(CENT_HOPPER_ADDRESS), 20)
+        .with_polling_interval(Duration::from_millis(10))
+        .build_and_initialize()
+        .await
+        .expect("should initialize")
+}
+
+#[tokio::test]
+async fn credits_are_paid_out_of_the_balance() {
+    let machine = Machine::standard();
+    let kiosk = kiosk(&machine).await;
+
+    kiosk.insert_credit_events().await;
+    assert_eq!(kiosk.balance(), 0, "inhibited acceptors do not credit");
+
+    kiosk.enable().await.expect("should enable");
+    kiosk.insert_credit_events().await;
+    kiosk.disable().await.expect("should disable");
+    assert_eq!(kiosk.balance(), 600);
+
+    assert!(matches!(
+        kiosk.payout(700).await,
+        Err(MachineError::InsufficientBalance {
+            requested: 700,
+            balance: 600
+        })
+    ));
+
+    let progress = kiosk.payout(240).await.expect("should pay out");
+    assert_eq!(progress.dispensed, 240);
+    assert_eq!(kiosk.balance(), 360);
+}
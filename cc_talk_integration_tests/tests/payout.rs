@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use cc_talk_integration_tests::{CENT_HOPPER_ADDRESS, EURO_HOPPER_ADDRESS, Machine};
+use cc_talk_tokio_host::device::payout_pool::{
+    PayoutEvent, PayoutJournal, PayoutOutcome, PayoutPool,
+};
+use tokio::sync::mpsc;
+
+async fn payout_pool(machine: &Machine, journal: PayoutJournal) -> PayoutPool {
+    PayoutPool::builder()
+        .add_hopper(machine.hopper(EURO_HOPPER_ADDRESS), 100)
+        .add_hopper(machine.hopper(CENT_HOPPER_ADDRESS), 20)
+        .polling_interval(Duration::from_millis(10))
+        .with_journal(journal)
+        .build_and_initialize()
+        .await
+        .expect("should initialize")
+}
+
+#[tokio::test]
+async fn payout_is_split_across_hoppers_and_journaled() {
+    let machine = Machine::standard();
+    let journal = PayoutJournal::default();
+    let pool = payout_pool(&machine, journal.clone()).await;
+
+    let progress = pool.payout(240).await.expect("should pay out");
+
+    assert!(progress.done);
+    assert_eq!((progress.dispensed, progress.remaining), (240, 0));
+    assert_eq!(progress.coins_dispensed, vec![100, 100, 20, 20]);
+    assert!(progress.empty_hoppers.is_empty());
+
+    let record = journal.last().expect("should journal the payout");
+    assert_eq!(record.outcome, PayoutOutcome::Completed);
+    assert_eq!(record.progress.dispensed, 240);
+
+    for (address, count) in [(EURO_HOPPER_ADDRESS, 2), (CENT_HOPPER_ADDRESS, 2)] {
+        let dispense_count = machine
+            .hopper(address)
+            .get_dispense_count()
+            .await
+            .expect("should read the dispense count");
+        assert_eq!(dispense_count, count, "hopper {address}");
+    }
+}
+
+#[tokio::test]
+async fn payout_events_follow_the_dispense() {
+    let machine = Machine::standard();
+    let pool = payout_pool(&machine, PayoutJournal::default()).await;
+
+    let (event_tx, mut event_rx) = mpsc::channel(64);
+    let progress = pool
+        .payout_with_events(120, event_tx)
+        .await
+        .expect("should pay out");
+    assert_eq!(progress.dispensed, 120);
+
+    let mut active_hoppers = Vec::new();
+    while let Ok(event) = event_rx.try_recv() {
+        match event {
+            PayoutEvent::Progress(progress) => {
+                if let Some(address) = progress.active_hopper
+                    && active_hoppers.last() != Some(&address)
+                {
+                    active_hoppers.push(address);
+                }
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+    }
+    assert_eq!(
+        active_hoppers,
+        vec![EURO_HOPPER_ADDRESS, CENT_HOPPER_ADDRESS]
+    );
+}
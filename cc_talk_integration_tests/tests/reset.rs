@@ -0,0 +1,99 @@
+use cc_talk_integration_tests::{EURO_HOPPER_ADDRESS, Machine, VALIDATOR_ADDRESS};
+use cc_talk_tokio_host::device::{base::DeviceCommon, reset::DeviceReset};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn coin_acceptor_is_enabled_again_after_a_reset() {
+    let machine = Machine::standard();
+    let (events_tx, mut events) = mpsc::channel(4);
+    let acceptor = machine
+        .coin_acceptor()
+        .with_reset_events(events_tx)
+        .with_reset_handler(|acceptor| {
+            Box::pin(async move {
+                acceptor.set_all_coin_inhibits(false).await?;
+                acceptor.disable_master_inhibit().await
+            })
+        });
+    acceptor
+        .set_all_coin_inhibits(false)
+        .await
+        .expect("should enable coins");
+    acceptor
+        .disable_master_inhibit()
+        .await
+        .expect("should enable");
+    acceptor.poll().await.expect("should credit");
+    assert_ne!(acceptor.event_counter(), 0);
+
+    acceptor.reset_device().await.expect("should reset");
+    acceptor.poll().await.expect("should poll the reset");
+
+    assert_eq!(
+        events.try_recv(),
+        Ok(DeviceReset {
+            address: acceptor.get_device().address(),
+            reinitialization: Some(Ok(())),
+        })
+    );
+    assert_eq!(acceptor.is_master_inhibit_disabled().await, Ok(true));
+
+    acceptor.poll().await.expect("should poll");
+    assert!(events.try_recv().is_err(), "the reset is reported once");
+    assert_ne!(acceptor.event_counter(), 0, "coins are accepted again");
+}
+
+#[tokio::test]
+async fn bill_validator_reports_resets_without_a_handler() {
+    let machine = Machine::standard();
+    let (events_tx, mut events) = mpsc::channel(4);
+    let validator = machine.bill_validator().with_reset_events(events_tx);
+    validator
+        .set_all_bill_inhibits(false)
+        .await
+        .expect("should enable bills");
+    validator
+        .disable_master_inhibit()
+        .await
+        .expect("should enable");
+    validator.poll().await.expect("should credit");
+
+    validator.reset_device().await.expect("should reset");
+    validator.poll().await.expect("should poll the reset");
+
+    assert_eq!(
+        events.try_recv(),
+        Ok(DeviceReset {
+            address: VALIDATOR_ADDRESS,
+            reinitialization: None,
+        })
+    );
+    assert_eq!(validator.is_master_inhibit_enabled().await, Ok(true));
+}
+
+#[tokio::test]
+async fn hopper_is_enabled_again_after_a_reset() {
+    let machine = Machine::standard();
+    let (events_tx, mut events) = mpsc::channel(4);
+    let hopper = machine
+        .hopper(EURO_HOPPER_ADDRESS)
+        .with_reset_events(events_tx)
+        .with_reset_handler(|hopper| Box::pin(hopper.enable_hopper()));
+    hopper.enable_hopper().await.expect("should enable");
+    hopper.payout(1).await.expect("should dispense");
+    assert_eq!(hopper.get_payout_status().await.map(|s| s.paid), Ok(1));
+
+    hopper.reset_device().await.expect("should reset");
+    let status = hopper
+        .get_payout_status()
+        .await
+        .expect("should read status");
+
+    assert_eq!(status.event_counter, 0);
+    assert_eq!(
+        events.try_recv().map(|reset| reset.reinitialization),
+        Ok(Some(Ok(())))
+    );
+    hopper.payout(1).await.expect("should dispense");
+    assert_eq!(hopper.get_payout_status().await.map(|s| s.paid), Ok(1));
+}
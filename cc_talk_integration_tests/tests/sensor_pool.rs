@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use cc_talk_integration_tests::{CENT_HOPPER_ADDRESS, EURO_HOPPER_ADDRESS, Machine};
+use cc_talk_tokio_host::device::{
+    payout_pool::HopperInventoryLevel,
+    payout_sensor_pool::{
+        CountSource, PayoutSensorPool, PollingStatus, RecoveryReason, SensorEvent, SensorPollGuard,
+    },
+};
+use tokio::sync::watch;
+
+/// Waits for the next event matching `predicate`.
+async fn wait_for(
+    guard: &mut SensorPollGuard,
+    predicate: impl Fn(&SensorEvent) -> bool + Send + Sync,
+) -> SensorEvent {
+    tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            let event = guard.recv().await.expect("should keep polling");
+            if predicate(&event) {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("should receive the event in time")
+}
+
+#[tokio::test]
+async fn low_hopper_is_marked_empty_until_refilled() {
+    let machine = Machine::in_memory_with(|_| {});
+    let sensor_pool = PayoutSensorPool::builder()
+        .add_hopper(machine.hopper(EURO_HOPPER_ADDRESS))
+        .add_hopper(machine.hopper(CENT_HOPPER_ADDRESS))
+        .polling_interval(Duration::from_millis(5))
+        .track_counts(CountSource::DispenseCount)
+        .build();
+    sensor_pool
+        .set_coin_count(EURO_HOPPER_ADDRESS, 10)
+        .expect("should be in the pool");
+    let (_status, status_receiver) = watch::channel(PollingStatus::Running);
+    let mut guard = sensor_pool
+        .try_start_polling(status_receiver)
+        .expect("should start polling");
+    assert!(sensor_pool.is_polling());
+
+    wait_for(&mut guard, |event| {
+        matches!(event, SensorEvent::InventoryUpdate { .. })
+    })
+    .await;
+    assert!(!sensor_pool.is_empty(EURO_HOPPER_ADDRESS));
+
+    // The 1 EUR hopper holds 10 coins, its low level.
+    let hopper = machine.hopper(EURO_HOPPER_ADDRESS);
+    hopper.enable_hopper().await.expect("should enable");
+    hopper.payout(1).await.expect("should pay out");
+    wait_for(&mut guard, |event| {
+        matches!(event, SensorEvent::MarkedEmpty { address } if *address == EURO_HOPPER_ADDRESS)
+    })
+    .await;
+    let SensorEvent::InventoryUpdate {
+        inventories,
+        errors,
+    } = wait_for(&mut guard, |event| {
+        matches!(event, SensorEvent::InventoryUpdate { .. })
+    })
+    .await
+    else {
+        unreachable!("should be an inventory update");
+    };
+    assert!(errors.is_empty());
+    let euro = inventories
+        .iter()
+        .find(|reading| reading.address == EURO_HOPPER_ADDRESS)
+        .and_then(|reading| reading.inventory)
+        .expect("should track the counts");
+    assert_eq!((euro.dispensed, euro.coins), (1, Some(9)));
+    assert_eq!(
+        euro.last_status.map(|status| status.paid),
+        Some(1),
+        "should keep the payout status"
+    );
+    assert!(sensor_pool.is_empty(EURO_HOPPER_ADDRESS));
+    assert!(!sensor_pool.is_empty(CENT_HOPPER_ADDRESS));
+
+    machine
+        .control()
+        .refill(EURO_HOPPER_ADDRESS, 91)
+        .expect("should refill");
+    let recovery = wait_for(&mut guard, |event| {
+        matches!(event, SensorEvent::MarkedNonEmpty { .. })
+    })
+    .await;
+    assert!(matches!(
+        recovery,
+        SensorEvent::MarkedNonEmpty {
+            address: EURO_HOPPER_ADDRESS,
+            reason: RecoveryReason::SensorRecovery {
+                level: HopperInventoryLevel::Medium
+            },
+        }
+    ));
+
+    drop(guard);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!sensor_pool.is_polling());
+}
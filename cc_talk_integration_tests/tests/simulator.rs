@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use cc_talk_core::cc_talk::HopperFlag;
+use cc_talk_emulator::OptoFault;
+use cc_talk_integration_tests::{CENT_HOPPER_ADDRESS, EURO_HOPPER_ADDRESS, Machine};
+use cc_talk_tokio_host::device::payout_pool::{PayoutJournal, PayoutPool};
+
+const COIN_INTERVAL: Duration = Duration::from_millis(20);
+
+#[tokio::test]
+async fn payout_follows_the_pace_of_the_hopper() {
+    let machine = Machine::in_memory_with(|scenario| {
+        scenario.hoppers[1].coin_interval_ms = 20;
+    });
+    let pool = PayoutPool::builder()
+        .add_hopper(machine.hopper(CENT_HOPPER_ADDRESS), 20)
+        .polling_interval(Duration::from_millis(5))
+        .with_journal(PayoutJournal::default())
+        .build_and_initialize()
+        .await
+        .expect("should initialize");
+
+    let started = Instant::now();
+    let progress = pool.payout(100).await.expect("should pay out");
+
+    assert_eq!((progress.dispensed, progress.remaining), (100, 0));
+    assert!(started.elapsed() >= COIN_INTERVAL * 5);
+    let hopper = machine
+        .control()
+        .hopper(CENT_HOPPER_ADDRESS)
+        .expect("should be a hopper");
+    assert_eq!(hopper.coins(), 45);
+    assert!(!hopper.is_dispensing());
+}
+
+#[tokio::test]
+async fn opto_fault_stops_the_hopper_until_reset() {
+    let machine = Machine::in_memory_with(|scenario| {
+        scenario.hoppers[0].opto_fault = Some(OptoFault::BlockedDuringPayout);
+        scenario.hoppers[0].opto_fault_after = 2;
+    });
+    let hopper = machine.hopper(EURO_HOPPER_ADDRESS);
+    hopper.enable_hopper().await.expect("should enable");
+
+    hopper.payout(5).await.expect("should start the payout");
+    let status = hopper
+        .get_payout_status()
+        .await
+        .expect("should read the status");
+    assert_eq!((status.paid, status.unpaid), (2, 3));
+    let flags = hopper.self_test().await.expect("should run the self test");
+    assert!(flags.contains(&HopperFlag::OptoBlockedPermanentlyDuringPayout));
+
+    hopper.payout(1).await.expect("should accept the command");
+    let status = hopper
+        .get_payout_status()
+        .await
+        .expect("should read the status");
+    assert_eq!((status.paid, status.unpaid), (0, 1));
+    assert_eq!(hopper.get_dispense_count().await, Ok(2));
+
+    machine
+        .control()
+        .execute("power_cycle 3")
+        .expect("should power cycle");
+    let flags = hopper.self_test().await.expect("should run the self test");
+    assert!(!flags.contains(&HopperFlag::OptoBlockedPermanentlyDuringPayout));
+}
@@ -0,0 +1,30 @@
+use cc_talk_core::cc_talk::SorterPath;
+use cc_talk_integration_tests::Machine;
+use cc_talk_tokio_host::device::coin_validator::CoinAcceptor;
+
+#[tokio::test]
+async fn sorter_is_detected_on_fitted_validators() {
+    let machine = Machine::boot_with(|scenario| scenario.selectors[0].sorter_path = 3);
+    let sorter = machine
+        .coin_acceptor()
+        .sorter()
+        .await
+        .expect("should detect the sorter")
+        .expect("should have a sorter");
+
+    assert_eq!(
+        sorter.get_coin_sorter_path(2).await,
+        Ok(SorterPath::Path(3))
+    );
+    assert!(sorter.coin_validator().poll().await.is_ok());
+}
+
+#[tokio::test]
+async fn pure_validators_have_no_sorter() {
+    let machine = Machine::boot_with(|scenario| scenario.selectors[0].sorter_path = 0);
+
+    assert_eq!(
+        machine.coin_acceptor().sorter().await.map(|s| s.is_some()),
+        Ok(false)
+    );
+}
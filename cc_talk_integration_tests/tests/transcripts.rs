@@ -0,0 +1,228 @@
+//! Replays device transcripts through the command parsers.
+//!
+//! The fixtures are synthetic, written by hand from the ccTalk specification and not captured
+//! from real devices, each one states its provenance in its header. Each fixture lists exchanges
+//! as a request line `>`, a reply line `<` and the `Debug` rendering of the parsed reply `=`, so
+//! any change in how a reply is parsed shows up here.
+
+use std::fmt::Debug;
+
+use cc_talk_core::cc_talk::{BillRouteCode, Header, Packet, crc8};
+use cc_talk_host::{
+    command::{Command, ParseResponseError},
+    core::core_commands::{
+        RequestBuildCodeCommand, RequestEquipementCategoryIdCommand, RequestManufacturerIdCommand,
+        RequestProductCodeCommand, SimplePollCommand,
+    },
+    core_plus::core_plus_commands::{
+        RequestCommsRevisionCommand, RequestSerialNumberCommand, RequestSoftwareRevisionCommand,
+    },
+    device::device_commands::{
+        EnableHopperCommand, PerformSelfCheckCommand, ReadBufferedBillEventsCommand,
+        ReadBufferedCreditOrErrorCodeCommand, RequestBillIdCommand, RequestCoinIdCommand,
+        RequestCountryScalingFactorCommand, RequestHopperCoinCommand, RequestHopperStatusCommand,
+        RequestInhibitStatusCommand, RequestMasterInhibitStatusCommand, RequestOptionFlagsCommand,
+        RequestPollingPriorityCommand, RequestSorterPathCommand, RequestStatusCommand,
+        RequestpayoutHighLowStatusCommand, RouteBillCommand, TestHopperCommand,
+    },
+};
+
+/// One request and its reply.
+struct Exchange {
+    line: usize,
+    request: Vec<u8>,
+    reply: Vec<u8>,
+    expected: String,
+}
+
+fn parse_frame(line: usize, hex: &str) -> Vec<u8> {
+    let frame: Vec<u8> = hex
+        .split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16).expect("fixture bytes should be hexadecimal"))
+        .collect();
+    assert_eq!(
+        crc8(&frame),
+        frame[frame.len() - 1],
+        "bad checksum on line {line}"
+    );
+    frame
+}
+
+fn load(transcript: &str) -> Vec<Exchange> {
+    let mut exchanges = Vec::new();
+    let mut lines = transcript
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    while let Some((line, request)) = lines.next() {
+        let request = request
+            .strip_prefix("> ")
+            .unwrap_or_else(|| panic!("line {line} should be a request"));
+        let (_, reply) = lines.next().expect("a reply should follow the request");
+        let (_, expected) = lines
+            .next()
+            .expect("a parsed reply should follow the reply");
+        exchanges.push(Exchange {
+            line,
+            request: parse_frame(line, request),
+            reply: parse_frame(
+                line + 1,
+                reply.strip_prefix("< ").expect("should be a reply"),
+            ),
+            expected: expected
+                .strip_prefix("= ")
+                .expect("should be a parsed reply")
+                .to_string(),
+        });
+    }
+    exchanges
+}
+
+fn render<T: Debug>(result: &Result<T, ParseResponseError>) -> String {
+    format!("{result:?}")
+}
+
+/// Host side of a transcript, keeps the event counters between polls.
+#[derive(Default)]
+struct Host {
+    coin_event_counter: u8,
+    bill_event_counter: u8,
+}
+
+impl Host {
+    fn parse(&mut self, header: Header, request: &[u8], reply: &[u8]) -> String {
+        match header {
+            Header::SimplePoll => render(&SimplePollCommand.parse_response(reply)),
+            Header::RequestEquipementCategoryId => {
+                render(&RequestEquipementCategoryIdCommand.parse_response(reply))
+            }
+            Header::RequestManufacturerId => {
+                render(&RequestManufacturerIdCommand.parse_response(reply))
+            }
+            Header::RequestProductCode => {
+                render(&RequestProductCodeCommand::default().parse_response(reply))
+            }
+            Header::RequestBuildCode => {
+                render(&RequestBuildCodeCommand::default().parse_response(reply))
+            }
+            Header::RequestSerialNumber => {
+                render(&RequestSerialNumberCommand.parse_response(reply))
+            }
+            Header::RequestSoftwareRevision => {
+                render(&RequestSoftwareRevisionCommand::default().parse_response(reply))
+            }
+            Header::RequestCommsRevision => {
+                render(&RequestCommsRevisionCommand.parse_response(reply))
+            }
+            Header::RequestHopperCoin => render(&RequestHopperCoinCommand.parse_response(reply)),
+            Header::RequestPayoutStatus => {
+                render(&RequestpayoutHighLowStatusCommand.parse_response(reply))
+            }
+            Header::EnableHopper => {
+                render(&EnableHopperCommand::new(request[0] == 165).parse_response(reply))
+            }
+            Header::TestHopper => render(&TestHopperCommand.parse_response(reply)),
+            Header::RequestHopperStatus => {
+                render(&RequestHopperStatusCommand.parse_response(reply))
+            }
+            Header::RequestCoinId => {
+                render(&RequestCoinIdCommand::new(request[0]).parse_response(reply))
+            }
+            Header::RequestSorterPaths => {
+                render(&RequestSorterPathCommand::new(request[0]).parse_response(reply))
+            }
+            Header::RequestPollingPriority => {
+                render(&RequestPollingPriorityCommand.parse_response(reply))
+            }
+            Header::RequestInhibitStatus => {
+                render(&RequestInhibitStatusCommand::<2>.parse_response(reply))
+            }
+            Header::RequestMasterInhibitStatus => {
+                render(&RequestMasterInhibitStatusCommand::<1>.parse_response(reply))
+            }
+            Header::RequestOptionFlags => render(&RequestOptionFlagsCommand.parse_response(reply)),
+            Header::PerformSelfCheck => render(&PerformSelfCheckCommand.parse_response(reply)),
+            Header::RequestStatus => render(&RequestStatusCommand.parse_response(reply)),
+            Header::ReadBufferedCreditOrErrorCodes => {
+                let result = ReadBufferedCreditOrErrorCodeCommand::new(self.coin_event_counter)
+                    .parse_response(reply);
+                if let Some(counter) = reply.first() {
+                    self.coin_event_counter = *counter;
+                }
+                render(&result)
+            }
+            Header::ReadBufferedBillEvents => {
+                let result = ReadBufferedBillEventsCommand::new(self.bill_event_counter)
+                    .parse_response(reply);
+                if let Some(counter) = reply.first() {
+                    self.bill_event_counter = *counter;
+                }
+                render(&result)
+            }
+            Header::RequestBillId => {
+                render(&RequestBillIdCommand::new(request[0]).parse_response(reply))
+            }
+            Header::RequestCountryScalingFactor => {
+                let country = std::str::from_utf8(request).expect("country codes are ASCII");
+                render(&RequestCountryScalingFactorCommand::new(country).parse_response(reply))
+            }
+            Header::RouteBill => {
+                let code = BillRouteCode::try_from(request[0]).expect("should be a route code");
+                render(&RouteBillCommand::new(code).parse_response(reply))
+            }
+            Header::ModifyInhibitStatus => {
+                assert!(reply.is_empty(), "modify commands are acknowledged");
+                "Ok(())".to_string()
+            }
+            header => panic!("no parser for {header:?}"),
+        }
+    }
+}
+
+fn replay(transcript: &str) {
+    let mut host = Host::default();
+    for exchange in load(transcript) {
+        let request = Packet::new(exchange.request);
+        let reply = Packet::new(exchange.reply);
+        assert_eq!(
+            reply.get_destination(),
+            Ok(1),
+            "replies go to the host, line {}",
+            exchange.line
+        );
+        assert_eq!(
+            reply.get_header(),
+            Ok(Header::Reply),
+            "line {}",
+            exchange.line
+        );
+
+        let header = request.get_header().expect("should be a known header");
+        let parsed = host.parse(
+            header,
+            request.get_data().expect("should hold data"),
+            reply.get_data().expect("should hold data"),
+        );
+        assert_eq!(
+            parsed, exchange.expected,
+            "{header:?} on line {} of the transcript",
+            exchange.line
+        );
+    }
+}
+
+#[test]
+fn sch2_hopper_transcript() {
+    replay(include_str!("../fixtures/sch2_hopper.txt"));
+}
+
+#[test]
+fn nri_coin_acceptor_transcript() {
+    replay(include_str!("../fixtures/nri_coin_acceptor.txt"));
+}
+
+#[test]
+fn itl_bill_validator_transcript() {
+    replay(include_str!("../fixtures/itl_bill_validator.txt"));
+}
@@ -1,7 +1,33 @@
+pub mod address_manager;
 pub mod base;
 pub mod bill_validator;
+pub mod bill_validator_client;
+pub mod bring_up;
+pub mod changer;
+pub mod clock_skew;
+pub mod coin_escrow;
+pub mod coin_event_stream;
+pub mod coin_sorter;
 pub mod coin_validator;
 pub mod currency_acceptor_pool;
+pub mod dispatcher;
+pub mod eeprom_persistence;
+pub mod enumeration;
+#[cfg(feature = "encryption")]
+pub mod event_encryption;
+pub mod float_manager;
+#[cfg(feature = "metrics")]
+pub mod latency;
+pub mod machine;
+pub mod manager;
+pub mod nak;
 pub mod payout;
 pub mod payout_pool;
 pub mod payout_sensor_pool;
+pub mod quirks;
+#[cfg(feature = "metrics")]
+pub mod reject_histogram;
+pub mod reset;
+pub mod service;
+pub mod topology;
+pub mod triage;
@@ -0,0 +1,638 @@
+use std::{fmt, str::FromStr};
+
+use cc_talk_core::cc_talk::{BusAddress, Category, ChecksumType, Device, SerialCode};
+use cc_talk_host::{
+    command::Command,
+    multi_drop::multi_drop_commands::{AddressClashCommand, AddressPollCommand},
+};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, instrument, warn};
+
+use crate::transport::tokio_transport::TransportMessage;
+
+use super::{
+    base::{CommandError, DeviceCommon},
+    enumeration::{Probe, free_address},
+};
+
+/// Default number of [`AddressManager::resolve_clashes`] rounds.
+pub const DEFAULT_CLASH_ROUNDS: u8 = 5;
+
+/// Address a device should answer at, the device is identified by its category and serial
+/// number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressAssignment {
+    pub category: Category,
+    pub serial: SerialCode,
+    pub address: BusAddress,
+}
+
+impl AddressAssignment {
+    fn is_device(&self, category: &Category, serial: &SerialCode) -> bool {
+        self.category == *category && self.serial.as_number() == serial.as_number()
+    }
+}
+
+/// Desired address of the devices of a bus, applied with [`AddressManager::apply`].
+///
+/// The map is stored as text, one device per line with its category, its serial number in
+/// decimal and its address, e.g. `Payout 66051 4`. Empty lines and lines starting with `#` are
+/// skipped when parsing.
+///
+/// ```ignore
+/// std::fs::write("addresses.txt", map.to_string())?;
+/// let map: AddressMap = std::fs::read_to_string("addresses.txt")?.parse()?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressMap {
+    assignments: Vec<AddressAssignment>,
+}
+
+/// A line of an [`AddressMap`] which cannot be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("line {line} of the address map: {reason}")]
+pub struct AddressMapError {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl AddressMap {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assigns `address` to the device, returns the assignments it replaces: the previous
+    /// address of the device and the device previously assigned to `address`.
+    pub fn assign(
+        &mut self,
+        category: Category,
+        serial: SerialCode,
+        address: BusAddress,
+    ) -> Vec<AddressAssignment> {
+        let (replaced, kept) =
+            std::mem::take(&mut self.assignments)
+                .into_iter()
+                .partition(|assignment| {
+                    assignment.is_device(&category, &serial) || assignment.address == address
+                });
+        self.assignments = kept;
+        self.assignments.push(AddressAssignment {
+            category,
+            serial,
+            address,
+        });
+        replaced
+    }
+
+    /// Assigns `address` to the device, see [`assign`](Self::assign).
+    #[must_use]
+    pub fn with_assignment(
+        mut self,
+        category: Category,
+        serial: SerialCode,
+        address: BusAddress,
+    ) -> Self {
+        self.assign(category, serial, address);
+        self
+    }
+
+    pub fn assignments(&self) -> &[AddressAssignment] {
+        &self.assignments
+    }
+
+    /// Returns the address assigned to the device.
+    pub fn address_of(&self, category: &Category, serial: &SerialCode) -> Option<BusAddress> {
+        self.assignments
+            .iter()
+            .find(|assignment| assignment.is_device(category, serial))
+            .map(|assignment| assignment.address)
+    }
+}
+
+impl fmt::Display for AddressMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for assignment in &self.assignments {
+            writeln!(
+                f,
+                "{:?} {} {}",
+                assignment.category,
+                assignment.serial.as_number(),
+                assignment.address.get()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for AddressMap {
+    type Err = AddressMapError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |reason| AddressMapError {
+                line: index + 1,
+                reason,
+            };
+            let [category, serial, address] = line.split_whitespace().collect::<Vec<_>>()[..]
+            else {
+                return Err(error("expected a category, a serial number and an address"));
+            };
+            let category = Category::from(category);
+            if category == Category::Unknown {
+                return Err(error("unknown category"));
+            }
+            let serial: u32 = serial.parse().map_err(|_| error("invalid serial number"))?;
+            let bytes = serial.to_le_bytes();
+            let serial = if serial > 0x00FF_FFFF {
+                SerialCode::from_le_bytes(&bytes)
+            } else {
+                SerialCode::from_le_bytes(&bytes[..3])
+            }
+            .ok_or_else(|| error("invalid serial number"))?;
+            let address = address
+                .parse()
+                .ok()
+                .and_then(|address| BusAddress::peripheral(address).ok())
+                .ok_or_else(|| error("invalid peripheral address"))?;
+            map.assign(category, serial, address);
+        }
+        Ok(map)
+    }
+}
+
+/// Result of [`AddressManager::resolve_clashes`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClashResolution {
+    /// Addresses several devices answered at, in the order they were randomized.
+    pub randomized: Vec<BusAddress>,
+    /// Addresses still shared after the last round.
+    pub unresolved: Vec<BusAddress>,
+    /// Addresses answering the last poll, in ascending order.
+    pub addresses: Vec<BusAddress>,
+}
+
+/// A device moved by [`AddressManager::apply`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressMove {
+    pub category: Category,
+    pub serial: Option<SerialCode>,
+    pub from: BusAddress,
+    pub to: BusAddress,
+}
+
+/// Result of [`AddressManager::apply`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressReport {
+    pub clashes: ClashResolution,
+    /// Moves in the order they were made, a device may be moved twice when it is moved out of
+    /// the way of another one.
+    pub moves: Vec<AddressMove>,
+    /// Assignments of devices which did not answer.
+    pub missing: Vec<AddressAssignment>,
+    /// Assignments left unapplied, the device at the address had no free address to go to.
+    pub blocked: Vec<AddressAssignment>,
+}
+
+/// Device found on the bus by [`AddressManager::apply`].
+struct Located {
+    address: BusAddress,
+    category: Category,
+    serial: Option<SerialCode>,
+}
+
+/// Manages the addresses of the devices of a bus with the MDCES commands.
+///
+/// Clashes, several devices answering at the same address, are found with `AddressClash` and
+/// resolved with `AddressRandom`. Devices are then moved to the addresses of an
+/// [`AddressMap`] with `AddressChange`, the device in the way of another one is moved first.
+///
+/// ```ignore
+/// let manager = AddressManager::new(sender);
+/// let map = AddressMap::new()
+///     .with_assignment(Category::Payout, serial, BusAddress::new(4));
+/// let report = manager.apply(&map).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct AddressManager {
+    sender: mpsc::Sender<TransportMessage>,
+    checksum_type: ChecksumType,
+    clash_rounds: u8,
+}
+
+impl AddressManager {
+    /// Creates a manager talking to the bus with 8 bit checksums.
+    pub const fn new(sender: mpsc::Sender<TransportMessage>) -> Self {
+        Self {
+            sender,
+            checksum_type: ChecksumType::Crc8,
+            clash_rounds: DEFAULT_CLASH_ROUNDS,
+        }
+    }
+
+    /// Checksum used to talk to the bus, every device has to use the same.
+    #[must_use]
+    pub const fn with_checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+        self.checksum_type = checksum_type;
+        self
+    }
+
+    /// Maximum number of polls and randomizations, a randomized device may land on a used
+    /// address and clash again.
+    #[must_use]
+    pub const fn with_clash_rounds(mut self, clash_rounds: u8) -> Self {
+        self.clash_rounds = clash_rounds;
+        self
+    }
+
+    /// Broadcasts an `AddressPoll`, returns the addresses which answered in ascending order.
+    ///
+    /// Devices sharing an address answer in the same slot, they show up once.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the poll cannot be sent.
+    pub async fn poll(&self) -> Result<Vec<BusAddress>, CommandError> {
+        let mut addresses = collect_addresses(
+            &self.sender,
+            BusAddress::BROADCAST,
+            self.checksum_type,
+            AddressPollCommand,
+        )
+        .await?;
+        addresses.sort_unstable_by_key(|address| address.get());
+        addresses.dedup();
+        debug!(addresses = ?addresses, "address poll answered");
+        Ok(addresses)
+    }
+
+    /// Sends an `AddressClash` to `address`, returns the number of devices which answered.
+    ///
+    /// More than one device is a clash, see [`resolve_clashes`](Self::resolve_clashes).
+    ///
+    /// # Errors
+    ///
+    /// Errors if the command cannot be sent.
+    pub async fn devices_at(&self, address: BusAddress) -> Result<usize, CommandError> {
+        let answers = collect_addresses(
+            &self.sender,
+            address,
+            self.checksum_type,
+            AddressClashCommand,
+        )
+        .await?;
+        Ok(answers.iter().filter(|answer| **answer == address).count())
+    }
+
+    /// Moves every device at `address` to a random address with `AddressRandom`.
+    ///
+    /// The acknowledgements of several devices collide, only failing to reach the transport is
+    /// an error.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the command cannot be sent.
+    pub async fn randomize(&self, address: BusAddress) -> Result<(), CommandError> {
+        match self.probe(address).randomize_address().await {
+            Err(error @ (CommandError::SendError | CommandError::ReceiveError)) => Err(error),
+            Err(error) => {
+                debug!(address = address.get(), error = %error, "randomize not acknowledged");
+                Ok(())
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Moves the device at `from` to `to` with `AddressChange`.
+    ///
+    /// # Errors
+    ///
+    /// - [`CommandError::AddressInUse`] if a device answers at `to`.
+    /// - [`CommandError::InvalidAddress`] if `to` is the broadcast or host address.
+    /// - The error of the exchange otherwise.
+    pub async fn reassign(&self, from: BusAddress, to: BusAddress) -> Result<(), CommandError> {
+        if from == to {
+            return Ok(());
+        }
+        if self.devices_at(to).await? > 0 {
+            return Err(CommandError::AddressInUse(to.get()));
+        }
+        self.probe(from).change_address(to).await
+    }
+
+    /// Polls the bus and randomizes the addresses several devices answer at, until no address
+    /// is shared or the rounds are exhausted.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a command cannot be sent.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn resolve_clashes(&self) -> Result<ClashResolution, CommandError> {
+        let mut resolution = ClashResolution::default();
+        for _ in 0..self.clash_rounds {
+            resolution.addresses = self.poll().await?;
+            let clashing = self.clashing(&resolution.addresses).await?;
+            if clashing.is_empty() {
+                return Ok(resolution);
+            }
+            for address in clashing {
+                warn!(address = address.get(), "address clash, randomizing");
+                self.randomize(address).await?;
+                resolution.randomized.push(address);
+            }
+        }
+        resolution.addresses = self.poll().await?;
+        resolution.unresolved = self.clashing(&resolution.addresses).await?;
+        if !resolution.unresolved.is_empty() {
+            warn!(unresolved = ?resolution.unresolved, "address clashes left");
+        }
+        Ok(resolution)
+    }
+
+    /// Resolves the clashes then moves the devices to the addresses of `map`.
+    ///
+    /// A device in the way of another one goes to its own assigned address if it is free, to a
+    /// free address of its category range otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a command cannot be sent or a move fails.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn apply(&self, map: &AddressMap) -> Result<AddressReport, CommandError> {
+        let clashes = self.resolve_clashes().await?;
+        let mut located = Vec::with_capacity(clashes.addresses.len());
+        for &address in &clashes.addresses {
+            let probe = self.probe(address);
+            located.push(Located {
+                address,
+                category: probe.get_category().await.unwrap_or(Category::Unknown),
+                serial: probe.get_serial_number().await.ok(),
+            });
+        }
+
+        let mut report = AddressReport {
+            clashes,
+            ..AddressReport::default()
+        };
+        for assignment in map.assignments() {
+            let Some(index) = located.iter().position(|device| {
+                device
+                    .serial
+                    .as_ref()
+                    .is_some_and(|serial| assignment.is_device(&device.category, serial))
+            }) else {
+                report.missing.push(assignment.clone());
+                continue;
+            };
+            if located[index].address == assignment.address {
+                continue;
+            }
+            if let Some(occupant) = located
+                .iter()
+                .position(|device| device.address == assignment.address)
+            {
+                let Some(parking) = parking_address(map, &located, occupant) else {
+                    warn!(
+                        address = assignment.address.get(),
+                        "no free address for the device in the way"
+                    );
+                    report.blocked.push(assignment.clone());
+                    continue;
+                };
+                report
+                    .moves
+                    .push(self.move_device(&mut located[occupant], parking).await?);
+            }
+            report.moves.push(
+                self.move_device(&mut located[index], assignment.address)
+                    .await?,
+            );
+        }
+        info!(
+            moves = report.moves.len(),
+            missing = report.missing.len(),
+            blocked = report.blocked.len(),
+            "address map applied"
+        );
+        Ok(report)
+    }
+
+    async fn clashing(&self, addresses: &[BusAddress]) -> Result<Vec<BusAddress>, CommandError> {
+        let mut clashing = Vec::new();
+        for &address in addresses {
+            if self.devices_at(address).await? > 1 {
+                clashing.push(address);
+            }
+        }
+        Ok(clashing)
+    }
+
+    async fn move_device(
+        &self,
+        device: &mut Located,
+        to: BusAddress,
+    ) -> Result<AddressMove, CommandError> {
+        self.probe(device.address).change_address(to).await?;
+        let address_move = AddressMove {
+            category: device.category.clone(),
+            serial: device.serial.clone(),
+            from: device.address,
+            to,
+        };
+        device.address = to;
+        Ok(address_move)
+    }
+
+    fn probe(&self, address: BusAddress) -> Probe {
+        Probe::new(address.get(), self.checksum_type, &self.sender)
+    }
+}
+
+/// Returns where the device at `occupant` goes to make room: its assigned address if it is
+/// free, else a free address of its category which no other device is assigned to.
+fn parking_address(map: &AddressMap, located: &[Located], occupant: usize) -> Option<BusAddress> {
+    let device = &located[occupant];
+    let mut used: Vec<u8> = located.iter().map(|device| device.address.get()).collect();
+    let assigned = device
+        .serial
+        .as_ref()
+        .and_then(|serial| map.address_of(&device.category, serial));
+    if let Some(assigned) = assigned
+        && !used.contains(&assigned.get())
+    {
+        return Some(assigned);
+    }
+    used.extend(
+        map.assignments()
+            .iter()
+            .map(|assignment| assignment.address.get()),
+    );
+    free_address(&device.category, &used)
+}
+
+/// Sends an `AddressPoll` or `AddressClash` to `address`, returns the address bytes received.
+pub(super) async fn collect_addresses<C>(
+    sender: &mpsc::Sender<TransportMessage>,
+    address: BusAddress,
+    checksum_type: ChecksumType,
+    command: C,
+) -> Result<Vec<BusAddress>, CommandError>
+where
+    C: Command<Response = BusAddress>,
+{
+    let (tx, rx) = oneshot::channel();
+    let device = Device::new(address.get(), Category::Unknown, checksum_type);
+    sender
+        .send(TransportMessage::new(&device, command, tx))
+        .await
+        .map_err(|_| CommandError::SendError)?;
+    let replies = rx.await.map_err(|_| CommandError::ReceiveError)??;
+    Ok(replies.into_iter().map(BusAddress::new).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use cc_talk_core::cc_talk::Header;
+
+    use crate::transport::tokio_transport::TransportError;
+
+    use super::*;
+
+    type Devices = Arc<Mutex<Vec<(u8, &'static str, SerialCode)>>>;
+
+    /// Answers like the devices of a bus, `AddressRandom` moves the devices to the addresses
+    /// of `random` in turn.
+    fn simulated_bus(devices: Devices, mut random: Vec<u8>) -> mpsc::Sender<TransportMessage> {
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(8);
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let mut devices = devices.lock().unwrap();
+                let at_address: Vec<usize> = (0..devices.len())
+                    .filter(|&index| devices[index].0 == message.address)
+                    .collect();
+                let reply = match message.header {
+                    Header::AddressPoll => {
+                        let mut addresses: Vec<u8> =
+                            devices.iter().map(|device| device.0).collect();
+                        addresses.sort_unstable();
+                        addresses.dedup();
+                        Ok(addresses)
+                    }
+                    Header::AddressClash => Ok(vec![message.address; at_address.len()]),
+                    _ if at_address.is_empty() => Err(TransportError::Timeout),
+                    header => {
+                        // Every device at the address executes the command, the first replies.
+                        for &index in &at_address {
+                            match header {
+                                Header::AddressRandom => devices[index].0 = random.remove(0),
+                                Header::AddressChange => devices[index].0 = message.data[0],
+                                _ => {}
+                            }
+                        }
+                        let device = &devices[at_address[0]];
+                        let data = match header {
+                            Header::RequestEquipementCategoryId => device.1.as_bytes().to_vec(),
+                            Header::RequestSerialNumber => {
+                                let serial = &device.2;
+                                vec![serial.fix(), serial.minor(), serial.major()]
+                            }
+                            _ => vec![],
+                        };
+                        let mut reply = vec![1, data.len() as u8, message.address, 0];
+                        reply.extend(data);
+                        reply.push(0);
+                        Ok(reply)
+                    }
+                };
+                message.respond_to.send(reply).expect("should respond");
+            }
+        });
+        tx
+    }
+
+    #[tokio::test]
+    async fn clashes_are_resolved_and_the_map_applied() {
+        let first = SerialCode::new(0, 0, 1);
+        let second = SerialCode::new(0, 0, 2);
+        let acceptor = SerialCode::new(0, 0, 3);
+        let devices: Devices = Arc::new(Mutex::new(vec![
+            (3, "Payout", first.clone()),
+            (3, "Payout", second.clone()),
+            (2, "Coin Acceptor", acceptor.clone()),
+        ]));
+        let manager = AddressManager::new(simulated_bus(devices.clone(), vec![7, 9]));
+
+        assert_eq!(manager.devices_at(BusAddress::new(3)).await, Ok(2));
+        let map = AddressMap::new()
+            .with_assignment(Category::Payout, first.clone(), BusAddress::new(3))
+            .with_assignment(Category::Payout, second.clone(), BusAddress::new(2))
+            .with_assignment(Category::CoinAcceptor, acceptor, BusAddress::new(11))
+            .with_assignment(
+                Category::Payout,
+                SerialCode::new(0, 0, 4),
+                BusAddress::new(5),
+            );
+        let report = manager.apply(&map).await.expect("should apply");
+
+        assert_eq!(report.clashes.randomized, [BusAddress::new(3)]);
+        assert!(report.clashes.unresolved.is_empty());
+        let moves: Vec<_> = report
+            .moves
+            .iter()
+            .map(|address_move| (address_move.from.get(), address_move.to.get()))
+            .collect();
+        assert_eq!(moves, [(7, 3), (2, 11), (9, 2)]);
+        assert_eq!(report.missing.len(), 1);
+        assert!(report.blocked.is_empty());
+        let addresses: Vec<u8> = devices.lock().unwrap().iter().map(|d| d.0).collect();
+        assert_eq!(addresses, [3, 2, 11]);
+
+        assert_eq!(
+            manager
+                .reassign(BusAddress::new(3), BusAddress::new(2))
+                .await,
+            Err(CommandError::AddressInUse(2))
+        );
+    }
+
+    #[test]
+    fn maps_round_trip_through_text() {
+        let mut map = AddressMap::new()
+            .with_assignment(
+                Category::Payout,
+                SerialCode::new(1, 2, 3),
+                BusAddress::new(4),
+            )
+            .with_assignment(
+                Category::BillValidator,
+                SerialCode::new_extended(1, 0, 0, 0),
+                BusAddress::new(40),
+            );
+        let replaced = map.assign(
+            Category::CoinAcceptor,
+            SerialCode::new(0, 0, 9),
+            BusAddress::new(4),
+        );
+        assert_eq!(replaced.len(), 1);
+
+        let text = map.to_string();
+        assert_eq!(text, "BillValidator 16777216 40\nCoinAcceptor 9 4\n");
+        assert_eq!(text.parse::<AddressMap>(), Ok(map));
+        assert_eq!(
+            "# bus 1\n\nPayout 66051 1".parse::<AddressMap>(),
+            Err(AddressMapError {
+                line: 3,
+                reason: "invalid peripheral address",
+            })
+        );
+    }
+}
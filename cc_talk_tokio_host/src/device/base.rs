@@ -1,21 +1,47 @@
 #![allow(dead_code, async_fn_in_trait)]
 
-use cc_talk_core::cc_talk::{Category, Device, Manufacturer, Packet, PacketError, SerialCode};
+use std::sync::{Arc, Mutex};
+
+use cc_talk_core::cc_talk::{
+    AcceptLimitError, BusAddress, Category, CurrencyToken, DataBlockError, DataStorageAvailability,
+    Device, Header, Manufacturer, Packet, PacketError, SerialCode,
+};
 use cc_talk_host::{
-    command::{Command, ParseResponseError},
+    command::{Command, ParseResponseError, ResponseStatus},
     core::core_commands::{
         RequestEquipementCategoryIdCommand, RequestManufacturerIdCommand,
         RequestProductCodeCommand, SimplePollCommand,
     },
     core_plus::core_plus_commands::{
-        RequestSerialNumberCommand, RequestSoftwareRevisionCommand, ResetDeviceCommand,
+        RequestDataStorageAvailabilityCommand, RequestSerialNumberCommand,
+        RequestSoftwareRevisionCommand, ResetDeviceCommand,
     },
+    device::device_commands::{
+        ConfigurationToEepromCommand, CountersToEepromCommand, ReadDataBlockCommand,
+        WriteDataBlockCommand,
+    },
+    multi_drop::multi_drop_commands::{AddressChangeCommand, AddressRandomCommand},
 };
 use thiserror::Error;
 use tokio::sync::{mpsc::Sender, oneshot};
-use tracing::{debug, instrument, trace, warn};
+use tracing::{debug, info, instrument, trace, warn};
+
+use crate::{
+    transport::{
+        correlation::CorrelationId,
+        machine_state::MachineState,
+        timestamp::{ReceptionSlot, Stamped, Timestamp},
+        tokio_transport::{TransportError, TransportMessage},
+    },
+    util::DropGuard,
+};
 
-use crate::transport::tokio_transport::{TransportError, TransportMessage};
+use super::{
+    coin_sorter::DivertError,
+    nak::NakCause,
+    quirks::DeviceQuirks,
+    triage::{TriageConfig, UnresponsiveCause, triage},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum CommandError {
@@ -35,6 +61,13 @@ pub enum CommandError {
     ChecksumError,
     #[error("Max retries exceeded")]
     MaxRetriesExceeded,
+    #[error("Queue full")]
+    QueueFull,
+    #[error("Echo mismatch")]
+    EchoMismatch,
+    /// The reply is longer than the command allows.
+    #[error("Response too long")]
+    ResponseTooLong,
     #[error("Send error")]
     SendError,
     #[error("Receive error")]
@@ -47,6 +80,43 @@ pub enum CommandError {
     InvalidPacket,
     #[error("Unable to parse response: {0}")]
     ParseError(&'static str),
+    #[error("address {0} cannot be used by a peripheral")]
+    InvalidAddress(u8),
+    /// Another device answers at the address a device was asked to move to.
+    #[error("address {0} is already used by another device")]
+    AddressInUse(u8),
+    #[error("{0}")]
+    InvalidAcceptLimit(AcceptLimitError),
+    #[error("{0}")]
+    InvalidDataBlock(DataBlockError),
+    #[error("{0}")]
+    InvalidDivert(DivertError),
+    /// The device NAKed the command, see [`CommandError::is_nack`].
+    #[error("device at address {address} NAKed {header:?}, {cause}")]
+    Rejected {
+        address: u8,
+        header: Header,
+        cause: NakCause,
+    },
+    /// The device replied Busy until the [`BusyPolicy`](cc_talk_host::command::BusyPolicy) of
+    /// the command gave up.
+    #[error("device at address {address} is busy, {header:?} not executed")]
+    Busy { address: u8, header: Header },
+    #[error("device at address {address} is not responding: {cause}")]
+    Unresponsive {
+        address: u8,
+        cause: UnresponsiveCause,
+    },
+    /// The command is not allowed in the current machine state, it was not sent.
+    #[error("command not allowed in {0:?} state")]
+    NotAllowed(MachineState),
+    /// The hopper dispenses another coin than the payout requested.
+    #[error("hopper at address {address} holds {loaded:?}, {expected:?} requested")]
+    WrongCoin {
+        address: u8,
+        expected: CurrencyToken,
+        loaded: CurrencyToken,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
@@ -55,6 +125,14 @@ pub enum PollingError {
     AlreadyLeased,
 }
 
+impl CommandError {
+    /// Returns `true` if the device NAKed the command, with or without a diagnosed cause.
+    #[must_use]
+    pub const fn is_nack(&self) -> bool {
+        matches!(self, Self::Nack | Self::Rejected { .. })
+    }
+}
+
 impl From<TransportError> for CommandError {
     fn from(error: TransportError) -> Self {
         match error {
@@ -66,6 +144,11 @@ impl From<TransportError> for CommandError {
             TransportError::SocketReadError => CommandError::SocketReadError,
             TransportError::ChecksumError => CommandError::ChecksumError,
             TransportError::MaxRetriesExceeded => CommandError::MaxRetriesExceeded,
+            TransportError::QueueFull => CommandError::QueueFull,
+            TransportError::EchoMismatch => CommandError::EchoMismatch,
+            TransportError::ResponseTooLong => CommandError::ResponseTooLong,
+            TransportError::Closed => CommandError::SendError,
+            TransportError::NotAllowed(state) => CommandError::NotAllowed(state),
         }
     }
 }
@@ -77,6 +160,7 @@ impl From<PacketError> for CommandError {
             PacketError::InvalidHeader(header) => CommandError::InvalidHeader(header),
             PacketError::InvalidPacket => CommandError::InvalidPacket,
             PacketError::OutOfBounds => CommandError::BufferOverflow,
+            PacketError::ChecksumMismatch { .. } => CommandError::ChecksumError,
         }
     }
 }
@@ -95,24 +179,344 @@ impl From<ParseResponseError> for CommandError {
 
 pub type DeviceResult<T> = Result<T, CommandError>;
 
+/// Long operation running on a device, see [`Header::long_operation_timeout`].
+///
+/// Clones share the same state, drivers use it to pause their credit polling while the device
+/// is busy instead of queueing polls behind the operation.
+#[derive(Debug, Clone, Default)]
+pub struct LongOperation {
+    current: Arc<Mutex<Option<Header>>>,
+}
+
+impl LongOperation {
+    /// Returns the header of the long operation currently running, if any.
+    #[must_use]
+    pub fn current(&self) -> Option<Header> {
+        *self.current.lock().expect("should not be poisoned")
+    }
+
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.current().is_some()
+    }
+
+    fn set(&self, header: Option<Header>) {
+        *self.current.lock().expect("should not be poisoned") = header;
+    }
+}
+
+/// Response of a command along with its reply packet, see [`DeviceCommon::execute_with_packet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawReply<T> {
+    pub response: T,
+    pub packet: Packet<Vec<u8>>,
+    /// Time the transport read the reply frame.
+    pub received_at: Timestamp,
+}
+
+impl<T> RawReply<T> {
+    /// Returns the data of the reply packet, including the bytes the command did not parse.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the packet is shorter than its data length.
+    pub fn payload(&self) -> Result<&[u8], PacketError> {
+        self.packet.get_data()
+    }
+}
+
+/// Rewrites the data of a reply frame with the quirks of the device.
+fn apply_quirks(quirks: &dyn DeviceQuirks, header: Header, frame: Vec<u8>) -> Vec<u8> {
+    // Destination, data length, source and header, then the data and the checksum.
+    if frame.len() < 5 {
+        return frame;
+    }
+    let Some(mut data) = quirks.adjust_response(header, &frame[4..frame.len() - 1]) else {
+        return frame;
+    };
+    if data.len() > usize::from(u8::MAX) {
+        warn!(
+            length = data.len(),
+            "adjusted reply too long, truncating it"
+        );
+        data.truncate(usize::from(u8::MAX));
+    }
+    trace!(
+        header = header as u8,
+        length = data.len(),
+        "reply adjusted by device quirks"
+    );
+    let mut adjusted = Vec::with_capacity(data.len() + 5);
+    adjusted.extend_from_slice(&[frame[0], data.len() as u8, frame[2], frame[3]]);
+    adjusted.extend_from_slice(&data);
+    adjusted.push(frame[frame.len() - 1]);
+    adjusted
+}
+
+/// Sends the command to the device, returns the acknowledged reply frame as received.
+///
+/// Busy replies are retried with the [`Command::busy_policy`], NAK replies are diagnosed.
+async fn exchange<D, C>(
+    device: &D,
+    command: &C,
+) -> (CorrelationId, Result<Stamped<Vec<u8>>, CommandError>)
+where
+    D: DeviceCommon + ?Sized,
+    C: Command,
+{
+    let policy = command.busy_policy();
+    let mut retry = 0;
+    loop {
+        let (correlation_id, result) = exchange_once(device, command).await;
+        let Ok(reply) = &result else {
+            return (correlation_id, result);
+        };
+        let status = reply
+            .value
+            .get(3)
+            .and_then(|&header| Header::try_from(header).ok())
+            .map_or(ResponseStatus::Ack, ResponseStatus::from);
+        if status != ResponseStatus::Busy {
+            return (correlation_id, result);
+        }
+        retry += 1;
+        let target = device.get_device();
+        let Some(delay) = policy.delay(retry) else {
+            debug!(address = target.address(), "device still busy, giving up");
+            return (
+                correlation_id,
+                Err(CommandError::Busy {
+                    address: target.address(),
+                    header: command.header(),
+                }),
+            );
+        };
+        debug!(address = target.address(), retry, "device busy, retrying");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Sends the command to the device, returns the reply packet adjusted by the quirks of the
+/// device.
+async fn send_stamped<D, C>(
+    device: &D,
+    command: &C,
+) -> (
+    CorrelationId,
+    Result<Stamped<Packet<Vec<u8>>>, CommandError>,
+)
+where
+    D: DeviceCommon + ?Sized,
+    C: Command,
+{
+    let header = command.header();
+    let (correlation_id, result) = exchange(device, command).await;
+    let result = result.map(|reply| {
+        reply
+            .map(|frame| match device.get_quirks() {
+                Some(quirks) => apply_quirks(quirks.as_ref(), header, frame),
+                None => frame,
+            })
+            .map(Packet::new)
+    });
+    (correlation_id, result)
+}
+
+/// Sends the command to the device once, returns the reply frame as received.
+///
+/// Replies of transports which do not stamp them are stamped when they are handed over.
+async fn exchange_once<D, C>(
+    device: &D,
+    command: &C,
+) -> (CorrelationId, Result<Stamped<Vec<u8>>, CommandError>)
+where
+    D: DeviceCommon + ?Sized,
+    C: Command,
+{
+    let header = command.header();
+    let has_parameters = !command.data().is_empty();
+    let long_operation = device
+        .get_long_operation()
+        .filter(|_| header.is_long_operation())
+        .map(|long_operation| {
+            debug!(header = header as u8, "starting long operation");
+            long_operation.set(Some(header));
+            DropGuard::new(long_operation, |long_operation| long_operation.set(None))
+        });
+
+    let (tx, rx) = oneshot::channel();
+    let received_at = ReceptionSlot::default();
+    let target = device.get_device();
+    let message = TransportMessage {
+        correlation_id: CorrelationId::next(),
+        address: target.address(),
+        checksum_type: *target.checksum_type(),
+        header,
+        data: TransportMessage::request_buffer(command.data()),
+        respond_to: tx,
+        received_at: received_at.clone(),
+    };
+    let correlation_id = message.correlation_id;
+    tracing::Span::current().record("correlation_id", tracing::field::display(correlation_id));
+    let sender = match device.get_express_sender() {
+        Some(express_sender) if message.is_express() => express_sender,
+        _ => device.get_sender(),
+    };
+    if sender.send(message).await.is_err() {
+        return (correlation_id, Err(CommandError::SendError));
+    }
+
+    let result = rx.await;
+    drop(long_operation);
+    let result = match result {
+        Ok(result) => result,
+        Err(_) => return (correlation_id, Err(CommandError::ReceiveError)),
+    };
+    let result = match result {
+        Err(TransportError::Timeout) if let Some(config) = device.timeout_triage() => {
+            let cause = triage(device.get_sender(), target, config).await;
+            warn!(address = target.address(), cause = %cause, "device is not responding");
+            Err(CommandError::Unresponsive {
+                address: target.address(),
+                cause,
+            })
+        }
+        Err(TransportError::Nack) => {
+            let cause = NakCause::diagnose(header, has_parameters, target.category());
+            debug!(address = target.address(), cause = %cause, "command NAKed");
+            Err(CommandError::Rejected {
+                address: target.address(),
+                header,
+                cause,
+            })
+        }
+        result => result.map_err(CommandError::from),
+    };
+    let result =
+        result.map(|frame| Stamped::new(frame, received_at.get().unwrap_or_else(Timestamp::now)));
+    (correlation_id, result)
+}
+
 pub trait DeviceCommon {
     fn get_device(&self) -> &Device;
     fn get_sender(&self) -> &Sender<TransportMessage>;
 
-    #[instrument(name = "device_send_command", skip(self), level = "debug")]
+    /// Sender used for express commands, see [`TransportMessage::is_express`].
+    ///
+    /// When `None` express commands go through [`Self::get_sender`] like any other command.
+    fn get_express_sender(&self) -> Option<&Sender<TransportMessage>> {
+        None
+    }
+
+    /// Tracks the long operations sent to the device.
+    ///
+    /// When `None` long operations are sent like any other command.
+    fn get_long_operation(&self) -> Option<&LongOperation> {
+        None
+    }
+
+    /// Quirks applied to the replies of the device before they are parsed, see
+    /// [`DeviceQuirks`].
+    fn get_quirks(&self) -> Option<&Arc<dyn DeviceQuirks>> {
+        None
+    }
+
+    /// Configuration of the [`triage`] run when a command times out after the transport
+    /// retries, `None` if timeouts are not triaged.
+    ///
+    /// The timeout is then reported as [`CommandError::Unresponsive`] with its likely cause
+    /// instead of [`CommandError::Timeout`].
+    fn timeout_triage(&self) -> Option<&TriageConfig> {
+        None
+    }
+
+    /// Returns `true` if timeouts are triaged, see [`Self::timeout_triage`].
+    fn triages_timeouts(&self) -> bool {
+        self.timeout_triage().is_some()
+    }
+
     async fn send_command<C>(&self, command: C) -> Result<Packet<Vec<u8>>, CommandError>
     where
         C: Command + core::fmt::Debug,
     {
-        let (tx, rx) = oneshot::channel();
-        let message = TransportMessage::new(self.get_device(), command, tx);
-        self.get_sender()
-            .send(message)
-            .await
-            .map_err(|_| CommandError::SendError)?;
+        self.send_correlated_command(command).await.1
+    }
 
-        let result = rx.await.map_err(|_| CommandError::ReceiveError)??;
-        Ok(Packet::new(result))
+    /// Sends the command like [`Self::send_command`] and returns the [`CorrelationId`] of the
+    /// transport message with the result.
+    #[instrument(
+        name = "device_send_command",
+        skip(self),
+        fields(correlation_id),
+        level = "debug"
+    )]
+    async fn send_correlated_command<C>(
+        &self,
+        command: C,
+    ) -> (CorrelationId, Result<Packet<Vec<u8>>, CommandError>)
+    where
+        C: Command + core::fmt::Debug,
+    {
+        let (correlation_id, result) = send_stamped(self, &command).await;
+        (correlation_id, result.map(|reply| reply.value))
+    }
+
+    /// Sends the command like [`Self::send_command`] and returns the reply packet with the time
+    /// the transport read it.
+    #[instrument(
+        name = "device_send_command",
+        skip(self),
+        fields(correlation_id),
+        level = "debug"
+    )]
+    async fn send_stamped_command<C>(
+        &self,
+        command: C,
+    ) -> Result<Stamped<Packet<Vec<u8>>>, CommandError>
+    where
+        C: Command + core::fmt::Debug,
+    {
+        send_stamped(self, &command).await.1
+    }
+
+    /// Sends the command and parses its reply, returning the reply packet along with the
+    /// response, e.g. to log or decode vendor specific bytes the command does not parse.
+    ///
+    /// The packet is the reply as received, before the [`DeviceQuirks`] of the device are
+    /// applied, the response is parsed from the adjusted reply like with any other command.
+    #[instrument(
+        name = "device_send_command",
+        skip(self),
+        fields(correlation_id),
+        level = "debug"
+    )]
+    async fn execute_with_packet<C>(&self, command: C) -> DeviceResult<RawReply<C::Response>>
+    where
+        C: Command + core::fmt::Debug,
+    {
+        let header = command.header();
+        let Stamped {
+            value: frame,
+            received_at,
+        } = exchange(self, &command).await.1?;
+        let packet = Packet::new(frame);
+        let response = match self.get_quirks() {
+            Some(quirks) => command.parse_response(
+                Packet::new(apply_quirks(
+                    quirks.as_ref(),
+                    header,
+                    packet.as_slice().to_vec(),
+                ))
+                .get_data()?,
+            ),
+            None => command.parse_response(packet.get_data()?),
+        }
+        .map_err(CommandError::from)?;
+        Ok(RawReply {
+            response,
+            packet,
+            received_at,
+        })
     }
 
     async fn simple_poll(&self) -> Result<(), CommandError> {
@@ -151,8 +555,10 @@ pub trait DeviceCommon {
 
     async fn get_product_code(&self) -> Result<String, CommandError> {
         trace!("requesting product code");
-        let response_packet = self.send_command(RequestProductCodeCommand).await?;
-        let product_code = RequestProductCodeCommand
+        let response_packet = self
+            .send_command(RequestProductCodeCommand::default())
+            .await?;
+        let product_code = RequestProductCodeCommand::default()
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)
             .map(|s| s.to_string())?;
@@ -170,10 +576,34 @@ pub trait DeviceCommon {
         Ok(serial)
     }
 
+    /// Reads the serial number and compares it with the serial number embedded in the product
+    /// id, as returned by the encrypted or ACMI product id commands.
+    ///
+    /// A mismatch is only logged, the serial number read from the device is returned either way.
+    async fn verify_serial_number(
+        &self,
+        product_id_serial: u32,
+    ) -> Result<SerialCode, CommandError> {
+        let serial = self.get_serial_number().await?;
+        if serial.as_number() == product_id_serial {
+            trace!(serial = %serial, "serial number matches product id");
+        } else {
+            warn!(
+                serial = %serial,
+                serial_number = serial.as_number(),
+                product_id_serial,
+                "serial number does not match product id"
+            );
+        }
+        Ok(serial)
+    }
+
     async fn get_software_revision(&self) -> Result<String, CommandError> {
         trace!("requesting software revision");
-        let response_packet = self.send_command(RequestSoftwareRevisionCommand).await?;
-        let revision = RequestSoftwareRevisionCommand
+        let response_packet = self
+            .send_command(RequestSoftwareRevisionCommand::default())
+            .await?;
+        let revision = RequestSoftwareRevisionCommand::default()
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)
             .map(|s| s.to_string())?;
@@ -191,4 +621,168 @@ pub trait DeviceCommon {
         debug!("device reset complete");
         Ok(())
     }
+
+    /// Moves the device to another address with the MDCES `AddressChange` command.
+    ///
+    /// The driver keeps using the old address, create a new driver for the new address.
+    async fn change_address(&self, new_address: BusAddress) -> Result<(), CommandError> {
+        info!(new_address = new_address.get(), "changing device address");
+        let command = AddressChangeCommand::new(new_address)
+            .map_err(|_| CommandError::InvalidAddress(new_address.get()))?;
+        let response_packet = self.send_command(command).await?;
+        AddressChangeCommand::new(new_address)
+            .map_err(|_| CommandError::InvalidAddress(new_address.get()))?
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(new_address = new_address.get(), "device address changed");
+        Ok(())
+    }
+
+    /// Moves the device to a random address with the MDCES `AddressRandom` command.
+    ///
+    /// Every device sharing the address moves, their acknowledgements may collide on the line.
+    async fn randomize_address(&self) -> Result<(), CommandError> {
+        info!("randomizing device address");
+        let response_packet = self.send_command(AddressRandomCommand).await?;
+        AddressRandomCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!("device address randomized");
+        Ok(())
+    }
+
+    /// Asks the device to store its counters in non-volatile memory.
+    ///
+    /// Devices which persist their counters on their own usually NAK this command.
+    async fn store_counters(&self) -> Result<(), CommandError> {
+        debug!("storing counters to EEPROM");
+        let response_packet = self.send_command(CountersToEepromCommand).await?;
+        CountersToEepromCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!("counters stored");
+        Ok(())
+    }
+
+    /// Asks the device to store its configuration, e.g. inhibits or sorter paths, in
+    /// non-volatile memory.
+    ///
+    /// Devices which persist their configuration on their own usually NAK this command.
+    async fn store_configuration(&self) -> Result<(), CommandError> {
+        debug!("storing configuration to EEPROM");
+        let response_packet = self.send_command(ConfigurationToEepromCommand).await?;
+        ConfigurationToEepromCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!("configuration stored");
+        Ok(())
+    }
+
+    /// Requests the layout of the data storage, needed to read or write data blocks.
+    async fn get_data_storage_availability(&self) -> Result<DataStorageAvailability, CommandError> {
+        trace!("requesting data storage availability");
+        let response_packet = self
+            .send_command(RequestDataStorageAvailabilityCommand)
+            .await?;
+        let storage = RequestDataStorageAvailabilityCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(storage = ?storage, "data storage availability received");
+        Ok(storage)
+    }
+
+    /// Reads a data block sized after `storage`, see [`Self::get_data_storage_availability`].
+    async fn read_data_block(
+        &self,
+        storage: &DataStorageAvailability,
+        block_number: u8,
+    ) -> Result<Vec<u8>, CommandError> {
+        trace!(block_number, "reading data block");
+        let command = ReadDataBlockCommand::new(storage, block_number)
+            .map_err(CommandError::InvalidDataBlock)?;
+        let response_packet = self
+            .send_command(
+                ReadDataBlockCommand::new(storage, block_number)
+                    .map_err(CommandError::InvalidDataBlock)?,
+            )
+            .await?;
+        let block = command
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(block_number, length = block.len(), "data block received");
+        Ok(block.to_vec())
+    }
+
+    /// Writes a data block, `data` must fit in the block size of `storage`.
+    async fn write_data_block(
+        &self,
+        storage: &DataStorageAvailability,
+        block_number: u8,
+        data: &[u8],
+    ) -> Result<(), CommandError> {
+        trace!(block_number, length = data.len(), "writing data block");
+        let command = WriteDataBlockCommand::new(storage, block_number, data)
+            .map_err(CommandError::InvalidDataBlock)?;
+        let response_packet = self
+            .send_command(
+                WriteDataBlockCommand::new(storage, block_number, data)
+                    .map_err(CommandError::InvalidDataBlock)?,
+            )
+            .await?;
+        command
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(block_number, "data block written");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::ChecksumType;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::device::payout::PayoutDevice;
+
+    /// Replies Busy to the first `busy` commands, then acknowledges, returns the commands sent.
+    fn busy_device(busy: usize) -> (PayoutDevice, tokio::task::JoinHandle<usize>) {
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(1);
+        let device = Device::new(3, Category::Payout, ChecksumType::Crc8);
+        let responder = tokio::spawn(async move {
+            let mut sent = 0;
+            while let Some(message) = rx.recv().await {
+                let header = if sent < busy {
+                    Header::Busy
+                } else {
+                    Header::Reply
+                };
+                sent += 1;
+                let reply = vec![1, 0, 3, header as u8, 0];
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+            sent
+        });
+        (PayoutDevice::new(device, tx), responder)
+    }
+
+    #[tokio::test]
+    async fn busy_replies_are_retried_with_the_policy() {
+        let (hopper, responder) = busy_device(2);
+        assert_eq!(hopper.simple_poll().await, Ok(()));
+        drop(hopper);
+        assert_eq!(responder.await.expect("should count"), 3);
+
+        let (hopper, responder) = busy_device(usize::MAX);
+        assert_eq!(
+            hopper.simple_poll().await,
+            Err(CommandError::Busy {
+                address: 3,
+                header: Header::SimplePoll
+            })
+        );
+        drop(hopper);
+        // The first attempt and the 3 retries of the default policy.
+        assert_eq!(responder.await.expect("should count"), 4);
+    }
 }
@@ -1,22 +1,34 @@
 #![allow(dead_code)]
 
 use std::{
+    collections::BTreeMap,
+    ops::RangeInclusive,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
 use cc_talk_core::cc_talk::{
-    BillRouteCode, BillRoutingError, BillValidatorPollResult, BitMask, CurrencyToken, Device,
+    BillRouteCode, BillRoutingError, BillValidatorOptionFlags, BillValidatorPollResult, BitMask,
+    CountryScalingFactor, CurrencyToken, Device, OptoReading, OptoScaling,
 };
 use cc_talk_host::{command::Command, device::device_commands::*};
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::{
-    device::base::PollingError, transport::tokio_transport::TransportMessage, util::DropGuard,
+    device::base::PollingError,
+    transport::{timestamp::Stamped, tokio_transport::TransportMessage},
+    util::DropGuard,
 };
 
-use super::base::{CommandError, DeviceCommon, DeviceResult};
+#[cfg(feature = "encryption")]
+use super::event_encryption::EventEncryption;
+use super::{
+    base::{CommandError, DeviceCommon, DeviceResult, LongOperation},
+    quirks::DeviceQuirks,
+    reset::{DeviceReset, ReinitializationFuture, ResetRecovery},
+    triage::TriageConfig,
+};
 
 /// A ccTalk bill validator device driver.
 ///
@@ -24,6 +36,9 @@ use super::base::{CommandError, DeviceCommon, DeviceResult};
 /// over the ccTalk protocol. It supports bill acceptance, inhibit control, escrow
 /// operations, and background polling for bill events.
 ///
+/// Validators without escrow, see [`has_escrow`](Self::has_escrow), are driven through the same
+/// methods: bills are stacked as soon as they are validated and routing them is a no-op.
+///
 /// # Cloning
 ///
 /// `BillValidator` implements [`Clone`] and shares its internal state across clones.
@@ -35,10 +50,24 @@ pub struct BillValidator {
     pub device: Device,
     /// Channel sender for communicating with the transport layer.
     pub sender: mpsc::Sender<TransportMessage>,
+    express_sender: Option<mpsc::Sender<TransportMessage>>,
+    timeout_triage: Option<TriageConfig>,
+    quirks: Option<Arc<dyn DeviceQuirks>>,
+    long_operation: LongOperation,
+    opto_scaling: OptoScaling,
     event_counter: Arc<Mutex<u8>>,
+    master_inhibit: Arc<Mutex<Option<bool>>>,
+    escrow: Arc<Mutex<Option<bool>>>,
     is_polling: Arc<Mutex<bool>>,
+    #[cfg(feature = "encryption")]
+    event_encryption: Option<EventEncryption>,
+    bill_table: Arc<Mutex<Option<BillTable>>>,
+    reset_recovery: ResetRecovery<BillValidator>,
 }
 
+/// Bill types read when building the [`BillTable`].
+pub const BILL_TYPES: RangeInclusive<u8> = 1..=16;
+
 type PollResultReceiver = mpsc::Receiver<DeviceResult<BillValidatorPollResult>>;
 
 impl BillValidator {
@@ -57,11 +86,98 @@ impl BillValidator {
         Self {
             device,
             sender,
+            express_sender: None,
+            quirks: None,
+            timeout_triage: None,
+            long_operation: LongOperation::default(),
+            opto_scaling: OptoScaling::default(),
             event_counter: Arc::new(Mutex::new(0)),
+            master_inhibit: Arc::new(Mutex::new(None)),
+            escrow: Arc::new(Mutex::new(None)),
             is_polling: Arc::new(Mutex::new(false)),
+            #[cfg(feature = "encryption")]
+            event_encryption: None,
+            bill_table: Arc::new(Mutex::new(None)),
+            reset_recovery: ResetRecovery::default(),
         }
     }
 
+    /// Routes express commands through the transport express lane.
+    ///
+    /// See [`CcTalkTokioTransport::express_sender`](crate::transport::tokio_transport::CcTalkTokioTransport::express_sender).
+    #[must_use]
+    pub fn with_express_lane(mut self, express_sender: mpsc::Sender<TransportMessage>) -> Self {
+        self.express_sender = Some(express_sender);
+        self
+    }
+
+    /// Rewrites the replies of a device deviating from the specification before they are
+    /// parsed, see [`DeviceQuirks`].
+    #[must_use]
+    pub fn with_quirks(mut self, quirks: Arc<dyn DeviceQuirks>) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Classifies the timeouts of this driver, see [`DeviceCommon::timeout_triage`].
+    #[must_use]
+    pub const fn with_timeout_triage(self) -> Self {
+        self.with_timeout_triage_config(TriageConfig::new())
+    }
+
+    /// Classifies the timeouts of this driver, knowing the other devices configured on the bus.
+    #[must_use]
+    pub const fn with_timeout_triage_config(mut self, config: TriageConfig) -> Self {
+        self.timeout_triage = Some(config);
+        self
+    }
+
+    /// Sets the format of the opto voltages returned by the device, see the product manual.
+    ///
+    /// Defaults to 8-bit readings scaled to 5.0V.
+    #[must_use]
+    pub fn with_opto_scaling(mut self, opto_scaling: OptoScaling) -> Self {
+        self.opto_scaling = opto_scaling;
+        self
+    }
+
+    /// Registers the re-initialization run when a poll detects an unexpected reset, e.g.
+    /// re-applying the bill inhibits and the escrow mode.
+    ///
+    /// See [`reset`](super::reset) for an example.
+    #[must_use]
+    pub fn with_reset_handler<F>(mut self, handler: F) -> Self
+    where
+        F: for<'a> Fn(&'a BillValidator) -> ReinitializationFuture<'a> + Send + Sync + 'static,
+    {
+        self.reset_recovery.set_handler(handler);
+        self
+    }
+
+    /// Returns `true` if a re-initialization was registered with
+    /// [`with_reset_handler`](Self::with_reset_handler).
+    #[must_use]
+    pub const fn has_reset_handler(&self) -> bool {
+        self.reset_recovery.has_handler()
+    }
+
+    /// Polls with `ReadEncryptedEvents` instead of `ReadBufferedBillEvents`, see [`EventEncryption`].
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn with_event_encryption(mut self, encryption: EventEncryption) -> Self {
+        self.event_encryption = Some(encryption);
+        self
+    }
+
+    /// Emits a [`DeviceReset`] to `events` whenever a poll detects an unexpected reset.
+    ///
+    /// Every call adds a receiver, the resets are emitted to all of them.
+    #[must_use]
+    pub fn with_reset_events(mut self, events: mpsc::Sender<DeviceReset>) -> Self {
+        self.reset_recovery.add_events(events);
+        self
+    }
+
     /// Returns the current event counter value.
     ///
     /// The event counter tracks the number of bill events that have occurred.
@@ -94,6 +210,7 @@ impl BillValidator {
             .map_err(|_| CommandError::BufferOverflow)?
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)?;
+        *self.master_inhibit.lock().expect("should not be poisoned") = Some(inhibit);
         info!(inhibit, "master inhibit status set");
         Ok(())
     }
@@ -129,10 +246,16 @@ impl BillValidator {
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)
             .map(|bytes| bytes[0] == 0)?;
+        *self.master_inhibit.lock().expect("should not be poisoned") = Some(status);
         debug!(inhibited = status, "master inhibit status received");
         Ok(status)
     }
 
+    /// Returns the master inhibit status last set or read, `None` until then.
+    pub fn cached_master_inhibit(&self) -> Option<bool> {
+        *self.master_inhibit.lock().expect("should not be poisoned")
+    }
+
     /// Checks if master inhibit is currently enabled.
     ///
     /// Returns `true` if the validator is rejecting all bills.
@@ -170,8 +293,40 @@ impl BillValidator {
         Ok(result)
     }
 
+    /// Requests the option flags of the bill validator, whether it has a stacker, an escrow and
+    /// which counters it keeps.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_option_flags(&self) -> DeviceResult<BillValidatorOptionFlags> {
+        trace!("requesting option flags");
+        let response_packet = self.send_command(RequestOptionFlagsCommand).await?;
+        let flags = RequestOptionFlagsCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?
+            .for_bill_validator();
+        *self.escrow.lock().expect("should not be poisoned") = Some(flags.escrow());
+        debug!(flags = ?flags, "option flags received");
+        Ok(flags)
+    }
+
+    /// Returns `true` if the validator can hold bills in escrow, the option flags are only
+    /// requested the first time.
+    ///
+    /// A validator without escrow stacks a bill as soon as it is validated and reports a
+    /// credit without a pending credit first. [`route_bill`](Self::route_bill) then answers
+    /// [`BillRoutingError::EscrowEmpty`] without talking to the validator and
+    /// [`set_operating_mode`](Self::set_operating_mode) leaves the escrow off.
+    pub async fn has_escrow(&self) -> DeviceResult<bool> {
+        let cached = *self.escrow.lock().expect("should not be poisoned");
+        match cached {
+            Some(escrow) => Ok(escrow),
+            None => Ok(self.get_option_flags().await?.escrow()),
+        }
+    }
+
     /// Sets the bill operating mode of the bill validator.
     ///
+    /// The escrow stays off on validators without one, see [`has_escrow`](Self::has_escrow).
+    ///
     /// # Arguments
     ///
     /// * `use_stacker` - `true` to enable the stacker for storing accepted bills.
@@ -183,6 +338,7 @@ impl BillValidator {
         use_stacker: bool,
         use_escrow: bool,
     ) -> DeviceResult<()> {
+        let use_escrow = use_escrow && self.escrow_or_unknown().await;
         debug!(use_stacker, use_escrow, "setting bill operating mode");
         let command = ModifyBillOperatingModeCommand::new(use_stacker, use_escrow);
         let response_packet = self.send_command(command).await?;
@@ -235,6 +391,158 @@ impl BillValidator {
         Ok(bills)
     }
 
+    /// Requests the scaling factor and decimal places the device uses for a country.
+    ///
+    /// Devices reply with a zero scaling factor for countries they do not support, see
+    /// [`CountryScalingFactor::is_supported`].
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_country_scaling_factor(
+        &self,
+        country_code: &str,
+    ) -> DeviceResult<CountryScalingFactor> {
+        trace!(country_code, "requesting country scaling factor");
+        let command = RequestCountryScalingFactorCommand::new(country_code);
+        let response_packet = self.send_command(command).await?;
+        let scaling = RequestCountryScalingFactorCommand::new(country_code)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(country_code, ?scaling, "country scaling factor received");
+        Ok(scaling)
+    }
+
+    /// Returns the bill id table, reading it from the device on first use.
+    ///
+    /// The table is cached and shared between clones. It is refreshed after a bank switch done
+    /// through [`set_bank`](Self::set_bank), a bill table upgrade finished through
+    /// [`finish_bill_table_upgrade`](Self::finish_bill_table_upgrade) or when a poll reports a
+    /// device reset.
+    pub async fn bill_table(&self) -> DeviceResult<BillTable> {
+        let cached = self
+            .bill_table
+            .lock()
+            .expect("should not be poisoned")
+            .clone();
+        match cached {
+            Some(table) => Ok(table),
+            None => self.refresh_bill_table().await,
+        }
+    }
+
+    /// Reads every bill type in [`BILL_TYPES`] and the scaling factor of each country, then
+    /// replaces the cached bill table.
+    ///
+    /// Bill types which cannot be read are left out of the table. Bills of a country whose
+    /// scaling factor cannot be read are valued from their id alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error if no bill type could be read.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn refresh_bill_table(&self) -> DeviceResult<BillTable> {
+        debug!("refreshing bill table");
+        let mut bills = BTreeMap::new();
+        let mut last_error = None;
+        for bill_type in BILL_TYPES {
+            match self.request_bill_id(bill_type).await {
+                Ok(token) => {
+                    bills.insert(bill_type, token);
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        if bills.is_empty()
+            && let Some(error) = last_error
+        {
+            warn!(error = %error, "unable to read any bill type");
+            return Err(error);
+        }
+
+        let mut scaling = BTreeMap::new();
+        for token in bills.values() {
+            let CurrencyToken::Currency(value) = token else {
+                continue;
+            };
+            let country_code = value.country_code();
+            if scaling.contains_key(country_code) {
+                continue;
+            }
+            match self.request_country_scaling_factor(country_code).await {
+                Ok(factor) if factor.is_supported() => {
+                    scaling.insert(country_code.to_string(), factor);
+                }
+                Ok(_) => {
+                    warn!(
+                        country_code,
+                        "country not supported by the device, using the bill id value"
+                    );
+                }
+                Err(error) => {
+                    warn!(country_code, error = %error, "failed to read country scaling factor, using the bill id value");
+                }
+            }
+        }
+
+        let table = BillTable { bills, scaling };
+        info!(bill_types = table.len(), "bill table refreshed");
+        *self.bill_table.lock().expect("should not be poisoned") = Some(table.clone());
+        Ok(table)
+    }
+
+    /// Drops the cached bill table, the next call to [`bill_table`](Self::bill_table) reads it
+    /// again from the device.
+    pub fn invalidate_bill_table(&self) {
+        if self
+            .bill_table
+            .lock()
+            .expect("should not be poisoned")
+            .take()
+            .is_some()
+        {
+            debug!("bill table invalidated");
+        }
+    }
+
+    /// Selects the bill bank and invalidates the cached bill table.
+    #[instrument(skip(self), fields(bank), level = "debug")]
+    pub async fn set_bank(&self, bank: u8) -> DeviceResult<()> {
+        debug!(bank, "selecting bill bank");
+        let response_packet = self
+            .send_command(ModifyBankSelectCommand::new(bank))
+            .await?;
+        ModifyBankSelectCommand::new(bank)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        self.invalidate_bill_table();
+        info!(bank, "bill bank selected");
+        Ok(())
+    }
+
+    /// Returns the currently selected bill bank.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_bank(&self) -> DeviceResult<u8> {
+        trace!("requesting bill bank");
+        let response_packet = self.send_command(RequestBankSelectCommand).await?;
+        let bank = RequestBankSelectCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(bank, "bill bank received");
+        Ok(bank)
+    }
+
+    /// Ends a bill table upgrade and invalidates the cached bill table.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn finish_bill_table_upgrade(&self) -> DeviceResult<()> {
+        debug!("finishing bill table upgrade");
+        let response_packet = self.send_command(FinishBillTableUpgradeCommand).await?;
+        FinishBillTableUpgradeCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        self.invalidate_bill_table();
+        info!("bill table upgrade finished");
+        Ok(())
+    }
+
     /// Sets the inhibit status for each of the 16 bill positions.
     ///
     /// # Arguments
@@ -264,6 +572,50 @@ impl BillValidator {
         Ok(())
     }
 
+    /// Requests the bill positions of a country, bit `n` is set when position `n + 1` holds a
+    /// bill of the country.
+    ///
+    /// Devices reply with an empty mask for countries they do not know.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_bill_position(&self, country_code: &str) -> DeviceResult<BitMask<2>> {
+        trace!(country_code, "requesting bill position");
+        let command = RequestBillPositionCommand::<2>::new(country_code);
+        let response_packet = self.send_command(command).await?;
+        let positions = RequestBillPositionCommand::<2>::new(country_code)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(
+            country_code,
+            positions = positions.count_ones(),
+            "bill position received"
+        );
+        Ok(positions)
+    }
+
+    /// Enables the bills of a country and inhibits every other bill.
+    ///
+    /// The position mask of the country is written as is as the new inhibit mask, returns the
+    /// positions which are now enabled. A country unknown to the device inhibits all bills.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn enable_country(&self, country_code: &str) -> DeviceResult<BitMask<2>> {
+        let positions = self.request_bill_position(country_code).await?;
+        if !positions.any() {
+            warn!(
+                country_code,
+                "country has no bill position, inhibiting all bills"
+            );
+        }
+        let mut inhibits = [true; 16];
+        for (position, inhibit) in inhibits.iter_mut().enumerate().take(positions.len()) {
+            *inhibit = !positions
+                .get_bit(position)
+                .map_err(|_| CommandError::BufferOverflow)?;
+        }
+        self.set_bill_inhibits(inhibits).await?;
+        info!(country_code, "country enabled");
+        Ok(positions)
+    }
+
     /// Sets the same inhibit status for all 16 bill positions.
     ///
     /// # Arguments
@@ -317,11 +669,18 @@ impl BillValidator {
     ///
     /// Returns `Ok(None)` if the routing was successful, or `Ok(Some(error))` if
     /// there was a routing error (e.g., stacker full, bill jammed).
+    ///
+    /// Validators without escrow, see [`has_escrow`](Self::has_escrow), already stacked the
+    /// bill, `Ok(Some(BillRoutingError::EscrowEmpty))` is returned without sending anything.
     #[instrument(skip(self), fields(route_code = ?route_code), level = "info")]
     pub async fn route_bill(
         &self,
         route_code: BillRouteCode,
     ) -> DeviceResult<Option<BillRoutingError>> {
+        if !self.escrow_or_unknown().await {
+            debug!(route_code = ?route_code, "no escrow, bill stacked on validation");
+            return Ok(Some(BillRoutingError::EscrowEmpty));
+        }
         info!(route_code = ?route_code, "routing bill");
         let command = RouteBillCommand::new(route_code);
         let response_packet = self.send_command(command).await?;
@@ -335,6 +694,19 @@ impl BillValidator {
         Ok(result)
     }
 
+    /// Returns `false` only if the option flags say the validator has no escrow, validators
+    /// which do not report their option flags are driven as if they had one.
+    async fn escrow_or_unknown(&self) -> bool {
+        self.has_escrow().await.unwrap_or_else(|error| {
+            debug!(error = %error, "option flags unavailable, assuming an escrow");
+            if error.is_nack() {
+                // The validator does not support the request, asking again will not help.
+                *self.escrow.lock().expect("should not be poisoned") = Some(true);
+            }
+            true
+        })
+    }
+
     /// Polls the bill validator for buffered bill events.
     ///
     /// This method reads the event buffer from the bill validator and returns
@@ -343,19 +715,36 @@ impl BillValidator {
     ///
     /// For continuous polling, consider using [`try_background_polling`](Self::try_background_polling)
     /// which handles the polling loop automatically.
+    ///
+    /// A device reset, e.g. after a currency update, invalidates the cached bill table and runs
+    /// the handler registered with [`with_reset_handler`](Self::with_reset_handler).
     pub async fn poll(&self) -> DeviceResult<BillValidatorPollResult> {
+        self.poll_stamped().await.map(|result| result.value)
+    }
+
+    /// Polls like [`poll`](Self::poll), the result is stamped with the time the transport read
+    /// the event buffer.
+    pub async fn poll_stamped(&self) -> DeviceResult<Stamped<BillValidatorPollResult>> {
         trace!("polling bill validator");
-        let response_packet = self
-            .send_command(ReadBufferedBillEventsCommand::default())
-            .await?;
+        let Stamped {
+            value: buffer,
+            received_at,
+        } = self.read_event_buffer().await?;
+        let counter_cleared = buffer.first() == Some(&0);
+        let unexpected_reset = counter_cleared && self.event_counter() != 0;
+        if unexpected_reset {
+            info!("bill validator reset detected");
+            self.invalidate_bill_table();
+        }
         let result = ReadBufferedBillEventsCommand::new(self.event_counter())
-            .parse_response(response_packet.get_data()?)
+            .parse_response(&buffer)
             .map_err(CommandError::from)
             .inspect(|result| {
-                self.event_counter
-                    .lock()
-                    .expect("should not be poisoned")
-                    .clone_from(&result.event_counter);
+                *self.event_counter.lock().expect("should not be poisoned") = if counter_cleared {
+                    0
+                } else {
+                    result.event_counter
+                };
             })?;
         if !result.events.is_empty() {
             debug!(
@@ -364,7 +753,28 @@ impl BillValidator {
                 "bill validator poll returned events"
             );
         }
-        Ok(result)
+        if unexpected_reset {
+            self.reset_recovery
+                .recover(self.device.address(), self)
+                .await;
+        }
+        Ok(Stamped::new(result, received_at))
+    }
+
+    /// Reads the event buffer, decrypted if [`with_event_encryption`](Self::with_event_encryption)
+    /// was set.
+    async fn read_event_buffer(&self) -> DeviceResult<Stamped<Vec<u8>>> {
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.event_encryption {
+            return encryption.read_events(self).await;
+        }
+        let reply = self
+            .send_stamped_command(ReadBufferedBillEventsCommand::default())
+            .await?;
+        Ok(Stamped::new(
+            reply.value.get_data()?.to_vec(),
+            reply.received_at,
+        ))
     }
 
     /// Returns the recommended polling priority (interval) for this device.
@@ -382,6 +792,56 @@ impl BillValidator {
         Ok(priority)
     }
 
+    /// Reads the opto voltages, scaled with the configured [`OptoScaling`].
+    #[instrument(skip(self), level = "debug")]
+    pub async fn read_opto_voltages(&self) -> DeviceResult<Vec<OptoReading>> {
+        trace!("reading opto voltages");
+        let command = ReadOptoVoltagesCommand::new(self.opto_scaling);
+        let response_packet = self.send_command(command).await?;
+        let readings = command
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(count = readings.len(), "opto voltages received");
+        Ok(readings.into_iter().collect())
+    }
+
+    /// Dumps the opto voltages, flagging the ones under `degraded_below_mv`.
+    ///
+    /// Optos usually lose voltage as they get dirty or age, checking them regularly
+    /// allows catching a failing opto before the validator starts rejecting bills.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn opto_diagnostics(&self, degraded_below_mv: u16) -> DeviceResult<OptoDiagnostics> {
+        let readings = self.read_opto_voltages().await?;
+        for (index, reading) in readings.iter().enumerate() {
+            if reading.is_below(degraded_below_mv) {
+                warn!(
+                    index,
+                    raw = reading.raw,
+                    millivolts = reading.millivolts,
+                    "opto voltage under threshold"
+                );
+            } else {
+                debug!(
+                    index,
+                    raw = reading.raw,
+                    millivolts = reading.millivolts,
+                    "opto voltage"
+                );
+            }
+        }
+        Ok(OptoDiagnostics {
+            readings,
+            degraded_below_mv,
+        })
+    }
+
+    /// Returns `true` while a long operation, e.g. a self-check, is running on the bill validator.
+    ///
+    /// Background polling skips its polls in the meantime.
+    pub fn is_busy(&self) -> bool {
+        self.long_operation.is_running()
+    }
+
     /// Starts background polling for bill events.
     ///
     /// This method spawns a background task that continuously polls the bill validator
@@ -423,7 +883,8 @@ impl BillValidator {
         &self,
         interval: Duration,
         channel_size: usize,
-    ) -> Result<DropGuard<PollResultReceiver, impl FnOnce(PollResultReceiver)>, PollingError> {
+    ) -> Result<DropGuard<PollResultReceiver, impl FnOnce(PollResultReceiver) + use<>>, PollingError>
+    {
         let mut is_polling = self.is_polling.lock().expect("should not be poisoned");
         if *is_polling {
             warn!("background polling already active");
@@ -444,12 +905,16 @@ impl BillValidator {
         let (stop_signal, mut stop_receiver) = oneshot::channel();
         let handle = tokio::spawn(async move {
             loop {
-                let poll_result = bv_clone.poll().await;
-                if tx.send(poll_result).await.is_err() {
-                    error!(
-                        "unable to send poll result, receiver may have been dropped. Stopping background polling."
-                    );
-                    break;
+                if bv_clone.is_busy() {
+                    trace!("long operation running, skipping poll");
+                } else {
+                    let poll_result = bv_clone.poll().await;
+                    if tx.send(poll_result).await.is_err() {
+                        error!(
+                            "unable to send poll result, receiver may have been dropped. Stopping background polling."
+                        );
+                        break;
+                    }
                 }
 
                 if stop_receiver.try_recv().is_ok() {
@@ -483,12 +948,208 @@ impl DeviceCommon for BillValidator {
     fn get_sender(&self) -> &mpsc::Sender<TransportMessage> {
         &self.sender
     }
+
+    fn get_express_sender(&self) -> Option<&mpsc::Sender<TransportMessage>> {
+        self.express_sender.as_ref()
+    }
+
+    fn get_quirks(&self) -> Option<&Arc<dyn DeviceQuirks>> {
+        self.quirks.as_ref()
+    }
+
+    fn timeout_triage(&self) -> Option<&TriageConfig> {
+        self.timeout_triage.as_ref()
+    }
+
+    fn get_long_operation(&self) -> Option<&LongOperation> {
+        Some(&self.long_operation)
+    }
+}
+
+/// Bill ids of a bill validator indexed by bill type, see [`BillValidator::bill_table`].
+///
+/// Values are scaled with the country scaling factors reported by the device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BillTable {
+    bills: BTreeMap<u8, CurrencyToken>,
+    scaling: BTreeMap<String, CountryScalingFactor>,
+}
+
+impl BillTable {
+    /// Returns the bill id of the given bill type.
+    pub fn get(&self, bill_type: u8) -> Option<&CurrencyToken> {
+        self.bills.get(&bill_type)
+    }
+
+    /// Returns the scaling factor read for a country, if the device supports it.
+    pub fn scaling_factor(&self, country_code: &str) -> Option<CountryScalingFactor> {
+        self.scaling.get(country_code).copied()
+    }
+
+    /// Returns the value of the given bill type in smallest currency units.
+    ///
+    /// Tokens and unknown bill types have no value.
+    pub fn value(&self, bill_type: u8) -> Option<u32> {
+        match self.get(bill_type)? {
+            CurrencyToken::Token => None,
+            CurrencyToken::Currency(value) => match self.scaling_factor(value.country_code()) {
+                Some(scaling) => value.scaled(scaling).map(|v| v.smallest_unit_value()),
+                None => Some(value.smallest_unit_value()),
+            },
+        }
+    }
+
+    /// Iterates over the known bill types in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &CurrencyToken)> {
+        self.bills
+            .iter()
+            .map(|(bill_type, token)| (*bill_type, token))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bills.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bills.is_empty()
+    }
+}
+
+impl FromIterator<(u8, CurrencyToken)> for BillTable {
+    fn from_iter<T: IntoIterator<Item = (u8, CurrencyToken)>>(iter: T) -> Self {
+        BillTable {
+            bills: iter.into_iter().collect(),
+            scaling: BTreeMap::new(),
+        }
+    }
+}
+
+/// Opto voltages read by [`BillValidator::opto_diagnostics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptoDiagnostics {
+    pub readings: Vec<OptoReading>,
+    pub degraded_below_mv: u16,
+}
+
+impl OptoDiagnostics {
+    /// Returns the index and reading of every opto under the threshold.
+    pub fn degraded(&self) -> impl Iterator<Item = (usize, &OptoReading)> {
+        self.readings
+            .iter()
+            .enumerate()
+            .filter(|(_, reading)| reading.is_below(self.degraded_below_mv))
+    }
+
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.degraded().next().is_none()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cc_talk_core::cc_talk::{Category, ChecksumType};
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Header};
+
+    #[test]
+    fn opto_diagnostics_flags_degraded_readings() {
+        let scaling = OptoScaling::default();
+        let diagnostics = OptoDiagnostics {
+            readings: vec![
+                scaling.reading(200),
+                scaling.reading(40),
+                scaling.reading(180),
+            ],
+            degraded_below_mv: 3000,
+        };
+
+        let degraded: Vec<usize> = diagnostics.degraded().map(|(index, _)| index).collect();
+        assert_eq!(degraded, vec![1]);
+        assert!(!diagnostics.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn bill_table_is_scaled_and_read_again_after_reset() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(40, Category::BillValidator, ChecksumType::Crc8);
+        let validator = BillValidator::new(device, tx);
+
+        tokio::spawn(async move {
+            let mut bill_id_requests = 0;
+            let mut polls = 0;
+            while let Some(message) = rx.recv().await {
+                let data: Vec<u8> = match message.header {
+                    Header::RequestBillId => {
+                        bill_id_requests += 1;
+                        match (message.data[0], bill_id_requests <= 16) {
+                            (1, true) => b"EU0005A".to_vec(),
+                            (1, false) => b"EU0020A".to_vec(),
+                            _ => b".......".to_vec(),
+                        }
+                    }
+                    Header::RequestCountryScalingFactor => vec![100, 0, 2],
+                    Header::ReadBufferedBillEvents => {
+                        polls += 1;
+                        if polls == 1 { vec![1, 1, 0] } else { vec![0] }
+                    }
+                    header => panic!("unexpected header {header:?}"),
+                };
+                let mut reply = vec![1, data.len() as u8, 40, 0];
+                reply.extend(data);
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+        });
+
+        let table = validator.bill_table().await.expect("should read the table");
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.value(1), Some(500));
+        assert_eq!(
+            table.scaling_factor("EU"),
+            Some(CountryScalingFactor::new(100, 2))
+        );
+
+        validator.poll().await.expect("should poll");
+        assert!(validator.bill_table.lock().unwrap().is_some());
+
+        validator.poll().await.expect("should poll the reset");
+        assert!(validator.bill_table.lock().unwrap().is_none());
+        let table = validator.bill_table().await.expect("should read the table");
+        assert_eq!(table.value(1), Some(2000));
+    }
+
+    #[tokio::test]
+    async fn enable_country_writes_the_position_mask_as_inhibit_mask() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(40, Category::BillValidator, ChecksumType::Crc8);
+        let validator = BillValidator::new(device, tx);
+
+        let responder = tokio::spawn(async move {
+            let message = rx.recv().await.expect("should request the position");
+            assert_eq!(message.header, Header::RequestBillPosition);
+            assert_eq!(message.data, b"GB");
+            message
+                .respond_to
+                .send(Ok(vec![1, 1, 40, 0, 0b0011_0000, 0]))
+                .expect("should respond");
+
+            let message = rx.recv().await.expect("should modify the inhibits");
+            assert_eq!(message.header, Header::ModifyInhibitStatus);
+            assert_eq!(message.data, [0b0011_0000, 0]);
+            message
+                .respond_to
+                .send(Ok(vec![1, 0, 40, 0, 0]))
+                .expect("should respond");
+        });
+
+        let enabled = validator
+            .enable_country("GB")
+            .await
+            .expect("should enable the country");
+        assert_eq!(enabled.count_ones(), 2);
+        assert!(enabled.get_bit(4).unwrap() && enabled.get_bit(5).unwrap());
+        responder.await.expect("responder should finish");
+    }
 
     fn create_test_validator() -> BillValidator {
         let (tx, _rx) = mpsc::channel(1);
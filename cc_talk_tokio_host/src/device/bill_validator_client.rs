@@ -0,0 +1,458 @@
+use std::{
+    collections::VecDeque,
+    ops::DerefMut,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use cc_talk_core::cc_talk::{
+    BillEvent, BillEventReason, BillRouteCode, BillRoutingError, BillValidatorPollResult,
+};
+use futures_core::Stream;
+use tokio::sync::mpsc;
+use tracing::{debug, info, instrument, warn};
+
+use super::{
+    base::{DeviceResult, PollingError},
+    bill_validator::BillValidator,
+    reset::DeviceReset,
+};
+
+type PollResultReceiver = mpsc::Receiver<DeviceResult<BillValidatorPollResult>>;
+
+/// Resets are detected once per poll, a few of them are buffered.
+const RESET_CHANNEL_SIZE: usize = 4;
+
+/// Item of a [`BillValidatorClient`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BillClientEvent {
+    /// A credit, an escrowed bill, a reject or an error, in the order they happened.
+    Event(BillEvent),
+    /// Events which were overwritten in the event buffer before they could be read, reported
+    /// before the events read along with them.
+    Lost(u8),
+    /// The validator lost power and cleared its event counter, a bill held in escrow is gone.
+    ///
+    /// Contains the result of re-enabling the validator, `None` if it was not enabled through
+    /// [`BillValidatorClient::enable`], or the result of the reset handler registered on the
+    /// validator.
+    PowerLoss(Option<DeviceResult<()>>),
+}
+
+/// High level bill validator client, handling the escrow workflow.
+///
+/// The client polls `ReadBufferedBillEvents` in the background and yields the events as a
+/// [`Stream`]. A bill held in escrow is reported as [`BillEvent::PendingCredit`], it is then
+/// stacked with [`accept`](Self::accept), given back with [`return_bill`](Self::return_bill) or
+/// kept longer with [`extend_escrow`](Self::extend_escrow). The credit is reported once the bill
+/// is stacked.
+///
+/// Validators without escrow, see [`BillValidator::has_escrow`], report the credit straight
+/// away, routing then answers [`BillRoutingError::EscrowEmpty`] and the same code drives both.
+///
+/// When the validator reports a power loss, i.e. its event counter goes back to 0, the inhibits
+/// given to [`enable`](Self::enable) are written again and the master inhibit is released. The
+/// power loss is detected by the driver, see [`reset`](super::reset), a reset handler registered
+/// with [`BillValidator::with_reset_handler`] replaces the re-enabling of the client.
+///
+/// Polling stops when the client is dropped.
+pub struct BillValidatorClient {
+    validator: BillValidator,
+    receiver: Box<dyn DerefMut<Target = PollResultReceiver> + Send + Sync>,
+    resets: mpsc::Receiver<DeviceReset>,
+    pending: VecDeque<BillClientEvent>,
+    escrow: Option<u8>,
+    /// Inhibits re-applied after a power loss, shared with the reset handler of the client.
+    inhibits: Arc<Mutex<Option<[bool; 16]>>>,
+    /// `false` if a reset handler was registered on the validator before the client.
+    reenables: bool,
+}
+
+impl std::fmt::Debug for BillValidatorClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BillValidatorClient")
+            .field("validator", &self.validator)
+            .field("pending", &self.pending)
+            .field("escrow", &self.escrow)
+            .field("inhibits", &self.inhibits)
+            .field("reenables", &self.reenables)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BillValidatorClient {
+    /// Starts polling `validator` every `interval`, see
+    /// [`BillValidator::try_background_polling`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PollingError::AlreadyLeased`] if the validator is already polled in the
+    /// background.
+    pub fn new(
+        validator: BillValidator,
+        interval: Duration,
+        channel_size: usize,
+    ) -> Result<Self, PollingError> {
+        let (resets_tx, resets) = mpsc::channel(RESET_CHANNEL_SIZE);
+        let inhibits = Arc::new(Mutex::new(None));
+        let reenables = !validator.has_reset_handler();
+        let mut validator = validator.with_reset_events(resets_tx);
+        if reenables {
+            let inhibits = Arc::clone(&inhibits);
+            validator = validator.with_reset_handler(move |validator| {
+                let inhibits = *inhibits.lock().expect("should not be poisoned");
+                Box::pin(async move {
+                    match inhibits {
+                        Some(inhibits) => reenable(validator, inhibits).await,
+                        None => Ok(()),
+                    }
+                })
+            });
+        }
+        let receiver = validator.try_background_polling(interval, channel_size)?;
+        Ok(Self {
+            validator,
+            receiver: Box::new(receiver),
+            resets,
+            pending: VecDeque::new(),
+            escrow: None,
+            inhibits,
+            reenables,
+        })
+    }
+
+    /// Returns the validator driven by the client.
+    pub fn validator(&self) -> &BillValidator {
+        &self.validator
+    }
+
+    /// Returns the bill type held in escrow, if any.
+    pub fn escrow(&self) -> Option<u8> {
+        self.escrow
+    }
+
+    /// Writes the bill inhibits and releases the master inhibit.
+    ///
+    /// The inhibits are written again after a power loss, until [`disable`](Self::disable) is
+    /// called.
+    ///
+    /// # Arguments
+    ///
+    /// * `inhibits` - An array of 16 boolean values where `true` disables the bill
+    ///   and `false` enables it.
+    #[instrument(skip(self), level = "info")]
+    pub async fn enable(&mut self, inhibits: [bool; 16]) -> DeviceResult<()> {
+        *self.inhibits.lock().expect("should not be poisoned") = Some(inhibits);
+        reenable(&self.validator, inhibits).await
+    }
+
+    /// Sets the master inhibit, the validator rejects all bills and is no longer re-enabled
+    /// after a power loss.
+    #[instrument(skip(self), level = "info")]
+    pub async fn disable(&mut self) -> DeviceResult<()> {
+        *self.inhibits.lock().expect("should not be poisoned") = None;
+        self.validator.enable_master_inhibit().await
+    }
+
+    /// Stacks the bill held in escrow.
+    ///
+    /// Returns `Ok(Some(error))` if the validator could not route the bill, see
+    /// [`BillValidator::route_bill`].
+    pub async fn accept(&mut self) -> DeviceResult<Option<BillRoutingError>> {
+        self.route(BillRouteCode::Stack).await
+    }
+
+    /// Gives the bill held in escrow back to the customer.
+    ///
+    /// Returns `Ok(Some(error))` if the validator could not route the bill, see
+    /// [`BillValidator::route_bill`].
+    pub async fn return_bill(&mut self) -> DeviceResult<Option<BillRoutingError>> {
+        self.route(BillRouteCode::Return).await
+    }
+
+    /// Restarts the escrow timeout of the validator, the bill stays in escrow.
+    ///
+    /// Returns `Ok(Some(error))` if the validator could not route the bill, see
+    /// [`BillValidator::route_bill`].
+    pub async fn extend_escrow(&mut self) -> DeviceResult<Option<BillRoutingError>> {
+        self.route(BillRouteCode::ExtendEscrow).await
+    }
+
+    async fn route(&mut self, route_code: BillRouteCode) -> DeviceResult<Option<BillRoutingError>> {
+        if self.escrow.is_none() {
+            debug!(route_code = ?route_code, "no bill known in escrow, routing anyway");
+        }
+        let result = self.validator.route_bill(route_code).await?;
+        if result == Some(BillRoutingError::EscrowEmpty) {
+            self.escrow = None;
+        }
+        Ok(result)
+    }
+
+    /// Waits for the next event, see [`Stream::poll_next`].
+    pub async fn next(&mut self) -> Option<DeviceResult<BillClientEvent>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Queues the power loss detected by the driver, the bill held in escrow is gone.
+    fn push_reset(&mut self, reset: DeviceReset) {
+        warn!(
+            address = reset.address,
+            "bill validator power loss detected"
+        );
+        self.escrow = None;
+        let enabled = self
+            .inhibits
+            .lock()
+            .expect("should not be poisoned")
+            .is_some();
+        let reinitialization = reset
+            .reinitialization
+            .filter(|_| enabled || !self.reenables);
+        self.pending
+            .push_back(BillClientEvent::PowerLoss(reinitialization));
+    }
+
+    /// Queues the events of a poll, oldest first, the device reports them newest first.
+    fn push_poll_result(&mut self, result: &BillValidatorPollResult) {
+        if result.lost_events > 0 {
+            self.pending
+                .push_back(BillClientEvent::Lost(result.lost_events));
+        }
+        for event in result.events.iter().rev() {
+            match event {
+                BillEvent::PendingCredit(bill_type) => self.escrow = Some(*bill_type),
+                BillEvent::Credit(_)
+                | BillEvent::Status(BillEventReason::BillReturnedFromEscrow) => self.escrow = None,
+                _ => {}
+            }
+            self.pending
+                .push_back(BillClientEvent::Event(event.clone()));
+        }
+    }
+}
+
+/// Writes the inhibits then releases the master inhibit.
+async fn reenable(validator: &BillValidator, inhibits: [bool; 16]) -> DeviceResult<()> {
+    validator.set_bill_inhibits(inhibits).await?;
+    validator.disable_master_inhibit().await?;
+    info!(
+        address = validator.device.address(),
+        "bill validator enabled"
+    );
+    Ok(())
+}
+
+impl Stream for BillValidatorClient {
+    type Item = DeviceResult<BillClientEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            // The reset is emitted before the poll result it was detected in is sent.
+            if let Poll::Ready(Some(reset)) = self.resets.poll_recv(cx) {
+                self.push_reset(reset);
+                continue;
+            }
+            match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(Ok(result))) => self.push_poll_result(&result),
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Device, Header};
+
+    use super::*;
+
+    fn frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![1, data.len() as u8, 40, 0];
+        frame.extend_from_slice(data);
+        let sum = frame.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        frame.push(sum.wrapping_neg());
+        frame
+    }
+
+    #[tokio::test]
+    async fn escrowed_bill_is_stacked_and_validator_re_enabled_after_power_loss() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(40, Category::BillValidator, ChecksumType::Crc8);
+        let validator = BillValidator::new(device, tx);
+
+        tokio::spawn(async move {
+            let mut stacked = false;
+            let mut credit_polls = 0;
+            while let Some(message) = rx.recv().await {
+                let data: Vec<u8> = match message.header {
+                    Header::ModifyInhibitStatus | Header::ModifyMasterInhibitStatus => vec![],
+                    Header::RequestOptionFlags => vec![0b11],
+                    Header::RouteBill => {
+                        assert_eq!(message.data, [1]);
+                        stacked = true;
+                        vec![]
+                    }
+                    Header::ReadBufferedBillEvents if !stacked => {
+                        vec![1, 3, 1, 0, 0, 0, 0, 0, 0, 0, 0]
+                    }
+                    Header::ReadBufferedBillEvents if credit_polls < 2 => {
+                        credit_polls += 1;
+                        vec![2, 3, 0, 3, 1, 0, 0, 0, 0, 0, 0]
+                    }
+                    Header::ReadBufferedBillEvents => vec![0; 11],
+                    header => panic!("unexpected header {header:?}"),
+                };
+                message
+                    .respond_to
+                    .send(Ok(frame(&data)))
+                    .expect("should respond");
+            }
+        });
+
+        let mut client = BillValidatorClient::new(validator, Duration::from_millis(1), 1)
+            .expect("should start polling");
+        client.enable([false; 16]).await.expect("should enable");
+
+        let event = client.next().await.expect("should poll");
+        assert_eq!(
+            event,
+            Ok(BillClientEvent::Event(BillEvent::PendingCredit(3)))
+        );
+        assert_eq!(client.escrow(), Some(3));
+
+        assert_eq!(client.accept().await, Ok(None));
+        let event = client.next().await.expect("should poll");
+        assert_eq!(event, Ok(BillClientEvent::Event(BillEvent::Credit(3))));
+        assert_eq!(client.escrow(), None);
+
+        let event = client.next().await.expect("should poll");
+        assert_eq!(event, Ok(BillClientEvent::PowerLoss(Some(Ok(())))));
+        assert!(
+            client
+                .validator()
+                .try_background_polling(Duration::from_millis(1), 1)
+                .is_err(),
+            "client should own the polling"
+        );
+    }
+
+    #[tokio::test]
+    async fn validators_without_escrow_credit_bills_directly() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(40, Category::BillValidator, ChecksumType::Crc8);
+        let validator = BillValidator::new(device, tx);
+
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let data: Vec<u8> = match message.header {
+                    Header::RequestOptionFlags => vec![0b01],
+                    Header::ModifyBillOperatingMode => {
+                        assert_eq!(message.data, [0b01]);
+                        vec![]
+                    }
+                    Header::ReadBufferedBillEvents => vec![1, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                    header => panic!("unexpected header {header:?}"),
+                };
+                message
+                    .respond_to
+                    .send(Ok(frame(&data)))
+                    .expect("should respond");
+            }
+        });
+
+        assert_eq!(validator.has_escrow().await, Ok(false));
+        validator
+            .set_operating_mode(true, true)
+            .await
+            .expect("should set the operating mode");
+        let mut client = BillValidatorClient::new(validator, Duration::from_millis(1), 1)
+            .expect("should start polling");
+
+        let event = client.next().await.expect("should poll");
+        assert_eq!(event, Ok(BillClientEvent::Event(BillEvent::Credit(3))));
+        assert_eq!(client.escrow(), None);
+        assert_eq!(
+            client.accept().await,
+            Ok(Some(BillRoutingError::EscrowEmpty))
+        );
+    }
+
+    #[tokio::test]
+    async fn cleared_counter_on_the_first_poll_is_not_a_power_loss() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(40, Category::BillValidator, ChecksumType::Crc8);
+        let validator = BillValidator::new(device, tx);
+
+        tokio::spawn(async move {
+            let mut polls = 0;
+            while let Some(message) = rx.recv().await {
+                let data: Vec<u8> = match message.header {
+                    Header::ReadBufferedBillEvents if polls == 0 => {
+                        polls += 1;
+                        vec![0; 11]
+                    }
+                    Header::ReadBufferedBillEvents => vec![1, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                    header => panic!("unexpected header {header:?}"),
+                };
+                message
+                    .respond_to
+                    .send(Ok(frame(&data)))
+                    .expect("should respond");
+            }
+        });
+
+        let mut client = BillValidatorClient::new(validator, Duration::from_millis(1), 1)
+            .expect("should start polling");
+        let event = client.next().await.expect("should poll");
+        assert_eq!(event, Ok(BillClientEvent::Event(BillEvent::Credit(3))));
+    }
+
+    #[tokio::test]
+    async fn registered_reset_handler_replaces_the_re_enabling() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(40, Category::BillValidator, ChecksumType::Crc8);
+        let runs = Arc::new(Mutex::new(0));
+        let handler_runs = Arc::clone(&runs);
+        let validator = BillValidator::new(device, tx).with_reset_handler(move |_| {
+            *handler_runs.lock().unwrap() += 1;
+            Box::pin(async { Ok(()) })
+        });
+
+        tokio::spawn(async move {
+            let mut polls = 0;
+            while let Some(message) = rx.recv().await {
+                let data: Vec<u8> = match message.header {
+                    Header::ModifyInhibitStatus | Header::ModifyMasterInhibitStatus => vec![],
+                    Header::ReadBufferedBillEvents if polls == 0 => {
+                        polls += 1;
+                        vec![1, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+                    }
+                    Header::ReadBufferedBillEvents => vec![0; 11],
+                    header => panic!("unexpected header {header:?}"),
+                };
+                message
+                    .respond_to
+                    .send(Ok(frame(&data)))
+                    .expect("should respond");
+            }
+        });
+
+        let mut client = BillValidatorClient::new(validator, Duration::from_millis(1), 1)
+            .expect("should start polling");
+        client.enable([false; 16]).await.expect("should enable");
+
+        let event = client.next().await.expect("should poll");
+        assert_eq!(event, Ok(BillClientEvent::Event(BillEvent::Credit(3))));
+        let event = client.next().await.expect("should poll");
+        assert_eq!(event, Ok(BillClientEvent::PowerLoss(Some(Ok(())))));
+        assert_eq!(*runs.lock().unwrap(), 1);
+    }
+}
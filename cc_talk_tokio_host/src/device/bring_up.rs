@@ -0,0 +1,669 @@
+//! Startup sequence of a bus.
+//!
+//! [`BringUp`] runs the recommended EMS sequence in order: enumerate the bus, read the identity
+//! of every device, establish the configured encryption, sync the clocks, apply the
+//! configuration profiles, clear the comms counters, enable the devices and start the pollers.
+//! Every step is reported, the sequence stops at the first step which fails so devices are
+//! never enabled half configured.
+//!
+//! ```ignore
+//! let mut bus = BringUp::new(sender, [2, 3, 4, 40])
+//!     .with_clock_sync()
+//!     .with_profile(Category::CoinAcceptor, |handle| {
+//!         Box::pin(async move {
+//!             match handle {
+//!                 DeviceHandle::CoinAcceptor(validator) => {
+//!                     validator.set_coin_inhibits([false; 16]).await
+//!                 }
+//!                 _ => Ok(()),
+//!             }
+//!         })
+//!     })
+//!     .run()
+//!     .await
+//!     .map_err(|report| anyhow!("bring-up failed: {report}"))?;
+//!
+//! while let Some(poll) = bus.coin_events(2).expect("should poll").recv().await {
+//!     println!("{poll:?}");
+//! }
+//! ```
+
+use std::{
+    fmt,
+    ops::DerefMut,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use cc_talk_core::cc_talk::{
+    BillValidatorPollResult, Category, ChecksumType, CoinAcceptorPollResult, SerialCode,
+};
+#[cfg(feature = "encryption")]
+use cc_talk_core::cc_talk::{ChallengeRng, HopperCipher};
+use cc_talk_host::device::device_commands::{ClearCommsStatusVariablesCommand, ModifyRtcCommand};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::{debug, info, instrument, warn};
+
+use crate::transport::tokio_transport::TransportMessage;
+
+use super::{
+    base::{CommandError, DeviceCommon, DeviceResult, PollingError},
+    enumeration::{Probe, enumerate},
+    manager::{AttachedDevice, DeviceHandle, identify},
+    reset::ReinitializationFuture,
+};
+
+type ProfileHandler =
+    Arc<dyn for<'a> Fn(&'a DeviceHandle) -> ReinitializationFuture<'a> + Send + Sync>;
+
+type CoinPoller =
+    Box<dyn DerefMut<Target = mpsc::Receiver<DeviceResult<CoinAcceptorPollResult>>> + Send + Sync>;
+
+type BillPoller =
+    Box<dyn DerefMut<Target = mpsc::Receiver<DeviceResult<BillValidatorPollResult>>> + Send + Sync>;
+
+/// A step of the [`BringUp`] sequence, in execution order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BringUpStep {
+    Enumerate,
+    Identify,
+    Encryption,
+    ClockSync,
+    Configuration,
+    ClearCommsCounters,
+    Enable,
+    StartPollers,
+}
+
+/// Why a [`BringUpStep`] failed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BringUpError {
+    #[error("no device answered")]
+    NoDevices,
+    /// The bus is mis-configured, see
+    /// [`DuplicateSerial`](super::enumeration::DuplicateSerial).
+    #[error("serial number {0} answered at several addresses")]
+    DuplicateSerial(SerialCode),
+    /// An encryption key is configured for an address without hopper.
+    #[error("no hopper answered at the address")]
+    MissingHopper,
+    #[error("no challenge RNG configured")]
+    MissingChallengeRng,
+    #[error("{0}")]
+    Command(#[from] CommandError),
+    #[error("{0}")]
+    Polling(#[from] PollingError),
+}
+
+/// A failure of the [`BringUp`] sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepFailure {
+    pub step: BringUpStep,
+    /// Device the failure is about, `None` for the whole bus.
+    pub address: Option<u8>,
+    pub error: BringUpError,
+}
+
+/// What the [`BringUp`] sequence did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BringUpReport {
+    /// Steps which ran without failure, steps with nothing configured are not run.
+    pub completed: Vec<BringUpStep>,
+    /// Failures of the step which stopped the sequence.
+    pub failures: Vec<StepFailure>,
+    /// Optional commands the devices NAKed, e.g. a hopper without real time clock.
+    pub unsupported: Vec<(BringUpStep, u8)>,
+}
+
+impl BringUpReport {
+    /// Returns `true` if the whole sequence ran.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.failures.is_empty() && self.completed.last() == Some(&BringUpStep::StartPollers)
+    }
+
+    fn fail(&mut self, step: BringUpStep, address: Option<u8>, error: impl Into<BringUpError>) {
+        let error = error.into();
+        warn!(step = ?step, address, error = %error, "bring-up step failed");
+        self.failures.push(StepFailure {
+            step,
+            address,
+            error,
+        });
+    }
+
+    /// Records the result of an optional command, a NAK only marks it as unsupported.
+    fn optional<T>(&mut self, step: BringUpStep, address: u8, result: DeviceResult<T>) {
+        match result {
+            Ok(_) => {}
+            Err(error) if error.is_nack() => {
+                debug!(step = ?step, address, "optional command not supported");
+                self.unsupported.push((step, address));
+            }
+            Err(error) => self.fail(step, Some(address), error),
+        }
+    }
+
+    /// Marks `step` as completed, returns `false` if it failed.
+    fn complete(&mut self, step: BringUpStep) -> bool {
+        if self.failures.iter().any(|failure| failure.step == step) {
+            return false;
+        }
+        info!(step = ?step, "bring-up step completed");
+        self.completed.push(step);
+        true
+    }
+}
+
+impl fmt::Display for BringUpReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "completed {:?}", self.completed)?;
+        for failure in &self.failures {
+            match failure.address {
+                Some(address) => write!(
+                    f,
+                    ", {:?} failed at address {address}: {}",
+                    failure.step, failure.error
+                )?,
+                None => write!(f, ", {:?} failed: {}", failure.step, failure.error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bus brought up by [`BringUp::run`], its devices are enabled and polled.
+///
+/// Dropping it stops the pollers.
+pub struct ReadyBus {
+    devices: Vec<AttachedDevice>,
+    report: BringUpReport,
+    coin_pollers: Vec<(u8, CoinPoller)>,
+    bill_pollers: Vec<(u8, BillPoller)>,
+}
+
+impl fmt::Debug for ReadyBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadyBus")
+            .field("devices", &self.devices)
+            .field("report", &self.report)
+            .field("coin_pollers", &self.coin_pollers.len())
+            .field("bill_pollers", &self.bill_pollers.len())
+            .finish()
+    }
+}
+
+impl ReadyBus {
+    /// Devices of the bus, in ascending address order.
+    #[must_use]
+    pub fn devices(&self) -> &[AttachedDevice] {
+        &self.devices
+    }
+
+    #[must_use]
+    pub fn device(&self, address: u8) -> Option<&AttachedDevice> {
+        self.devices.iter().find(|device| device.address == address)
+    }
+
+    #[must_use]
+    pub const fn report(&self) -> &BringUpReport {
+        &self.report
+    }
+
+    /// Poll results of the coin acceptor at `address`, `None` if it is not polled.
+    pub fn coin_events(
+        &mut self,
+        address: u8,
+    ) -> Option<&mut mpsc::Receiver<DeviceResult<CoinAcceptorPollResult>>> {
+        self.coin_pollers
+            .iter_mut()
+            .find(|(poller_address, _)| *poller_address == address)
+            .map(|(_, poller)| &mut ***poller)
+    }
+
+    /// Poll results of the bill validator at `address`, `None` if it is not polled.
+    pub fn bill_events(
+        &mut self,
+        address: u8,
+    ) -> Option<&mut mpsc::Receiver<DeviceResult<BillValidatorPollResult>>> {
+        self.bill_pollers
+            .iter_mut()
+            .find(|(poller_address, _)| *poller_address == address)
+            .map(|(_, poller)| &mut ***poller)
+    }
+}
+
+/// Startup sequence of a bus, see the [module documentation](self).
+pub struct BringUp {
+    sender: mpsc::Sender<TransportMessage>,
+    addresses: Vec<u8>,
+    checksum_type: ChecksumType,
+    clock_sync: bool,
+    profiles: Vec<(Category, ProfileHandler)>,
+    polling: Option<(Duration, usize)>,
+    #[cfg(feature = "encryption")]
+    hopper_ciphers: Vec<(u8, Arc<dyn HopperCipher + Send + Sync>)>,
+    #[cfg(feature = "encryption")]
+    rng: Option<Box<dyn ChallengeRng + Send>>,
+}
+
+impl BringUp {
+    /// Brings up the devices answering at `addresses`.
+    ///
+    /// Devices are talked to with 8 bit checksums, coin acceptors and bill validators are
+    /// polled every 100 ms.
+    pub fn new(
+        sender: mpsc::Sender<TransportMessage>,
+        addresses: impl IntoIterator<Item = u8>,
+    ) -> Self {
+        Self {
+            sender,
+            addresses: addresses.into_iter().collect(),
+            checksum_type: ChecksumType::Crc8,
+            clock_sync: false,
+            profiles: Vec::new(),
+            polling: Some((Duration::from_millis(100), 32)),
+            #[cfg(feature = "encryption")]
+            hopper_ciphers: Vec::new(),
+            #[cfg(feature = "encryption")]
+            rng: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+        self.checksum_type = checksum_type;
+        self
+    }
+
+    /// Sets the real time clock of the devices to the system time, devices without clock NAK
+    /// and are reported as unsupported.
+    #[must_use]
+    pub fn with_clock_sync(mut self) -> Self {
+        self.clock_sync = true;
+        self
+    }
+
+    /// Applies `profile` to every device of `category`, in the order the profiles are added.
+    #[must_use]
+    pub fn with_profile<F>(mut self, category: Category, profile: F) -> Self
+    where
+        F: for<'a> Fn(&'a DeviceHandle) -> ReinitializationFuture<'a> + Send + Sync + 'static,
+    {
+        self.profiles.push((category, Arc::new(profile)));
+        self
+    }
+
+    /// Polls the coin acceptors and bill validators every `interval`, up to `channel_size`
+    /// poll results are buffered.
+    #[must_use]
+    pub const fn with_polling(mut self, interval: Duration, channel_size: usize) -> Self {
+        self.polling = Some((interval, channel_size));
+        self
+    }
+
+    /// Leaves the polling to the caller, e.g. through a
+    /// [`CurrencyAcceptorPool`](super::currency_acceptor_pool::CurrencyAcceptorPool).
+    #[must_use]
+    pub const fn without_polling(mut self) -> Self {
+        self.polling = None;
+        self
+    }
+
+    /// Establishes the encryption of the hopper at `address` with `cipher`, the challenges are
+    /// drawn from the RNG set with [`Self::with_challenge_rng`].
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn with_hopper_cipher(
+        mut self,
+        address: u8,
+        cipher: impl HopperCipher + Send + Sync + 'static,
+    ) -> Self {
+        self.hopper_ciphers.push((address, Arc::new(cipher)));
+        self
+    }
+
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn with_challenge_rng(mut self, rng: impl ChallengeRng + Send + 'static) -> Self {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    /// Runs the sequence.
+    ///
+    /// # Errors
+    ///
+    /// Returns the report of the sequence if a step failed, the steps after it are not run.
+    #[instrument(skip(self), fields(addresses = self.addresses.len()), level = "info")]
+    #[cfg_attr(not(feature = "encryption"), allow(unused_mut))]
+    pub async fn run(mut self) -> Result<ReadyBus, BringUpReport> {
+        let mut report = BringUpReport::default();
+
+        let enumeration = enumerate(
+            &self.sender,
+            self.addresses.iter().copied(),
+            self.checksum_type,
+        )
+        .await;
+        if enumeration.devices.is_empty() {
+            report.fail(BringUpStep::Enumerate, None, BringUpError::NoDevices);
+        }
+        for duplicate in &enumeration.duplicates {
+            for remediation in &duplicate.remediations {
+                report.fail(
+                    BringUpStep::Enumerate,
+                    Some(remediation.address),
+                    BringUpError::DuplicateSerial(duplicate.serial.clone()),
+                );
+            }
+        }
+        if !report.complete(BringUpStep::Enumerate) {
+            return Err(report);
+        }
+
+        let mut devices = Vec::with_capacity(enumeration.devices.len());
+        for device in &enumeration.devices {
+            devices.push(identify(&self.sender, device.address, self.checksum_type).await);
+        }
+        report.complete(BringUpStep::Identify);
+
+        #[cfg(feature = "encryption")]
+        if !self.hopper_ciphers.is_empty() {
+            self.establish_encryption(&devices, &mut report).await;
+            if !report.complete(BringUpStep::Encryption) {
+                return Err(report);
+            }
+        }
+        if self.clock_sync {
+            self.sync_clocks(&devices, &mut report).await;
+            if !report.complete(BringUpStep::ClockSync) {
+                return Err(report);
+            }
+        }
+        if !self.profiles.is_empty() {
+            self.apply_profiles(&devices, &mut report).await;
+            if !report.complete(BringUpStep::Configuration) {
+                return Err(report);
+            }
+        }
+        for device in &devices {
+            let probe = Probe::new(device.address, self.checksum_type, &self.sender);
+            let result = probe
+                .execute_with_packet(ClearCommsStatusVariablesCommand)
+                .await;
+            report.optional(BringUpStep::ClearCommsCounters, device.address, result);
+        }
+        if !report.complete(BringUpStep::ClearCommsCounters) {
+            return Err(report);
+        }
+
+        for device in &devices {
+            if let Err(error) = enable(&device.handle).await {
+                report.fail(BringUpStep::Enable, Some(device.address), error);
+            }
+        }
+        if !report.complete(BringUpStep::Enable) {
+            return Err(report);
+        }
+
+        self.start_pollers(devices, report)
+    }
+
+    #[cfg(feature = "encryption")]
+    async fn establish_encryption(
+        &mut self,
+        devices: &[AttachedDevice],
+        report: &mut BringUpReport,
+    ) {
+        for (address, cipher) in &self.hopper_ciphers {
+            let address = *address;
+            let hopper = devices.iter().find_map(|device| match &device.handle {
+                DeviceHandle::Hopper(hopper) if device.address == address => Some(hopper),
+                _ => None,
+            });
+            let Some(hopper) = hopper else {
+                report.fail(
+                    BringUpStep::Encryption,
+                    Some(address),
+                    BringUpError::MissingHopper,
+                );
+                continue;
+            };
+            let Some(rng) = self.rng.as_mut() else {
+                report.fail(
+                    BringUpStep::Encryption,
+                    Some(address),
+                    BringUpError::MissingChallengeRng,
+                );
+                continue;
+            };
+            // The status only decrypts with the key the hopper holds.
+            if let Err(error) = hopper
+                .get_encrypted_hopper_status(cipher.as_ref(), rng.as_mut())
+                .await
+            {
+                report.fail(BringUpStep::Encryption, Some(address), error);
+            }
+        }
+    }
+
+    async fn sync_clocks(&self, devices: &[AttachedDevice], report: &mut BringUpReport) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let now = u32::try_from(now).unwrap_or(u32::MAX);
+        for device in devices {
+            let probe = Probe::new(device.address, self.checksum_type, &self.sender);
+            let result = probe.execute_with_packet(ModifyRtcCommand::new(now)).await;
+            report.optional(BringUpStep::ClockSync, device.address, result);
+        }
+    }
+
+    async fn apply_profiles(&self, devices: &[AttachedDevice], report: &mut BringUpReport) {
+        for device in devices {
+            for (category, profile) in &self.profiles {
+                if *category != device.category {
+                    continue;
+                }
+                if let Err(error) = profile(&device.handle).await {
+                    report.fail(BringUpStep::Configuration, Some(device.address), error);
+                }
+            }
+        }
+    }
+
+    fn start_pollers(
+        &self,
+        devices: Vec<AttachedDevice>,
+        mut report: BringUpReport,
+    ) -> Result<ReadyBus, BringUpReport> {
+        let mut coin_pollers: Vec<(u8, CoinPoller)> = Vec::new();
+        let mut bill_pollers: Vec<(u8, BillPoller)> = Vec::new();
+        if let Some((interval, channel_size)) = self.polling {
+            for device in &devices {
+                let result = match &device.handle {
+                    DeviceHandle::CoinAcceptor(validator) => validator
+                        .try_background_polling(interval, channel_size)
+                        .map(|poller| coin_pollers.push((device.address, Box::new(poller)))),
+                    DeviceHandle::BillValidator(validator) => validator
+                        .try_background_polling(interval, channel_size)
+                        .map(|poller| bill_pollers.push((device.address, Box::new(poller)))),
+                    _ => Ok(()),
+                };
+                if let Err(error) = result {
+                    report.fail(BringUpStep::StartPollers, Some(device.address), error);
+                }
+            }
+        }
+        if !report.complete(BringUpStep::StartPollers) {
+            return Err(report);
+        }
+        info!(devices = devices.len(), "bus ready");
+        Ok(ReadyBus {
+            devices,
+            report,
+            coin_pollers,
+            bill_pollers,
+        })
+    }
+}
+
+/// Lets the device accept currency or pay out.
+async fn enable(handle: &DeviceHandle) -> DeviceResult<()> {
+    match handle {
+        DeviceHandle::Hopper(hopper) => hopper.enable_hopper().await,
+        DeviceHandle::CoinAcceptor(validator) => validator.disable_master_inhibit().await,
+        DeviceHandle::BillValidator(validator) => validator.disable_master_inhibit().await,
+        DeviceHandle::Changer(changer) => changer.disable_master_inhibit().await,
+        DeviceHandle::Unsupported => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use cc_talk_core::cc_talk::Header;
+
+    use super::*;
+    use crate::transport::tokio_transport::TransportError;
+
+    type SentHeaders = Arc<Mutex<Vec<(u8, Header)>>>;
+
+    fn reply(address: u8, data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![1, data.len() as u8, address, 0];
+        frame.extend_from_slice(data);
+        frame.push(0);
+        frame
+    }
+
+    /// A coin acceptor at address 2 and a hopper without clock at address 3, returns the
+    /// headers sent to each address.
+    fn bus() -> (mpsc::Sender<TransportMessage>, SentHeaders) {
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(1);
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&sent);
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let address = message.address;
+                log.lock()
+                    .expect("should not be poisoned")
+                    .push((address, message.header));
+                let category: &[u8] = match address {
+                    2 => b"Coin Acceptor",
+                    3 => b"Payout",
+                    _ => {
+                        let _ = message.respond_to.send(Err(TransportError::Timeout));
+                        continue;
+                    }
+                };
+                let response = match message.header {
+                    Header::RequestEquipementCategoryId => Ok(reply(address, category)),
+                    Header::RequestSerialNumber => Ok(reply(address, &[address, 0, 0])),
+                    Header::RequestManufacturerId => Ok(reply(address, b"WHM")),
+                    Header::ReadBufferedCreditOrErrorCodes => Ok(reply(address, &[0; 11])),
+                    Header::ModifyRealTimeClock if address == 3 => Err(TransportError::Nack),
+                    Header::SimplePoll
+                    | Header::ModifyRealTimeClock
+                    | Header::ClearCommsStatusVariable
+                    | Header::ModifyMasterInhibitStatus
+                    | Header::EnableHopper => Ok(reply(address, &[])),
+                    _ => Err(TransportError::Nack),
+                };
+                let _ = message.respond_to.send(response);
+            }
+        });
+        (tx, sent)
+    }
+
+    #[tokio::test]
+    async fn devices_are_brought_up_in_order() {
+        let (sender, sent) = bus();
+        let profiled = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&profiled);
+        let mut bus = BringUp::new(sender, 2..=4)
+            .with_clock_sync()
+            .with_profile(Category::Payout, move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async { Ok(()) })
+            })
+            .with_polling(Duration::from_millis(10), 4)
+            .run()
+            .await
+            .expect("should be ready");
+
+        assert!(bus.report().is_ready());
+        assert_eq!(
+            bus.report().completed,
+            [
+                BringUpStep::Enumerate,
+                BringUpStep::Identify,
+                BringUpStep::ClockSync,
+                BringUpStep::Configuration,
+                BringUpStep::ClearCommsCounters,
+                BringUpStep::Enable,
+                BringUpStep::StartPollers,
+            ]
+        );
+        assert_eq!(bus.report().unsupported, [(BringUpStep::ClockSync, 3)]);
+        assert_eq!(profiled.load(Ordering::SeqCst), 1);
+        assert_eq!(bus.devices().len(), 2);
+        assert!(bus.bill_events(2).is_none());
+        let poll = bus
+            .coin_events(2)
+            .expect("should poll the coin acceptor")
+            .recv()
+            .await
+            .expect("should poll");
+        assert!(poll.is_ok());
+
+        let sent = sent.lock().expect("should not be poisoned");
+        let position = |header| {
+            sent.iter()
+                .position(|&(address, sent)| address == 2 && sent == header)
+        };
+        assert!(
+            position(Header::ClearCommsStatusVariable)
+                < position(Header::ModifyMasterInhibitStatus)
+        );
+        assert!(sent.contains(&(3, Header::EnableHopper)));
+    }
+
+    #[tokio::test]
+    async fn sequence_stops_at_the_failed_step() {
+        let (sender, _) = bus();
+        let report = BringUp::new(sender.clone(), [4, 5])
+            .run()
+            .await
+            .expect_err("nothing answers");
+        assert_eq!(
+            report.failures,
+            [StepFailure {
+                step: BringUpStep::Enumerate,
+                address: None,
+                error: BringUpError::NoDevices,
+            }]
+        );
+        assert!(report.completed.is_empty());
+
+        let report = BringUp::new(sender, [2, 3])
+            .with_profile(Category::CoinAcceptor, |_| {
+                Box::pin(async { Err(CommandError::Nack) })
+            })
+            .run()
+            .await
+            .expect_err("the profile fails");
+        assert_eq!(
+            report.completed,
+            [BringUpStep::Enumerate, BringUpStep::Identify]
+        );
+        assert_eq!(report.failures[0].step, BringUpStep::Configuration);
+        assert_eq!(report.failures[0].address, Some(2));
+        assert!(!report.is_ready());
+    }
+}
@@ -0,0 +1,883 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cc_talk_core::cc_talk::{
+    BitMask, ChangerDevice, ChangerError, ChangerFlags, ChangerPollResult, CoinAcceptorPollResult,
+    CoinEvent, CurrencyToken, Device,
+};
+use cc_talk_host::{command::Command, device::device_commands::*};
+use tokio::sync::mpsc;
+use tracing::{debug, info, instrument, trace, warn};
+
+use crate::transport::tokio_transport::TransportMessage;
+
+use super::{
+    base::{CommandError, DeviceCommon, DeviceResult, LongOperation},
+    coin_validator::COIN_POSITIONS,
+    reset::{DeviceReset, ReinitializationFuture, ResetRecovery},
+    triage::TriageConfig,
+};
+
+/// A ccTalk changer device driver.
+///
+/// A changer combines a coin acceptor with its own hoppers, coins accepted by the changer are
+/// routed to the hoppers until they are full and to the cashbox otherwise. The changer keeps a
+/// balance for each of its hoppers.
+///
+/// # Cloning
+///
+/// `Changer` implements [`Clone`] and shares its internal state across clones.
+#[derive(Debug, Clone)]
+pub struct Changer {
+    /// The underlying ccTalk device configuration.
+    pub device: Device,
+    /// Channel sender for communicating with the transport layer.
+    pub sender: mpsc::Sender<TransportMessage>,
+    express_sender: Option<mpsc::Sender<TransportMessage>>,
+    timeout_triage: Option<TriageConfig>,
+    long_operation: LongOperation,
+    event_counter: Arc<Mutex<u8>>,
+    master_inhibit: Arc<Mutex<Option<bool>>>,
+    reset_recovery: ResetRecovery<Changer>,
+}
+
+impl Changer {
+    /// Creates a new `Changer` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The ccTalk device configuration containing address and checksum type.
+    /// * `sender` - A channel sender for communicating with the transport layer.
+    pub fn new(device: Device, sender: mpsc::Sender<TransportMessage>) -> Self {
+        debug!(
+            address = device.address(),
+            category = ?device.category(),
+            "creating changer"
+        );
+        Self {
+            device,
+            sender,
+            express_sender: None,
+            timeout_triage: None,
+            long_operation: LongOperation::default(),
+            event_counter: Arc::new(Mutex::new(0)),
+            master_inhibit: Arc::new(Mutex::new(None)),
+            reset_recovery: ResetRecovery::default(),
+        }
+    }
+
+    /// Routes express commands through the transport express lane.
+    ///
+    /// See [`CcTalkTokioTransport::express_sender`](crate::transport::tokio_transport::CcTalkTokioTransport::express_sender).
+    #[must_use]
+    pub fn with_express_lane(mut self, express_sender: mpsc::Sender<TransportMessage>) -> Self {
+        self.express_sender = Some(express_sender);
+        self
+    }
+
+    /// Classifies the timeouts of this driver, see [`DeviceCommon::timeout_triage`].
+    #[must_use]
+    pub const fn with_timeout_triage(self) -> Self {
+        self.with_timeout_triage_config(TriageConfig::new())
+    }
+
+    /// Classifies the timeouts of this driver, knowing the other devices configured on the bus.
+    #[must_use]
+    pub const fn with_timeout_triage_config(mut self, config: TriageConfig) -> Self {
+        self.timeout_triage = Some(config);
+        self
+    }
+
+    /// Registers the re-initialization run when a poll detects an unexpected reset, e.g.
+    /// re-applying the coin inhibits and disabling the master inhibit.
+    ///
+    /// See [`reset`](super::reset) for an example.
+    #[must_use]
+    pub fn with_reset_handler<F>(mut self, handler: F) -> Self
+    where
+        F: for<'a> Fn(&'a Changer) -> ReinitializationFuture<'a> + Send + Sync + 'static,
+    {
+        self.reset_recovery.set_handler(handler);
+        self
+    }
+
+    /// Emits a [`DeviceReset`] to `events` whenever a poll detects an unexpected reset.
+    ///
+    /// Every call adds a receiver, the resets are emitted to all of them.
+    #[must_use]
+    pub fn with_reset_events(mut self, events: mpsc::Sender<DeviceReset>) -> Self {
+        self.reset_recovery.add_events(events);
+        self
+    }
+
+    /// Returns the current event counter value, updated by [`poll`](Self::poll).
+    pub fn event_counter(&self) -> u8 {
+        *self.event_counter.lock().expect("should not be poisoned")
+    }
+
+    /// Sets the master inhibit status of the changer coin acceptor.
+    ///
+    /// # Arguments
+    ///
+    /// * `inhibit` - `true` to enable master inhibit (reject all coins), `false` to disable.
+    #[instrument(skip(self), fields(inhibit), level = "debug")]
+    pub async fn set_master_inhibit(&self, inhibit: bool) -> DeviceResult<()> {
+        debug!(inhibit, "setting master inhibit status");
+        let mut bitmask = BitMask::<1>::new(1).map_err(|_| CommandError::BufferOverflow)?;
+        bitmask
+            .set_bit(0, !inhibit)
+            .map_err(|_| CommandError::BufferOverflow)?;
+        let command = ModifyMasterInhibitStatusCommand::<1>::build(bitmask)
+            .map_err(|_| CommandError::BufferOverflow)?;
+        let response_packet = self.send_command(command).await?;
+        let bitmask = BitMask::<1>::new(1).map_err(|_| CommandError::BufferOverflow)?;
+        ModifyMasterInhibitStatusCommand::<1>::build(bitmask)
+            .map_err(|_| CommandError::BufferOverflow)?
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        *self.master_inhibit.lock().expect("should not be poisoned") = Some(inhibit);
+        info!(inhibit, "master inhibit status set");
+        Ok(())
+    }
+
+    /// Returns the master inhibit status last set, `None` until then.
+    pub fn cached_master_inhibit(&self) -> Option<bool> {
+        *self.master_inhibit.lock().expect("should not be poisoned")
+    }
+
+    /// Enables the master inhibit, the changer rejects all coins.
+    pub async fn enable_master_inhibit(&self) -> DeviceResult<()> {
+        self.set_master_inhibit(true).await
+    }
+
+    /// Disables the master inhibit, the changer accepts coins according to its coin inhibits.
+    pub async fn disable_master_inhibit(&self) -> DeviceResult<()> {
+        self.set_master_inhibit(false).await
+    }
+
+    /// Polls the changer for buffered coin credits and errors.
+    ///
+    /// An event counter returning to 0 is an unexpected reset, the handler registered with
+    /// [`with_reset_handler`](Self::with_reset_handler) runs before the result is returned.
+    pub async fn poll(&self) -> DeviceResult<CoinAcceptorPollResult> {
+        trace!("polling changer");
+        let response_packet = self
+            .send_command(ReadBufferedCreditOrErrorCodeCommand::default())
+            .await?;
+        let buffer = response_packet.get_data()?;
+        let counter_cleared = buffer.first() == Some(&0);
+        let unexpected_reset = counter_cleared && self.event_counter() != 0;
+        let result = ReadBufferedCreditOrErrorCodeCommand::new(self.event_counter())
+            .parse_response(buffer)
+            .map_err(CommandError::from)
+            .inspect(|result| {
+                *self.event_counter.lock().expect("should not be poisoned") = if counter_cleared {
+                    0
+                } else {
+                    result.event_counter
+                };
+            })?;
+        if !result.events.is_empty() {
+            debug!(
+                event_counter = result.event_counter,
+                events_count = result.events.len(),
+                "changer poll returned events"
+            );
+        }
+        if unexpected_reset {
+            self.reset_recovery
+                .recover(self.device.address(), self)
+                .await;
+        }
+        Ok(result)
+    }
+
+    /// Requests the coin ID (currency token) for a coin position.
+    #[instrument(skip(self), fields(coin_position), level = "trace")]
+    pub async fn request_coin_id(&self, coin_position: u8) -> DeviceResult<CurrencyToken> {
+        trace!(coin_position, "requesting coin ID");
+        let response_packet = self
+            .send_command(RequestCoinIdCommand::new(coin_position))
+            .await?;
+        let token = RequestCoinIdCommand::new(coin_position)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        trace!(coin_position, token = ?token, "coin ID received");
+        Ok(token)
+    }
+
+    /// Sets the sorter override status of the 8 sorter paths, `true` overrides the path to
+    /// another one, usually the cashbox.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn modify_sorter_override_status(&self, overrides: [bool; 8]) -> DeviceResult<()> {
+        debug!(overrides = ?overrides, "modifying sorter override status");
+        let mut bitmask = BitMask::<1>::new(8).map_err(|_| CommandError::BufferOverflow)?;
+        for (i, should_override) in overrides.iter().enumerate() {
+            bitmask
+                // Invert value since 0 is override and 1 is no override
+                .set_bit(i, !*should_override)
+                .map_err(|_| CommandError::BufferOverflow)?;
+        }
+        let response_packet = self
+            .send_command(
+                ModifySorterOverrideStatusCommand::build(bitmask.clone())
+                    .map_err(|_| CommandError::BufferOverflow)?,
+            )
+            .await?;
+        ModifySorterOverrideStatusCommand::build(bitmask)
+            .map_err(|_| CommandError::BufferOverflow)?
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        Ok(())
+    }
+
+    /// Requests the sorter override status of the 8 sorter paths, `true` if the path is
+    /// overridden.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_sorter_override_status(&self) -> DeviceResult<[bool; 8]> {
+        trace!("requesting sorter override status");
+        let response_packet = self
+            .send_command(RequestSorterOverrideStatusCommand)
+            .await?;
+        let mask = RequestSorterOverrideStatusCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        let mut overrides = [false; 8];
+        for (i, overridden) in overrides.iter_mut().enumerate() {
+            // A cleared bit is an active override.
+            *overridden = !mask.get_bit(i).map_err(|_| CommandError::BufferOverflow)?;
+        }
+        debug!(overrides = ?overrides, "sorter override status received");
+        Ok(overrides)
+    }
+
+    /// Requests the coin held by a hopper of the changer and its balance.
+    #[instrument(skip(self), fields(hopper), level = "debug")]
+    pub async fn request_hopper_balance(&self, hopper: u8) -> DeviceResult<(CurrencyToken, u16)> {
+        trace!(hopper, "requesting hopper balance");
+        let response_packet = self
+            .send_command(RequestHopperBalanceCommand::new(hopper))
+            .await?;
+        let (token, balance) = RequestHopperBalanceCommand::new(hopper)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(hopper, token = ?token, balance, "hopper balance received");
+        Ok((token, balance))
+    }
+
+    /// Overwrites the balance the changer keeps for one of its hoppers.
+    #[instrument(skip(self), fields(hopper, balance), level = "debug")]
+    pub async fn modify_hopper_balance(&self, hopper: u8, balance: u16) -> DeviceResult<()> {
+        debug!(hopper, balance, "modifying hopper balance");
+        let response_packet = self
+            .send_command(ModifyHopperBalanceCommand::new(hopper, balance))
+            .await?;
+        ModifyHopperBalanceCommand::new(hopper, balance)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        Ok(())
+    }
+
+    /// Requests the value held in the cashbox, in smallest currency units.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_cashbox_value(&self) -> DeviceResult<u32> {
+        trace!("requesting cashbox value");
+        let response_packet = self.send_command(RequestCashBoxValueCommand).await?;
+        let value = RequestCashBoxValueCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(value, "cashbox value received");
+        Ok(value)
+    }
+
+    /// Requests the activity flags currently raised by the changer.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_activity_register(&self) -> DeviceResult<Vec<ChangerFlags>> {
+        trace!("requesting activity register");
+        let response_packet = self.send_command(RequestActivityRegisterCommand).await?;
+        let flags = RequestActivityRegisterCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(flags = ?flags, "activity register received");
+        Ok(flags.into_iter().collect())
+    }
+
+    /// Requests the last error of the changer and the sub-device which raised it.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_error_status(&self) -> DeviceResult<(ChangerDevice, ChangerError)> {
+        trace!("requesting error status");
+        let response_packet = self.send_command(RequestErrorStatusCommand).await?;
+        let status = RequestErrorStatusCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(status = ?status, "error status received");
+        Ok(status)
+    }
+
+    /// Pays out `amount`, in smallest currency units, from the hoppers of the changer.
+    ///
+    /// The payout runs in the background, follow it with [`verify_money_out`](Self::verify_money_out).
+    #[instrument(skip(self), fields(amount), level = "debug")]
+    pub async fn pay_money_out(&self, amount: u32) -> DeviceResult<()> {
+        debug!(amount, "paying money out");
+        let response_packet = self.send_command(PayMoneyOutCommand::new(amount)).await?;
+        PayMoneyOutCommand::new(amount)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        info!(amount, "money out started");
+        Ok(())
+    }
+
+    /// Requests the value paid and left unpaid by the last [`pay_money_out`](Self::pay_money_out).
+    #[instrument(skip(self), level = "debug")]
+    pub async fn verify_money_out(&self) -> DeviceResult<ChangerPollResult> {
+        trace!("verifying money out");
+        let response_packet = self.send_command(VerifyMoneyOutCommand).await?;
+        let result = VerifyMoneyOutCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(
+            event_counter = result.event_counter,
+            paid = result.paid,
+            unpaid = result.unpaid,
+            "money out verified"
+        );
+        Ok(result)
+    }
+
+    /// Empties `count` coins of a hopper of the changer into the cashbox, 0 empties the hopper
+    /// and hopper 255 purges the whole changer.
+    #[instrument(skip(self), fields(hopper, count), level = "debug")]
+    pub async fn purge_hopper(&self, hopper: u8, count: u8) -> DeviceResult<()> {
+        debug!(hopper, count, "purging hopper");
+        let response_packet = self
+            .send_command(PurgeHopperCommand::new(hopper, count))
+            .await?;
+        PurgeHopperCommand::new(hopper, count)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        info!(hopper, count, "hopper purge started");
+        Ok(())
+    }
+
+    /// Starts a refill session for the given hoppers, see [`RefillSession`].
+    ///
+    /// Reads the coin and balance of each hopper and maps the coin positions of the changer to
+    /// the hopper holding the same coin. The sorter overrides are then cleared, so the accepted
+    /// coins follow the normal sorting of the changer into its hoppers, and the master inhibit
+    /// is disabled. The previous overrides are restored by [`RefillSession::finish`].
+    ///
+    /// Credits still buffered in the changer are counted as accepted for the hoppers, poll the
+    /// changer before starting a session.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a hopper balance or the sorter overrides cannot be read, or if the changer
+    /// cannot be armed.
+    #[instrument(skip(self, hoppers), level = "debug")]
+    pub async fn begin_refill(
+        &self,
+        hoppers: impl IntoIterator<Item = u8>,
+    ) -> DeviceResult<RefillSession> {
+        let mut refills = BTreeMap::new();
+        for hopper in hoppers {
+            let (coin, balance) = self.request_hopper_balance(hopper).await?;
+            refills.insert(
+                hopper,
+                HopperRefill {
+                    hopper,
+                    coin,
+                    initial_balance: balance,
+                    balance,
+                    credited: 0,
+                },
+            );
+        }
+
+        let mut routes = BTreeMap::new();
+        for position in COIN_POSITIONS {
+            let Ok(token) = self.request_coin_id(position).await else {
+                continue;
+            };
+            if let Some(refill) = refills.values().find(|refill| refill.coin == token) {
+                trace!(
+                    position,
+                    hopper = refill.hopper,
+                    "coin position refills hopper"
+                );
+                routes.insert(position, refill.hopper);
+            }
+        }
+
+        let previous_overrides = self.request_sorter_override_status().await?;
+        let session = RefillSession {
+            changer: self.clone(),
+            started: Instant::now(),
+            routes,
+            refills,
+            unrouted_coins: 0,
+            previous_overrides,
+        };
+        session.arm().await?;
+        info!(
+            hoppers = session.refills.len(),
+            routed_positions = session.routes.len(),
+            "refill session started"
+        );
+        Ok(session)
+    }
+}
+
+impl DeviceCommon for Changer {
+    fn get_device(&self) -> &Device {
+        &self.device
+    }
+
+    fn get_sender(&self) -> &mpsc::Sender<TransportMessage> {
+        &self.sender
+    }
+
+    fn get_express_sender(&self) -> Option<&mpsc::Sender<TransportMessage>> {
+        self.express_sender.as_ref()
+    }
+
+    fn timeout_triage(&self) -> Option<&TriageConfig> {
+        self.timeout_triage.as_ref()
+    }
+
+    fn get_long_operation(&self) -> Option<&LongOperation> {
+        Some(&self.long_operation)
+    }
+}
+
+/// Refill of a single changer hopper, see [`RefillSession`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopperRefill {
+    pub hopper: u8,
+    /// Coin held by the hopper.
+    pub coin: CurrencyToken,
+    /// Balance of the hopper when the session started.
+    pub initial_balance: u16,
+    /// Balance last reported by the changer, which counts the coins it routes to the hopper.
+    pub balance: u16,
+    /// Coins of the hopper accepted during the session, including those diverted to the
+    /// cashbox.
+    pub credited: u16,
+}
+
+impl HopperRefill {
+    /// Returns the coins added to the hopper during the session, as counted by the changer.
+    pub const fn added(&self) -> u16 {
+        self.balance.saturating_sub(self.initial_balance)
+    }
+
+    /// Returns the coins accepted for the hopper which did not reach it, usually diverted to
+    /// the cashbox once the hopper was full.
+    pub const fn diverted(&self) -> u16 {
+        self.credited.saturating_sub(self.added())
+    }
+
+    /// Returns the value added to the hopper in smallest currency units, tokens have no value.
+    pub fn value_added(&self) -> u32 {
+        match &self.coin {
+            CurrencyToken::Token => 0,
+            CurrencyToken::Currency(value) => value.smallest_unit_value() * u32::from(self.added()),
+        }
+    }
+}
+
+/// Summary of a finished [`RefillSession`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefillReport {
+    pub hoppers: Vec<HopperRefill>,
+    /// Coins accepted which are not held by any of the refilled hoppers, usually routed to the
+    /// cashbox.
+    pub unrouted_coins: u32,
+    pub duration: Duration,
+}
+
+impl RefillReport {
+    /// Returns the total value added to the hoppers in smallest currency units.
+    pub fn value_added(&self) -> u32 {
+        self.hoppers.iter().map(HopperRefill::value_added).sum()
+    }
+
+    /// Returns the total number of coins added to the hoppers.
+    pub fn coins_added(&self) -> u32 {
+        self.hoppers
+            .iter()
+            .map(|refill| u32::from(refill.added()))
+            .sum()
+    }
+
+    /// Returns the total number of coins accepted for the hoppers which did not reach them.
+    pub fn coins_diverted(&self) -> u32 {
+        self.hoppers
+            .iter()
+            .map(|refill| u32::from(refill.diverted()))
+            .sum()
+    }
+}
+
+/// Refill mode of a [`Changer`], started with [`Changer::begin_refill`].
+///
+/// Credits accepted during the session are not sales. The changer counts the coins it routes
+/// to its hoppers itself, see `ModifyHopperBalance`, so the session reconciles every hopper
+/// which was credited against `RequestHopperBalance` and reports the coins which did not
+/// reach it. Call [`poll`](Self::poll) periodically and [`finish`](Self::finish) to get the
+/// refill report.
+///
+/// A reset of the changer clears its sorter overrides and inhibits it, the session arms it
+/// again when a poll reports the reset.
+#[derive(Debug)]
+pub struct RefillSession {
+    changer: Changer,
+    started: Instant,
+    /// Maps coin position -> hopper
+    routes: BTreeMap<u8, u8>,
+    refills: BTreeMap<u8, HopperRefill>,
+    unrouted_coins: u32,
+    previous_overrides: [bool; 8],
+}
+
+impl RefillSession {
+    /// Returns the refill of a hopper of the session.
+    pub fn hopper(&self, hopper: u8) -> Option<&HopperRefill> {
+        self.refills.get(&hopper)
+    }
+
+    /// Returns the report of the coins counted so far.
+    pub fn report(&self) -> RefillReport {
+        RefillReport {
+            hoppers: self.refills.values().cloned().collect(),
+            unrouted_coins: self.unrouted_coins,
+            duration: self.started.elapsed(),
+        }
+    }
+
+    /// Polls the changer, counts the accepted coins and reconciles the balance of every hopper
+    /// which was credited.
+    ///
+    /// After a reset the changer is armed again and every hopper is reconciled, the credits
+    /// buffered before the reset are lost.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the poll fails, if the changer cannot be armed again or if a hopper balance
+    /// cannot be read, the balances are read again by [`finish`](Self::finish).
+    pub async fn poll(&mut self) -> DeviceResult<CoinAcceptorPollResult> {
+        let (result, reset) = self.poll_changer().await?;
+        let mut credited = self.count(&result);
+        if reset {
+            warn!("changer reset during refill, arming it again");
+            self.arm().await?;
+            credited = self.refills.keys().copied().collect();
+        }
+
+        let mut last_error = None;
+        for hopper in credited {
+            if let Err(error) = self.reconcile(hopper).await {
+                warn!(hopper, error = %error, "failed to reconcile hopper balance");
+                last_error = Some(error);
+            }
+        }
+        match last_error {
+            Some(error) => Err(error),
+            None => Ok(result),
+        }
+    }
+
+    /// Ends the session: enables the master inhibit, counts the last coins, restores the sorter
+    /// overrides and reconciles the balance of every hopper.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the master inhibit or the sorter overrides cannot be set, or if a balance
+    /// cannot be read.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn finish(mut self) -> DeviceResult<RefillReport> {
+        self.changer.enable_master_inhibit().await?;
+        // Every hopper is reconciled below, a reset only loses the last credits.
+        match self.poll_changer().await {
+            Ok((result, _)) => {
+                self.count(&result);
+            }
+            Err(error) => warn!(error = %error, "final refill poll failed"),
+        }
+        self.changer
+            .modify_sorter_override_status(self.previous_overrides)
+            .await?;
+
+        let hoppers: Vec<u8> = self.refills.keys().copied().collect();
+        for hopper in hoppers {
+            self.reconcile(hopper).await?;
+        }
+
+        let report = self.report();
+        info!(
+            coins_added = report.coins_added(),
+            coins_diverted = report.coins_diverted(),
+            value_added = report.value_added(),
+            unrouted_coins = report.unrouted_coins,
+            "refill session finished"
+        );
+        Ok(report)
+    }
+
+    /// Polls the changer, the flag is set if its event counter returned to 0 since the last
+    /// poll.
+    async fn poll_changer(&self) -> DeviceResult<(CoinAcceptorPollResult, bool)> {
+        let event_counter = self.changer.event_counter();
+        let result = self.changer.poll().await?;
+        let reset = event_counter != 0 && result.events.contains(&CoinEvent::Reset);
+        Ok((result, reset))
+    }
+
+    /// Counts the credits of `result`, returns the hoppers which were credited.
+    fn count(&mut self, result: &CoinAcceptorPollResult) -> Vec<u8> {
+        let mut credited = Vec::new();
+        for event in result.events.iter() {
+            match event {
+                CoinEvent::Credit(credit) => {
+                    let refill = self
+                        .routes
+                        .get(&credit.credit)
+                        .and_then(|hopper| self.refills.get_mut(hopper));
+                    if let Some(refill) = refill {
+                        refill.credited = refill.credited.saturating_add(1);
+                        trace!(
+                            hopper = refill.hopper,
+                            credited = refill.credited,
+                            "coin credited for hopper"
+                        );
+                        if !credited.contains(&refill.hopper) {
+                            credited.push(refill.hopper);
+                        }
+                    } else {
+                        debug!(
+                            position = credit.credit,
+                            "coin not held by a refilled hopper"
+                        );
+                        self.unrouted_coins += 1;
+                    }
+                }
+                CoinEvent::Reset => {}
+                CoinEvent::Error(error) => debug!(error = ?error, "changer error during refill"),
+            }
+        }
+        credited
+    }
+
+    /// Clears the sorter overrides, so the coins reach the hoppers, and disables the master
+    /// inhibit.
+    async fn arm(&self) -> DeviceResult<()> {
+        self.changer
+            .modify_sorter_override_status([false; 8])
+            .await?;
+        self.changer.disable_master_inhibit().await
+    }
+
+    /// Reads the balance the changer counted for `hopper`.
+    async fn reconcile(&mut self, hopper: u8) -> DeviceResult<()> {
+        let (_, balance) = self.changer.request_hopper_balance(hopper).await?;
+        if let Some(refill) = self.refills.get_mut(&hopper) {
+            refill.balance = balance;
+            if refill.diverted() > 0 {
+                debug!(
+                    hopper,
+                    diverted = refill.diverted(),
+                    "coins did not reach the hopper"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Header};
+
+    /// Replies like a changer counting the coins it routes to its hoppers, the balances of
+    /// hopper 1 and 2 after each poll are given by `balances`.
+    fn refill_responder(
+        mut rx: mpsc::Receiver<TransportMessage>,
+        polls: Vec<Vec<u8>>,
+        balances: Vec<(u16, u16)>,
+    ) -> tokio::task::JoinHandle<Vec<(Header, Vec<u8>)>> {
+        tokio::spawn(async move {
+            let mut sent = Vec::new();
+            let mut polled = 0;
+            while let Some(message) = rx.recv().await {
+                let data: Vec<u8> = match message.header {
+                    Header::RequestHopperBalance => {
+                        let (first, second) = balances[polled];
+                        match message.data[0] {
+                            1 => [b"EU050A".as_slice(), &first.to_le_bytes()].concat(),
+                            _ => [b"EU100A".as_slice(), &second.to_le_bytes()].concat(),
+                        }
+                    }
+                    Header::RequestCoinId => match message.data[0] {
+                        3 => b"EU050A".to_vec(),
+                        4 => b"EU100A".to_vec(),
+                        5 => b"EU200A".to_vec(),
+                        _ => b"......".to_vec(),
+                    },
+                    Header::ReadBufferedCreditOrErrorCodes => {
+                        polled += 1;
+                        polls[polled - 1].clone()
+                    }
+                    // Sorter path 1 is overridden.
+                    Header::RequestSorterOverrideStatus => vec![0b1111_1110],
+                    Header::ModifySorterOverrideStatus | Header::ModifyMasterInhibitStatus => {
+                        sent.push((message.header, message.data));
+                        vec![]
+                    }
+                    header => panic!("unexpected header {header:?}"),
+                };
+                let mut reply = vec![1, data.len() as u8, 55, 0];
+                reply.extend(data);
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+            sent
+        })
+    }
+
+    #[tokio::test]
+    async fn refill_session_reconciles_the_hopper_balances() {
+        let (tx, rx) = mpsc::channel(1);
+        let device = Device::new(55, Category::Changer, ChecksumType::Crc8);
+        let changer = Changer::new(device, tx);
+        // Two coins for hopper 1 of which one reaches it, one coin without hopper, then one
+        // coin for hopper 2.
+        let responder = refill_responder(
+            rx,
+            vec![
+                vec![3, 3, 1, 3, 1, 5, 2, 0, 0, 0, 0],
+                vec![4, 4, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+            vec![(10, 256), (11, 256), (11, 257)],
+        );
+
+        let mut session = changer
+            .begin_refill([1, 2])
+            .await
+            .expect("should begin refill");
+        session.poll().await.expect("should poll");
+        let hopper = session.hopper(1).expect("should refill hopper 1");
+        assert_eq!(
+            (hopper.credited, hopper.added(), hopper.diverted()),
+            (2, 1, 1)
+        );
+        assert_eq!(session.hopper(2).map(HopperRefill::added), Some(0));
+
+        let report = session.finish().await.expect("should finish");
+        drop(changer);
+        assert_eq!(report.coins_added(), 2);
+        assert_eq!(report.coins_diverted(), 1);
+        assert_eq!(report.unrouted_coins, 1);
+        assert_eq!(report.value_added(), 150);
+        assert_eq!(report.hoppers[0].balance, 11);
+        assert_eq!(report.hoppers[1].balance, 257);
+
+        // The overrides are cleared for the session and restored, the balances are never
+        // written.
+        let sent = responder.await.expect("should join");
+        assert_eq!(
+            sent,
+            vec![
+                (Header::ModifySorterOverrideStatus, vec![0xFF]),
+                (Header::ModifyMasterInhibitStatus, vec![1]),
+                (Header::ModifyMasterInhibitStatus, vec![0]),
+                (Header::ModifySorterOverrideStatus, vec![0xFE]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn refill_session_arms_the_changer_again_after_a_reset() {
+        let (tx, rx) = mpsc::channel(1);
+        let (reset_tx, mut reset_rx) = mpsc::channel(1);
+        let device = Device::new(55, Category::Changer, ChecksumType::Crc8);
+        let changer = Changer::new(device, tx).with_reset_events(reset_tx);
+        let responder = refill_responder(
+            rx,
+            vec![
+                vec![1, 3, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+            vec![(10, 256), (11, 256), (12, 256), (12, 256)],
+        );
+
+        let mut session = changer
+            .begin_refill([1, 2])
+            .await
+            .expect("should begin refill");
+        session.poll().await.expect("should poll");
+        let result = session.poll().await.expect("should poll");
+        assert_eq!(result.events[..], [CoinEvent::Reset]);
+        assert_eq!(changer.event_counter(), 0);
+        let reset = reset_rx.try_recv().expect("should emit the reset");
+        assert_eq!(reset.address, 55);
+        // Coins accepted before the reset are still counted by the changer.
+        assert_eq!(session.hopper(1).map(HopperRefill::added), Some(2));
+
+        let report = session.finish().await.expect("should finish");
+        drop(changer);
+        assert_eq!(report.coins_added(), 2);
+
+        let sent = responder.await.expect("should join");
+        assert_eq!(
+            sent,
+            vec![
+                (Header::ModifySorterOverrideStatus, vec![0xFF]),
+                (Header::ModifyMasterInhibitStatus, vec![1]),
+                (Header::ModifySorterOverrideStatus, vec![0xFF]),
+                (Header::ModifyMasterInhibitStatus, vec![1]),
+                (Header::ModifyMasterInhibitStatus, vec![0]),
+                (Header::ModifySorterOverrideStatus, vec![0xFE]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn money_out_is_paid_and_verified() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(55, Category::Changer, ChecksumType::Crc8);
+        let changer = Changer::new(device, tx);
+
+        let responder = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            while let Some(message) = rx.recv().await {
+                let data: Vec<u8> = match message.header {
+                    Header::VerifyMoneyOut => vec![1, 0xF4, 1, 0, 0, 10, 0, 0, 0],
+                    Header::PayMoneyOut | Header::PurgeHopper => vec![],
+                    header => panic!("unexpected header {header:?}"),
+                };
+                requests.push(message.data);
+                let mut reply = vec![1, data.len() as u8, 55, 0];
+                reply.extend(data);
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+            requests
+        });
+
+        changer.pay_money_out(510).await.expect("should pay out");
+        let result = changer.verify_money_out().await.expect("should verify");
+        assert_eq!((result.paid, result.unpaid), (500, 10));
+        changer.purge_hopper(2, 0).await.expect("should purge");
+        drop(changer);
+
+        let requests = responder.await.expect("should join");
+        assert_eq!(requests, vec![vec![0xFE, 1, 0, 0], vec![], vec![2, 0]]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Skew between the real time clock of the devices and the host clock.
+//!
+//! Devices with a real time clock stamp their audit data with their own time, the skew measured
+//! against the reception time of the reply converts those times to the host clock so they line
+//! up with the events stamped by the transport.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use cc_talk_host::device::device_commands::RequestRtcCommand;
+use tracing::{debug, instrument};
+
+use super::base::{DeviceCommon, DeviceResult};
+use crate::transport::timestamp::Timestamp;
+
+/// Offset of a device real time clock from the host wall clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkew {
+    /// Device time minus host time, in seconds, positive when the device clock is ahead.
+    pub offset_seconds: i64,
+    /// Reception time of the `RequestRealTimeClock` reply the offset was measured from.
+    pub measured_at: Timestamp,
+}
+
+impl ClockSkew {
+    /// Computes the skew of a device reporting `device_seconds` since the Unix epoch in a reply
+    /// received at `received_at`.
+    #[must_use]
+    pub fn new(device_seconds: u32, received_at: Timestamp) -> Self {
+        let host_seconds = received_at
+            .wall_clock
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self {
+            offset_seconds: i64::from(device_seconds)
+                - i64::try_from(host_seconds).unwrap_or(i64::MAX),
+            measured_at: received_at,
+        }
+    }
+
+    /// Converts a device time, in seconds since the Unix epoch, to the host wall clock.
+    ///
+    /// Returns `None` if the converted time is before the Unix epoch.
+    #[must_use]
+    pub fn to_host_time(&self, device_seconds: u32) -> Option<SystemTime> {
+        let host_seconds = i64::from(device_seconds).checked_sub(self.offset_seconds)?;
+        let host_seconds = u64::try_from(host_seconds).ok()?;
+        UNIX_EPOCH.checked_add(Duration::from_secs(host_seconds))
+    }
+}
+
+/// Shared clock skew of the devices, keyed by device address.
+///
+/// Clones share the same measurements, the application keeps one to convert the times reported
+/// by the devices when writing its audit logs.
+#[derive(Debug, Clone, Default)]
+pub struct ClockSkewTracker {
+    skews: Arc<Mutex<HashMap<u8, ClockSkew>>>,
+}
+
+impl ClockSkewTracker {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the real time clock of `device` and records its skew from the host clock.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the device does not answer `RequestRealTimeClock`, e.g. it has no real time
+    /// clock, the previous measurement is kept.
+    #[instrument(skip_all, fields(address = device.get_device().address()), level = "debug")]
+    pub async fn measure<D: DeviceCommon + Sync>(&self, device: &D) -> DeviceResult<ClockSkew> {
+        let reply = device.execute_with_packet(RequestRtcCommand).await?;
+        let skew = ClockSkew::new(reply.response, reply.received_at);
+        debug!(
+            offset_seconds = skew.offset_seconds,
+            "device clock skew measured"
+        );
+        self.skews
+            .lock()
+            .expect("should not be poisoned")
+            .insert(device.get_device().address(), skew);
+        Ok(skew)
+    }
+
+    /// Returns the last skew measured for the device at `address`.
+    #[must_use]
+    pub fn skew(&self, address: u8) -> Option<ClockSkew> {
+        self.skews
+            .lock()
+            .expect("should not be poisoned")
+            .get(&address)
+            .copied()
+    }
+
+    /// Converts a time reported by the device at `address` to the host wall clock.
+    ///
+    /// Returns `None` if the skew of the device was never measured.
+    #[must_use]
+    pub fn to_host_time(&self, address: u8, device_seconds: u32) -> Option<SystemTime> {
+        self.skew(address)?.to_host_time(device_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Device, Header};
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        device::coin_validator::CoinValidator, transport::tokio_transport::TransportMessage,
+    };
+
+    #[tokio::test]
+    async fn skew_is_measured_against_the_reception_time() {
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(1);
+        let validator = CoinValidator::new(
+            Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8),
+            tx,
+        );
+        let received_at = Timestamp::now();
+        let host_seconds = received_at
+            .wall_clock
+            .duration_since(UNIX_EPOCH)
+            .expect("should be after the epoch")
+            .as_secs();
+        let device_seconds = u32::try_from(host_seconds + 90).expect("should fit");
+
+        tokio::spawn(async move {
+            let message = rx.recv().await.expect("should request the clock");
+            assert_eq!(message.header, Header::RequestRealTimeClock);
+            message.received_at.stamp(received_at);
+            let [b0, b1, b2, b3] = device_seconds.to_le_bytes();
+            message
+                .respond_to
+                .send(Ok(vec![1, 4, 2, 0, b0, b1, b2, b3, 0]))
+                .expect("should respond");
+        });
+
+        let tracker = ClockSkewTracker::new();
+        assert_eq!(tracker.to_host_time(2, device_seconds), None);
+        let skew = tracker.measure(&validator).await.expect("should measure");
+        assert_eq!(skew.offset_seconds, 90);
+        assert_eq!(skew.measured_at, received_at);
+        assert_eq!(tracker.skew(2), Some(skew));
+        assert_eq!(
+            tracker.to_host_time(2, device_seconds),
+            UNIX_EPOCH.checked_add(Duration::from_secs(host_seconds))
+        );
+        assert_eq!(skew.to_host_time(0), None);
+    }
+}
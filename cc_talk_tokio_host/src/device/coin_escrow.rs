@@ -0,0 +1,282 @@
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use cc_talk_core::cc_talk::{Device, EscrowOperatingStatus, EscrowServiceStatus, EscrowStatus};
+use cc_talk_host::{command::Command, device::device_commands::*};
+use tokio::sync::mpsc;
+use tracing::{debug, info, instrument, trace, warn};
+
+use crate::transport::tokio_transport::TransportMessage;
+
+use super::{
+    base::{CommandError, DeviceCommon, DeviceResult},
+    triage::TriageConfig,
+};
+
+/// Interval between two status requests while waiting for the escrow flaps.
+const ESCROW_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Default time given to the escrow to accept or return a coin.
+pub const DEFAULT_ESCROW_OPERATION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What to do with the coin held by a coin escrow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscrowDecision {
+    /// Keep the coin in the escrow, e.g. until the vend succeeded.
+    Hold,
+    /// Drop the coin into the machine.
+    Accept,
+    /// Give the coin back to the customer.
+    Return,
+}
+
+/// A ccTalk coin escrow device driver.
+///
+/// A coin escrow sits below a coin acceptor and holds the last accepted coin until the host
+/// decides to accept or return it. Only escrows answering `RequestEscrowStatus` support this,
+/// see [`is_supported`](Self::is_supported).
+#[derive(Debug, Clone)]
+pub struct CoinEscrow {
+    /// The underlying ccTalk device configuration.
+    pub device: Device,
+    /// Channel sender for communicating with the transport layer.
+    pub sender: mpsc::Sender<TransportMessage>,
+    express_sender: Option<mpsc::Sender<TransportMessage>>,
+    timeout_triage: Option<TriageConfig>,
+}
+
+impl CoinEscrow {
+    /// Creates a new `CoinEscrow` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The ccTalk device configuration containing address and checksum type.
+    /// * `sender` - A channel sender for communicating with the transport layer.
+    pub fn new(device: Device, sender: mpsc::Sender<TransportMessage>) -> Self {
+        debug!(
+            address = device.address(),
+            category = ?device.category(),
+            "creating coin escrow"
+        );
+        Self {
+            device,
+            sender,
+            express_sender: None,
+            timeout_triage: None,
+        }
+    }
+
+    /// Routes express commands through the transport express lane.
+    ///
+    /// See [`CcTalkTokioTransport::express_sender`](crate::transport::tokio_transport::CcTalkTokioTransport::express_sender).
+    #[must_use]
+    pub fn with_express_lane(mut self, express_sender: mpsc::Sender<TransportMessage>) -> Self {
+        self.express_sender = Some(express_sender);
+        self
+    }
+
+    /// Classifies the timeouts of this driver, see [`DeviceCommon::timeout_triage`].
+    #[must_use]
+    pub const fn with_timeout_triage(self) -> Self {
+        self.with_timeout_triage_config(TriageConfig::new())
+    }
+
+    /// Classifies the timeouts of this driver, knowing the other devices configured on the bus.
+    #[must_use]
+    pub const fn with_timeout_triage_config(mut self, config: TriageConfig) -> Self {
+        self.timeout_triage = Some(config);
+        self
+    }
+
+    /// Returns `true` if the device answers escrow status requests.
+    pub async fn is_supported(&self) -> bool {
+        match self.request_status().await {
+            Ok(_) => true,
+            Err(error) => {
+                debug!(error = %error, "escrow status not supported");
+                false
+            }
+        }
+    }
+
+    /// Requests the operating status, fill level and fault code of the escrow.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_status(&self) -> DeviceResult<EscrowStatus> {
+        trace!("requesting escrow status");
+        let response_packet = self.send_command(RequestEscrowStatusCommand).await?;
+        let status: EscrowStatus = RequestEscrowStatusCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?
+            .into();
+        if status.has_fault() {
+            warn!(status = ?status, "escrow reports a fault");
+        } else {
+            debug!(status = ?status, "escrow status received");
+        }
+        Ok(status)
+    }
+
+    /// Starts moving the held coin, the call returns before the flaps finished moving.
+    ///
+    /// Use [`decide`](Self::decide) to wait for the escrow to be idle again.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn operate(&self, mode: DivertMode) -> DeviceResult<()> {
+        debug!(?mode, "operating escrow");
+        let response_packet = self.send_command(OperateEscrowCommand::new(mode)).await?;
+        OperateEscrowCommand::new(mode)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        Ok(())
+    }
+
+    /// Accepts the held coin and waits for the escrow to be idle.
+    pub async fn accept(&self) -> DeviceResult<EscrowStatus> {
+        self.decide(EscrowDecision::Accept, DEFAULT_ESCROW_OPERATION_TIMEOUT)
+            .await
+    }
+
+    /// Returns the held coin and waits for the escrow to be idle.
+    pub async fn return_coin(&self) -> DeviceResult<EscrowStatus> {
+        self.decide(EscrowDecision::Return, DEFAULT_ESCROW_OPERATION_TIMEOUT)
+            .await
+    }
+
+    /// Applies a decision to the held coin and returns the escrow status once it is idle.
+    ///
+    /// [`EscrowDecision::Hold`] only reads the status. A fault while moving the coin is reported
+    /// in the returned status, check [`EscrowStatus::has_fault`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CommandError::Timeout`] if the escrow is still operating after `timeout`.
+    #[instrument(skip(self), level = "info")]
+    pub async fn decide(
+        &self,
+        decision: EscrowDecision,
+        timeout: Duration,
+    ) -> DeviceResult<EscrowStatus> {
+        let mode = match decision {
+            EscrowDecision::Hold => return self.request_status().await,
+            EscrowDecision::Accept => DivertMode::AcceptCoins,
+            EscrowDecision::Return => DivertMode::ReturnCoins,
+        };
+        self.operate(mode).await?;
+
+        let started = Instant::now();
+        loop {
+            let status = self.request_status().await?;
+            if !matches!(status.operating, EscrowOperatingStatus::Operating) {
+                info!(?decision, status = ?status, "escrow decision applied");
+                return Ok(status);
+            }
+            if started.elapsed() >= timeout {
+                warn!(?decision, ?timeout, "escrow still operating");
+                return Err(CommandError::Timeout);
+            }
+            tokio::time::sleep(ESCROW_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Requests whether the escrow needs servicing, `clear` resets the report afterwards.
+    ///
+    /// Returns `None` if the escrow does not track its service status.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_service_status(
+        &self,
+        clear: bool,
+    ) -> DeviceResult<Option<EscrowServiceStatus>> {
+        trace!(clear, "requesting escrow service status");
+        let response_packet = if clear {
+            self.send_command(RequestServiceStatusCommand::new_clear_report())
+                .await?
+        } else {
+            self.send_command(RequestServiceStatusCommand::new_report())
+                .await?
+        };
+        let status = RequestServiceStatusCommand::new_report()
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(status = ?status, "escrow service status received");
+        Ok(status)
+    }
+}
+
+impl DeviceCommon for CoinEscrow {
+    fn get_device(&self) -> &Device {
+        &self.device
+    }
+
+    fn get_sender(&self) -> &mpsc::Sender<TransportMessage> {
+        &self.sender
+    }
+
+    fn get_express_sender(&self) -> Option<&mpsc::Sender<TransportMessage>> {
+        self.express_sender.as_ref()
+    }
+
+    fn timeout_triage(&self) -> Option<&TriageConfig> {
+        self.timeout_triage.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cc_talk_core::cc_talk::{
+        Category, ChecksumType, EscrowFaultCode, EscrowLevelStatus, Header,
+    };
+
+    #[tokio::test]
+    async fn decide_waits_until_the_escrow_is_idle() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(160, Category::Escrow, ChecksumType::Crc8);
+        let escrow = CoinEscrow::new(device, tx);
+
+        let responder = tokio::spawn(async move {
+            let mut operations = Vec::new();
+            let mut status_requests = 0;
+            while let Some(message) = rx.recv().await {
+                let data = match message.header {
+                    Header::OperateEscrow => {
+                        operations.push(message.data[0]);
+                        vec![]
+                    }
+                    Header::RequestEscrowStatus => {
+                        status_requests += 1;
+                        match status_requests {
+                            1 => vec![0, 0, 0],
+                            2 => vec![1, 0, 0],
+                            _ => vec![0, 0, 21],
+                        }
+                    }
+                    header => panic!("unexpected header {header:?}"),
+                };
+                let mut reply = vec![1, data.len() as u8, 160, 0];
+                reply.extend(data);
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+            operations
+        });
+
+        let status = escrow
+            .decide(EscrowDecision::Hold, DEFAULT_ESCROW_OPERATION_TIMEOUT)
+            .await
+            .expect("should read status");
+        assert!(status.is_idle());
+
+        let status = escrow.return_coin().await.expect("should return coin");
+        assert!(status.is_idle());
+        assert_eq!(status.level, EscrowLevelStatus::EmptyOrNotFull);
+        assert_eq!(
+            status.fault,
+            EscrowFaultCode::FailureToCloseAcceptFlapAfterAccept
+        );
+        assert!(status.has_fault());
+
+        drop(escrow);
+        let operations = responder.await.expect("should join");
+        assert_eq!(operations, vec![DivertMode::ReturnCoins as u8]);
+    }
+}
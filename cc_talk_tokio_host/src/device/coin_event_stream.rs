@@ -0,0 +1,179 @@
+use std::{
+    collections::VecDeque,
+    ops::DerefMut,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use cc_talk_core::cc_talk::{CoinAcceptorPollResult, CoinEvent};
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use super::base::DeviceResult;
+
+type PollResultReceiver = mpsc::Receiver<DeviceResult<CoinAcceptorPollResult>>;
+
+/// Item of a [`CoinEventStream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoinStreamEvent {
+    /// A credit, an error or a reset, in the order they happened.
+    Event(CoinEvent),
+    /// Events which were overwritten in the event buffer before they could be read, reported
+    /// before the events read along with them.
+    Lost(u8),
+}
+
+/// Coin events read by the background polling of a [`CoinValidator`](super::coin_validator::CoinValidator).
+///
+/// The validator tracks the event counter, including its wrap from 255 to 1, so each event is
+/// yielded exactly once. A reset of the device is yielded as [`CoinEvent::Reset`], failed polls
+/// as errors, the stream then keeps polling.
+///
+/// Polling stops when the stream is dropped.
+pub struct CoinEventStream {
+    receiver: Box<dyn DerefMut<Target = PollResultReceiver> + Send + Sync>,
+    pending: VecDeque<CoinStreamEvent>,
+}
+
+impl std::fmt::Debug for CoinEventStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoinEventStream")
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CoinEventStream {
+    pub(crate) fn new(
+        receiver: impl DerefMut<Target = PollResultReceiver> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            receiver: Box::new(receiver),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Waits for the next event, see [`Stream::poll_next`].
+    pub async fn next(&mut self) -> Option<DeviceResult<CoinStreamEvent>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Queues the events of a poll, oldest first, the device reports them newest first.
+    fn push_poll_result(&mut self, result: &CoinAcceptorPollResult) {
+        if result.lost_events > 0 {
+            self.pending
+                .push_back(CoinStreamEvent::Lost(result.lost_events));
+        }
+        self.pending.extend(
+            result
+                .events
+                .iter()
+                .rev()
+                .copied()
+                .map(CoinStreamEvent::Event),
+        );
+    }
+}
+
+impl Stream for CoinEventStream {
+    type Item = DeviceResult<CoinStreamEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(Ok(result))) => self.push_poll_result(&result),
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use cc_talk_core::cc_talk::{
+        Category, ChecksumType, CoinAcceptorError, CoinCredit, Device, Header, SorterPath,
+    };
+
+    use crate::{
+        device::{base::CommandError, coin_validator::CoinValidator},
+        transport::tokio_transport::TransportError,
+    };
+
+    use super::*;
+
+    fn credit(credit: u8) -> CoinStreamEvent {
+        CoinStreamEvent::Event(CoinEvent::Credit(CoinCredit {
+            credit,
+            sorter_path: SorterPath::Path(1),
+        }))
+    }
+
+    #[tokio::test]
+    async fn events_are_yielded_oldest_first_across_the_counter_wrap() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+        let validator = CoinValidator::new(device, tx);
+
+        tokio::spawn(async move {
+            let replies: [&[u8]; 4] = [
+                &[254, 5, 1, 4, 1, 3, 1, 2, 1, 1, 1],
+                &[2, 0, 1, 7, 1, 6, 1, 0, 0, 0, 0],
+                &[],
+                &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            ];
+            for reply in replies {
+                let message = rx.recv().await.expect("should poll");
+                assert_eq!(message.header, Header::ReadBufferedCreditOrErrorCodes);
+                let response = if reply.is_empty() {
+                    Err(TransportError::Timeout)
+                } else {
+                    let mut frame = vec![1, reply.len() as u8, 2, 0];
+                    frame.extend_from_slice(reply);
+                    let sum = frame.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+                    frame.push(sum.wrapping_neg());
+                    Ok(frame)
+                };
+                message.respond_to.send(response).expect("should respond");
+            }
+        });
+
+        let mut stream = validator
+            .event_stream(Duration::from_millis(1), 4)
+            .expect("should start polling");
+        let mut events = Vec::new();
+        for _ in 0..11 {
+            events.push(stream.next().await.expect("should keep polling"));
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                // The first poll synchronises the counter, older events are lost.
+                Ok(CoinStreamEvent::Lost(249)),
+                Ok(credit(1)),
+                Ok(credit(2)),
+                Ok(credit(3)),
+                Ok(credit(4)),
+                Ok(credit(5)),
+                // 254 to 2 wraps over 255 and 1.
+                Ok(credit(6)),
+                Ok(credit(7)),
+                Ok(CoinStreamEvent::Event(CoinEvent::Error(
+                    CoinAcceptorError::RejectCoin
+                ))),
+                Err(CommandError::Timeout),
+                Ok(CoinStreamEvent::Event(CoinEvent::Reset)),
+            ]
+        );
+
+        drop(stream);
+        assert!(validator.event_stream(Duration::from_millis(1), 4).is_ok());
+    }
+}
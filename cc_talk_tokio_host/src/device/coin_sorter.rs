@@ -0,0 +1,413 @@
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+
+use cc_talk_core::cc_talk::{BitMask, Device, SorterPath};
+use cc_talk_host::{command::Command, device::device_commands::*};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::{debug, info, instrument, trace};
+
+use crate::transport::tokio_transport::TransportMessage;
+
+use super::{
+    base::{CommandError, DeviceCommon, DeviceResult, LongOperation},
+    coin_validator::{CoinAcceptor, CoinValidator},
+    triage::TriageConfig,
+};
+
+/// Number of sorter paths, each one has an override bit.
+const SORTER_PATHS: u8 = 8;
+
+/// Intent of a diversion: accepted coins of `coin_position` should go to sorter `path`, e.g.
+/// the cashbox path when the hopper they fill is full.
+///
+/// See [`CoinSorter::divert`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DivertTarget {
+    pub coin_position: u8,
+    pub path: u8,
+}
+
+impl DivertTarget {
+    #[must_use]
+    pub const fn new(coin_position: u8, path: u8) -> Self {
+        Self {
+            coin_position,
+            path,
+        }
+    }
+}
+
+/// Sorter configuration a [`DivertPlan`] is computed from, see [`CoinSorter::layout`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SorterLayout {
+    pub default_path: u8,
+    /// Sorter path of each coin position, positions without a path are left out.
+    pub coin_paths: BTreeMap<u8, u8>,
+}
+
+/// Diversion which cannot be planned, see [`DivertPlan::compute`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum DivertError {
+    #[error("coin position {0} has no sorter path")]
+    UnknownCoin(u8),
+    #[error("sorter path {0} is out of range, paths go from 1 to 8")]
+    InvalidPath(u8),
+}
+
+/// Sorter changes diverting coins as requested by [`DivertTarget`]s.
+///
+/// The sorter has a single default path and one override bit per path, an overridden path sends
+/// all its coins to the default path. Diversions are made with overrides, which are not stored
+/// by the device, and only fall back to changing the sorter path of a coin when an override
+/// would divert other coins too or the default path is already used for another destination.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DivertPlan {
+    /// New default path, `None` if it is kept.
+    pub default_path: Option<u8>,
+    /// Paths sending their coins to the default path, indexed from path 1.
+    pub overrides: [bool; 8],
+    /// Coin positions moved to another path, as `(coin_position, path)`.
+    pub coin_paths: Vec<(u8, u8)>,
+}
+
+impl DivertPlan {
+    /// Computes the changes diverting the coins of `targets` on a sorter configured as `layout`.
+    ///
+    /// The plan replaces the overrides of previous plans, coins which are not targeted follow
+    /// their own path again.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a target coin has no sorter path or a path is out of range.
+    pub fn compute(layout: &SorterLayout, targets: &[DivertTarget]) -> Result<Self, DivertError> {
+        // Destination of the diverted coins of each source path.
+        let mut sources: BTreeMap<u8, Vec<DivertTarget>> = BTreeMap::new();
+        for target in targets {
+            if !(1..=SORTER_PATHS).contains(&target.path) {
+                return Err(DivertError::InvalidPath(target.path));
+            }
+            let path = *layout
+                .coin_paths
+                .get(&target.coin_position)
+                .ok_or(DivertError::UnknownCoin(target.coin_position))?;
+            if path != target.path {
+                sources.entry(path).or_default().push(*target);
+            }
+        }
+
+        // A path can be overridden if all its coins go to the same destination.
+        let overridable = |path: u8, targets: &[DivertTarget]| {
+            let destination = targets[0].path;
+            (1..=SORTER_PATHS).contains(&path)
+                && targets.iter().all(|target| target.path == destination)
+                && layout
+                    .coin_paths
+                    .iter()
+                    .filter(|(_, coin_path)| **coin_path == path)
+                    .all(|(position, _)| {
+                        targets
+                            .iter()
+                            .any(|target| target.coin_position == *position)
+                    })
+        };
+        let mut destinations: BTreeMap<u8, usize> = BTreeMap::new();
+        for (path, targets) in &sources {
+            if overridable(*path, targets) {
+                *destinations.entry(targets[0].path).or_default() += 1;
+            }
+        }
+        // Keep the default path when possible, otherwise serve most of the overrides.
+        let default_path = if destinations.contains_key(&layout.default_path) {
+            Some(layout.default_path)
+        } else {
+            destinations
+                .iter()
+                .max_by_key(|(_, count)| **count)
+                .map(|(destination, _)| *destination)
+        };
+
+        let mut plan = Self {
+            default_path: default_path.filter(|path| *path != layout.default_path),
+            ..Self::default()
+        };
+        for (path, targets) in sources {
+            if default_path == Some(targets[0].path) && overridable(path, &targets) {
+                plan.overrides[usize::from(path - 1)] = true;
+            } else {
+                plan.coin_paths.extend(
+                    targets
+                        .iter()
+                        .map(|target| (target.coin_position, target.path)),
+                );
+            }
+        }
+        Ok(plan)
+    }
+}
+
+/// Sorter fitted below a ccTalk coin validator.
+///
+/// The sorter routes accepted coins to different paths, e.g. to fill hoppers. It is obtained
+/// from [`CoinValidator::sorter`] so that sorter commands are never sent to pure validators.
+///
+/// Shares its state, e.g. polling and event counters, with the validator it was obtained from.
+#[derive(Debug, Clone)]
+pub struct CoinSorter {
+    validator: CoinValidator,
+}
+
+impl CoinSorter {
+    pub(crate) const fn new(validator: CoinValidator) -> Self {
+        Self { validator }
+    }
+
+    /// Sets the default sorter path for accepted coins.
+    ///
+    /// The sorter path determines which physical output path coins are directed to
+    /// after acceptance.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_default_path` - The sorter path number (device-specific range).
+    #[instrument(skip(self), fields(new_default_path), level = "debug")]
+    pub async fn set_default_sorter_path(&self, new_default_path: u8) -> DeviceResult<()> {
+        debug!(path = new_default_path, "setting default sorter path");
+        let command = ModifyDefaultSorterPathCommand::new(new_default_path);
+        let response_packet = self.send_command(command).await?;
+        ModifyDefaultSorterPathCommand::new(new_default_path)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        info!(path = new_default_path, "default sorter path set");
+        Ok(())
+    }
+
+    /// Returns the current default sorter path.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_default_sorter_path(&self) -> DeviceResult<SorterPath> {
+        trace!("requesting default sorter path");
+        let response_packet = self.send_command(RequestDefaultSorterPathCommand).await?;
+        let path = RequestDefaultSorterPathCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(path = ?path, "default sorter path received");
+        Ok(path)
+    }
+
+    /// Sets the sorter override status for each of the 8 sorter paths.
+    /// The `overrides` array should contain 8 boolean values, where each value corresponds
+    /// True: sorter override to a different or default path.
+    /// False: no action
+    #[instrument(skip(self), level = "debug")]
+    pub async fn modify_sorter_override_status(&self, overrides: [bool; 8]) -> DeviceResult<()> {
+        debug!(overrides = ?overrides, "modifying sorter override status");
+        let mut bitmask = BitMask::<1>::new(8).map_err(|_| CommandError::BufferOverflow)?;
+        for (i, should_override) in overrides.iter().enumerate() {
+            bitmask
+                // Invert value since 0 is override and 1 is no override
+                .set_bit(i, !*should_override)
+                .map_err(|_| CommandError::BufferOverflow)?;
+        }
+
+        let command = ModifySorterOverrideStatusCommand::build(bitmask)
+            .map_err(|_| CommandError::BufferOverflow)?;
+
+        let response_packet = self.send_command(command).await?;
+        let bitmask = BitMask::<1>::new(8).map_err(|_| CommandError::BufferOverflow)?;
+        ModifySorterOverrideStatusCommand::build(bitmask)
+            .map_err(|_| CommandError::BufferOverflow)?
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        info!(overrides = ?overrides, "sorter override status modified");
+        Ok(())
+    }
+
+    /// Requests the sorter override status for each of the 8 sorter paths.
+    /// The returned BitMask will have 8 bits, where each bit corresponds to a sorter
+    /// 1: sorter override to a different or default path.
+    /// 0: no override
+    #[instrument(skip(self), level = "debug")]
+    pub async fn request_sorter_override_status(&self) -> DeviceResult<BitMask<1>> {
+        trace!("requesting sorter override status");
+        let response_packet = self
+            .send_command(RequestSorterOverrideStatusCommand)
+            .await?;
+        let mask = RequestSorterOverrideStatusCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)
+            .map(|mut mask| {
+                mask.flip();
+                mask
+            })?;
+        debug!(mask = ?mask, "sorter override status received");
+        Ok(mask)
+    }
+
+    /// Sets the sorter path for a specific coin position.
+    ///
+    /// # Arguments
+    ///
+    /// * `coin_position` - The coin position (0-15).
+    /// * `path` - The sorter path to assign to this coin.
+    #[instrument(skip(self), fields(coin_position, path), level = "debug")]
+    pub async fn set_coin_sorter_path(&self, coin_position: u8, path: u8) -> DeviceResult<()> {
+        debug!(coin_position, path, "setting coin sorter path");
+        let command = ModifySorterPathCommand::new(coin_position, path);
+        let response_packet = self.send_command(command).await?;
+        ModifySorterPathCommand::new(coin_position, path)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        trace!(coin_position, path, "coin sorter path set");
+        Ok(())
+    }
+
+    /// Returns the sorter path configured for a specific coin position.
+    ///
+    /// # Arguments
+    ///
+    /// * `coin_position` - The coin position (0-15).
+    #[instrument(skip(self), fields(coin_position), level = "debug")]
+    pub async fn get_coin_sorter_path(&self, coin_position: u8) -> DeviceResult<SorterPath> {
+        trace!(coin_position, "requesting coin sorter path");
+        let response_packet = self
+            .send_command(RequestSorterPathCommand::new(coin_position))
+            .await?;
+        let path = RequestSorterPathCommand::new(coin_position)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        trace!(coin_position, path = ?path, "coin sorter path received");
+        Ok(path)
+    }
+}
+
+impl CoinSorter {
+    /// Reads the default path and the sorter path of every coin of the coin table.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn layout(&self) -> DeviceResult<SorterLayout> {
+        let default_path = match self.get_default_sorter_path().await? {
+            SorterPath::Path(path) => path,
+            SorterPath::NotSupported => 0,
+        };
+        let mut coin_paths = BTreeMap::new();
+        for (position, _) in self.validator.coin_table().await?.iter() {
+            if let SorterPath::Path(path) = self.get_coin_sorter_path(position).await? {
+                coin_paths.insert(position, path);
+            }
+        }
+        debug!(default_path, coin_paths = ?coin_paths, "sorter layout read");
+        Ok(SorterLayout {
+            default_path,
+            coin_paths,
+        })
+    }
+
+    /// Diverts accepted coins as described by `targets` and returns the applied plan.
+    ///
+    /// Each call replaces the previous diversions, call it with no target to route every coin
+    /// to its own path again. See [`DivertPlan`] for how the changes are chosen.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn divert(&self, targets: &[DivertTarget]) -> DeviceResult<DivertPlan> {
+        let layout = self.layout().await?;
+        let plan = DivertPlan::compute(&layout, targets).map_err(CommandError::InvalidDivert)?;
+        if let Some(default_path) = plan.default_path {
+            self.set_default_sorter_path(default_path).await?;
+        }
+        for (coin_position, path) in &plan.coin_paths {
+            self.set_coin_sorter_path(*coin_position, *path).await?;
+        }
+        self.modify_sorter_override_status(plan.overrides).await?;
+        info!(plan = ?plan, "coins diverted");
+        Ok(plan)
+    }
+}
+
+impl CoinAcceptor for CoinSorter {
+    fn coin_validator(&self) -> &CoinValidator {
+        &self.validator
+    }
+}
+
+impl DeviceCommon for CoinSorter {
+    fn get_device(&self) -> &Device {
+        self.validator.get_device()
+    }
+
+    fn get_sender(&self) -> &mpsc::Sender<TransportMessage> {
+        self.validator.get_sender()
+    }
+
+    fn get_express_sender(&self) -> Option<&mpsc::Sender<TransportMessage>> {
+        self.validator.get_express_sender()
+    }
+
+    fn timeout_triage(&self) -> Option<&TriageConfig> {
+        self.validator.timeout_triage()
+    }
+
+    fn get_long_operation(&self) -> Option<&LongOperation> {
+        self.validator.get_long_operation()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn layout() -> SorterLayout {
+        SorterLayout {
+            default_path: 1,
+            coin_paths: BTreeMap::from([(1, 2), (2, 2), (3, 3), (4, 4)]),
+        }
+    }
+
+    #[test]
+    fn diversions_use_overrides_of_the_default_path() {
+        let plan = DivertPlan::compute(
+            &layout(),
+            &[
+                DivertTarget::new(1, 1),
+                DivertTarget::new(2, 1),
+                DivertTarget::new(3, 1),
+            ],
+        )
+        .unwrap();
+        assert_eq!(plan.default_path, None);
+        assert_eq!(
+            plan.overrides,
+            [false, true, true, false, false, false, false, false]
+        );
+        assert!(plan.coin_paths.is_empty());
+
+        let plan = DivertPlan::compute(&layout(), &[DivertTarget::new(4, 5)]).unwrap();
+        assert_eq!(plan.default_path, Some(5));
+        assert!(plan.overrides[3]);
+    }
+
+    #[test]
+    fn diversions_fall_back_to_coin_paths() {
+        // Coin 2 shares path 2 with coin 1, which stays.
+        let plan = DivertPlan::compute(&layout(), &[DivertTarget::new(1, 1)]).unwrap();
+        assert_eq!(plan.overrides, [false; 8]);
+        assert_eq!(plan.coin_paths, vec![(1, 1)]);
+
+        // A single default path serves one destination.
+        let plan = DivertPlan::compute(
+            &layout(),
+            &[DivertTarget::new(3, 1), DivertTarget::new(4, 5)],
+        )
+        .unwrap();
+        assert_eq!(plan.default_path, None);
+        assert!(plan.overrides[2]);
+        assert_eq!(plan.coin_paths, vec![(4, 5)]);
+
+        assert_eq!(
+            DivertPlan::compute(&layout(), &[DivertTarget::new(9, 1)]),
+            Err(DivertError::UnknownCoin(9))
+        );
+        assert_eq!(
+            DivertPlan::compute(&layout(), &[DivertTarget::new(1, 9)]),
+            Err(DivertError::InvalidPath(9))
+        );
+    }
+}
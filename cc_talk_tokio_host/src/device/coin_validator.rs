@@ -1,26 +1,53 @@
 #![allow(dead_code)]
 
 use std::{
+    collections::BTreeMap,
+    ops::RangeInclusive,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use cc_talk_core::cc_talk::{BitMask, CoinAcceptorPollResult, CurrencyToken, Device, SorterPath};
+use cc_talk_core::cc_talk::{
+    AcceptLimitFormat, BitMask, CoinAcceptorPollResult, CoinEvent, CoinType, CreditCodeFormat,
+    CurrencyToken, Device, SorterPath, TeachModeStatus,
+};
 use cc_talk_host::{command::Command, device::device_commands::*};
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::{
-    device::base::PollingError, transport::tokio_transport::TransportMessage, util::DropGuard,
+    device::base::PollingError,
+    transport::{timestamp::Stamped, tokio_transport::TransportMessage},
+    util::DropGuard,
+};
+
+#[cfg(feature = "encryption")]
+use super::event_encryption::EventEncryption;
+use super::{
+    base::{CommandError, DeviceCommon, DeviceResult, LongOperation},
+    coin_event_stream::CoinEventStream,
+    coin_sorter::CoinSorter,
+    quirks::DeviceQuirks,
+    reset::{DeviceReset, ReinitializationFuture, ResetRecovery},
+    triage::TriageConfig,
 };
 
-use super::base::{CommandError, DeviceCommon, DeviceResult};
+/// Behaviour shared by every coin acceptor driver, whether or not a sorter is fitted.
+///
+/// Accept `impl CoinAcceptor` to handle a [`CoinValidator`] and a [`CoinSorter`] alike.
+pub trait CoinAcceptor: DeviceCommon {
+    /// Returns the driver handling coin acceptance.
+    fn coin_validator(&self) -> &CoinValidator;
+}
 
 /// A ccTalk coin validator device driver.
 ///
 /// This struct provides methods to communicate with and control a coin validator
-/// over the ccTalk protocol. It supports coin acceptance, inhibit control, sorter
-/// path configuration, and background polling for coin events.
+/// over the ccTalk protocol. It supports coin acceptance, inhibit control and
+/// background polling for coin events.
+///
+/// Sorter paths are only configurable on validators fitted with a sorter, see
+/// [`sorter`](Self::sorter).
 ///
 /// # Cloning
 ///
@@ -33,10 +60,26 @@ pub struct CoinValidator {
     pub device: Device,
     /// Channel sender for communicating with the transport layer.
     pub sender: mpsc::Sender<TransportMessage>,
+    express_sender: Option<mpsc::Sender<TransportMessage>>,
+    timeout_triage: Option<TriageConfig>,
+    quirks: Option<Arc<dyn DeviceQuirks>>,
+    accept_limit_format: AcceptLimitFormat,
+    long_operation: LongOperation,
     event_counter: Arc<Mutex<u8>>,
+    master_inhibit: Arc<Mutex<Option<bool>>>,
     is_polling: Arc<Mutex<bool>>,
+    #[cfg(feature = "encryption")]
+    event_encryption: Option<EventEncryption>,
+    coin_table: Arc<Mutex<Option<CoinTable>>>,
+    token_values: Arc<Mutex<BTreeMap<u8, u32>>>,
+    throttled_acceptance: Arc<Mutex<Option<ThrottledAcceptance>>>,
+    value_limit: Arc<Mutex<Option<ValueLimit>>>,
+    reset_recovery: ResetRecovery<CoinValidator>,
 }
 
+/// Coin positions read when building the [`CoinTable`].
+pub const COIN_POSITIONS: RangeInclusive<u8> = 1..=16;
+
 type PollResultReceiver = mpsc::Receiver<DeviceResult<CoinAcceptorPollResult>>;
 
 impl CoinValidator {
@@ -55,11 +98,125 @@ impl CoinValidator {
         CoinValidator {
             device,
             sender,
+            express_sender: None,
+            quirks: None,
+            timeout_triage: None,
+            accept_limit_format: AcceptLimitFormat::default(),
+            long_operation: LongOperation::default(),
             event_counter: Arc::new(Mutex::new(0)),
+            master_inhibit: Arc::new(Mutex::new(None)),
             is_polling: Arc::new(Mutex::new(false)),
+            #[cfg(feature = "encryption")]
+            event_encryption: None,
+            coin_table: Arc::new(Mutex::new(None)),
+            token_values: Arc::new(Mutex::new(BTreeMap::new())),
+            throttled_acceptance: Arc::new(Mutex::new(None)),
+            value_limit: Arc::new(Mutex::new(None)),
+            reset_recovery: ResetRecovery::default(),
         }
     }
 
+    /// Routes express commands through the transport express lane.
+    ///
+    /// See [`CcTalkTokioTransport::express_sender`](crate::transport::tokio_transport::CcTalkTokioTransport::express_sender).
+    #[must_use]
+    pub fn with_express_lane(mut self, express_sender: mpsc::Sender<TransportMessage>) -> Self {
+        self.express_sender = Some(express_sender);
+        self
+    }
+
+    /// Rewrites the replies of a device deviating from the specification before they are
+    /// parsed, see [`DeviceQuirks`].
+    #[must_use]
+    pub fn with_quirks(mut self, quirks: Arc<dyn DeviceQuirks>) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Classifies the timeouts of this driver, see [`DeviceCommon::timeout_triage`].
+    #[must_use]
+    pub const fn with_timeout_triage(self) -> Self {
+        self.with_timeout_triage_config(TriageConfig::new())
+    }
+
+    /// Classifies the timeouts of this driver, knowing the other devices configured on the bus.
+    #[must_use]
+    pub const fn with_timeout_triage_config(mut self, config: TriageConfig) -> Self {
+        self.timeout_triage = Some(config);
+        self
+    }
+
+    /// Sets the `SetAcceptLimit` payload format supported by the device.
+    ///
+    /// Defaults to [`AcceptLimitFormat::SingleByte`], see [`set_accept_limit`](Self::set_accept_limit).
+    #[must_use]
+    pub const fn with_accept_limit_format(mut self, format: AcceptLimitFormat) -> Self {
+        self.accept_limit_format = format;
+        self
+    }
+
+    /// Registers the re-initialization run when a poll detects an unexpected reset, e.g.
+    /// re-applying the coin inhibits and disabling the master inhibit.
+    ///
+    /// See [`reset`](super::reset) for an example.
+    #[must_use]
+    pub fn with_reset_handler<F>(mut self, handler: F) -> Self
+    where
+        F: for<'a> Fn(&'a CoinValidator) -> ReinitializationFuture<'a> + Send + Sync + 'static,
+    {
+        self.reset_recovery.set_handler(handler);
+        self
+    }
+
+    /// Polls with `ReadEncryptedEvents` instead of `ReadBufferedCreditOrErrorCodes`, see [`EventEncryption`].
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn with_event_encryption(mut self, encryption: EventEncryption) -> Self {
+        self.event_encryption = Some(encryption);
+        self
+    }
+
+    /// Emits a [`DeviceReset`] to `events` whenever a poll detects an unexpected reset.
+    ///
+    /// Every call adds a receiver, the resets are emitted to all of them.
+    #[must_use]
+    pub fn with_reset_events(mut self, events: mpsc::Sender<DeviceReset>) -> Self {
+        self.reset_recovery.add_events(events);
+        self
+    }
+
+    /// Values token positions, in smallest currency units, see
+    /// [`set_token_value`](Self::set_token_value).
+    #[must_use]
+    pub fn with_token_values(self, values: impl IntoIterator<Item = (u8, u32)>) -> Self {
+        for (position, value) in values {
+            self.set_token_value(position, Some(value));
+        }
+        self
+    }
+
+    /// Sets the value credited for the token at `position`, `None` to credit it without value.
+    ///
+    /// Tokens carry no value in their coin id, the configured values are used by
+    /// [`CoinTable::value`] like coin values, e.g. to enforce a host-side value limit or by the
+    /// currency acceptor pool.
+    pub fn set_token_value(&self, position: u8, value: Option<u32>) {
+        let mut token_values = self.token_values.lock().expect("should not be poisoned");
+        match value {
+            Some(value) => token_values.insert(position, value),
+            None => token_values.remove(&position),
+        };
+        if let Some(table) = self
+            .coin_table
+            .lock()
+            .expect("should not be poisoned")
+            .as_mut()
+        {
+            table.token_values.clone_from(&token_values);
+        }
+        debug!(position, value, "token value set");
+    }
+
     /// Returns the current event counter value.
     ///
     /// The event counter tracks the number of coin events that have occurred.
@@ -93,6 +250,7 @@ impl CoinValidator {
             .map_err(|_| CommandError::BufferOverflow)?
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)?;
+        *self.master_inhibit.lock().expect("should not be poisoned") = Some(inhibit);
         info!(inhibit, "master inhibit status set");
         Ok(())
     }
@@ -128,10 +286,16 @@ impl CoinValidator {
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)
             .map(|bytes| bytes[0] == 0)?;
+        *self.master_inhibit.lock().expect("should not be poisoned") = Some(status);
         debug!(inhibited = status, "master inhibit status received");
         Ok(status)
     }
 
+    /// Returns the master inhibit status last set or read, `None` until then.
+    pub fn cached_master_inhibit(&self) -> Option<bool> {
+        *self.master_inhibit.lock().expect("should not be poisoned")
+    }
+
     /// Checks if master inhibit is currently enabled.
     ///
     /// Returns `true` if the validator is rejecting all coins.
@@ -147,121 +311,26 @@ impl CoinValidator {
         Ok(!status)
     }
 
-    /// Sets the default sorter path for accepted coins.
+    /// Detects a sorter and returns the driver configuring its paths.
     ///
-    /// The sorter path determines which physical output path coins are directed to
-    /// after acceptance.
-    ///
-    /// # Arguments
-    ///
-    /// * `new_default_path` - The sorter path number (device-specific range).
-    #[instrument(skip(self), fields(new_default_path), level = "debug")]
-    pub async fn set_default_sorter_path(&self, new_default_path: u8) -> DeviceResult<()> {
-        debug!(path = new_default_path, "setting default sorter path");
-        let command = ModifyDefaultSorterPathCommand::new(new_default_path);
-        let response_packet = self.send_command(command).await?;
-        ModifyDefaultSorterPathCommand::new(new_default_path)
-            .parse_response(response_packet.get_data()?)
-            .map_err(CommandError::from)?;
-        info!(path = new_default_path, "default sorter path set");
-        Ok(())
-    }
-
-    /// Returns the current default sorter path.
-    #[instrument(skip(self), level = "debug")]
-    pub async fn get_default_sorter_path(&self) -> DeviceResult<SorterPath> {
-        trace!("requesting default sorter path");
-        let response_packet = self.send_command(RequestDefaultSorterPathCommand).await?;
-        let path = RequestDefaultSorterPathCommand
-            .parse_response(response_packet.get_data()?)
-            .map_err(CommandError::from)?;
-        debug!(path = ?path, "default sorter path received");
-        Ok(path)
-    }
-
-    /// Sets the sorter override status for each of the 8 sorter paths.
-    /// The `overrides` array should contain 8 boolean values, where each value corresponds
-    /// True: sorter override to a different or default path.
-    /// False: no action
+    /// Requests the sorter path of the first coin position, returns `None` if the device
+    /// NAKs the request or reports that sorter paths are not supported.
     #[instrument(skip(self), level = "debug")]
-    pub async fn modify_sorter_override_status(&self, overrides: [bool; 8]) -> DeviceResult<()> {
-        debug!(overrides = ?overrides, "modifying sorter override status");
-        let mut bitmask = BitMask::<1>::new(8).map_err(|_| CommandError::BufferOverflow)?;
-        for (i, should_override) in overrides.iter().enumerate() {
-            bitmask
-                // Invert value since 0 is override and 1 is no override
-                .set_bit(i, !*should_override)
-                .map_err(|_| CommandError::BufferOverflow)?;
+    pub async fn sorter(&self) -> DeviceResult<Option<CoinSorter>> {
+        trace!("detecting sorter");
+        let path = match self.send_command(RequestSorterPathCommand::new(1)).await {
+            Ok(response_packet) => RequestSorterPathCommand::new(1)
+                .parse_response(response_packet.get_data()?)
+                .map_err(CommandError::from)?,
+            Err(error) if error.is_nack() => SorterPath::NotSupported,
+            Err(error) => return Err(error),
+        };
+        if path == SorterPath::NotSupported {
+            debug!("no sorter fitted");
+            return Ok(None);
         }
-
-        let command = ModifySorterOverrideStatusCommand::build(bitmask)
-            .map_err(|_| CommandError::BufferOverflow)?;
-
-        let response_packet = self.send_command(command).await?;
-        let bitmask = BitMask::<1>::new(8).map_err(|_| CommandError::BufferOverflow)?;
-        ModifySorterOverrideStatusCommand::build(bitmask)
-            .map_err(|_| CommandError::BufferOverflow)?
-            .parse_response(response_packet.get_data()?)
-            .map_err(CommandError::from)?;
-        info!(overrides = ?overrides, "sorter override status modified");
-        Ok(())
-    }
-
-    /// Requests the sorter override status for each of the 8 sorter paths.
-    /// The returned BitMask will have 8 bits, where each bit corresponds to a sorter
-    /// 1: sorter override to a different or default path.
-    /// 0: no override
-    #[instrument(skip(self), level = "debug")]
-    pub async fn request_sorter_override_status(&self) -> DeviceResult<BitMask<1>> {
-        trace!("requesting sorter override status");
-        let response_packet = self
-            .send_command(RequestSorterOverrideStatusCommand)
-            .await?;
-        let mask = RequestSorterOverrideStatusCommand
-            .parse_response(response_packet.get_data()?)
-            .map_err(CommandError::from)
-            .map(|mut mask| {
-                mask.flip();
-                mask
-            })?;
-        debug!(mask = ?mask, "sorter override status received");
-        Ok(mask)
-    }
-
-    /// Sets the sorter path for a specific coin position.
-    ///
-    /// # Arguments
-    ///
-    /// * `coin_position` - The coin position (0-15).
-    /// * `path` - The sorter path to assign to this coin.
-    #[instrument(skip(self), fields(coin_position, path), level = "debug")]
-    pub async fn set_coin_sorter_path(&self, coin_position: u8, path: u8) -> DeviceResult<()> {
-        debug!(coin_position, path, "setting coin sorter path");
-        let command = ModifySorterPathCommand::new(coin_position, path);
-        let response_packet = self.send_command(command).await?;
-        ModifySorterPathCommand::new(coin_position, path)
-            .parse_response(response_packet.get_data()?)
-            .map_err(CommandError::from)?;
-        trace!(coin_position, path, "coin sorter path set");
-        Ok(())
-    }
-
-    /// Returns the sorter path configured for a specific coin position.
-    ///
-    /// # Arguments
-    ///
-    /// * `coin_position` - The coin position (0-15).
-    #[instrument(skip(self), fields(coin_position), level = "debug")]
-    pub async fn get_coin_sorter_path(&self, coin_position: u8) -> DeviceResult<SorterPath> {
-        trace!(coin_position, "requesting coin sorter path");
-        let response_packet = self
-            .send_command(RequestSorterPathCommand::new(coin_position))
-            .await?;
-        let path = RequestSorterPathCommand::new(coin_position)
-            .parse_response(response_packet.get_data()?)
-            .map_err(CommandError::from)?;
-        trace!(coin_position, path = ?path, "coin sorter path received");
-        Ok(path)
+        debug!(path = ?path, "sorter detected");
+        Ok(Some(CoinSorter::new(self.clone())))
     }
 
     /// Polls the coin validator for buffered credit and error events.
@@ -272,19 +341,32 @@ impl CoinValidator {
     ///
     /// For continuous polling, consider using [`try_background_polling`](Self::try_background_polling)
     /// which handles the polling loop automatically.
+    ///
+    /// An event counter returning to 0 is an unexpected reset, the handler registered with
+    /// [`with_reset_handler`](Self::with_reset_handler) runs before the result is returned.
     pub async fn poll(&self) -> DeviceResult<CoinAcceptorPollResult> {
+        self.poll_stamped().await.map(|result| result.value)
+    }
+
+    /// Polls like [`poll`](Self::poll), the result is stamped with the time the transport read
+    /// the event buffer.
+    pub async fn poll_stamped(&self) -> DeviceResult<Stamped<CoinAcceptorPollResult>> {
         trace!("polling coin validator");
-        let response_packet = self
-            .send_command(ReadBufferedCreditOrErrorCodeCommand::default())
-            .await?;
+        let Stamped {
+            value: buffer,
+            received_at,
+        } = self.read_event_buffer().await?;
+        let counter_cleared = buffer.first() == Some(&0);
+        let unexpected_reset = counter_cleared && self.event_counter() != 0;
         let result = ReadBufferedCreditOrErrorCodeCommand::new(self.event_counter())
-            .parse_response(response_packet.get_data()?)
+            .parse_response(&buffer)
             .map_err(CommandError::from)
             .inspect(|result| {
-                self.event_counter
-                    .lock()
-                    .expect("should not be poisoned")
-                    .clone_from(&result.event_counter);
+                *self.event_counter.lock().expect("should not be poisoned") = if counter_cleared {
+                    0
+                } else {
+                    result.event_counter
+                };
             })?;
         if !result.events.is_empty() {
             debug!(
@@ -293,7 +375,33 @@ impl CoinValidator {
                 "coin validator poll returned events"
             );
         }
-        Ok(result)
+        if result.events.contains(&CoinEvent::Reset) {
+            self.invalidate_coin_table();
+        }
+        self.track_throttled_acceptance(&result);
+        self.track_value_limit(&result).await;
+        if unexpected_reset {
+            self.reset_recovery
+                .recover(self.device.address(), self)
+                .await;
+        }
+        Ok(Stamped::new(result, received_at))
+    }
+
+    /// Reads the event buffer, decrypted if [`with_event_encryption`](Self::with_event_encryption)
+    /// was set.
+    async fn read_event_buffer(&self) -> DeviceResult<Stamped<Vec<u8>>> {
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.event_encryption {
+            return encryption.read_events(self).await;
+        }
+        let reply = self
+            .send_stamped_command(ReadBufferedCreditOrErrorCodeCommand::default())
+            .await?;
+        Ok(Stamped::new(
+            reply.value.get_data()?.to_vec(),
+            reply.received_at,
+        ))
     }
 
     /// Requests the coin ID (currency token) for a specific coin position.
@@ -358,6 +466,136 @@ impl CoinValidator {
         self.request_coin_id_range(16).await
     }
 
+    /// Returns the coin id table, reading it from the device on first use.
+    ///
+    /// The table is cached and shared between clones. It is refreshed after a bank switch done
+    /// through [`set_bank`](Self::set_bank) or when a poll reports a device reset.
+    pub async fn coin_table(&self) -> DeviceResult<CoinTable> {
+        let cached = self
+            .coin_table
+            .lock()
+            .expect("should not be poisoned")
+            .clone();
+        match cached {
+            Some(table) => Ok(table),
+            None => self.refresh_coin_table().await,
+        }
+    }
+
+    /// Reads every coin position in [`COIN_POSITIONS`] and replaces the cached coin table.
+    ///
+    /// Positions which cannot be read are left out of the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error if no position could be read.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn refresh_coin_table(&self) -> DeviceResult<CoinTable> {
+        debug!("refreshing coin table");
+        let mut coins = BTreeMap::new();
+        let mut last_error = None;
+        for position in COIN_POSITIONS {
+            match self.request_coin_id(position).await {
+                Ok(token) => {
+                    coins.insert(position, token);
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        if coins.is_empty()
+            && let Some(error) = last_error
+        {
+            warn!(error = %error, "unable to read any coin position");
+            return Err(error);
+        }
+
+        let table = CoinTable {
+            coins,
+            token_values: self
+                .token_values
+                .lock()
+                .expect("should not be poisoned")
+                .clone(),
+        };
+        info!(positions = table.len(), "coin table refreshed");
+        *self.coin_table.lock().expect("should not be poisoned") = Some(table.clone());
+        Ok(table)
+    }
+
+    /// Drops the cached coin table, the next call to [`coin_table`](Self::coin_table) reads it
+    /// again from the device.
+    pub fn invalidate_coin_table(&self) {
+        if self
+            .coin_table
+            .lock()
+            .expect("should not be poisoned")
+            .take()
+            .is_some()
+        {
+            debug!("coin table invalidated");
+        }
+    }
+
+    /// Programs a token window at `position`, `data` is manufacturer specific.
+    ///
+    /// Invalidates the cached coin table, the token shows up on its next read.
+    #[instrument(skip(self), fields(position, data), level = "debug")]
+    pub async fn program_token(&self, position: u8, data: u8) -> DeviceResult<()> {
+        debug!(position, "programming token");
+        let response_packet = self
+            .send_command(UploadWindowDataCommand::program_token(position, data))
+            .await?;
+        UploadWindowDataCommand::program_token(position, data)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        self.invalidate_coin_table();
+        info!(position, "token programmed");
+        Ok(())
+    }
+
+    /// Deletes the token window at `position` and invalidates the cached coin table.
+    #[instrument(skip(self), fields(position), level = "debug")]
+    pub async fn delete_token(&self, position: u8) -> DeviceResult<()> {
+        debug!(position, "deleting token");
+        let response_packet = self
+            .send_command(UploadWindowDataCommand::delete_token(position))
+            .await?;
+        UploadWindowDataCommand::delete_token(position)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        self.invalidate_coin_table();
+        info!(position, "token deleted");
+        Ok(())
+    }
+
+    /// Selects the coin bank and invalidates the cached coin table.
+    #[instrument(skip(self), fields(bank), level = "debug")]
+    pub async fn set_bank(&self, bank: u8) -> DeviceResult<()> {
+        debug!(bank, "selecting coin bank");
+        let response_packet = self
+            .send_command(ModifyBankSelectCommand::new(bank))
+            .await?;
+        ModifyBankSelectCommand::new(bank)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        self.invalidate_coin_table();
+        info!(bank, "coin bank selected");
+        Ok(())
+    }
+
+    /// Returns the currently selected coin bank.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_bank(&self) -> DeviceResult<u8> {
+        trace!("requesting coin bank");
+        let response_packet = self.send_command(RequestBankSelectCommand).await?;
+        let bank = RequestBankSelectCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(bank, "coin bank received");
+        Ok(bank)
+    }
+
     /// Sets the inhibit status for each of the 16 coin positions.
     /// True: coin is DISABLED
     /// False: coin is ENABLED
@@ -422,6 +660,230 @@ impl CoinValidator {
         Ok(inhibits)
     }
 
+    /// Sets the inhibit and sorter override registers for the current coin and the next coin
+    /// in one operation.
+    ///
+    /// The coin validator switches to the `next` registers after each accept sequence.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn set_inhibit_and_override_registers(
+        &self,
+        current: AcceptanceRegisters,
+        next: AcceptanceRegisters,
+    ) -> DeviceResult<()> {
+        debug!("setting inhibit and override registers");
+        let command = current.command(&next)?;
+        let response_packet = self.send_command(command).await?;
+        current
+            .command(&next)?
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!("inhibit and override registers set");
+        Ok(())
+    }
+
+    /// Switches to throttled acceptance, the coin validator accepts a single coin at a time.
+    ///
+    /// The `registers` are applied to the current coin while the next coin is always
+    /// inhibited, after each accept sequence the coin validator disables itself until the host
+    /// confirms the credit with [`confirm_credit`](Self::confirm_credit). This gives strict
+    /// one-coin-at-a-time behavior, e.g. for high value tokens.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn enable_throttled_acceptance(
+        &self,
+        registers: AcceptanceRegisters,
+    ) -> DeviceResult<()> {
+        self.set_inhibit_and_override_registers(registers, registers.next_coin_disabled())
+            .await?;
+        *self
+            .throttled_acceptance
+            .lock()
+            .expect("should not be poisoned") = Some(ThrottledAcceptance {
+            registers,
+            awaiting_confirmation: false,
+        });
+        info!("throttled acceptance enabled");
+        Ok(())
+    }
+
+    /// Leaves throttled acceptance, the registers given to
+    /// [`enable_throttled_acceptance`](Self::enable_throttled_acceptance) are kept for every coin.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn disable_throttled_acceptance(&self) -> DeviceResult<()> {
+        let throttled = self
+            .throttled_acceptance
+            .lock()
+            .expect("should not be poisoned")
+            .take();
+        if let Some(throttled) = throttled {
+            self.set_inhibit_and_override_registers(throttled.registers, throttled.registers)
+                .await?;
+            info!("throttled acceptance disabled");
+        }
+        Ok(())
+    }
+
+    /// Returns the throttled acceptance state, `None` when acceptance is not throttled.
+    pub fn throttled_acceptance(&self) -> Option<ThrottledAcceptance> {
+        *self
+            .throttled_acceptance
+            .lock()
+            .expect("should not be poisoned")
+    }
+
+    /// Confirms the last credit and re-arms throttled acceptance for the next coin.
+    ///
+    /// Does nothing when acceptance is not throttled.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn confirm_credit(&self) -> DeviceResult<()> {
+        let Some(throttled) = self.throttled_acceptance() else {
+            trace!("acceptance not throttled, nothing to confirm");
+            return Ok(());
+        };
+        self.set_inhibit_and_override_registers(
+            throttled.registers,
+            throttled.registers.next_coin_disabled(),
+        )
+        .await?;
+        if let Some(throttled) = self
+            .throttled_acceptance
+            .lock()
+            .expect("should not be poisoned")
+            .as_mut()
+        {
+            throttled.awaiting_confirmation = false;
+        }
+        debug!("credit confirmed, acceptance re-armed");
+        Ok(())
+    }
+
+    /// Marks throttled acceptance as waiting for confirmation once a credit shows up in the poll
+    /// result, rejected coins and errors leave the registers armed.
+    fn track_throttled_acceptance(&self, result: &CoinAcceptorPollResult) {
+        let mut throttled = self
+            .throttled_acceptance
+            .lock()
+            .expect("should not be poisoned");
+        let Some(throttled) = throttled.as_mut() else {
+            return;
+        };
+        if result.events.contains(&CoinEvent::Reset) {
+            warn!("coin validator reset, throttled acceptance registers were lost");
+        }
+        if result
+            .events
+            .iter()
+            .any(|event| matches!(event, CoinEvent::Credit(_)))
+            && !throttled.awaiting_confirmation
+        {
+            throttled.awaiting_confirmation = true;
+            debug!("accept sequence seen, waiting for credit confirmation");
+        }
+    }
+
+    /// Accepts coins up to `amount`, in smallest currency units, and resets the accepted value.
+    ///
+    /// Sends the cash value limit of `ModifyEncryptedInhibitAndOverrideRegisters` so that the
+    /// device rejects any coin going over the amount, whatever the polling latency. Devices
+    /// NAKing it fall back to host-side tracking: [`poll`](Self::poll) adds up the credits,
+    /// valued through the coin table, and enables the master inhibit once the amount is
+    /// reached. A coin inserted before that poll is still accepted.
+    ///
+    /// Every coin is enabled and the master inhibit disabled, an `amount` of 0 removes the limit.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn accept_up_to_value(&self, amount: u32) -> DeviceResult<Option<ValueLimit>> {
+        let inhibits = BitMask::<4>::new_filled(32).map_err(|_| CommandError::BufferOverflow)?;
+        let overrides = BitMask::<1>::new_filled(8).map_err(|_| CommandError::BufferOverflow)?;
+        let command = ModifyEncryptedInhibitAndOverrideRegistersCommand::build(
+            inhibits.clone(),
+            amount,
+            0,
+            overrides.clone(),
+        )
+        .map_err(|_| CommandError::BufferOverflow)?;
+        let enforcement = match self.send_command(command).await {
+            Ok(response_packet) => {
+                ModifyEncryptedInhibitAndOverrideRegistersCommand::build(
+                    inhibits, amount, 0, overrides,
+                )
+                .map_err(|_| CommandError::BufferOverflow)?
+                .parse_response(response_packet.get_data()?)
+                .map_err(CommandError::from)?;
+                LimitEnforcement::Device
+            }
+            Err(error) if error.is_nack() => {
+                debug!("cash value limit not supported, tracking the value on the host");
+                self.set_all_coin_inhibits(false).await?;
+                if amount > 0 {
+                    // Values are resolved through the coin table while polling.
+                    self.coin_table().await?;
+                }
+                LimitEnforcement::Host
+            }
+            Err(error) => return Err(error),
+        };
+        let limit = (amount > 0).then_some(ValueLimit {
+            amount,
+            accepted: 0,
+            enforcement,
+        });
+        *self.value_limit.lock().expect("should not be poisoned") = limit;
+        self.disable_master_inhibit().await?;
+        info!(amount, enforcement = ?enforcement, "accepting coins up to value");
+        Ok(limit)
+    }
+
+    /// Returns the limit set by [`accept_up_to_value`](Self::accept_up_to_value), `None` when
+    /// acceptance is not limited.
+    pub fn value_limit(&self) -> Option<ValueLimit> {
+        *self.value_limit.lock().expect("should not be poisoned")
+    }
+
+    /// Adds the credits to the value limit and inhibits the device once a host-side limit is
+    /// reached.
+    async fn track_value_limit(&self, result: &CoinAcceptorPollResult) {
+        let Some(limit) = self.value_limit() else {
+            return;
+        };
+        let coin_table = match limit.enforcement {
+            LimitEnforcement::Host => self.coin_table().await.ok(),
+            LimitEnforcement::Device => self
+                .coin_table
+                .lock()
+                .expect("should not be poisoned")
+                .clone(),
+        };
+        let credited: u32 = result
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                CoinEvent::Credit(credit) => coin_table.as_ref()?.value(credit.credit),
+                _ => None,
+            })
+            .sum();
+        if credited == 0 {
+            return;
+        }
+        let accepted = {
+            let mut value_limit = self.value_limit.lock().expect("should not be poisoned");
+            let Some(value_limit) = value_limit.as_mut() else {
+                return;
+            };
+            value_limit.accepted = value_limit.accepted.saturating_add(credited);
+            *value_limit
+        };
+        debug!(
+            accepted = accepted.accepted,
+            amount = accepted.amount,
+            "value accepted"
+        );
+        if accepted.enforcement == LimitEnforcement::Host && accepted.is_reached() {
+            info!(amount = accepted.amount, "value limit reached, inhibiting");
+            if let Err(error) = self.enable_master_inhibit().await {
+                error!(error = %error, "unable to inhibit after reaching the value limit");
+            }
+        }
+    }
+
     /// Returns the recommended polling priority (interval) for this device.
     ///
     /// The polling priority indicates how frequently the device should be polled
@@ -437,6 +899,99 @@ impl CoinValidator {
         Ok(priority)
     }
 
+    /// Returns the credit code format used by the coin validator in its credit events.
+    ///
+    /// See [`CoinCredit::credit_code`](cc_talk_core::cc_talk::CoinCredit::credit_code).
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_credit_code_format(&self) -> DeviceResult<CreditCodeFormat> {
+        trace!("requesting option flags");
+        let response_packet = self.send_command(RequestOptionFlagsCommand).await?;
+        let format = RequestOptionFlagsCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?
+            .for_coin_acceptor()
+            .credit_code_format();
+        debug!(format = ?format, "credit code format received");
+        Ok(format)
+    }
+
+    /// Limits the number of coins accepted before the coin validator inhibits itself.
+    ///
+    /// The limit is sent in the format set with
+    /// [`with_accept_limit_format`](Self::with_accept_limit_format), limits which do not fit
+    /// are rejected with [`CommandError::InvalidAcceptLimit`] without sending anything.
+    #[instrument(skip(self), fields(limit), level = "debug")]
+    pub async fn set_accept_limit(&self, limit: u16) -> DeviceResult<()> {
+        debug!(limit, format = ?self.accept_limit_format, "setting accept limit");
+        let command = SetAcceptLimitCommand::build(limit, self.accept_limit_format)
+            .map_err(CommandError::InvalidAcceptLimit)?;
+        let response_packet = self.send_command(command).await?;
+        SetAcceptLimitCommand::new(0)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        info!(limit, "accept limit set");
+        Ok(())
+    }
+
+    /// Starts teach mode for a coin position.
+    ///
+    /// The returned [`TeachSession`] is used to follow the progress of the teach and to abort it.
+    /// Dropping the session does not abort the teach on the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `coin_position` - The coin position to teach (1-16).
+    /// * `orientation` - Optional orientation, only supported by some validators.
+    #[instrument(skip(self), fields(coin_position, orientation), level = "debug")]
+    pub async fn start_teach(
+        &self,
+        coin_position: u8,
+        orientation: Option<u8>,
+    ) -> DeviceResult<TeachSession> {
+        debug!(coin_position, orientation, "starting teach mode");
+        let command = match orientation {
+            Some(orientation) => {
+                TeachModeControlCommand::new_with_orientation(coin_position, orientation)
+            }
+            None => TeachModeControlCommand::new(coin_position),
+        };
+        let response_packet = self.send_command(command).await?;
+        TeachModeControlCommand::new(coin_position)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        info!(coin_position, orientation, "teach mode started");
+        Ok(TeachSession {
+            validator: self.clone(),
+            coin_position,
+        })
+    }
+
+    /// Requests the teach mode status, optionally aborting the current teach.
+    ///
+    /// Returns the number of coins entered so far along with the teach status.
+    #[instrument(skip(self), fields(abort), level = "debug")]
+    pub async fn request_teach_status(&self, abort: bool) -> DeviceResult<TeachProgress> {
+        trace!(abort, "requesting teach status");
+        let response_packet = self
+            .send_command(RequestTeachModeStatusCommand::new(abort))
+            .await?;
+        let (coins_entered, status) = RequestTeachModeStatusCommand::new(abort)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(coins_entered, status = ?status, "teach status received");
+        Ok(TeachProgress {
+            coins_entered,
+            status,
+        })
+    }
+
+    /// Returns `true` while a long operation, e.g. a self-check, is running on the coin validator.
+    ///
+    /// Background polling skips its polls in the meantime.
+    pub fn is_busy(&self) -> bool {
+        self.long_operation.is_running()
+    }
+
     /// Starts background polling for coin events.
     ///
     /// This method spawns a background task that continuously polls the coin validator
@@ -478,7 +1033,8 @@ impl CoinValidator {
         &self,
         interval: Duration,
         channel_size: usize,
-    ) -> Result<DropGuard<PollResultReceiver, impl FnOnce(PollResultReceiver)>, PollingError> {
+    ) -> Result<DropGuard<PollResultReceiver, impl FnOnce(PollResultReceiver) + use<>>, PollingError>
+    {
         let mut is_polling = self.is_polling.lock().expect("should not be poisoned");
         if *is_polling {
             warn!("background polling already active");
@@ -499,12 +1055,16 @@ impl CoinValidator {
         let (stop_signal, mut stop_receiver) = oneshot::channel();
         let handle = tokio::spawn(async move {
             loop {
-                let poll_result = cv_clone.poll().await;
-                if tx.send(poll_result).await.is_err() {
-                    error!(
-                        "unable to send poll result, receiver may have been dropped. Stopping background polling."
-                    );
-                    break;
+                if cv_clone.is_busy() {
+                    trace!("long operation running, skipping poll");
+                } else {
+                    let poll_result = cv_clone.poll().await;
+                    if tx.send(poll_result).await.is_err() {
+                        error!(
+                            "unable to send poll result, receiver may have been dropped. Stopping background polling."
+                        );
+                        break;
+                    }
                 }
 
                 if stop_receiver.try_recv().is_ok() {
@@ -528,6 +1088,322 @@ impl CoinValidator {
 
         Ok(rx_with_guard)
     }
+
+    /// Starts background polling and returns the coin events one by one, see
+    /// [`CoinEventStream`].
+    ///
+    /// The arguments are the ones of [`try_background_polling`](Self::try_background_polling).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PollingError::AlreadyLeased`] if background polling is already active
+    /// on this instance or any of its clones.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut events = validator.event_stream(Duration::from_millis(100), 32)?;
+    ///
+    /// while let Some(event) = events.next().await {
+    ///     match event {
+    ///         Ok(CoinStreamEvent::Event(CoinEvent::Credit(credit))) => println!("{credit:?}"),
+    ///         Ok(CoinStreamEvent::Lost(count)) => eprintln!("{count} events lost"),
+    ///         Ok(_) => {}
+    ///         Err(e) => eprintln!("Poll error: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn event_stream(
+        &self,
+        interval: Duration,
+        channel_size: usize,
+    ) -> Result<CoinEventStream, PollingError> {
+        self.try_background_polling(interval, channel_size)
+            .map(CoinEventStream::new)
+    }
+}
+
+/// Inhibit and sorter override register values of a coin validator.
+///
+/// See [`CoinValidator::set_inhibit_and_override_registers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcceptanceRegisters {
+    /// Inhibit status of the 16 coin positions.
+    /// True: coin is DISABLED
+    /// False: coin is ENABLED
+    pub inhibits: [bool; 16],
+    /// Sorter override status of the 8 sorter paths.
+    /// True: sorter override to a different or default path.
+    /// False: no action
+    pub sorter_overrides: [bool; 8],
+}
+
+impl AcceptanceRegisters {
+    pub const fn new(inhibits: [bool; 16], sorter_overrides: [bool; 8]) -> Self {
+        Self {
+            inhibits,
+            sorter_overrides,
+        }
+    }
+
+    /// Returns the same registers with every coin position inhibited.
+    pub const fn next_coin_disabled(&self) -> Self {
+        Self {
+            inhibits: [true; 16],
+            sorter_overrides: self.sorter_overrides,
+        }
+    }
+
+    fn command(&self, next: &Self) -> DeviceResult<ModifyInhibitAndOverrideRegistersCommand> {
+        ModifyInhibitAndOverrideRegistersCommand::build(
+            inhibit_mask(&self.inhibits)?,
+            override_mask(&self.sorter_overrides)?,
+            inhibit_mask(&next.inhibits)?,
+            override_mask(&next.sorter_overrides)?,
+        )
+        .map_err(|_| CommandError::BufferOverflow)
+    }
+}
+
+impl Default for AcceptanceRegisters {
+    /// Every coin enabled, no sorter override.
+    fn default() -> Self {
+        Self::new([false; 16], [false; 8])
+    }
+}
+
+/// Builds the inhibit bit mask, where a set bit enables the coin.
+fn inhibit_mask(inhibits: &[bool; 16]) -> DeviceResult<BitMask<2>> {
+    let mut bitmask = BitMask::<2>::new(16).map_err(|_| CommandError::BufferOverflow)?;
+    for (i, disable) in inhibits.iter().enumerate() {
+        bitmask
+            .set_bit(i, !*disable)
+            .map_err(|_| CommandError::BufferOverflow)?;
+    }
+    Ok(bitmask)
+}
+
+/// Builds the sorter override bit mask, where a cleared bit overrides the path.
+fn override_mask(overrides: &[bool; 8]) -> DeviceResult<BitMask<1>> {
+    let mut bitmask = BitMask::<1>::new(8).map_err(|_| CommandError::BufferOverflow)?;
+    for (i, should_override) in overrides.iter().enumerate() {
+        bitmask
+            .set_bit(i, !*should_override)
+            .map_err(|_| CommandError::BufferOverflow)?;
+    }
+    Ok(bitmask)
+}
+
+/// State of throttled acceptance, see [`CoinValidator::enable_throttled_acceptance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottledAcceptance {
+    /// Registers applied to each coin once acceptance is re-armed.
+    pub registers: AcceptanceRegisters,
+    awaiting_confirmation: bool,
+}
+
+impl ThrottledAcceptance {
+    /// Returns `true` once a coin went through an accept sequence, the coin validator stays
+    /// inhibited until [`CoinValidator::confirm_credit`] is called.
+    pub const fn is_awaiting_confirmation(&self) -> bool {
+        self.awaiting_confirmation
+    }
+}
+
+/// Who rejects coins going over a [`ValueLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitEnforcement {
+    /// The coin acceptor, through the cash value of `ModifyEncryptedInhibitAndOverrideRegisters`.
+    Device,
+    /// The host, by enabling the master inhibit once the limit is reached.
+    Host,
+}
+
+/// Value limit set by [`CoinValidator::accept_up_to_value`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueLimit {
+    /// Maximum value to accept, in smallest currency units.
+    pub amount: u32,
+    /// Value credited since the limit was set, as seen by the host.
+    pub accepted: u32,
+    pub enforcement: LimitEnforcement,
+}
+
+impl ValueLimit {
+    /// Value which can still be accepted.
+    pub const fn remaining(&self) -> u32 {
+        self.amount.saturating_sub(self.accepted)
+    }
+
+    pub const fn is_reached(&self) -> bool {
+        self.accepted >= self.amount
+    }
+}
+
+/// Coin ids of a coin validator indexed by coin position, see [`CoinValidator::coin_table`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoinTable {
+    coins: BTreeMap<u8, CurrencyToken>,
+    token_values: BTreeMap<u8, u32>,
+}
+
+impl CoinTable {
+    /// Returns the coin id at the given position.
+    pub fn get(&self, position: u8) -> Option<&CurrencyToken> {
+        self.coins.get(&position)
+    }
+
+    /// Returns the value of the coin at the given position in smallest currency units.
+    ///
+    /// Tokens are valued with [`CoinValidator::set_token_value`], unknown positions and tokens
+    /// without a configured value have no value.
+    pub fn value(&self, position: u8) -> Option<u32> {
+        match self.get(position)? {
+            CurrencyToken::Token => self.token_values.get(&position).copied(),
+            CurrencyToken::Currency(value) => Some(value.smallest_unit_value()),
+        }
+    }
+
+    /// Returns `true` if the window at `position` holds a token.
+    pub fn is_token(&self, position: u8) -> bool {
+        matches!(self.get(position), Some(CurrencyToken::Token))
+    }
+
+    /// Iterates over the positions holding a token.
+    pub fn token_positions(&self) -> impl Iterator<Item = u8> + '_ {
+        self.iter()
+            .filter(|(_, token)| matches!(token, CurrencyToken::Token))
+            .map(|(position, _)| position)
+    }
+
+    /// Iterates over the positions holding a coin.
+    pub fn coin_positions(&self) -> impl Iterator<Item = u8> + '_ {
+        self.iter()
+            .filter(|(_, token)| matches!(token, CurrencyToken::Currency(_)))
+            .map(|(position, _)| position)
+    }
+
+    /// Returns the values of the credit codes reported by a device in coin value format, keyed
+    /// by credit code.
+    ///
+    /// Each coin of the table is mapped to the codes encoding its value. When a coin value can't
+    /// be encoded, e.g. above 1260, the device reports values in a larger unit: the smallest
+    /// power of ten encoding every coin of the table is used, so the returned values are always
+    /// in smallest currency units. The token code (255) is valued when all the valued token
+    /// positions share the same value, otherwise the token credits can't be told apart.
+    pub fn cvf_values(&self) -> BTreeMap<u8, u32> {
+        let coin_values: Vec<u32> = self
+            .coin_positions()
+            .filter_map(|position| self.value(position))
+            .collect();
+        let scale = [1, 10, 100, 1000]
+            .into_iter()
+            .find(|&scale| {
+                coin_values
+                    .iter()
+                    .all(|&value| value % scale == 0 && cvf_codes(value / scale).next().is_some())
+            })
+            .unwrap_or(1);
+
+        let mut values = BTreeMap::new();
+        for &value in &coin_values {
+            if value % scale == 0 {
+                values.extend(cvf_codes(value / scale).map(|code| (code, value)));
+            }
+        }
+
+        let mut token_values = self
+            .token_positions()
+            .filter_map(|position| self.value(position));
+        if let Some(token_value) = token_values.next()
+            && token_values.all(|value| value == token_value)
+        {
+            values.insert(CVF_TOKEN_CODE, token_value);
+        }
+        values
+    }
+
+    /// Iterates over the known positions in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &CurrencyToken)> {
+        self.coins
+            .iter()
+            .map(|(position, token)| (*position, token))
+    }
+
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coins.is_empty()
+    }
+}
+
+/// Credit code reported for a token in coin value format.
+const CVF_TOKEN_CODE: u8 = 255;
+
+/// Iterates over the coin value format codes decoding to `value`.
+fn cvf_codes(value: u32) -> impl Iterator<Item = u8> {
+    (1..CVF_TOKEN_CODE)
+        .filter(move |&code| matches!(CoinType::from(code), CoinType::Coin(coin) if u32::from(coin) == value))
+}
+
+impl FromIterator<(u8, CurrencyToken)> for CoinTable {
+    fn from_iter<T: IntoIterator<Item = (u8, CurrencyToken)>>(iter: T) -> Self {
+        CoinTable {
+            coins: iter.into_iter().collect(),
+            token_values: BTreeMap::new(),
+        }
+    }
+}
+
+/// Progress of a teach mode session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TeachProgress {
+    /// Number of coins entered since teach mode was started.
+    pub coins_entered: u8,
+    pub status: TeachModeStatus,
+}
+
+impl TeachProgress {
+    /// Returns `true` once the teach completed, failed or was aborted.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            TeachModeStatus::Completed | TeachModeStatus::Aborted | TeachModeStatus::Error
+        )
+    }
+}
+
+/// A teach mode session started with [`CoinValidator::start_teach`].
+#[derive(Debug, Clone)]
+pub struct TeachSession {
+    validator: CoinValidator,
+    coin_position: u8,
+}
+
+impl TeachSession {
+    /// Returns the coin position being taught.
+    pub fn coin_position(&self) -> u8 {
+        self.coin_position
+    }
+
+    /// Returns the current progress of the teach.
+    pub async fn progress(&self) -> DeviceResult<TeachProgress> {
+        self.validator.request_teach_status(false).await
+    }
+
+    /// Aborts the teach and returns the final progress reported by the device.
+    pub async fn abort(self) -> DeviceResult<TeachProgress> {
+        warn!(coin_position = self.coin_position, "aborting teach mode");
+        self.validator.request_teach_status(true).await
+    }
+}
+
+impl CoinAcceptor for CoinValidator {
+    fn coin_validator(&self) -> &CoinValidator {
+        self
+    }
 }
 
 impl DeviceCommon for CoinValidator {
@@ -538,12 +1414,28 @@ impl DeviceCommon for CoinValidator {
     fn get_sender(&self) -> &mpsc::Sender<TransportMessage> {
         &self.sender
     }
+
+    fn get_express_sender(&self) -> Option<&mpsc::Sender<TransportMessage>> {
+        self.express_sender.as_ref()
+    }
+
+    fn get_quirks(&self) -> Option<&Arc<dyn DeviceQuirks>> {
+        self.quirks.as_ref()
+    }
+
+    fn timeout_triage(&self) -> Option<&TriageConfig> {
+        self.timeout_triage.as_ref()
+    }
+
+    fn get_long_operation(&self) -> Option<&LongOperation> {
+        Some(&self.long_operation)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cc_talk_core::cc_talk::{Category, ChecksumType};
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Header};
 
     fn create_test_validator() -> CoinValidator {
         let (tx, _rx) = mpsc::channel(1);
@@ -551,6 +1443,276 @@ mod tests {
         CoinValidator::new(device, tx)
     }
 
+    #[tokio::test]
+    async fn long_operation_marks_validator_busy() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+        let validator = CoinValidator::new(device, tx);
+        let clone = validator.clone();
+
+        let self_check =
+            tokio::spawn(async move { clone.send_command(PerformSelfCheckCommand).await });
+        let message = rx.recv().await.expect("should receive the self-check");
+        assert_eq!(message.header, Header::PerformSelfCheck);
+        assert!(validator.is_busy());
+
+        message
+            .respond_to
+            .send(Ok(vec![1, 0, 2, 0, 253]))
+            .expect("should respond");
+        self_check
+            .await
+            .expect("should join")
+            .expect("should succeed");
+        assert!(!validator.is_busy());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn encrypted_events_are_polled_like_buffered_credits() {
+        use cc_talk_core::cc_talk::{
+            ChallengeRng, DesPeripheralCipher, EncryptedEvents, HopperDesKey,
+        };
+
+        struct Counter(u8);
+        impl ChallengeRng for Counter {
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                for byte in dest {
+                    self.0 += 1;
+                    *byte = self.0;
+                }
+            }
+        }
+
+        let cipher = DesPeripheralCipher::new(HopperDesKey::new([8, 7, 6, 5, 4, 3, 2, 1]));
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(1);
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+        let validator = CoinValidator::new(device, tx)
+            .with_event_encryption(EventEncryption::new(cipher, Counter(0)));
+
+        tokio::spawn(async move {
+            let mut event_counter = 0;
+            while let Some(message) = rx.recv().await {
+                assert_eq!(message.header, Header::ReadEncryptedEvents);
+                event_counter += 1;
+                let events = EncryptedEvents {
+                    event_counter,
+                    results: [[event_counter, 0], [1, 0], [0, 0], [0, 0], [0, 0]],
+                };
+                let mut reply = vec![1, 16, 2, 0];
+                reply.extend(cipher.encrypt_events(&events, message.data[0], [0xAA, 0x55]));
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+        });
+
+        let result = validator.poll().await.expect("should poll");
+        assert_eq!(result.events[..], [CoinEvent::new(1, 0)]);
+        let result = validator.poll().await.expect("should poll");
+        assert_eq!(result.events[..], [CoinEvent::new(2, 0)]);
+        assert_eq!(validator.event_counter(), 2);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn encrypted_monetary_ids_are_requested_per_position() {
+        use cc_talk_core::cc_talk::{
+            ChallengeRng, DesPeripheralCipher, HopperDesKey, MonetaryCode, MonetaryId,
+        };
+
+        struct Fixed;
+        impl ChallengeRng for Fixed {
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                dest.fill(0x3C);
+            }
+        }
+
+        let cipher = DesPeripheralCipher::new(HopperDesKey::new([8, 7, 6, 5, 4, 3, 2, 1]));
+        let id = MonetaryId {
+            position: 3,
+            code: MonetaryCode::Country(*b"EU"),
+            scaling_factor: 0,
+            decimal_places: 2,
+            value: 50,
+            issue_level: b'A',
+            issue_number: 1,
+        };
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(1);
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+        let validator = CoinValidator::new(device, tx);
+        let encryption = EventEncryption::new(cipher, Fixed);
+
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                assert_eq!(message.header, Header::RequestEncryptedMonetaryId);
+                assert_eq!(message.data, [3, 0x3C]);
+                let mut reply = vec![1, 16, 2, 0];
+                reply.extend(cipher.encrypt_monetary_id(&id, message.data[1], 0x77));
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+        });
+
+        let received = encryption
+            .request_monetary_id(&validator, 3)
+            .await
+            .expect("should decrypt");
+        assert_eq!(received, Some(id));
+        assert!(encryption.request_monetary_id(&validator, 4).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn throttled_acceptance_rearms_after_confirmation() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+        let validator = CoinValidator::new(device, tx);
+
+        let responder = tokio::spawn(async move {
+            let mut registers = Vec::new();
+            while let Some(message) = rx.recv().await {
+                let reply = match message.header {
+                    Header::ReadBufferedCreditOrErrorCodes => {
+                        vec![1, 11, 2, 0, 1, 3, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+                    }
+                    header => {
+                        assert_eq!(header, Header::ModifyInhibitAndOverrideRegisters);
+                        registers.push(message.data);
+                        vec![1, 0, 2, 0, 253]
+                    }
+                };
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+            registers
+        });
+
+        let mut inhibits = [true; 16];
+        inhibits[2] = false;
+        let registers = AcceptanceRegisters::new(inhibits, [false; 8]);
+        validator
+            .enable_throttled_acceptance(registers)
+            .await
+            .expect("should enable");
+        let throttled = validator
+            .throttled_acceptance()
+            .expect("should be throttled");
+        assert_eq!(throttled.registers, registers);
+        assert!(!throttled.is_awaiting_confirmation());
+
+        let poll = validator.poll().await.expect("should poll");
+        assert_eq!(poll.events.len(), 1);
+        assert!(
+            validator
+                .throttled_acceptance()
+                .is_some_and(|t| t.is_awaiting_confirmation())
+        );
+
+        validator.confirm_credit().await.expect("should confirm");
+        assert!(
+            validator
+                .throttled_acceptance()
+                .is_some_and(|t| !t.is_awaiting_confirmation())
+        );
+
+        validator
+            .disable_throttled_acceptance()
+            .await
+            .expect("should disable");
+        assert!(validator.throttled_acceptance().is_none());
+        // Confirming without throttling does not talk to the device.
+        validator.confirm_credit().await.expect("should confirm");
+
+        drop(validator);
+        let sent = responder.await.expect("should join");
+        let armed = vec![0x04, 0x00, 0xFF, 0x00, 0x00, 0xFF];
+        assert_eq!(
+            sent,
+            vec![
+                armed.clone(),
+                armed,
+                vec![0x04, 0x00, 0xFF, 0x04, 0x00, 0xFF],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn throttled_acceptance_stays_armed_after_a_reject() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+        let validator = CoinValidator::new(device, tx);
+
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let reply = match message.header {
+                    // Result A of 0 is an error, 1 is a rejected coin.
+                    Header::ReadBufferedCreditOrErrorCodes => {
+                        vec![1, 11, 2, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+                    }
+                    _ => vec![1, 0, 2, 0, 253],
+                };
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+        });
+
+        let registers = AcceptanceRegisters::new([true; 16], [false; 8]);
+        validator
+            .enable_throttled_acceptance(registers)
+            .await
+            .expect("should enable");
+
+        let poll = validator.poll().await.expect("should poll");
+        assert!(matches!(poll.events[..], [CoinEvent::Error(_)]));
+        assert!(
+            validator
+                .throttled_acceptance()
+                .is_some_and(|t| !t.is_awaiting_confirmation())
+        );
+    }
+
+    #[tokio::test]
+    async fn value_limit_is_enforced_by_devices_supporting_header_223() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8);
+        let validator = CoinValidator::new(device, tx);
+
+        let responder = tokio::spawn(async move {
+            let mut headers = Vec::new();
+            while let Some(message) = rx.recv().await {
+                if message.header == Header::ModifyEncryptedInhibitAndOverrideRegisters {
+                    assert_eq!(&message.data[4..8], &500u32.to_le_bytes());
+                }
+                headers.push(message.header);
+                message
+                    .respond_to
+                    .send(Ok(vec![1, 0, 2, 0, 253]))
+                    .expect("should respond");
+            }
+            headers
+        });
+
+        let limit = validator
+            .accept_up_to_value(500)
+            .await
+            .expect("should limit the value");
+        assert_eq!(
+            limit,
+            Some(ValueLimit {
+                amount: 500,
+                accepted: 0,
+                enforcement: LimitEnforcement::Device,
+            })
+        );
+        assert_eq!(validator.value_limit(), limit);
+
+        drop(validator);
+        assert_eq!(
+            responder.await.expect("should join"),
+            vec![
+                Header::ModifyEncryptedInhibitAndOverrideRegisters,
+                Header::ModifyMasterInhibitStatus,
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn try_background_polling_returns_already_leased_when_called_twice() {
         let validator = create_test_validator();
@@ -600,4 +1762,104 @@ mod tests {
             .expect("clone should be able to start polling after original's guard dropped");
         drop(new_guard);
     }
+
+    #[test]
+    fn coin_table_lookup() {
+        let euro = CurrencyToken::build("EU050A").expect("should parse");
+        let table: CoinTable = [(3, euro.clone()), (1, CurrencyToken::Token)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(3), Some(&euro));
+        assert_eq!(table.value(3), Some(50));
+        assert_eq!(table.value(1), None);
+        assert_eq!(table.value(2), None);
+        assert!(table.is_token(1));
+        assert_eq!(table.token_positions().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(table.coin_positions().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(
+            table
+                .iter()
+                .map(|(position, _)| position)
+                .collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+    }
+
+    #[test]
+    fn cvf_values_follow_the_coin_table() {
+        let coin = |id| CurrencyToken::build(id).expect("should parse");
+        let mut table: CoinTable = [
+            (1, coin("EU010A")),
+            (2, coin("EU200A")),
+            (3, CurrencyToken::Token),
+            (4, CurrencyToken::Token),
+        ]
+        .into_iter()
+        .collect();
+        table.token_values.insert(3, 150);
+
+        let values = table.cvf_values();
+        assert_eq!(values.get(&10), Some(&10));
+        assert_eq!(values.get(&129), Some(&10));
+        assert_eq!(values.get(&148), Some(&200));
+        assert_eq!(values.get(&255), Some(&150));
+        assert_eq!(values.get(&5), None);
+
+        table.token_values.insert(4, 100);
+        assert_eq!(table.cvf_values().get(&255), None);
+
+        let table: CoinTable = [(1, coin("US500A")), (2, coin("US002K"))]
+            .into_iter()
+            .collect();
+        let values = table.cvf_values();
+        assert_eq!(values.get(&50), Some(&500));
+        assert_eq!(values.get(&148), Some(&2000));
+        assert_eq!(values.get(&178), None);
+    }
+
+    #[tokio::test]
+    async fn coin_table_is_shared_and_invalidated() {
+        let validator = create_test_validator();
+        let cloned = validator.clone();
+
+        *validator.coin_table.lock().unwrap() = Some(CoinTable::default());
+        assert_eq!(cloned.coin_table().await, Ok(CoinTable::default()));
+
+        cloned.invalidate_coin_table();
+        assert!(validator.coin_table.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn token_values_apply_to_the_cached_coin_table() {
+        let validator = create_test_validator().with_token_values([(1, 250)]);
+        let table: CoinTable = [(1, CurrencyToken::Token), (2, CurrencyToken::Token)]
+            .into_iter()
+            .collect();
+        *validator.coin_table.lock().unwrap() = Some(table);
+
+        validator.set_token_value(2, Some(100));
+        let table = validator.coin_table().await.expect("should be cached");
+        assert_eq!(table.value(1), Some(250));
+        assert_eq!(table.value(2), Some(100));
+
+        validator.set_token_value(1, None);
+        let table = validator.coin_table().await.expect("should be cached");
+        assert_eq!(table.value(1), None);
+    }
+
+    #[test]
+    fn teach_progress_is_finished_on_final_status() {
+        let progress = |status| TeachProgress {
+            coins_entered: 3,
+            status,
+        };
+
+        assert!(!progress(TeachModeStatus::InProgress).is_finished());
+        assert!(!progress(TeachModeStatus::Unknown).is_finished());
+        assert!(progress(TeachModeStatus::Completed).is_finished());
+        assert!(progress(TeachModeStatus::Aborted).is_finished());
+        assert!(progress(TeachModeStatus::Error).is_finished());
+    }
 }
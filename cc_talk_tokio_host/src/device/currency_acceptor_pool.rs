@@ -8,6 +8,7 @@
 //! - Denomination filtering by value range
 //! - Bill escrow handling with configurable routing modes
 //! - Value-based payment acceptance
+//! - Time-limited acceptance sessions, see [`CurrencyAcceptorPool::start_session`]
 //!
 //! # Example
 //!
@@ -30,15 +31,19 @@
 
 mod builder;
 mod config;
+mod credit_event;
 mod device_id;
 mod poll_result;
 mod pool;
+mod session;
 
 pub use builder::CurrencyAcceptorPoolBuilder;
-pub use config::{BillRoutingMode, DenominationRange, DeviceValueMap};
+pub use config::{BillRoutingMode, DenominationRange, DeviceValueMap, DriftCheck};
+pub use credit_event::{CreditEvent, CreditRoute, RawCreditCode};
 pub use device_id::DeviceId;
-pub use poll_result::{CurrencyCredit, PendingBill, PoolPollError, PoolPollResult};
+pub use poll_result::{CurrencyCredit, PendingBill, PoolPollError, PoolPollResult, StateDrift};
 pub use pool::{CurrencyAcceptorPool, PaymentProgress, PaymentResult};
+pub use session::{AcceptanceSession, SessionConfig, SessionEnd, SessionReport};
 
 use crate::device::base::{CommandError, PollingError};
 use thiserror::Error;
@@ -58,6 +63,10 @@ pub enum PoolError {
     #[error("pool has no devices")]
     NoDevices,
 
+    /// No device of the pool uses the given address.
+    #[error("device not found: address {0}")]
+    DeviceNotFound(u8),
+
     /// Failed to read currency ID from a device position.
     #[error("failed to read currency ID at position {position} from {device}")]
     CurrencyIdReadFailed { device: String, position: u8 },
@@ -1,10 +1,15 @@
 use std::time::Duration;
 
-use crate::device::{bill_validator::BillValidator, coin_validator::CoinValidator};
+use crate::{
+    device::{
+        bill_validator::BillValidator, coin_validator::CoinValidator, service::ServiceRegistry,
+    },
+    transport::machine_state::MachineStateHandle,
+};
 
 use super::{
     PoolResult,
-    config::{BillRoutingMode, DenominationRange},
+    config::{BillRoutingMode, DenominationRange, DriftCheck},
     pool::CurrencyAcceptorPool,
 };
 
@@ -29,6 +34,10 @@ pub struct CurrencyAcceptorPoolBuilder {
     denomination_range: DenominationRange,
     bill_routing_mode: BillRoutingMode,
     polling_interval: Duration,
+    service_registry: ServiceRegistry,
+    max_polling_pause: Duration,
+    drift_check: Option<DriftCheck>,
+    machine_state: Option<MachineStateHandle>,
 }
 
 impl CurrencyAcceptorPoolBuilder {
@@ -39,6 +48,8 @@ impl CurrencyAcceptorPoolBuilder {
     /// - Accept all denominations (0 to u32::MAX)
     /// - Auto-stack bills
     /// - 100ms polling interval
+    /// - Polling pauses lifted after 5 minutes
+    /// - No master inhibit drift check
     #[must_use]
     pub fn new() -> Self {
         Self {
@@ -47,6 +58,10 @@ impl CurrencyAcceptorPoolBuilder {
             denomination_range: DenominationRange::default(),
             bill_routing_mode: BillRoutingMode::default(),
             polling_interval: Duration::from_millis(100),
+            service_registry: ServiceRegistry::default(),
+            max_polling_pause: Duration::from_secs(300),
+            drift_check: None,
+            machine_state: None,
         }
     }
 
@@ -137,6 +152,44 @@ impl CurrencyAcceptorPoolBuilder {
         self
     }
 
+    /// Sets the registry used to track devices taken out of service.
+    ///
+    /// Share one registry between pools to get a single view of the machine.
+    #[must_use]
+    pub fn with_service_registry(mut self, registry: ServiceRegistry) -> Self {
+        self.service_registry = registry;
+        self
+    }
+
+    /// Sets how long a polling pause lasts at most before polling resumes automatically.
+    ///
+    /// See [`CurrencyAcceptorPool::pause_polling`].
+    #[must_use]
+    pub fn with_max_polling_pause(mut self, max_pause: Duration) -> Self {
+        self.max_polling_pause = max_pause;
+        self
+    }
+
+    /// Periodically compares the master inhibit status of the devices with the intended state.
+    ///
+    /// See [`CurrencyAcceptorPool::check_master_inhibits`].
+    #[must_use]
+    pub fn with_drift_check(mut self, check: DriftCheck) -> Self {
+        self.drift_check = Some(check);
+        self
+    }
+
+    /// Shares the machine state with the pool, devices are expected to be inhibited while the
+    /// state does not allow acceptance.
+    ///
+    /// Without it, the drift check re-enables the devices inhibited by a
+    /// [`DeviceManager::lockdown`](crate::device::manager::DeviceManager::lockdown).
+    #[must_use]
+    pub fn with_machine_state(mut self, machine_state: MachineStateHandle) -> Self {
+        self.machine_state = Some(machine_state);
+        self
+    }
+
     /// Builds the pool without initializing it.
     ///
     /// You must call [`CurrencyAcceptorPool::initialize`] before using the pool.
@@ -148,7 +201,11 @@ impl CurrencyAcceptorPoolBuilder {
             self.denomination_range,
             self.bill_routing_mode,
             self.polling_interval,
+            self.service_registry,
+            self.max_polling_pause,
         )
+        .with_drift_check(self.drift_check)
+        .with_machine_state(self.machine_state)
     }
 
     /// Builds and initializes the pool.
@@ -193,6 +250,7 @@ mod tests {
         assert_eq!(pool.denomination_range(), DenominationRange::default());
         assert_eq!(pool.bill_routing_mode(), BillRoutingMode::AutoStack);
         assert_eq!(pool.polling_interval(), Duration::from_millis(100));
+        assert_eq!(pool.max_polling_pause(), Duration::from_secs(300));
     }
 
     #[test]
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 /// Filter for accepted denominations by value range.
 ///
@@ -53,6 +53,26 @@ pub enum BillRoutingMode {
     Manual,
 }
 
+/// Periodic comparison of the master inhibit status of the devices with the state intended by
+/// the host, see [`CurrencyAcceptorPool::check_master_inhibits`](super::CurrencyAcceptorPool::check_master_inhibits).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriftCheck {
+    /// Minimum time between two checks, checks run as part of a poll.
+    pub interval: Duration,
+    /// Sets drifted devices back to the intended state.
+    pub auto_correct: bool,
+}
+
+impl Default for DriftCheck {
+    /// Checks every 10 seconds and corrects drifted devices.
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            auto_correct: true,
+        }
+    }
+}
+
 /// Maps position indices (0-15) to currency values for a single device.
 ///
 /// This is populated during initialization by reading coin/bill IDs from devices.
@@ -0,0 +1,205 @@
+use cc_talk_core::cc_talk::{BillEvent, CoinCredit, CoinEvent, SorterPath};
+
+use super::{device_id::DeviceId, poll_result::CurrencyCredit};
+use crate::transport::timestamp::Timestamp;
+
+/// Where accepted currency went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CreditRoute {
+    /// Coin sent down a sorter path.
+    Sorter(SorterPath),
+    /// Bill sent to the stacker or cashbox.
+    Stacked,
+    /// Bill held in escrow, it is only credited once stacked.
+    Escrow,
+}
+
+/// Result A and result B bytes of the buffered event, as reported by the device.
+///
+/// Result A is the coin credit code or the bill type, result B the sorter path or the bill
+/// routing, `0` for stacked and `1` for escrow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawCreditCode {
+    pub result_a: u8,
+    pub result_b: u8,
+}
+
+/// Credit reported by a coin or a bill acceptor.
+///
+/// Coins and bills are accounted for the same way, the media type is only visible through the
+/// [`source`](Self::source) and the [`route`](Self::route).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreditEvent {
+    pub source: DeviceId,
+    /// Value in smallest units (e.g., cents).
+    pub value: u32,
+    pub route: CreditRoute,
+    pub raw: RawCreditCode,
+    /// When the transport read the event buffer reporting the credit, `None` if the event was
+    /// not read from a device.
+    pub received_at: Option<Timestamp>,
+}
+
+impl CreditEvent {
+    /// Creates the event of a coin credit worth `value`.
+    #[must_use]
+    pub const fn coin(source: DeviceId, value: u32, credit: CoinCredit) -> Self {
+        let sorter_path = match credit.sorter_path {
+            SorterPath::NotSupported => 0,
+            SorterPath::Path(path) => path,
+        };
+        Self {
+            source,
+            value,
+            route: CreditRoute::Sorter(credit.sorter_path),
+            raw: RawCreditCode {
+                result_a: credit.credit,
+                result_b: sorter_path,
+            },
+            received_at: None,
+        }
+    }
+
+    /// Creates the event of a stacked bill worth `value`.
+    #[must_use]
+    pub const fn bill(source: DeviceId, value: u32, bill_type: u8) -> Self {
+        Self {
+            source,
+            value,
+            route: CreditRoute::Stacked,
+            raw: RawCreditCode {
+                result_a: bill_type,
+                result_b: 0,
+            },
+            received_at: None,
+        }
+    }
+
+    /// Sets the reception time of the event buffer reporting the credit.
+    #[must_use]
+    pub const fn with_received_at(mut self, received_at: Timestamp) -> Self {
+        self.received_at = Some(received_at);
+        self
+    }
+
+    /// Converts a coin acceptor event, `value` returns the value of the credit.
+    ///
+    /// Returns `None` for errors and resets, and for credits `value` cannot value.
+    pub fn from_coin_event(
+        source: DeviceId,
+        event: &CoinEvent,
+        value: impl FnOnce(&CoinCredit) -> Option<u32>,
+    ) -> Option<Self> {
+        match event {
+            CoinEvent::Credit(credit) => {
+                value(credit).map(|value| Self::coin(source, value, *credit))
+            }
+            CoinEvent::Error(_) | CoinEvent::Reset => None,
+        }
+    }
+
+    /// Converts a bill validator event, `value` returns the value of the bill type.
+    ///
+    /// Bills held in escrow are converted with [`CreditRoute::Escrow`]. Returns `None` for
+    /// rejects, faults and status events, and for bill types `value` cannot value.
+    pub fn from_bill_event(
+        source: DeviceId,
+        event: &BillEvent,
+        value: impl FnOnce(u8) -> Option<u32>,
+    ) -> Option<Self> {
+        match event {
+            BillEvent::Credit(bill_type) => {
+                value(*bill_type).map(|value| Self::bill(source, value, *bill_type))
+            }
+            BillEvent::PendingCredit(bill_type) => value(*bill_type).map(|value| Self {
+                route: CreditRoute::Escrow,
+                raw: RawCreditCode {
+                    result_a: *bill_type,
+                    result_b: 1,
+                },
+                ..Self::bill(source, value, *bill_type)
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` once the currency is credited, i.e. it is not held in escrow.
+    #[must_use]
+    pub const fn is_credited(&self) -> bool {
+        !matches!(self.route, CreditRoute::Escrow)
+    }
+}
+
+impl From<CreditEvent> for CurrencyCredit {
+    fn from(event: CreditEvent) -> Self {
+        Self::new(event.value, event.source, event.raw.result_a)
+    }
+}
+
+impl std::fmt::Display for CreditEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} from {} ({:?}, code {}/{})",
+            self.value, self.source, self.route, self.raw.result_a, self.raw.result_b
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::BillEventReason;
+
+    use super::*;
+
+    #[test]
+    fn coin_and_bill_credits_share_the_model() {
+        let coin = CoinEvent::new(5, 2);
+        let coin = CreditEvent::from_coin_event(DeviceId::CoinValidator(0), &coin, |credit| {
+            Some(u32::from(credit.credit) * 10)
+        })
+        .unwrap();
+        assert_eq!(coin.value, 50);
+        assert_eq!(coin.route, CreditRoute::Sorter(SorterPath::Path(2)));
+        assert_eq!(
+            coin.raw,
+            RawCreditCode {
+                result_a: 5,
+                result_b: 2
+            }
+        );
+
+        let bill = BillEvent::from_result(3, 0).unwrap();
+        let bill = CreditEvent::from_bill_event(DeviceId::BillValidator(1), &bill, |_| Some(2000))
+            .unwrap();
+        assert_eq!(bill.route, CreditRoute::Stacked);
+        assert!(bill.is_credited());
+        assert_eq!(
+            CurrencyCredit::from(bill),
+            CurrencyCredit::new(2000, DeviceId::BillValidator(1), 3)
+        );
+    }
+
+    #[test]
+    fn escrow_bills_are_not_credited() {
+        let pending = BillEvent::PendingCredit(2);
+        let event =
+            CreditEvent::from_bill_event(DeviceId::BillValidator(0), &pending, |_| Some(1000))
+                .unwrap();
+        assert_eq!(event.route, CreditRoute::Escrow);
+        assert_eq!(event.raw.result_b, 1);
+        assert!(!event.is_credited());
+
+        let reject = BillEvent::Reject(BillEventReason::InhibitedBillViaSerial);
+        assert_eq!(
+            CreditEvent::from_bill_event(DeviceId::BillValidator(0), &reject, |_| Some(1000)),
+            None
+        );
+        assert_eq!(
+            CreditEvent::from_coin_event(DeviceId::CoinValidator(0), &CoinEvent::Reset, |_| {
+                Some(1)
+            }),
+            None
+        );
+    }
+}
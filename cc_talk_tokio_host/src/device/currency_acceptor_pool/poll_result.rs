@@ -1,4 +1,4 @@
-use super::device_id::DeviceId;
+use super::{credit_event::CreditEvent, device_id::DeviceId};
 use crate::device::base::CommandError;
 
 /// A confirmed currency credit from a coin or bill acceptor.
@@ -59,6 +59,53 @@ impl PendingBill {
     }
 }
 
+/// A device whose master inhibit status differs from the state intended by the host.
+///
+/// Devices drift when they inhibit themselves, reset or receive commands from another host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateDrift {
+    pub source: DeviceId,
+    pub address: u8,
+    /// Master inhibit intended by the host, `true` if the device should reject currency.
+    pub expected_inhibit: bool,
+    /// `true` if the device was set back to the intended state.
+    pub corrected: bool,
+}
+
+impl StateDrift {
+    #[must_use]
+    pub const fn new(
+        source: DeviceId,
+        address: u8,
+        expected_inhibit: bool,
+        corrected: bool,
+    ) -> Self {
+        Self {
+            source,
+            address,
+            expected_inhibit,
+            corrected,
+        }
+    }
+}
+
+impl std::fmt::Display for StateDrift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = |inhibit| if inhibit { "inhibited" } else { "accepting" };
+        write!(
+            f,
+            "{} is {}, expected {}",
+            self.source,
+            state(!self.expected_inhibit),
+            state(self.expected_inhibit)
+        )?;
+        if self.corrected {
+            write!(f, " (corrected)")?;
+        }
+        Ok(())
+    }
+}
+
 /// Error that occurred while polling a specific device.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PoolPollError {
@@ -89,11 +136,15 @@ impl std::error::Error for PoolPollError {}
 pub struct PoolPollResult {
     /// Confirmed credits received during this poll.
     pub credits: Vec<CurrencyCredit>,
+    /// The credits as media independent [`CreditEvent`]s, in the same order.
+    pub credit_events: Vec<CreditEvent>,
     /// Bills currently held in escrow (for manual routing mode).
     pub pending_bills: Vec<PendingBill>,
     /// Errors that occurred while polling individual devices.
     /// Polling continues despite individual device errors.
     pub errors: Vec<PoolPollError>,
+    /// Devices found drifted by the master inhibit check run during this poll.
+    pub state_drifts: Vec<StateDrift>,
     /// Total value received in this poll (sum of credits).
     pub total_received: u32,
 }
@@ -104,8 +155,10 @@ impl PoolPollResult {
     pub const fn new() -> Self {
         Self {
             credits: Vec::new(),
+            credit_events: Vec::new(),
             pending_bills: Vec::new(),
             errors: Vec::new(),
+            state_drifts: Vec::new(),
             total_received: 0,
         }
     }
@@ -116,6 +169,12 @@ impl PoolPollResult {
         self.credits.push(credit);
     }
 
+    /// Adds a credit reported as a [`CreditEvent`], which is also added to `credits`.
+    pub fn add_credit_event(&mut self, event: CreditEvent) {
+        self.add_credit(event.into());
+        self.credit_events.push(event);
+    }
+
     /// Adds a pending bill to the result.
     pub fn add_pending_bill(&mut self, bill: PendingBill) {
         self.pending_bills.push(bill);
@@ -1,19 +1,30 @@
 #![allow(dead_code)]
 
 use std::{
+    collections::HashMap,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use cc_talk_core::cc_talk::{BillEvent, BillRouteCode, CoinEvent, CurrencyToken};
-use tokio::sync::{mpsc, oneshot};
+use cc_talk_core::cc_talk::{
+    BillEvent, BillRouteCode, CoinCredit, CoinEvent, CoinType, CreditCode, CreditCodeFormat,
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::{
     device::{
         base::{DeviceCommon, PollingError},
-        bill_validator::BillValidator,
-        coin_validator::CoinValidator,
+        bill_validator::{BillTable, BillValidator},
+        coin_validator::{CoinTable, CoinValidator},
+        service::ServiceRegistry,
+    },
+    transport::{
+        machine_state::{CommandClass, MachineStateHandle},
+        timestamp::Stamped,
     },
     util::DropGuard,
 };
@@ -21,9 +32,10 @@ use crate::{
 use super::{
     PoolError, PoolResult,
     builder::CurrencyAcceptorPoolBuilder,
-    config::{BillRoutingMode, DenominationRange, DeviceValueMap},
+    config::{BillRoutingMode, DenominationRange, DeviceValueMap, DriftCheck},
+    credit_event::CreditEvent,
     device_id::DeviceId,
-    poll_result::{CurrencyCredit, PendingBill, PoolPollError, PoolPollResult},
+    poll_result::{CurrencyCredit, PendingBill, PoolPollError, PoolPollResult, StateDrift},
 };
 
 type PoolPollReceiver = mpsc::Receiver<PoolPollResult>;
@@ -73,13 +85,24 @@ pub struct CurrencyAcceptorPool {
     bill_validators: Vec<BillValidator>,
     /// Maps position -> value for each coin validator
     coin_value_maps: Vec<DeviceValueMap>,
-    /// Maps position -> value for each bill validator
-    bill_value_maps: Vec<DeviceValueMap>,
+    /// Credit code format reported by each coin validator
+    coin_credit_formats: Vec<CreditCodeFormat>,
+    /// Values of the coin value format credit codes, built from the coin table.
+    coin_cvf_maps: Vec<DeviceValueMap>,
     denomination_range: DenominationRange,
     bill_routing_mode: BillRoutingMode,
     polling_interval: Duration,
     is_polling: Arc<Mutex<bool>>,
     initialized: Arc<Mutex<bool>>,
+    service: ServiceRegistry,
+    /// Deadline of the polling pause of each paused address.
+    paused: Arc<Mutex<HashMap<u8, Instant>>>,
+    max_polling_pause: Duration,
+    /// Whether the devices should accept currency, set by `enable` and `disable`.
+    accepting: Arc<Mutex<bool>>,
+    drift_check: Option<DriftCheck>,
+    last_drift_check: Arc<Mutex<Option<Instant>>>,
+    machine_state: Option<MachineStateHandle>,
 }
 
 impl CurrencyAcceptorPool {
@@ -98,6 +121,8 @@ impl CurrencyAcceptorPool {
         denomination_range: DenominationRange,
         bill_routing_mode: BillRoutingMode,
         polling_interval: Duration,
+        service: ServiceRegistry,
+        max_polling_pause: Duration,
     ) -> Self {
         let coin_count = coin_validators.len();
         let bill_count = bill_validators.len();
@@ -116,15 +141,33 @@ impl CurrencyAcceptorPool {
             coin_validators,
             bill_validators,
             coin_value_maps: vec![DeviceValueMap::new(); coin_count],
-            bill_value_maps: vec![DeviceValueMap::new(); bill_count],
+            coin_credit_formats: vec![CreditCodeFormat::CoinPosition; coin_count],
+            coin_cvf_maps: vec![DeviceValueMap::new(); coin_count],
             denomination_range,
             bill_routing_mode,
             polling_interval,
             is_polling: Arc::new(Mutex::new(false)),
             initialized: Arc::new(Mutex::new(false)),
+            service,
+            paused: Arc::new(Mutex::new(HashMap::new())),
+            max_polling_pause,
+            accepting: Arc::new(Mutex::new(false)),
+            drift_check: None,
+            last_drift_check: Arc::new(Mutex::new(None)),
+            machine_state: None,
         }
     }
 
+    pub(super) const fn with_drift_check(mut self, drift_check: Option<DriftCheck>) -> Self {
+        self.drift_check = drift_check;
+        self
+    }
+
+    pub(super) fn with_machine_state(mut self, machine_state: Option<MachineStateHandle>) -> Self {
+        self.machine_state = machine_state;
+        self
+    }
+
     /// Returns the number of coin validators in the pool.
     #[must_use]
     pub fn coin_validator_count(&self) -> usize {
@@ -167,6 +210,18 @@ impl CurrencyAcceptorPool {
         self.polling_interval
     }
 
+    /// Returns the configured master inhibit drift check.
+    #[must_use]
+    pub const fn drift_check(&self) -> Option<DriftCheck> {
+        self.drift_check
+    }
+
+    /// Returns the service registry used to track quiesced devices.
+    #[must_use]
+    pub const fn service_registry(&self) -> &ServiceRegistry {
+        &self.service
+    }
+
     /// Initializes the pool by reading currency IDs from all devices
     /// and configuring inhibits based on the denomination range.
     ///
@@ -197,30 +252,48 @@ impl CurrencyAcceptorPool {
         // Initialize coin validators
         for (idx, cv) in self.coin_validators.iter().enumerate() {
             debug!(device_idx = idx, "initializing coin validator");
+            match cv.get_credit_code_format().await {
+                Ok(format) => self.coin_credit_formats[idx] = format,
+                Err(e) => {
+                    warn!(device_idx = idx, error = %e, "failed to read option flags, assuming coin position format");
+                }
+            }
             let value_map = &mut self.coin_value_maps[idx];
             let mut inhibits = [true; 16]; // Start with all inhibited
             let mut enabled_count = 0;
 
-            for position in 0..16u8 {
-                if let Ok(token) = cv.request_coin_id(position).await
-                    && let Some(value) = Self::extract_value(&token)
-                {
-                    value_map.insert(position, value);
-                    // Enable positions within denomination range
-                    if self.denomination_range.contains(value) {
-                        inhibits[position as usize] = false;
-                        enabled_count += 1;
-                        trace!(device_idx = idx, position, value, "coin position enabled");
-                    } else {
-                        trace!(
-                            device_idx = idx,
-                            position, value, "coin position inhibited (outside denomination range)"
-                        );
-                    }
+            let coin_table = match cv.coin_table().await {
+                Ok(coin_table) => coin_table,
+                Err(e) => {
+                    warn!(device_idx = idx, error = %e, "failed to read coin table");
+                    CoinTable::default()
+                }
+            };
+            if self.coin_credit_formats[idx] == CreditCodeFormat::CoinValueFormat {
+                self.coin_cvf_maps[idx] = coin_table.cvf_values().into_iter().collect();
+                debug!(
+                    device_idx = idx,
+                    codes = self.coin_cvf_maps[idx].len(),
+                    "coin value format table built"
+                );
+            }
+            for (position, _) in coin_table.iter() {
+                let Some(value) = coin_table.value(position) else {
+                    continue;
+                };
+                value_map.insert(position, value);
+                // Enable positions within denomination range, coin positions are 1-indexed
+                if self.denomination_range.contains(value) {
+                    inhibits[usize::from(position - 1)] = false;
+                    enabled_count += 1;
+                    trace!(device_idx = idx, position, value, "coin position enabled");
+                } else {
+                    trace!(
+                        device_idx = idx,
+                        position, value, "coin position inhibited (outside denomination range)"
+                    );
                 }
             }
-
-            inhibits.rotate_left(1);
 
             // Set coin inhibits based on denomination range
             if let Err(e) = cv.set_coin_inhibits(inhibits).await {
@@ -242,33 +315,36 @@ impl CurrencyAcceptorPool {
         // Initialize bill validators
         for (idx, bv) in self.bill_validators.iter().enumerate() {
             debug!(device_idx = idx, "initializing bill validator");
-            let value_map = &mut self.bill_value_maps[idx];
             let mut inhibits = [true; 16]; // Start with all inhibited
             let mut enabled_count = 0;
 
-            for position in 0..16u8 {
-                if let Ok(token) = bv.request_bill_id(position).await
-                    && let Some(value) = Self::extract_value(&token)
-                {
-                    value_map.insert(position, value);
-                    // Enable positions within denomination range
-                    if self.denomination_range.contains(value) {
-                        inhibits[position as usize] = false;
-                        enabled_count += 1;
-                        trace!(device_idx = idx, position, value, "bill position enabled");
-                    } else {
-                        trace!(
-                            device_idx = idx,
-                            position, value, "bill position inhibited (outside denomination range)"
-                        );
-                    }
+            // The bill table is cached by the validator, it is read again after a bank switch or
+            // a currency update before further credits are valued.
+            let bill_table = match bv.refresh_bill_table().await {
+                Ok(bill_table) => bill_table,
+                Err(e) => {
+                    warn!(device_idx = idx, error = %e, "failed to read bill table");
+                    BillTable::default()
+                }
+            };
+            for (bill_type, _) in bill_table.iter() {
+                let Some(value) = bill_table.value(bill_type) else {
+                    continue;
+                };
+                // Enable bill types within denomination range, bill types are 1-indexed
+                if self.denomination_range.contains(value) {
+                    inhibits[usize::from(bill_type - 1)] = false;
+                    enabled_count += 1;
+                    trace!(device_idx = idx, bill_type, value, "bill type enabled");
+                } else {
+                    trace!(
+                        device_idx = idx,
+                        bill_type, value, "bill type inhibited (outside denomination range)"
+                    );
                 }
             }
 
             // Set bill inhibits based on denomination range
-            // Bill IDs are 1-indexed, so position 0 in the inhibits array
-            // corresponds to bill ID 1. Rotate left by 1 to align correctly.
-            inhibits.rotate_left(1);
             if let Err(e) = bv.set_bill_inhibits(inhibits).await {
                 warn!(device_idx = idx, error = %e, "failed to set bill inhibits");
             }
@@ -284,13 +360,14 @@ impl CurrencyAcceptorPool {
 
             info!(
                 device_idx = idx,
-                positions_configured = value_map.len(),
+                positions_configured = bill_table.len(),
                 positions_enabled = enabled_count,
                 use_escrow,
                 "bill validator initialized"
             );
         }
 
+        *self.accepting.lock().expect("should not be poisoned") = false;
         *self.initialized.lock().expect("should not be poisoned") = true;
         info!("currency acceptor pool initialization complete");
         Ok(())
@@ -300,15 +377,31 @@ impl CurrencyAcceptorPool {
     ///
     /// This disables the master inhibit on all devices, allowing them to
     /// accept coins/bills according to their individual inhibit settings.
+    /// Devices which are out of service are left inhibited.
     #[instrument(skip(self))]
     pub async fn enable(&self) -> PoolResult<()> {
         debug!("enabling all devices in pool");
+        *self.accepting.lock().expect("should not be poisoned") = true;
         for (idx, cv) in self.coin_validators.iter().enumerate() {
+            if self.service.is_out_of_service(cv.device.address()) {
+                debug!(
+                    device_idx = idx,
+                    "coin validator out of service, not enabling"
+                );
+                continue;
+            }
             if let Err(e) = cv.disable_master_inhibit().await {
                 warn!(device_idx = idx, error = %e, "failed to disable master inhibit on coin validator");
             }
         }
         for (idx, bv) in self.bill_validators.iter().enumerate() {
+            if self.service.is_out_of_service(bv.device.address()) {
+                debug!(
+                    device_idx = idx,
+                    "bill validator out of service, not enabling"
+                );
+                continue;
+            }
             if let Err(e) = bv.disable_master_inhibit().await {
                 warn!(device_idx = idx, error = %e, "failed to disable master inhibit on bill validator");
             }
@@ -324,6 +417,7 @@ impl CurrencyAcceptorPool {
     #[instrument(skip(self))]
     pub async fn disable(&self) -> PoolResult<()> {
         debug!("disabling all devices in pool");
+        *self.accepting.lock().expect("should not be poisoned") = false;
         for (idx, cv) in self.coin_validators.iter().enumerate() {
             if let Err(e) = cv.enable_master_inhibit().await {
                 warn!(device_idx = idx, error = %e, "failed to enable master inhibit on coin validator");
@@ -338,10 +432,96 @@ impl CurrencyAcceptorPool {
         Ok(())
     }
 
+    /// Returns the longest polling pause, see [`pause_polling`](Self::pause_polling).
+    #[must_use]
+    pub const fn max_polling_pause(&self) -> Duration {
+        self.max_polling_pause
+    }
+
+    /// Stops polling the device at `address` until [`resume_polling`](Self::resume_polling).
+    ///
+    /// Use it to silence a device during a firmware upgrade or teach mode without stopping
+    /// the polling of the other devices. Polling resumes automatically after
+    /// [`max_polling_pause`](Self::max_polling_pause) in case the pause is never lifted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PoolError::DeviceNotFound`] if no device of the pool uses this address.
+    pub fn pause_polling(&self, address: u8) -> PoolResult<()> {
+        self.pause_polling_for(address, self.max_polling_pause)
+    }
+
+    /// Stops polling the device at `address` for at most `duration`, see
+    /// [`pause_polling`](Self::pause_polling).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PoolError::DeviceNotFound`] if no device of the pool uses this address.
+    pub fn pause_polling_for(&self, address: u8, duration: Duration) -> PoolResult<()> {
+        self.ensure_device(address)?;
+        self.paused
+            .lock()
+            .expect("should not be poisoned")
+            .insert(address, Instant::now() + duration);
+        info!(address, ?duration, "polling paused");
+        Ok(())
+    }
+
+    /// Polls the device at `address` again, does nothing if its polling is not paused.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PoolError::DeviceNotFound`] if no device of the pool uses this address.
+    pub fn resume_polling(&self, address: u8) -> PoolResult<()> {
+        self.ensure_device(address)?;
+        if self
+            .paused
+            .lock()
+            .expect("should not be poisoned")
+            .remove(&address)
+            .is_some()
+        {
+            info!(address, "polling resumed");
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the polling of the device at `address` is paused.
+    ///
+    /// Expired pauses are lifted.
+    #[must_use]
+    pub fn is_polling_paused(&self, address: u8) -> bool {
+        let mut paused = self.paused.lock().expect("should not be poisoned");
+        match paused.get(&address) {
+            Some(deadline) if *deadline <= Instant::now() => {
+                paused.remove(&address);
+                warn!(address, "polling pause expired, polling resumed");
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    fn ensure_device(&self, address: u8) -> PoolResult<()> {
+        let known = self
+            .coin_validators
+            .iter()
+            .map(|cv| cv.device.address())
+            .chain(self.bill_validators.iter().map(|bv| bv.device.address()))
+            .any(|device_address| device_address == address);
+        if known {
+            Ok(())
+        } else {
+            Err(PoolError::DeviceNotFound(address))
+        }
+    }
+
     /// Polls all devices in the pool and returns aggregated results.
     ///
     /// This method polls each coin and bill validator, processing their
-    /// events and converting position indices to currency values.
+    /// events and converting position indices to currency values. Devices
+    /// which are out of service or whose polling is paused are skipped.
     ///
     /// # Bill Routing
     ///
@@ -355,91 +535,319 @@ impl CurrencyAcceptorPool {
         // Poll coin validators
         for (idx, cv) in self.coin_validators.iter().enumerate() {
             let device_id = DeviceId::CoinValidator(idx);
+            if cv.is_busy() {
+                trace!(device = %device_id, "long operation running, skipping poll");
+                continue;
+            }
+            if self.service.is_out_of_service(cv.device.address()) {
+                trace!(device = %device_id, "out of service, skipping poll");
+                continue;
+            }
+            if self.is_polling_paused(cv.device.address()) {
+                trace!(device = %device_id, "polling paused, skipping poll");
+                continue;
+            }
+
+            self.poll_coin_validator(idx, cv, &mut result).await;
+        }
+
+        // Poll bill validators
+        for (idx, bv) in self.bill_validators.iter().enumerate() {
+            let device_id = DeviceId::BillValidator(idx);
+            if bv.is_busy() {
+                trace!(device = %device_id, "long operation running, skipping poll");
+                continue;
+            }
+            if self.service.is_out_of_service(bv.device.address()) {
+                trace!(device = %device_id, "out of service, skipping poll");
+                continue;
+            }
+            if self.is_polling_paused(bv.device.address()) {
+                trace!(device = %device_id, "polling paused, skipping poll");
+                continue;
+            }
+
+            self.poll_bill_validator(idx, bv, &mut result).await;
+        }
+
+        if let Some(check) = self.drift_check
+            && self.drift_check_due(check.interval)
+        {
+            result.state_drifts = self.check_master_inhibits(check.auto_correct).await;
+        }
+
+        result
+    }
+
+    fn drift_check_due(&self, interval: Duration) -> bool {
+        let mut last = self
+            .last_drift_check
+            .lock()
+            .expect("should not be poisoned");
+        let now = Instant::now();
+        match *last {
+            Some(checked_at) if now.duration_since(checked_at) < interval => false,
+            _ => {
+                *last = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Reads the master inhibit status of every device and returns those which differ from the
+    /// state set by [`enable`](Self::enable) and [`disable`](Self::disable).
+    ///
+    /// Devices drift when they inhibit themselves, reset or receive commands from another host,
+    /// and silently stop accepting currency. Out of service devices are expected to be
+    /// inhibited, so is every device while the machine state does not allow acceptance, e.g.
+    /// after a [`DeviceManager::lockdown`](crate::device::manager::DeviceManager::lockdown).
+    /// Devices running a long operation, whose polling is paused or which do not answer are
+    /// skipped. With `auto_correct`, drifted devices are set back to the intended state.
+    ///
+    /// Runs periodically during [`poll`](Self::poll) if a [`DriftCheck`] is configured.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn check_master_inhibits(&self, auto_correct: bool) -> Vec<StateDrift> {
+        let accepting = *self.accepting.lock().expect("should not be poisoned")
+            && self
+                .machine_state
+                .as_ref()
+                .is_none_or(|machine_state| machine_state.state().allows(CommandClass::Acceptance));
+        let mut drifts = Vec::new();
+
+        for (idx, cv) in self.coin_validators.iter().enumerate() {
+            let address = cv.device.address();
+            if cv.is_busy() || self.is_polling_paused(address) {
+                continue;
+            }
+            let expected = !accepting || self.service.is_out_of_service(address);
+            match cv.get_master_inhibit_status().await {
+                Ok(inhibited) if inhibited != expected => {
+                    let corrected = auto_correct && cv.set_master_inhibit(expected).await.is_ok();
+                    drifts.push(StateDrift::new(
+                        DeviceId::CoinValidator(idx),
+                        address,
+                        expected,
+                        corrected,
+                    ));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    debug!(device_idx = idx, error = %e, "failed to read coin validator master inhibit");
+                }
+            }
+        }
+        for (idx, bv) in self.bill_validators.iter().enumerate() {
+            let address = bv.device.address();
+            if bv.is_busy() || self.is_polling_paused(address) {
+                continue;
+            }
+            let expected = !accepting || self.service.is_out_of_service(address);
+            match bv.get_master_inhibit_status().await {
+                Ok(inhibited) if inhibited != expected => {
+                    let corrected = auto_correct && bv.set_master_inhibit(expected).await.is_ok();
+                    drifts.push(StateDrift::new(
+                        DeviceId::BillValidator(idx),
+                        address,
+                        expected,
+                        corrected,
+                    ));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    debug!(device_idx = idx, error = %e, "failed to read bill validator master inhibit");
+                }
+            }
+        }
+
+        for drift in &drifts {
+            warn!(device = %drift.source, address = drift.address, corrected = drift.corrected, "master inhibit drifted");
+        }
+        drifts
+    }
+
+    /// Polls a single coin validator and adds its credits to `result`.
+    async fn poll_coin_validator(
+        &self,
+        idx: usize,
+        cv: &CoinValidator,
+        result: &mut PoolPollResult,
+    ) {
+        let device_id = DeviceId::CoinValidator(idx);
+        match cv.poll_stamped().await {
+            Ok(Stamped {
+                value: poll_result,
+                received_at,
+            }) => {
+                for event in poll_result.events.iter() {
+                    if let CoinEvent::Credit(credit) = event {
+                        let position = credit.credit;
+                        if let Some(value) = Self::coin_credit_value(
+                            credit,
+                            self.coin_credit_formats[idx],
+                            &self.coin_value_maps[idx],
+                            &self.coin_cvf_maps[idx],
+                        ) {
+                            info!(
+                                device = %device_id,
+                                position,
+                                value,
+                                "coin credit received"
+                            );
+                            result.add_credit_event(
+                                CreditEvent::coin(device_id, value, *credit)
+                                    .with_received_at(received_at),
+                            );
+                        } else {
+                            warn!(
+                                device = %device_id,
+                                position,
+                                format = ?self.coin_credit_formats[idx],
+                                "coin credit received for unknown position"
+                            );
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                debug!(device = %device_id, error = %e, "coin validator poll error");
+                result.add_error(PoolPollError::new(device_id, e));
+            }
+        }
+    }
 
-            match cv.poll().await {
-                Ok(poll_result) => {
-                    for event in poll_result.events.iter() {
-                        if let CoinEvent::Credit(credit) = event {
-                            let position = credit.credit;
-                            if let Some(&value) = self.coin_value_maps[idx].get(&position) {
+    /// Polls a single bill validator and adds its credits to `result`.
+    async fn poll_bill_validator(
+        &self,
+        idx: usize,
+        bv: &BillValidator,
+        result: &mut PoolPollResult,
+    ) {
+        let device_id = DeviceId::BillValidator(idx);
+        match bv.poll_stamped().await {
+            Ok(Stamped {
+                value: poll_result,
+                received_at,
+            }) => {
+                let bill_table = if poll_result.events.iter().any(|event| {
+                    matches!(event, BillEvent::Credit(_) | BillEvent::PendingCredit(_))
+                }) {
+                    // Re-read if the poll detected a reset or the bank was switched.
+                    match bv.bill_table().await {
+                        Ok(bill_table) => bill_table,
+                        Err(e) => {
+                            warn!(device = %device_id, error = %e, "failed to read bill table, bill credits cannot be valued");
+                            result.add_error(PoolPollError::new(device_id, e));
+                            BillTable::default()
+                        }
+                    }
+                } else {
+                    BillTable::default()
+                };
+                for event in poll_result.events.iter() {
+                    match event {
+                        BillEvent::Credit(bill_type) => {
+                            if let Some(value) = bill_table.value(*bill_type) {
                                 info!(
                                     device = %device_id,
-                                    position,
+                                    bill_type,
                                     value,
-                                    "coin credit received"
+                                    "bill credit received"
+                                );
+                                result.add_credit_event(
+                                    CreditEvent::bill(device_id, value, *bill_type)
+                                        .with_received_at(received_at),
                                 );
-                                result.add_credit(CurrencyCredit::new(value, device_id, position));
                             } else {
                                 warn!(
                                     device = %device_id,
-                                    position,
-                                    "coin credit received for unknown position"
+                                    bill_type,
+                                    "bill credit received for unknown position"
                                 );
                             }
                         }
+                        BillEvent::PendingCredit(bill_type) => {
+                            self.handle_pending_bill(bv, idx, *bill_type, &bill_table, result)
+                                .await;
+                        }
+                        BillEvent::Reject(reason) => {
+                            warn!(device = %device_id, reason = %reason, "bill rejected");
+                        }
+                        BillEvent::FraudAttempt(reason) => {
+                            warn!(device = %device_id, reason = %reason, "bill fraud attempt detected");
+                        }
+                        BillEvent::FatalError(reason) => {
+                            error!(device = %device_id, reason = %reason, "bill validator fatal error");
+                        }
+                        BillEvent::Status(reason) => {
+                            info!(device = %device_id, reason = %reason, "bill validator status");
+                        }
                     }
                 }
-                Err(e) => {
-                    debug!(device = %device_id, error = %e, "coin validator poll error");
-                    result.add_error(PoolPollError::new(device_id, e));
-                }
+            }
+            Err(e) => {
+                debug!(device = %device_id, error = %e, "bill validator poll error");
+                result.add_error(PoolPollError::new(device_id, e));
             }
         }
+    }
 
-        // Poll bill validators
-        for (idx, bv) in self.bill_validators.iter().enumerate() {
-            let device_id = DeviceId::BillValidator(idx);
+    /// Quiesces a single device so a technician can safely remove it.
+    ///
+    /// This method:
+    /// 1. Enables the master inhibit, the device stops accepting currency
+    /// 2. Polls the device one last time to flush its buffered credits
+    /// 3. Stores the device counters to EEPROM
+    /// 4. Marks the device out of service in the [`ServiceRegistry`]
+    ///
+    /// Out of service devices are skipped by [`poll`](Self::poll) and [`enable`](Self::enable)
+    /// until [`ServiceRegistry::return_to_service`] is called.
+    ///
+    /// Returns the result of the final poll, its credits still have to be accounted for and
+    /// bills held in escrow (`BillRoutingMode::Manual`) still have to be routed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PoolError::DeviceNotFound`] if no device of the pool uses this address, or the
+    /// command error if the master inhibit could not be enabled. The device is left in service
+    /// in both cases.
+    #[instrument(skip(self))]
+    pub async fn quiesce(&self, address: u8) -> PoolResult<PoolPollResult> {
+        let mut result = PoolPollResult::new();
 
-            match bv.poll().await {
-                Ok(poll_result) => {
-                    for event in poll_result.events.iter() {
-                        match event {
-                            BillEvent::Credit(bill_type) => {
-                                if let Some(&value) = self.bill_value_maps[idx].get(bill_type) {
-                                    info!(
-                                        device = %device_id,
-                                        bill_type,
-                                        value,
-                                        "bill credit received"
-                                    );
-                                    result.add_credit(CurrencyCredit::new(
-                                        value, device_id, *bill_type,
-                                    ));
-                                } else {
-                                    warn!(
-                                        device = %device_id,
-                                        bill_type,
-                                        "bill credit received for unknown position"
-                                    );
-                                }
-                            }
-                            BillEvent::PendingCredit(bill_type) => {
-                                self.handle_pending_bill(bv, idx, *bill_type, &mut result)
-                                    .await;
-                            }
-                            BillEvent::Reject(reason) => {
-                                warn!(device = %device_id, reason = %reason, "bill rejected");
-                            }
-                            BillEvent::FraudAttempt(reason) => {
-                                warn!(device = %device_id, reason = %reason, "bill fraud attempt detected");
-                            }
-                            BillEvent::FatalError(reason) => {
-                                error!(device = %device_id, reason = %reason, "bill validator fatal error");
-                            }
-                            BillEvent::Status(reason) => {
-                                info!(device = %device_id, reason = %reason, "bill validator status");
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    debug!(device = %device_id, error = %e, "bill validator poll error");
-                    result.add_error(PoolPollError::new(device_id, e));
-                }
+        if let Some(idx) = self
+            .coin_validators
+            .iter()
+            .position(|cv| cv.device.address() == address)
+        {
+            let cv = &self.coin_validators[idx];
+            cv.enable_master_inhibit().await?;
+            self.poll_coin_validator(idx, cv, &mut result).await;
+            if let Err(e) = cv.store_counters().await {
+                warn!(device_idx = idx, error = %e, "failed to store coin validator counters");
             }
+        } else if let Some(idx) = self
+            .bill_validators
+            .iter()
+            .position(|bv| bv.device.address() == address)
+        {
+            let bv = &self.bill_validators[idx];
+            bv.enable_master_inhibit().await?;
+            self.poll_bill_validator(idx, bv, &mut result).await;
+            if let Err(e) = bv.store_counters().await {
+                warn!(device_idx = idx, error = %e, "failed to store bill validator counters");
+            }
+        } else {
+            return Err(PoolError::DeviceNotFound(address));
         }
 
-        result
+        self.service.mark_out_of_service(address);
+        info!(
+            address,
+            credits = result.credits.len(),
+            pending_bills = result.pending_bills.len(),
+            "device quiesced"
+        );
+        Ok(result)
     }
 
     /// Handles a pending bill based on the configured routing mode.
@@ -448,13 +856,11 @@ impl CurrencyAcceptorPool {
         bv: &BillValidator,
         device_idx: usize,
         bill_type: u8,
+        bill_table: &BillTable,
         result: &mut PoolPollResult,
     ) {
         let device_id = DeviceId::BillValidator(device_idx);
-        let value = self.bill_value_maps[device_idx]
-            .get(&bill_type)
-            .copied()
-            .unwrap_or(0);
+        let value = bill_table.value(bill_type).unwrap_or(0);
 
         match self.bill_routing_mode {
             BillRoutingMode::AutoStack => {
@@ -882,11 +1288,22 @@ impl CurrencyAcceptorPool {
         Ok(rx_with_guard)
     }
 
-    /// Extracts the value in smallest currency units from a `CurrencyToken`.
-    fn extract_value(token: &CurrencyToken) -> Option<u32> {
-        match token {
-            CurrencyToken::Token => None,
-            CurrencyToken::Currency(value) => Some(value.smallest_unit_value()),
+    /// Resolves the value of a coin credit, either through the position value map or through
+    /// the coin value format table.
+    ///
+    /// Without a coin value format table, e.g. the coin table couldn't be read, coin codes are
+    /// valued directly and tokens have no value.
+    fn coin_credit_value(
+        credit: &CoinCredit,
+        format: CreditCodeFormat,
+        value_map: &DeviceValueMap,
+        cvf_map: &DeviceValueMap,
+    ) -> Option<u32> {
+        match credit.credit_code(format) {
+            CreditCode::Position(position) => value_map.get(&position).copied(),
+            CreditCode::Value(_) if !cvf_map.is_empty() => cvf_map.get(&credit.credit).copied(),
+            CreditCode::Value(CoinType::Coin(value)) => Some(u32::from(value)),
+            CreditCode::Value(CoinType::Token | CoinType::None) => None,
         }
     }
 }
@@ -912,6 +1329,8 @@ mod tests {
             DenominationRange::new(50, 10000),
             BillRoutingMode::AutoStack,
             Duration::from_millis(100),
+            ServiceRegistry::new(),
+            Duration::from_secs(60),
         )
     }
 
@@ -934,12 +1353,112 @@ mod tests {
         assert_eq!(pool.polling_interval(), Duration::from_millis(100));
     }
 
+    #[test]
+    fn coin_credit_value_depends_on_credit_code_format() {
+        use cc_talk_core::cc_talk::SorterPath;
+
+        let value_map = DeviceValueMap::from([(3, 50)]);
+        let no_cvf_map = DeviceValueMap::new();
+        let credit = |code| CoinCredit {
+            credit: code,
+            sorter_path: SorterPath::NotSupported,
+        };
+
+        let position = CreditCodeFormat::CoinPosition;
+        assert_eq!(
+            CurrencyAcceptorPool::coin_credit_value(&credit(3), position, &value_map, &no_cvf_map),
+            Some(50)
+        );
+        assert_eq!(
+            CurrencyAcceptorPool::coin_credit_value(&credit(4), position, &value_map, &no_cvf_map),
+            None
+        );
+
+        let cvf = CreditCodeFormat::CoinValueFormat;
+        assert_eq!(
+            CurrencyAcceptorPool::coin_credit_value(&credit(3), cvf, &value_map, &no_cvf_map),
+            Some(3)
+        );
+        assert_eq!(
+            CurrencyAcceptorPool::coin_credit_value(&credit(148), cvf, &value_map, &no_cvf_map),
+            Some(200)
+        );
+        assert_eq!(
+            CurrencyAcceptorPool::coin_credit_value(&credit(255), cvf, &value_map, &no_cvf_map),
+            None
+        );
+
+        let cvf_map = DeviceValueMap::from([(148, 2000), (255, 150)]);
+        assert_eq!(
+            CurrencyAcceptorPool::coin_credit_value(&credit(148), cvf, &value_map, &cvf_map),
+            Some(2000)
+        );
+        assert_eq!(
+            CurrencyAcceptorPool::coin_credit_value(&credit(255), cvf, &value_map, &cvf_map),
+            Some(150)
+        );
+        assert_eq!(
+            CurrencyAcceptorPool::coin_credit_value(&credit(3), cvf, &value_map, &cvf_map),
+            None
+        );
+    }
+
     #[test]
     fn pool_not_initialized_by_default() {
         let pool = create_test_pool();
         assert!(!pool.is_initialized());
     }
 
+    #[tokio::test]
+    async fn out_of_service_devices_are_not_polled() {
+        let pool = create_test_pool();
+
+        // The transport is gone, every poll fails.
+        assert_eq!(pool.poll().await.errors.len(), 2);
+
+        pool.service_registry().mark_out_of_service(2);
+        pool.service_registry().mark_out_of_service(40);
+        let result = pool.poll().await;
+        assert!(result.is_empty());
+        assert!(!result.has_errors());
+
+        pool.service_registry().return_to_service(40);
+        assert_eq!(pool.poll().await.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn paused_devices_are_not_polled_until_resumed() {
+        let pool = create_test_pool();
+
+        pool.pause_polling(2).expect("should pause");
+        assert!(pool.is_polling_paused(2));
+        assert_eq!(pool.poll().await.errors.len(), 1);
+
+        pool.resume_polling(2).expect("should resume");
+        assert_eq!(pool.poll().await.errors.len(), 2);
+
+        pool.pause_polling_for(40, Duration::from_millis(20))
+            .expect("should pause");
+        assert_eq!(pool.poll().await.errors.len(), 1);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!pool.is_polling_paused(40), "the pause expired");
+        assert_eq!(pool.poll().await.errors.len(), 2);
+
+        assert!(matches!(
+            pool.pause_polling(3),
+            Err(PoolError::DeviceNotFound(3))
+        ));
+    }
+
+    #[tokio::test]
+    async fn quiesce_unknown_device_is_an_error() {
+        let pool = create_test_pool();
+
+        let result = pool.quiesce(3).await;
+        assert!(matches!(result, Err(PoolError::DeviceNotFound(3))));
+        assert!(pool.service_registry().out_of_service().is_empty());
+    }
+
     #[tokio::test]
     async fn try_background_polling_returns_already_leased_when_called_twice() {
         let pool = create_test_pool();
@@ -0,0 +1,282 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{sync::mpsc, task::JoinHandle, time::Instant};
+use tracing::{debug, info, instrument};
+
+use super::{
+    PoolResult,
+    poll_result::CurrencyCredit,
+    pool::{CurrencyAcceptorPool, PaymentProgress},
+};
+
+/// Configuration of an [`AcceptanceSession`].
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// How long the devices accept currency.
+    pub duration: Duration,
+    /// Value at which the session ends early, `None` accepts until the session expires.
+    pub target: Option<u32>,
+    progress_tx: Option<mpsc::Sender<PaymentProgress>>,
+}
+
+impl SessionConfig {
+    /// Accepts currency for `duration`.
+    #[must_use]
+    pub const fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            target: None,
+            progress_tx: None,
+        }
+    }
+
+    /// Ends the session as soon as `target` is inserted.
+    #[must_use]
+    pub const fn with_target(mut self, target: u32) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Sends a [`PaymentProgress`] for every credit, its `target_value` is 0 without a target.
+    #[must_use]
+    pub fn with_progress(mut self, progress_tx: mpsc::Sender<PaymentProgress>) -> Self {
+        self.progress_tx = Some(progress_tx);
+        self
+    }
+}
+
+/// Why an [`AcceptanceSession`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// The target value was inserted.
+    TargetReached,
+    /// The session duration elapsed.
+    Expired,
+    /// The session was cancelled or dropped.
+    Cancelled,
+}
+
+/// Value inserted during an [`AcceptanceSession`].
+#[derive(Debug, Clone)]
+pub struct SessionReport {
+    /// Total value inserted, may exceed the target.
+    pub total_received: u32,
+    /// Credits in the order they were inserted.
+    pub credits: Vec<CurrencyCredit>,
+    pub end: SessionEnd,
+    /// How long the devices accepted currency.
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+struct SessionState {
+    deadline: Instant,
+    cancelled: bool,
+    total_received: u32,
+    credits: Vec<CurrencyCredit>,
+}
+
+/// Payment window during which the pool accepts currency, see
+/// [`CurrencyAcceptorPool::start_session`].
+///
+/// The devices are inhibited again when the window expires, when the target is reached or when
+/// the session is cancelled. Dropping the session cancels it.
+#[derive(Debug)]
+pub struct AcceptanceSession {
+    state: Arc<Mutex<SessionState>>,
+    task: Option<JoinHandle<PoolResult<SessionReport>>>,
+}
+
+impl AcceptanceSession {
+    /// Returns the value inserted so far.
+    #[must_use]
+    pub fn received(&self) -> u32 {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .total_received
+    }
+
+    /// Returns the credits inserted so far.
+    #[must_use]
+    pub fn credits(&self) -> Vec<CurrencyCredit> {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .credits
+            .clone()
+    }
+
+    /// Returns the time left before the session expires.
+    #[must_use]
+    pub fn time_left(&self) -> Duration {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .deadline
+            .saturating_duration_since(Instant::now())
+    }
+
+    /// Pushes the expiry back, e.g. while the customer is still inserting coins.
+    pub fn extend(&self, duration: Duration) {
+        let mut state = self.state.lock().expect("should not be poisoned");
+        state.deadline += duration;
+        debug!(
+            deadline_in_ms = state
+                .deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis() as u64,
+            "acceptance session extended"
+        );
+    }
+
+    /// Ends the session at the next poll, the devices are inhibited.
+    pub fn cancel(&self) {
+        self.state.lock().expect("should not be poisoned").cancelled = true;
+    }
+
+    /// Waits for the session to end and returns what was inserted.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the devices could not be enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the session task panicked.
+    pub async fn finish(mut self) -> PoolResult<SessionReport> {
+        let task = self.task.take().expect("should only be finished once");
+        task.await.expect("session task should not panic")
+    }
+}
+
+impl Drop for AcceptanceSession {
+    fn drop(&mut self) {
+        if self.task.is_some() {
+            self.cancel();
+        }
+    }
+}
+
+impl CurrencyAcceptorPool {
+    /// Opens a payment window: enables the devices and aggregates the credits in the
+    /// background until the session expires, reaches its target or is cancelled.
+    ///
+    /// The devices are inhibited when the session ends, credits still buffered by the devices
+    /// are collected by a last poll.
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let session = pool.start_session(
+    ///     SessionConfig::new(Duration::from_secs(30)).with_target(500),
+    /// );
+    /// // ... show session.received() and session.time_left() ...
+    /// let report = session.finish().await?;
+    /// ```
+    #[instrument(skip(self, config), fields(duration_ms = config.duration.as_millis() as u64, target = config.target))]
+    pub fn start_session(&self, config: SessionConfig) -> AcceptanceSession {
+        let state = Arc::new(Mutex::new(SessionState {
+            deadline: Instant::now() + config.duration,
+            cancelled: false,
+            total_received: 0,
+            credits: Vec::new(),
+        }));
+        let pool = self.clone();
+        let task_state = Arc::clone(&state);
+        let task = tokio::spawn(async move { pool.run_session(config, task_state).await });
+        AcceptanceSession {
+            state,
+            task: Some(task),
+        }
+    }
+
+    async fn run_session(
+        &self,
+        config: SessionConfig,
+        state: Arc<Mutex<SessionState>>,
+    ) -> PoolResult<SessionReport> {
+        let started = Instant::now();
+        if let Err(e) = self.enable().await {
+            let _ = self.disable().await;
+            return Err(e);
+        }
+        info!("acceptance session started");
+
+        let end = loop {
+            let result = self.poll().await;
+            record_credits(&config, &state, result.credits);
+
+            let (deadline, cancelled, received) = {
+                let state = state.lock().expect("should not be poisoned");
+                (state.deadline, state.cancelled, state.total_received)
+            };
+            if config.target.is_some_and(|target| received >= target) {
+                break SessionEnd::TargetReached;
+            }
+            if cancelled {
+                break SessionEnd::Cancelled;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break SessionEnd::Expired;
+            }
+            tokio::time::sleep(self.polling_interval().min(deadline - now)).await;
+        };
+
+        let _ = self.disable().await;
+        let duration = started.elapsed();
+        // Coins validated just before the inhibit are still buffered.
+        let result = self.poll().await;
+        record_credits(&config, &state, result.credits);
+
+        let state = state.lock().expect("should not be poisoned");
+        let report = SessionReport {
+            total_received: state.total_received,
+            credits: state.credits.clone(),
+            end,
+            duration,
+        };
+        drop(state);
+        info!(
+            total_received = report.total_received,
+            credits_count = report.credits.len(),
+            end = ?report.end,
+            "acceptance session ended"
+        );
+        Ok(report)
+    }
+}
+
+/// Adds the credits to the session and reports the progress.
+fn record_credits(
+    config: &SessionConfig,
+    state: &Mutex<SessionState>,
+    credits: Vec<CurrencyCredit>,
+) {
+    let mut state = state.lock().expect("should not be poisoned");
+    for credit in credits {
+        state.total_received += credit.value;
+        let target_value = config.target.unwrap_or(0);
+        debug!(
+            credit_value = credit.value,
+            total_received = state.total_received,
+            "credit added to session"
+        );
+        if let Some(tx) = &config.progress_tx {
+            // Don't fail the session if the progress receiver is dropped
+            let _ = tx.try_send(PaymentProgress {
+                credit: credit.clone(),
+                total_received: state.total_received,
+                target_value,
+                remaining: target_value.saturating_sub(state.total_received),
+            });
+        }
+        state.credits.push(credit);
+    }
+}
@@ -0,0 +1,583 @@
+//! Forwarding of bus events to application callbacks and HTTP webhooks.
+//!
+//! Small deployments often only need to react to credits, faults and hoppers to refill. The
+//! [`EventDispatcher`] runs in its own task, receives [`BusEvent`]s converted from the pool
+//! results and events, and forwards the selected kinds to each sink. Webhooks need the
+//! `webhooks` feature:
+//!
+//! ```ignore
+//! let (events, _task) = EventDispatcher::new()
+//!     .with_callback(&[EventKind::Credit], |event| {
+//!         Box::pin(async move { println!("{event:?}") })
+//!     })
+//!     .with_webhook(
+//!         &[EventKind::Fault, EventKind::RefillNeeded],
+//!         Webhook::new("http://10.0.0.2:8080/cctalk")?,
+//!     )
+//!     .spawn(32);
+//!
+//! for event in BusEvent::from_pool_poll(&pool.poll().await) {
+//!     events.send(event).await?;
+//! }
+//! ```
+
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+#[cfg(feature = "webhooks")]
+use std::{sync::OnceLock, time::Duration};
+
+#[cfg(feature = "webhooks")]
+use serde_json::json;
+#[cfg(feature = "webhooks")]
+use thiserror::Error;
+use tokio::{sync::mpsc, task::JoinHandle};
+#[cfg(feature = "webhooks")]
+use tracing::{debug, warn};
+
+use super::{
+    base::CommandError,
+    currency_acceptor_pool::{CurrencyCredit, PoolPollResult, StateDrift},
+    payout_pool::{HopperInventoryLevel, PayoutEvent},
+    payout_sensor_pool::SensorEvent,
+};
+
+/// Future returned by an event callback.
+pub type CallbackFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+type Callback = Arc<dyn Fn(BusEvent) -> CallbackFuture + Send + Sync>;
+
+/// Kind of a [`BusEvent`], used to select the events a sink receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Credit,
+    Fault,
+    RefillNeeded,
+    StateDrift,
+}
+
+/// Event forwarded by an [`EventDispatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BusEvent {
+    /// Currency was accepted.
+    Credit(CurrencyCredit),
+    /// A device failed, `device` names it as the pool which reported the error does.
+    Fault { device: String, error: CommandError },
+    /// A hopper is empty or low and should be refilled.
+    RefillNeeded {
+        address: u8,
+        level: HopperInventoryLevel,
+    },
+    /// A device master inhibit differs from the state intended by the host.
+    StateDrift(StateDrift),
+}
+
+impl BusEvent {
+    #[must_use]
+    pub const fn kind(&self) -> EventKind {
+        match self {
+            Self::Credit(_) => EventKind::Credit,
+            Self::Fault { .. } => EventKind::Fault,
+            Self::RefillNeeded { .. } => EventKind::RefillNeeded,
+            Self::StateDrift(_) => EventKind::StateDrift,
+        }
+    }
+
+    /// Returns the credits, poll errors and state drifts of a currency acceptor pool poll.
+    #[must_use]
+    pub fn from_pool_poll(result: &PoolPollResult) -> Vec<Self> {
+        result
+            .credits
+            .iter()
+            .cloned()
+            .map(Self::Credit)
+            .chain(result.errors.iter().map(|error| Self::Fault {
+                device: error.source.to_string(),
+                error: error.error.clone(),
+            }))
+            .chain(result.state_drifts.iter().copied().map(Self::StateDrift))
+            .collect()
+    }
+
+    /// Returns the refill needs and errors reported by a payout sensor pool.
+    ///
+    /// A refill is needed when a hopper drops to a low or empty level or is marked empty.
+    #[must_use]
+    pub fn from_sensor_event(event: &SensorEvent) -> Vec<Self> {
+        match event {
+            SensorEvent::InventoryUpdate { errors, .. } => errors
+                .iter()
+                .map(|error| Self::Fault {
+                    device: format!("hopper {}", error.address),
+                    error: error.error.clone(),
+                })
+                .collect(),
+            SensorEvent::LevelChanged {
+                address, current, ..
+            } if matches!(
+                current,
+                HopperInventoryLevel::Empty | HopperInventoryLevel::Low
+            ) =>
+            {
+                vec![Self::RefillNeeded {
+                    address: *address,
+                    level: *current,
+                }]
+            }
+            SensorEvent::MarkedEmpty { address } => vec![Self::RefillNeeded {
+                address: *address,
+                level: HopperInventoryLevel::Empty,
+            }],
+            SensorEvent::LevelChanged { .. } | SensorEvent::MarkedNonEmpty { .. } => Vec::new(),
+        }
+    }
+
+    /// Returns the refill need or fault of a payout event, if any.
+    #[must_use]
+    pub fn from_payout_event(event: &PayoutEvent) -> Option<Self> {
+        match event {
+            PayoutEvent::HopperEmpty { address, .. } => Some(Self::RefillNeeded {
+                address: *address,
+                level: HopperInventoryLevel::Empty,
+            }),
+            PayoutEvent::HopperError { address, error } => Some(Self::Fault {
+                device: format!("hopper {address}"),
+                error: error.clone(),
+            }),
+            PayoutEvent::Progress(_) | PayoutEvent::PlanRebalanced { .. } => None,
+        }
+    }
+
+    /// Renders the event as the JSON object posted to webhooks.
+    #[cfg(feature = "webhooks")]
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let value = match self {
+            Self::Credit(credit) => json!({
+                "kind": "credit",
+                "value": credit.value,
+                "device": credit.source.to_string(),
+                "position": credit.position,
+            }),
+            Self::Fault { device, error } => json!({
+                "kind": "fault",
+                "device": device,
+                "error": error.to_string(),
+            }),
+            Self::RefillNeeded { address, level } => json!({
+                "kind": "refill_needed",
+                "address": address,
+                "level": format!("{level:?}"),
+            }),
+            Self::StateDrift(drift) => json!({
+                "kind": "state_drift",
+                "device": drift.source.to_string(),
+                "address": drift.address,
+                "expected_inhibit": drift.expected_inhibit,
+                "corrected": drift.corrected,
+            }),
+        };
+        value.to_string()
+    }
+}
+
+#[cfg(feature = "webhooks")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum WebhookError {
+    #[error("only http:// and https:// webhook URLs are supported")]
+    UnsupportedScheme,
+    #[error("invalid webhook URL")]
+    InvalidUrl,
+}
+
+/// HTTP endpoint receiving each event as a JSON `POST`.
+///
+/// Each webhook delivers from its own task fed by a bounded queue, a slow or unreachable
+/// endpoint never delays the other sinks. Events are dropped with a warning while the queue is
+/// full.
+///
+/// Failed deliveries, i.e. connection errors, timeouts and non 2xx statuses, are retried with
+/// an exponential backoff, the event is dropped with a warning once the retries are exhausted.
+#[cfg(feature = "webhooks")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    url: reqwest::Url,
+    max_retries: u32,
+    initial_backoff: Duration,
+    timeout: Duration,
+    queue_capacity: usize,
+}
+
+#[cfg(feature = "webhooks")]
+impl Webhook {
+    /// Creates a webhook posting to `url`, e.g. `http://10.0.0.2:8080/cctalk`.
+    ///
+    /// Defaults to 3 retries starting with a 500ms backoff, a 5s request timeout and a queue of
+    /// 32 events.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the URL is invalid or is neither an `http://` nor an `https://` URL.
+    pub fn new(url: &str) -> Result<Self, WebhookError> {
+        let url = reqwest::Url::parse(url).map_err(|_| WebhookError::InvalidUrl)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(WebhookError::UnsupportedScheme);
+        }
+        Ok(Self {
+            url,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(5),
+            queue_capacity: 32,
+        })
+    }
+
+    /// Sets the number of retries after a failed delivery and the first backoff, which doubles
+    /// after each retry.
+    #[must_use]
+    pub const fn with_retries(mut self, max_retries: u32, initial_backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of events waiting for delivery before new events are dropped.
+    #[must_use]
+    pub const fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// Spawns the task delivering the queued events.
+    fn spawn(self) -> WebhookQueue {
+        let (sender, mut receiver) = mpsc::channel::<BusEvent>(self.queue_capacity.max(1));
+        let task = tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            while let Some(event) = receiver.recv().await {
+                self.deliver(&client, &event).await;
+            }
+        });
+        WebhookQueue { sender, task }
+    }
+
+    async fn deliver(&self, client: &reqwest::Client, event: &BusEvent) {
+        let body = event.to_json();
+        let mut backoff = self.initial_backoff;
+        for attempt in 0..=self.max_retries {
+            match self.post(client, &body).await {
+                Ok(()) => {
+                    debug!(kind = ?event.kind(), attempt, "webhook delivered");
+                    return;
+                }
+                Err(error) => warn!(attempt, %error, "webhook delivery failed"),
+            }
+            if attempt < self.max_retries {
+                tokio::time::sleep(backoff).await;
+                backoff = backoff.saturating_mul(2);
+            }
+        }
+        warn!(kind = ?event.kind(), "webhook retries exhausted, event dropped");
+    }
+
+    async fn post(&self, client: &reqwest::Client, body: &str) -> Result<(), reqwest::Error> {
+        client
+            .post(self.url.clone())
+            .timeout(self.timeout)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_owned())
+            .send()
+            .await?
+            .error_for_status()
+            .map(drop)
+    }
+}
+
+/// Queue of a webhook, its task is spawned with the first event.
+#[cfg(feature = "webhooks")]
+struct WebhookQueue {
+    sender: mpsc::Sender<BusEvent>,
+    task: JoinHandle<()>,
+}
+
+#[cfg(feature = "webhooks")]
+struct WebhookSink {
+    webhook: Webhook,
+    queue: OnceLock<WebhookQueue>,
+}
+
+#[cfg(feature = "webhooks")]
+impl WebhookSink {
+    fn enqueue(&self, event: &BusEvent) {
+        let queue = self.queue.get_or_init(|| self.webhook.clone().spawn());
+        if queue.sender.try_send(event.clone()).is_err() {
+            warn!(kind = ?event.kind(), url = %self.webhook.url, "webhook queue full, event dropped");
+        }
+    }
+
+    /// Waits for the queued events to be delivered.
+    async fn drain(self) {
+        if let Some(WebhookQueue { sender, task }) = self.queue.into_inner() {
+            drop(sender);
+            if let Err(error) = task.await {
+                warn!(%error, "webhook task failed");
+            }
+        }
+    }
+}
+
+enum Target {
+    Callback(Callback),
+    #[cfg(feature = "webhooks")]
+    Webhook(WebhookSink),
+}
+
+struct Sink {
+    kinds: Vec<EventKind>,
+    target: Target,
+}
+
+/// Forwards the selected [`BusEvent`]s to callbacks and webhooks, see the
+/// [module documentation](self).
+///
+/// Callbacks receive events in order, one after the other, a slow callback delays the next
+/// events. Webhooks are delivered from their own tasks, see [`Webhook`].
+#[derive(Default)]
+pub struct EventDispatcher {
+    sinks: Vec<Sink>,
+}
+
+impl fmt::Debug for EventDispatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventDispatcher")
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+impl EventDispatcher {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `callback` with each event of the given kinds.
+    #[must_use]
+    pub fn with_callback<F>(mut self, kinds: &[EventKind], callback: F) -> Self
+    where
+        F: Fn(BusEvent) -> CallbackFuture + Send + Sync + 'static,
+    {
+        self.sinks.push(Sink {
+            kinds: kinds.to_vec(),
+            target: Target::Callback(Arc::new(callback)),
+        });
+        self
+    }
+
+    /// Posts each event of the given kinds to `webhook`.
+    #[cfg(feature = "webhooks")]
+    #[must_use]
+    pub fn with_webhook(mut self, kinds: &[EventKind], webhook: Webhook) -> Self {
+        self.sinks.push(Sink {
+            kinds: kinds.to_vec(),
+            target: Target::Webhook(WebhookSink {
+                webhook,
+                queue: OnceLock::new(),
+            }),
+        });
+        self
+    }
+
+    /// Forwards `event` to every sink which selected its kind.
+    ///
+    /// Waits for the callbacks, events are only queued for the webhooks.
+    pub async fn dispatch(&self, event: &BusEvent) {
+        for sink in self
+            .sinks
+            .iter()
+            .filter(|sink| sink.kinds.contains(&event.kind()))
+        {
+            match &sink.target {
+                Target::Callback(callback) => callback(event.clone()).await,
+                #[cfg(feature = "webhooks")]
+                Target::Webhook(webhook) => webhook.enqueue(event),
+            }
+        }
+    }
+
+    /// Waits for the events queued for the webhooks to be delivered.
+    pub async fn shutdown(self) {
+        for sink in self.sinks {
+            match sink.target {
+                Target::Callback(_) => {}
+                #[cfg(feature = "webhooks")]
+                Target::Webhook(webhook) => webhook.drain().await,
+            }
+        }
+    }
+
+    /// Runs the dispatcher in its own task, fed by the returned sender.
+    ///
+    /// The task stops once every sender is dropped and the queued events are dispatched and
+    /// delivered to the webhooks.
+    #[must_use]
+    pub fn spawn(self, capacity: usize) -> (mpsc::Sender<BusEvent>, JoinHandle<()>) {
+        let (sender, mut receiver) = mpsc::channel(capacity);
+        let handle = tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                self.dispatch(&event).await;
+            }
+            self.shutdown().await;
+        });
+        (sender, handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+    #[cfg(feature = "webhooks")]
+    use std::time::Duration;
+
+    #[cfg(feature = "webhooks")]
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::device::currency_acceptor_pool::DeviceId;
+
+    #[cfg(feature = "webhooks")]
+    #[test]
+    fn webhook_urls_are_validated() {
+        let webhook = Webhook::new("http://10.0.0.2:8080/cctalk/events").unwrap();
+        assert_eq!(webhook.url.port(), Some(8080));
+        assert_eq!(webhook.url.path(), "/cctalk/events");
+        assert!(Webhook::new("https://host/").is_ok());
+        assert_eq!(
+            Webhook::new("ftp://host/"),
+            Err(WebhookError::UnsupportedScheme)
+        );
+        assert_eq!(Webhook::new("host"), Err(WebhookError::InvalidUrl));
+    }
+
+    #[cfg(feature = "webhooks")]
+    #[test]
+    fn events_are_rendered_as_json() {
+        let fault = BusEvent::Fault {
+            device: "hopper \"3\"".to_string(),
+            error: CommandError::Timeout,
+        };
+        assert_eq!(
+            fault.to_json(),
+            r#"{"device":"hopper \"3\"","error":"Timeout","kind":"fault"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn callbacks_receive_the_selected_kinds() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let (events, task) = EventDispatcher::new()
+            .with_callback(&[EventKind::RefillNeeded], move |event| {
+                let sink = sink.clone();
+                Box::pin(async move { sink.lock().unwrap().push(event) })
+            })
+            .spawn(4);
+
+        let credit = BusEvent::Credit(CurrencyCredit::new(100, DeviceId::CoinValidator(0), 3));
+        let refill = BusEvent::from_payout_event(&PayoutEvent::HopperEmpty {
+            address: 3,
+            coin_value: 100,
+        })
+        .unwrap();
+        events.send(credit).await.unwrap();
+        events.send(refill.clone()).await.unwrap();
+        drop(events);
+        task.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![refill]);
+    }
+
+    #[cfg(feature = "webhooks")]
+    #[tokio::test]
+    async fn webhooks_are_retried_until_accepted() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut bodies = Vec::new();
+            for status in ["503 Service Unavailable", "204 No Content"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0u8; 1024];
+                let n = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..n]).to_string();
+                bodies.push(request.split("\r\n\r\n").nth(1).unwrap().to_string());
+                let response = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n");
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            bodies
+        });
+
+        let dispatcher = EventDispatcher::new().with_webhook(
+            &[EventKind::Fault],
+            Webhook::new(&url)
+                .unwrap()
+                .with_retries(2, Duration::from_millis(1)),
+        );
+        dispatcher
+            .dispatch(&BusEvent::Fault {
+                device: "hopper 3".to_string(),
+                error: CommandError::Timeout,
+            })
+            .await;
+        dispatcher.shutdown().await;
+
+        let bodies = server.await.unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(
+            bodies[1],
+            r#"{"device":"hopper 3","error":"Timeout","kind":"fault"}"#
+        );
+    }
+
+    #[cfg(feature = "webhooks")]
+    #[tokio::test]
+    async fn unresponsive_webhooks_do_not_delay_the_callbacks() {
+        // Accepts the connections but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let dispatcher = EventDispatcher::new()
+            .with_webhook(
+                &[EventKind::Fault],
+                Webhook::new(&url)
+                    .unwrap()
+                    .with_timeout(Duration::from_secs(60))
+                    .with_queue_capacity(1),
+            )
+            .with_callback(&[EventKind::Fault], move |event| {
+                let sink = sink.clone();
+                Box::pin(async move { sink.lock().unwrap().push(event) })
+            });
+
+        let fault = BusEvent::Fault {
+            device: "hopper 3".to_string(),
+            error: CommandError::Timeout,
+        };
+        tokio::time::timeout(Duration::from_secs(1), async {
+            for _ in 0..3 {
+                dispatcher.dispatch(&fault).await;
+            }
+        })
+        .await
+        .expect("should not wait for the webhook");
+
+        assert_eq!(received.lock().unwrap().len(), 3);
+        drop(listener);
+    }
+}
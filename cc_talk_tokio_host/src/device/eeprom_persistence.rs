@@ -0,0 +1,297 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use tracing::{debug, info, instrument, warn};
+
+use super::base::{DeviceCommon, DeviceResult};
+
+/// Default minimum time between two scheduled EEPROM writes.
+pub const DEFAULT_EEPROM_WRITE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// What an EEPROM write stored, see [`EepromPersistence`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EepromWrites {
+    /// `ConfigurationToEEPROM` was acknowledged.
+    pub configuration: bool,
+    /// `CountersToEEPROM` was acknowledged.
+    pub counters: bool,
+}
+
+impl EepromWrites {
+    /// Returns `true` if nothing was written.
+    pub const fn is_empty(&self) -> bool {
+        !self.configuration && !self.counters
+    }
+}
+
+#[derive(Debug, Default)]
+struct PersistenceState {
+    configuration_dirty: bool,
+    counters_dirty: bool,
+    last_write: Option<Instant>,
+    configuration_persisted_at: Option<SystemTime>,
+    counters_persisted_at: Option<SystemTime>,
+}
+
+/// Batches the `ConfigurationToEEPROM` and `CountersToEEPROM` writes of a device.
+///
+/// EEPROMs only survive a limited number of writes, storing after every change wears them out.
+/// Changes are marked with [`mark_configuration_changed`](Self::mark_configuration_changed) and
+/// [`mark_counters_changed`](Self::mark_counters_changed), [`persist_if_due`](Self::persist_if_due)
+/// then writes them at most once per interval and [`flush`](Self::flush) writes them right away,
+/// e.g. on shutdown or before the machine is quiesced.
+///
+/// Devices which persist on their own NAK the commands, the changes are then considered stored.
+/// Clones share the same state, e.g. a clone can be handed to the code changing the counters.
+///
+/// # Example
+///
+/// ```ignore
+/// let persistence = EepromPersistence::new(hopper.clone());
+/// persistence.mark_counters_changed();
+///
+/// loop {
+///     persistence.persist_if_due().await?;
+///     tokio::time::sleep(Duration::from_secs(60)).await;
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct EepromPersistence<D> {
+    device: D,
+    write_interval: Duration,
+    state: Arc<Mutex<PersistenceState>>,
+}
+
+impl<D: DeviceCommon> EepromPersistence<D> {
+    /// Batches the writes of `device`, at most once per [`DEFAULT_EEPROM_WRITE_INTERVAL`].
+    pub fn new(device: D) -> Self {
+        Self {
+            device,
+            write_interval: DEFAULT_EEPROM_WRITE_INTERVAL,
+            state: Arc::new(Mutex::new(PersistenceState::default())),
+        }
+    }
+
+    /// Sets the minimum time between two scheduled writes.
+    #[must_use]
+    pub const fn with_write_interval(mut self, write_interval: Duration) -> Self {
+        self.write_interval = write_interval;
+        self
+    }
+
+    /// Records a configuration change which has to survive a power loss.
+    pub fn mark_configuration_changed(&self) {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .configuration_dirty = true;
+    }
+
+    /// Records a counter change which has to survive a power loss.
+    pub fn mark_counters_changed(&self) {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .counters_dirty = true;
+    }
+
+    /// Returns `true` if changes are waiting to be written.
+    pub fn is_dirty(&self) -> bool {
+        let state = self.state.lock().expect("should not be poisoned");
+        state.configuration_dirty || state.counters_dirty
+    }
+
+    /// Returns when the configuration was last stored, to report it along with the audit data.
+    pub fn configuration_persisted_at(&self) -> Option<SystemTime> {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .configuration_persisted_at
+    }
+
+    /// Returns when the counters were last stored, to report it along with the audit data.
+    pub fn counters_persisted_at(&self) -> Option<SystemTime> {
+        self.state
+            .lock()
+            .expect("should not be poisoned")
+            .counters_persisted_at
+    }
+
+    /// Returns `true` if changes are waiting and the write interval elapsed since the last write.
+    pub fn is_due(&self) -> bool {
+        let state = self.state.lock().expect("should not be poisoned");
+        (state.configuration_dirty || state.counters_dirty)
+            && state
+                .last_write
+                .is_none_or(|last_write| last_write.elapsed() >= self.write_interval)
+    }
+
+    /// Writes the pending changes if the write interval elapsed, see [`is_due`](Self::is_due).
+    ///
+    /// # Errors
+    ///
+    /// Errors if a write fails, the changes are then written again on the next call.
+    pub async fn persist_if_due(&self) -> DeviceResult<EepromWrites> {
+        if !self.is_due() {
+            return Ok(EepromWrites::default());
+        }
+        self.flush().await
+    }
+
+    /// Writes the pending changes now, regardless of the write interval.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a write fails, the changes are then written again on the next call.
+    #[instrument(skip(self), fields(address = self.device.get_device().address()), level = "debug")]
+    pub async fn flush(&self) -> DeviceResult<EepromWrites> {
+        let (configuration_dirty, counters_dirty) = {
+            let mut state = self.state.lock().expect("should not be poisoned");
+            let dirty = (state.configuration_dirty, state.counters_dirty);
+            // Changes made while writing are kept for the next write.
+            state.configuration_dirty = false;
+            state.counters_dirty = false;
+            dirty
+        };
+
+        let mut writes = EepromWrites::default();
+        if configuration_dirty {
+            match self.store(self.device.store_configuration().await) {
+                Ok(stored) => writes.configuration = stored,
+                Err(error) => {
+                    self.restore_dirty(true, counters_dirty);
+                    return Err(error);
+                }
+            }
+        }
+        if counters_dirty {
+            match self.store(self.device.store_counters().await) {
+                Ok(stored) => writes.counters = stored,
+                Err(error) => {
+                    self.restore_dirty(false, true);
+                    return Err(error);
+                }
+            }
+        }
+
+        let mut state = self.state.lock().expect("should not be poisoned");
+        if configuration_dirty || counters_dirty {
+            state.last_write = Some(Instant::now());
+        }
+        let now = SystemTime::now();
+        if configuration_dirty {
+            state.configuration_persisted_at = Some(now);
+        }
+        if counters_dirty {
+            state.counters_persisted_at = Some(now);
+        }
+        if !writes.is_empty() {
+            info!(
+                configuration = writes.configuration,
+                counters = writes.counters,
+                "changes stored to EEPROM"
+            );
+        }
+        Ok(writes)
+    }
+
+    /// Maps a NAK to `Ok(false)`, the device persists on its own.
+    fn store(&self, result: DeviceResult<()>) -> DeviceResult<bool> {
+        match result {
+            Ok(()) => Ok(true),
+            Err(error) if error.is_nack() => {
+                debug!(error = %error, "device persists on its own");
+                Ok(false)
+            }
+            Err(error) => {
+                warn!(error = %error, "EEPROM write failed");
+                Err(error)
+            }
+        }
+    }
+
+    fn restore_dirty(&self, configuration: bool, counters: bool) {
+        let mut state = self.state.lock().expect("should not be poisoned");
+        state.configuration_dirty |= configuration;
+        state.counters_dirty |= counters;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Device, Header};
+    use tokio::sync::mpsc;
+
+    use crate::{device::payout::PayoutDevice, transport::tokio_transport::TransportError};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn writes_are_batched_and_flushed() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(3, Category::Payout, ChecksumType::Crc8);
+        let hopper = PayoutDevice::new(device, tx);
+
+        let written = tokio::spawn(async move {
+            let mut headers = Vec::new();
+            while let Some(message) = rx.recv().await {
+                headers.push(message.header);
+                let response = if headers.len() == 1 {
+                    Err(TransportError::Timeout)
+                } else {
+                    Ok(vec![1, 0, 3, 0, 0])
+                };
+                message.respond_to.send(response).expect("should respond");
+            }
+            headers
+        });
+
+        let persistence =
+            EepromPersistence::new(hopper).with_write_interval(Duration::from_secs(3600));
+        assert_eq!(
+            persistence.persist_if_due().await,
+            Ok(EepromWrites::default())
+        );
+
+        persistence.mark_counters_changed();
+        assert!(persistence.persist_if_due().await.is_err());
+        assert!(persistence.is_due(), "failed write should be retried");
+        assert_eq!(
+            persistence.persist_if_due().await,
+            Ok(EepromWrites {
+                configuration: false,
+                counters: true
+            })
+        );
+        assert!(persistence.counters_persisted_at().is_some());
+
+        persistence.clone().mark_configuration_changed();
+        persistence.mark_counters_changed();
+        assert!(!persistence.is_due(), "interval should not have elapsed");
+        assert_eq!(
+            persistence.persist_if_due().await,
+            Ok(EepromWrites::default())
+        );
+        assert_eq!(
+            persistence.flush().await,
+            Ok(EepromWrites {
+                configuration: true,
+                counters: true
+            })
+        );
+        assert!(!persistence.is_dirty());
+
+        drop(persistence);
+        assert_eq!(
+            written.await.expect("responder should finish"),
+            vec![
+                Header::CountersToEEPROM,
+                Header::CountersToEEPROM,
+                Header::ConfigurationToEEPROM,
+                Header::CountersToEEPROM,
+            ]
+        );
+    }
+}
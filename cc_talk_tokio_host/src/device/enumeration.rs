@@ -0,0 +1,301 @@
+use std::fmt;
+
+use cc_talk_core::cc_talk::{Address, BusAddress, Category, ChecksumType, Device, SerialCode};
+use tokio::sync::mpsc;
+use tracing::{debug, info, instrument, trace, warn};
+
+use crate::transport::tokio_transport::TransportMessage;
+
+use super::base::DeviceCommon;
+
+/// A device which answered during [`enumerate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumeratedDevice {
+    pub address: u8,
+    pub category: Category,
+    /// `None` if the device did not answer the serial number request.
+    pub serial: Option<SerialCode>,
+}
+
+/// Suggested fix for a [`DuplicateSerial`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRemediation {
+    /// Address of the device which should be moved.
+    pub address: u8,
+    /// Free address in the default range of the device category, `None` if the range is full.
+    ///
+    /// Apply it with [`DeviceCommon::change_address`], then enumerate the bus again.
+    pub suggested_address: Option<BusAddress>,
+}
+
+impl fmt::Display for AddressRemediation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.suggested_address {
+            Some(suggested) => write!(
+                f,
+                "move the device at address {} to address {} with AddressChange",
+                self.address,
+                suggested.get()
+            ),
+            None => write!(
+                f,
+                "no free address left for the device at address {}, disconnect it or check its address selector",
+                self.address
+            ),
+        }
+    }
+}
+
+/// The same serial number answered at several addresses of the bus.
+///
+/// Either a single device answers at several addresses, or devices share their serial number,
+/// in both cases the bus is mis-configured. Devices are only compared within a category.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DuplicateSerial {
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "super::topology::ser::debug")
+    )]
+    pub category: Category,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "super::topology::ser::display")
+    )]
+    pub serial: SerialCode,
+    /// Addresses which answered with the serial number, in ascending order.
+    pub addresses: Vec<u8>,
+    /// One remediation for each address but the first.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub remediations: Vec<AddressRemediation>,
+}
+
+/// Result of [`enumerate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BusEnumeration {
+    /// Devices which answered, in ascending address order.
+    pub devices: Vec<EnumeratedDevice>,
+    pub duplicates: Vec<DuplicateSerial>,
+}
+
+impl BusEnumeration {
+    /// Builds the enumeration result and detects the duplicate serial numbers.
+    #[must_use]
+    pub fn new(mut devices: Vec<EnumeratedDevice>) -> Self {
+        devices.sort_by_key(|device| device.address);
+        let duplicates = find_duplicates(&devices);
+        Self {
+            devices,
+            duplicates,
+        }
+    }
+
+    /// Returns the device which answered at the given address.
+    #[must_use]
+    pub fn device(&self, address: u8) -> Option<&EnumeratedDevice> {
+        self.devices.iter().find(|device| device.address == address)
+    }
+
+    /// Returns `true` if the bus is mis-configured, see [`DuplicateSerial`].
+    #[must_use]
+    pub fn has_duplicates(&self) -> bool {
+        !self.duplicates.is_empty()
+    }
+}
+
+/// Device used to probe an address, its category is unknown until it answers.
+pub(super) struct Probe {
+    device: Device,
+    sender: mpsc::Sender<TransportMessage>,
+}
+
+impl Probe {
+    pub(super) fn new(
+        address: u8,
+        checksum_type: ChecksumType,
+        sender: &mpsc::Sender<TransportMessage>,
+    ) -> Self {
+        Self {
+            device: Device::new(address, Category::Unknown, checksum_type),
+            sender: sender.clone(),
+        }
+    }
+}
+
+impl DeviceCommon for Probe {
+    fn get_device(&self) -> &Device {
+        &self.device
+    }
+
+    fn get_sender(&self) -> &mpsc::Sender<TransportMessage> {
+        &self.sender
+    }
+}
+
+/// Polls every address and reads the category and serial number of the devices which answer.
+///
+/// Each duplicate serial number is logged as a warning with its remediation.
+#[instrument(skip(sender, addresses), level = "debug")]
+pub async fn enumerate(
+    sender: &mpsc::Sender<TransportMessage>,
+    addresses: impl IntoIterator<Item = u8>,
+    checksum_type: ChecksumType,
+) -> BusEnumeration {
+    let mut devices = Vec::new();
+    for address in addresses {
+        let probe = Probe::new(address, checksum_type, sender);
+        if probe.simple_poll().await.is_err() {
+            trace!(address, "no device answered");
+            continue;
+        }
+        let category = probe.get_category().await.unwrap_or(Category::Unknown);
+        let serial = match probe.get_serial_number().await {
+            Ok(serial) => Some(serial),
+            Err(error) => {
+                debug!(address, error = %error, "unable to read serial number");
+                None
+            }
+        };
+        debug!(address, category = ?category, serial = ?serial, "device found");
+        devices.push(EnumeratedDevice {
+            address,
+            category,
+            serial,
+        });
+    }
+
+    let enumeration = BusEnumeration::new(devices);
+    for duplicate in &enumeration.duplicates {
+        for remediation in &duplicate.remediations {
+            warn!(
+                serial = %duplicate.serial,
+                category = ?duplicate.category,
+                addresses = ?duplicate.addresses,
+                address = remediation.address,
+                suggested_address = remediation.suggested_address.map(|address| address.get()),
+                "same serial number answered at several addresses, {}",
+                remediation
+            );
+        }
+    }
+    info!(
+        devices = enumeration.devices.len(),
+        duplicates = enumeration.duplicates.len(),
+        "bus enumerated"
+    );
+    enumeration
+}
+
+fn find_duplicates(devices: &[EnumeratedDevice]) -> Vec<DuplicateSerial> {
+    let mut duplicates: Vec<DuplicateSerial> = Vec::new();
+    for (idx, device) in devices.iter().enumerate() {
+        let Some(serial) = &device.serial else {
+            continue;
+        };
+        if duplicates
+            .iter()
+            .any(|duplicate| duplicate.addresses.contains(&device.address))
+        {
+            continue;
+        }
+        let addresses: Vec<u8> = devices[idx..]
+            .iter()
+            .filter(|other| {
+                other.category == device.category && other.serial.as_ref() == Some(serial)
+            })
+            .map(|other| other.address)
+            .collect();
+        if addresses.len() > 1 {
+            duplicates.push(DuplicateSerial {
+                category: device.category.clone(),
+                serial: serial.clone(),
+                addresses,
+                remediations: Vec::new(),
+            });
+        }
+    }
+
+    let mut used: Vec<u8> = devices.iter().map(|device| device.address).collect();
+    for duplicate in &mut duplicates {
+        for &address in &duplicate.addresses[1..] {
+            let suggested_address = free_address(&duplicate.category, &used);
+            if let Some(suggested) = suggested_address {
+                used.push(suggested.get());
+            }
+            duplicate.remediations.push(AddressRemediation {
+                address,
+                suggested_address,
+            });
+        }
+    }
+    duplicates
+}
+
+/// Returns the first address of the category default range which is not used.
+pub(super) fn free_address(category: &Category, used: &[u8]) -> Option<BusAddress> {
+    let candidates = match category.default_address() {
+        Address::Single(address) => address..=address,
+        Address::SingleAndRange(_, range) => range,
+    };
+    candidates
+        .filter(|address| !used.contains(address))
+        .find_map(|address| BusAddress::peripheral(address).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(address: u8, category: Category, serial: Option<SerialCode>) -> EnumeratedDevice {
+        EnumeratedDevice {
+            address,
+            category,
+            serial,
+        }
+    }
+
+    #[test]
+    fn duplicate_serials_get_a_free_address() {
+        let serial = SerialCode::new(1, 2, 3);
+        let enumeration = BusEnumeration::new(vec![
+            device(4, Category::Payout, Some(serial.clone())),
+            device(3, Category::Payout, Some(serial.clone())),
+            device(5, Category::Payout, Some(serial.clone())),
+            device(2, Category::CoinAcceptor, Some(serial.clone())),
+            device(6, Category::Payout, None),
+        ]);
+
+        assert!(enumeration.has_duplicates());
+        assert_eq!(enumeration.duplicates.len(), 1);
+        let duplicate = &enumeration.duplicates[0];
+        assert_eq!(duplicate.category, Category::Payout);
+        assert_eq!(duplicate.addresses, vec![3, 4, 5]);
+        assert_eq!(
+            duplicate.remediations,
+            vec![
+                AddressRemediation {
+                    address: 4,
+                    suggested_address: Some(BusAddress::new(7)),
+                },
+                AddressRemediation {
+                    address: 5,
+                    suggested_address: Some(BusAddress::new(8)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn single_address_categories_have_no_free_address() {
+        let serial = SerialCode::new(9, 9, 9);
+        let enumeration = BusEnumeration::new(vec![
+            device(55, Category::Changer, Some(serial.clone())),
+            device(56, Category::Changer, Some(serial)),
+        ]);
+
+        let remediation = enumeration.duplicates[0].remediations[0];
+        assert_eq!(remediation.address, 56);
+        assert_eq!(remediation.suggested_address, None);
+        assert!(remediation.to_string().contains("no free address"));
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex};
+
+use cc_talk_core::cc_talk::{ChallengeRng, CoinAcceptorPollResult, MonetaryId, PeripheralCipher};
+use cc_talk_host::{
+    command::Command,
+    device::device_commands::{ReadEncryptedEventsCommand, RequestEncryptedMonetaryIdCommand},
+};
+use tracing::trace;
+
+use super::base::{CommandError, DeviceCommon, DeviceResult};
+use crate::transport::timestamp::Stamped;
+
+/// Cipher and challenge source of a coin acceptor or bill validator reading its events with
+/// `ReadEncryptedEvents`, header 112, and its coins or bills with `RequestEncryptedMonetaryId`,
+/// header 108.
+///
+/// Clones share the same cipher and RNG.
+#[derive(Clone)]
+pub struct EventEncryption {
+    cipher: Arc<dyn PeripheralCipher + Send + Sync>,
+    rng: Arc<Mutex<Box<dyn ChallengeRng + Send>>>,
+}
+
+impl EventEncryption {
+    pub fn new(
+        cipher: impl PeripheralCipher + Send + Sync + 'static,
+        rng: impl ChallengeRng + Send + 'static,
+    ) -> Self {
+        Self {
+            cipher: Arc::new(cipher),
+            rng: Arc::new(Mutex::new(Box::new(rng))),
+        }
+    }
+
+    /// Reads and decrypts the event buffer of `device`, returns it laid out like a
+    /// `ReadBufferedCreditOrErrorCodes` or `ReadBufferedBillEvents` reply with the time the
+    /// transport read it.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the exchange fails, or if the reply does not decrypt or does not answer the
+    /// challenge.
+    pub async fn read_events<D: DeviceCommon + Sync>(
+        &self,
+        device: &D,
+    ) -> DeviceResult<Stamped<Vec<u8>>> {
+        let mut challenge = [0u8; 1];
+        self.rng
+            .lock()
+            .expect("should not be poisoned")
+            .fill_bytes(&mut challenge);
+        trace!("reading encrypted events");
+        let command = ReadEncryptedEventsCommand::<_, CoinAcceptorPollResult>::with_challenge(
+            self.cipher.as_ref(),
+            challenge[0],
+            0,
+        );
+        let reply = device.send_stamped_command(command).await?;
+        let events = ReadEncryptedEventsCommand::<_, CoinAcceptorPollResult>::with_challenge(
+            self.cipher.as_ref(),
+            challenge[0],
+            0,
+        )
+        .decrypt(reply.value.get_data()?)
+        .map_err(CommandError::from)?;
+        Ok(Stamped::new(events.buffer().to_vec(), reply.received_at))
+    }
+
+    /// Requests and decrypts the coin or bill programmed at `position` of `device`, `None` if
+    /// the position is blank.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the exchange fails, or if the reply does not decrypt or does not answer the
+    /// position and challenge.
+    pub async fn request_monetary_id<D: DeviceCommon + Sync>(
+        &self,
+        device: &D,
+        position: u8,
+    ) -> DeviceResult<Option<MonetaryId>> {
+        let mut challenge = [0u8; 1];
+        self.rng
+            .lock()
+            .expect("should not be poisoned")
+            .fill_bytes(&mut challenge);
+        trace!(position, "requesting encrypted monetary id");
+        let command = RequestEncryptedMonetaryIdCommand::with_challenge(
+            self.cipher.as_ref(),
+            position,
+            challenge[0],
+        );
+        let response_packet = device.send_command(command).await?;
+        let id = RequestEncryptedMonetaryIdCommand::with_challenge(
+            self.cipher.as_ref(),
+            position,
+            challenge[0],
+        )
+        .parse_response(response_packet.get_data()?)
+        .map_err(CommandError::from)?;
+        Ok(id)
+    }
+}
+
+impl core::fmt::Debug for EventEncryption {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EventEncryption")
+            .field("cipher", &self.cipher)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,453 @@
+use std::fmt;
+
+use thiserror::Error;
+use tracing::{info, instrument, warn};
+
+use super::{
+    base::{CommandError, DeviceResult},
+    payout::PayoutDevice,
+};
+
+/// Payout counter written and read back by the [`FloatManager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatSetting {
+    Float,
+    Capacity,
+    AbsoluteCount,
+}
+
+impl fmt::Display for FloatSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FloatSetting::Float => write!(f, "payout float"),
+            FloatSetting::Capacity => write!(f, "payout capacity"),
+            FloatSetting::AbsoluteCount => write!(f, "payout absolute count"),
+        }
+    }
+}
+
+/// Violation of `float <= capacity <= hardware maximum` or of `absolute count <= capacity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum FloatIssue {
+    #[error("float of {float} coins exceeds the capacity of {capacity} coins")]
+    FloatAboveCapacity { float: u16, capacity: u16 },
+    #[error("capacity of {capacity} coins exceeds the hardware maximum of {maximum} coins")]
+    CapacityAboveMaximum { capacity: u16, maximum: u16 },
+    /// The device counts more coins than fit in it, its counter was most likely mis-set.
+    #[error("absolute count of {count} coins exceeds the capacity of {capacity} coins")]
+    CountAboveCapacity { count: u16, capacity: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FloatError {
+    #[error("{0}")]
+    Inconsistent(FloatIssue),
+    #[error("device rejected {setting} of {value} coins")]
+    Rejected { setting: FloatSetting, value: u16 },
+    #[error("{setting} read back as {read} coins after writing {written} coins")]
+    ReadBackMismatch {
+        setting: FloatSetting,
+        written: u16,
+        read: u16,
+    },
+    #[error(transparent)]
+    Command(#[from] CommandError),
+}
+
+/// Payout counters of a device, see [`FloatManager::levels`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatLevels {
+    pub float: u16,
+    pub capacity: u16,
+    pub absolute_count: u16,
+}
+
+impl FloatLevels {
+    /// Returns every consistency rule the levels violate.
+    #[must_use]
+    pub fn issues(&self, hardware_maximum: u16) -> Vec<FloatIssue> {
+        let mut issues = Vec::new();
+        if self.float > self.capacity {
+            issues.push(FloatIssue::FloatAboveCapacity {
+                float: self.float,
+                capacity: self.capacity,
+            });
+        }
+        if self.capacity > hardware_maximum {
+            issues.push(FloatIssue::CapacityAboveMaximum {
+                capacity: self.capacity,
+                maximum: hardware_maximum,
+            });
+        }
+        if self.absolute_count > self.capacity {
+            issues.push(FloatIssue::CountAboveCapacity {
+                count: self.absolute_count,
+                capacity: self.capacity,
+            });
+        }
+        issues
+    }
+}
+
+/// Result of [`FloatManager::reconcile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconciliation {
+    Consistent,
+    /// The absolute count exceeded the capacity.
+    ///
+    /// `corrected` is `true` if the count was set to the capacity, see
+    /// [`FloatManager::with_auto_correction`].
+    CountAboveCapacity {
+        count: u16,
+        capacity: u16,
+        corrected: bool,
+    },
+}
+
+/// Keeps the payout float, capacity and absolute count of a device consistent.
+///
+/// Every write is validated against `float <= capacity <= hardware maximum` before it is
+/// sent, then read back, since devices may silently clamp the values they store.
+#[derive(Debug, Clone)]
+pub struct FloatManager {
+    hopper: PayoutDevice,
+    hopper_number: Option<u8>,
+    hardware_maximum: u16,
+    auto_correction: bool,
+}
+
+impl FloatManager {
+    /// Manages the counters of `hopper`, which holds at most `hardware_maximum` coins.
+    #[must_use]
+    pub fn new(hopper: PayoutDevice, hardware_maximum: u16) -> Self {
+        Self {
+            hopper,
+            hopper_number: None,
+            hardware_maximum,
+            auto_correction: false,
+        }
+    }
+
+    /// Selects the hopper of a multi-hopper device.
+    #[must_use]
+    pub const fn with_hopper_number(mut self, hopper_number: u8) -> Self {
+        self.hopper_number = Some(hopper_number);
+        self
+    }
+
+    /// Lets [`reconcile`](Self::reconcile) set an absolute count exceeding the capacity to
+    /// the capacity.
+    #[must_use]
+    pub const fn with_auto_correction(mut self) -> Self {
+        self.auto_correction = true;
+        self
+    }
+
+    /// Reads the payout float, capacity and absolute count.
+    #[instrument(skip(self), fields(address = self.hopper.device.address()), level = "debug")]
+    pub async fn levels(&self) -> DeviceResult<FloatLevels> {
+        Ok(FloatLevels {
+            float: self.read(FloatSetting::Float).await?,
+            capacity: self.read(FloatSetting::Capacity).await?,
+            absolute_count: self.read(FloatSetting::AbsoluteCount).await?,
+        })
+    }
+
+    /// Sets the number of coins kept in the hopper.
+    ///
+    /// Fails without writing if the float exceeds the current capacity.
+    #[instrument(skip(self), fields(address = self.hopper.device.address()), level = "debug")]
+    pub async fn set_float(&self, float: u16) -> Result<(), FloatError> {
+        let capacity = self.read(FloatSetting::Capacity).await?;
+        if float > capacity {
+            return Err(FloatError::Inconsistent(FloatIssue::FloatAboveCapacity {
+                float,
+                capacity,
+            }));
+        }
+        self.write_verified(FloatSetting::Float, float).await
+    }
+
+    /// Sets the number of coins the hopper holds.
+    ///
+    /// Fails without writing if the capacity exceeds the hardware maximum or is below the
+    /// current float.
+    #[instrument(skip(self), fields(address = self.hopper.device.address()), level = "debug")]
+    pub async fn set_capacity(&self, capacity: u16) -> Result<(), FloatError> {
+        if capacity > self.hardware_maximum {
+            return Err(FloatError::Inconsistent(FloatIssue::CapacityAboveMaximum {
+                capacity,
+                maximum: self.hardware_maximum,
+            }));
+        }
+        let float = self.read(FloatSetting::Float).await?;
+        if float > capacity {
+            return Err(FloatError::Inconsistent(FloatIssue::FloatAboveCapacity {
+                float,
+                capacity,
+            }));
+        }
+        self.write_verified(FloatSetting::Capacity, capacity).await
+    }
+
+    /// Checks the absolute count against the capacity.
+    ///
+    /// A count above the capacity suggests a mis-set counter, it is logged as a warning and
+    /// corrected to the capacity when auto-correction is enabled.
+    #[instrument(skip(self), fields(address = self.hopper.device.address()), level = "debug")]
+    pub async fn reconcile(&self) -> Result<Reconciliation, FloatError> {
+        let capacity = self.read(FloatSetting::Capacity).await?;
+        let count = self.read(FloatSetting::AbsoluteCount).await?;
+        if count <= capacity {
+            return Ok(Reconciliation::Consistent);
+        }
+        warn!(
+            count,
+            capacity, "absolute count exceeds the capacity, the counter is probably mis-set"
+        );
+        if self.auto_correction {
+            self.write_verified(FloatSetting::AbsoluteCount, capacity)
+                .await?;
+            info!(count, capacity, "absolute count corrected to the capacity");
+        }
+        Ok(Reconciliation::CountAboveCapacity {
+            count,
+            capacity,
+            corrected: self.auto_correction,
+        })
+    }
+
+    async fn read(&self, setting: FloatSetting) -> DeviceResult<u16> {
+        match setting {
+            FloatSetting::Float => self.hopper.get_payout_float(self.hopper_number).await,
+            FloatSetting::Capacity => self.hopper.get_payout_capacity(self.hopper_number).await,
+            FloatSetting::AbsoluteCount => {
+                self.hopper
+                    .get_payout_absolute_count(self.hopper_number)
+                    .await
+            }
+        }
+    }
+
+    async fn write_verified(&self, setting: FloatSetting, value: u16) -> Result<(), FloatError> {
+        let result = match setting {
+            FloatSetting::Float => {
+                self.hopper
+                    .set_payout_float(self.hopper_number, value)
+                    .await
+            }
+            FloatSetting::Capacity => {
+                self.hopper
+                    .set_payout_capacity(self.hopper_number, value)
+                    .await
+            }
+            FloatSetting::AbsoluteCount => {
+                self.hopper
+                    .set_payout_absolute_count(self.hopper_number, value)
+                    .await
+            }
+        };
+        match result {
+            Err(error) if error.is_nack() => {
+                warn!(%setting, value, "device rejected the value");
+                return Err(FloatError::Rejected { setting, value });
+            }
+            result => result?,
+        }
+
+        let read = self.read(setting).await?;
+        if read != value {
+            warn!(%setting, written = value, read, "device stored a different value");
+            return Err(FloatError::ReadBackMismatch {
+                setting,
+                written: value,
+                read,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Device, Header};
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::transport::tokio_transport::{TransportError, TransportMessage};
+
+    /// Emulates a hopper storing float, capacity and count, clamping the float to 500 coins
+    /// and rejecting capacities above 1000 coins.
+    fn spawn_hopper(
+        mut rx: mpsc::Receiver<TransportMessage>,
+        levels: Arc<Mutex<FloatLevels>>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let mut levels = levels.lock().expect("should not be poisoned");
+                let written = match message.data.as_slice() {
+                    [lsb, msb] => Some(u16::from_le_bytes([*lsb, *msb])),
+                    _ => None,
+                };
+                let data = match (message.header, written) {
+                    (Header::RequestPayoutFloat, None) => Ok(levels.float.to_le_bytes().to_vec()),
+                    (Header::RequestPayoutCapacity, None) => {
+                        Ok(levels.capacity.to_le_bytes().to_vec())
+                    }
+                    (Header::RequestPayoutAbsoluteCount, None) => {
+                        Ok(levels.absolute_count.to_le_bytes().to_vec())
+                    }
+                    (Header::ModifyPayoutFloat, Some(float)) => {
+                        levels.float = float.min(500);
+                        Ok(vec![])
+                    }
+                    (Header::ModifyPayoutCapacity, Some(capacity)) if capacity <= 1000 => {
+                        levels.capacity = capacity;
+                        Ok(vec![])
+                    }
+                    (Header::ModifyPayoutAbsoluteCount, Some(count)) => {
+                        levels.absolute_count = count;
+                        Ok(vec![])
+                    }
+                    _ => Err(TransportError::Nack),
+                };
+                drop(levels);
+                let reply = data.map(|data| {
+                    let mut reply = vec![1, data.len() as u8, message.address, 0];
+                    reply.extend(data);
+                    reply.push(0);
+                    reply
+                });
+                message.respond_to.send(reply).expect("should respond");
+            }
+        })
+    }
+
+    fn manager(
+        levels: FloatLevels,
+    ) -> (
+        FloatManager,
+        Arc<Mutex<FloatLevels>>,
+        tokio::task::JoinHandle<()>,
+    ) {
+        let (tx, rx) = mpsc::channel(1);
+        let levels = Arc::new(Mutex::new(levels));
+        let bus = spawn_hopper(rx, levels.clone());
+        let hopper = PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx);
+        (FloatManager::new(hopper, 2000), levels, bus)
+    }
+
+    #[test]
+    fn levels_report_every_issue() {
+        let levels = FloatLevels {
+            float: 300,
+            capacity: 200,
+            absolute_count: 250,
+        };
+        assert_eq!(
+            levels.issues(100),
+            vec![
+                FloatIssue::FloatAboveCapacity {
+                    float: 300,
+                    capacity: 200
+                },
+                FloatIssue::CapacityAboveMaximum {
+                    capacity: 200,
+                    maximum: 100
+                },
+                FloatIssue::CountAboveCapacity {
+                    count: 250,
+                    capacity: 200
+                },
+            ]
+        );
+        assert_eq!(levels.issues(2000).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn writes_are_validated_and_read_back() {
+        let (manager, levels, _bus) = manager(FloatLevels {
+            float: 100,
+            capacity: 800,
+            absolute_count: 400,
+        });
+
+        assert_eq!(
+            manager.set_float(900).await,
+            Err(FloatError::Inconsistent(FloatIssue::FloatAboveCapacity {
+                float: 900,
+                capacity: 800
+            }))
+        );
+        assert_eq!(
+            manager.set_capacity(2500).await,
+            Err(FloatError::Inconsistent(FloatIssue::CapacityAboveMaximum {
+                capacity: 2500,
+                maximum: 2000
+            }))
+        );
+        assert_eq!(
+            manager.set_capacity(1500).await,
+            Err(FloatError::Rejected {
+                setting: FloatSetting::Capacity,
+                value: 1500
+            })
+        );
+        assert_eq!(
+            manager.set_float(600).await,
+            Err(FloatError::ReadBackMismatch {
+                setting: FloatSetting::Float,
+                written: 600,
+                read: 500
+            })
+        );
+
+        manager.set_capacity(1000).await.expect("should set");
+        manager.set_float(250).await.expect("should set");
+        let expected = FloatLevels {
+            float: 250,
+            capacity: 1000,
+            absolute_count: 400,
+        };
+        assert_eq!(manager.levels().await, Ok(expected));
+        assert_eq!(*levels.lock().expect("should not be poisoned"), expected);
+    }
+
+    #[tokio::test]
+    async fn counts_above_capacity_are_corrected_on_request() {
+        let (manager, levels, _bus) = manager(FloatLevels {
+            float: 100,
+            capacity: 800,
+            absolute_count: 1200,
+        });
+
+        assert_eq!(
+            manager.reconcile().await,
+            Ok(Reconciliation::CountAboveCapacity {
+                count: 1200,
+                capacity: 800,
+                corrected: false
+            })
+        );
+        assert_eq!(
+            levels
+                .lock()
+                .expect("should not be poisoned")
+                .absolute_count,
+            1200
+        );
+
+        let manager = manager.with_auto_correction();
+        assert_eq!(
+            manager.reconcile().await,
+            Ok(Reconciliation::CountAboveCapacity {
+                count: 1200,
+                capacity: 800,
+                corrected: true
+            })
+        );
+        assert_eq!(manager.reconcile().await, Ok(Reconciliation::Consistent));
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::{debug, info, instrument, warn};
+
+use super::base::{DeviceCommon, DeviceResult};
+
+/// Default ratio between the current and the baseline p95 latency above which a
+/// [`LatencyDrift`] is reported.
+pub const DEFAULT_DRIFT_FACTOR: f64 = 2.0;
+
+/// Round-trip latency of `SimplePoll` measured by [`measure_latency`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LatencyProfile {
+    /// Number of successful round trips the profile was computed from.
+    pub samples: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl LatencyProfile {
+    /// Computes the nearest-rank percentiles of the samples, `None` if there are none.
+    #[must_use]
+    pub fn from_samples(mut samples: Vec<Duration>) -> Option<Self> {
+        let max = *samples.iter().max()?;
+        samples.sort_unstable();
+        Some(Self {
+            samples: samples.len(),
+            p50: percentile(&samples, 50),
+            p95: percentile(&samples, 95),
+            max,
+        })
+    }
+
+    /// Time a single exchange with the device should be given on the bus.
+    ///
+    /// This is the p95 latency, the scheduler uses it as the minimum gap of the device, see
+    /// [`SchedulerConfig::with_latency_profiles`](crate::transport::scheduler::SchedulerConfig::with_latency_profiles).
+    /// Use it to size polling intervals as well, so that an exchange rarely overruns its slot.
+    #[must_use]
+    pub const fn slot(&self) -> Duration {
+        self.p95
+    }
+}
+
+/// Nearest-rank percentile of sorted, non-empty samples.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Sends `SimplePoll` `iterations` times and profiles the round-trip latency.
+///
+/// The latency includes the time the command waits in the transport queue, calibrate while
+/// the bus is otherwise idle. Stops at the first failed poll, the profile of the polls which
+/// succeeded until then is returned.
+///
+/// # Errors
+///
+/// Errors if the first poll fails.
+#[instrument(skip(device), fields(address = device.get_device().address()), level = "debug")]
+pub async fn measure_latency(
+    device: &impl DeviceCommon,
+    iterations: usize,
+) -> DeviceResult<Option<LatencyProfile>> {
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let started = Instant::now();
+        if let Err(error) = device.simple_poll().await {
+            if samples.is_empty() {
+                return Err(error);
+            }
+            warn!(
+                error = %error,
+                samples = samples.len(),
+                "latency measurement interrupted, profiling the polls which succeeded"
+            );
+            break;
+        }
+        samples.push(started.elapsed());
+    }
+    let profile = LatencyProfile::from_samples(samples);
+    debug!(profile = ?profile, "latency measured");
+    Ok(profile)
+}
+
+/// Latency of a device which drifted away from its baseline, see [`LatencyProfiles::record`].
+///
+/// A growing latency usually points at degrading wiring or connectors, causing retries on
+/// the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LatencyDrift {
+    pub address: u8,
+    pub baseline: LatencyProfile,
+    pub current: LatencyProfile,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeviceLatency {
+    baseline: LatencyProfile,
+    current: LatencyProfile,
+}
+
+/// Shared latency profiles, keyed by device address.
+///
+/// The first profile recorded for an address is its baseline, later calibrations are
+/// compared against it to detect drift. Clones share the same profiles.
+#[derive(Debug, Clone)]
+pub struct LatencyProfiles {
+    devices: Arc<Mutex<HashMap<u8, DeviceLatency>>>,
+    drift_factor: f64,
+}
+
+impl Default for LatencyProfiles {
+    fn default() -> Self {
+        Self {
+            devices: Arc::default(),
+            drift_factor: DEFAULT_DRIFT_FACTOR,
+        }
+    }
+}
+
+impl LatencyProfiles {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the ratio between the current and the baseline p95 above which a drift is
+    /// reported, defaults to [`DEFAULT_DRIFT_FACTOR`].
+    #[must_use]
+    pub const fn with_drift_factor(mut self, drift_factor: f64) -> Self {
+        self.drift_factor = drift_factor;
+        self
+    }
+
+    /// Returns the latest profile recorded for the address.
+    #[must_use]
+    pub fn current(&self, address: u8) -> Option<LatencyProfile> {
+        self.devices
+            .lock()
+            .expect("should not be poisoned")
+            .get(&address)
+            .map(|latency| latency.current)
+    }
+
+    /// Returns the first profile recorded for the address.
+    #[must_use]
+    pub fn baseline(&self, address: u8) -> Option<LatencyProfile> {
+        self.devices
+            .lock()
+            .expect("should not be poisoned")
+            .get(&address)
+            .map(|latency| latency.baseline)
+    }
+
+    /// Records a new profile and compares it against the baseline of the address.
+    ///
+    /// Returns the drift, also logged as a warning, if the p95 latency exceeds the baseline
+    /// by the drift factor.
+    pub fn record(&self, address: u8, profile: LatencyProfile) -> Option<LatencyDrift> {
+        let mut devices = self.devices.lock().expect("should not be poisoned");
+        let latency = devices.entry(address).or_insert(DeviceLatency {
+            baseline: profile,
+            current: profile,
+        });
+        latency.current = profile;
+        let baseline = latency.baseline;
+        drop(devices);
+
+        if profile.p95 > baseline.p95.mul_f64(self.drift_factor) {
+            warn!(
+                address,
+                baseline_p95 = ?baseline.p95,
+                current_p95 = ?profile.p95,
+                "response latency drifted, check the wiring of the device"
+            );
+            return Some(LatencyDrift {
+                address,
+                baseline,
+                current: profile,
+            });
+        }
+        info!(address, p50 = ?profile.p50, p95 = ?profile.p95, "latency profile recorded");
+        None
+    }
+
+    /// Drops the profiles of the address, the next recorded profile becomes the baseline.
+    ///
+    /// Call this after the wiring has been repaired.
+    pub fn reset_baseline(&self, address: u8) {
+        self.devices
+            .lock()
+            .expect("should not be poisoned")
+            .remove(&address);
+    }
+
+    /// Measures the latency of the device and records it, see [`Self::record`].
+    ///
+    /// Returns `Ok(None)` if the profile is within the drift factor of the baseline or if
+    /// `iterations` is zero.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the first poll fails, see [`measure_latency`].
+    pub async fn calibrate(
+        &self,
+        device: &impl DeviceCommon,
+        iterations: usize,
+    ) -> DeviceResult<Option<LatencyDrift>> {
+        let address = device.get_device().address();
+        Ok(measure_latency(device, iterations)
+            .await?
+            .and_then(|profile| self.record(address, profile)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn millis(samples: impl IntoIterator<Item = u64>) -> Vec<Duration> {
+        samples.into_iter().map(Duration::from_millis).collect()
+    }
+
+    fn profile(p95: u64) -> LatencyProfile {
+        LatencyProfile::from_samples(millis([p95])).expect("should have samples")
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let profile = LatencyProfile::from_samples(millis((1..=20).rev())).expect("should profile");
+        assert_eq!(profile.samples, 20);
+        assert_eq!(profile.p50, Duration::from_millis(10));
+        assert_eq!(profile.p95, Duration::from_millis(19));
+        assert_eq!(profile.max, Duration::from_millis(20));
+        assert_eq!(profile.slot(), profile.p95);
+
+        let single = LatencyProfile::from_samples(millis([7])).expect("should profile");
+        assert_eq!(
+            (single.p50, single.p95),
+            (Duration::from_millis(7), Duration::from_millis(7))
+        );
+        assert_eq!(LatencyProfile::from_samples(Vec::new()), None);
+    }
+
+    #[test]
+    fn drift_is_reported_against_the_first_profile() {
+        let profiles = LatencyProfiles::new();
+        let clone = profiles.clone();
+
+        assert_eq!(profiles.record(3, profile(10)), None);
+        assert_eq!(profiles.record(3, profile(20)), None);
+        let drift = profiles.record(3, profile(21)).expect("should drift");
+        assert_eq!(drift.baseline, profile(10));
+        assert_eq!(clone.current(3), Some(profile(21)));
+
+        clone.reset_baseline(3);
+        assert_eq!(profiles.record(3, profile(21)), None);
+        assert_eq!(profiles.baseline(3), Some(profile(21)));
+    }
+
+    #[tokio::test]
+    async fn failed_poll_keeps_the_samples_measured_before() {
+        use cc_talk_core::cc_talk::{Category, ChecksumType, Device};
+        use tokio::sync::mpsc;
+
+        use crate::{
+            device::payout::PayoutDevice,
+            transport::tokio_transport::{TransportError, TransportMessage},
+        };
+
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(1);
+        tokio::spawn(async move {
+            let mut polls = 0;
+            while let Some(message) = rx.recv().await {
+                polls += 1;
+                let reply = if polls <= 3 {
+                    Ok(vec![1, 0, 3, 0, 0])
+                } else {
+                    Err(TransportError::Timeout)
+                };
+                message.respond_to.send(reply).expect("should respond");
+            }
+        });
+        let hopper = PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx);
+
+        let profile = measure_latency(&hopper, 10)
+            .await
+            .expect("should keep the samples")
+            .expect("should profile");
+        assert_eq!(profile.samples, 3);
+        assert!(measure_latency(&hopper, 10).await.is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Combined machine facade for the common kiosk topology.
+//!
+//! A [`Machine`] aggregates a [`CurrencyAcceptorPool`] and a [`PayoutPool`] behind a balance:
+//! credits increase it and payouts decrease it by what was actually dispensed.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let machine = Machine::builder()
+//!     .coin_acceptor(coin_validator)
+//!     .bill_validator(bill_validator)
+//!     .add_hopper(euro_hopper, 100)
+//!     .add_hopper(cent_hopper, 20)
+//!     .build_and_initialize()
+//!     .await?;
+//!
+//! machine.enable().await?;
+//! while machine.balance() < 500 {
+//!     machine.insert_credit_events().await;
+//!     tokio::time::sleep(Duration::from_millis(100)).await;
+//! }
+//! machine.disable().await?;
+//! machine.payout(machine.balance() - 500).await?;
+//! ```
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use thiserror::Error;
+use tracing::{info, instrument, warn};
+
+use super::{
+    bill_validator::BillValidator,
+    coin_validator::CoinValidator,
+    currency_acceptor_pool::{
+        BillRoutingMode, CurrencyAcceptorPool, CurrencyAcceptorPoolBuilder, PoolError,
+        PoolPollResult,
+    },
+    payout::PayoutDevice,
+    payout_pool::{DispenseProgress, PayoutPool, PayoutPoolBuilder, PayoutPoolError},
+};
+
+/// Errors that can occur when operating a [`Machine`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MachineError {
+    #[error("acceptor error: {0}")]
+    Acceptor(#[from] PoolError),
+
+    #[error("payout error: {0}")]
+    Payout(#[from] PayoutPoolError),
+
+    /// The payout exceeds the balance, nothing was dispensed.
+    #[error("insufficient balance: requested {requested}, balance {balance}")]
+    InsufficientBalance { requested: u32, balance: u32 },
+}
+
+/// Result type for machine operations.
+pub type MachineResult<T> = Result<T, MachineError>;
+
+/// Builder for a [`Machine`].
+#[derive(Debug, Default)]
+pub struct MachineBuilder {
+    acceptors: CurrencyAcceptorPoolBuilder,
+    payout: PayoutPoolBuilder,
+}
+
+impl MachineBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn coin_acceptor(mut self, coin_acceptor: CoinValidator) -> Self {
+        self.acceptors = self.acceptors.add_coin_validator(coin_acceptor);
+        self
+    }
+
+    /// Bills are stacked as soon as they are validated.
+    #[must_use]
+    pub fn bill_validator(mut self, bill_validator: BillValidator) -> Self {
+        self.acceptors = self
+            .acceptors
+            .add_bill_validator(bill_validator)
+            .with_bill_routing_mode(BillRoutingMode::AutoStack);
+        self
+    }
+
+    /// Adds a hopper with the value of its coin, in smallest currency units.
+    #[must_use]
+    pub fn add_hopper(mut self, hopper: PayoutDevice, value: u32) -> Self {
+        self.payout = self.payout.add_hopper(hopper, value);
+        self
+    }
+
+    /// Sets the polling interval of both the acceptors and the hoppers.
+    #[must_use]
+    pub fn with_polling_interval(mut self, interval: Duration) -> Self {
+        self.acceptors = self.acceptors.with_polling_interval(interval);
+        self.payout = self.payout.polling_interval(interval);
+        self
+    }
+
+    /// Builds the machine and initializes every device, the acceptors stay inhibited.
+    pub async fn build_and_initialize(self) -> MachineResult<Machine> {
+        let acceptors = self.acceptors.build_and_initialize().await?;
+        let payout = self.payout.build_and_initialize().await?;
+        info!(
+            acceptors = acceptors.device_count(),
+            hoppers = payout.hopper_count(),
+            "machine initialized"
+        );
+        Ok(Machine {
+            acceptors,
+            payout,
+            balance: Arc::default(),
+        })
+    }
+}
+
+/// One coin acceptor, one bill validator and any number of hoppers sharing a balance.
+///
+/// The underlying pools stay reachable through [`Machine::acceptors`] and
+/// [`Machine::payout_pool`] for anything the facade does not cover.
+#[derive(Debug, Clone)]
+pub struct Machine {
+    acceptors: CurrencyAcceptorPool,
+    payout: PayoutPool,
+    balance: Arc<Mutex<u32>>,
+}
+
+impl Machine {
+    #[must_use]
+    pub fn builder() -> MachineBuilder {
+        MachineBuilder::new()
+    }
+
+    #[must_use]
+    pub const fn acceptors(&self) -> &CurrencyAcceptorPool {
+        &self.acceptors
+    }
+
+    #[must_use]
+    pub const fn payout_pool(&self) -> &PayoutPool {
+        &self.payout
+    }
+
+    /// Returns the value credited and not paid out yet, in smallest currency units.
+    #[must_use]
+    pub fn balance(&self) -> u32 {
+        *self.balance.lock().expect("should not be poisoned")
+    }
+
+    /// Lets the acceptors accept currency.
+    pub async fn enable(&self) -> MachineResult<()> {
+        self.acceptors.enable().await?;
+        Ok(())
+    }
+
+    /// Inhibits the acceptors.
+    pub async fn disable(&self) -> MachineResult<()> {
+        self.acceptors.disable().await?;
+        Ok(())
+    }
+
+    /// Polls the acceptors once and adds their credits to the balance.
+    ///
+    /// Device errors are reported in the result without failing the poll.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn insert_credit_events(&self) -> PoolPollResult {
+        let result = self.acceptors.poll().await;
+        if result.total_received > 0 {
+            let mut balance = self.balance.lock().expect("should not be poisoned");
+            *balance = balance.saturating_add(result.total_received);
+            info!(
+                received = result.total_received,
+                balance = *balance,
+                "credits added to the balance"
+            );
+        }
+        result
+    }
+
+    /// Pays `amount` out of the balance.
+    ///
+    /// The amount is deducted from the balance before the payout starts and the value which was
+    /// not dispensed is refunded when the payout ends, also when it fails part way or when the
+    /// returned future is dropped, see
+    /// [`CancellationPolicy`](super::payout_pool::CancellationPolicy).
+    #[instrument(skip(self), level = "debug")]
+    pub async fn payout(&self, amount: u32) -> MachineResult<DispenseProgress> {
+        {
+            let mut balance = self.balance.lock().expect("should not be poisoned");
+            if amount > *balance {
+                return Err(MachineError::InsufficientBalance {
+                    requested: amount,
+                    balance: *balance,
+                });
+            }
+            *balance -= amount;
+        }
+        let balance = Arc::clone(&self.balance);
+        let result = self
+            .payout
+            .payout_settled(amount, move |progress| {
+                let mut balance = balance.lock().expect("should not be poisoned");
+                *balance = balance.saturating_add(progress.remaining);
+                if progress.remaining > 0 {
+                    warn!(
+                        amount,
+                        dispensed = progress.dispensed,
+                        balance = *balance,
+                        "payout incomplete"
+                    );
+                }
+            })
+            .await;
+        Ok(result?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Device, Header};
+    use tokio::sync::mpsc;
+
+    use crate::{
+        device::payout_pool::{HopperSelectionStrategy, PayoutOutcome},
+        transport::tokio_transport::TransportMessage,
+    };
+
+    use super::*;
+
+    /// Hopper at address 3 paying one coin per status poll.
+    fn spawn_hopper(mut rx: mpsc::Receiver<TransportMessage>) {
+        tokio::spawn(async move {
+            let (mut remaining, mut paid) = (0u8, 0u8);
+            while let Some(message) = rx.recv().await {
+                let data = match message.header {
+                    Header::DispenseHopperCoins => {
+                        remaining = *message.data.last().expect("should have coins");
+                        vec![1]
+                    }
+                    Header::RequestHopperStatus => {
+                        if remaining > 0 {
+                            remaining -= 1;
+                            paid += 1;
+                        }
+                        vec![1, remaining, paid, 0]
+                    }
+                    Header::EmergencyStop => vec![std::mem::take(&mut remaining)],
+                    Header::RequestSerialNumber => vec![1, 2, 3],
+                    _ => vec![],
+                };
+                let mut reply = vec![1, data.len() as u8, 3, 0];
+                reply.extend(data);
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+        });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_payout_refunds_what_was_not_dispensed() {
+        let (tx, rx) = mpsc::channel(1);
+        spawn_hopper(rx);
+        let hopper = PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx);
+        let machine = Machine {
+            acceptors: CurrencyAcceptorPool::builder().build(),
+            payout: PayoutPool::builder()
+                .add_hopper(hopper, 100)
+                .selection_strategy(HopperSelectionStrategy::LargestFirst)
+                .polling_interval(Duration::from_millis(5))
+                .build(),
+            balance: Arc::new(Mutex::new(10_000)),
+        };
+
+        let payout = tokio::time::timeout(Duration::from_millis(20), machine.payout(10_000)).await;
+        assert!(payout.is_err(), "payout should still be running");
+        // The amount stays reserved until the stopped payout is settled.
+        assert!(matches!(
+            machine.payout(100).await,
+            Err(MachineError::InsufficientBalance { balance: 0, .. })
+        ));
+
+        let journal = machine.payout_pool().journal();
+        while journal.is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let record = journal.last().expect("should be journaled");
+        assert_eq!(record.outcome, PayoutOutcome::Stopped);
+        assert!(record.progress.dispensed > 0);
+        assert_eq!(machine.balance(), record.progress.remaining);
+        assert_eq!(machine.balance(), 10_000 - record.progress.dispensed);
+    }
+}
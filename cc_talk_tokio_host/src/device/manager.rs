@@ -0,0 +1,580 @@
+use cc_talk_core::cc_talk::{
+    BitMask, BusAddress, Category, ChecksumType, Device, Manufacturer, SerialCode,
+};
+use cc_talk_host::{
+    command::Command,
+    device::device_commands::{EnableHopperCommand, ModifyMasterInhibitStatusCommand},
+};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, instrument, warn};
+
+use crate::transport::{
+    machine_state::{MachineState, MachineStateHandle},
+    tokio_transport::TransportMessage,
+};
+
+use super::{
+    address_manager::AddressManager,
+    base::{CommandError, DeviceCommon},
+    bill_validator::BillValidator,
+    changer::Changer,
+    coin_validator::CoinValidator,
+    enumeration::Probe,
+    payout::PayoutDevice,
+};
+
+/// State of a device before [`DeviceManager::lockdown`], restored by
+/// [`DeviceManager::unlock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SavedState {
+    MasterInhibit(bool),
+    HopperEnabled(bool),
+}
+
+/// Result of [`DeviceManager::lockdown`] and [`DeviceManager::unlock`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockdownReport {
+    /// Addresses of the devices locked down or restored, in ascending order.
+    pub devices: Vec<u8>,
+    /// Devices which did not acknowledge the command, they are in an unknown state.
+    pub failed: Vec<(u8, CommandError)>,
+}
+
+impl LockdownReport {
+    /// Returns `true` if every device acknowledged the command.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Driver of an [`AttachedDevice`], chosen from its category.
+#[derive(Debug)]
+pub enum DeviceHandle {
+    Hopper(PayoutDevice),
+    CoinAcceptor(CoinValidator),
+    BillValidator(BillValidator),
+    Changer(Changer),
+    /// The category has no driver in this crate.
+    Unsupported,
+}
+
+/// A device found by [`DeviceManager::discover`].
+#[derive(Debug)]
+pub struct AttachedDevice {
+    pub address: u8,
+    pub category: Category,
+    /// `None` if the device did not answer the manufacturer request.
+    pub manufacturer: Option<Manufacturer>,
+    /// `None` if the device did not answer the serial number request.
+    pub serial: Option<SerialCode>,
+    pub handle: DeviceHandle,
+}
+
+/// Registry of the devices attached to the bus, discovered with an MDCES `AddressPoll`.
+///
+/// Every device which supports MDCES answers the poll with its address, the manager then reads
+/// its category, manufacturer and serial number and creates the matching driver.
+///
+/// ```ignore
+/// let mut manager = DeviceManager::new(sender);
+/// manager.discover().await?;
+/// for hopper in manager.hoppers() {
+///     hopper.enable_hopper().await?;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DeviceManager {
+    sender: mpsc::Sender<TransportMessage>,
+    checksum_type: ChecksumType,
+    devices: Vec<AttachedDevice>,
+    broadcast_lockdown: bool,
+    /// States to restore on unlock, `None` if the bus is not locked down.
+    saved_states: Option<Vec<(u8, SavedState)>>,
+    /// Machine state to restore on unlock, `None` if the bus is not locked down.
+    saved_machine_state: Option<MachineState>,
+    machine_state: MachineStateHandle,
+}
+
+impl DeviceManager {
+    /// Creates an empty registry, devices are talked to with 8 bit checksums.
+    pub fn new(sender: mpsc::Sender<TransportMessage>) -> Self {
+        Self {
+            sender,
+            checksum_type: ChecksumType::Crc8,
+            devices: Vec::new(),
+            broadcast_lockdown: false,
+            saved_states: None,
+            saved_machine_state: None,
+            machine_state: MachineStateHandle::new(),
+        }
+    }
+
+    /// Checksum used to talk to the bus, every device has to use the same.
+    #[must_use]
+    pub fn with_checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+        self.checksum_type = checksum_type;
+        self
+    }
+
+    /// Broadcasts the master inhibit and the hopper disable before addressing the devices one
+    /// by one on [`lockdown`](Self::lockdown), every device stops at once.
+    ///
+    /// The acknowledgements of a broadcast collide, the devices are still addressed one by one
+    /// to confirm they are locked down. Only enable this if no device on the bus reads header
+    /// 228 or 164 as anything else.
+    #[must_use]
+    pub const fn with_broadcast_lockdown(mut self, broadcast: bool) -> Self {
+        self.broadcast_lockdown = broadcast;
+        self
+    }
+
+    /// Shares `machine_state` with the transport, see
+    /// [`CcTalkTokioTransport::with_machine_state`](crate::transport::tokio_transport::CcTalkTokioTransport::with_machine_state).
+    ///
+    /// The transport enforces the state, a manager whose state is not shared with the transport
+    /// only records it.
+    #[must_use]
+    pub fn with_machine_state(mut self, machine_state: MachineStateHandle) -> Self {
+        self.machine_state = machine_state;
+        self
+    }
+
+    /// Polls the bus and replaces the registry with the devices which answered.
+    ///
+    /// Devices which answer the poll but not the category request are registered as
+    /// [`Category::Unknown`] with [`DeviceHandle::Unsupported`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the address poll cannot be sent.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn discover(&mut self) -> Result<&[AttachedDevice], CommandError> {
+        let addresses = self.address_poll().await?;
+        let mut devices = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            devices.push(identify(&self.sender, address.get(), self.checksum_type).await);
+        }
+        self.devices = devices;
+        info!(devices = self.devices.len(), "bus discovered");
+        Ok(&self.devices)
+    }
+
+    /// Devices found by the last [`discover`](Self::discover), in ascending address order.
+    pub fn devices(&self) -> &[AttachedDevice] {
+        &self.devices
+    }
+
+    /// Returns the device at `address`.
+    pub fn device(&self, address: u8) -> Option<&AttachedDevice> {
+        self.devices.iter().find(|device| device.address == address)
+    }
+
+    pub fn hoppers(&self) -> impl Iterator<Item = &PayoutDevice> {
+        self.devices
+            .iter()
+            .filter_map(|device| match &device.handle {
+                DeviceHandle::Hopper(hopper) => Some(hopper),
+                _ => None,
+            })
+    }
+
+    pub fn coin_acceptors(&self) -> impl Iterator<Item = &CoinValidator> {
+        self.devices
+            .iter()
+            .filter_map(|device| match &device.handle {
+                DeviceHandle::CoinAcceptor(acceptor) => Some(acceptor),
+                _ => None,
+            })
+    }
+
+    pub fn bill_validators(&self) -> impl Iterator<Item = &BillValidator> {
+        self.devices
+            .iter()
+            .filter_map(|device| match &device.handle {
+                DeviceHandle::BillValidator(validator) => Some(validator),
+                _ => None,
+            })
+    }
+
+    pub fn changers(&self) -> impl Iterator<Item = &Changer> {
+        self.devices
+            .iter()
+            .filter_map(|device| match &device.handle {
+                DeviceHandle::Changer(changer) => Some(changer),
+                _ => None,
+            })
+    }
+
+    #[must_use]
+    pub fn machine_state(&self) -> MachineState {
+        self.machine_state.state()
+    }
+
+    /// Switches the machine to `state`, returns the previous state.
+    ///
+    /// In [`MachineState::Maintenance`] payouts fail with
+    /// [`CommandError::NotAllowed`], in [`MachineState::OutOfService`] only diagnostics and the
+    /// commands stopping the devices are sent. Hoppers are not disabled nor acceptors inhibited,
+    /// use [`lockdown`](Self::lockdown) for that.
+    pub fn set_machine_state(&self, state: MachineState) -> MachineState {
+        self.machine_state.set_state(state)
+    }
+
+    /// Returns `true` between a [`lockdown`](Self::lockdown) and a complete
+    /// [`unlock`](Self::unlock).
+    pub const fn is_locked_down(&self) -> bool {
+        self.saved_states.is_some()
+    }
+
+    /// Master-inhibits every coin acceptor, bill validator and changer and disables every
+    /// hopper, e.g. on an alarm or a tamper switch.
+    ///
+    /// The machine is switched to [`MachineState::OutOfService`] first, the transport then
+    /// refuses to enable the devices again and the drift check of a
+    /// [`CurrencyAcceptorPool`](super::currency_acceptor_pool::CurrencyAcceptorPool) sharing the
+    /// state keeps them inhibited.
+    ///
+    /// The state of each device before the first lockdown is taken from the driver cache, or
+    /// read from coin acceptors and bill validators when nothing is cached. Devices in an
+    /// unknown state stay locked down on [`unlock`](Self::unlock). Locking down again keeps the
+    /// states saved by the first call.
+    #[instrument(skip(self), level = "info")]
+    pub async fn lockdown(&mut self) -> LockdownReport {
+        let machine_state = self.machine_state.set_state(MachineState::OutOfService);
+        self.saved_machine_state.get_or_insert(machine_state);
+        if self.broadcast_lockdown {
+            self.broadcast_lockdown().await;
+        }
+        let mut saved_states = Vec::new();
+        let mut report = LockdownReport::default();
+        for device in &self.devices {
+            let Some((saved, result)) = lock_down(&device.handle).await else {
+                continue;
+            };
+            saved_states.push((device.address, saved));
+            match result {
+                Ok(()) => report.devices.push(device.address),
+                Err(error) => {
+                    warn!(address = device.address, error = %error, "device not locked down");
+                    report.failed.push((device.address, error));
+                }
+            }
+        }
+        self.saved_states.get_or_insert(saved_states);
+        info!(
+            locked = report.devices.len(),
+            failed = report.failed.len(),
+            "bus locked down"
+        );
+        report
+    }
+
+    /// Restores the machine state and the device states saved by [`lockdown`](Self::lockdown).
+    ///
+    /// Devices which fail to restore are kept, unlocking again retries them. Does nothing if
+    /// the bus is not locked down.
+    #[instrument(skip(self), level = "info")]
+    pub async fn unlock(&mut self) -> LockdownReport {
+        let mut report = LockdownReport::default();
+        let Some(saved_states) = self.saved_states.take() else {
+            return report;
+        };
+        if let Some(machine_state) = self.saved_machine_state.take() {
+            self.machine_state.set_state(machine_state);
+        }
+        let mut remaining = Vec::new();
+        for (address, saved) in saved_states {
+            let Some(device) = self.device(address) else {
+                debug!(address, "device gone, not restored");
+                continue;
+            };
+            match restore(&device.handle, saved).await {
+                Some(Ok(())) => report.devices.push(address),
+                Some(Err(error)) => {
+                    warn!(address, error = %error, "device not restored");
+                    report.failed.push((address, error));
+                    remaining.push((address, saved));
+                }
+                None => debug!(address, "device replaced, not restored"),
+            }
+        }
+        if !remaining.is_empty() {
+            self.saved_states = Some(remaining);
+        }
+        info!(
+            restored = report.devices.len(),
+            failed = report.failed.len(),
+            "bus unlocked"
+        );
+        report
+    }
+
+    /// Broadcasts the master inhibit and the hopper disable, the replies are ignored.
+    async fn broadcast_lockdown(&self) {
+        let device = Device::new(
+            BusAddress::BROADCAST.get(),
+            Category::Unknown,
+            self.checksum_type,
+        );
+        let inhibit = BitMask::<1>::new(1)
+            .ok()
+            .and_then(|bitmask| ModifyMasterInhibitStatusCommand::<1>::build(bitmask).ok());
+        if let Some(inhibit) = inhibit {
+            broadcast(&self.sender, &device, inhibit).await;
+        }
+        broadcast(&self.sender, &device, EnableHopperCommand::new(false)).await;
+    }
+
+    /// Broadcasts an `AddressPoll`, returns the addresses which answered in ascending order.
+    async fn address_poll(&self) -> Result<Vec<BusAddress>, CommandError> {
+        AddressManager::new(self.sender.clone())
+            .with_checksum_type(self.checksum_type)
+            .poll()
+            .await
+    }
+}
+
+/// Locks down the device behind `handle`, returns its previous state and the outcome.
+async fn lock_down(handle: &DeviceHandle) -> Option<(SavedState, Result<(), CommandError>)> {
+    let locked = match handle {
+        DeviceHandle::CoinAcceptor(acceptor) => {
+            let inhibited = match acceptor.cached_master_inhibit() {
+                Some(inhibited) => inhibited,
+                None => acceptor.get_master_inhibit_status().await.unwrap_or(true),
+            };
+            (
+                SavedState::MasterInhibit(inhibited),
+                acceptor.set_master_inhibit(true).await,
+            )
+        }
+        DeviceHandle::BillValidator(validator) => {
+            let inhibited = match validator.cached_master_inhibit() {
+                Some(inhibited) => inhibited,
+                None => validator.get_master_inhibit_status().await.unwrap_or(true),
+            };
+            (
+                SavedState::MasterInhibit(inhibited),
+                validator.set_master_inhibit(true).await,
+            )
+        }
+        DeviceHandle::Changer(changer) => (
+            SavedState::MasterInhibit(changer.cached_master_inhibit().unwrap_or(true)),
+            changer.set_master_inhibit(true).await,
+        ),
+        DeviceHandle::Hopper(hopper) => (
+            SavedState::HopperEnabled(hopper.cached_enabled().unwrap_or(false)),
+            hopper.disable_hopper().await,
+        ),
+        DeviceHandle::Unsupported => return None,
+    };
+    Some(locked)
+}
+
+/// Applies `saved` to the device behind `handle`, `None` if the driver does not match.
+async fn restore(handle: &DeviceHandle, saved: SavedState) -> Option<Result<(), CommandError>> {
+    let restored = match (handle, saved) {
+        (DeviceHandle::CoinAcceptor(acceptor), SavedState::MasterInhibit(inhibited)) => {
+            acceptor.set_master_inhibit(inhibited).await
+        }
+        (DeviceHandle::BillValidator(validator), SavedState::MasterInhibit(inhibited)) => {
+            validator.set_master_inhibit(inhibited).await
+        }
+        (DeviceHandle::Changer(changer), SavedState::MasterInhibit(inhibited)) => {
+            changer.set_master_inhibit(inhibited).await
+        }
+        (DeviceHandle::Hopper(hopper), SavedState::HopperEnabled(enabled)) => {
+            hopper.change_hopper_status(enabled).await
+        }
+        _ => return None,
+    };
+    Some(restored)
+}
+
+/// Sends `command` to every device, the colliding acknowledgements are ignored.
+async fn broadcast<C: Command>(
+    sender: &mpsc::Sender<TransportMessage>,
+    device: &Device,
+    command: C,
+) {
+    let (tx, rx) = oneshot::channel();
+    if sender
+        .send(TransportMessage::new(device, command, tx))
+        .await
+        .is_err()
+    {
+        warn!("unable to broadcast the lockdown");
+        return;
+    }
+    if let Ok(Err(error)) = rx.await {
+        debug!(error = %error, "broadcast not acknowledged");
+    }
+}
+
+/// Reads the identity of the device at `address` and creates the driver of its category.
+pub(super) async fn identify(
+    sender: &mpsc::Sender<TransportMessage>,
+    address: u8,
+    checksum_type: ChecksumType,
+) -> AttachedDevice {
+    let probe = Probe::new(address, checksum_type, sender);
+    let category = probe.get_category().await.unwrap_or(Category::Unknown);
+    let manufacturer = probe.get_manufacturer_id().await.ok();
+    let serial = probe.get_serial_number().await.ok();
+    debug!(
+        address,
+        category = ?category,
+        manufacturer = ?manufacturer,
+        serial = ?serial,
+        "device identified"
+    );
+
+    let device = Device::new(address, category.clone(), checksum_type);
+    let sender = sender.clone();
+    let handle = match category {
+        Category::Payout => DeviceHandle::Hopper(PayoutDevice::new(device, sender)),
+        Category::CoinAcceptor => DeviceHandle::CoinAcceptor(CoinValidator::new(device, sender)),
+        Category::BillValidator => DeviceHandle::BillValidator(BillValidator::new(device, sender)),
+        Category::Changer => DeviceHandle::Changer(Changer::new(device, sender)),
+        _ => DeviceHandle::Unsupported,
+    };
+    AttachedDevice {
+        address,
+        category,
+        manufacturer,
+        serial,
+        handle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use cc_talk_core::cc_talk::Header;
+
+    use crate::transport::tokio_transport::TransportError;
+
+    use super::*;
+
+    /// Last master inhibit mask or hopper enable byte written to each address.
+    type States = Arc<Mutex<HashMap<u8, u8>>>;
+
+    fn simulated_bus(
+        devices: Vec<(u8, &'static str)>,
+        states: States,
+        broadcasts: Arc<Mutex<Vec<Header>>>,
+    ) -> mpsc::Sender<TransportMessage> {
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(8);
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let category = devices
+                    .iter()
+                    .find(|device| device.0 == message.address)
+                    .map(|device| device.1);
+                let reply = match (message.header, category) {
+                    (Header::AddressPoll, _) => Ok(devices.iter().map(|device| device.0).collect()),
+                    (header, None) => {
+                        broadcasts.lock().unwrap().push(header);
+                        Err(TransportError::Timeout)
+                    }
+                    (header, Some(category)) => {
+                        let mut states = states.lock().unwrap();
+                        let data = match header {
+                            Header::RequestEquipementCategoryId => category.as_bytes().to_vec(),
+                            Header::RequestMasterInhibitStatus => vec![states[&message.address]],
+                            Header::ModifyMasterInhibitStatus | Header::EnableHopper => {
+                                states.insert(message.address, message.data[0]);
+                                vec![]
+                            }
+                            _ => vec![],
+                        };
+                        let mut reply = vec![1, data.len() as u8, message.address, 0];
+                        reply.extend(data);
+                        reply.push(0);
+                        Ok(reply)
+                    }
+                };
+                message.respond_to.send(reply).expect("should respond");
+            }
+        });
+        tx
+    }
+
+    #[tokio::test]
+    async fn lockdown_is_undone_by_unlock() {
+        let states: States = Arc::new(Mutex::new(HashMap::from([(2, 1), (3, 0), (40, 0)])));
+        let broadcasts = Arc::new(Mutex::new(Vec::new()));
+        let sender = simulated_bus(
+            vec![(2, "Coin Acceptor"), (3, "Payout"), (40, "Bill Validator")],
+            states.clone(),
+            broadcasts.clone(),
+        );
+        let mut manager = DeviceManager::new(sender).with_broadcast_lockdown(true);
+        manager.discover().await.expect("should discover");
+        let hopper = manager.hoppers().next().expect("should find the hopper");
+        hopper.enable_hopper().await.expect("should enable");
+
+        let report = manager.lockdown().await;
+        assert_eq!(report.devices, [2, 3, 40]);
+        assert!(report.is_complete());
+        assert!(manager.is_locked_down());
+        assert_eq!(
+            *broadcasts.lock().unwrap(),
+            [Header::ModifyMasterInhibitStatus, Header::EnableHopper]
+        );
+        assert_eq!(
+            *states.lock().unwrap(),
+            HashMap::from([(2, 0), (3, 0), (40, 0)])
+        );
+
+        // The states saved by the first lockdown are kept.
+        manager.lockdown().await;
+        let report = manager.unlock().await;
+        assert_eq!(report.devices, [2, 3, 40]);
+        assert!(!manager.is_locked_down());
+        assert_eq!(
+            *states.lock().unwrap(),
+            HashMap::from([(2, 1), (3, 0xA5), (40, 0)])
+        );
+        assert_eq!(manager.unlock().await, LockdownReport::default());
+    }
+
+    #[tokio::test]
+    async fn drift_check_keeps_the_acceptors_inhibited_after_lockdown() {
+        use crate::device::currency_acceptor_pool::{CurrencyAcceptorPool, DriftCheck};
+
+        let states: States = Arc::new(Mutex::new(HashMap::from([(2, 0)])));
+        let sender = simulated_bus(
+            vec![(2, "Coin Acceptor")],
+            states.clone(),
+            Arc::new(Mutex::new(Vec::new())),
+        );
+        let machine_state = MachineStateHandle::new();
+        let pool = CurrencyAcceptorPool::builder()
+            .add_coin_validator(CoinValidator::new(
+                Device::new(2, Category::CoinAcceptor, ChecksumType::Crc8),
+                sender.clone(),
+            ))
+            .with_drift_check(DriftCheck::default())
+            .with_machine_state(machine_state.clone())
+            .build();
+        let mut manager = DeviceManager::new(sender).with_machine_state(machine_state);
+        manager.discover().await.expect("should discover");
+        pool.enable().await.expect("should enable");
+        assert_eq!(states.lock().unwrap()[&2], 1);
+
+        manager.lockdown().await;
+        assert_eq!(manager.machine_state(), MachineState::OutOfService);
+        assert!(pool.check_master_inhibits(true).await.is_empty());
+        assert_eq!(states.lock().unwrap()[&2], 0);
+
+        manager.unlock().await;
+        assert_eq!(manager.machine_state(), MachineState::Operational);
+        assert_eq!(states.lock().unwrap()[&2], 1);
+        assert!(pool.check_master_inhibits(true).await.is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+
+use cc_talk_core::cc_talk::{Category, Header};
+
+/// Likely reason of a NAK, guessed by [`NakCause::diagnose`].
+///
+/// ccTalk NAKs carry no reason, the cause is inferred from the header, its parameters and the
+/// category of the device. It is a hint for error messages, not a certainty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NakCause {
+    /// The header is specific to another category of devices.
+    WrongCategory { expected: Category },
+    /// The device does not implement the header, most headers are optional.
+    UnsupportedHeader,
+    /// A parameter is outside the range supported by the device.
+    InvalidParameter,
+    /// The device cannot execute the command in its current state.
+    WrongState { hint: &'static str },
+}
+
+impl NakCause {
+    /// Guesses why a device of `category` NAKed `header`, sent with or without parameters.
+    ///
+    /// Pass [`Category::Unknown`] when the category of the device is not known. Changers are
+    /// never told they use the wrong category since they combine acceptors and hoppers.
+    #[must_use]
+    pub fn diagnose(header: Header, has_parameters: bool, category: &Category) -> Self {
+        if let Some(expected) = header_category(header)
+            && !matches!(category, Category::Unknown | Category::Changer)
+            && *category != expected
+        {
+            return Self::WrongCategory { expected };
+        }
+        if let Some(hint) = state_hint(header) {
+            return Self::WrongState { hint };
+        }
+        if has_parameters {
+            Self::InvalidParameter
+        } else {
+            Self::UnsupportedHeader
+        }
+    }
+}
+
+impl fmt::Display for NakCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongCategory { expected } => {
+                write!(f, "the header is meant for {expected:?} devices")
+            }
+            Self::UnsupportedHeader => write!(f, "the device probably does not implement it"),
+            Self::InvalidParameter => write!(
+                f,
+                "a parameter is probably out of range or the device does not implement it"
+            ),
+            Self::WrongState { hint } => write!(f, "{hint}"),
+        }
+    }
+}
+
+/// Category whose devices implement the header, `None` for headers shared by several
+/// categories.
+fn header_category(header: Header) -> Option<Category> {
+    match header {
+        Header::ReadBufferedCreditOrErrorCodes
+        | Header::ModifySorterOverrideStatus
+        | Header::RequestSorterOverrideStatus
+        | Header::ModifySorterPaths
+        | Header::RequestSorterPaths
+        | Header::ModifyDefaultSorterPath
+        | Header::RequestDefaultSorterPath
+        | Header::ModifyCoinId
+        | Header::RequestCoinId
+        | Header::RequestCoinPosition
+        | Header::TeachModeControl
+        | Header::RequestTeachStatus
+        | Header::ModifyInhibitAndOverrideRegisters
+        | Header::ModifyEncryptedInhibitAndOverrideRegisters
+        | Header::SetAcceptLimit => Some(Category::CoinAcceptor),
+        Header::ReadBufferedBillEvents
+        | Header::ModifyBillId
+        | Header::RequestBillId
+        | Header::RequestCountryScalingFactor
+        | Header::RequestBillPosition
+        | Header::RouteBill
+        | Header::ModifyBillOperatingMode
+        | Header::RequestBillOperatingMode
+        | Header::PerformStackerCycle
+        | Header::UploadBillTables
+        | Header::BeginBillTableUpgrade
+        | Header::FinishBillTableUpgrade => Some(Category::BillValidator),
+        Header::RequestHopperStatus
+        | Header::DispenseHopperCoins
+        | Header::DispenseHopperValue
+        | Header::RequestHopperDispenseCount
+        | Header::EnableHopper
+        | Header::TestHopper
+        | Header::PumpRNG
+        | Header::RequestCipherKey
+        | Header::RequestHopperCoin
+        | Header::RequestHopperCoinValue
+        | Header::RequestHopperPollingValue
+        | Header::EmergencyStopValue
+        | Header::PurgeHopper
+        | Header::ModifyPayoutFloat
+        | Header::RequestPayoutFloat
+        | Header::ModifyPayoutCapacity
+        | Header::RequestPayoutCapacity
+        | Header::ModifyPayoutAbsoluteCount
+        | Header::RequestPayoutAbsoluteCount => Some(Category::Payout),
+        _ => None,
+    }
+}
+
+/// Hint for headers which devices NAK in some states only.
+fn state_hint(header: Header) -> Option<&'static str> {
+    match header {
+        Header::DispenseHopperCoins | Header::DispenseHopperValue | Header::PurgeHopper => Some(
+            "the hopper is probably disabled or still flagged after a fault, enable it or reset it",
+        ),
+        Header::RouteBill => Some("no bill is probably held in escrow"),
+        Header::PerformStackerCycle => {
+            Some("a bill is probably held in escrow or the stacker is jammed")
+        }
+        Header::RequestTeachStatus => Some("teach mode is probably not running"),
+        Header::FinishBillTableUpgrade | Header::UploadBillTables => {
+            Some("the bill table upgrade was probably not started")
+        }
+        Header::EnterNewPinNumber => Some("the PIN probably has to be entered first"),
+        Header::ReadDataBlock | Header::WriteDataBlock => {
+            Some("the block is probably outside the data storage or the memory is write protected")
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn naks_are_diagnosed_from_header_parameters_and_category() {
+        assert_eq!(
+            NakCause::diagnose(Header::RouteBill, true, &Category::CoinAcceptor),
+            NakCause::WrongCategory {
+                expected: Category::BillValidator
+            }
+        );
+        assert!(matches!(
+            NakCause::diagnose(Header::RouteBill, true, &Category::BillValidator),
+            NakCause::WrongState { .. }
+        ));
+        assert_eq!(
+            NakCause::diagnose(Header::RequestCoinId, true, &Category::Changer),
+            NakCause::InvalidParameter
+        );
+        assert_eq!(
+            NakCause::diagnose(Header::RequestBuildCode, false, &Category::Unknown),
+            NakCause::UnsupportedHeader
+        );
+    }
+}
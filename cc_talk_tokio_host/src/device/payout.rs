@@ -1,7 +1,14 @@
 #![allow(dead_code)]
 
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+#[cfg(feature = "encryption")]
+use cc_talk_core::cc_talk::{ChallengeRng, EncryptedHopperStatus, HopperCipher};
 use cc_talk_core::cc_talk::{
-    CurrencyToken, Device, HopperDispenseStatus, HopperFlag, HopperStatus,
+    CurrencyToken, Device, HopperDispenseStatus, HopperFlag, HopperStatus, HopperVariables,
 };
 use cc_talk_host::{command::Command, device::device_commands::*};
 use tokio::sync::mpsc;
@@ -9,11 +16,30 @@ use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::transport::tokio_transport::TransportMessage;
 
-use super::base::{CommandError, DeviceCommon, DeviceResult};
+use super::{
+    base::{CommandError, DeviceCommon, DeviceResult},
+    quirks::DeviceQuirks,
+    reset::{DeviceReset, ReinitializationFuture, ResetRecovery},
+    triage::TriageConfig,
+};
 
 pub struct PayoutDevice {
     pub device: Device,
     pub sender: mpsc::Sender<TransportMessage>,
+    express_sender: Option<mpsc::Sender<TransportMessage>>,
+    timeout_triage: Option<TriageConfig>,
+    quirks: Option<Arc<dyn DeviceQuirks>>,
+    event_counter: Arc<Mutex<u8>>,
+    enabled: Arc<Mutex<Option<bool>>>,
+    hopper_coin: Arc<Mutex<HopperCoinCache>>,
+    reset_recovery: ResetRecovery<PayoutDevice>,
+}
+
+/// Replies of `RequestHopperCoin` and `RequestHopperCoinValue`, shared between clones.
+#[derive(Debug, Default)]
+struct HopperCoinCache {
+    coin: Option<CurrencyToken>,
+    values: BTreeMap<u8, (CurrencyToken, u16)>,
 }
 
 impl std::fmt::Debug for PayoutDevice {
@@ -31,9 +57,82 @@ impl PayoutDevice {
             category = ?device.category(),
             "creating payout device"
         );
-        PayoutDevice { device, sender }
+        PayoutDevice {
+            device,
+            sender,
+            express_sender: None,
+            quirks: None,
+            timeout_triage: None,
+            event_counter: Arc::new(Mutex::new(0)),
+            enabled: Arc::new(Mutex::new(None)),
+            hopper_coin: Arc::new(Mutex::new(HopperCoinCache::default())),
+            reset_recovery: ResetRecovery::default(),
+        }
+    }
+
+    /// Routes express commands through the transport express lane.
+    ///
+    /// See [`CcTalkTokioTransport::express_sender`](crate::transport::tokio_transport::CcTalkTokioTransport::express_sender).
+    #[must_use]
+    pub fn with_express_lane(mut self, express_sender: mpsc::Sender<TransportMessage>) -> Self {
+        self.express_sender = Some(express_sender);
+        self
+    }
+
+    /// Rewrites the replies of a device deviating from the specification before they are
+    /// parsed, see [`DeviceQuirks`].
+    #[must_use]
+    pub fn with_quirks(mut self, quirks: Arc<dyn DeviceQuirks>) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Classifies the timeouts of this driver, see [`DeviceCommon::timeout_triage`].
+    #[must_use]
+    pub const fn with_timeout_triage(self) -> Self {
+        self.with_timeout_triage_config(TriageConfig::new())
+    }
+
+    /// Classifies the timeouts of this driver, knowing the other devices configured on the bus.
+    #[must_use]
+    pub const fn with_timeout_triage_config(mut self, config: TriageConfig) -> Self {
+        self.timeout_triage = Some(config);
+        self
+    }
+
+    /// Registers the re-initialization run when a poll detects an unexpected reset, e.g.
+    /// enabling the hopper and re-entering its PIN.
+    ///
+    /// See [`reset`](super::reset) for an example.
+    #[must_use]
+    pub fn with_reset_handler<F>(mut self, handler: F) -> Self
+    where
+        F: for<'a> Fn(&'a PayoutDevice) -> ReinitializationFuture<'a> + Send + Sync + 'static,
+    {
+        self.reset_recovery.set_handler(handler);
+        self
     }
 
+    /// Emits a [`DeviceReset`] to `events` whenever a poll detects an unexpected reset.
+    ///
+    /// Every call adds a receiver, the resets are emitted to all of them.
+    #[must_use]
+    pub fn with_reset_events(mut self, events: mpsc::Sender<DeviceReset>) -> Self {
+        self.reset_recovery.add_events(events);
+        self
+    }
+
+    /// Returns the event counter of the last dispense status.
+    pub fn event_counter(&self) -> u8 {
+        *self.event_counter.lock().expect("should not be poisoned")
+    }
+
+    /// Requests the dispense status of the hopper.
+    ///
+    /// An event counter returning to 0 is an unexpected reset, the cached hopper coin is
+    /// invalidated and the handler registered with [`with_reset_handler`](Self::with_reset_handler)
+    /// runs before the status is returned.
+
     #[instrument(skip(self), level = "debug")]
     pub async fn get_payout_status(&self) -> DeviceResult<HopperDispenseStatus> {
         trace!("requesting hopper dispense status");
@@ -42,6 +141,16 @@ impl PayoutDevice {
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)?;
         debug!(status = ?status, "hopper dispense status received");
+        let previous = std::mem::replace(
+            &mut *self.event_counter.lock().expect("should not be poisoned"),
+            status.event_counter,
+        );
+        if status.event_counter == 0 && previous != 0 {
+            self.invalidate_hopper_coin();
+            self.reset_recovery
+                .recover(self.device.address(), self)
+                .await;
+        }
         Ok(status)
     }
 
@@ -81,10 +190,16 @@ impl PayoutDevice {
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)
             .map(|_| ())?;
+        *self.enabled.lock().expect("should not be poisoned") = Some(enabled);
         info!(enabled, "hopper status changed");
         Ok(())
     }
 
+    /// Returns the hopper status last set, `None` until then.
+    pub fn cached_enabled(&self) -> Option<bool> {
+        *self.enabled.lock().expect("should not be poisoned")
+    }
+
     pub async fn enable_hopper(&self) -> DeviceResult<()> {
         debug!("enabling hopper");
         self.change_hopper_status(true).await
@@ -154,6 +269,59 @@ impl PayoutDevice {
         Ok(result)
     }
 
+    /// Dispenses from an encrypted hopper.
+    ///
+    /// The RNG of the hopper is pumped with 8 bytes from `rng`, the security code is then
+    /// computed from the cipher key it returns, see [`HopperCipher::security_code`].
+    #[cfg(feature = "encryption")]
+    #[instrument(skip(self, cipher, rng), fields(coins), level = "info")]
+    pub async fn payout_encrypted<C: HopperCipher + Sync + ?Sized, R: ChallengeRng>(
+        &self,
+        coins: u8,
+        cipher: &C,
+        mut rng: R,
+    ) -> DeviceResult<Option<u8>> {
+        debug!(coins, "initiating encrypted payout");
+        let mut pumped = [0u8; 8];
+        rng.fill_bytes(&mut pumped);
+        trace!("pumping RNG");
+        self.send_command(PumpRngCommand::new(pumped)).await?;
+        trace!("requesting cipher key");
+        let response_packet = self.send_command(RequestCipherKeyCommand).await?;
+        let cipher_key = RequestCipherKeyCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        let security_code = cipher.security_code(&pumped, &cipher_key, coins);
+        let command = DispenseHopperCoinsCommand::new_encrypted(coins, security_code);
+        let response_packet = self.send_command(command).await?;
+        let result = DispenseHopperCoinsCommand::new(coins)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        info!(coins, result = ?result, "encrypted payout completed");
+        Ok(result)
+    }
+
+    /// Requests the hopper status encrypted by `cipher`, the challenge bytes are drawn from
+    /// `rng`.
+    #[cfg(feature = "encryption")]
+    #[instrument(skip(self, cipher, rng), level = "debug")]
+    pub async fn get_encrypted_hopper_status<C: HopperCipher + Sync + ?Sized, R: ChallengeRng>(
+        &self,
+        cipher: &C,
+        mut rng: R,
+    ) -> DeviceResult<EncryptedHopperStatus> {
+        let mut challenge = [0u8; 3];
+        rng.fill_bytes(&mut challenge);
+        trace!("requesting encrypted hopper status");
+        let command = RequestEncryptedHopperStatusCommand::new(cipher, challenge);
+        let response_packet = self.send_command(command).await?;
+        let status = RequestEncryptedHopperStatusCommand::new(cipher, challenge)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(status = ?status, "encrypted hopper status received");
+        Ok(status)
+    }
+
     #[instrument(skip(self), fields(hopper_number, count), level = "info")]
     pub async fn purge(&self, hopper_number: u8, count: u8) -> DeviceResult<()> {
         warn!(hopper_number, count, "purging hopper");
@@ -188,19 +356,209 @@ impl PayoutDevice {
         Ok(result)
     }
 
+    /// Returns the coin dispensed by the hopper, reading it from the device on first use.
+    ///
+    /// The coin is cached and shared between clones until a poll reports a device reset or
+    /// [`invalidate_hopper_coin`](Self::invalidate_hopper_coin) is called.
     #[instrument(skip(self), level = "debug")]
     pub async fn get_hopper_coin(&self) -> DeviceResult<CurrencyToken> {
+        if let Some(token) = self.coin_cache().coin.clone() {
+            return Ok(token);
+        }
         trace!("requesting hopper coin info");
         let response_packet = self.send_command(RequestHopperCoinCommand).await?;
         let token = RequestHopperCoinCommand
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)?;
         debug!(token = ?token, "hopper coin info received");
+        self.coin_cache().coin = Some(token.clone());
         Ok(token)
     }
 
+    /// Checks that the hopper dispenses `expected` before a payout of that coin.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CommandError::WrongCoin`] if the hopper holds another coin.
+    pub async fn check_hopper_coin(&self, expected: &CurrencyToken) -> DeviceResult<()> {
+        let loaded = self.get_hopper_coin().await?;
+        if loaded == *expected {
+            return Ok(());
+        }
+        warn!(expected = ?expected, loaded = ?loaded, "hopper holds another coin");
+        Err(CommandError::WrongCoin {
+            address: self.device.address(),
+            expected: expected.clone(),
+            loaded,
+        })
+    }
+
+    /// Drops the cached hopper coin and coin values, they are read again from the device on
+    /// their next use.
+    pub fn invalidate_hopper_coin(&self) {
+        let mut cache = self.coin_cache();
+        if cache.coin.take().is_some() | !std::mem::take(&mut cache.values).is_empty() {
+            debug!("hopper coin invalidated");
+        }
+    }
+
+    fn coin_cache(&self) -> std::sync::MutexGuard<'_, HopperCoinCache> {
+        self.hopper_coin.lock().expect("should not be poisoned")
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_variables(&self) -> DeviceResult<HopperVariables> {
+        trace!("requesting hopper variable set");
+        let response_packet = self.send_command(RequestHopperVariableSetCommand).await?;
+        let variables = RequestHopperVariableSetCommand
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(variables = ?variables, "hopper variable set received");
+        Ok(variables)
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn set_variables(&self, variables: HopperVariables) -> DeviceResult<()> {
+        info!(variables = ?variables, "modifying hopper variable set");
+        let command = ModifyHopperVariableSetCommand::new(variables);
+        let response_packet = self.send_command(command).await?;
+        ModifyHopperVariableSetCommand::new(variables)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!("hopper variable set modified");
+        Ok(())
+    }
+
+    /// Reads the variable set and writes it back with single coin mode changed.
+    pub async fn set_single_coin_mode(&self, enabled: bool) -> DeviceResult<HopperVariables> {
+        let variables = self.get_variables().await?.with_single_coin_mode(enabled);
+        self.set_variables(variables).await?;
+        Ok(variables)
+    }
+
+    /// Reads the variable set and writes it back with the use other hopper flag changed.
+    pub async fn set_use_other_hopper(&self, enabled: bool) -> DeviceResult<HopperVariables> {
+        let variables = self.get_variables().await?.with_use_other_hopper(enabled);
+        self.set_variables(variables).await?;
+        Ok(variables)
+    }
+
+    /// Reads the payout float, `hopper_number` selects the hopper of a multi-hopper device.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_payout_float(&self, hopper_number: Option<u8>) -> DeviceResult<u16> {
+        trace!("requesting payout float");
+        let command = hopper_number.map_or_else(
+            RequestPayoutFloatCommand::new,
+            RequestPayoutFloatCommand::new_with_hopper,
+        );
+        let response_packet = self.send_command(command).await?;
+        let value = RequestPayoutFloatCommand::new()
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(value, "payout float received");
+        Ok(value)
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn set_payout_float(
+        &self,
+        hopper_number: Option<u8>,
+        coins: u16,
+    ) -> DeviceResult<()> {
+        info!(coins, "modifying payout float");
+        let command = match hopper_number {
+            Some(hopper_number) => ModifyPayoutFloatCommand::new_with_hopper(hopper_number, coins),
+            None => ModifyPayoutFloatCommand::new(coins),
+        };
+        let response_packet = self.send_command(command).await?;
+        ModifyPayoutFloatCommand::new(0)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!("payout float modified");
+        Ok(())
+    }
+
+    /// Reads the payout capacity, `hopper_number` selects the hopper of a multi-hopper device.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_payout_capacity(&self, hopper_number: Option<u8>) -> DeviceResult<u16> {
+        trace!("requesting payout capacity");
+        let command = hopper_number.map_or_else(
+            RequestPayoutCapacityCommand::new,
+            RequestPayoutCapacityCommand::new_with_hopper,
+        );
+        let response_packet = self.send_command(command).await?;
+        let value = RequestPayoutCapacityCommand::new()
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(value, "payout capacity received");
+        Ok(value)
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn set_payout_capacity(
+        &self,
+        hopper_number: Option<u8>,
+        capacity: u16,
+    ) -> DeviceResult<()> {
+        info!(capacity, "modifying payout capacity");
+        let command = match hopper_number {
+            Some(hopper_number) => {
+                ModifyPayoutCapacityCommand::new_with_hopper(hopper_number, capacity)
+            }
+            None => ModifyPayoutCapacityCommand::new(capacity),
+        };
+        let response_packet = self.send_command(command).await?;
+        ModifyPayoutCapacityCommand::new(0)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!("payout capacity modified");
+        Ok(())
+    }
+
+    /// Reads the payout absolute count, `hopper_number` selects the hopper of a multi-hopper device.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_payout_absolute_count(&self, hopper_number: Option<u8>) -> DeviceResult<u16> {
+        trace!("requesting payout absolute count");
+        let command = hopper_number.map_or_else(
+            RequestPayoutAbsoluteCountCommand::new,
+            RequestPayoutAbsoluteCountCommand::new_with_hopper,
+        );
+        let response_packet = self.send_command(command).await?;
+        let value = RequestPayoutAbsoluteCountCommand::new()
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!(value, "payout absolute count received");
+        Ok(value)
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn set_payout_absolute_count(
+        &self,
+        hopper_number: Option<u8>,
+        count: u16,
+    ) -> DeviceResult<()> {
+        info!(count, "modifying payout absolute count");
+        let command = match hopper_number {
+            Some(hopper_number) => {
+                ModifyPayoutAbsoluteCountCommand::new_with_hopper(hopper_number, u32::from(count))
+            }
+            None => ModifyPayoutAbsoluteCountCommand::new(u32::from(count)),
+        };
+        let response_packet = self.send_command(command).await?;
+        ModifyPayoutAbsoluteCountCommand::new(0)
+            .parse_response(response_packet.get_data()?)
+            .map_err(CommandError::from)?;
+        debug!("payout absolute count modified");
+        Ok(())
+    }
+
+    /// Returns the coin and value of `coin_type` for multi-coin hoppers, cached like
+    /// [`get_hopper_coin`](Self::get_hopper_coin).
     #[instrument(skip(self), fields(coin_type), level = "debug")]
     pub async fn get_hopper_coin_value(&self, coin_type: u8) -> DeviceResult<(CurrencyToken, u16)> {
+        if let Some(cached) = self.coin_cache().values.get(&coin_type).cloned() {
+            return Ok(cached);
+        }
         trace!(coin_type, "requesting hopper coin value");
         let response_packet = self
             .send_command(RequestHopperCoinValueCommand::new(coin_type))
@@ -209,6 +567,7 @@ impl PayoutDevice {
             .parse_response(response_packet.get_data()?)
             .map_err(CommandError::from)?;
         debug!(coin_type, token = ?result.0, value = result.1, "hopper coin value received");
+        self.coin_cache().values.insert(coin_type, result.clone());
         Ok(result)
     }
 
@@ -231,6 +590,13 @@ impl Clone for PayoutDevice {
         Self {
             device: self.device.clone(),
             sender: self.sender.clone(),
+            express_sender: self.express_sender.clone(),
+            timeout_triage: self.timeout_triage,
+            quirks: self.quirks.clone(),
+            event_counter: self.event_counter.clone(),
+            enabled: self.enabled.clone(),
+            hopper_coin: self.hopper_coin.clone(),
+            reset_recovery: self.reset_recovery.clone(),
         }
     }
 }
@@ -243,4 +609,73 @@ impl DeviceCommon for PayoutDevice {
     fn get_sender(&self) -> &mpsc::Sender<TransportMessage> {
         &self.sender
     }
+
+    fn get_express_sender(&self) -> Option<&mpsc::Sender<TransportMessage>> {
+        self.express_sender.as_ref()
+    }
+
+    fn get_quirks(&self) -> Option<&Arc<dyn DeviceQuirks>> {
+        self.quirks.as_ref()
+    }
+
+    fn timeout_triage(&self) -> Option<&TriageConfig> {
+        self.timeout_triage.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Header};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn hopper_coin_is_cached_until_a_reset() {
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(8);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&requests);
+        let event_counters = Arc::new(Mutex::new(vec![3u8, 0, 2, 0, 0, 0, 0, 0]));
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let data = match message.header {
+                    Header::RequestHopperCoin => {
+                        counted.fetch_add(1, Ordering::SeqCst);
+                        b"EU200A".to_vec()
+                    }
+                    Header::RequestHopperStatus => {
+                        event_counters.lock().unwrap().drain(..4).collect()
+                    }
+                    _ => vec![],
+                };
+                let mut reply = vec![1, data.len() as u8, message.address, 0];
+                reply.extend(data);
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+        });
+        let hopper = PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx);
+        let euro = CurrencyToken::build("EU200A").unwrap();
+
+        assert_eq!(hopper.get_hopper_coin().await, Ok(euro.clone()));
+        hopper.clone().check_hopper_coin(&euro).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let token = CurrencyToken::Token;
+        assert_eq!(
+            hopper.check_hopper_coin(&token).await,
+            Err(CommandError::WrongCoin {
+                address: 3,
+                expected: token,
+                loaded: euro.clone(),
+            })
+        );
+
+        hopper.get_payout_status().await.unwrap();
+        hopper.get_payout_status().await.unwrap();
+        assert_eq!(hopper.event_counter(), 0);
+        assert_eq!(hopper.get_hopper_coin().await, Ok(euro));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
 }
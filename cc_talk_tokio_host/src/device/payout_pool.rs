@@ -6,10 +6,11 @@
 //! - Pool-level hopper enable/disable (no hardware commands)
 //! - Inventory level monitoring via sensors
 //! - Value-based payout with automatic hopper selection
-//! - Greedy algorithm for optimal coin selection
+//! - Pluggable hopper selection strategies, balancing the hopper fill levels by default
 //! - Automatic replanning when hoppers run empty
 //! - Per-payment async event notifications
 //! - Emergency stop coordination
+//! - Cancellation-safe payouts with a payout journal
 //!
 //! # Example
 //!
@@ -35,14 +36,21 @@ mod builder;
 mod config;
 mod error;
 mod event;
+mod journal;
 mod poll_result;
 mod pool;
+mod strategy;
 
 pub use builder::PayoutPoolBuilder;
-pub use config::HopperSelectionStrategy;
+pub use config::{CancellationPolicy, HopperSelectionStrategy};
 pub use error::{PayoutPoolError, PayoutPoolResult};
 pub use event::PayoutEvent;
+pub use journal::{DEFAULT_PAYOUT_JOURNAL_CAPACITY, PayoutJournal, PayoutOutcome, PayoutRecord};
 pub use poll_result::{
     DispenseProgress, HopperInventory, HopperInventoryLevel, HopperPollError, PayoutPollResult,
 };
 pub use pool::PayoutPool;
+pub use strategy::{
+    BalanceLeveling, GreedyLargestFirst, GreedySmallestFirst, HopperCandidate, ReserveAware,
+    SelectionStrategy,
+};
@@ -1,10 +1,19 @@
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
+use cc_talk_core::cc_talk::CurrencyToken;
 use derive_builder::Builder;
 
-use crate::device::payout::PayoutDevice;
+use crate::device::{payout::PayoutDevice, service::ServiceRegistry};
 
-use super::{PayoutPoolResult, config::HopperSelectionStrategy, pool::PayoutPool};
+use super::{
+    PayoutPoolResult,
+    config::{CancellationPolicy, HopperSelectionStrategy},
+    journal::PayoutJournal,
+    pool::PayoutPool,
+};
 
 /// Internal configuration struct used by `derive_builder` to generate
 /// [`PayoutPoolBuilder`].
@@ -21,6 +30,9 @@ pub(crate) struct PayoutPoolConfig {
     #[builder(setter(custom), default)]
     hoppers: Vec<(PayoutDevice, u32)>,
 
+    #[builder(setter(custom), default)]
+    hopper_coins: HashMap<u8, CurrencyToken>,
+
     #[builder(default)]
     selection_strategy: HopperSelectionStrategy,
 
@@ -29,6 +41,15 @@ pub(crate) struct PayoutPoolConfig {
 
     #[builder(setter(custom), default)]
     initially_disabled: HashSet<u8>,
+
+    #[builder(setter(custom), default)]
+    service_registry: ServiceRegistry,
+
+    #[builder(default)]
+    cancellation_policy: CancellationPolicy,
+
+    #[builder(setter(custom), default)]
+    journal: PayoutJournal,
 }
 
 impl PayoutPoolBuilder {
@@ -46,6 +67,28 @@ impl PayoutPoolBuilder {
         self
     }
 
+    /// Adds a hopper to the pool with its coin value and the coin it must hold.
+    ///
+    /// The coin reported by the hopper is checked before it dispenses, a hopper holding another
+    /// coin fails with [`CommandError::WrongCoin`](crate::device::base::CommandError::WrongCoin)
+    /// and is left out of the payout, the error is reported with a
+    /// [`PayoutEvent::HopperError`](super::PayoutEvent::HopperError).
+    #[must_use]
+    pub fn add_hopper_with_coin(
+        self,
+        hopper: PayoutDevice,
+        value: u32,
+        coin: CurrencyToken,
+    ) -> Self {
+        let address = hopper.device.address();
+        let mut builder = self.add_hopper(hopper, value);
+        builder
+            .hopper_coins
+            .get_or_insert_with(HashMap::new)
+            .insert(address, coin);
+        builder
+    }
+
     /// Adds multiple hoppers to the pool.
     ///
     /// Each tuple contains a payout device and its coin value.
@@ -65,6 +108,25 @@ impl PayoutPoolBuilder {
         self
     }
 
+    /// Sets the registry used to track hoppers taken out of service.
+    ///
+    /// Share one registry between pools to get a single view of the machine.
+    #[must_use]
+    pub fn with_service_registry(mut self, registry: ServiceRegistry) -> Self {
+        self.service_registry = Some(registry);
+        self
+    }
+
+    /// Sets the journal the pool records its payouts in.
+    ///
+    /// Defaults to a journal keeping the last
+    /// [`DEFAULT_PAYOUT_JOURNAL_CAPACITY`](super::DEFAULT_PAYOUT_JOURNAL_CAPACITY) payouts.
+    #[must_use]
+    pub fn with_journal(mut self, journal: PayoutJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
     /// Builds the pool.
     ///
     /// You must call [`PayoutPool::initialize`] before using the pool for payout operations.
@@ -75,7 +137,11 @@ impl PayoutPoolBuilder {
             self.selection_strategy.unwrap_or_default(),
             self.polling_interval.unwrap_or(Duration::from_millis(250)),
             self.initially_disabled.unwrap_or_default(),
+            self.service_registry.unwrap_or_default(),
+            self.cancellation_policy.unwrap_or_default(),
+            self.journal.unwrap_or_default(),
         )
+        .with_hopper_coins(self.hopper_coins.unwrap_or_default())
     }
 
     /// Builds and initializes the pool.
@@ -111,9 +177,13 @@ mod tests {
         assert_eq!(pool.hopper_count(), 0);
         assert_eq!(
             pool.selection_strategy(),
-            &HopperSelectionStrategy::LargestFirst
+            &HopperSelectionStrategy::BalanceInventory
         );
         assert_eq!(pool.polling_interval(), Duration::from_millis(250));
+        assert_eq!(
+            pool.cancellation_policy(),
+            CancellationPolicy::EmergencyStop
+        );
     }
 
     #[test]
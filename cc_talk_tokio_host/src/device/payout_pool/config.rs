@@ -1,18 +1,96 @@
+use std::sync::Arc;
+
+use super::{
+    poll_result::HopperInventoryLevel,
+    strategy::{
+        BalanceLeveling, GreedyLargestFirst, GreedySmallestFirst, HopperCandidate, ReserveAware,
+        SelectionStrategy,
+    },
+};
+
 /// Strategy for selecting hoppers during payout operations.
 ///
-/// Determines the order in which hoppers are used when dispensing coins.
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Determines which hoppers are used, and in which order, when dispensing coins. Custom
+/// strategies implementing [`SelectionStrategy`] are plugged in with [`Self::custom`].
+#[derive(Debug, Clone, Default)]
 pub enum HopperSelectionStrategy {
     /// Use hoppers with highest coin value first (greedy algorithm).
     /// This minimizes the number of coins dispensed.
-    #[default]
     LargestFirst,
     /// Use hoppers with lowest coin value first.
     /// This maximizes the number of coins dispensed.
     SmallestFirst,
-    /// Prefer hoppers with highest inventory levels.
-    /// This helps balance inventory across hoppers.
+    /// Use hoppers with highest coin value first and spread the coins of a denomination across
+    /// its hoppers by fill level, see [`BalanceLeveling`].
+    /// This equalizes wear and avoids emptying a single hopper.
+    #[default]
     BalanceInventory,
+    /// Like [`Self::BalanceInventory`], holding back the hoppers at or below the given level
+    /// unless they are needed, see [`ReserveAware`].
+    ReserveAware(HopperInventoryLevel),
+    /// A custom strategy.
+    Custom(Arc<dyn SelectionStrategy>),
+}
+
+impl HopperSelectionStrategy {
+    /// Wraps a custom strategy.
+    pub fn custom(strategy: impl SelectionStrategy + 'static) -> Self {
+        Self::Custom(Arc::new(strategy))
+    }
+}
+
+impl SelectionStrategy for HopperSelectionStrategy {
+    fn plan(&self, value: u32, candidates: &[HopperCandidate]) -> Vec<(u8, u8)> {
+        match self {
+            Self::LargestFirst => GreedyLargestFirst.plan(value, candidates),
+            Self::SmallestFirst => GreedySmallestFirst.plan(value, candidates),
+            Self::BalanceInventory => BalanceLeveling.plan(value, candidates),
+            Self::ReserveAware(reserve) => {
+                ReserveAware::new(BalanceLeveling, *reserve).plan(value, candidates)
+            }
+            Self::Custom(strategy) => strategy.plan(value, candidates),
+        }
+    }
+
+    fn uses_inventory(&self) -> bool {
+        match self {
+            Self::LargestFirst | Self::SmallestFirst => false,
+            Self::BalanceInventory | Self::ReserveAware(_) => true,
+            Self::Custom(strategy) => strategy.uses_inventory(),
+        }
+    }
+}
+
+/// Custom strategies are equal if they are the same instance.
+impl PartialEq for HopperSelectionStrategy {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::LargestFirst, Self::LargestFirst)
+            | (Self::SmallestFirst, Self::SmallestFirst)
+            | (Self::BalanceInventory, Self::BalanceInventory) => true,
+            (Self::ReserveAware(a), Self::ReserveAware(b)) => a == b,
+            (Self::Custom(a), Self::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for HopperSelectionStrategy {}
+
+/// What happens to a payout whose future is dropped before it completes.
+///
+/// Dropping the future never abandons the payout silently, it is either stopped or kept running
+/// in the background, and the result is recorded in the pool
+/// [`PayoutJournal`](super::PayoutJournal) in both cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CancellationPolicy {
+    /// Stop the active hopper with an emergency stop and drop the rest of the payout plan.
+    ///
+    /// The hopper is stopped at the next status poll, coins paid until then are journaled.
+    #[default]
+    EmergencyStop,
+    /// Keep paying out in the background until the payout completes.
+    Detach,
 }
 
 #[cfg(test)]
@@ -20,10 +98,19 @@ mod tests {
     use super::*;
 
     #[test]
-    fn default_strategy_is_largest_first() {
+    fn default_strategy_is_balance_inventory() {
         assert_eq!(
             HopperSelectionStrategy::default(),
-            HopperSelectionStrategy::LargestFirst
+            HopperSelectionStrategy::BalanceInventory
+        );
+        assert!(HopperSelectionStrategy::default().uses_inventory());
+    }
+
+    #[test]
+    fn default_cancellation_policy_is_emergency_stop() {
+        assert_eq!(
+            CancellationPolicy::default(),
+            CancellationPolicy::EmergencyStop
         );
     }
 }
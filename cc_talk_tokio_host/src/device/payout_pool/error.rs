@@ -40,6 +40,10 @@ pub enum PayoutPoolError {
     #[error("payout already in progress")]
     PayoutInProgress,
 
+    /// The payout task was aborted before it completed, e.g. by a runtime shutdown.
+    #[error("payout aborted")]
+    PayoutAborted,
+
     /// All hoppers failed during the operation.
     #[error("all hoppers failed")]
     AllHoppersFailed,
@@ -0,0 +1,163 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use tracing::info;
+
+use crate::transport::correlation::CorrelationId;
+
+use super::poll_result::DispenseProgress;
+
+/// Default number of payouts kept by a [`PayoutJournal`].
+pub const DEFAULT_PAYOUT_JOURNAL_CAPACITY: usize = 32;
+
+/// How a journaled payout ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayoutOutcome {
+    /// The payout ran to completion and its result was returned to the caller.
+    Completed,
+    /// The payout future was dropped and the payout was stopped with an emergency stop, see
+    /// [`CancellationPolicy::EmergencyStop`](super::CancellationPolicy::EmergencyStop).
+    Stopped,
+    /// The payout future was dropped and the payout ran to completion in the background, see
+    /// [`CancellationPolicy::Detach`](super::CancellationPolicy::Detach).
+    Detached,
+}
+
+/// A payout recorded by a [`PayoutJournal`].
+#[derive(Debug, Clone)]
+pub struct PayoutRecord {
+    /// Id of the payout, the commands sent for it are logged within its `payout` span.
+    pub correlation_id: CorrelationId,
+    pub outcome: PayoutOutcome,
+    /// Final progress, `remaining` holds the value which was not paid.
+    pub progress: DispenseProgress,
+    pub finished_at: SystemTime,
+}
+
+/// Bounded journal of the last payouts of a [`PayoutPool`](super::PayoutPool).
+///
+/// Every payout is journaled when it ends, including payouts whose future was dropped by the
+/// caller. Clones of this handle share the same records.
+#[derive(Debug, Clone)]
+pub struct PayoutJournal {
+    records: Arc<Mutex<VecDeque<PayoutRecord>>>,
+    capacity: usize,
+}
+
+impl Default for PayoutJournal {
+    fn default() -> Self {
+        Self::new(DEFAULT_PAYOUT_JOURNAL_CAPACITY)
+    }
+}
+
+impl PayoutJournal {
+    /// Creates a journal keeping the last `capacity` payouts.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Returns the journaled payouts, oldest first.
+    #[must_use]
+    pub fn records(&self) -> Vec<PayoutRecord> {
+        self.records
+            .lock()
+            .expect("should not be poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the last journaled payout.
+    #[must_use]
+    pub fn last(&self) -> Option<PayoutRecord> {
+        self.records
+            .lock()
+            .expect("should not be poisoned")
+            .back()
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.records.lock().expect("should not be poisoned").len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().expect("should not be poisoned").clear();
+    }
+
+    pub(crate) fn record(
+        &self,
+        correlation_id: CorrelationId,
+        outcome: PayoutOutcome,
+        progress: DispenseProgress,
+    ) {
+        info!(
+            %correlation_id,
+            ?outcome,
+            requested = progress.requested,
+            dispensed = progress.dispensed,
+            "payout journaled"
+        );
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().expect("should not be poisoned");
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(PayoutRecord {
+            correlation_id,
+            outcome,
+            progress,
+            finished_at: SystemTime::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_keeps_the_last_payouts() {
+        let journal = PayoutJournal::new(2);
+        let stopped = CorrelationId::next();
+        journal.record(
+            CorrelationId::next(),
+            PayoutOutcome::Completed,
+            DispenseProgress::new(100),
+        );
+        journal.record(stopped, PayoutOutcome::Stopped, DispenseProgress::new(200));
+        journal.record(
+            CorrelationId::next(),
+            PayoutOutcome::Detached,
+            DispenseProgress::new(300),
+        );
+
+        let records = journal.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].correlation_id, stopped);
+        assert_eq!(records[0].outcome, PayoutOutcome::Stopped);
+        assert_eq!(records[0].progress.requested, 200);
+        assert_eq!(
+            journal.last().map(|record| record.outcome),
+            Some(PayoutOutcome::Detached)
+        );
+
+        journal.clear();
+        assert!(journal.is_empty());
+    }
+}
@@ -7,19 +7,25 @@ use std::{
     time::Duration,
 };
 
+use cc_talk_core::cc_talk::CurrencyToken;
 use tokio::sync::mpsc;
-use tracing::{debug, error, info, instrument, trace, warn};
+use tracing::{Instrument, debug, error, info, info_span, instrument, trace, warn};
 
-use crate::device::{base::DeviceCommon, payout::PayoutDevice};
+use crate::{
+    device::{base::DeviceCommon, payout::PayoutDevice, service::ServiceRegistry},
+    transport::correlation::CorrelationId,
+};
 
 use super::{
     PayoutPoolError, PayoutPoolResult,
     builder::PayoutPoolBuilder,
-    config::HopperSelectionStrategy,
+    config::{CancellationPolicy, HopperSelectionStrategy},
     event::PayoutEvent,
+    journal::{PayoutJournal, PayoutOutcome},
     poll_result::{
         DispenseProgress, HopperInventory, HopperInventoryLevel, HopperPollError, PayoutPollResult,
     },
+    strategy::{HopperCandidate, SelectionStrategy, planned_value},
 };
 
 /// Maximum number of consecutive failures before giving up on a hopper.
@@ -37,6 +43,13 @@ const MAX_FAILURES: u8 = 5;
 /// - Automatic payout plan rebalancing when hoppers run empty
 /// - Emergency stop coordination
 ///
+/// # Cancellation
+///
+/// A payout runs in its own task, dropping the future returned by [`PayoutPool::payout`]
+/// never leaves a hopper dispensing unattended. The [`CancellationPolicy`] decides whether the
+/// payout is stopped or kept running in the background, in both cases its result is recorded
+/// in the [`PayoutJournal`].
+///
 /// # Cloning
 ///
 /// `PayoutPool` implements [`Clone`] and shares its internal state
@@ -63,12 +76,19 @@ pub struct PayoutPool {
     hoppers: Vec<PayoutDevice>,
     /// Maps hopper address -> coin value.
     hopper_values: HashMap<u8, u32>,
+    /// Maps hopper address -> coin the hopper must hold, checked before dispensing.
+    hopper_coins: HashMap<u8, CurrencyToken>,
     /// Set of hopper addresses that are disabled at the pool level.
     disabled_hoppers: Arc<Mutex<HashSet<u8>>>,
     selection_strategy: HopperSelectionStrategy,
     polling_interval: Duration,
     initialized: Arc<AtomicBool>,
     is_dispensing: Arc<AtomicBool>,
+    /// Address of the hopper the payout in progress dispenses from.
+    active_hopper: Arc<Mutex<Option<u8>>>,
+    service: ServiceRegistry,
+    cancellation_policy: CancellationPolicy,
+    journal: PayoutJournal,
 }
 
 impl PayoutPool {
@@ -86,6 +106,9 @@ impl PayoutPool {
         selection_strategy: HopperSelectionStrategy,
         polling_interval: Duration,
         initially_disabled: HashSet<u8>,
+        service: ServiceRegistry,
+        cancellation_policy: CancellationPolicy,
+        journal: PayoutJournal,
     ) -> Self {
         let mut hopper_values = HashMap::new();
         let mut hopper_devices = Vec::with_capacity(hoppers.len());
@@ -101,20 +124,31 @@ impl PayoutPool {
             selection_strategy = ?selection_strategy,
             polling_interval_ms = polling_interval.as_millis() as u64,
             initially_disabled = ?initially_disabled,
+            cancellation_policy = ?cancellation_policy,
             "creating payout pool"
         );
 
         Self {
             hoppers: hopper_devices,
             hopper_values,
+            hopper_coins: HashMap::new(),
             disabled_hoppers: Arc::new(Mutex::new(initially_disabled)),
             selection_strategy,
             polling_interval,
             initialized: Arc::new(AtomicBool::new(false)),
             is_dispensing: Arc::new(AtomicBool::new(false)),
+            active_hopper: Arc::new(Mutex::new(None)),
+            service,
+            cancellation_policy,
+            journal,
         }
     }
 
+    pub(super) fn with_hopper_coins(mut self, hopper_coins: HashMap<u8, CurrencyToken>) -> Self {
+        self.hopper_coins = hopper_coins;
+        self
+    }
+
     /// Returns the number of hoppers in the pool.
     #[must_use]
     pub fn hopper_count(&self) -> usize {
@@ -139,6 +173,24 @@ impl PayoutPool {
         self.polling_interval
     }
 
+    /// Returns what happens to a payout whose future is dropped.
+    #[must_use]
+    pub const fn cancellation_policy(&self) -> CancellationPolicy {
+        self.cancellation_policy
+    }
+
+    /// Returns the journal the payouts are recorded in.
+    #[must_use]
+    pub const fn journal(&self) -> &PayoutJournal {
+        &self.journal
+    }
+
+    /// Returns the service registry used to track quiesced hoppers.
+    #[must_use]
+    pub const fn service_registry(&self) -> &ServiceRegistry {
+        &self.service
+    }
+
     /// Returns the addresses of all hoppers in the pool.
     #[must_use]
     pub fn hopper_addresses(&self) -> Vec<u8> {
@@ -217,31 +269,51 @@ impl PayoutPool {
             .clone()
     }
 
-    /// Returns the available hoppers filtered to exclude disabled and extra exclusions.
+    /// Returns the available hoppers filtered to exclude disabled, out of service and extra
+    /// exclusions.
     ///
-    /// Returns `(address, coin_value)` pairs sorted by the selection strategy.
-    fn available_hopper_values(&self, extra_exclusions: &HashSet<u8>) -> Vec<(u8, u32)> {
+    /// The candidates are sorted by address, ordering them is up to the selection strategy.
+    fn available_hoppers(
+        &self,
+        extra_exclusions: &HashSet<u8>,
+        levels: &HashMap<u8, HopperInventoryLevel>,
+    ) -> Vec<HopperCandidate> {
         let disabled = self
             .disabled_hoppers
             .lock()
             .expect("should not be poisoned");
-        let mut hoppers: Vec<(u8, u32)> = self
+        let mut hoppers: Vec<HopperCandidate> = self
             .hopper_values
             .iter()
-            .filter(|(addr, _)| !disabled.contains(addr) && !extra_exclusions.contains(addr))
-            .map(|(&addr, &val)| (addr, val))
+            .filter(|(addr, _)| {
+                !disabled.contains(addr)
+                    && !extra_exclusions.contains(addr)
+                    && !self.service.is_out_of_service(**addr)
+            })
+            .map(|(&addr, &val)| {
+                let level = levels.get(&addr).copied().unwrap_or_default();
+                HopperCandidate::new(addr, val, level)
+            })
             .collect();
+        hoppers.sort_by_key(|candidate| candidate.address);
+        hoppers
+    }
 
-        match self.selection_strategy {
-            HopperSelectionStrategy::LargestFirst | HopperSelectionStrategy::BalanceInventory => {
-                hoppers.sort_by(|a, b| b.1.cmp(&a.1));
-            }
-            HopperSelectionStrategy::SmallestFirst => {
-                hoppers.sort_by(|a, b| a.1.cmp(&b.1));
-            }
+    /// Returns the fill level of the hoppers if the selection strategy uses them.
+    async fn inventory_levels(&self) -> HashMap<u8, HopperInventoryLevel> {
+        if !self.selection_strategy.uses_inventory() {
+            return HashMap::new();
         }
-
-        hoppers
+        let result = self.poll_inventories().await;
+        self.hoppers
+            .iter()
+            .filter_map(|hopper| {
+                let address = hopper.device.address();
+                result
+                    .get_by_address(address)
+                    .map(|inventory| (address, inventory.level))
+            })
+            .collect()
     }
 
     /// Initializes the pool by verifying all hoppers are responsive.
@@ -298,12 +370,17 @@ impl PayoutPool {
     ///
     /// Polls all hoppers including disabled ones, since physical
     /// monitoring is still useful regardless of pool-level state.
+    /// Hoppers which are out of service may be physically removed and are skipped.
     #[instrument(skip(self))]
     pub async fn poll_inventories(&self) -> PayoutPollResult {
         let mut result = PayoutPollResult::new();
 
         for hopper in &self.hoppers {
             let address = hopper.device.address();
+            if self.service.is_out_of_service(address) {
+                trace!(address, "hopper out of service, skipping inventory poll");
+                continue;
+            }
             let value = self.hopper_values.get(&address).copied().unwrap_or(0);
 
             match hopper.get_sensor_status().await {
@@ -349,11 +426,11 @@ impl PayoutPool {
 
     /// Calculates whether the requested value can be dispensed with enabled hoppers.
     ///
-    /// Note: This is a theoretical check assuming unlimited coins in each hopper.
-    /// Actual availability depends on hopper inventory.
+    /// Note: This is a theoretical check assuming unlimited coins in each hopper, the fill
+    /// levels are not polled. Actual availability depends on hopper inventory.
     #[must_use]
     pub fn can_payout(&self, value: u32) -> bool {
-        let available = self.available_hopper_values(&HashSet::new());
+        let available = self.available_hoppers(&HashSet::new(), &HashMap::new());
         self.generate_payout_plan(value, &available).1 == 0
     }
 
@@ -371,6 +448,59 @@ impl PayoutPool {
         Ok(())
     }
 
+    /// Quiesces a single hopper so a technician can safely remove it.
+    ///
+    /// This method:
+    /// 1. Aborts the dispense of the hopper if the payout in progress dispenses from it, the
+    ///    running payout replans the remaining value on the other hoppers. Payouts using
+    ///    other hoppers are not stopped
+    /// 2. Disables the hopper
+    /// 3. Marks the hopper out of service, payouts no longer plan with it
+    /// 4. Stores the hopper counters to EEPROM
+    ///
+    /// The hopper stays out of service until [`ServiceRegistry::return_to_service`] is called.
+    /// If it cannot be disabled it is left in service, quiescing it can be retried.
+    ///
+    /// Returns the number of coins left unpaid by the aborted dispense, zero if the hopper
+    /// was idle.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PayoutPoolError::HopperNotFound`] if the address is not in the pool, or
+    /// [`PayoutPoolError::CommandError`] if the hopper could not be disabled.
+    #[instrument(skip(self), fields(address))]
+    pub async fn quiesce(&self, address: u8) -> PayoutPoolResult<u8> {
+        let hopper = self.get_hopper(address)?;
+
+        let mut unpaid = 0;
+        if *self.active_hopper.lock().expect("should not be poisoned") == Some(address) {
+            match hopper.emergency_stop().await {
+                Ok(coins) => {
+                    unpaid = coins;
+                    if coins > 0 {
+                        warn!(address, unpaid = coins, "in-flight dispense aborted");
+                    }
+                }
+                Err(e) => {
+                    error!(address, error = %e, "failed to abort in-flight dispense");
+                }
+            }
+        }
+
+        hopper
+            .disable_hopper()
+            .await
+            .map_err(|error| PayoutPoolError::CommandError { address, error })?;
+        self.service.mark_out_of_service(address);
+
+        if let Err(e) = hopper.store_counters().await {
+            warn!(address, error = %e, "failed to store hopper counters");
+        }
+
+        info!(address, unpaid, "hopper quiesced");
+        Ok(unpaid)
+    }
+
     /// Dispenses the specified value from the pool.
     ///
     /// Uses the configured selection strategy to choose hoppers, respects
@@ -385,9 +515,29 @@ impl PayoutPool {
     /// # Returns
     ///
     /// Returns the final dispense progress showing what was actually dispensed.
+    ///
+    /// # Cancellation
+    ///
+    /// Dropping the returned future applies the pool [`CancellationPolicy`], the payout is
+    /// journaled either way.
     #[instrument(skip(self), fields(value))]
     pub async fn payout(&self, value: u32) -> PayoutPoolResult<DispenseProgress> {
-        self.payout_guarded(value, None).await
+        self.payout_guarded(value, None, None).await
+    }
+
+    /// Dispenses the specified value like [`payout`](Self::payout) and hands the final progress
+    /// to `settle` when the payout ends.
+    ///
+    /// `settle` runs in the payout task, also when the returned future is dropped, and with
+    /// nothing dispensed if another payout is in progress.
+    #[instrument(skip(self, settle), fields(value))]
+    pub(crate) async fn payout_settled(
+        &self,
+        value: u32,
+        settle: impl FnOnce(&DispenseProgress) + Send + 'static,
+    ) -> PayoutPoolResult<DispenseProgress> {
+        self.payout_guarded(value, None, Some(Box::new(settle)))
+            .await
     }
 
     /// Dispenses the specified value with event notifications.
@@ -405,28 +555,73 @@ impl PayoutPool {
         value: u32,
         event_tx: mpsc::Sender<PayoutEvent>,
     ) -> PayoutPoolResult<DispenseProgress> {
-        self.payout_guarded(value, Some(event_tx)).await
+        self.payout_guarded(value, Some(event_tx), None).await
     }
 
     /// Guards payout with the dispensing lock.
+    ///
+    /// The payout runs in a spawned task so it outlives the caller future, the task releases
+    /// the dispensing lock, journals the result and settles it.
     async fn payout_guarded(
         &self,
         value: u32,
         event_tx: Option<mpsc::Sender<PayoutEvent>>,
+        settle: Option<Settle>,
     ) -> PayoutPoolResult<DispenseProgress> {
         if self
             .is_dispensing
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
             .is_err()
         {
+            if let Some(settle) = settle {
+                settle(&DispenseProgress::new(value));
+            }
             return Err(PayoutPoolError::PayoutInProgress);
         }
+        let lock = DispensingLock(Arc::clone(&self.is_dispensing));
+
+        let mut guard = CancellationGuard {
+            policy: self.cancellation_policy,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            detached: Arc::new(AtomicBool::new(false)),
+            armed: true,
+        };
+        let pool = self.clone();
+        let cancelled = Arc::clone(&guard.cancelled);
+        let detached = Arc::clone(&guard.detached);
+        let correlation_id = CorrelationId::next();
+        let span = info_span!("payout", correlation_id = %correlation_id);
+        let task = tokio::spawn(
+            async move {
+                let _lock = lock;
+                let progress = pool.payout_inner(value, &event_tx, &cancelled).await;
+                let outcome = if cancelled.load(Ordering::Acquire) {
+                    PayoutOutcome::Stopped
+                } else if detached.load(Ordering::Acquire) {
+                    PayoutOutcome::Detached
+                } else {
+                    PayoutOutcome::Completed
+                };
+                pool.journal
+                    .record(correlation_id, outcome, progress.clone());
+                if let Some(settle) = settle {
+                    settle(&progress);
+                }
+                progress
+            }
+            .instrument(span),
+        );
 
-        let result = self.payout_inner(value, &event_tx).await;
-
-        self.is_dispensing.store(false, Ordering::Release);
-
-        result
+        let result = task.await;
+        guard.armed = false;
+        match result {
+            Ok(progress) => Ok(progress),
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => {
+                error!(value, error = %e, "payout task aborted");
+                Err(PayoutPoolError::PayoutAborted)
+            }
+        }
     }
 
     /// Internal payout implementation.
@@ -437,14 +632,16 @@ impl PayoutPool {
         &self,
         value: u32,
         event_tx: &Option<mpsc::Sender<PayoutEvent>>,
-    ) -> PayoutPoolResult<DispenseProgress> {
+        cancelled: &AtomicBool,
+    ) -> DispenseProgress {
         info!(value, "starting payout");
 
         let mut progress = DispenseProgress::new(value);
         let mut exhausted_hoppers = HashSet::new();
 
         // Build available hoppers (respecting disabled)
-        let available_hoppers = self.available_hopper_values(&exhausted_hoppers);
+        let levels = self.inventory_levels().await;
+        let available_hoppers = self.available_hoppers(&exhausted_hoppers, &levels);
 
         // Generate initial plan (Vec preserves strategy order)
         let (mut plan, remainder) = self.generate_payout_plan(value, &available_hoppers);
@@ -460,6 +657,14 @@ impl PayoutPool {
         while let Some((address, count)) = plan.first().copied() {
             plan.remove(0);
 
+            if cancelled.load(Ordering::Acquire) {
+                warn!(
+                    remaining = progress.remaining,
+                    "payout cancelled, dropping the rest of the plan"
+                );
+                break;
+            }
+
             let Some(hopper) = self.hoppers.iter().find(|h| h.device.address() == address) else {
                 warn!(address, "hopper not found in pool");
                 continue;
@@ -474,11 +679,20 @@ impl PayoutPool {
             emit_event(event_tx, PayoutEvent::Progress(progress.clone()));
 
             // Dispense coins from this hopper
+            *self.active_hopper.lock().expect("should not be poisoned") = Some(address);
             let dispensed = self
-                .dispense_from_hopper(hopper, count, coin_value, &mut progress, event_tx)
+                .dispense_from_hopper(
+                    hopper,
+                    count,
+                    coin_value,
+                    &mut progress,
+                    event_tx,
+                    cancelled,
+                )
                 .await;
+            *self.active_hopper.lock().expect("should not be poisoned") = None;
 
-            if dispensed < count {
+            if dispensed < count && !cancelled.load(Ordering::Acquire) {
                 // Hopper ran empty or failed — mark as exhausted and replan
                 warn!(
                     address,
@@ -500,7 +714,8 @@ impl PayoutPool {
 
                 // Replan with remaining value, excluding exhausted AND disabled
                 if progress.remaining > 0 {
-                    let available = self.available_hopper_values(&exhausted_hoppers);
+                    let levels = self.inventory_levels().await;
+                    let available = self.available_hoppers(&exhausted_hoppers, &levels);
                     if !available.is_empty() {
                         let (new_plan, _) =
                             self.generate_payout_plan(progress.remaining, &available);
@@ -537,10 +752,13 @@ impl PayoutPool {
             "payout completed"
         );
 
-        Ok(progress)
+        progress
     }
 
     /// Dispenses coins from a single hopper, polling for completion.
+    ///
+    /// If the payout is cancelled the hopper is stopped at the next poll, the coins paid until
+    /// the stop are still counted.
     async fn dispense_from_hopper(
         &self,
         hopper: &PayoutDevice,
@@ -548,11 +766,21 @@ impl PayoutPool {
         coin_value: u32,
         progress: &mut DispenseProgress,
         event_tx: &Option<mpsc::Sender<PayoutEvent>>,
+        cancelled: &AtomicBool,
     ) -> u8 {
         let address = hopper.device.address();
         let mut dispensed: u8 = 0;
         let mut failures: u8 = 0;
 
+        // The coin is read once and cached by the driver until the hopper resets.
+        if let Some(expected) = self.hopper_coins.get(&address)
+            && let Err(e) = hopper.check_hopper_coin(expected).await
+        {
+            error!(address, count, error = %e, "hopper coin not verified, not dispensing");
+            emit_event(event_tx, PayoutEvent::HopperError { address, error: e });
+            return 0;
+        }
+
         if let Err(e) = hopper.enable_hopper().await {
             error!(address, count, error = %e, "failed to enable hopper");
             emit_event(event_tx, PayoutEvent::HopperError { address, error: e });
@@ -573,6 +801,14 @@ impl PayoutPool {
         while remaining > 0 && failures < MAX_FAILURES {
             interval.tick().await;
 
+            let stopping = cancelled.load(Ordering::Acquire);
+            if stopping {
+                warn!(address, "payout cancelled, stopping hopper");
+                if let Err(e) = hopper.emergency_stop().await {
+                    error!(address, error = %e, "failed to stop hopper");
+                }
+            }
+
             match hopper.get_payout_status().await {
                 Ok(status) => {
                     failures = 0;
@@ -610,6 +846,10 @@ impl PayoutPool {
                     }
                 }
             }
+
+            if stopping {
+                break;
+            }
         }
 
         if let Err(e) = hopper.disable_hopper().await {
@@ -625,34 +865,18 @@ impl PayoutPool {
         dispensed
     }
 
-    /// Generates a payout plan using the greedy algorithm.
+    /// Generates a payout plan with the selection strategy.
     ///
     /// Returns `(plan, remainder)` where plan is a list of `(hopper_address, coin_count)`
     /// pairs in strategy order, and remainder is the value that couldn't be dispensed.
     fn generate_payout_plan(
         &self,
         value: u32,
-        available_hoppers: &[(u8, u32)],
+        available_hoppers: &[HopperCandidate],
     ) -> (Vec<(u8, u8)>, u32) {
-        let mut plan = Vec::new();
-        let mut remaining = value;
-
-        // available_hoppers is already sorted by selection strategy
-        for &(address, coin_value) in available_hoppers {
-            if coin_value == 0 || remaining == 0 {
-                continue;
-            }
-
-            let quantity = remaining / coin_value;
-            if quantity > 0 {
-                // Cap at u8::MAX per dispense command
-                let capped_quantity = quantity.min(u8::MAX as u32) as u8;
-                plan.push((address, capped_quantity));
-                remaining -= u32::from(capped_quantity) * coin_value;
-            }
-        }
-
-        (plan, remaining)
+        let plan = self.selection_strategy.plan(value, available_hoppers);
+        let remainder = value.saturating_sub(planned_value(&plan, available_hoppers));
+        (plan, remainder)
     }
 
     /// Gets a reference to a hopper by address.
@@ -664,6 +888,45 @@ impl PayoutPool {
     }
 }
 
+/// Receives the final progress of a payout, see [`PayoutPool::payout_settled`].
+type Settle = Box<dyn FnOnce(&DispenseProgress) + Send>;
+
+/// Releases the pool dispensing lock when the payout task ends, even if it panics.
+struct DispensingLock(Arc<AtomicBool>);
+
+impl Drop for DispensingLock {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Applies the [`CancellationPolicy`] if the payout future is dropped before the payout task
+/// completes.
+struct CancellationGuard {
+    policy: CancellationPolicy,
+    cancelled: Arc<AtomicBool>,
+    detached: Arc<AtomicBool>,
+    armed: bool,
+}
+
+impl Drop for CancellationGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        match self.policy {
+            CancellationPolicy::EmergencyStop => {
+                warn!("payout future dropped, stopping the payout");
+                self.cancelled.store(true, Ordering::Release);
+            }
+            CancellationPolicy::Detach => {
+                warn!("payout future dropped, the payout continues in the background");
+                self.detached.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
 /// Conditionally emits an event if a sender is available.
 fn emit_event(event_tx: &Option<mpsc::Sender<PayoutEvent>>, event: PayoutEvent) {
     if let Some(tx) = event_tx {
@@ -674,9 +937,11 @@ fn emit_event(event_tx: &Option<mpsc::Sender<PayoutEvent>>, event: PayoutEvent)
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cc_talk_core::cc_talk::{Category, ChecksumType, Device};
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Device, Header};
     use tokio::sync::mpsc;
 
+    use crate::{device::payout_pool::PayoutRecord, transport::tokio_transport::TransportMessage};
+
     fn create_test_pool() -> PayoutPool {
         let (tx, _rx) = mpsc::channel(1);
 
@@ -695,6 +960,9 @@ mod tests {
             HopperSelectionStrategy::LargestFirst,
             Duration::from_millis(250),
             HashSet::new(),
+            ServiceRegistry::new(),
+            CancellationPolicy::default(),
+            PayoutJournal::default(),
         )
     }
 
@@ -732,7 +1000,7 @@ mod tests {
     #[test]
     fn generate_payout_plan_largest_first() {
         let pool = create_test_pool();
-        let available = pool.available_hopper_values(&HashSet::new());
+        let available = pool.available_hoppers(&HashSet::new(), &HashMap::new());
 
         // 170 = 1x100 + 1x50 + 1x20
         let (plan, remainder) = pool.generate_payout_plan(170, &available);
@@ -758,7 +1026,7 @@ mod tests {
     #[test]
     fn generate_payout_plan_preserves_strategy_order() {
         let pool = create_test_pool();
-        let available = pool.available_hopper_values(&HashSet::new());
+        let available = pool.available_hoppers(&HashSet::new(), &HashMap::new());
 
         // With LargestFirst, the plan should be ordered: 100, 50, 20
         let (plan, _) = pool.generate_payout_plan(170, &available);
@@ -787,9 +1055,12 @@ mod tests {
             HopperSelectionStrategy::SmallestFirst,
             Duration::from_millis(250),
             HashSet::new(),
+            ServiceRegistry::new(),
+            CancellationPolicy::default(),
+            PayoutJournal::default(),
         );
 
-        let available = pool.available_hopper_values(&HashSet::new());
+        let available = pool.available_hoppers(&HashSet::new(), &HashMap::new());
 
         // 100 = 5x20 with smallest first
         let (plan, remainder) = pool.generate_payout_plan(100, &available);
@@ -797,6 +1068,38 @@ mod tests {
         assert!(plan.contains(&(5, 5))); // 5x20 = 100
     }
 
+    #[test]
+    fn generate_payout_plan_balances_fill_levels() {
+        let (tx, _rx) = mpsc::channel(1);
+        let hoppers = [3, 4, 5].map(|address| {
+            let hopper = PayoutDevice::new(
+                Device::new(address, Category::Payout, ChecksumType::Crc8),
+                tx.clone(),
+            );
+            (hopper, 100)
+        });
+        let pool = PayoutPool::new(
+            hoppers.into(),
+            HopperSelectionStrategy::default(),
+            Duration::from_millis(250),
+            HashSet::new(),
+            ServiceRegistry::new(),
+            CancellationPolicy::default(),
+            PayoutJournal::default(),
+        );
+        let levels = HashMap::from([
+            (3, HopperInventoryLevel::Low),
+            (4, HopperInventoryLevel::High),
+            (5, HopperInventoryLevel::Empty),
+        ]);
+        let available = pool.available_hoppers(&HashSet::new(), &levels);
+
+        // The coins are spread 3:1 between the full and the low hopper, the empty one is spared.
+        let (plan, remainder) = pool.generate_payout_plan(800, &available);
+        assert_eq!(remainder, 0);
+        assert_eq!(plan, vec![(4, 6), (3, 2)]);
+    }
+
     #[test]
     fn can_payout_exact_amount() {
         let pool = create_test_pool();
@@ -835,6 +1138,41 @@ mod tests {
         assert!(matches!(result, Err(PayoutPoolError::HopperNotFound(99))));
     }
 
+    #[tokio::test]
+    async fn quiesce_hopper_not_found() {
+        let pool = create_test_pool();
+        let result = pool.quiesce(99).await;
+        assert!(matches!(result, Err(PayoutPoolError::HopperNotFound(99))));
+        assert!(pool.service_registry().out_of_service().is_empty());
+    }
+
+    #[tokio::test]
+    async fn quiesce_keeps_the_hopper_in_service_when_it_cannot_be_disabled() {
+        // The hoppers of the test pool do not answer
+        let pool = create_test_pool();
+        let result = pool.quiesce(3).await;
+        assert!(matches!(
+            result,
+            Err(PayoutPoolError::CommandError { address: 3, .. })
+        ));
+        assert!(pool.service_registry().out_of_service().is_empty());
+        assert!(pool.can_payout(100));
+    }
+
+    #[test]
+    fn can_payout_respects_out_of_service_hoppers() {
+        let pool = create_test_pool();
+        assert!(pool.can_payout(170));
+
+        // Without the 20-cent hopper 170 cannot be paid exactly
+        pool.service_registry().mark_out_of_service(5);
+        assert!(!pool.can_payout(170));
+        assert!(pool.can_payout(150));
+
+        pool.service_registry().return_to_service(5);
+        assert!(pool.can_payout(170));
+    }
+
     #[test]
     fn enable_hopper_not_found() {
         let pool = create_test_pool();
@@ -865,33 +1203,33 @@ mod tests {
     }
 
     #[test]
-    fn available_hopper_values_excludes_disabled() {
+    fn available_hoppers_excludes_disabled() {
         let pool = create_test_pool();
 
         // All available
-        let available = pool.available_hopper_values(&HashSet::new());
+        let available = pool.available_hoppers(&HashSet::new(), &HashMap::new());
         assert_eq!(available.len(), 3);
 
         // Disable hopper 4
         pool.disable_hopper(4).expect("should succeed");
-        let available = pool.available_hopper_values(&HashSet::new());
+        let available = pool.available_hoppers(&HashSet::new(), &HashMap::new());
         assert_eq!(available.len(), 2);
-        assert!(!available.iter().any(|(a, _)| *a == 4));
-        assert!(available.iter().any(|(a, _)| *a == 3));
-        assert!(available.iter().any(|(a, _)| *a == 5));
+        assert!(!available.iter().any(|c| c.address == 4));
+        assert!(available.iter().any(|c| c.address == 3));
+        assert!(available.iter().any(|c| c.address == 5));
     }
 
     #[test]
-    fn available_hopper_values_excludes_extra() {
+    fn available_hoppers_excludes_extra() {
         let pool = create_test_pool();
 
         let mut extra = HashSet::new();
         extra.insert(3);
         extra.insert(5);
 
-        let available = pool.available_hopper_values(&extra);
+        let available = pool.available_hoppers(&extra, &HashMap::new());
         assert_eq!(available.len(), 1);
-        assert!(available.iter().any(|(a, _)| *a == 4));
+        assert!(available.iter().any(|c| c.address == 4));
     }
 
     #[test]
@@ -912,6 +1250,9 @@ mod tests {
             HopperSelectionStrategy::LargestFirst,
             Duration::from_millis(250),
             initially_disabled,
+            ServiceRegistry::new(),
+            CancellationPolicy::default(),
+            PayoutJournal::default(),
         );
 
         assert!(pool.is_hopper_disabled(3));
@@ -919,6 +1260,199 @@ mod tests {
         assert_eq!(pool.disabled_hoppers().len(), 1);
     }
 
+    /// Hopper at address 3 paying one coin per status poll, returns whether it was stopped.
+    fn spawn_hopper(mut rx: mpsc::Receiver<TransportMessage>) -> tokio::task::JoinHandle<bool> {
+        tokio::spawn(async move {
+            let (mut remaining, mut paid, mut stopped) = (0u8, 0u8, false);
+            while let Some(message) = rx.recv().await {
+                let data = match message.header {
+                    Header::RequestSerialNumber => vec![1, 2, 3],
+                    Header::DispenseHopperCoins => {
+                        remaining = *message.data.last().expect("should have coins");
+                        vec![1]
+                    }
+                    Header::RequestHopperStatus => {
+                        if remaining > 0 {
+                            remaining -= 1;
+                            paid += 1;
+                        }
+                        vec![1, remaining, paid, 0]
+                    }
+                    Header::EmergencyStop => {
+                        stopped = true;
+                        vec![std::mem::take(&mut remaining)]
+                    }
+                    Header::EnableHopper | Header::CountersToEEPROM => vec![],
+                    header => panic!("unexpected header {header:?}"),
+                };
+                let mut reply = vec![1, data.len() as u8, 3, 0];
+                reply.extend(data);
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+            stopped
+        })
+    }
+
+    fn create_single_hopper_pool(
+        tx: mpsc::Sender<TransportMessage>,
+        cancellation_policy: CancellationPolicy,
+    ) -> PayoutPool {
+        let hopper = PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx);
+        // The hopper has no level sensors.
+        PayoutPool::builder()
+            .add_hopper(hopper, 100)
+            .selection_strategy(HopperSelectionStrategy::LargestFirst)
+            .polling_interval(Duration::from_millis(5))
+            .cancellation_policy(cancellation_policy)
+            .build()
+    }
+
+    /// Waits for the payout to be journaled, the clock is paused and advances by itself while
+    /// the tasks are idle.
+    async fn wait_for_journal(pool: &PayoutPool) -> PayoutRecord {
+        for _ in 0..200 {
+            if let Some(record) = pool.journal().last() {
+                return record;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("payout was not journaled");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_payout_is_stopped_and_journaled() {
+        let (tx, rx) = mpsc::channel(1);
+        let hopper = spawn_hopper(rx);
+        let pool = create_single_hopper_pool(tx, CancellationPolicy::EmergencyStop);
+
+        let payout = tokio::time::timeout(Duration::from_millis(20), pool.payout(10_000)).await;
+        assert!(payout.is_err(), "payout should still be running");
+
+        let record = wait_for_journal(&pool).await;
+        assert_eq!(record.outcome, PayoutOutcome::Stopped);
+        assert!(record.progress.dispensed > 0);
+        assert!(record.progress.remaining > 0);
+        assert_eq!(
+            record.progress.dispensed + record.progress.remaining,
+            10_000
+        );
+        assert!(!pool.is_dispensing.load(Ordering::Acquire));
+
+        drop(pool);
+        assert!(hopper.await.expect("should join"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_payout_is_detached_and_journaled() {
+        let (tx, rx) = mpsc::channel(1);
+        let hopper = spawn_hopper(rx);
+        let pool = create_single_hopper_pool(tx, CancellationPolicy::Detach);
+
+        let payout = tokio::time::timeout(Duration::from_millis(10), pool.payout(500)).await;
+        assert!(payout.is_err(), "payout should still be running");
+
+        let record = wait_for_journal(&pool).await;
+        assert_eq!(record.outcome, PayoutOutcome::Detached);
+        assert_eq!(record.progress.dispensed, 500);
+        assert_eq!(record.progress.coins_count(), 5);
+
+        drop(pool);
+        assert!(!hopper.await.expect("should join"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn quiesce_does_not_stop_a_payout_from_another_hopper() {
+        let (tx_3, rx_3) = mpsc::channel(1);
+        let (tx_4, rx_4) = mpsc::channel(1);
+        let hopper_3 = spawn_hopper(rx_3);
+        let hopper_4 = spawn_hopper(rx_4);
+        let pool = PayoutPool::builder()
+            .add_hopper(
+                PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx_3),
+                100,
+            )
+            .add_hopper(
+                PayoutDevice::new(Device::new(4, Category::Payout, ChecksumType::Crc8), tx_4),
+                50,
+            )
+            .selection_strategy(HopperSelectionStrategy::LargestFirst)
+            .polling_interval(Duration::from_millis(5))
+            .build();
+
+        let payout = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.payout(500).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(pool.quiesce(4).await.expect("should quiesce"), 0);
+        assert_eq!(pool.service_registry().out_of_service(), [4]);
+
+        let progress = payout.await.expect("should join").expect("should pay out");
+        assert_eq!(progress.dispensed, 500);
+        assert!(progress.empty_hoppers.is_empty());
+
+        drop(pool);
+        assert!(!hopper_3.await.expect("should join"));
+        assert!(!hopper_4.await.expect("should join"));
+    }
+
+    #[tokio::test]
+    async fn hopper_holding_another_coin_does_not_dispense() {
+        use cc_talk_core::cc_talk::CurrencyToken;
+
+        use crate::device::base::CommandError;
+
+        let (tx, mut rx) = mpsc::channel::<TransportMessage>(1);
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let data = match message.header {
+                    Header::RequestHopperCoin => b"EU050A".to_vec(),
+                    Header::EnableHopper | Header::DispenseHopperCoins => {
+                        panic!("should not dispense")
+                    }
+                    _ => vec![],
+                };
+                let mut reply = vec![1, data.len() as u8, 3, 0];
+                reply.extend(data);
+                reply.push(0);
+                message.respond_to.send(Ok(reply)).expect("should respond");
+            }
+        });
+        let hopper = PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx);
+        let euro = CurrencyToken::build("EU100A").expect("should build");
+        let pool = PayoutPool::builder()
+            .add_hopper_with_coin(hopper, 100, euro.clone())
+            .polling_interval(Duration::from_millis(5))
+            .build();
+
+        let (event_tx, mut event_rx) = mpsc::channel(16);
+        let progress = pool
+            .payout_with_events(200, event_tx)
+            .await
+            .expect("should run the payout");
+        assert_eq!(progress.dispensed, 0);
+        assert_eq!(progress.empty_hoppers, [3]);
+
+        let mut errors = Vec::new();
+        while let Ok(event) = event_rx.try_recv() {
+            if let PayoutEvent::HopperError { address, error } = event {
+                errors.push((address, error));
+            }
+        }
+        assert_eq!(
+            errors,
+            [(
+                3,
+                CommandError::WrongCoin {
+                    address: 3,
+                    expected: euro,
+                    loaded: CurrencyToken::build("EU050A").expect("should build"),
+                }
+            )]
+        );
+    }
+
     #[test]
     fn pool_is_clone() {
         let pool = create_test_pool();
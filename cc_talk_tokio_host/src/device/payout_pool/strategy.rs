@@ -0,0 +1,310 @@
+use std::{cmp::Reverse, fmt};
+
+use super::poll_result::HopperInventoryLevel;
+
+/// A hopper the payout can be planned on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopperCandidate {
+    pub address: u8,
+    pub coin_value: u32,
+    /// Fill level from the last inventory poll, [`HopperInventoryLevel::Unknown`] if the
+    /// strategy does not use levels or the poll failed.
+    pub level: HopperInventoryLevel,
+}
+
+impl HopperCandidate {
+    #[must_use]
+    pub const fn new(address: u8, coin_value: u32, level: HopperInventoryLevel) -> Self {
+        Self {
+            address,
+            coin_value,
+            level,
+        }
+    }
+}
+
+/// Splits a payout value across the available hoppers.
+///
+/// The [`PayoutPool`](super::PayoutPool) plans every payout, and replans whenever a hopper runs
+/// empty, with its strategy. The built-in strategies are selected through
+/// [`HopperSelectionStrategy`](super::HopperSelectionStrategy), custom ones are plugged in with
+/// [`HopperSelectionStrategy::custom`](super::HopperSelectionStrategy::custom).
+pub trait SelectionStrategy: fmt::Debug + Send + Sync {
+    /// Returns the `(hopper_address, coin_count)` pairs to dispense, in dispense order.
+    ///
+    /// Each count is at most [`u8::MAX`], a hopper appears at most once. The value left once
+    /// the plan is dispensed is the remainder the pool can't pay out.
+    fn plan(&self, value: u32, candidates: &[HopperCandidate]) -> Vec<(u8, u8)>;
+
+    /// Returns `true` if the strategy needs the fill levels of the candidates, the pool then
+    /// polls the hopper sensors before planning.
+    fn uses_inventory(&self) -> bool {
+        false
+    }
+}
+
+/// Pays out with the largest coins first, minimizing the number of coins dispensed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GreedyLargestFirst;
+
+impl SelectionStrategy for GreedyLargestFirst {
+    fn plan(&self, value: u32, candidates: &[HopperCandidate]) -> Vec<(u8, u8)> {
+        let mut ordered: Vec<_> = candidates.iter().collect();
+        ordered.sort_by_key(|candidate| (Reverse(candidate.coin_value), candidate.address));
+        greedy(value, ordered)
+    }
+}
+
+/// Pays out with the smallest coins first, maximizing the number of coins dispensed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GreedySmallestFirst;
+
+impl SelectionStrategy for GreedySmallestFirst {
+    fn plan(&self, value: u32, candidates: &[HopperCandidate]) -> Vec<(u8, u8)> {
+        let mut ordered: Vec<_> = candidates.iter().collect();
+        ordered.sort_by_key(|candidate| (candidate.coin_value, candidate.address));
+        greedy(value, ordered)
+    }
+}
+
+/// Pays out with the largest coins first and spreads the coins of a denomination across its
+/// hoppers according to their fill levels.
+///
+/// Fuller hoppers dispense more coins, so the hoppers of a denomination wear evenly and none is
+/// emptied while the others stay full. Hoppers reporting [`HopperInventoryLevel::Empty`] are
+/// only used once the other hoppers of their denomination are at their per-plan limit.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BalanceLeveling;
+
+impl SelectionStrategy for BalanceLeveling {
+    fn plan(&self, value: u32, candidates: &[HopperCandidate]) -> Vec<(u8, u8)> {
+        let mut ordered: Vec<_> = candidates
+            .iter()
+            .filter(|candidate| candidate.coin_value > 0)
+            .collect();
+        ordered.sort_by_key(|candidate| {
+            (
+                Reverse(candidate.coin_value),
+                Reverse(weight(candidate.level)),
+                candidate.address,
+            )
+        });
+
+        let mut plan = Vec::new();
+        let mut remaining = value;
+        for group in ordered.chunk_by(|a, b| a.coin_value == b.coin_value) {
+            let coin_value = group[0].coin_value;
+            let capacity = u32::from(u8::MAX) * u32::try_from(group.len()).unwrap_or(u32::MAX);
+            let count = (remaining / coin_value).min(capacity);
+            if count == 0 {
+                continue;
+            }
+            remaining -= count * coin_value;
+            plan.extend(spread(count, group));
+        }
+        plan
+    }
+
+    fn uses_inventory(&self) -> bool {
+        true
+    }
+}
+
+/// Holds back the hoppers at or below a reserve level, they are only used when the other
+/// hoppers can't pay the value out exactly.
+///
+/// Keeps a float of each denomination for as long as possible, e.g. so the small coins needed
+/// to give change don't run out.
+#[derive(Debug, Clone, Copy)]
+pub struct ReserveAware<S = BalanceLeveling> {
+    inner: S,
+    reserve: HopperInventoryLevel,
+}
+
+impl<S: SelectionStrategy> ReserveAware<S> {
+    /// Plans with `inner`, holding back the hoppers at or below `reserve`.
+    ///
+    /// Hoppers with an [`HopperInventoryLevel::Unknown`] level are never held back.
+    pub const fn new(inner: S, reserve: HopperInventoryLevel) -> Self {
+        Self { inner, reserve }
+    }
+
+    /// Returns the level at or below which the hoppers are held back.
+    pub const fn reserve(&self) -> HopperInventoryLevel {
+        self.reserve
+    }
+}
+
+impl Default for ReserveAware {
+    fn default() -> Self {
+        Self::new(BalanceLeveling, HopperInventoryLevel::Low)
+    }
+}
+
+impl<S: SelectionStrategy> SelectionStrategy for ReserveAware<S> {
+    fn plan(&self, value: u32, candidates: &[HopperCandidate]) -> Vec<(u8, u8)> {
+        let unreserved: Vec<_> = candidates
+            .iter()
+            .filter(|candidate| candidate.level > self.reserve)
+            .copied()
+            .collect();
+        if unreserved.len() < candidates.len() {
+            let plan = self.inner.plan(value, &unreserved);
+            if planned_value(&plan, candidates) == value {
+                return plan;
+            }
+        }
+        self.inner.plan(value, candidates)
+    }
+
+    fn uses_inventory(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the value the plan dispenses.
+pub(crate) fn planned_value(plan: &[(u8, u8)], candidates: &[HopperCandidate]) -> u32 {
+    plan.iter()
+        .filter_map(|&(address, count)| {
+            candidates
+                .iter()
+                .find(|candidate| candidate.address == address)
+                .map(|candidate| u32::from(count) * candidate.coin_value)
+        })
+        .sum()
+}
+
+/// Takes as many coins as possible from each hopper in turn.
+fn greedy<'a>(value: u32, ordered: impl IntoIterator<Item = &'a HopperCandidate>) -> Vec<(u8, u8)> {
+    let mut plan = Vec::new();
+    let mut remaining = value;
+    for candidate in ordered {
+        if candidate.coin_value == 0 || remaining == 0 {
+            continue;
+        }
+        let count = (remaining / candidate.coin_value).min(u32::from(u8::MAX));
+        if count > 0 {
+            plan.push((candidate.address, count as u8));
+            remaining -= count * candidate.coin_value;
+        }
+    }
+    plan
+}
+
+/// Share of the coins a hopper takes in [`BalanceLeveling`], a hopper with an unknown level is
+/// assumed half full.
+const fn weight(level: HopperInventoryLevel) -> u32 {
+    match level {
+        HopperInventoryLevel::Empty => 0,
+        HopperInventoryLevel::Low => 1,
+        HopperInventoryLevel::Medium | HopperInventoryLevel::Unknown => 2,
+        HopperInventoryLevel::High => 3,
+    }
+}
+
+/// Spreads `count` coins across hoppers of the same denomination in proportion to their
+/// weight, `group` is sorted by decreasing weight.
+fn spread(count: u32, group: &[&HopperCandidate]) -> Vec<(u8, u8)> {
+    let mut assigned = vec![0u32; group.len()];
+    for _ in 0..count {
+        let open = (0..group.len()).filter(|&index| assigned[index] < u32::from(u8::MAX));
+        // The hopper furthest below its share takes the coin, Empty hoppers only take coins
+        // once the others are full.
+        let next = open
+            .clone()
+            .filter(|&index| weight(group[index].level) > 0)
+            .min_by(|&a, &b| {
+                let (weight_a, weight_b) = (weight(group[a].level), weight(group[b].level));
+                ((assigned[a] + 1) * weight_b).cmp(&((assigned[b] + 1) * weight_a))
+            })
+            .or_else(|| open.clone().next());
+        let Some(index) = next else {
+            break;
+        };
+        assigned[index] += 1;
+    }
+
+    group
+        .iter()
+        .zip(assigned)
+        .filter(|&(_, count)| count > 0)
+        .map(|(candidate, count)| (candidate.address, count as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use HopperInventoryLevel::{Empty, High, Low, Medium, Unknown};
+
+    #[test]
+    fn largest_first_minimizes_coins() {
+        let candidates = [
+            HopperCandidate::new(5, 20, Unknown),
+            HopperCandidate::new(3, 100, Unknown),
+            HopperCandidate::new(4, 50, Unknown),
+        ];
+        assert_eq!(
+            GreedyLargestFirst.plan(170, &candidates),
+            vec![(3, 1), (4, 1), (5, 1)]
+        );
+        assert_eq!(GreedySmallestFirst.plan(100, &candidates), vec![(5, 5)]);
+    }
+
+    #[test]
+    fn balance_leveling_spreads_a_denomination_by_fill_level() {
+        let candidates = [
+            HopperCandidate::new(3, 100, High),
+            HopperCandidate::new(4, 100, Low),
+            HopperCandidate::new(5, 100, Empty),
+            HopperCandidate::new(6, 20, Medium),
+        ];
+
+        // The full hopper takes three coins for each coin of the low one.
+        assert_eq!(
+            BalanceLeveling.plan(820, &candidates),
+            vec![(3, 6), (4, 2), (6, 1)]
+        );
+        // Levels being equal, the coins are split evenly.
+        let unknown = candidates.map(|candidate| HopperCandidate {
+            level: Unknown,
+            ..candidate
+        });
+        assert_eq!(
+            BalanceLeveling.plan(600, &unknown),
+            vec![(3, 2), (4, 2), (5, 2)]
+        );
+    }
+
+    #[test]
+    fn balance_leveling_uses_empty_hoppers_last() {
+        let candidates = [
+            HopperCandidate::new(3, 1, Empty),
+            HopperCandidate::new(4, 1, High),
+        ];
+        assert_eq!(BalanceLeveling.plan(10, &candidates), vec![(4, 10)]);
+        assert_eq!(
+            BalanceLeveling.plan(300, &candidates),
+            vec![(4, 255), (3, 45)]
+        );
+    }
+
+    #[test]
+    fn reserve_aware_holds_back_low_hoppers() {
+        let candidates = [
+            HopperCandidate::new(3, 100, High),
+            HopperCandidate::new(4, 50, Medium),
+            HopperCandidate::new(5, 20, Low),
+        ];
+        let strategy = ReserveAware::default();
+
+        assert_eq!(strategy.plan(200, &candidates), vec![(3, 2)]);
+        // 170 can't be paid without the 20 coins.
+        assert_eq!(
+            strategy.plan(170, &candidates),
+            vec![(3, 1), (4, 1), (5, 1)]
+        );
+        assert_eq!(strategy.plan(150, &candidates), vec![(3, 1), (4, 1)]);
+    }
+}
@@ -6,8 +6,11 @@
 //!
 //! - Continuous background polling of hopper sensor levels
 //! - Inventory level change detection with event notifications
-//! - Manual and automatic empty-state tracking per hopper
-//! - Auto-recovery when a previously-empty hopper is refilled
+//! - Manual and automatic empty-state tracking per hopper, a hopper is marked empty when its
+//!   low level sensor triggers
+//! - Auto-recovery when a previously-empty hopper is refilled to a configured level
+//! - Optional coin inventory tracking from the payout status and counters, see
+//!   [`PayoutSensorPoolBuilder::track_counts`]
 //!
 //! # Example
 //!
@@ -19,7 +22,9 @@
 //!     .add_hopper(hopper2)
 //!     .add_hopper(hopper3)
 //!     .polling_interval(Duration::from_millis(500))
+//!     .track_counts(CountSource::AbsoluteCount)
 //!     .build();
+//! sensor_pool.set_coin_count(3, 500)?;
 //!
 //! // Start background polling — returns a guard with an event receiver
 //! let (_tx, rx) = tokio::sync::watch::channel(PollingStatus::Running);
@@ -44,9 +49,11 @@
 mod builder;
 mod error;
 mod event;
+mod inventory;
 mod pool;
 
 pub use builder::PayoutSensorPoolBuilder;
 pub use error::{PayoutSensorPoolError, PayoutSensorPoolResult};
 pub use event::{HopperSensorError, HopperSensorReading, RecoveryReason, SensorEvent};
+pub use inventory::{CountSource, HopperCoinInventory};
 pub use pool::{PayoutSensorPool, PollingStatus, SensorPollGuard};
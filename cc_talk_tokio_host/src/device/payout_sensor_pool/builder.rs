@@ -1,8 +1,11 @@
 use std::time::Duration;
 
-use crate::device::payout::PayoutDevice;
+use crate::device::{payout::PayoutDevice, payout_pool::HopperInventoryLevel};
 
-use super::pool::PayoutSensorPool;
+use super::{
+    inventory::CountSource,
+    pool::{DEFAULT_RECOVERY_LEVEL, PayoutSensorPool},
+};
 
 /// Builder for constructing a [`PayoutSensorPool`].
 #[derive(Debug)]
@@ -10,6 +13,8 @@ pub struct PayoutSensorPoolBuilder {
     hoppers: Vec<PayoutDevice>,
     polling_interval: Duration,
     channel_size: usize,
+    recovery_level: HopperInventoryLevel,
+    count_source: Option<CountSource>,
 }
 
 impl PayoutSensorPoolBuilder {
@@ -20,6 +25,8 @@ impl PayoutSensorPoolBuilder {
             hoppers: Vec::new(),
             polling_interval: Duration::from_secs(10),
             channel_size: 16,
+            recovery_level: DEFAULT_RECOVERY_LEVEL,
+            count_source: None,
         }
     }
 
@@ -55,10 +62,35 @@ impl PayoutSensorPoolBuilder {
         self
     }
 
+    /// Sets the level at or above which a hopper marked empty is marked non-empty again.
+    ///
+    /// Defaults to [`HopperInventoryLevel::Medium`].
+    #[must_use]
+    pub const fn recovery_level(mut self, level: HopperInventoryLevel) -> Self {
+        self.recovery_level = level;
+        self
+    }
+
+    /// Polls `RequestPayoutStatus` and the counter of `source` along with the sensors to track
+    /// the coin inventory of the hoppers.
+    ///
+    /// The counts are not tracked by default.
+    #[must_use]
+    pub const fn track_counts(mut self, source: CountSource) -> Self {
+        self.count_source = Some(source);
+        self
+    }
+
     /// Builds the [`PayoutSensorPool`].
     #[must_use]
     pub fn build(self) -> PayoutSensorPool {
-        PayoutSensorPool::new(self.hoppers, self.polling_interval, self.channel_size)
+        PayoutSensorPool::new(
+            self.hoppers,
+            self.polling_interval,
+            self.channel_size,
+            self.recovery_level,
+            self.count_source,
+        )
     }
 }
 
@@ -2,6 +2,8 @@ use cc_talk_core::cc_talk::HopperStatus;
 
 use crate::device::{base::CommandError, payout_pool::HopperInventoryLevel};
 
+use super::inventory::HopperCoinInventory;
+
 /// Events emitted by the [`super::PayoutSensorPool`] during background polling.
 #[derive(Debug, Clone)]
 pub enum SensorEvent {
@@ -21,7 +23,7 @@ pub enum SensorEvent {
         /// The current inventory level.
         current: HopperInventoryLevel,
     },
-    /// A hopper was marked empty.
+    /// A hopper was marked empty, its low level sensor triggered.
     MarkedEmpty {
         /// The ccTalk address of the hopper.
         address: u8,
@@ -44,6 +46,8 @@ pub struct HopperSensorReading {
     pub level: HopperInventoryLevel,
     /// The raw sensor status from the device.
     pub status: HopperStatus,
+    /// The coin inventory, `None` if the pool does not track the counts.
+    pub inventory: Option<HopperCoinInventory>,
 }
 
 /// Error polling a specific hopper.
@@ -0,0 +1,83 @@
+use cc_talk_core::cc_talk::{CounterStep, HopperDispenseStatus, WrappingCounter};
+
+/// Counter a [`super::PayoutSensorPool`] tracks the dispensed coins with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountSource {
+    /// `RequestPayoutAbsoluteCount`, a 16 bit counter kept by the hopper.
+    AbsoluteCount,
+    /// `RequestHopperDispenseCount`, a 24 bit lifetime counter, for hoppers without an
+    /// absolute count.
+    DispenseCount,
+}
+
+impl CountSource {
+    /// Moves the counter last read as `previous` to `reading`, returns the new counter value.
+    fn step(self, previous: Option<u32>, reading: u32) -> (u32, CounterStep) {
+        match self {
+            Self::AbsoluteCount => step::<16>(previous, reading),
+            Self::DispenseCount => step::<24>(previous, reading),
+        }
+    }
+}
+
+fn step<const BITS: u32>(previous: Option<u32>, reading: u32) -> (u32, CounterStep) {
+    let mut counter = previous.map_or_else(WrappingCounter::<BITS>::new, WrappingCounter::resume);
+    let step = counter.update(reading);
+    (counter.last().unwrap_or(reading), step)
+}
+
+/// Coin inventory of a hopper, tracked from its counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HopperCoinInventory {
+    /// Last value of the counter, `None` until it is first read.
+    pub counter: Option<u32>,
+    /// Coins dispensed since the tracking started.
+    pub dispensed: u32,
+    /// Coins left in the hopper, `None` until set with
+    /// [`set_coin_count`](super::PayoutSensorPool::set_coin_count).
+    pub coins: Option<u32>,
+    /// Last reply to `RequestPayoutStatus`.
+    pub last_status: Option<HopperDispenseStatus>,
+}
+
+impl HopperCoinInventory {
+    /// Records a counter reading and returns the coins dispensed since the previous one.
+    ///
+    /// Wraps and resets of the counter are told apart by a [`WrappingCounter`].
+    pub(crate) fn record(&mut self, source: CountSource, counter: u32) -> u32 {
+        let (counter, step) = source.step(self.counter, counter);
+        let delta = step.delta();
+
+        self.counter = Some(counter);
+        self.dispensed = self.dispensed.saturating_add(delta);
+        if let Some(coins) = &mut self.coins {
+            *coins = coins.saturating_sub(delta);
+        }
+        delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispensed_coins_are_counted_across_wraps_and_resets() {
+        let mut inventory = HopperCoinInventory {
+            coins: Some(100),
+            ..HopperCoinInventory::default()
+        };
+
+        assert_eq!(inventory.record(CountSource::AbsoluteCount, 65_530), 0);
+        assert_eq!(inventory.record(CountSource::AbsoluteCount, 4), 10);
+        // Power cycled hopper, the counter restarts.
+        assert_eq!(inventory.record(CountSource::AbsoluteCount, 2), 2);
+        assert_eq!(inventory.dispensed, 12);
+        assert_eq!(inventory.coins, Some(88));
+
+        let mut inventory = HopperCoinInventory::default();
+        inventory.record(CountSource::DispenseCount, 70_000);
+        assert_eq!(inventory.record(CountSource::DispenseCount, 70_020), 20);
+        assert_eq!(inventory.coins, None);
+    }
+}
@@ -8,7 +8,11 @@ use tokio::sync::{self, mpsc, oneshot};
 use tracing::{debug, error, info, trace, warn};
 
 use crate::{
-    device::{base::PollingError, payout::PayoutDevice, payout_pool::HopperInventoryLevel},
+    device::{
+        base::{CommandError, PollingError},
+        payout::PayoutDevice,
+        payout_pool::HopperInventoryLevel,
+    },
     util::DropGuard,
 };
 
@@ -16,11 +20,12 @@ use super::{
     builder::PayoutSensorPoolBuilder,
     error::{PayoutSensorPoolError, PayoutSensorPoolResult},
     event::{HopperSensorError, HopperSensorReading, RecoveryReason, SensorEvent},
+    inventory::{CountSource, HopperCoinInventory},
 };
 
-/// The inventory level at or above which a hopper is automatically recovered
-/// from the empty state.
-const RECOVERY_THRESHOLD: HopperInventoryLevel = HopperInventoryLevel::Medium;
+/// The default inventory level at or above which a hopper is automatically
+/// recovered from the empty state.
+pub(crate) const DEFAULT_RECOVERY_LEVEL: HopperInventoryLevel = HopperInventoryLevel::Medium;
 
 /// Guard returned by [`PayoutSensorPool::try_start_polling`].
 ///
@@ -39,7 +44,8 @@ pub enum PollingStatus {
 ///
 /// `PayoutSensorPool` provides continuous inventory monitoring with
 /// empty-state management. It polls `PayoutDevice::get_sensor_status()`
-/// directly, without any dependency on [`crate::device::payout_pool::PayoutPool`].
+/// directly, without any dependency on [`crate::device::payout_pool::PayoutPool`],
+/// and the payout status and counters when the counts are tracked.
 ///
 /// # Cloning
 ///
@@ -54,10 +60,14 @@ pub struct PayoutSensorPool {
     /// [`HopperInventoryLevel::Empty`] and remains sticky until recovery
     /// threshold is met or [`mark_non_empty`](Self::mark_non_empty) is called.
     last_levels: Arc<Mutex<HashMap<u8, HopperInventoryLevel>>>,
+    /// Coin inventory per hopper, only filled when the counts are tracked.
+    coin_inventories: Arc<Mutex<HashMap<u8, HopperCoinInventory>>>,
     /// Whether background polling is active.
     is_polling: Arc<Mutex<bool>>,
     polling_interval: Duration,
     channel_size: usize,
+    recovery_level: HopperInventoryLevel,
+    count_source: Option<CountSource>,
 }
 
 impl PayoutSensorPool {
@@ -74,13 +84,18 @@ impl PayoutSensorPool {
         hoppers: Vec<PayoutDevice>,
         polling_interval: Duration,
         channel_size: usize,
+        recovery_level: HopperInventoryLevel,
+        count_source: Option<CountSource>,
     ) -> Self {
         Self {
             hoppers,
             last_levels: Arc::new(Mutex::new(HashMap::new())),
+            coin_inventories: Arc::new(Mutex::new(HashMap::new())),
             is_polling: Arc::new(Mutex::new(false)),
             polling_interval,
             channel_size,
+            recovery_level,
+            count_source,
         }
     }
 
@@ -179,11 +194,81 @@ impl PayoutSensorPool {
             .clone()
     }
 
+    /// Sets the number of coins in a hopper, e.g. after it was refilled.
+    ///
+    /// The count goes down as the counter of the hopper goes up, see
+    /// [`PayoutSensorPoolBuilder::track_counts`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PayoutSensorPoolError::HopperNotFound`] if the address
+    /// is not in the pool.
+    pub fn set_coin_count(&self, address: u8, coins: u32) -> PayoutSensorPoolResult<()> {
+        if !self.has_hopper(address) {
+            return Err(PayoutSensorPoolError::HopperNotFound(address));
+        }
+
+        self.coin_inventories
+            .lock()
+            .expect("should not be poisoned")
+            .entry(address)
+            .or_default()
+            .coins = Some(coins);
+
+        info!(address, coins, "hopper coin count set");
+        Ok(())
+    }
+
+    /// Returns the tracked coin inventory of a specific hopper.
+    #[must_use]
+    pub fn coin_inventory(&self, address: u8) -> Option<HopperCoinInventory> {
+        self.coin_inventories
+            .lock()
+            .expect("should not be poisoned")
+            .get(&address)
+            .copied()
+    }
+
+    /// Returns `true` while the background polling runs.
+    #[must_use]
+    pub fn is_polling(&self) -> bool {
+        *self.is_polling.lock().expect("should not be poisoned")
+    }
+
+    /// Polls the payout status and the counter of a hopper and updates its coin inventory.
+    async fn poll_counts(
+        &self,
+        hopper: &PayoutDevice,
+        source: CountSource,
+    ) -> Result<HopperCoinInventory, CommandError> {
+        let status = hopper.get_payout_status().await?;
+        let counter = match source {
+            CountSource::AbsoluteCount => u32::from(hopper.get_payout_absolute_count(None).await?),
+            CountSource::DispenseCount => hopper.get_dispense_count().await?,
+        };
+
+        let address = hopper.device.address();
+        let mut inventories = self
+            .coin_inventories
+            .lock()
+            .expect("should not be poisoned");
+        let inventory = inventories.entry(address).or_default();
+        let dispensed = inventory.record(source, counter);
+        inventory.last_status = Some(status);
+        let inventory = *inventory;
+        drop(inventories);
+        if dispensed > 0 {
+            trace!(address, dispensed, coins = ?inventory.coins, "hopper coins dispensed");
+        }
+        Ok(inventory)
+    }
+
     /// Starts background sensor polling.
     ///
     /// Spawns a background task that continuously polls all hoppers for
     /// inventory levels via [`PayoutDevice::get_sensor_status()`] and sends
-    /// [`SensorEvent`]s through a channel.
+    /// [`SensorEvent`]s through a channel. A hopper whose low level sensor
+    /// triggers is marked empty until its level reaches the recovery level.
     ///
     /// The returned [`SensorPollGuard`] wraps a receiver channel. When the
     /// guard is dropped, the background polling task is automatically stopped.
@@ -245,12 +330,15 @@ impl PayoutSensorPool {
                             };
 
                             let was_empty = previous == Some(HopperInventoryLevel::Empty);
+                            let recovered = sensor_level >= pool_clone.recovery_level;
+                            let low_level_triggered = sensor_level == HopperInventoryLevel::Empty
+                                || (status.low_level_supported && !status.higher_than_low_level);
 
                             // If the hopper was marked empty, only update
                             // its level when the sensor reports at or above
-                            // the recovery threshold.
-                            let effective_level = if was_empty
-                                && sensor_level < RECOVERY_THRESHOLD
+                            // the recovery level.
+                            let effective_level = if (was_empty && !recovered)
+                                || (!was_empty && low_level_triggered)
                             {
                                 HopperInventoryLevel::Empty
                             } else {
@@ -280,9 +368,14 @@ impl PayoutSensorPool {
                                     .await;
                             }
 
+                            if !was_empty && low_level_triggered {
+                                info!(address, %sensor_level, "hopper low level sensor triggered");
+                                let _ = tx.send(SensorEvent::MarkedEmpty { address }).await;
+                            }
+
                             // Auto-recovery: hopper was empty and sensor
-                            // now reports at or above the threshold.
-                            if was_empty && sensor_level >= RECOVERY_THRESHOLD {
+                            // now reports at or above the recovery level.
+                            if was_empty && recovered {
                                 info!(address, %sensor_level, "hopper auto-recovered from empty state");
                                 let _ = tx
                                     .send(SensorEvent::MarkedNonEmpty {
@@ -294,10 +387,25 @@ impl PayoutSensorPool {
                                     .await;
                             }
 
+                            let inventory = match pool_clone.count_source {
+                                Some(source) => {
+                                    match pool_clone.poll_counts(hopper, source).await {
+                                        Ok(inventory) => Some(inventory),
+                                        Err(e) => {
+                                            debug!(address, %e, "hopper count poll error");
+                                            errors.push(HopperSensorError { address, error: e });
+                                            None
+                                        }
+                                    }
+                                }
+                                None => None,
+                            };
+
                             inventories.push(HopperSensorReading {
                                 address,
                                 level: effective_level,
                                 status,
+                                inventory,
                             });
                         }
                         Err(e) => {
@@ -377,10 +485,7 @@ mod tests {
         assert!(!sensor.is_empty(3));
         sensor.mark_empty(3).unwrap();
         assert!(sensor.is_empty(3));
-        assert_eq!(
-            sensor.last_inventory(3),
-            Some(HopperInventoryLevel::Empty)
-        );
+        assert_eq!(sensor.last_inventory(3), Some(HopperInventoryLevel::Empty));
 
         sensor.mark_non_empty(3).unwrap();
         assert!(!sensor.is_empty(3));
@@ -410,6 +515,24 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn set_coin_count_tracks_the_hopper() {
+        let sensor = create_sensor_pool();
+
+        assert_eq!(sensor.coin_inventory(3), None);
+        sensor.set_coin_count(3, 250).unwrap();
+        assert_eq!(
+            sensor
+                .coin_inventory(3)
+                .and_then(|inventory| inventory.coins),
+            Some(250)
+        );
+        assert!(matches!(
+            sensor.set_coin_count(99, 1),
+            Err(PayoutSensorPoolError::HopperNotFound(99))
+        ));
+    }
+
     #[test]
     fn is_empty_returns_false_by_default() {
         let sensor = create_sensor_pool();
@@ -430,15 +553,9 @@ mod tests {
         assert!(!sensor.is_empty(4));
         assert!(sensor.is_empty(5));
 
-        assert_eq!(
-            sensor.last_inventory(3),
-            Some(HopperInventoryLevel::Empty)
-        );
+        assert_eq!(sensor.last_inventory(3), Some(HopperInventoryLevel::Empty));
         assert_eq!(sensor.last_inventory(4), None);
-        assert_eq!(
-            sensor.last_inventory(5),
-            Some(HopperInventoryLevel::Empty)
-        );
+        assert_eq!(sensor.last_inventory(5), Some(HopperInventoryLevel::Empty));
     }
 
     #[test]
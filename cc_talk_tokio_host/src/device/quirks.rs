@@ -0,0 +1,299 @@
+//! Handling of devices deviating from the specification.
+//!
+//! Some devices reply with more or less data than specified, scale their values differently or
+//! append vendor specific status bytes. Instead of forking the generic parsers, a
+//! [`DeviceQuirks`] implementation rewrites the replies of such a device into the specified
+//! format before they are parsed. The drivers run the quirks registered with their
+//! `with_quirks`, the [`QuirkRegistry`] finds the quirks of a device from its manufacturer and
+//! product code and raises the response size caps of the transport for the replies the quirks
+//! shorten, e.g.
+//!
+//! ```ignore
+//! let mut registry = QuirkRegistry::new();
+//! registry.register(
+//!     Manufacturer::Azkoyen,
+//!     Some("HOPPER-X"),
+//!     Arc::new(ResponseLength::new(Header::RequestHopperStatus, 4)),
+//! );
+//!
+//! let hopper = PayoutDevice::new(device, sender);
+//! let hopper = match registry.resolve_with_caps(&hopper, &response_caps).await? {
+//!     Some(quirks) => hopper.with_quirks(quirks),
+//!     None => hopper,
+//! };
+//! ```
+
+use std::{fmt::Debug, sync::Arc};
+
+use cc_talk_core::cc_talk::{Header, Manufacturer};
+use tracing::{debug, instrument};
+
+use super::base::{DeviceCommon, DeviceResult};
+use crate::transport::response_caps::ResponseSizeCaps;
+
+/// Deviations of a device from the specification, applied at parse time.
+pub trait DeviceQuirks: Debug + Send + Sync {
+    /// Rewrites the reply to `header` into the specified format.
+    ///
+    /// Returns `None` to parse the reply as received.
+    fn adjust_response(&self, header: Header, payload: &[u8]) -> Option<Vec<u8>>;
+
+    /// Returns the longest reply payload the device sends to `header`, when it exceeds the
+    /// [command table](cc_talk_host::command_table).
+    ///
+    /// Returns `None` to keep the limit of the table, see [`ResponseSizeCaps::raise_for_quirks`].
+    fn max_response_length(&self, _header: Header) -> Option<u8> {
+        None
+    }
+}
+
+/// Truncates, or pads with zeros, the reply to a header to the specified length.
+///
+/// Handles devices appending extra status bytes or omitting trailing ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseLength {
+    header: Header,
+    length: usize,
+}
+
+impl ResponseLength {
+    pub const fn new(header: Header, length: usize) -> Self {
+        Self { header, length }
+    }
+}
+
+impl DeviceQuirks for ResponseLength {
+    fn adjust_response(&self, header: Header, payload: &[u8]) -> Option<Vec<u8>> {
+        if header != self.header || payload.len() == self.length {
+            return None;
+        }
+        let mut adjusted = payload.to_vec();
+        adjusted.resize(self.length, 0);
+        Some(adjusted)
+    }
+
+    /// Any extra bytes are truncated, the reply may be as long as a frame allows.
+    fn max_response_length(&self, header: Header) -> Option<u8> {
+        (header == self.header).then_some(u8::MAX)
+    }
+}
+
+/// Applies several quirks in order, each one sees the reply adjusted by the previous ones.
+#[derive(Debug, Clone, Default)]
+pub struct QuirkChain(Vec<Arc<dyn DeviceQuirks>>);
+
+impl QuirkChain {
+    pub fn new(quirks: Vec<Arc<dyn DeviceQuirks>>) -> Self {
+        Self(quirks)
+    }
+}
+
+impl DeviceQuirks for QuirkChain {
+    fn adjust_response(&self, header: Header, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut adjusted: Option<Vec<u8>> = None;
+        for quirks in &self.0 {
+            let current = adjusted.as_deref().unwrap_or(payload);
+            if let Some(next) = quirks.adjust_response(header, current) {
+                adjusted = Some(next);
+            }
+        }
+        adjusted
+    }
+
+    fn max_response_length(&self, header: Header) -> Option<u8> {
+        self.0
+            .iter()
+            .filter_map(|quirks| quirks.max_response_length(header))
+            .max()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct QuirkEntry {
+    manufacturer: Manufacturer,
+    product_code: Option<String>,
+    quirks: Arc<dyn DeviceQuirks>,
+}
+
+/// Quirks of known devices, keyed by manufacturer and product code.
+#[derive(Debug, Clone, Default)]
+pub struct QuirkRegistry {
+    entries: Vec<QuirkEntry>,
+}
+
+impl QuirkRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the quirks of a product, or of every product of the manufacturer when
+    /// `product_code` is `None`.
+    ///
+    /// Registering the same key again replaces its quirks.
+    pub fn register(
+        &mut self,
+        manufacturer: Manufacturer,
+        product_code: Option<&str>,
+        quirks: Arc<dyn DeviceQuirks>,
+    ) -> &mut Self {
+        let product_code = product_code.map(str::to_string);
+        self.entries.retain(|entry| {
+            entry.manufacturer != manufacturer || entry.product_code != product_code
+        });
+        self.entries.push(QuirkEntry {
+            manufacturer,
+            product_code,
+            quirks,
+        });
+        self
+    }
+
+    /// Returns the quirks of a product, quirks registered for the product take precedence over
+    /// the ones registered for the whole manufacturer.
+    pub fn get(
+        &self,
+        manufacturer: Manufacturer,
+        product_code: &str,
+    ) -> Option<Arc<dyn DeviceQuirks>> {
+        let matching = |product: Option<&str>| {
+            self.entries.iter().find(|entry| {
+                entry.manufacturer == manufacturer && entry.product_code.as_deref() == product
+            })
+        };
+        matching(Some(product_code))
+            .or_else(|| matching(None))
+            .map(|entry| Arc::clone(&entry.quirks))
+    }
+
+    /// Reads the manufacturer and product code of `device` and returns its quirks.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the device does not answer the identification requests.
+    #[instrument(skip_all, fields(address = device.get_device().address()), level = "debug")]
+    pub async fn resolve<D: DeviceCommon>(
+        &self,
+        device: &D,
+    ) -> DeviceResult<Option<Arc<dyn DeviceQuirks>>> {
+        let manufacturer = device.get_manufacturer_id().await?;
+        let product_code = device.get_product_code().await?;
+        let quirks = self.get(manufacturer, &product_code);
+        debug!(
+            manufacturer = ?manufacturer,
+            product_code,
+            found = quirks.is_some(),
+            "device quirks resolved"
+        );
+        Ok(quirks)
+    }
+
+    /// Resolves the quirks of `device` like [`Self::resolve`] and raises the response size caps
+    /// of its address for the headers the quirks accept longer replies to.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the device does not answer the identification requests.
+    pub async fn resolve_with_caps<D: DeviceCommon>(
+        &self,
+        device: &D,
+        response_caps: &ResponseSizeCaps,
+    ) -> DeviceResult<Option<Arc<dyn DeviceQuirks>>> {
+        let quirks = self.resolve(device).await?;
+        if let Some(quirks) = &quirks {
+            response_caps.raise_for_quirks(device.get_device().address(), quirks.as_ref());
+        }
+        Ok(quirks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{Category, ChecksumType, Device};
+    use cc_talk_host::{command::Command, device::device_commands::RequestHopperStatusCommand};
+    use tokio::sync::mpsc;
+
+    use crate::device::payout::PayoutDevice;
+
+    use super::*;
+
+    #[test]
+    fn product_quirks_take_precedence_over_manufacturer_quirks() {
+        let mut registry = QuirkRegistry::new();
+        registry
+            .register(
+                Manufacturer::MoneyControlsInternational,
+                None,
+                Arc::new(ResponseLength::new(Header::RequestHopperStatus, 4)),
+            )
+            .register(
+                Manufacturer::MoneyControlsInternational,
+                Some("SCH2"),
+                Arc::new(ResponseLength::new(Header::RequestHopperStatus, 2)),
+            );
+
+        let adjust = |product| {
+            registry
+                .get(Manufacturer::MoneyControlsInternational, product)
+                .expect("should have quirks")
+                .adjust_response(Header::RequestHopperStatus, &[1, 2, 3])
+        };
+        assert_eq!(adjust("SCH2"), Some(vec![1, 2]));
+        assert_eq!(adjust("SCH3"), Some(vec![1, 2, 3, 0]));
+        assert!(registry.get(Manufacturer::Azkoyen, "SCH2").is_none());
+    }
+
+    #[tokio::test]
+    async fn driver_parses_the_adjusted_reply() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(3, Category::Payout, ChecksumType::Crc8);
+        let quirks = QuirkChain::new(vec![
+            Arc::new(ResponseLength::new(Header::RequestHopperStatus, 5)),
+            Arc::new(ResponseLength::new(Header::RequestHopperStatus, 4)),
+        ]);
+        let hopper = PayoutDevice::new(device, tx).with_quirks(Arc::new(quirks));
+
+        tokio::spawn(async move {
+            let message = rx.recv().await.expect("should request the status");
+            // Two vendor specific bytes after the 4 specified ones.
+            let reply = vec![1, 6, 3, 0, 7, 3, 10, 2, 0xAA, 0xBB, 0];
+            message.respond_to.send(Ok(reply)).expect("should respond");
+        });
+
+        let packet = hopper
+            .send_command(RequestHopperStatusCommand)
+            .await
+            .expect("should reply");
+        assert_eq!(packet.get_data().expect("should have data"), [7, 3, 10, 2]);
+        assert!(
+            RequestHopperStatusCommand
+                .parse_response(packet.get_data().expect("should have data"))
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn raw_reply_keeps_the_vendor_bytes() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let device = Device::new(3, Category::Payout, ChecksumType::Crc8);
+        let hopper = PayoutDevice::new(device, tx).with_quirks(Arc::new(ResponseLength::new(
+            Header::RequestHopperStatus,
+            4,
+        )));
+
+        tokio::spawn(async move {
+            let message = rx.recv().await.expect("should request the status");
+            let reply = vec![1, 6, 3, 0, 7, 3, 10, 2, 0xAA, 0xBB, 0];
+            message.respond_to.send(Ok(reply)).expect("should respond");
+        });
+
+        let reply = hopper
+            .execute_with_packet(RequestHopperStatusCommand)
+            .await
+            .expect("should reply");
+        assert_eq!(
+            reply.payload().expect("should have data"),
+            [7, 3, 10, 2, 0xAA, 0xBB]
+        );
+        assert_eq!(reply.response.event_counter, 7);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use cc_talk_core::cc_talk::{CoinAcceptorError, CoinAcceptorPollResult, CoinEvent};
+
+/// Width of a histogram bucket by default.
+pub const DEFAULT_BUCKET_WIDTH: Duration = Duration::from_secs(60);
+
+/// How long rejects are kept by default, a day.
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    start: Instant,
+    counts: HashMap<CoinAcceptorError, u32>,
+}
+
+/// Reject and error codes reported by a coin acceptor, counted per time bucket.
+///
+/// Feed it the events read by the credit poller, e.g. from a
+/// [`CoinEventStream`](super::coin_event_stream::CoinEventStream), then query the most frequent
+/// reasons over a window such as the last hour to tune the acceptance, e.g. a coin type which is
+/// rejected too often.
+///
+/// Rejects older than the retention are dropped, a query can't look further back.
+#[derive(Debug, Clone)]
+pub struct RejectHistogram {
+    bucket_width: Duration,
+    retention: Duration,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Default for RejectHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RejectHistogram {
+    /// Creates a histogram with one minute buckets keeping a day of rejects.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            bucket_width: DEFAULT_BUCKET_WIDTH,
+            retention: DEFAULT_RETENTION,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    /// Sets the width of a bucket, the resolution of the queried windows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is zero.
+    #[must_use]
+    pub fn with_bucket_width(mut self, bucket_width: Duration) -> Self {
+        assert!(!bucket_width.is_zero(), "bucket width should not be zero");
+        self.bucket_width = bucket_width;
+        self
+    }
+
+    /// Sets how long rejects are kept.
+    #[must_use]
+    pub const fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Counts an error reported now. [`CoinAcceptorError::NullEvent`] is ignored.
+    pub fn record(&mut self, error: CoinAcceptorError) {
+        self.record_at(error, Instant::now());
+    }
+
+    /// Counts an error reported at `at`. [`CoinAcceptorError::NullEvent`] is ignored.
+    ///
+    /// Errors are expected in chronological order, an error older than the newest bucket is
+    /// counted in it.
+    pub fn record_at(&mut self, error: CoinAcceptorError, at: Instant) {
+        if error == CoinAcceptorError::NullEvent {
+            return;
+        }
+        let bucket = match self.buckets.back_mut() {
+            Some(bucket) if at < bucket.start + self.bucket_width => bucket,
+            _ => {
+                self.buckets.push_back(Bucket {
+                    start: at,
+                    counts: HashMap::new(),
+                });
+                self.buckets.back_mut().expect("a bucket was just pushed")
+            }
+        };
+        *bucket.counts.entry(error).or_insert(0) += 1;
+        self.expire(at);
+    }
+
+    /// Counts the error of an event, other events are ignored.
+    pub fn record_event(&mut self, event: &CoinEvent) {
+        if let CoinEvent::Error(error) = event {
+            self.record(*error);
+        }
+    }
+
+    /// Counts the errors of a poll.
+    pub fn record_poll(&mut self, result: &CoinAcceptorPollResult) {
+        let now = Instant::now();
+        for event in result.events.iter().rev() {
+            if let CoinEvent::Error(error) = event {
+                self.record_at(*error, now);
+            }
+        }
+    }
+
+    /// Returns the number of times each error was reported over the last `window`, most
+    /// frequent first. Errors reported as often are ordered by code.
+    ///
+    /// The window is rounded to whole buckets.
+    #[must_use]
+    pub fn counts(&self, window: Duration) -> Vec<(CoinAcceptorError, u32)> {
+        self.counts_at(window, Instant::now())
+    }
+
+    /// Returns the `n` errors reported the most over the last `window`, see [`Self::counts`].
+    #[must_use]
+    pub fn top(&self, window: Duration, n: usize) -> Vec<(CoinAcceptorError, u32)> {
+        let mut counts = self.counts(window);
+        counts.truncate(n);
+        counts
+    }
+
+    /// Returns the number of errors reported over the last `window`.
+    #[must_use]
+    pub fn total(&self, window: Duration) -> u32 {
+        self.counts(window).iter().map(|(_, count)| count).sum()
+    }
+
+    /// Forgets every reject.
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    fn counts_at(&self, window: Duration, now: Instant) -> Vec<(CoinAcceptorError, u32)> {
+        let mut totals = HashMap::new();
+        for bucket in self
+            .buckets
+            .iter()
+            .rev()
+            .take_while(|bucket| now.saturating_duration_since(bucket.start) < window)
+        {
+            for (error, count) in &bucket.counts {
+                *totals.entry(*error).or_insert(0) += count;
+            }
+        }
+        let mut counts = totals.into_iter().collect::<Vec<_>>();
+        counts.sort_by(|(a_error, a_count), (b_error, b_count)| {
+            b_count
+                .cmp(a_count)
+                .then_with(|| (*a_error as u8).cmp(&(*b_error as u8)))
+        });
+        counts
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|bucket| now.saturating_duration_since(bucket.start) >= self.retention)
+        {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_are_windowed_and_sorted_by_frequency() {
+        let start = Instant::now();
+        let mut histogram = RejectHistogram::new()
+            .with_bucket_width(Duration::from_secs(60))
+            .with_retention(Duration::from_secs(3600));
+
+        histogram.record_at(CoinAcceptorError::InhibitedCoin, start);
+        histogram.record_at(CoinAcceptorError::RejectCoin, start);
+        histogram.record_at(CoinAcceptorError::NullEvent, start);
+        let later = start + Duration::from_secs(600);
+        histogram.record_at(CoinAcceptorError::RejectCoin, later);
+        histogram.record_at(CoinAcceptorError::RejectCoin, later);
+        histogram.record_at(CoinAcceptorError::ValidationTimeout, later);
+
+        let now = later + Duration::from_secs(1);
+        assert_eq!(
+            histogram.counts_at(Duration::from_secs(3600), now),
+            vec![
+                (CoinAcceptorError::RejectCoin, 3),
+                (CoinAcceptorError::InhibitedCoin, 1),
+                (CoinAcceptorError::ValidationTimeout, 1),
+            ]
+        );
+        assert_eq!(
+            histogram.counts_at(Duration::from_secs(300), now),
+            vec![
+                (CoinAcceptorError::RejectCoin, 2),
+                (CoinAcceptorError::ValidationTimeout, 1),
+            ]
+        );
+
+        // The first bucket falls out of the retention.
+        histogram.record_at(
+            CoinAcceptorError::RejectCoin,
+            start + Duration::from_secs(3600),
+        );
+        assert_eq!(
+            histogram.counts_at(Duration::from_secs(7200), start + Duration::from_secs(3601)),
+            vec![
+                (CoinAcceptorError::RejectCoin, 3),
+                (CoinAcceptorError::ValidationTimeout, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Recovery from unexpected device resets.
+//!
+//! Devices report a power up or a reset by returning their event counter to 0, after which they
+//! are inhibited again and forgot their inhibits, sorter overrides or PIN. Drivers detect the
+//! reset while polling, emit a [`DeviceReset`] and run the re-initialization registered with
+//! their `with_reset_handler`, e.g.
+//!
+//! ```ignore
+//! let validator = CoinValidator::new(device, sender).with_reset_handler(|validator| {
+//!     Box::pin(async move {
+//!         validator.set_coin_inhibits([true; 16]).await?;
+//!         validator.disable_master_inhibit().await
+//!     })
+//! });
+//! ```
+
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use super::base::DeviceResult;
+
+/// Future returned by a reset handler.
+pub type ReinitializationFuture<'a> = Pin<Box<dyn Future<Output = DeviceResult<()>> + Send + 'a>>;
+
+type ResetHandler<D> = Arc<dyn for<'a> Fn(&'a D) -> ReinitializationFuture<'a> + Send + Sync>;
+
+/// A device reset detected while polling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceReset {
+    pub address: u8,
+    /// Result of the registered re-initialization, `None` if there is none.
+    pub reinitialization: Option<DeviceResult<()>>,
+}
+
+/// Reset handler and event channel of a driver, shared between its clones.
+pub(crate) struct ResetRecovery<D> {
+    handler: Option<ResetHandler<D>>,
+    events: Vec<mpsc::Sender<DeviceReset>>,
+}
+
+impl<D> Default for ResetRecovery<D> {
+    fn default() -> Self {
+        Self {
+            handler: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl<D> Clone for ResetRecovery<D> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+impl<D> std::fmt::Debug for ResetRecovery<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResetRecovery")
+            .field("handler", &self.handler.is_some())
+            .field("events", &self.events.len())
+            .finish()
+    }
+}
+
+impl<D> ResetRecovery<D> {
+    pub(crate) fn set_handler<F>(&mut self, handler: F)
+    where
+        F: for<'a> Fn(&'a D) -> ReinitializationFuture<'a> + Send + Sync + 'static,
+    {
+        self.handler = Some(Arc::new(handler));
+    }
+
+    /// Adds a receiver of the [`DeviceReset`] events.
+    pub(crate) fn add_events(&mut self, events: mpsc::Sender<DeviceReset>) {
+        self.events.push(events);
+    }
+
+    pub(crate) const fn has_handler(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    /// Runs the handler on `device` and emits the [`DeviceReset`].
+    ///
+    /// The event is dropped for the channels which are full, recovery never waits for the
+    /// receivers.
+    pub(crate) async fn recover(&self, address: u8, device: &D) -> DeviceReset {
+        warn!(address, "unexpected device reset");
+        let reinitialization = match &self.handler {
+            Some(handler) => Some(handler(device).await),
+            None => None,
+        };
+        match &reinitialization {
+            Some(Ok(())) => info!(address, "device re-initialized after reset"),
+            Some(Err(error)) => warn!(address, error = %error, "re-initialization failed"),
+            None => {}
+        }
+        let reset = DeviceReset {
+            address,
+            reinitialization,
+        };
+        for events in &self.events {
+            if let Err(error) = events.try_send(reset.clone()) {
+                warn!(address, error = %error, "unable to emit device reset");
+            }
+        }
+        reset
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tracing::info;
+
+/// Service state of a single device address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ServiceState {
+    /// The device takes part in normal operation.
+    #[default]
+    InService,
+    /// The device has been quiesced and can safely be removed by a technician.
+    ///
+    /// Pools skip out-of-service devices when polling, enabling or paying out.
+    OutOfService,
+}
+
+/// Shared service state registry, keyed by device address.
+///
+/// Pools mark a device out of service once it has been quiesced, clones of this handle
+/// can be shared between pools and kept by the application to drive a service menu.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceRegistry {
+    devices: Arc<Mutex<HashMap<u8, ServiceState>>>,
+}
+
+impl ServiceRegistry {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the service state for the given address.
+    ///
+    /// Devices which were never quiesced are in service.
+    #[must_use]
+    pub fn state(&self, address: u8) -> ServiceState {
+        self.devices
+            .lock()
+            .expect("should not be poisoned")
+            .get(&address)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn is_out_of_service(&self, address: u8) -> bool {
+        self.state(address) == ServiceState::OutOfService
+    }
+
+    /// Returns the addresses of every device currently out of service, sorted.
+    #[must_use]
+    pub fn out_of_service(&self) -> Vec<u8> {
+        let mut addresses: Vec<u8> = self
+            .devices
+            .lock()
+            .expect("should not be poisoned")
+            .iter()
+            .filter(|(_, state)| **state == ServiceState::OutOfService)
+            .map(|(&address, _)| address)
+            .collect();
+        addresses.sort_unstable();
+        addresses
+    }
+
+    /// Puts a device back into normal operation after service.
+    ///
+    /// This does not send any hardware commands, the owning pool has to re-enable the
+    /// device (e.g. [`CurrencyAcceptorPool::enable`](super::currency_acceptor_pool::CurrencyAcceptorPool::enable)).
+    pub fn return_to_service(&self, address: u8) {
+        self.devices
+            .lock()
+            .expect("should not be poisoned")
+            .remove(&address);
+        info!(address, "device returned to service");
+    }
+
+    pub(crate) fn mark_out_of_service(&self, address: u8) {
+        self.devices
+            .lock()
+            .expect("should not be poisoned")
+            .insert(address, ServiceState::OutOfService);
+        info!(address, "device marked out of service");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn devices_are_in_service_by_default() {
+        let registry = ServiceRegistry::new();
+        assert_eq!(registry.state(2), ServiceState::InService);
+        assert!(registry.out_of_service().is_empty());
+    }
+
+    #[test]
+    fn out_of_service_is_shared_between_clones() {
+        let registry = ServiceRegistry::new();
+        let clone = registry.clone();
+
+        registry.mark_out_of_service(40);
+        registry.mark_out_of_service(2);
+
+        assert!(clone.is_out_of_service(2));
+        assert_eq!(clone.out_of_service(), vec![2, 40]);
+
+        clone.return_to_service(2);
+        assert_eq!(registry.state(2), ServiceState::InService);
+        assert_eq!(registry.out_of_service(), vec![40]);
+    }
+}
@@ -0,0 +1,311 @@
+use std::fmt::Write;
+
+use cc_talk_core::cc_talk::{BusAddress, Category, ChecksumType, Manufacturer, SerialCode};
+use cc_talk_host::{
+    command::Command,
+    device::device_commands::{PerformSelfCheckCommand, TestHopperCommand},
+};
+use tokio::sync::mpsc;
+use tracing::{debug, instrument};
+
+use crate::transport::tokio_transport::TransportMessage;
+
+use super::{
+    base::{CommandError, DeviceCommon},
+    enumeration::{BusEnumeration, DuplicateSerial, Probe},
+};
+
+/// Result of the self check of a device in a [`BusTopology`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DeviceHealth {
+    /// The self check reported nothing.
+    Ok,
+    /// Fault code or hopper flags reported by the self check.
+    Faults(Vec<String>),
+    /// The device has no self check or did not answer it.
+    Unknown,
+}
+
+impl DeviceHealth {
+    fn label(&self) -> String {
+        match self {
+            Self::Ok => "ok".to_string(),
+            Self::Faults(faults) => faults.join(", "),
+            Self::Unknown => "unknown".to_string(),
+        }
+    }
+}
+
+/// A device of a [`BusTopology`], the fields are `None` if the device did not answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TopologyDevice {
+    pub address: u8,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::debug"))]
+    pub category: Category,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::display_option"))]
+    pub manufacturer: Option<Manufacturer>,
+    pub product_code: Option<String>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::display_option"))]
+    pub serial: Option<SerialCode>,
+    pub software_revision: Option<String>,
+    pub health: DeviceHealth,
+}
+
+/// Description of an enumerated bus, exported as a GraphViz graph or, with the `serde`
+/// feature, serialized e.g. as JSON for documentation and support tickets.
+///
+/// ```ignore
+/// let enumeration = enumerate(&sender, 2..=255, ChecksumType::Crc8).await;
+/// let topology = BusTopology::collect(&sender, &enumeration, ChecksumType::Crc8).await;
+/// std::fs::write("bus.dot", topology.to_dot())?;
+/// std::fs::write("bus.json", serde_json::to_string_pretty(&topology)?)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BusTopology {
+    /// Devices in ascending address order.
+    pub devices: Vec<TopologyDevice>,
+    #[cfg_attr(feature = "serde", serde(rename = "duplicate_serials"))]
+    pub duplicates: Vec<DuplicateSerial>,
+}
+
+impl BusTopology {
+    /// Reads the identity and runs the self check of every enumerated device.
+    ///
+    /// Hoppers run `TestHopper`, the other devices `PerformSelfCheck`.
+    #[instrument(skip_all, fields(devices = enumeration.devices.len()), level = "debug")]
+    pub async fn collect(
+        sender: &mpsc::Sender<TransportMessage>,
+        enumeration: &BusEnumeration,
+        checksum_type: ChecksumType,
+    ) -> Self {
+        let mut devices = Vec::with_capacity(enumeration.devices.len());
+        for device in &enumeration.devices {
+            let probe = Probe::new(device.address, checksum_type, sender);
+            let health = match self_check(&probe, &device.category).await {
+                Ok(health) => health,
+                Err(error) => {
+                    debug!(address = device.address, error = %error, "self check failed");
+                    DeviceHealth::Unknown
+                }
+            };
+            devices.push(TopologyDevice {
+                address: device.address,
+                category: device.category.clone(),
+                manufacturer: probe.get_manufacturer_id().await.ok(),
+                product_code: probe.get_product_code().await.ok(),
+                serial: device.serial.clone(),
+                software_revision: probe.get_software_revision().await.ok(),
+                health,
+            });
+        }
+        Self {
+            devices,
+            duplicates: enumeration.duplicates.clone(),
+        }
+    }
+
+    /// Renders the topology as a GraphViz `dot` graph, the host is linked to every device and
+    /// devices sharing a serial number are linked in red.
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cctalk {\n  rankdir=LR;\n  node [shape=box];\n");
+        let _ = writeln!(
+            dot,
+            "  host [label=\"Host\\naddress {}\", shape=ellipse];",
+            BusAddress::HOST.get()
+        );
+        for device in &self.devices {
+            let mut label = format!("{:?} @ {}", device.category, device.address);
+            let identity: Vec<String> = device
+                .manufacturer
+                .map(|manufacturer| manufacturer.abbreviated_name().to_string())
+                .into_iter()
+                .chain(device.product_code.clone())
+                .collect();
+            if !identity.is_empty() {
+                let _ = write!(label, "\n{}", identity.join(" "));
+            }
+            if let Some(serial) = &device.serial {
+                let _ = write!(label, "\nserial {serial}");
+            }
+            if let Some(revision) = &device.software_revision {
+                let _ = write!(label, "\nfirmware {revision}");
+            }
+            let _ = write!(label, "\nhealth: {}", device.health.label());
+            let color = match device.health {
+                DeviceHealth::Ok => "darkgreen",
+                DeviceHealth::Faults(_) => "red",
+                DeviceHealth::Unknown => "gray",
+            };
+            let _ = writeln!(
+                dot,
+                "  d{} [label={}, color={color}];\n  host -> d{};",
+                device.address,
+                dot_string(&label),
+                device.address
+            );
+        }
+        for duplicate in &self.duplicates {
+            for pair in duplicate.addresses.windows(2) {
+                let _ = writeln!(
+                    dot,
+                    "  d{} -> d{} [label={}, color=red, style=dashed, dir=none];",
+                    pair[0],
+                    pair[1],
+                    dot_string(&format!("same serial {}", duplicate.serial))
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+async fn self_check(probe: &Probe, category: &Category) -> Result<DeviceHealth, CommandError> {
+    if *category == Category::Payout {
+        let packet = probe.send_command(TestHopperCommand).await?;
+        let flags = TestHopperCommand.parse_response(packet.get_data()?)?;
+        return Ok(if flags.is_empty() {
+            DeviceHealth::Ok
+        } else {
+            DeviceHealth::Faults(flags.iter().map(|flag| format!("{flag:?}")).collect())
+        });
+    }
+    let packet = probe.send_command(PerformSelfCheckCommand).await?;
+    let fault = PerformSelfCheckCommand.parse_response(packet.get_data()?)?;
+    Ok(if fault.is_ok() {
+        DeviceHealth::Ok
+    } else {
+        DeviceHealth::Faults(vec![format!("{:?}", fault.code)])
+    })
+}
+
+/// Serializes the `cc_talk_core` types, which do not implement `Serialize`, as strings.
+#[cfg(feature = "serde")]
+pub(crate) mod ser {
+    use std::fmt;
+
+    use serde::Serializer;
+
+    pub(crate) fn debug<T: fmt::Debug, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{value:?}"))
+    }
+
+    pub(crate) fn display<T: fmt::Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    #[allow(clippy::ref_option)]
+    pub(crate) fn display_option<T: fmt::Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Quotes a `dot` label, new lines are kept as line breaks.
+fn dot_string(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::device::enumeration::EnumeratedDevice;
+
+    fn topology() -> BusTopology {
+        let serial = SerialCode::new(0, 11, 184);
+        let enumeration = BusEnumeration::new(vec![
+            EnumeratedDevice {
+                address: 3,
+                category: Category::Payout,
+                serial: Some(serial.clone()),
+            },
+            EnumeratedDevice {
+                address: 4,
+                category: Category::Payout,
+                serial: Some(serial),
+            },
+        ]);
+        let mut devices: Vec<TopologyDevice> = enumeration
+            .devices
+            .iter()
+            .map(|device| TopologyDevice {
+                address: device.address,
+                category: device.category.clone(),
+                manufacturer: None,
+                product_code: Some("SCH\"2".to_string()),
+                serial: device.serial.clone(),
+                software_revision: None,
+                health: DeviceHealth::Ok,
+            })
+            .collect();
+        devices[1].health = DeviceHealth::Faults(vec!["PayoutDisabled".to_string()]);
+        BusTopology {
+            devices,
+            duplicates: enumeration.duplicates,
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_export_lists_devices_and_duplicates() {
+        let json = serde_json::to_value(topology()).expect("should serialize");
+
+        assert_eq!(
+            json["devices"][0],
+            serde_json::json!({
+                "address": 3,
+                "category": "Payout",
+                "manufacturer": null,
+                "product_code": "SCH\"2",
+                "serial": "0.11.184",
+                "software_revision": null,
+                "health": "ok",
+            })
+        );
+        assert_eq!(
+            json["devices"][1]["health"],
+            serde_json::json!({"faults": ["PayoutDisabled"]})
+        );
+        assert_eq!(
+            json["duplicate_serials"],
+            serde_json::json!([{"category": "Payout", "serial": "0.11.184", "addresses": [3, 4]}])
+        );
+        assert_eq!(
+            serde_json::to_string(&BusTopology::default()).expect("should serialize"),
+            "{\"devices\":[],\"duplicate_serials\":[]}"
+        );
+    }
+
+    #[test]
+    fn dot_export_links_the_host_and_the_duplicates() {
+        let dot = topology().to_dot();
+
+        assert!(dot.starts_with("digraph cctalk {"));
+        assert!(dot.contains("  host -> d3;\n"));
+        assert!(dot.contains("d4 [label=\"Payout @ 4\\nSCH\\\"2\\nserial 0.11.184\\nhealth: PayoutDisabled\", color=red];"));
+        assert!(dot.contains("d3 -> d4 [label=\"same serial 0.11.184\", color=red"));
+    }
+}
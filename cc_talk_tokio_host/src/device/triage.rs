@@ -0,0 +1,321 @@
+use std::fmt;
+
+use cc_talk_core::cc_talk::{Address, Category, Device, Packet};
+use cc_talk_host::{
+    command::Command,
+    core::core_commands::{RequestEquipementCategoryIdCommand, SimplePollCommand},
+    device::device_commands::RequestCommsStatusVariablesCommand,
+};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, instrument, trace};
+
+use crate::transport::tokio_transport::{TransportError, TransportMessage};
+
+/// Communication error counters kept by the device, see `RequestCommsStatusVariables`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommsStatus {
+    pub rx_timeouts: u8,
+    pub rx_bytes_ignored: u8,
+    pub rx_bad_checksums: u8,
+}
+
+impl From<(u8, u8, u8)> for CommsStatus {
+    fn from((rx_timeouts, rx_bytes_ignored, rx_bad_checksums): (u8, u8, u8)) -> Self {
+        Self {
+            rx_timeouts,
+            rx_bytes_ignored,
+            rx_bad_checksums,
+        }
+    }
+}
+
+/// Likely cause of a command timing out, found by [`triage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnresponsiveCause {
+    /// Nothing answers, neither at the device address nor at the default address of its
+    /// category.
+    Unpowered,
+    /// A device of the same category answers at the default address of the category instead.
+    WrongAddress { answering_address: u8 },
+    /// Bytes come back but never form a valid frame.
+    WrongBaud,
+    /// The device answers `SimplePoll` but not the command which timed out.
+    Wedged {
+        /// `None` if the device does not support `RequestCommsStatusVariables`.
+        comms_status: Option<CommsStatus>,
+    },
+}
+
+impl fmt::Display for UnresponsiveCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unpowered => write!(f, "no answer on the bus, check the power and the cabling"),
+            Self::WrongAddress { answering_address } => write!(
+                f,
+                "the device answers at address {answering_address}, check its address selector"
+            ),
+            Self::WrongBaud => write!(
+                f,
+                "replies are garbled, check the baud rate and the line termination"
+            ),
+            Self::Wedged { comms_status: None } => {
+                write!(f, "the device answers polls but not commands, reset it")
+            }
+            Self::Wedged {
+                comms_status: Some(status),
+            } => write!(
+                f,
+                "the device answers polls but not commands ({} rx timeouts, {} ignored bytes, {} bad checksums), reset it",
+                status.rx_timeouts, status.rx_bytes_ignored, status.rx_bad_checksums
+            ),
+        }
+    }
+}
+
+/// Configuration of the [`triage`] run by the drivers, see
+/// [`DeviceCommon::timeout_triage`](super::base::DeviceCommon::timeout_triage).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TriageConfig {
+    /// One bit per bus address.
+    known_addresses: [u64; 4],
+}
+
+impl TriageConfig {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            known_addresses: [0; 4],
+        }
+    }
+
+    /// Adds the address of another device configured on the bus.
+    ///
+    /// The default address of a category is not probed when it is the address of a known
+    /// device, the device answering there is not the unresponsive one.
+    #[must_use]
+    pub const fn with_known_address(mut self, address: u8) -> Self {
+        self.known_addresses[address as usize / 64] |= 1 << (address % 64);
+        self
+    }
+
+    /// Adds the addresses of the other devices configured on the bus, see
+    /// [`Self::with_known_address`].
+    #[must_use]
+    pub fn with_known_addresses(self, addresses: impl IntoIterator<Item = u8>) -> Self {
+        addresses
+            .into_iter()
+            .fold(self, |config, address| config.with_known_address(address))
+    }
+
+    /// Returns `true` if a device is configured at `address`.
+    #[must_use]
+    pub const fn is_known(&self, address: u8) -> bool {
+        self.known_addresses[address as usize / 64] & (1 << (address % 64)) != 0
+    }
+}
+
+/// Works out why `device` stopped answering.
+///
+/// The device is polled first, a device which still answers is wedged and its comms status
+/// variables are read when supported. Otherwise the default address of its category is polled
+/// to detect a mis-configured address selector, unless another device known to `config` is
+/// configured there. Every probe goes through the transport retries, a triage of an unpowered
+/// device takes several timeouts.
+#[instrument(skip(sender, config), fields(address = device.address()), level = "debug")]
+pub async fn triage(
+    sender: &mpsc::Sender<TransportMessage>,
+    device: &Device,
+    config: &TriageConfig,
+) -> UnresponsiveCause {
+    match exchange(sender, device, SimplePollCommand).await {
+        Ok(_) => {
+            let comms_status = exchange(sender, device, RequestCommsStatusVariablesCommand)
+                .await
+                .ok()
+                .and_then(|reply| parse(&RequestCommsStatusVariablesCommand, reply))
+                .map(CommsStatus::from);
+            debug!(comms_status = ?comms_status, "device answers polls");
+            return UnresponsiveCause::Wedged { comms_status };
+        }
+        Err(TransportError::ChecksumError) => {
+            debug!("device answers with garbled replies");
+            return UnresponsiveCause::WrongBaud;
+        }
+        Err(error) => trace!(error = %error, "device does not answer polls"),
+    }
+
+    let default_address = match device.category().default_address() {
+        Address::Single(address) | Address::SingleAndRange(address, _) => address,
+    };
+    if config.is_known(default_address) {
+        debug!(
+            default_address,
+            "default address belongs to a known device, not probed"
+        );
+    } else if *device.category() != Category::Unknown && default_address != device.address() {
+        let default_device = Device::new(
+            default_address,
+            device.category().clone(),
+            *device.checksum_type(),
+        );
+        let category = exchange(sender, &default_device, RequestEquipementCategoryIdCommand)
+            .await
+            .ok()
+            .and_then(|reply| parse(&RequestEquipementCategoryIdCommand, reply));
+        if category.as_ref() == Some(device.category()) {
+            debug!(default_address, "device answers at the default address");
+            return UnresponsiveCause::WrongAddress {
+                answering_address: default_address,
+            };
+        }
+    }
+
+    UnresponsiveCause::Unpowered
+}
+
+async fn exchange<C>(
+    sender: &mpsc::Sender<TransportMessage>,
+    device: &Device,
+    command: C,
+) -> Result<Vec<u8>, TransportError>
+where
+    C: Command,
+{
+    let (tx, rx) = oneshot::channel();
+    sender
+        .send(TransportMessage::new(device, command, tx))
+        .await
+        .map_err(|_| TransportError::SocketWriteError)?;
+    rx.await.map_err(|_| TransportError::SocketReadError)?
+}
+
+fn parse<C>(command: &C, reply: Vec<u8>) -> Option<C::Response>
+where
+    C: Command,
+{
+    let packet = Packet::new(reply);
+    command.parse_response(packet.get_data().ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{ChecksumType, Header};
+
+    use super::*;
+    use crate::device::{
+        base::{CommandError, DeviceCommon},
+        payout::PayoutDevice,
+    };
+
+    fn spawn_bus(
+        mut rx: mpsc::Receiver<TransportMessage>,
+        answer: impl Fn(&TransportMessage) -> Result<Vec<u8>, TransportError> + Send + 'static,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let reply = answer(&message).map(|data| {
+                    let mut reply = vec![1, data.len() as u8, message.address, 0];
+                    reply.extend(data);
+                    reply.push(0);
+                    reply
+                });
+                message.respond_to.send(reply).expect("should respond");
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_classified_when_triage_is_enabled() {
+        let (tx, rx) = mpsc::channel(1);
+        let device = Device::new(4, Category::Payout, ChecksumType::Crc8);
+        let hopper = PayoutDevice::new(device, tx).with_timeout_triage();
+        let bus = spawn_bus(rx, |message| match (message.address, message.header) {
+            (4, Header::SimplePoll) => Ok(vec![]),
+            (4, Header::RequestCommsStatusVariables) => Ok(vec![5, 0, 2]),
+            (3, Header::RequestEquipementCategoryId) => Ok(b"Payout".to_vec()),
+            _ => Err(TransportError::Timeout),
+        });
+
+        assert_eq!(
+            hopper.get_payout_status().await,
+            Err(CommandError::Unresponsive {
+                address: 4,
+                cause: UnresponsiveCause::Wedged {
+                    comms_status: Some(CommsStatus {
+                        rx_timeouts: 5,
+                        rx_bytes_ignored: 0,
+                        rx_bad_checksums: 2,
+                    }),
+                },
+            })
+        );
+        assert!(hopper.triages_timeouts());
+
+        drop(hopper);
+        bus.await.expect("should join");
+    }
+
+    #[tokio::test]
+    async fn triage_detects_wrong_address_wrong_baud_and_unpowered() {
+        let (tx, rx) = mpsc::channel(1);
+        let bus = spawn_bus(rx, |message| match (message.address, message.header) {
+            (3, Header::RequestEquipementCategoryId) => Ok(b"Payout".to_vec()),
+            (40, _) => Err(TransportError::ChecksumError),
+            _ => Err(TransportError::Timeout),
+        });
+
+        let hopper = Device::new(4, Category::Payout, ChecksumType::Crc8);
+        assert_eq!(
+            triage(&tx, &hopper, &TriageConfig::new()).await,
+            UnresponsiveCause::WrongAddress {
+                answering_address: 3
+            }
+        );
+        let validator = Device::new(40, Category::BillValidator, ChecksumType::Crc8);
+        assert_eq!(
+            triage(&tx, &validator, &TriageConfig::new()).await,
+            UnresponsiveCause::WrongBaud
+        );
+        let acceptor = Device::new(5, Category::CoinAcceptor, ChecksumType::Crc8);
+        assert_eq!(
+            triage(&tx, &acceptor, &TriageConfig::new()).await,
+            UnresponsiveCause::Unpowered
+        );
+
+        let plain = PayoutDevice::new(Device::new(4, Category::Payout, ChecksumType::Crc8), tx);
+        assert_eq!(plain.get_payout_status().await, Err(CommandError::Timeout));
+
+        drop(plain);
+        bus.await.expect("should join");
+    }
+
+    #[tokio::test]
+    async fn configured_device_at_the_default_address_is_not_a_wrong_address() {
+        let (tx, rx) = mpsc::channel(1);
+        // The hopper configured at 3 answers, the one configured at 4 is unpowered.
+        let bus = spawn_bus(rx, |message| match (message.address, message.header) {
+            (3, Header::SimplePoll) => Ok(vec![]),
+            (3, Header::RequestEquipementCategoryId) => Ok(b"Payout".to_vec()),
+            _ => Err(TransportError::Timeout),
+        });
+
+        let unpowered = PayoutDevice::new(
+            Device::new(4, Category::Payout, ChecksumType::Crc8),
+            tx.clone(),
+        )
+        .with_timeout_triage_config(TriageConfig::new().with_known_addresses([3, 4]));
+        let powered = PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx)
+            .with_timeout_triage_config(TriageConfig::new().with_known_addresses([3, 4]));
+        assert!(powered.simple_poll().await.is_ok());
+        assert_eq!(
+            unpowered.simple_poll().await,
+            Err(CommandError::Unresponsive {
+                address: 4,
+                cause: UnresponsiveCause::Unpowered,
+            })
+        );
+
+        drop((powered, unpowered));
+        bus.await.expect("should join");
+    }
+}
@@ -1,2 +1,22 @@
+pub(crate) mod bus_stream;
+pub mod correlation;
+pub mod encryption;
+pub mod frame_log;
+pub mod handle;
+pub mod health;
+#[cfg(feature = "insecure-debug")]
+pub mod insecure_debug;
+pub mod machine_state;
+pub mod queue;
+pub mod response_caps;
 pub mod retry;
+pub mod scheduler;
+#[cfg(feature = "serial")]
+pub mod serial;
+pub mod stats;
+#[cfg(feature = "tcp")]
+pub mod tcp;
+pub mod timestamp;
 pub mod tokio_transport;
+pub mod unsolicited;
+pub mod watchdog;
@@ -0,0 +1,202 @@
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+#[cfg(feature = "tcp")]
+use tokio::net::TcpStream;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::UnixStream,
+};
+#[cfg(feature = "serial")]
+use tokio_serial::{SerialPort, SerialStream};
+
+#[cfg(feature = "serial")]
+use super::serial::SerialConfig;
+#[cfg(feature = "tcp")]
+use super::tcp::{ReconnectConfig, TcpConfig};
+
+/// Where the transport reaches the bus.
+#[derive(Debug, Clone)]
+pub(crate) enum Endpoint {
+    UnixSocket(String),
+    #[cfg(feature = "serial")]
+    Serial(SerialConfig),
+    #[cfg(feature = "tcp")]
+    Tcp(TcpConfig),
+}
+
+impl Endpoint {
+    pub(crate) async fn connect(&self) -> io::Result<BusStream> {
+        match self {
+            Self::UnixSocket(path) => UnixStream::connect(path).await.map(BusStream::Unix),
+            #[cfg(feature = "serial")]
+            Self::Serial(config) => config
+                .open()
+                .map(|stream| BusStream::Serial {
+                    stream,
+                    inter_byte_timeout: config.inter_byte_timeout,
+                })
+                .map_err(io::Error::from),
+            #[cfg(feature = "tcp")]
+            Self::Tcp(config) => {
+                let stream = tokio::time::timeout(
+                    config.connect_timeout,
+                    TcpStream::connect(&config.address),
+                )
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+                // Frames are short, waiting to coalesce them only adds latency.
+                stream.set_nodelay(true)?;
+                Ok(BusStream::Tcp(stream))
+            }
+        }
+    }
+
+    /// Whether a lost connection is re-established, see [`Self::reconnect_config`].
+    pub(crate) const fn reconnects(&self) -> bool {
+        match self {
+            #[cfg(feature = "tcp")]
+            Self::Tcp(_) => true,
+            _ => false,
+        }
+    }
+
+    /// How a lost connection is re-established, `None` if it is not.
+    #[cfg(feature = "tcp")]
+    pub(crate) const fn reconnect_config(&self) -> Option<&ReconnectConfig> {
+        match self {
+            Self::Tcp(config) => Some(&config.reconnect),
+            _ => None,
+        }
+    }
+
+    /// Baud rate used by the baud fallback recovery step, `None` if the endpoint has no baud
+    /// rate.
+    pub(crate) const fn fallback_baud_rate(&self) -> Option<u32> {
+        match self {
+            #[cfg(feature = "serial")]
+            Self::Serial(config) => Some(config.fallback_baud_rate),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnixSocket(path) => write!(f, "socket {path}"),
+            #[cfg(feature = "serial")]
+            Self::Serial(config) => write!(f, "serial port {}", config.path),
+            #[cfg(feature = "tcp")]
+            Self::Tcp(config) => write!(f, "bridge {}", config.address),
+        }
+    }
+}
+
+/// Connection to the bus.
+pub(crate) enum BusStream {
+    Unix(UnixStream),
+    #[cfg(feature = "tcp")]
+    Tcp(TcpStream),
+    #[cfg(feature = "serial")]
+    Serial {
+        stream: SerialStream,
+        inter_byte_timeout: Duration,
+    },
+}
+
+impl BusStream {
+    /// Reads the bytes already received, without waiting.
+    pub(crate) fn try_read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Unix(stream) => stream.try_read(buffer),
+            #[cfg(feature = "tcp")]
+            Self::Tcp(stream) => stream.try_read(buffer),
+            #[cfg(feature = "serial")]
+            Self::Serial { stream, .. } => stream.try_read(buffer),
+        }
+    }
+
+    /// Maximum gap between two bytes of a frame, `None` if frames are not timed byte by byte.
+    pub(crate) const fn inter_byte_timeout(&self) -> Option<Duration> {
+        match self {
+            #[cfg(feature = "serial")]
+            Self::Serial {
+                inter_byte_timeout, ..
+            } => Some(*inter_byte_timeout),
+            _ => None,
+        }
+    }
+
+    /// Changes the baud rate, returns `false` if the connection has no baud rate.
+    #[cfg_attr(
+        not(feature = "serial"),
+        allow(unused_variables, clippy::unnecessary_wraps)
+    )]
+    pub(crate) fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<bool> {
+        match self {
+            #[cfg(feature = "serial")]
+            Self::Serial { stream, .. } => stream
+                .set_baud_rate(baud_rate)
+                .map(|()| true)
+                .map_err(io::Error::from),
+            _ => Ok(false),
+        }
+    }
+}
+
+impl AsyncRead for BusStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "tcp")]
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "serial")]
+            Self::Serial { stream, .. } => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for BusStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "tcp")]
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "serial")]
+            Self::Serial { stream, .. } => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "tcp")]
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "serial")]
+            Self::Serial { stream, .. } => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "tcp")]
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "serial")]
+            Self::Serial { stream, .. } => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static NEXT_CORRELATION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Identifier of a submitted command, used to follow it through the logs of every layer.
+///
+/// Ids come from a process wide monotonic sequence, a later command always gets a greater id.
+/// Every [`TransportMessage`](super::tokio_transport::TransportMessage) gets one, it is found in
+/// the transport logs, the [`FrameLog`](super::frame_log::FrameLog) frames and the
+/// [`TransportStats`](super::stats::TransportStats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CorrelationId(u64);
+
+impl CorrelationId {
+    /// Returns the next id of the sequence.
+    #[must_use]
+    pub fn next() -> Self {
+        Self(NEXT_CORRELATION_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    #[must_use]
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ids_are_monotonic() {
+        let first = CorrelationId::next();
+        let second = CorrelationId::next();
+        assert!(second > first);
+        assert_eq!(first.to_string(), format!("#{}", first.get()));
+    }
+}
@@ -0,0 +1,109 @@
+//! Protocol level encryption of the frames exchanged with the devices.
+//!
+//! The transport encrypts the frames of the devices with a key using the [`FrameCipher`] it was
+//! given, the drivers keep working unchanged. Keys change at runtime through an
+//! [`EncryptionKeys`] handle, the key sent with `SwitchEncryptionCode` is applied by the
+//! transport itself once the device acknowledged it.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use cc_talk_core::cc_talk::{BnvKey, FrameCipher};
+use tracing::info;
+
+/// Frame cipher shared by the transport and its messages.
+pub type SharedFrameCipher = Arc<dyn FrameCipher + Send + Sync>;
+
+/// Shared protocol encryption keys, keyed by device address.
+///
+/// Clones share the same keys, the application keeps one to set the key of a device after
+/// power up or to clear it once the device stopped encrypting.
+#[derive(Debug, Clone, Default)]
+pub struct EncryptionKeys {
+    keys: Arc<Mutex<HashMap<u8, BnvKey>>>,
+}
+
+impl EncryptionKeys {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the key of the device at `address`, `None` if its frames are not encrypted.
+    #[must_use]
+    pub fn get(&self, address: u8) -> Option<BnvKey> {
+        self.keys
+            .lock()
+            .expect("should not be poisoned")
+            .get(&address)
+            .copied()
+    }
+
+    /// Encrypts the frames of the device at `address` with `key` from the next message on,
+    /// returns the previous key.
+    pub fn set(&self, address: u8, key: BnvKey) -> Option<BnvKey> {
+        info!(address, "protocol encryption key set");
+        self.keys
+            .lock()
+            .expect("should not be poisoned")
+            .insert(address, key)
+    }
+
+    /// Stops encrypting the frames of the device at `address`, returns the previous key.
+    pub fn clear(&self, address: u8) -> Option<BnvKey> {
+        let previous = self
+            .keys
+            .lock()
+            .expect("should not be poisoned")
+            .remove(&address);
+        if previous.is_some() {
+            info!(address, "protocol encryption key cleared");
+        }
+        previous
+    }
+}
+
+/// Cipher and key a single frame is encrypted with.
+#[derive(Clone)]
+pub(crate) struct FrameEncryption {
+    pub cipher: SharedFrameCipher,
+    pub key: BnvKey,
+}
+
+impl FrameEncryption {
+    pub fn encrypt(&self, frame: &mut [u8]) {
+        self.cipher.encrypt(&self.key, frame);
+    }
+
+    pub fn decrypt(&self, frame: &mut [u8]) {
+        self.cipher.decrypt(&self.key, frame);
+    }
+}
+
+impl std::fmt::Debug for FrameEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameEncryption")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys_are_shared_between_clones() {
+        let keys = EncryptionKeys::new();
+        let transport = keys.clone();
+        let key = BnvKey::new(123_456).expect("valid key");
+
+        assert_eq!(keys.set(3, key), None);
+        assert_eq!(transport.get(3), Some(key));
+        assert_eq!(transport.get(4), None);
+        assert_eq!(keys.clear(3), Some(key));
+        assert_eq!(transport.get(3), None);
+    }
+}
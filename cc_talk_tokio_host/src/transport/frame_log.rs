@@ -0,0 +1,371 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use cc_talk_core::cc_talk::Packet;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{mpsc, oneshot},
+};
+use tracing::{debug, warn};
+
+use super::correlation::CorrelationId;
+
+/// Default number of frames kept by a [`FrameLog`].
+pub const DEFAULT_FRAME_LOG_CAPACITY: usize = 64;
+
+/// Number of dumps waiting to be written before new dumps are dropped.
+const DUMP_QUEUE_CAPACITY: usize = 16;
+
+/// Direction of a raw frame on the bus, seen from the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+    /// Written by the host.
+    Tx,
+    /// Read from the bus.
+    Rx,
+}
+
+impl fmt::Display for FrameDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tx => write!(f, "TX"),
+            Self::Rx => write!(f, "RX"),
+        }
+    }
+}
+
+/// A raw frame as written to or read from the bus.
+///
+/// Received frames may be truncated if the read failed half way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame {
+    pub direction: FrameDirection,
+    pub timestamp: SystemTime,
+    pub bytes: Vec<u8>,
+    /// Message the frame was exchanged for, `None` for unsolicited frames.
+    pub correlation_id: Option<CorrelationId>,
+}
+
+impl RawFrame {
+    /// Returns the frame bytes as space separated hex.
+    #[must_use]
+    pub fn hex(&self) -> String {
+        let mut hex = String::with_capacity(self.bytes.len() * 3);
+        for (i, byte) in self.bytes.iter().enumerate() {
+            if i > 0 {
+                hex.push(' ');
+            }
+            let _ = write!(hex, "{byte:02X}");
+        }
+        hex
+    }
+
+    /// Returns a human readable description of the frame fields, see [`Packet::display`].
+    #[must_use]
+    pub fn decoded(&self) -> String {
+        Packet::new(self.bytes.as_slice()).display().to_string()
+    }
+
+    /// Returns a multi-line annotated hexdump of the frame, see [`Packet::hexdump`].
+    #[must_use]
+    pub fn hexdump(&self) -> String {
+        Packet::new(self.bytes.as_slice()).hexdump().to_string()
+    }
+}
+
+impl fmt::Display for RawFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        write!(f, "{millis} {}", self.direction)?;
+        if let Some(correlation_id) = self.correlation_id {
+            write!(f, " {correlation_id}")?;
+        }
+        write!(f, " [{}] {}", self.hex(), self.decoded())
+    }
+}
+
+/// Bounded ring buffer of the last raw frames exchanged on a bus.
+///
+/// The transport records every frame it writes and reads, and dumps the buffer at debug level
+/// when a protocol error or an unexpected timeout occurs, see
+/// [`CcTalkTokioTransport::with_frame_log`](super::tokio_transport::CcTalkTokioTransport::with_frame_log).
+/// Clones of this handle can be kept by the application to inspect the frames.
+#[derive(Debug, Clone)]
+pub struct FrameLog {
+    frames: Arc<Mutex<VecDeque<RawFrame>>>,
+    capacity: usize,
+    dump_file: Option<Arc<DumpFile>>,
+}
+
+impl Default for FrameLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAME_LOG_CAPACITY)
+    }
+}
+
+impl FrameLog {
+    /// Creates a frame log keeping the last `capacity` frames.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            dump_file: None,
+        }
+    }
+
+    /// Appends every dump to the given file, in addition to the debug log.
+    ///
+    /// Within a tokio runtime the file is written by a task of its own, dumps never block the
+    /// transport.
+    #[must_use]
+    pub fn with_dump_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dump_file = Some(Arc::new(DumpFile {
+            path: path.into(),
+            writer: OnceLock::new(),
+        }));
+        self
+    }
+
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns a copy of the buffered frames, oldest first.
+    #[must_use]
+    pub fn frames(&self) -> Vec<RawFrame> {
+        self.frames
+            .lock()
+            .expect("should not be poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.frames.lock().expect("should not be poisoned").clear();
+    }
+
+    pub(crate) fn record(
+        &self,
+        direction: FrameDirection,
+        bytes: &[u8],
+        correlation_id: Option<CorrelationId>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut frames = self.frames.lock().expect("should not be poisoned");
+        if frames.len() == self.capacity {
+            frames.pop_front();
+        }
+        frames.push_back(RawFrame {
+            direction,
+            timestamp: SystemTime::now(),
+            bytes: bytes.to_vec(),
+            correlation_id,
+        });
+    }
+
+    /// Dumps the buffered frames at debug level, and to the dump file if one is set.
+    pub fn dump(&self, reason: &str) {
+        let frames = self.frames();
+        debug!("dumping {} raw frames: {}", frames.len(), reason);
+        for frame in &frames {
+            debug!("{}", frame);
+        }
+
+        let Some(dump_file) = &self.dump_file else {
+            return;
+        };
+        let mut dump = format!("--- {reason}\n");
+        for frame in &frames {
+            let _ = writeln!(dump, "{frame}");
+        }
+        dump_file.write(dump);
+    }
+
+    /// Waits for the dumps queued for the dump file to be written.
+    pub async fn flush(&self) {
+        if let Some(dump_file) = &self.dump_file {
+            dump_file.flush().await;
+        }
+    }
+}
+
+enum DumpJob {
+    Write(String),
+    Flush(oneshot::Sender<()>),
+}
+
+/// File the dumps are appended to, written by a task spawned with the first dump.
+#[derive(Debug)]
+struct DumpFile {
+    path: PathBuf,
+    writer: OnceLock<mpsc::Sender<DumpJob>>,
+}
+
+impl DumpFile {
+    fn write(&self, dump: String) {
+        // Outside of a runtime there is no event loop to block, the dump is written in place.
+        if tokio::runtime::Handle::try_current().is_err() {
+            if let Err(error) = append(&self.path, &dump) {
+                warn!(
+                    "unable to dump raw frames to {}: {}",
+                    self.path.display(),
+                    error
+                );
+            }
+            return;
+        }
+
+        let writer = self.writer.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel(DUMP_QUEUE_CAPACITY);
+            tokio::spawn(write_dumps(self.path.clone(), receiver));
+            sender
+        });
+        if writer.try_send(DumpJob::Write(dump)).is_err() {
+            warn!(
+                "raw frame dump to {} dropped, the writer is behind",
+                self.path.display()
+            );
+        }
+    }
+
+    async fn flush(&self) {
+        let Some(writer) = self.writer.get() else {
+            return;
+        };
+        let (done, written) = oneshot::channel();
+        if writer.send(DumpJob::Flush(done)).await.is_ok() {
+            let _ = written.await;
+        }
+    }
+}
+
+async fn write_dumps(path: PathBuf, mut jobs: mpsc::Receiver<DumpJob>) {
+    while let Some(job) = jobs.recv().await {
+        match job {
+            DumpJob::Write(dump) => {
+                if let Err(error) = append_async(&path, &dump).await {
+                    warn!("unable to dump raw frames to {}: {}", path.display(), error);
+                }
+            }
+            DumpJob::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+fn append(path: &Path, dump: &str) -> io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(dump.as_bytes())?;
+    file.flush()
+}
+
+async fn append_async(path: &Path, dump: &str) -> io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(dump.as_bytes()).await?;
+    file.flush().await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_frames() {
+        let log = FrameLog::new(2);
+        let clone = log.clone();
+
+        log.record(FrameDirection::Tx, &[2, 0, 1, 254, 255], None);
+        log.record(FrameDirection::Rx, &[1, 0, 2, 0, 253], None);
+        log.record(FrameDirection::Tx, &[2, 0, 1, 245, 8], None);
+
+        let frames = clone.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].direction, FrameDirection::Rx);
+        assert_eq!(frames[1].bytes, vec![2, 0, 1, 245, 8]);
+
+        clone.clear();
+        assert!(log.frames().is_empty());
+    }
+
+    #[test]
+    fn frames_are_decoded() {
+        let log = FrameLog::new(4);
+        log.record(FrameDirection::Tx, &[2, 0, 1, 254, 255], None);
+        log.record(FrameDirection::Rx, &[1, 2, 2, 0, 0xAB, 0xCD, 0x10], None);
+        log.record(FrameDirection::Rx, &[1, 4, 2, 0, 1], None);
+        log.record(FrameDirection::Rx, &[1, 4], None);
+
+        let frames = log.frames();
+        assert_eq!(frames[0].hex(), "02 00 01 FE FF");
+        assert_eq!(
+            frames[0].decoded(),
+            "dst=2 src=1 header=SimplePoll(254) len=0 data=[] checksum=FF crc8 ok"
+        );
+        assert_eq!(
+            frames[1].decoded(),
+            "dst=1 src=2 header=Reply(0) len=2 data=[AB CD] checksum=10 bad"
+        );
+        assert_eq!(
+            frames[2].decoded(),
+            "dst=1 src=2 header=Reply(0) len=4 data=[01] (incomplete)"
+        );
+        assert_eq!(frames[3].decoded(), "truncated [01 04]");
+        assert!(frames[0].hexdump().starts_with("0000  02"));
+    }
+
+    #[test]
+    fn dump_appends_to_file() {
+        let dir = tempfile::tempdir().expect("should create a temp dir");
+        let path = dir.path().join("frames.log");
+        let log = FrameLog::new(4).with_dump_file(&path);
+        log.record(FrameDirection::Tx, &[2, 0, 1, 254, 255], None);
+
+        log.dump("first");
+        log.dump("second");
+
+        let content = std::fs::read_to_string(&path).expect("should read the dump");
+        assert_eq!(content.matches("TX [02 00 01 FE FF]").count(), 2);
+        assert!(content.contains("--- first"));
+        assert!(content.contains("--- second"));
+    }
+
+    #[tokio::test]
+    async fn dump_is_written_by_a_task_within_a_runtime() {
+        let dir = tempfile::tempdir().expect("should create a temp dir");
+        let path = dir.path().join("frames.log");
+        let log = FrameLog::new(4).with_dump_file(&path);
+        log.record(FrameDirection::Rx, &[1, 0, 2, 0, 253], None);
+
+        log.dump("first");
+        log.dump("second");
+        log.flush().await;
+
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .expect("should read the dump");
+        assert_eq!(content.matches("RX [01 00 02 00 FD]").count(), 2);
+        assert!(content.find("--- first") < content.find("--- second"));
+    }
+}
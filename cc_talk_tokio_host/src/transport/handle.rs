@@ -0,0 +1,195 @@
+//! Cloneable access to a running [`CcTalkTokioTransport`].
+//!
+//! # Ordering guarantees
+//!
+//! The transport task is the only one writing to the bus, it handles one message at a time from
+//! its write to its reply, retries included. Any number of tasks can therefore send through
+//! clones of the same [`TransportHandle`], or of the sender given to the drivers, without a lock
+//! of their own:
+//!
+//! - exchanges never interleave on the bus, a reply is always matched to its own request;
+//! - the messages to an address are handled in the order they reached the channel, those of a
+//!   single task to a single address therefore keep their order;
+//! - across addresses, the [scheduler](super::scheduler) picks the address with the highest
+//!   priority which was served least recently, by default no address is favoured;
+//! - an address with a minimum gap is skipped until the gap elapsed, the other addresses are
+//!   served in the meantime;
+//! - express messages, see [`TransportMessage::is_express`], overtake the queued messages and
+//!   may be sent in between the retries of the message on the wire;
+//! - with [`BackpressurePolicy::DropOldestPoll`](super::queue::BackpressurePolicy::DropOldestPoll)
+//!   a queued poll may be dropped, its sender gets [`TransportError::QueueFull`].
+//!
+//! Ordering across tasks talking to the same address is not defined, sequences relying on the
+//! device state, e.g. reading and then acknowledging events, belong in a single task or driver.
+//!
+//! The drivers only keep short-lived locks on their own state, none is held while waiting for a
+//! reply, so concurrent callers on different addresses only wait for the bus itself.
+
+use cc_talk_core::cc_talk::Device;
+use cc_talk_host::command::Command;
+use tokio::sync::{mpsc, oneshot};
+
+use super::tokio_transport::{CcTalkTokioTransport, TransportError, TransportMessage};
+
+/// Cloneable handle sending commands to any address through a running transport, see the
+/// [module documentation](self) for the ordering guarantees.
+#[derive(Debug, Clone)]
+pub struct TransportHandle {
+    sender: mpsc::Sender<TransportMessage>,
+    express_sender: Option<mpsc::Sender<TransportMessage>>,
+}
+
+impl TransportHandle {
+    /// Creates a handle sending through the channel the transport was created with.
+    pub const fn new(sender: mpsc::Sender<TransportMessage>) -> Self {
+        Self {
+            sender,
+            express_sender: None,
+        }
+    }
+
+    /// Sends the express commands through the express lane of the transport, see
+    /// [`CcTalkTokioTransport::express_sender`].
+    #[must_use]
+    pub fn with_express_lane(mut self, express_sender: mpsc::Sender<TransportMessage>) -> Self {
+        self.express_sender = Some(express_sender);
+        self
+    }
+
+    /// Returns the regular sender, e.g. to create a driver.
+    pub fn sender(&self) -> mpsc::Sender<TransportMessage> {
+        self.sender.clone()
+    }
+
+    /// Returns the express sender, if the handle has one.
+    pub fn express_sender(&self) -> Option<mpsc::Sender<TransportMessage>> {
+        self.express_sender.clone()
+    }
+
+    /// Sends the command to the device and returns the reply frame.
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`TransportError::Closed`] if the transport is not running, with the error
+    /// of the exchange otherwise.
+    pub async fn send<C: Command>(
+        &self,
+        device: &Device,
+        command: C,
+    ) -> Result<Vec<u8>, TransportError> {
+        let (respond_to, response) = oneshot::channel();
+        let message = TransportMessage::new(device, command, respond_to);
+        let sender = match &self.express_sender {
+            Some(express_sender) if message.is_express() => express_sender,
+            _ => &self.sender,
+        };
+        sender
+            .send(message)
+            .await
+            .map_err(|_| TransportError::Closed)?;
+        response.await.map_err(|_| TransportError::Closed)?
+    }
+}
+
+impl CcTalkTokioTransport {
+    /// Returns a [`TransportHandle`] sending through `sender`, the sender of the channel the
+    /// transport was created with, and through the express lane of the transport.
+    pub fn handle(&self, sender: mpsc::Sender<TransportMessage>) -> TransportHandle {
+        TransportHandle::new(sender).with_express_lane(self.express_sender())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use cc_talk_core::cc_talk::{Category, ChecksumType, crc8};
+    use cc_talk_host::core::core_commands::SimplePollCommand;
+    use tempfile::TempDir;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixListener,
+    };
+
+    use crate::transport::retry::RetryConfig;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_callers_keep_their_order_and_replies() {
+        let temp_dir = TempDir::new().expect("should create a temp dir");
+        let socket_path = temp_dir.path().join("bus.sock");
+        let listener = UnixListener::bind(&socket_path).expect("should bind");
+        let frames = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = frames.clone();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("should accept");
+            let mut frame = [0u8; 5];
+            while stream.read_exact(&mut frame).await.is_ok() {
+                recorded.lock().expect("should lock").push(frame[0]);
+                // Reply from the addressed device, slowly enough for the callers to race.
+                tokio::time::sleep(Duration::from_millis(2)).await;
+                let mut reply = [1, 0, frame[0], 0, 0];
+                reply[4] = crc8(&reply);
+                stream.write_all(&reply).await.expect("should reply");
+            }
+        });
+
+        let (tx, rx) = mpsc::channel(4);
+        let transport = CcTalkTokioTransport::new(
+            rx,
+            socket_path.to_string_lossy().to_string(),
+            Duration::from_millis(200),
+            Duration::ZERO,
+            RetryConfig::default(),
+            false,
+        );
+        let handle = transport.handle(tx);
+        tokio::spawn(transport.run());
+
+        let callers = [2u8, 3, 4].map(|address| {
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                let device = Device::new(address, Category::Unknown, ChecksumType::Crc8);
+                for _ in 0..5 {
+                    let reply = handle
+                        .send(&device, SimplePollCommand)
+                        .await
+                        .expect("should reply");
+                    assert_eq!(
+                        reply[2], address,
+                        "reply should come from the polled device"
+                    );
+                }
+            })
+        });
+        for caller in callers {
+            caller.await.expect("caller should not panic");
+        }
+
+        // Messages queued by a single task keep their order.
+        let [first, second, third] =
+            [5u8, 6, 7].map(|address| Device::new(address, Category::Unknown, ChecksumType::Crc8));
+        let (first, second, third) = tokio::join!(
+            handle.send(&first, SimplePollCommand),
+            handle.send(&second, SimplePollCommand),
+            handle.send(&third, SimplePollCommand),
+        );
+        assert!(first.is_ok() && second.is_ok() && third.is_ok());
+
+        let frames = frames.lock().expect("should lock").clone();
+        assert_eq!(frames[15..], [5, 6, 7]);
+        let frames = &frames[..15];
+        for address in [2, 3, 4] {
+            assert_eq!(frames.iter().filter(|&&a| a == address).count(), 5);
+        }
+        assert!(
+            frames.windows(2).any(|pair| pair[0] != pair[1]),
+            "callers should be interleaved: {frames:?}"
+        );
+    }
+}
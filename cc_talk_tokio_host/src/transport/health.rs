@@ -0,0 +1,79 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Communication health counters for a single device address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceCommsHealth {
+    /// Number of times the line had to be re-synchronized after a corrupted reply.
+    pub resync_count: u32,
+}
+
+/// Shared communication health registry, keyed by device address.
+///
+/// The transport owns the writing side, clones of this handle can be kept by the
+/// application to inspect the health of each device while the transport is running.
+#[derive(Debug, Clone, Default)]
+pub struct CommsHealth {
+    devices: Arc<Mutex<HashMap<u8, DeviceCommsHealth>>>,
+}
+
+impl CommsHealth {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the health counters for the given address.
+    ///
+    /// Devices which never had a recorded event return zeroed counters.
+    #[must_use]
+    pub fn device(&self, address: u8) -> DeviceCommsHealth {
+        self.devices
+            .lock()
+            .expect("should not be poisoned")
+            .get(&address)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns a copy of the health counters of every device seen so far.
+    #[must_use]
+    pub fn snapshot(&self) -> HashMap<u8, DeviceCommsHealth> {
+        self.devices.lock().expect("should not be poisoned").clone()
+    }
+
+    pub(crate) fn record_resync(&self, address: u8) {
+        let mut devices = self.devices.lock().expect("should not be poisoned");
+        let health = devices.entry(address).or_default();
+        health.resync_count = health.resync_count.saturating_add(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unknown_device_has_zeroed_counters() {
+        let health = CommsHealth::new();
+        assert_eq!(health.device(2), DeviceCommsHealth::default());
+        assert!(health.snapshot().is_empty());
+    }
+
+    #[test]
+    fn resyncs_are_counted_per_device() {
+        let health = CommsHealth::new();
+        let clone = health.clone();
+
+        health.record_resync(2);
+        health.record_resync(2);
+        health.record_resync(3);
+
+        assert_eq!(clone.device(2).resync_count, 2);
+        assert_eq!(clone.device(3).resync_count, 1);
+        assert_eq!(clone.snapshot().len(), 2);
+    }
+}
@@ -0,0 +1,92 @@
+//! Dual logging of encrypted exchanges, only built with the `insecure-debug` feature.
+//!
+//! Encryption layers call [`log_encrypted_payload`] with the payload as sent on the wire and
+//! as seen by the command, both are logged at trace level under the [`TARGET`] target, so
+//! they only show up once that target is explicitly enabled in the subscriber, e.g.
+//! `RUST_LOG=cc_talk_insecure=trace`.
+//!
+//! The plaintext of encrypted commands is what the encryption protects, never enable this
+//! feature in a production build. Payloads carrying key material, see
+//! [`carries_key_material`], are redacted on both sides.
+
+use cc_talk_core::cc_talk::Header;
+use tracing::trace;
+
+use super::frame_log::FrameDirection;
+
+/// Tracing target of the dual log.
+pub const TARGET: &str = "cc_talk_insecure";
+
+/// Returns `true` for the headers whose payload holds keys, PINs or RNG seeds.
+#[must_use]
+pub const fn carries_key_material(header: Header) -> bool {
+    matches!(
+        header,
+        Header::ReadDHPubKey
+            | Header::SendDHPubKey
+            | Header::RequestCipherKey
+            | Header::SwitchEncryptionKey
+            | Header::PumpRNG
+            | Header::EnterPinNumber
+            | Header::EnterNewPinNumber
+    )
+}
+
+/// Formats a payload for the dual log, redacting it if it carries key material.
+#[must_use]
+pub fn format_payload(header: Header, payload: &[u8]) -> String {
+    if carries_key_material(header) {
+        format!("<{} bytes redacted>", payload.len())
+    } else {
+        format!("{payload:02X?}")
+    }
+}
+
+/// Logs an encrypted payload next to its plaintext.
+///
+/// `layer` names the encryption layer, e.g. `"bnv"` or `"des"`, as several layers may be
+/// active on a single bus.
+pub fn log_encrypted_payload(
+    layer: &str,
+    address: u8,
+    header: Header,
+    direction: FrameDirection,
+    ciphertext: &[u8],
+    plaintext: &[u8],
+) {
+    trace!(
+        target: TARGET,
+        layer,
+        address,
+        header = header as u8,
+        %direction,
+        ciphertext = %format_payload(header, ciphertext),
+        plaintext = %format_payload(header, plaintext),
+        "encrypted payload"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_material_is_redacted() {
+        assert_eq!(
+            format_payload(Header::RequestCipherKey, &[1, 2, 3]),
+            "<3 bytes redacted>"
+        );
+        assert_eq!(
+            format_payload(Header::ReadEncryptedEvents, &[0x0A, 0xFF]),
+            "[0A, FF]"
+        );
+        log_encrypted_payload(
+            "test",
+            3,
+            Header::SendDHPubKey,
+            FrameDirection::Tx,
+            &[1],
+            &[2],
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Bus wide machine state restricting which commands reach the devices.
+//!
+//! A technician servicing the machine should not be able to trigger a payout by accident, the
+//! transport checks every message against the [`MachineState`] before writing it and fails the
+//! commands the state does not allow with
+//! [`TransportError::NotAllowed`](super::tokio_transport::TransportError::NotAllowed).
+//!
+//! Commands stopping the devices, see [`CommandClass::Safety`], are allowed in every state.
+
+use std::sync::{Arc, Mutex};
+
+use cc_talk_core::cc_talk::Header;
+use tracing::info;
+
+/// Operating state of the whole machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MachineState {
+    /// Every command is allowed.
+    #[default]
+    Operational,
+    /// The machine is being serviced, everything but payouts is allowed.
+    Maintenance,
+    /// Only diagnostics are allowed, nothing moves nor changes on the devices.
+    OutOfService,
+}
+
+impl MachineState {
+    /// Returns `true` if commands of `class` may be sent in this state.
+    #[must_use]
+    pub const fn allows(self, class: CommandClass) -> bool {
+        match self {
+            Self::Operational => true,
+            Self::Maintenance => !matches!(class, CommandClass::Payout),
+            Self::OutOfService => matches!(class, CommandClass::Safety | CommandClass::Diagnostic),
+        }
+    }
+}
+
+/// What a command does to the device, see [`CommandClass::of`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandClass {
+    /// Stops the device: emergency stops, turning acceptance off or disabling a hopper.
+    Safety,
+    /// Polls and requests which only read the device.
+    Diagnostic,
+    /// Enables acceptance or routes the accepted currency.
+    Acceptance,
+    /// Dispenses, purges or arms a hopper.
+    Payout,
+    /// Anything else: configuration, teach and calibration, actuator tests, firmware upgrades,
+    /// address changes and resets.
+    Configuration,
+}
+
+impl CommandClass {
+    /// Classifies a command from its header and data.
+    ///
+    /// Headers which are neither known to only read the device nor to accept or pay out are
+    /// [`Configuration`](Self::Configuration).
+    #[must_use]
+    pub fn of(header: Header, data: &[u8]) -> Self {
+        match header {
+            Header::EmergencyStop | Header::EmergencyStopValue => Self::Safety,
+            Header::ModifyMasterInhibitStatus => {
+                if data.first().is_some_and(|mask| mask & 1 == 0) {
+                    Self::Safety
+                } else {
+                    Self::Acceptance
+                }
+            }
+            Header::EnableHopper => {
+                if data.first() == Some(&165) {
+                    Self::Payout
+                } else {
+                    Self::Safety
+                }
+            }
+            Header::DispenseHopperCoins
+            | Header::DispenseHopperValue
+            | Header::PayMoneyOut
+            | Header::PurgeHopper => Self::Payout,
+            Header::ModifyInhibitStatus
+            | Header::ModifyInhibitAndOverrideRegisters
+            | Header::ModifyEncryptedInhibitAndOverrideRegisters
+            | Header::ModifyBillOperatingMode
+            | Header::RouteBill
+            | Header::OperateEscrow
+            | Header::SetAcceptLimit => Self::Acceptance,
+            Header::SimplePoll
+            | Header::AddressPoll
+            | Header::AddressClash
+            | Header::RequestPollingPriority
+            | Header::RequestStatus
+            | Header::RequestVariableSet
+            | Header::RequestManufacturerId
+            | Header::RequestEquipementCategoryId
+            | Header::RequestProductCode
+            | Header::RequestDatabaseVersion
+            | Header::RequestSerialNumber
+            | Header::RequestSoftwareRevision
+            | Header::ReadInputLines
+            | Header::ReadOptoStates
+            | Header::PerformSelfCheck
+            | Header::RequestInhibitStatus
+            | Header::ReadBufferedCreditOrErrorCodes
+            | Header::RequestMasterInhibitStatus
+            | Header::RequestInsertionCounter
+            | Header::RequestAcceptCounter
+            | Header::RequestEncryptedProductId
+            | Header::RequestSorterOverrideStatus
+            | Header::RequestPayoutStatus
+            | Header::RequestDataStorageAvailability
+            | Header::ReadDataBlock
+            | Header::RequestOptionFlags
+            | Header::RequestCoinPosition
+            | Header::RequestSorterPaths
+            | Header::RequestPayoutAbsoluteCount
+            | Header::RequestTeachStatus
+            | Header::ACMIUnencryptedProductId
+            | Header::CalculateROMChecksum
+            | Header::RequestCreationDate
+            | Header::RequestLastModificationDate
+            | Header::RequestRejectCounter
+            | Header::RequestFraudCounter
+            | Header::RequestBuildCode
+            | Header::RequestDefaultSorterPath
+            | Header::RequestPayoutCapacity
+            | Header::RequestCoinId
+            | Header::RequestSecuritySetting
+            | Header::RequestBankSelect
+            | Header::RequestAlarmCounter
+            | Header::RequestPayoutFloat
+            | Header::RequestThermistorReading
+            | Header::RequestHopperCoin
+            | Header::RequestBaseYear
+            | Header::RequestAddressMode
+            | Header::RequestHopperDispenseCount
+            | Header::RequestHopperStatus
+            | Header::TestHopper
+            | Header::ReadBufferedBillEvents
+            | Header::RequestBillId
+            | Header::RequestCountryScalingFactor
+            | Header::RequestBillPosition
+            | Header::RequestBillOperatingMode
+            | Header::RequestIndividualAcceptCounter
+            | Header::RequestIndividualErrorCounter
+            | Header::ReadOptoVoltages
+            | Header::RequestCurrencyRevision
+            | Header::RequestFirmwareUpgradeCapability
+            | Header::RequestHopperPollingValue
+            | Header::RequestHopperCoinValue
+            | Header::RequestIndexedHopperDispenseCount
+            | Header::ReadBarCodeData
+            | Header::RequestMoneyIn
+            | Header::RequestMoneyOut
+            | Header::VerifyMoneyOut
+            | Header::RequestActivityRegister
+            | Header::RequestErrorStatus
+            | Header::RequestHopperBalance
+            | Header::RequestCashBoxValue
+            | Header::RequestRealTimeClock
+            | Header::RequestUsbId
+            | Header::ReadEncryptedEvents
+            | Header::RequestEncryptionSupport
+            | Header::RequestEncryptedHopperStatus
+            | Header::RequestEncryptedMonetaryId
+            | Header::RequestEscrowStatus
+            | Header::RequestServiceStatus
+            | Header::RequestCommsRevision
+            | Header::RequestCommsStatusVariables => Self::Diagnostic,
+            _ => Self::Configuration,
+        }
+    }
+}
+
+/// Shared [`MachineState`], read by the transport before every message.
+///
+/// Clones share the same state, the application keeps one to switch the machine in and out of
+/// maintenance, see [`DeviceManager::with_machine_state`](crate::device::manager::DeviceManager::with_machine_state).
+#[derive(Debug, Clone, Default)]
+pub struct MachineStateHandle {
+    state: Arc<Mutex<MachineState>>,
+}
+
+impl MachineStateHandle {
+    /// Creates a handle in [`MachineState::Operational`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn state(&self) -> MachineState {
+        *self.state.lock().expect("should not be poisoned")
+    }
+
+    /// Switches the machine to `state`, returns the previous state.
+    ///
+    /// Only new messages are checked against the state, it does not stop a payout already on
+    /// the wire.
+    pub fn set_state(&self, state: MachineState) -> MachineState {
+        let previous = core::mem::replace(
+            &mut *self.state.lock().expect("should not be poisoned"),
+            state,
+        );
+        if previous != state {
+            info!(?previous, ?state, "machine state changed");
+        }
+        previous
+    }
+
+    /// Returns `true` if the command may be sent in the current state.
+    #[must_use]
+    pub fn allows(&self, header: Header, data: &[u8]) -> bool {
+        self.state().allows(CommandClass::of(header, data))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn payouts_are_only_allowed_when_operational() {
+        let handle = MachineStateHandle::new();
+        assert!(handle.allows(Header::DispenseHopperCoins, &[]));
+
+        assert_eq!(
+            handle.set_state(MachineState::Maintenance),
+            MachineState::Operational
+        );
+        assert!(!handle.allows(Header::DispenseHopperCoins, &[]));
+        assert!(!handle.allows(Header::EnableHopper, &[165]));
+        assert!(handle.allows(Header::EnableHopper, &[0]));
+        assert!(handle.allows(Header::ModifyMasterInhibitStatus, &[1]));
+        assert!(handle.allows(Header::TeachModeControl, &[3]));
+
+        handle.set_state(MachineState::OutOfService);
+        assert!(handle.allows(Header::RequestHopperStatus, &[]));
+        assert!(handle.allows(Header::ModifyMasterInhibitStatus, &[0]));
+        assert!(handle.allows(Header::EmergencyStop, &[]));
+        assert!(!handle.allows(Header::ModifyMasterInhibitStatus, &[1]));
+        assert!(!handle.allows(Header::TeachModeControl, &[3]));
+        assert!(!handle.allows(Header::ResetDevice, &[]));
+    }
+}
@@ -0,0 +1,81 @@
+use cc_talk_core::cc_talk::Header;
+use tokio::sync::mpsc;
+
+use super::tokio_transport::TransportMessage;
+
+/// Default capacity of the transport queue, see [`QueueConfig`].
+pub const DEFAULT_QUEUE_CAPACITY: usize = 32;
+
+/// What the transport does with a regular message once its queue is full.
+///
+/// Express messages, see [`TransportMessage::is_express`], have their own lane and are never
+/// subject to the policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackpressurePolicy {
+    /// Stops reading the channel until a slot frees up, senders wait in
+    /// [`mpsc::Sender::send`] once the channel is full too.
+    #[default]
+    Block,
+    /// Rejects the message with [`TransportError::QueueFull`](super::tokio_transport::TransportError::QueueFull).
+    FailFast,
+    /// Drops the oldest queued poll to make room, see [`is_droppable_poll`]. The message is
+    /// rejected like with [`BackpressurePolicy::FailFast`] when no poll is queued.
+    DropOldestPoll,
+}
+
+/// Size of the transport queue and behavior on saturation.
+///
+/// The transport moves the messages out of the channel into its own queue so express messages
+/// can overtake them, the capacity bounds that queue. Use [`QueueConfig::channel`] to size the
+/// channel the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueConfig {
+    pub capacity: usize,
+    pub policy: BackpressurePolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_QUEUE_CAPACITY,
+            policy: BackpressurePolicy::default(),
+        }
+    }
+}
+
+impl QueueConfig {
+    #[must_use]
+    pub const fn new(capacity: usize, policy: BackpressurePolicy) -> Self {
+        Self { capacity, policy }
+    }
+
+    /// Creates the channel feeding the transport, with the configured capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity is 0.
+    #[must_use]
+    pub fn channel(
+        &self,
+    ) -> (
+        mpsc::Sender<TransportMessage>,
+        mpsc::Receiver<TransportMessage>,
+    ) {
+        mpsc::channel(self.capacity)
+    }
+}
+
+/// Returns `true` for the polls [`BackpressurePolicy::DropOldestPoll`] may drop.
+///
+/// These reads do not change the state of the device, their data is returned again by the next
+/// poll, e.g. buffered credits stay in the buffer until a newer event counter is read.
+#[must_use]
+pub const fn is_droppable_poll(header: Header) -> bool {
+    matches!(
+        header,
+        Header::SimplePoll
+            | Header::ReadBufferedCreditOrErrorCodes
+            | Header::ReadBufferedBillEvents
+            | Header::RequestHopperStatus
+    )
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use cc_talk_core::cc_talk::Header;
+use cc_talk_host::command_table::max_response_length;
+use tracing::debug;
+
+use crate::device::quirks::DeviceQuirks;
+
+/// Longest replies the transport reads, longer replies are rejected with
+/// [`TransportError::ResponseTooLong`](super::tokio_transport::TransportError::ResponseTooLong)
+/// as soon as their length byte is read.
+///
+/// By default the payload is capped to the longest reply of the command in the
+/// [command table](cc_talk_host::command_table), headers missing from the table accept up to 255
+/// bytes. A device can be capped further, e.g. a peripheral known to send short replies only.
+///
+/// Devices with [`DeviceQuirks`] may send longer replies than the table allows, the quirks raise
+/// the table limit of their headers with [`Self::raise_for_quirks`]. The raised limits are shared
+/// by the clones of the caps, use [`CcTalkTokioTransport::response_size_caps`] to raise them
+/// while the transport runs.
+///
+/// [`CcTalkTokioTransport::response_size_caps`]: super::tokio_transport::CcTalkTokioTransport::response_size_caps
+#[derive(Debug, Clone)]
+pub struct ResponseSizeCaps {
+    command_table: bool,
+    device_caps: HashMap<u8, u8>,
+    quirk_limits: Arc<Mutex<HashMap<(u8, u8), u8>>>,
+}
+
+impl Default for ResponseSizeCaps {
+    fn default() -> Self {
+        Self {
+            command_table: true,
+            device_caps: HashMap::new(),
+            quirk_limits: Arc::default(),
+        }
+    }
+}
+
+impl ResponseSizeCaps {
+    /// Accepts any reply length, only the device caps apply.
+    #[must_use]
+    pub fn unlimited() -> Self {
+        Self {
+            command_table: false,
+            ..Self::default()
+        }
+    }
+
+    /// Caps the payload of every reply from the device at `address` to `max_length` bytes.
+    #[must_use]
+    pub fn with_device_cap(mut self, address: u8, max_length: u8) -> Self {
+        self.device_caps.insert(address, max_length);
+        self
+    }
+
+    /// Replaces the table limit of the headers the quirks of the device at `address` accept
+    /// longer replies to, see [`DeviceQuirks::max_response_length`].
+    ///
+    /// The device cap still applies.
+    pub fn raise_for_quirks(&self, address: u8, quirks: &dyn DeviceQuirks) {
+        let mut quirk_limits = self.quirk_limits.lock().expect("should not be poisoned");
+        quirk_limits.retain(|&(quirked_address, _), _| quirked_address != address);
+        for &header in Header::ALL {
+            if let Some(max_length) = quirks.max_response_length(header) {
+                debug!(
+                    address,
+                    header = header as u8,
+                    max_length,
+                    "response size cap raised by device quirks"
+                );
+                quirk_limits.insert((address, header as u8), max_length);
+            }
+        }
+    }
+
+    /// Returns the longest payload accepted from `address` in reply to `header`.
+    pub fn limit(&self, address: u8, header: Header) -> u8 {
+        let quirk_limit = self
+            .quirk_limits
+            .lock()
+            .expect("should not be poisoned")
+            .get(&(address, header as u8))
+            .copied();
+        let table_limit = match quirk_limit {
+            Some(quirk_limit) => quirk_limit,
+            None if self.command_table => max_response_length(header),
+            None => u8::MAX,
+        };
+        self.device_caps
+            .get(&address)
+            .map_or(table_limit, |&device_cap| table_limit.min(device_cap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::device::quirks::ResponseLength;
+
+    use super::*;
+
+    #[test]
+    fn table_and_device_caps_are_combined() {
+        let caps = ResponseSizeCaps::default().with_device_cap(3, 2);
+        assert_eq!(caps.limit(2, Header::RequestHopperStatus), 4);
+        assert_eq!(caps.limit(3, Header::RequestHopperStatus), 2);
+        assert_eq!(caps.limit(2, Header::SimplePoll), 0);
+        assert_eq!(caps.limit(2, Header::Reply), u8::MAX);
+
+        let caps = ResponseSizeCaps::unlimited().with_device_cap(3, 2);
+        assert_eq!(caps.limit(2, Header::SimplePoll), u8::MAX);
+        assert_eq!(caps.limit(3, Header::RequestManufacturerId), 2);
+    }
+
+    #[test]
+    fn quirks_raise_the_table_limit_of_their_headers() {
+        let caps = ResponseSizeCaps::default().with_device_cap(4, 6);
+        let quirks = ResponseLength::new(Header::RequestHopperStatus, 4);
+        caps.clone().raise_for_quirks(3, &quirks);
+        caps.raise_for_quirks(4, &quirks);
+
+        assert_eq!(caps.limit(3, Header::RequestHopperStatus), u8::MAX);
+        assert_eq!(caps.limit(3, Header::SimplePoll), 0);
+        assert_eq!(caps.limit(2, Header::RequestHopperStatus), 4);
+        assert_eq!(caps.limit(4, Header::RequestHopperStatus), 6);
+    }
+}
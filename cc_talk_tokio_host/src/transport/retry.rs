@@ -25,6 +25,32 @@ impl Default for RetryConfig {
     }
 }
 
+/// Line re-synchronization performed when a reply fails checksum validation.
+///
+/// A corrupted reply usually means the host and the device disagree on where a frame starts,
+/// retrying straight away tends to read the tail of the previous frame. Resync first drains the
+/// line until it stays quiet, optionally sends a `SimplePoll` to realign with the device, and only
+/// then lets the original command be retried.
+#[derive(Debug, Clone)]
+pub struct ResyncConfig {
+    /// How long the line has to stay silent before it is considered flushed.
+    pub quiet_period: Duration,
+    /// Upper bound on the time spent flushing a chatty line.
+    pub max_flush_duration: Duration,
+    /// Issue a `SimplePoll` to the device once the line is flushed.
+    pub simple_poll: bool,
+}
+
+impl Default for ResyncConfig {
+    fn default() -> Self {
+        ResyncConfig {
+            quiet_period: Duration::from_millis(50),
+            max_flush_duration: Duration::from_millis(500),
+            simple_poll: true,
+        }
+    }
+}
+
 impl RetryConfig {
     pub fn create_retry_instance(&self) -> RetryInstance {
         RetryInstance::new(
@@ -75,7 +101,9 @@ impl RetryInstance {
     pub fn should_retry(&self, error: TransportError) -> bool {
         match error {
             TransportError::Timeout => self.retry_on_timeout,
-            TransportError::ChecksumError => self.retry_on_checksum_error,
+            TransportError::ChecksumError | TransportError::EchoMismatch => {
+                self.retry_on_checksum_error
+            }
             TransportError::Nack => self.retry_on_nack,
             TransportError::SocketWriteError | TransportError::SocketReadError => {
                 self.retry_on_socket_error
@@ -0,0 +1,377 @@
+//! Order in which the transport sends the queued messages.
+//!
+//! Every address has its own FIFO queue, the transport picks the next message among the queues
+//! of the addresses it is allowed to talk to:
+//!
+//! - addresses still within their minimum gap, see [`SchedulerConfig::with_minimum_gap`] and
+//!   [`SchedulerConfig::with_latency_profiles`], are skipped until the gap elapsed;
+//! - a message waiting for longer than [`SchedulerConfig::with_max_wait`] is sent first, the
+//!   oldest one if there are several;
+//! - otherwise the addresses with the highest [`DevicePriority`] go first, e.g. the credit polls
+//!   of a coin acceptor overtake the audit reads of a hopper;
+//! - between addresses of the same priority, the one served least recently goes first, a caller
+//!   flooding one address does not hold back the others.
+//!
+//! Express messages, see [`TransportMessage::is_express`], bypass the scheduler and its gaps.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::tokio_transport::TransportMessage;
+#[cfg(feature = "metrics")]
+use crate::device::latency::LatencyProfiles;
+
+/// Scheduling priority of a device, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DevicePriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// Per-address priorities and gaps of the scheduler, every address is
+/// [`DevicePriority::Normal`] without gap by default.
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerConfig {
+    priorities: HashMap<u8, DevicePriority>,
+    minimum_gaps: HashMap<u8, Duration>,
+    #[cfg(feature = "metrics")]
+    latency_profiles: Option<LatencyProfiles>,
+    max_wait: Option<Duration>,
+}
+
+impl SchedulerConfig {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedules the messages for `address` with `priority`.
+    #[must_use]
+    pub fn with_priority(mut self, address: u8, priority: DevicePriority) -> Self {
+        self.priorities.insert(address, priority);
+        self
+    }
+
+    /// Waits at least `gap` between the end of an exchange with `address` and the next message
+    /// sent to it, on top of the minimum delay of the transport.
+    ///
+    /// Some devices need time to process a command, e.g. after writing to their EEPROM, the
+    /// other addresses are served in the meantime.
+    #[must_use]
+    pub fn with_minimum_gap(mut self, address: u8, gap: Duration) -> Self {
+        self.minimum_gaps.insert(address, gap);
+        self
+    }
+
+    /// Gives the addresses without a minimum gap the [slot](crate::device::latency::LatencyProfile::slot)
+    /// of their latest profile as gap.
+    ///
+    /// The profiles are read on every exchange, the profiles recorded while the transport runs
+    /// apply from the next exchange on.
+    #[cfg(feature = "metrics")]
+    #[must_use]
+    pub fn with_latency_profiles(mut self, latency_profiles: LatencyProfiles) -> Self {
+        self.latency_profiles = Some(latency_profiles);
+        self
+    }
+
+    /// Sends a message waiting for longer than `max_wait` ahead of the priorities, so low
+    /// priority devices are not starved by a busy high priority one.
+    #[must_use]
+    pub const fn with_max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = Some(max_wait);
+        self
+    }
+
+    pub fn priority(&self, address: u8) -> DevicePriority {
+        self.priorities.get(&address).copied().unwrap_or_default()
+    }
+
+    pub fn minimum_gap(&self, address: u8) -> Duration {
+        let gap = self.minimum_gaps.get(&address).copied();
+        #[cfg(feature = "metrics")]
+        let gap = gap.or_else(|| {
+            self.latency_profiles
+                .as_ref()?
+                .current(address)
+                .map(|profile| profile.slot())
+        });
+        gap.unwrap_or(Duration::ZERO)
+    }
+}
+
+struct Queued {
+    sequence: u64,
+    queued_at: Instant,
+    message: TransportMessage,
+}
+
+#[derive(Default)]
+struct AddressQueue {
+    messages: VecDeque<Queued>,
+    /// Turn at which the address was last served, 0 if never.
+    last_turn: u64,
+    /// End of the minimum gap after the last exchange.
+    ready_at: Option<Instant>,
+}
+
+impl AddressQueue {
+    fn is_ready(&self, now: Instant) -> bool {
+        !self.messages.is_empty() && self.ready_at.is_none_or(|ready_at| ready_at <= now)
+    }
+}
+
+/// Per-address queues of the transport, see the [module documentation](self).
+pub(crate) struct BusScheduler {
+    config: SchedulerConfig,
+    queues: BTreeMap<u8, AddressQueue>,
+    sequence: u64,
+    turns: u64,
+    length: usize,
+}
+
+impl BusScheduler {
+    pub(crate) fn new(config: SchedulerConfig) -> Self {
+        Self {
+            config,
+            queues: BTreeMap::new(),
+            sequence: 0,
+            turns: 0,
+            length: 0,
+        }
+    }
+
+    /// Returns the number of queued messages, for every address.
+    pub(crate) const fn len(&self) -> usize {
+        self.length
+    }
+
+    pub(crate) const fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub(crate) fn push(&mut self, message: TransportMessage, now: Instant) {
+        self.sequence += 1;
+        self.length += 1;
+        self.queues
+            .entry(message.address)
+            .or_default()
+            .messages
+            .push_back(Queued {
+                sequence: self.sequence,
+                queued_at: now,
+                message,
+            });
+    }
+
+    /// Removes the oldest message matching `predicate`, across every address.
+    pub(crate) fn remove_oldest(
+        &mut self,
+        predicate: impl Fn(&TransportMessage) -> bool,
+    ) -> Option<TransportMessage> {
+        let (address, position, _) = self
+            .queues
+            .iter()
+            .flat_map(|(address, queue)| {
+                queue
+                    .messages
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, queued)| predicate(&queued.message))
+                    .map(|(position, queued)| (*address, position, queued.sequence))
+            })
+            .min_by_key(|(_, _, sequence)| *sequence)?;
+        let queued = self.queues.get_mut(&address)?.messages.remove(position)?;
+        self.length -= 1;
+        Some(queued.message)
+    }
+
+    /// Returns the next message to send at `now`, `None` if no address is ready.
+    pub(crate) fn pop(&mut self, now: Instant) -> Option<TransportMessage> {
+        let ready = || {
+            self.queues
+                .iter()
+                .filter(|(_, queue)| queue.is_ready(now))
+                .filter_map(|(address, queue)| Some((*address, queue, queue.messages.front()?)))
+        };
+        let starving = self.config.max_wait.and_then(|max_wait| {
+            ready()
+                .filter(|(_, _, head)| now.saturating_duration_since(head.queued_at) >= max_wait)
+                .min_by_key(|(_, _, head)| head.sequence)
+        });
+        let (address, _, _) = starving.or_else(|| {
+            ready().min_by_key(|(address, queue, head)| {
+                (
+                    core::cmp::Reverse(self.config.priority(*address)),
+                    queue.last_turn,
+                    head.sequence,
+                )
+            })
+        })?;
+
+        self.turns += 1;
+        let queue = self.queues.get_mut(&address)?;
+        queue.last_turn = self.turns;
+        let queued = queue.messages.pop_front()?;
+        self.length -= 1;
+        Some(queued.message)
+    }
+
+    /// Returns when the next queued message can be sent, `None` if no message is queued.
+    pub(crate) fn ready_at(&self, now: Instant) -> Option<Instant> {
+        self.queues
+            .values()
+            .filter(|queue| !queue.messages.is_empty())
+            .map(|queue| queue.ready_at.map_or(now, |ready_at| ready_at.max(now)))
+            .min()
+    }
+
+    /// Starts the minimum gap of `address`, its exchange ended at `now`.
+    pub(crate) fn exchange_done(&mut self, address: u8, now: Instant) {
+        let gap = self.config.minimum_gap(address);
+        if gap.is_zero() {
+            return;
+        }
+        self.queues.entry(address).or_default().ready_at = Some(now + gap);
+    }
+
+    /// Returns the queued messages in arrival order.
+    #[cfg(test)]
+    pub(crate) fn messages(&self) -> Vec<&TransportMessage> {
+        let mut queued: Vec<_> = self
+            .queues
+            .values()
+            .flat_map(|queue| queue.messages.iter())
+            .collect();
+        queued.sort_by_key(|queued| queued.sequence);
+        queued.into_iter().map(|queued| &queued.message).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{ChecksumType, Header};
+    use tokio::sync::oneshot;
+
+    use crate::transport::{correlation::CorrelationId, timestamp::ReceptionSlot};
+
+    use super::*;
+
+    fn message(address: u8, header: Header) -> TransportMessage {
+        let (respond_to, _) = oneshot::channel();
+        TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address,
+            checksum_type: ChecksumType::Crc8,
+            header,
+            data: vec![],
+            respond_to,
+            received_at: ReceptionSlot::default(),
+        }
+    }
+
+    fn drain(scheduler: &mut BusScheduler, now: Instant) -> Vec<u8> {
+        std::iter::from_fn(|| scheduler.pop(now))
+            .map(|message| message.address)
+            .collect()
+    }
+
+    #[test]
+    fn addresses_take_turns_by_priority() {
+        let now = Instant::now();
+        let mut scheduler = BusScheduler::new(
+            SchedulerConfig::new()
+                .with_priority(2, DevicePriority::High)
+                .with_priority(5, DevicePriority::Low),
+        );
+        for address in [3, 3, 3, 5, 4, 2, 2] {
+            scheduler.push(message(address, Header::SimplePoll), now);
+        }
+        assert_eq!(scheduler.len(), 7);
+        assert_eq!(drain(&mut scheduler, now), [2, 2, 3, 4, 3, 3, 5]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn starving_messages_go_first() {
+        let now = Instant::now();
+        let mut scheduler = BusScheduler::new(
+            SchedulerConfig::new()
+                .with_priority(2, DevicePriority::High)
+                .with_max_wait(Duration::from_millis(100)),
+        );
+        scheduler.push(message(3, Header::RequestHopperCoin), now);
+        scheduler.push(message(2, Header::ReadBufferedCreditOrErrorCodes), now);
+        let later = now + Duration::from_millis(50);
+        scheduler.push(message(2, Header::ReadBufferedCreditOrErrorCodes), later);
+        assert_eq!(drain(&mut scheduler, later), [2, 2, 3]);
+
+        scheduler.push(message(3, Header::RequestHopperCoin), now);
+        scheduler.push(message(2, Header::ReadBufferedCreditOrErrorCodes), later);
+        assert_eq!(
+            drain(&mut scheduler, now + Duration::from_millis(100)),
+            [3, 2]
+        );
+    }
+
+    #[test]
+    fn minimum_gap_holds_the_address_back() {
+        let now = Instant::now();
+        let gap = Duration::from_millis(30);
+        let mut scheduler = BusScheduler::new(SchedulerConfig::new().with_minimum_gap(3, gap));
+        for address in [3, 3, 4] {
+            scheduler.push(message(address, Header::SimplePoll), now);
+        }
+        assert_eq!(scheduler.ready_at(now), Some(now));
+        assert_eq!(scheduler.pop(now).map(|message| message.address), Some(3));
+        scheduler.exchange_done(3, now);
+        scheduler.exchange_done(4, now);
+
+        assert_eq!(drain(&mut scheduler, now), [4]);
+        assert_eq!(scheduler.ready_at(now), Some(now + gap));
+        assert_eq!(drain(&mut scheduler, now + gap), [3]);
+        assert_eq!(scheduler.ready_at(now), None);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn latency_profiles_size_the_minimum_gaps() {
+        use crate::device::latency::LatencyProfile;
+
+        let profiles = LatencyProfiles::new();
+        let config = SchedulerConfig::new()
+            .with_minimum_gap(4, Duration::from_millis(5))
+            .with_latency_profiles(profiles.clone());
+        assert_eq!(config.minimum_gap(3), Duration::ZERO);
+
+        let profile = LatencyProfile::from_samples(vec![Duration::from_millis(12); 4])
+            .expect("should have samples");
+        profiles.record(3, profile);
+        profiles.record(4, profile);
+        assert_eq!(config.minimum_gap(3), profile.slot());
+        assert_eq!(config.minimum_gap(4), Duration::from_millis(5));
+    }
+
+    #[test]
+    fn oldest_matching_message_is_removed() {
+        let now = Instant::now();
+        let mut scheduler = BusScheduler::new(SchedulerConfig::new());
+        scheduler.push(message(4, Header::RequestSerialNumber), now);
+        scheduler.push(message(3, Header::SimplePoll), now);
+        scheduler.push(message(2, Header::SimplePoll), now);
+        let removed = scheduler.remove_oldest(|message| message.header == Header::SimplePoll);
+        assert_eq!(removed.map(|message| message.address), Some(3));
+        let headers: Vec<_> = scheduler
+            .messages()
+            .iter()
+            .map(|message| message.address)
+            .collect();
+        assert_eq!(headers, [4, 2]);
+    }
+}
@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+pub use tokio_serial::{DataBits, Parity, StopBits};
+use tokio_serial::{SerialPort, SerialStream};
+
+use super::tokio_transport::CcTalkTokioTransport;
+
+/// Baud rate of the ccTalk serial interface.
+pub const DEFAULT_BAUD_RATE: u32 = 9600;
+
+/// Maximum gap between two bytes of a frame allowed by the ccTalk specification.
+pub const DEFAULT_INTER_BYTE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// A [`CcTalkTokioTransport`] talking to the bus through a serial port, e.g. an RS-232
+/// interface or a USB CDC adapter, see [`CcTalkTokioTransport::serial`].
+pub type CcTalkSerialTransport = CcTalkTokioTransport;
+
+/// Serial port settings of a [`CcTalkSerialTransport`].
+///
+/// The defaults match the ccTalk serial interface, 9600 baud, 8 data bits, no parity and 1
+/// stop bit, with the echo of the shared two-wire bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialConfig {
+    /// Path of the serial port, e.g. `/dev/ttyUSB0`.
+    pub path: String,
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    /// Maximum gap between two bytes of a frame, a longer gap aborts the frame.
+    pub inter_byte_timeout: Duration,
+    /// Every byte sent on a two-wire bus is received back, the echo is read and checked
+    /// against the sent frame before waiting for the reply. Disable it for interfaces
+    /// filtering the echo, e.g. RS-232 interfaces with separate receive and transmit lines.
+    pub echo: bool,
+    /// Baud rate used by the [`BaudFallback`](super::watchdog::RecoveryStep::BaudFallback)
+    /// recovery step.
+    pub fallback_baud_rate: u32,
+}
+
+impl SerialConfig {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            baud_rate: DEFAULT_BAUD_RATE,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            inter_byte_timeout: DEFAULT_INTER_BYTE_TIMEOUT,
+            echo: true,
+            fallback_baud_rate: DEFAULT_BAUD_RATE,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_inter_byte_timeout(mut self, inter_byte_timeout: Duration) -> Self {
+        self.inter_byte_timeout = inter_byte_timeout;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Opens the serial port with the configured settings.
+    pub(crate) fn open(&self) -> tokio_serial::Result<SerialStream> {
+        let builder = tokio_serial::new(&self.path, self.baud_rate)
+            .data_bits(self.data_bits)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .timeout(self.inter_byte_timeout);
+        let stream = SerialStream::open(&builder)?;
+        // Bytes received before the port was opened belong to no exchange.
+        stream.clear(tokio_serial::ClearBuffer::All)?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{ChecksumType, Header};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        sync::{mpsc, oneshot},
+        time::Instant,
+    };
+
+    use crate::transport::{
+        correlation::CorrelationId,
+        retry::RetryConfig,
+        timestamp::ReceptionSlot,
+        tokio_transport::{TransportError, TransportMessage},
+    };
+
+    use super::*;
+
+    fn simple_poll() -> (
+        TransportMessage,
+        oneshot::Receiver<Result<Vec<u8>, TransportError>>,
+    ) {
+        let (respond_to, response) = oneshot::channel();
+        let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address: 2,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::SimplePoll,
+            data: vec![],
+            respond_to,
+            received_at: ReceptionSlot::default(),
+        };
+        (message, response)
+    }
+
+    #[tokio::test]
+    async fn echo_is_checked_and_frames_are_timed_byte_by_byte() {
+        let (mut line, port) = SerialStream::pair().expect("should create a pty pair");
+        let path = port.name().expect("pty should have a name");
+
+        tokio::spawn(async move {
+            let mut frame = [0u8; 5];
+            // Echo, then the reply.
+            line.read_exact(&mut frame).await.expect("should read poll");
+            line.write_all(&frame).await.expect("should echo");
+            line.write_all(&[1, 0, 2, 0, 253])
+                .await
+                .expect("should reply");
+            // Another device talks during the second poll.
+            line.read_exact(&mut frame).await.expect("should read poll");
+            frame[3] ^= 0xFF;
+            line.write_all(&frame).await.expect("should echo");
+            // The third reply stalls after its first bytes.
+            line.read_exact(&mut frame).await.expect("should read poll");
+            line.write_all(&frame).await.expect("should echo");
+            line.write_all(&[1, 0, 2]).await.expect("should reply");
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        });
+
+        let (tx, rx) = mpsc::channel(1);
+        let retry_config = RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        };
+        let transport = CcTalkSerialTransport::serial(
+            rx,
+            SerialConfig::new(path).with_inter_byte_timeout(Duration::from_millis(20)),
+            Duration::from_secs(1),
+            Duration::ZERO,
+            retry_config,
+        )
+        .with_resync(None);
+        tokio::spawn(transport.run());
+
+        let (message, response) = simple_poll();
+        tx.send(message).await.expect("transport should run");
+        assert_eq!(
+            response.await.expect("should respond"),
+            Ok(vec![1, 0, 2, 0, 253])
+        );
+
+        let (message, response) = simple_poll();
+        tx.send(message).await.expect("transport should run");
+        assert_eq!(
+            response.await.expect("should respond"),
+            Err(TransportError::EchoMismatch)
+        );
+
+        let (message, response) = simple_poll();
+        let started = Instant::now();
+        tx.send(message).await.expect("transport should run");
+        assert_eq!(
+            response.await.expect("should respond"),
+            Err(TransportError::Timeout)
+        );
+        assert!(
+            started.elapsed() < Duration::from_millis(500),
+            "the gap should abort the frame before the reply timeout"
+        );
+        drop(port);
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::correlation::CorrelationId;
+
+/// Transport counters, as returned by [`TransportStats::snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TransportStatsSnapshot {
+    /// When counting started, i.e. when the transport was created or the stats were reset.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub since: Instant,
+    /// Frames written on the bus, including retries and resync polls.
+    pub frames_tx: u64,
+    /// Frames read from the bus, including corrupted ones.
+    pub frames_rx: u64,
+    /// Attempts made after a failed attempt for the same message.
+    pub retries: u64,
+    pub timeouts: u64,
+    pub checksum_failures: u64,
+    pub nacks: u64,
+    /// Replies with the `Busy` header.
+    pub busy_replies: u64,
+    /// Frames read while no request was outstanding or not addressed to the host, see
+    /// [`UnsolicitedFramePolicy`](super::unsolicited::UnsolicitedFramePolicy).
+    pub unsolicited_frames: u64,
+    /// Exchanges which received a valid reply.
+    pub exchanges: u64,
+    /// Sum of the time between writing a frame and reading its valid reply.
+    pub total_latency: Duration,
+    /// Message of the last exchange which received a valid reply.
+    pub last_correlation_id: Option<CorrelationId>,
+    /// Regular messages waiting to be sent, in the transport queue and in its channel.
+    pub queue_depth: usize,
+    /// Highest [`Self::queue_depth`] seen.
+    pub max_queue_depth: usize,
+    /// Messages rejected because the queue was full, see
+    /// [`BackpressurePolicy`](super::queue::BackpressurePolicy).
+    pub rejected_messages: u64,
+    /// Polls dropped to make room in a full queue.
+    pub dropped_polls: u64,
+    /// Replies written in the buffer of their request.
+    pub reused_buffers: u64,
+    /// Replies for which the buffer of the request had to grow, see
+    /// [`TransportMessage::request_buffer`](super::tokio_transport::TransportMessage::request_buffer).
+    pub buffer_allocations: u64,
+}
+
+impl TransportStatsSnapshot {
+    fn new(since: Instant) -> Self {
+        Self {
+            since,
+            frames_tx: 0,
+            frames_rx: 0,
+            retries: 0,
+            timeouts: 0,
+            checksum_failures: 0,
+            nacks: 0,
+            busy_replies: 0,
+            unsolicited_frames: 0,
+            exchanges: 0,
+            total_latency: Duration::ZERO,
+            last_correlation_id: None,
+            queue_depth: 0,
+            max_queue_depth: 0,
+            rejected_messages: 0,
+            dropped_polls: 0,
+            reused_buffers: 0,
+            buffer_allocations: 0,
+        }
+    }
+
+    /// Returns the average time between writing a frame and reading its valid reply, `None`
+    /// before the first successful exchange.
+    #[must_use]
+    pub fn average_latency(&self) -> Option<Duration> {
+        let exchanges = u32::try_from(self.exchanges).unwrap_or(u32::MAX);
+        (exchanges > 0).then(|| self.total_latency / exchanges)
+    }
+}
+
+/// Shared transport statistics.
+///
+/// The transport owns the writing side, clones of this handle can be kept by the application,
+/// e.g. to export metrics, while the transport is running.
+#[derive(Debug, Clone)]
+pub struct TransportStats {
+    counters: Arc<Mutex<TransportStatsSnapshot>>,
+}
+
+impl Default for TransportStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransportStats {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            counters: Arc::new(Mutex::new(TransportStatsSnapshot::new(Instant::now()))),
+        }
+    }
+
+    /// Returns a copy of the counters since the transport was created or the last reset.
+    #[must_use]
+    pub fn snapshot(&self) -> TransportStatsSnapshot {
+        *self.counters.lock().expect("should not be poisoned")
+    }
+
+    /// Zeroes every counter, returning their values before the reset.
+    ///
+    /// The current queue depth is kept as it is not a counter.
+    pub fn reset(&self) -> TransportStatsSnapshot {
+        let mut counters = self.counters.lock().expect("should not be poisoned");
+        let mut reset = TransportStatsSnapshot::new(Instant::now());
+        reset.queue_depth = counters.queue_depth;
+        reset.max_queue_depth = counters.queue_depth;
+        std::mem::replace(&mut *counters, reset)
+    }
+
+    fn update(&self, update: impl FnOnce(&mut TransportStatsSnapshot)) {
+        update(&mut self.counters.lock().expect("should not be poisoned"));
+    }
+
+    pub(crate) fn record_tx(&self) {
+        self.update(|counters| counters.frames_tx += 1);
+    }
+
+    pub(crate) fn record_rx(&self) {
+        self.update(|counters| counters.frames_rx += 1);
+    }
+
+    pub(crate) fn record_retry(&self) {
+        self.update(|counters| counters.retries += 1);
+    }
+
+    pub(crate) fn record_timeout(&self) {
+        self.update(|counters| counters.timeouts += 1);
+    }
+
+    pub(crate) fn record_checksum_failure(&self) {
+        self.update(|counters| counters.checksum_failures += 1);
+    }
+
+    pub(crate) fn record_nack(&self) {
+        self.update(|counters| counters.nacks += 1);
+    }
+
+    pub(crate) fn record_busy(&self) {
+        self.update(|counters| counters.busy_replies += 1);
+    }
+
+    pub(crate) fn record_unsolicited(&self) {
+        self.update(|counters| counters.unsolicited_frames += 1);
+    }
+
+    pub(crate) fn record_queue_depth(&self, depth: usize) {
+        self.update(|counters| {
+            counters.queue_depth = depth;
+            counters.max_queue_depth = counters.max_queue_depth.max(depth);
+        });
+    }
+
+    pub(crate) fn record_rejected(&self) {
+        self.update(|counters| counters.rejected_messages += 1);
+    }
+
+    pub(crate) fn record_dropped_poll(&self) {
+        self.update(|counters| counters.dropped_polls += 1);
+    }
+
+    pub(crate) fn record_reply_buffer(&self, reused: bool) {
+        self.update(|counters| {
+            if reused {
+                counters.reused_buffers += 1;
+            } else {
+                counters.buffer_allocations += 1;
+            }
+        });
+    }
+
+    pub(crate) fn record_exchange(&self, latency: Duration, correlation_id: CorrelationId) {
+        self.update(|counters| {
+            counters.exchanges += 1;
+            counters.total_latency += latency;
+            counters.last_correlation_id = Some(correlation_id);
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counters_are_shared_and_reset() {
+        let stats = TransportStats::new();
+        let clone = stats.clone();
+
+        stats.record_tx();
+        stats.record_tx();
+        stats.record_rx();
+        stats.record_retry();
+        stats.record_timeout();
+        let last = CorrelationId::next();
+        stats.record_exchange(Duration::from_millis(10), CorrelationId::next());
+        stats.record_exchange(Duration::from_millis(30), last);
+
+        let snapshot = clone.snapshot();
+        assert_eq!(snapshot.frames_tx, 2);
+        assert_eq!(snapshot.frames_rx, 1);
+        assert_eq!(snapshot.retries, 1);
+        assert_eq!(snapshot.timeouts, 1);
+        assert_eq!(snapshot.average_latency(), Some(Duration::from_millis(20)));
+        assert_eq!(snapshot.last_correlation_id, Some(last));
+
+        let before_reset = clone.reset();
+        assert_eq!(before_reset, snapshot);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.frames_tx, 0);
+        assert_eq!(snapshot.average_latency(), None);
+        assert!(snapshot.since >= before_reset.since);
+    }
+}
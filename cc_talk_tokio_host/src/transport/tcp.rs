@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use super::tokio_transport::CcTalkTokioTransport;
+
+/// A [`CcTalkTokioTransport`] talking to the bus through a ccTalk-over-IP bridge, e.g. a
+/// network serial server exposing the bus of a remote kiosk, see [`CcTalkTokioTransport::tcp`].
+pub type CcTalkTcpTransport = CcTalkTokioTransport;
+
+/// How a lost connection is re-established.
+///
+/// The delay between two attempts starts at `initial_delay` and doubles after each failed
+/// attempt, up to `max_delay`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconnectConfig {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Attempts made before giving up, `None` to keep trying.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// Returns the delay before the attempt following `failed_attempts` failed attempts, `None`
+    /// once every attempt was made.
+    #[must_use]
+    pub fn delay(&self, failed_attempts: u32) -> Option<Duration> {
+        if self
+            .max_attempts
+            .is_some_and(|max_attempts| failed_attempts >= max_attempts)
+        {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(failed_attempts.saturating_sub(1));
+        Some(if failed_attempts == 0 {
+            Duration::ZERO
+        } else {
+            self.initial_delay
+                .saturating_mul(factor)
+                .min(self.max_delay)
+        })
+    }
+}
+
+/// Settings of a [`CcTalkTcpTransport`].
+///
+/// The connection is re-established following the [`ReconnectConfig`] when it drops, the
+/// message being sent is then retried like after any other socket error, see
+/// [`RetryConfig::retry_on_socket_error`](super::retry::RetryConfig::retry_on_socket_error).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpConfig {
+    /// Address of the bridge, e.g. `192.168.1.20:4001`.
+    pub address: String,
+    /// Time allowed to establish a connection.
+    pub connect_timeout: Duration,
+    pub reconnect: ReconnectConfig,
+    /// Whether the bridge forwards the echo of the two-wire bus, see
+    /// [`SerialConfig::echo`](super::serial::SerialConfig::echo).
+    pub echo: bool,
+}
+
+impl TcpConfig {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            connect_timeout: Duration::from_secs(5),
+            reconnect: ReconnectConfig::default(),
+            echo: true,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    #[must_use]
+    pub fn with_reconnect(mut self, reconnect: ReconnectConfig) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cc_talk_core::cc_talk::{ChecksumType, Header};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::{mpsc, oneshot},
+    };
+
+    use crate::transport::{
+        correlation::CorrelationId,
+        retry::RetryConfig,
+        timestamp::ReceptionSlot,
+        tokio_transport::{TransportError, TransportMessage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn reconnect_delay_backs_off_up_to_the_maximum() {
+        let reconnect = ReconnectConfig {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(350),
+            max_attempts: Some(4),
+        };
+        assert_eq!(reconnect.delay(0), Some(Duration::ZERO));
+        assert_eq!(reconnect.delay(1), Some(Duration::from_millis(100)));
+        assert_eq!(reconnect.delay(2), Some(Duration::from_millis(200)));
+        assert_eq!(reconnect.delay(3), Some(Duration::from_millis(350)));
+        assert_eq!(reconnect.delay(4), None);
+    }
+
+    fn simple_poll() -> (
+        TransportMessage,
+        oneshot::Receiver<Result<Vec<u8>, TransportError>>,
+    ) {
+        let (respond_to, response) = oneshot::channel();
+        let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address: 2,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::SimplePoll,
+            data: vec![],
+            respond_to,
+            received_at: ReceptionSlot::default(),
+        };
+        (message, response)
+    }
+
+    #[tokio::test]
+    async fn dropped_connection_is_reestablished() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("should bind");
+        let address = listener.local_addr().expect("should have an address");
+
+        tokio::spawn(async move {
+            // The bridge drops the first connection after the first reply.
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.expect("should accept");
+                let mut frame = [0u8; 5];
+                stream
+                    .read_exact(&mut frame)
+                    .await
+                    .expect("should read poll");
+                stream
+                    .write_all(&[1, 0, 2, 0, 253])
+                    .await
+                    .expect("should reply");
+            }
+        });
+
+        let (tx, rx) = mpsc::channel(1);
+        let retry_config = RetryConfig {
+            retry_delay: Duration::ZERO,
+            ..RetryConfig::default()
+        };
+        let transport = CcTalkTcpTransport::tcp(
+            rx,
+            TcpConfig::new(address.to_string())
+                .with_echo(false)
+                .with_reconnect(ReconnectConfig {
+                    initial_delay: Duration::from_millis(10),
+                    ..ReconnectConfig::default()
+                }),
+            Duration::from_millis(200),
+            Duration::ZERO,
+            retry_config,
+        );
+        tokio::spawn(transport.run());
+
+        for _ in 0..2 {
+            let (message, response) = simple_poll();
+            tx.send(message).await.expect("transport should run");
+            assert_eq!(
+                response.await.expect("should respond"),
+                Ok(vec![1, 0, 2, 0, 253])
+            );
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Reception time of the replies, captured by the transport as soon as a frame is read.
+//!
+//! Replies can wait in the device layer, e.g. behind a busy retry or a slow consumer, the
+//! reception time is taken when the transport reads the frame so events line up with the
+//! other systems of the site, e.g. CCTV or ERP audit logs.
+
+use std::{
+    sync::{Arc, OnceLock},
+    time::{Instant, SystemTime},
+};
+
+/// Monotonic and wall clock time of the same moment.
+///
+/// The monotonic instant orders events and measures durations, the wall clock time lines the
+/// events up with external systems and may jump when the host clock is adjusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub monotonic: Instant,
+    pub wall_clock: SystemTime,
+}
+
+impl Timestamp {
+    #[must_use]
+    pub fn now() -> Self {
+        Self {
+            monotonic: Instant::now(),
+            wall_clock: SystemTime::now(),
+        }
+    }
+}
+
+/// A value with the reception time of the reply it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamped<T> {
+    pub value: T,
+    pub received_at: Timestamp,
+}
+
+impl<T> Stamped<T> {
+    #[must_use]
+    pub const fn new(value: T, received_at: Timestamp) -> Self {
+        Self { value, received_at }
+    }
+
+    /// Converts the value, keeping the reception time.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Stamped<U> {
+        Stamped {
+            value: f(self.value),
+            received_at: self.received_at,
+        }
+    }
+}
+
+/// Slot of a [`TransportMessage`](super::tokio_transport::TransportMessage) the transport
+/// writes the reception time of the reply in.
+///
+/// Clones share the slot, the sender keeps one to read the time once the reply is received.
+/// Transports which do not stamp the replies leave it empty.
+#[derive(Debug, Clone, Default)]
+pub struct ReceptionSlot {
+    received_at: Arc<OnceLock<Timestamp>>,
+}
+
+impl ReceptionSlot {
+    /// Records the reception time, only the first one is kept.
+    pub fn stamp(&self, received_at: Timestamp) {
+        let _ = self.received_at.set(received_at);
+    }
+
+    #[must_use]
+    pub fn get(&self) -> Option<Timestamp> {
+        self.received_at.get().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_the_first_reception_is_kept() {
+        let slot = ReceptionSlot::default();
+        let sender = slot.clone();
+        assert_eq!(sender.get(), None);
+
+        let first = Timestamp::now();
+        slot.stamp(first);
+        slot.stamp(Timestamp::now());
+        assert_eq!(sender.get(), Some(first));
+        assert_eq!(
+            Stamped::new(2, first).map(|value| value * 2),
+            Stamped::new(4, first)
+        );
+    }
+}
@@ -1,21 +1,43 @@
 #![allow(dead_code)]
 
 use cc_talk_core::cc_talk::{
-    Category, ChecksumType, DATA_LENGTH_OFFSET, Device, Header, MAX_BLOCK_LENGTH, Packet,
-    deserializer::deserialize, serializer::serialize,
+    BnvKey, BusAddress, ChecksumType, DATA_LENGTH_OFFSET, Device, EchoCanceller, FrameCipher,
+    Header, MAX_BLOCK_LENGTH, Packet, PacketBuilder, PacketError,
 };
 use cc_talk_host::command::Command;
-use std::time::Duration;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
 use thiserror::Error;
 use tokio::{
     io::{self, AsyncReadExt, AsyncWriteExt},
-    net::UnixStream,
     sync::{mpsc, oneshot},
-    time::timeout,
+    time::{Instant, timeout},
+};
+use tracing::{debug, error, info, instrument, trace, warn};
+
+#[cfg(feature = "serial")]
+use super::serial::SerialConfig;
+#[cfg(feature = "tcp")]
+use super::tcp::{ReconnectConfig, TcpConfig};
+use super::{
+    bus_stream::{BusStream, Endpoint},
+    correlation::CorrelationId,
+    encryption::{EncryptionKeys, FrameEncryption, SharedFrameCipher},
+    frame_log::{FrameDirection, FrameLog},
+    health::CommsHealth,
+    machine_state::{MachineState, MachineStateHandle},
+    queue::{BackpressurePolicy, QueueConfig, is_droppable_poll},
+    response_caps::ResponseSizeCaps,
+    retry::{ResyncConfig, RetryConfig},
+    scheduler::{BusScheduler, SchedulerConfig},
+    stats::TransportStats,
+    timestamp::{ReceptionSlot, Timestamp},
+    unsolicited::{UnsolicitedFramePolicy, split_frames},
+    watchdog::{BusRecovery, RecoveryStep, WatchdogConfig},
 };
-use tracing::{error, info, trace};
-
-use super::retry::RetryConfig;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
 pub enum TransportError {
@@ -35,13 +57,52 @@ pub enum TransportError {
     ChecksumError,
     #[error("Max retries exceeded")]
     MaxRetriesExceeded,
+    /// The transport queue was full, see [`BackpressurePolicy`].
+    #[error("Queue full")]
+    QueueFull,
+    /// The echo of a frame differs from the frame, another device talked at the same time.
+    #[error("Echo mismatch")]
+    EchoMismatch,
+    /// The reply is longer than the command allows, see [`ResponseSizeCaps`].
+    #[error("Response too long")]
+    ResponseTooLong,
+    /// The transport task stopped, see [`TransportHandle`](super::handle::TransportHandle).
+    #[error("Transport closed")]
+    Closed,
+    /// The command is not allowed in the current [`MachineState`], it was not sent.
+    #[error("Not allowed in {0:?} state")]
+    NotAllowed(MachineState),
 }
 
+/// Capacity of the express lane channel, see [`CcTalkTokioTransport::express_sender`].
+pub const EXPRESS_LANE_CAPACITY: usize = 8;
+
+/// Devices answer an `AddressPoll` or `AddressClash` after a delay of 4ms per unit of their
+/// address.
+const ADDRESS_SLOT: Duration = Duration::from_millis(4);
+
 pub struct CcTalkTokioTransport {
     receiver: mpsc::Receiver<TransportMessage>,
-    socket_path: String,
+    scheduler: BusScheduler,
+    express_sender: mpsc::Sender<TransportMessage>,
+    express_receiver: mpsc::Receiver<TransportMessage>,
+    express_queue: VecDeque<TransportMessage>,
+    queue_config: QueueConfig,
+    endpoint: Endpoint,
     timeout: Duration,
     retry_config: RetryConfig,
+    resync_config: Option<ResyncConfig>,
+    health: CommsHealth,
+    stats: TransportStats,
+    frame_log: Option<FrameLog>,
+    unsolicited_policy: UnsolicitedFramePolicy,
+    watchdog: Option<WatchdogConfig>,
+    encryption_keys: EncryptionKeys,
+    frame_cipher: Option<SharedFrameCipher>,
+    checksum_types: HashMap<u8, ChecksumType>,
+    response_caps: ResponseSizeCaps,
+    machine_state: Option<MachineStateHandle>,
+    last_exchange: Instant,
     minimum_delay: Duration,
     echo: bool,
     send_buffer: Vec<u8>,
@@ -49,11 +110,19 @@ pub struct CcTalkTokioTransport {
 }
 
 pub struct TransportMessage {
+    /// Assigned by [`TransportMessage::new`], see [`CorrelationId`].
+    pub correlation_id: CorrelationId,
     pub address: u8,
     pub checksum_type: ChecksumType,
     pub header: Header,
+    /// Data of the command, the reply is written back in this buffer, see
+    /// [`TransportMessage::request_buffer`].
     pub data: Vec<u8>,
+    /// Receives the reply frame, or the address bytes of the devices which answered an
+    /// `AddressPoll` or `AddressClash`.
     pub respond_to: oneshot::Sender<Result<Vec<u8>, TransportError>>,
+    /// Stamped with the time the reply frame was read, see [`ReceptionSlot`].
+    pub received_at: ReceptionSlot,
 }
 
 impl TransportMessage {
@@ -66,30 +135,68 @@ impl TransportMessage {
         T: Command,
     {
         TransportMessage {
+            correlation_id: CorrelationId::next(),
             address: device.address(),
             checksum_type: *device.checksum_type(),
             header: command.header(),
-            data: command.data().to_vec(),
+            data: Self::request_buffer(command.data()),
             respond_to,
+            received_at: ReceptionSlot::default(),
+        }
+    }
+
+    /// Copies the data of a command in a buffer large enough for any frame.
+    ///
+    /// The transport writes the reply in the buffer of the request, the buffers returned by
+    /// this function are never reallocated on the way back.
+    #[must_use]
+    pub fn request_buffer(data: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(MAX_BLOCK_LENGTH);
+        buffer.extend_from_slice(data);
+        buffer
+    }
+
+    /// Returns `true` for commands which are sent through the express lane.
+    ///
+    /// Express commands are `EmergencyStop`, `EmergencyStopValue` and `ModifyMasterInhibitStatus`
+    /// when it turns acceptance off. They are scheduled ahead of any queued message and are
+    /// allowed to jump in between the retries of the message currently on the wire.
+    ///
+    /// Express commands sent on the regular channel still wait for a free slot in it, use
+    /// [`CcTalkTokioTransport::express_sender`] to bypass a full channel.
+    pub fn is_express(&self) -> bool {
+        match self.header {
+            Header::EmergencyStop | Header::EmergencyStopValue => true,
+            Header::ModifyMasterInhibitStatus => {
+                self.data.first().is_some_and(|mask| mask & 1 == 0)
+            }
+            _ => false,
         }
     }
 }
 
 #[derive(Debug)]
 struct Message<'a> {
+    pub correlation_id: CorrelationId,
     pub address: u8,
     pub checksum_type: ChecksumType,
+    pub encryption: Option<FrameEncryption>,
     pub header: Header,
     pub data: &'a [u8],
+    /// Longest reply payload read, see [`ResponseSizeCaps`].
+    pub max_response_length: u8,
 }
 
 impl<'a> Message<'a> {
-    fn from(transport_message: &'a TransportMessage) -> Self {
+    fn from(transport_message: &'a TransportMessage, encryption: Option<FrameEncryption>) -> Self {
         Message {
+            correlation_id: transport_message.correlation_id,
             address: transport_message.address,
             checksum_type: transport_message.checksum_type,
+            encryption,
             header: transport_message.header,
             data: &transport_message.data,
+            max_response_length: u8::MAX,
         }
     }
 }
@@ -103,77 +210,890 @@ impl CcTalkTokioTransport {
         retry_config: RetryConfig,
         echo: bool,
     ) -> Self {
+        Self::with_endpoint(
+            receiver,
+            Endpoint::UnixSocket(socket_path),
+            timeout,
+            minimum_delay,
+            retry_config,
+            echo,
+        )
+    }
+
+    /// Creates a transport talking to the bus through a serial port, see [`SerialConfig`].
+    ///
+    /// The echo setting of the configuration replaces the `echo` argument of
+    /// [`CcTalkTokioTransport::new`].
+    #[cfg(feature = "serial")]
+    pub fn serial(
+        receiver: mpsc::Receiver<TransportMessage>,
+        serial_config: SerialConfig,
+        timeout: Duration,
+        minimum_delay: Duration,
+        retry_config: RetryConfig,
+    ) -> Self {
+        let echo = serial_config.echo;
+        Self::with_endpoint(
+            receiver,
+            Endpoint::Serial(serial_config),
+            timeout,
+            minimum_delay,
+            retry_config,
+            echo,
+        )
+    }
+
+    /// Creates a transport talking to the bus through a ccTalk-over-IP bridge, see
+    /// [`TcpConfig`].
+    ///
+    /// The connection is re-established with a backoff when it drops or when the bridge is
+    /// not reachable yet. The echo setting of the configuration replaces the `echo` argument
+    /// of [`CcTalkTokioTransport::new`].
+    #[cfg(feature = "tcp")]
+    pub fn tcp(
+        receiver: mpsc::Receiver<TransportMessage>,
+        tcp_config: TcpConfig,
+        timeout: Duration,
+        minimum_delay: Duration,
+        retry_config: RetryConfig,
+    ) -> Self {
+        let echo = tcp_config.echo;
+        Self::with_endpoint(
+            receiver,
+            Endpoint::Tcp(tcp_config),
+            timeout,
+            minimum_delay,
+            retry_config,
+            echo,
+        )
+    }
+
+    fn with_endpoint(
+        receiver: mpsc::Receiver<TransportMessage>,
+        endpoint: Endpoint,
+        timeout: Duration,
+        minimum_delay: Duration,
+        retry_config: RetryConfig,
+        echo: bool,
+    ) -> Self {
+        let (express_sender, express_receiver) = mpsc::channel(EXPRESS_LANE_CAPACITY);
         CcTalkTokioTransport {
             receiver,
-            socket_path,
+            scheduler: BusScheduler::new(SchedulerConfig::default()),
+            express_sender,
+            express_receiver,
+            express_queue: VecDeque::new(),
+            queue_config: QueueConfig::default(),
+            endpoint,
             timeout,
             minimum_delay,
             retry_config,
+            resync_config: Some(ResyncConfig::default()),
+            health: CommsHealth::new(),
+            stats: TransportStats::new(),
+            frame_log: None,
+            unsolicited_policy: UnsolicitedFramePolicy::default(),
+            watchdog: None,
+            encryption_keys: EncryptionKeys::new(),
+            frame_cipher: None,
+            checksum_types: HashMap::new(),
+            response_caps: ResponseSizeCaps::default(),
+            machine_state: None,
+            last_exchange: Instant::now(),
             echo,
             send_buffer: vec![0; MAX_BLOCK_LENGTH],
             receive_buffer: vec![0; MAX_BLOCK_LENGTH],
         }
     }
 
-    pub async fn run(mut self) -> io::Result<()> {
-        let mut socket = match UnixStream::connect(&self.socket_path).await {
-            Ok(socket) => {
-                info!("connected to socket at {}", &self.socket_path);
-                socket
+    /// Sets the capacity of the queue and what happens when it is full, see [`QueueConfig`].
+    #[must_use]
+    pub fn with_queue_config(mut self, queue_config: QueueConfig) -> Self {
+        self.queue_config = queue_config;
+        self
+    }
+
+    /// Sets the per-address priorities and gaps used to pick the next message, see
+    /// [`SchedulerConfig`].
+    ///
+    /// Messages queued before the call are dropped.
+    #[must_use]
+    pub fn with_scheduler_config(mut self, scheduler_config: SchedulerConfig) -> Self {
+        self.scheduler = BusScheduler::new(scheduler_config);
+        self
+    }
+
+    /// Sets the resync procedure used after a checksum error, `None` disables it and
+    /// retries immediately.
+    #[must_use]
+    pub fn with_resync(mut self, resync_config: Option<ResyncConfig>) -> Self {
+        self.resync_config = resync_config;
+        self
+    }
+
+    /// Keeps the last raw frames written and read in the given ring buffer, the buffer is dumped
+    /// at debug level whenever a protocol error or a timeout occurs.
+    #[must_use]
+    pub fn with_frame_log(mut self, frame_log: FrameLog) -> Self {
+        self.frame_log = Some(frame_log);
+        self
+    }
+
+    /// Sets what is done with unsolicited frames, defaults to [`UnsolicitedFramePolicy::Log`].
+    #[must_use]
+    pub fn with_unsolicited_frame_policy(mut self, policy: UnsolicitedFramePolicy) -> Self {
+        self.unsolicited_policy = policy;
+        self
+    }
+
+    /// Runs a recovery ladder when the bus stays silent, see [`WatchdogConfig`].
+    #[must_use]
+    pub fn with_watchdog(mut self, watchdog: WatchdogConfig) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Encrypts the frames exchanged with the devices which have a protocol encryption key with
+    /// `cipher`, see [`FrameCipher`].
+    #[must_use]
+    pub fn with_frame_cipher(mut self, cipher: impl FrameCipher + Send + Sync + 'static) -> Self {
+        self.frame_cipher = Some(Arc::new(cipher));
+        self
+    }
+
+    /// Encrypts the frames exchanged with the device at `address` with its protocol encryption
+    /// key, the drivers keep working unchanged.
+    ///
+    /// Requires a cipher, see [`Self::with_frame_cipher`].
+    #[must_use]
+    pub fn with_encryption_key(self, address: u8, key: BnvKey) -> Self {
+        self.encryption_keys.set(address, key);
+        self
+    }
+
+    /// Reads the protocol encryption keys from `encryption_keys`, shared with the application
+    /// to change them at runtime.
+    ///
+    /// The key sent with a `SwitchEncryptionCode` command is applied once the device
+    /// acknowledged it, the acknowledgement is still decrypted with the previous key.
+    #[must_use]
+    pub fn with_encryption_keys(mut self, encryption_keys: EncryptionKeys) -> Self {
+        self.encryption_keys = encryption_keys;
+        self
+    }
+
+    /// Returns a handle to the protocol encryption keys, see [`Self::with_encryption_keys`].
+    pub fn encryption_keys(&self) -> EncryptionKeys {
+        self.encryption_keys.clone()
+    }
+
+    /// Returns the cipher and key the frames of `address` are encrypted with.
+    fn encryption(&self, address: u8) -> Option<FrameEncryption> {
+        Some(FrameEncryption {
+            cipher: self.frame_cipher.clone()?,
+            key: self.encryption_keys.get(address)?,
+        })
+    }
+
+    /// Applies the key of an acknowledged `SwitchEncryptionCode` command.
+    fn switch_encryption_key(&self, address: u8, data: &[u8]) {
+        match <[u8; 3]>::try_from(data).ok().and_then(BnvKey::from_bytes) {
+            Some(key) => {
+                self.encryption_keys.set(address, key);
             }
-            Err(error) => {
-                error!("unable to connect to socket: {}", error);
-                return Err(error);
+            None => warn!(
+                "invalid encryption code switched for {}, keeping the previous key",
+                address
+            ),
+        }
+    }
+
+    /// Builds and verifies the frames exchanged with the device at `address` with
+    /// `checksum_type`, whatever the checksum type of the device the drivers were given.
+    ///
+    /// Bill validators usually use CRC-16 while coin acceptors and hoppers use the simple
+    /// checksum, a single driver configuration then works with both.
+    #[must_use]
+    pub fn with_checksum_type(mut self, address: u8, checksum_type: ChecksumType) -> Self {
+        self.checksum_types.insert(address, checksum_type);
+        self
+    }
+
+    /// Sets the longest replies read, defaults to the command table, see [`ResponseSizeCaps`].
+    #[must_use]
+    pub fn with_response_size_caps(mut self, response_caps: ResponseSizeCaps) -> Self {
+        self.response_caps = response_caps;
+        self
+    }
+
+    /// Returns a handle to the response size caps, the limits raised by device quirks through
+    /// it apply to the running transport, see [`ResponseSizeCaps::raise_for_quirks`].
+    pub fn response_size_caps(&self) -> ResponseSizeCaps {
+        self.response_caps.clone()
+    }
+
+    /// Fails the commands the machine state does not allow instead of sending them, see
+    /// [`MachineStateHandle`].
+    ///
+    /// The state is checked when the message is about to be written, a payout queued before
+    /// the machine entered maintenance is not sent either.
+    #[must_use]
+    pub fn with_machine_state(mut self, machine_state: MachineStateHandle) -> Self {
+        self.machine_state = Some(machine_state);
+        self
+    }
+
+    /// Returns the checksum type configured for `address`, `requested` if none is.
+    fn checksum_type(&self, address: u8, requested: ChecksumType) -> ChecksumType {
+        self.checksum_types
+            .get(&address)
+            .copied()
+            .unwrap_or(requested)
+    }
+
+    /// Returns a handle to the raw frame ring buffer, if enabled with [`Self::with_frame_log`].
+    pub fn frame_log(&self) -> Option<FrameLog> {
+        self.frame_log.clone()
+    }
+
+    /// Returns a handle to the per-device communication health counters.
+    ///
+    /// The handle stays valid after the transport has been moved into [`Self::run`].
+    pub fn health(&self) -> CommsHealth {
+        self.health.clone()
+    }
+
+    /// Returns a handle to the transport counters, see [`TransportStats`].
+    #[must_use]
+    pub fn stats(&self) -> TransportStats {
+        self.stats.clone()
+    }
+
+    /// Returns a sender for the express lane.
+    ///
+    /// Every message sent through it is handled as an express message, regardless of its header,
+    /// and never waits behind the regular channel.
+    pub fn express_sender(&self) -> mpsc::Sender<TransportMessage> {
+        self.express_sender.clone()
+    }
+
+    /// Returns the worst case delay between an express command being queued and it being
+    /// written on the line, assuming the express lane is otherwise empty.
+    ///
+    /// An express command only has to wait for the exchange currently on the wire: its write and
+    /// reads, the resync procedure if the reply was corrupted, and the minimum delay between
+    /// messages. A long operation on the wire, see [`Header::long_operation_timeout`], extends its
+    /// read timeout and therefore this bound.
+    pub fn express_latency_bound(&self) -> Duration {
+        let exchange = self.timeout * 3;
+        let resync = self
+            .resync_config
+            .as_ref()
+            .map_or(Duration::ZERO, |config| {
+                config.max_flush_duration
+                    + if config.simple_poll {
+                        exchange
+                    } else {
+                        Duration::ZERO
+                    }
+            });
+        exchange + resync + self.minimum_delay
+    }
+
+    /// Read timeout for a header, extended for headers triggering a long operation on the device
+    /// so it is not retried while still busy.
+    fn reply_timeout(&self, header: Header) -> Duration {
+        header
+            .long_operation_timeout()
+            .map_or(self.timeout, |long_timeout| long_timeout.max(self.timeout))
+    }
+
+    fn enqueue(&mut self, message: TransportMessage) {
+        if message.is_express() {
+            self.express_queue.push_back(message);
+            return;
+        }
+
+        if self.scheduler.len() >= self.queue_config.capacity {
+            match self.queue_config.policy {
+                // Only reached when the queue was empty, `drain_receivers` stops reading first.
+                BackpressurePolicy::Block => {}
+                BackpressurePolicy::FailFast => return self.reject(message),
+                BackpressurePolicy::DropOldestPoll => {
+                    let Some(dropped) = self
+                        .scheduler
+                        .remove_oldest(|queued| is_droppable_poll(queued.header))
+                    else {
+                        return self.reject(message);
+                    };
+                    debug!(
+                        "queue full, dropping poll {} for {}",
+                        dropped.correlation_id, dropped.address
+                    );
+                    self.stats.record_dropped_poll();
+                    handle_error(dropped, TransportError::QueueFull, "poll dropped");
+                }
+            }
+        }
+        self.scheduler.push(message, Instant::now());
+    }
+
+    fn reject(&self, message: TransportMessage) {
+        self.stats.record_rejected();
+        handle_error(
+            message,
+            TransportError::QueueFull,
+            "queue full, message rejected",
+        );
+    }
+
+    /// Moves every message waiting in the channels into the local queues.
+    ///
+    /// With [`BackpressurePolicy::Block`] the regular channel is left alone once the queue is
+    /// full, its senders wait instead.
+    fn drain_receivers(&mut self) {
+        while let Ok(message) = self.express_receiver.try_recv() {
+            self.express_queue.push_back(message);
+        }
+        while self.accepts_messages() {
+            let Ok(message) = self.receiver.try_recv() else {
+                break;
+            };
+            self.enqueue(message);
+        }
+        self.record_queue_depth();
+    }
+
+    /// Returns `false` once the queue is full with [`BackpressurePolicy::Block`].
+    fn accepts_messages(&self) -> bool {
+        self.queue_config.policy != BackpressurePolicy::Block
+            || self.scheduler.len() < self.queue_config.capacity
+    }
+
+    /// Returns the machine state if it does not allow the message.
+    fn disallowed_by(&self, message: &TransportMessage) -> Option<MachineState> {
+        self.machine_state
+            .as_ref()
+            .filter(|machine_state| !machine_state.allows(message.header, &message.data))
+            .map(MachineStateHandle::state)
+    }
+
+    fn record_queue_depth(&self) {
+        self.stats
+            .record_queue_depth(self.scheduler.len() + self.receiver.len());
+    }
+
+    /// Returns the next message to send, express messages first then in the order of the
+    /// [scheduler](super::scheduler), waiting for the minimum gap of the queued addresses.
+    ///
+    /// Returns `None` once the regular channel is closed and every queued message was sent.
+    async fn next_message(&mut self) -> Option<TransportMessage> {
+        loop {
+            self.drain_receivers();
+            let now = Instant::now();
+            let message = self
+                .express_queue
+                .pop_front()
+                .or_else(|| self.scheduler.pop(now));
+            if let Some(message) = message {
+                self.record_queue_depth();
+                if let Some(state) = self.disallowed_by(&message) {
+                    handle_error(
+                        message,
+                        TransportError::NotAllowed(state),
+                        "message not allowed in the machine state",
+                    );
+                    continue;
+                }
+                if self.frame_cipher.is_none()
+                    && self.encryption_keys.get(message.address).is_some()
+                {
+                    handle_error(
+                        message,
+                        TransportError::PacketCreationError,
+                        "encryption key set without a frame cipher",
+                    );
+                    continue;
+                }
+                return Some(message);
             }
+
+            let channel_open = !self.receiver.is_closed() || !self.receiver.is_empty();
+            if !channel_open && self.scheduler.is_empty() {
+                return None;
+            }
+            let ready_at = self.scheduler.ready_at(now);
+            tokio::select! {
+                biased;
+                Some(message) = self.express_receiver.recv() => {
+                    self.express_queue.push_back(message);
+                }
+                message = self.receiver.recv(), if channel_open && self.accepts_messages() => {
+                    // A closed channel is checked again on the next iteration.
+                    if let Some(message) = message {
+                        self.enqueue(message);
+                    }
+                }
+                () = tokio::time::sleep_until(ready_at.unwrap_or(now)), if ready_at.is_some() => {}
+            }
+        }
+    }
+
+    /// Sends every queued express message, used to pre-empt the retries of a regular message.
+    async fn process_express(&mut self, socket: &mut BusStream) {
+        self.drain_receivers();
+        while let Some(express_message) = self.express_queue.pop_front() {
+            debug!(
+                "pre-empting retries for express message to {}, header: {}",
+                express_message.address, express_message.header as u8
+            );
+            Box::pin(self.process(socket, express_message, false)).await;
+            self.drain_receivers();
+        }
+    }
+
+    #[instrument(
+        skip_all,
+        fields(correlation_id = %transport_message.correlation_id),
+        level = "debug"
+    )]
+    async fn process(
+        &mut self,
+        socket: &mut BusStream,
+        transport_message: TransportMessage,
+        allow_preemption: bool,
+    ) {
+        trace!(
+            "received message {} for {}, header: {}",
+            transport_message.correlation_id,
+            transport_message.address,
+            transport_message.header as u8
+        );
+
+        let mut retry_instance = self.retry_config.create_retry_instance();
+        let mut response_length: Option<usize> = None;
+        let message = Message {
+            checksum_type: self
+                .checksum_type(transport_message.address, transport_message.checksum_type),
+            max_response_length: self
+                .response_caps
+                .limit(transport_message.address, transport_message.header),
+            ..Message::from(
+                &transport_message,
+                self.encryption(transport_message.address),
+            )
         };
+        let reply_timeout = self.reply_timeout(message.header);
+        if reply_timeout != self.timeout {
+            debug!(
+                "long operation for {}, header: {}, waiting up to {:?} for the reply",
+                message.address, message.header as u8, reply_timeout
+            );
+        }
+        let mut attempts = 0u32;
+        while retry_instance.can_retry() {
+            if attempts > 0 {
+                self.stats.record_retry();
+            }
+            attempts += 1;
+
+            let started = Instant::now();
+            match handle_message(
+                &message,
+                &mut self.send_buffer,
+                &mut self.receive_buffer,
+                reply_timeout,
+                socket,
+                self.echo,
+                &FrameObserver {
+                    correlation_id: Some(message.correlation_id),
+                    stats: &self.stats,
+                    frame_log: self.frame_log.as_ref(),
+                    unsolicited_policy: &self.unsolicited_policy,
+                },
+            )
+            .await
+            {
+                Ok(length) => {
+                    transport_message.received_at.stamp(Timestamp::now());
+                    if message.header == Header::SwitchEncryptionMode {
+                        self.switch_encryption_key(message.address, message.data);
+                    }
+                    self.stats
+                        .record_exchange(started.elapsed(), message.correlation_id);
+                    self.last_exchange = Instant::now();
+                    response_length = Some(length);
+                    break;
+                }
+                Err((error_code, error_message)) => {
+                    error!("{} handling message. Info: {}", error_code, error_message);
+                    if error_code == TransportError::Nack {
+                        self.last_exchange = Instant::now();
+                    }
+                    match error_code {
+                        TransportError::Timeout => self.stats.record_timeout(),
+                        TransportError::ChecksumError => self.stats.record_checksum_failure(),
+                        TransportError::Nack => self.stats.record_nack(),
+                        _ => {}
+                    }
+                    if error_code != TransportError::Nack
+                        && let Some(frame_log) = &self.frame_log
+                    {
+                        frame_log.dump(&format!(
+                            "{} for message {} to {}, header: {}: {}",
+                            error_code,
+                            message.correlation_id,
+                            message.address,
+                            message.header as u8,
+                            error_message
+                        ));
+                    }
+                    if matches!(
+                        error_code,
+                        TransportError::ChecksumError | TransportError::EchoMismatch
+                    ) && let Some(resync_config) = self.resync_config.clone()
+                    {
+                        self.resync(&message, &resync_config, socket).await;
+                        self.health.record_resync(message.address);
+                    }
+                    if matches!(
+                        error_code,
+                        TransportError::SocketWriteError | TransportError::SocketReadError
+                    ) && self.endpoint.reconnects()
+                    {
+                        match self.connect().await {
+                            Ok(reconnected) => *socket = reconnected,
+                            Err(error) => {
+                                warn!("unable to reconnect to {}: {}", self.endpoint, error);
+                            }
+                        }
+                    }
+                    retry_instance.evaluate_error(error_code);
+                    if allow_preemption && retry_instance.can_retry() {
+                        self.process_express(socket).await;
+                    }
+                    retry_instance.delay_for_retry().await;
+                }
+            }
+        }
 
-        while let Some(transport_message) = self.receiver.recv().await {
-            trace!(
-                "received message for {}, header: {}",
-                transport_message.address, transport_message.header as u8
+        let silent =
+            response_length.is_none() && retry_instance.last_error() != TransportError::Nack;
+        let probe = (
+            transport_message.correlation_id,
+            transport_message.address,
+            message.checksum_type,
+        );
+        if let Some(length) = response_length {
+            let reply = self.reply_buffer(transport_message.data, length);
+            transport_message.respond_to.send(Ok(reply)).ok();
+        } else {
+            error!(
+                "too many retries for message {} to {}, header: {}",
+                transport_message.correlation_id,
+                transport_message.address,
+                transport_message.header as u8
             );
+            transport_message
+                .respond_to
+                .send(Err(retry_instance.last_error()))
+                .ok();
+        }
+        if silent {
+            self.watch_bus(probe, socket).await;
+        }
+        self.scheduler.exchange_done(probe.1, Instant::now());
 
-            let mut retry_instance = self.retry_config.create_retry_instance();
-            let mut response_data: Option<Vec<u8>> = None;
-            let message = Message::from(&transport_message);
-            while retry_instance.can_retry() {
-                match handle_message(
-                    &message,
-                    &mut self.send_buffer,
+        if !self.minimum_delay.is_zero() {
+            tokio::time::sleep(self.minimum_delay).await;
+        }
+    }
+
+    /// Copies the first `length` bytes of the receive buffer in `buffer`, the buffer of the
+    /// request, which is only reallocated when smaller than the reply.
+    fn reply_buffer(&self, mut buffer: Vec<u8>, length: usize) -> Vec<u8> {
+        self.stats.record_reply_buffer(buffer.capacity() >= length);
+        buffer.clear();
+        buffer.extend_from_slice(&self.receive_buffer[..length]);
+        buffer
+    }
+
+    /// Drains the line until no byte has been received for the quiet period, then optionally
+    /// realigns with the device using a `SimplePoll`.
+    async fn resync(
+        &mut self,
+        message: &Message<'_>,
+        resync_config: &ResyncConfig,
+        socket: &mut BusStream,
+    ) {
+        let flushed = flush_line(
+            &mut self.receive_buffer,
+            resync_config,
+            socket,
+            self.frame_log.as_ref(),
+        )
+        .await;
+        debug!(
+            "resyncing with {}, flushed {} bytes",
+            message.address, flushed
+        );
+
+        if resync_config.simple_poll {
+            let poll = Message {
+                correlation_id: message.correlation_id,
+                address: message.address,
+                checksum_type: message.checksum_type,
+                encryption: message.encryption.clone(),
+                header: Header::SimplePoll,
+                data: &[],
+                max_response_length: self
+                    .response_caps
+                    .limit(message.address, Header::SimplePoll),
+            };
+            if let Err((error_code, error_message)) = handle_message(
+                &poll,
+                &mut self.send_buffer,
+                &mut self.receive_buffer,
+                self.timeout,
+                socket,
+                self.echo,
+                &FrameObserver {
+                    correlation_id: Some(message.correlation_id),
+                    stats: &self.stats,
+                    frame_log: self.frame_log.as_ref(),
+                    unsolicited_policy: &self.unsolicited_policy,
+                },
+            )
+            .await
+            {
+                warn!(
+                    "resync poll to {} failed: {}. Info: {}",
+                    message.address, error_code, error_message
+                );
+            }
+        }
+    }
+
+    /// Runs the recovery ladder if no exchange succeeded for the watchdog silence period.
+    ///
+    /// Each step is followed by a `SimplePoll` to the device described by `probe`.
+    async fn watch_bus(
+        &mut self,
+        probe: (CorrelationId, u8, ChecksumType),
+        socket: &mut BusStream,
+    ) {
+        let Some(watchdog) = self.watchdog.clone() else {
+            return;
+        };
+        let silent_for = self.last_exchange.elapsed();
+        if silent_for < watchdog.silence_period {
+            return;
+        }
+        let (correlation_id, address, checksum_type) = probe;
+        warn!(
+            "no successful exchange for {:?}, running the bus recovery ladder",
+            silent_for
+        );
+
+        let poll = Message {
+            correlation_id,
+            address,
+            checksum_type,
+            encryption: self.encryption(address),
+            header: Header::SimplePoll,
+            data: &[],
+            max_response_length: self.response_caps.limit(address, Header::SimplePoll),
+        };
+        let mut attempted = Vec::with_capacity(watchdog.steps.len());
+        let mut restored_by = None;
+        for step in watchdog.steps.iter().copied() {
+            attempted.push(step);
+            if !self.run_recovery_step(step, &poll, socket).await {
+                continue;
+            }
+            let observer = FrameObserver {
+                correlation_id: Some(correlation_id),
+                stats: &self.stats,
+                frame_log: self.frame_log.as_ref(),
+                unsolicited_policy: &self.unsolicited_policy,
+            };
+            match handle_message(
+                &poll,
+                &mut self.send_buffer,
+                &mut self.receive_buffer,
+                self.timeout,
+                socket,
+                self.echo,
+                &observer,
+            )
+            .await
+            {
+                Ok(_) | Err((TransportError::Nack, _)) => {
+                    restored_by = Some(step);
+                    break;
+                }
+                Err((error_code, _)) => debug!("bus still silent after {}: {}", step, error_code),
+            }
+        }
+
+        self.last_exchange = Instant::now();
+        match restored_by {
+            Some(step) => info!("communication restored by {}", step),
+            None => error!("bus still silent after {:?}", attempted),
+        }
+        let recovery = BusRecovery {
+            silent_for,
+            attempted,
+            restored_by,
+        };
+        if let Some(events) = &watchdog.events
+            && let Err(error) = events.try_send(recovery)
+        {
+            warn!("unable to report the bus recovery: {}", error);
+        }
+    }
+
+    /// Runs a single recovery step, returns `false` if it could not be run.
+    async fn run_recovery_step(
+        &mut self,
+        step: RecoveryStep,
+        poll: &Message<'_>,
+        socket: &mut BusStream,
+    ) -> bool {
+        debug!("running recovery step {}", step);
+        match step {
+            RecoveryStep::Flush => {
+                let resync_config = self.resync_config.clone().unwrap_or_default();
+                let flushed = flush_line(
                     &mut self.receive_buffer,
+                    &resync_config,
+                    socket,
+                    self.frame_log.as_ref(),
+                )
+                .await;
+                debug!("flushed {} bytes", flushed);
+                true
+            }
+            RecoveryStep::Reopen => match self.connect().await {
+                Ok(reopened) => {
+                    *socket = reopened;
+                    true
+                }
+                Err(error) => {
+                    warn!("unable to reopen {}: {}", self.endpoint, error);
+                    false
+                }
+            },
+            RecoveryStep::BroadcastReset => {
+                let reset = Message {
+                    correlation_id: poll.correlation_id,
+                    address: BusAddress::BROADCAST.get(),
+                    checksum_type: poll.checksum_type,
+                    encryption: None,
+                    header: Header::ResetDevice,
+                    data: &[],
+                    max_response_length: 0,
+                };
+                let observer = FrameObserver {
+                    correlation_id: Some(poll.correlation_id),
+                    stats: &self.stats,
+                    frame_log: self.frame_log.as_ref(),
+                    unsolicited_policy: &self.unsolicited_policy,
+                };
+                // Nobody replies to broadcasts.
+                match handle_send(
+                    &reset,
+                    &mut self.send_buffer,
+                    socket,
                     self.timeout,
-                    &mut socket,
                     self.echo,
+                    &observer,
                 )
                 .await
                 {
-                    Ok(data) => {
-                        response_data = Some(data);
-                        break;
-                    }
+                    Ok(()) => true,
                     Err((error_code, error_message)) => {
-                        error!("{} handling message. Info: {}", error_code, error_message);
-                        retry_instance.evaluate_and_wait(error_code).await;
+                        warn!(
+                            "broadcast reset failed: {}. Info: {}",
+                            error_code, error_message
+                        );
+                        false
+                    }
+                }
+            }
+            RecoveryStep::BaudFallback => {
+                let Some(baud_rate) = self.endpoint.fallback_baud_rate() else {
+                    debug!(
+                        "{} has no baud rate, skipping the baud fallback",
+                        self.endpoint
+                    );
+                    return false;
+                };
+                match socket.set_baud_rate(baud_rate) {
+                    Ok(changed) => changed,
+                    Err(error) => {
+                        warn!("unable to fall back to the default baud rate: {}", error);
+                        false
                     }
                 }
             }
+        }
+    }
 
-            if let Some(data) = response_data {
-                transport_message.respond_to.send(Ok(data)).ok();
-            } else {
-                error!(
-                    "too many retries for message to {}, header: {}",
-                    transport_message.address, transport_message.header as u8
-                );
-                transport_message
-                    .respond_to
-                    .send(Err(retry_instance.last_error()))
-                    .ok();
+    /// Connects to the endpoint, with the backoff of its [`ReconnectConfig`] if it has one.
+    async fn connect(&self) -> io::Result<BusStream> {
+        #[cfg(feature = "tcp")]
+        if let Some(reconnect) = self.endpoint.reconnect_config() {
+            return self.reconnect(reconnect).await;
+        }
+        self.endpoint.connect().await
+    }
+
+    /// Connects to the endpoint, waiting between the failed attempts.
+    #[cfg(feature = "tcp")]
+    async fn reconnect(&self, reconnect: &ReconnectConfig) -> io::Result<BusStream> {
+        let mut failed_attempts = 0;
+        loop {
+            let Some(delay) = reconnect.delay(failed_attempts) else {
+                return Err(io::ErrorKind::NotConnected.into());
+            };
+            tokio::time::sleep(delay).await;
+            match self.endpoint.connect().await {
+                Ok(socket) => {
+                    if failed_attempts > 0 {
+                        info!(
+                            "connected to {} after {} failed attempts",
+                            self.endpoint, failed_attempts
+                        );
+                    }
+                    return Ok(socket);
+                }
+                Err(error) => {
+                    failed_attempts += 1;
+                    warn!(
+                        "unable to connect to {} (attempt {}): {}",
+                        self.endpoint, failed_attempts, error
+                    );
+                }
             }
+        }
+    }
 
-            if !self.minimum_delay.is_zero() {
-                tokio::time::sleep(self.minimum_delay).await;
+    pub async fn run(mut self) -> io::Result<()> {
+        let mut socket = match self.connect().await {
+            Ok(socket) => {
+                info!("connected to {}", self.endpoint);
+                socket
+            }
+            Err(error) => {
+                error!("unable to connect to {}: {}", self.endpoint, error);
+                return Err(error);
             }
+        };
+
+        self.last_exchange = Instant::now();
+        while let Some(transport_message) = self.next_message().await {
+            self.process(&mut socket, transport_message, true).await;
         }
 
         socket.flush().await?;
@@ -189,53 +1109,89 @@ fn handle_error(message: TransportMessage, error: TransportError, error_message:
     }
 }
 
-fn build_packet(message: &Message, packet: &mut Packet<&mut [u8]>) -> Result<(), TransportError> {
-    packet
-        .set_destination(message.address)
-        .map_err(|_| TransportError::BufferOverflow)?;
-    packet
-        .set_source(1)
-        .map_err(|_| TransportError::BufferOverflow)?;
-    packet
-        .set_header(message.header)
-        .map_err(|_| TransportError::BufferOverflow)?;
-    packet
-        .set_data(message.data)
+/// Writes the signed, and if needed encrypted, frame of the message, returns its length.
+fn build_packet(message: &Message, buffer: &mut [u8]) -> Result<usize, TransportError> {
+    let length = PacketBuilder::new(message.address, message.header)
+        .data(message.data)
+        .checksum_type(message.checksum_type)
+        .write_to(buffer)
         .map_err(|_| TransportError::BufferOverflow)?;
+    if let Some(encryption) = &message.encryption {
+        encryption.encrypt(&mut buffer[..length]);
+    }
+    Ok(length)
+}
 
-    Ok(())
+/// Records the frames of an exchange to the transport counters and, if enabled, the frame log.
+struct FrameObserver<'a> {
+    /// Message the frames belong to, unsolicited frames never belong to one.
+    correlation_id: Option<CorrelationId>,
+    stats: &'a TransportStats,
+    frame_log: Option<&'a FrameLog>,
+    unsolicited_policy: &'a UnsolicitedFramePolicy,
+}
+
+impl FrameObserver<'_> {
+    fn tx(&self, bytes: &[u8]) {
+        self.stats.record_tx();
+        if let Some(frame_log) = self.frame_log {
+            frame_log.record(FrameDirection::Tx, bytes, self.correlation_id);
+        }
+    }
+
+    fn rx(&self, bytes: &[u8]) {
+        self.stats.record_rx();
+        if let Some(frame_log) = self.frame_log {
+            frame_log.record(FrameDirection::Rx, bytes, self.correlation_id);
+        }
+    }
+
+    fn unsolicited(&self, bytes: &[u8]) {
+        self.stats.record_rx();
+        if let Some(frame_log) = self.frame_log {
+            frame_log.record(FrameDirection::Rx, bytes, None);
+        }
+        self.stats.record_unsolicited();
+        self.unsolicited_policy.handle(bytes);
+    }
+}
+
+/// Reads the bytes which arrived since the last exchange, without waiting.
+///
+/// No request is outstanding at that point, so everything read is unsolicited.
+fn drain_unsolicited(read_buffer: &mut [u8], socket: &mut BusStream, observer: &FrameObserver<'_>) {
+    let mut pending = Vec::new();
+    while let Ok(bytes_read @ 1..) = socket.try_read(read_buffer) {
+        pending.extend_from_slice(&read_buffer[..bytes_read]);
+    }
+    for frame in split_frames(&pending) {
+        observer.unsolicited(frame);
+    }
 }
 
 async fn handle_send(
     message: &Message<'_>,
-    send_packet: &mut Packet<&mut [u8]>,
-    socket: &mut UnixStream,
+    send_buffer: &mut [u8],
+    socket: &mut BusStream,
     write_timeout: Duration,
     echo: bool,
+    observer: &FrameObserver<'_>,
 ) -> Result<(), (TransportError, &'static str)> {
     trace!("building packet for message");
-    if let Err(error) = build_packet(message, send_packet) {
-        return Err((error, "failed to build packet"));
-    }
-
-    trace!("serializing packet");
-    let device = Device::new(message.address, Category::Unknown, message.checksum_type);
-    if serialize(&device, send_packet).is_err() {
-        return Err((
-            TransportError::PacketCreationError,
-            "failed to serialize packet",
-        ));
-    }
+    let packet_length = match build_packet(message, send_buffer) {
+        Ok(packet_length) => packet_length,
+        Err(error) => return Err((error, "failed to build packet")),
+    };
 
-    let packet_length = send_packet.get_logical_size();
     trace!(
         "writing packet of length {}, {:?}",
         packet_length,
-        &send_packet.as_slice()[..packet_length]
+        &send_buffer[..packet_length]
     );
+    observer.tx(&send_buffer[..packet_length]);
     match timeout(
         write_timeout,
-        socket.write_all(&send_packet.as_slice()[..packet_length]),
+        socket.write_all(&send_buffer[..packet_length]),
     )
     .await
     {
@@ -243,13 +1199,30 @@ async fn handle_send(
             trace!("packet sent successfully");
             let _ = socket.flush().await;
             if echo {
-                let _ = socket
-                    .read_exact(&mut send_packet.as_mut_slice()[..packet_length])
-                    .await;
-            }
-            Ok(())
-        }
-        Ok(Err(_)) => Err((
+                let mut echo_canceller = EchoCanceller::new();
+                echo_canceller.transmitted(&send_buffer[..packet_length]);
+                let mut echoed = [0u8; MAX_BLOCK_LENGTH];
+                match read_exact_within(socket, &mut echoed[..packet_length], write_timeout, false)
+                    .await
+                {
+                    Ok(()) => {
+                        if let Err(mismatch) = echo_canceller.strip(&echoed[..packet_length]) {
+                            debug!("{}", mismatch);
+                            return Err((
+                                TransportError::EchoMismatch,
+                                "echo does not match the sent frame",
+                            ));
+                        }
+                    }
+                    Err(None) => return Err((TransportError::Timeout, "timeout reading echo")),
+                    Err(Some(_)) => {
+                        return Err((TransportError::SocketReadError, "failed to read echo"));
+                    }
+                }
+            }
+            Ok(())
+        }
+        Ok(Err(_)) => Err((
             TransportError::SocketWriteError,
             "failed to write to socket",
         )),
@@ -257,28 +1230,69 @@ async fn handle_send(
     }
 }
 
+/// Fills `buffer` within `read_timeout`.
+///
+/// On a serial line the gap between two bytes is bounded by the inter-byte timeout too, from
+/// the first byte on or right away when `frame_started`. Fails with `None` on a timeout.
+async fn read_exact_within(
+    socket: &mut BusStream,
+    buffer: &mut [u8],
+    read_timeout: Duration,
+    frame_started: bool,
+) -> Result<(), Option<io::Error>> {
+    let deadline = Instant::now() + read_timeout;
+    let Some(inter_byte_timeout) = socket.inter_byte_timeout() else {
+        return match tokio::time::timeout_at(deadline, socket.read_exact(buffer)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(error)) => Err(Some(error)),
+            Err(_) => Err(None),
+        };
+    };
+
+    let mut bytes_read = 0;
+    while bytes_read < buffer.len() {
+        let limit = if bytes_read > 0 || frame_started {
+            deadline.min(Instant::now() + inter_byte_timeout)
+        } else {
+            deadline
+        };
+        match tokio::time::timeout_at(limit, socket.read(&mut buffer[bytes_read..])).await {
+            Ok(Ok(0)) => return Err(Some(io::ErrorKind::UnexpectedEof.into())),
+            Ok(Ok(read)) => bytes_read += read,
+            Ok(Err(error)) => return Err(Some(error)),
+            Err(_) => {
+                if bytes_read > 0 {
+                    debug!("inter-byte timeout after {} bytes", bytes_read);
+                }
+                return Err(None);
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn read_packet_header(
     read_buffer: &mut [u8],
     read_timeout: Duration,
-    socket: &mut UnixStream,
+    socket: &mut BusStream,
 ) -> Result<usize, (TransportError, &'static str)> {
-    match timeout(read_timeout, socket.read_exact(&mut read_buffer[..5])).await {
-        Ok(Ok(read_bytes)) => {
-            trace!("read response header ({} bytes)", read_bytes);
-            Ok(read_bytes)
+    match read_exact_within(socket, &mut read_buffer[..5], read_timeout, false).await {
+        Ok(()) => {
+            trace!("read response header (5 bytes)");
+            Ok(5)
         }
-        Ok(Err(_)) => Err((
+        Err(Some(_)) => Err((
             TransportError::SocketReadError,
             "failed to read response header",
         )),
-        Err(_) => Err((TransportError::Timeout, "timeout reading response header")),
+        Err(None) => Err((TransportError::Timeout, "timeout reading response header")),
     }
 }
 
 async fn read_full_packet(
     read_buffer: &mut [u8],
     read_timeout: Duration,
-    socket: &mut UnixStream,
+    socket: &mut BusStream,
 ) -> Result<usize, (TransportError, &'static str)> {
     let data_length = read_buffer[DATA_LENGTH_OFFSET] as usize;
     trace!(
@@ -287,21 +1301,23 @@ async fn read_full_packet(
         &read_buffer[..5]
     );
     if data_length > 0 {
-        return match timeout(
+        return match read_exact_within(
+            socket,
+            &mut read_buffer[5..(5 + data_length)],
             read_timeout,
-            socket.read_exact(&mut read_buffer[5..(5 + data_length)]),
+            true,
         )
         .await
         {
-            Ok(Ok(bytes_read)) => {
+            Ok(()) => {
                 trace!("read {} bytes of response data", data_length);
-                Ok(bytes_read)
+                Ok(data_length)
             }
-            Ok(Err(_)) => Err((
+            Err(Some(_)) => Err((
                 TransportError::SocketReadError,
                 "failed to read response data",
             )),
-            Err(_) => Err((TransportError::Timeout, "timeout reading response data")),
+            Err(None) => Err((TransportError::Timeout, "timeout reading response data")),
         };
     }
     Ok(0)
@@ -312,53 +1328,160 @@ async fn handle_message(
     send_buffer: &mut [u8],
     read_buffer: &mut [u8],
     rw_timeout: Duration,
-    socket: &mut UnixStream,
+    socket: &mut BusStream,
     echo: bool,
-) -> Result<Vec<u8>, (TransportError, &'static str)> {
-    let mut send_packet = Packet::new(send_buffer);
-
+    observer: &FrameObserver<'_>,
+) -> Result<usize, (TransportError, &'static str)> {
+    drain_unsolicited(read_buffer, socket, observer);
     if let Err((error_code, error_message)) =
-        handle_send(message, &mut send_packet, socket, rw_timeout, echo).await
+        handle_send(message, send_buffer, socket, rw_timeout, echo, observer).await
     {
         return Err((error_code, error_message));
     }
 
-    let mut bytes_read = match read_packet_header(read_buffer, rw_timeout, socket).await {
-        Ok(bytes_read) => bytes_read,
-        Err((error_code, error_message)) => return Err((error_code, error_message)),
+    if matches!(message.header, Header::AddressPoll | Header::AddressClash) {
+        return Ok(read_addresses(read_buffer, rw_timeout, socket, observer).await);
+    }
+
+    // Frames addressed to another device are skipped, the reply has to arrive before the
+    // deadline nonetheless.
+    let deadline = Instant::now() + rw_timeout;
+    let bytes_read = loop {
+        let read_timeout = deadline.saturating_duration_since(Instant::now());
+        let mut bytes_read = match read_packet_header(read_buffer, read_timeout, socket).await {
+            Ok(bytes_read) => bytes_read,
+            Err((error_code, error_message)) => return Err((error_code, error_message)),
+        };
+        // The payload of an oversized reply is consumed to stay in sync with the line but never
+        // parsed.
+        let oversized = read_buffer[0] == BusAddress::HOST.get()
+            && read_buffer[DATA_LENGTH_OFFSET] > message.max_response_length;
+
+        bytes_read += match read_full_packet(read_buffer, read_timeout, socket).await {
+            Ok(bytes_read) => bytes_read,
+            Err((error_code, error_message)) => {
+                observer.rx(&read_buffer[..bytes_read]);
+                return Err((error_code, error_message));
+            }
+        };
+        if oversized {
+            observer.rx(&read_buffer[..bytes_read]);
+            warn!(
+                "reply from {} to header {} has {} bytes of data, at most {} expected",
+                message.address,
+                message.header as u8,
+                read_buffer[DATA_LENGTH_OFFSET],
+                message.max_response_length
+            );
+            return Err((
+                TransportError::ResponseTooLong,
+                "response longer than the command allows",
+            ));
+        }
+        if read_buffer[0] == BusAddress::HOST.get() {
+            break bytes_read;
+        }
+        observer.unsolicited(&read_buffer[..bytes_read]);
     };
+    observer.rx(&read_buffer[..bytes_read]);
+    if let Some(encryption) = &message.encryption {
+        encryption.decrypt(&mut read_buffer[..bytes_read]);
+    }
 
-    bytes_read += match read_full_packet(read_buffer, rw_timeout, socket).await {
-        Ok(bytes_read) => bytes_read,
-        Err((error_code, error_message)) => {
-            return Err((error_code, error_message));
+    let response_packet = match Packet::parse(&read_buffer[..bytes_read], message.checksum_type) {
+        Ok(packet) => packet,
+        Err(PacketError::ChecksumMismatch { .. }) => {
+            return Err((
+                TransportError::ChecksumError,
+                "response packet checksum mismatch",
+            ));
+        }
+        Err(_) => {
+            return Err((
+                TransportError::ChecksumError,
+                "failed to validate response packet",
+            ));
         }
     };
 
-    let mut response_packet = Packet::new(&mut read_buffer[..bytes_read]);
-    if deserialize(&mut response_packet, message.checksum_type).is_err() {
-        return Err((
-            TransportError::ChecksumError,
-            "failed to deserialize response packet",
-        ));
+    match response_packet.header() {
+        Header::NACK => return Err((TransportError::Nack, "received NACK response")),
+        Header::Busy => observer.stats.record_busy(),
+        _ => {}
     }
 
-    if response_packet.get_header().unwrap_or(Header::Reply) == Header::NACK {
-        return Err((TransportError::Nack, "received NACK response"));
-    };
+    Ok(bytes_read)
+}
+
+/// Collects the unframed address bytes sent in reply to an `AddressPoll` or `AddressClash` at
+/// the start of `read_buffer`, returns their count.
+///
+/// Every device answers in its own time slot, the whole window is waited for since silence
+/// between two slots does not mean all devices answered.
+async fn read_addresses(
+    read_buffer: &mut [u8],
+    rw_timeout: Duration,
+    socket: &mut BusStream,
+    observer: &FrameObserver<'_>,
+) -> usize {
+    let deadline = Instant::now() + ADDRESS_SLOT * u32::from(u8::MAX) + rw_timeout;
+    let mut bytes_read = 0;
+    while bytes_read < read_buffer.len() {
+        match tokio::time::timeout_at(deadline, socket.read(&mut read_buffer[bytes_read..])).await {
+            Ok(Ok(0) | Err(_)) | Err(_) => break,
+            Ok(Ok(read)) => bytes_read += read,
+        }
+    }
+    trace!("{} devices answered the address poll", bytes_read);
+    observer.rx(&read_buffer[..bytes_read]);
+    bytes_read
+}
+
+async fn flush_line(
+    read_buffer: &mut [u8],
+    resync_config: &ResyncConfig,
+    socket: &mut BusStream,
+    frame_log: Option<&FrameLog>,
+) -> usize {
+    let deadline = Instant::now() + resync_config.max_flush_duration;
+    let mut flushed = 0;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        match timeout(
+            resync_config.quiet_period.min(remaining),
+            socket.read(read_buffer),
+        )
+        .await
+        {
+            Ok(Ok(0) | Err(_)) | Err(_) => break,
+            Ok(Ok(bytes_read)) => {
+                if let Some(frame_log) = frame_log {
+                    frame_log.record(FrameDirection::Rx, &read_buffer[..bytes_read], None);
+                }
+                flushed += bytes_read;
+            }
+        }
+    }
 
-    Ok(read_buffer[..bytes_read].to_vec())
+    flushed
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cc_talk_core::cc_talk::{ChecksumType, Header, MAX_BLOCK_LENGTH};
+    use crate::transport::scheduler::DevicePriority;
+    use cc_talk_core::cc_talk::{ChecksumKind, ChecksumType, Header, MAX_BLOCK_LENGTH};
     use std::path::Path;
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
     use tempfile::TempDir;
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
-    use tokio::net::UnixListener;
+    use tokio::net::{UnixListener, UnixStream};
     use tokio::sync::{mpsc, oneshot};
 
     fn create_test_socket_path() -> (TempDir, String) {
@@ -375,9 +1498,15 @@ mod tests {
         receiver: mpsc::Receiver<TransportMessage>,
         socket_path: String,
     ) -> CcTalkTokioTransport {
+        let (express_sender, express_receiver) = mpsc::channel(EXPRESS_LANE_CAPACITY);
         CcTalkTokioTransport {
             receiver,
-            socket_path,
+            scheduler: BusScheduler::new(SchedulerConfig::default()),
+            express_sender,
+            express_receiver,
+            express_queue: VecDeque::new(),
+            queue_config: QueueConfig::default(),
+            endpoint: Endpoint::UnixSocket(socket_path),
             echo: false,
             retry_config: RetryConfig {
                 max_retries: 0,
@@ -387,6 +1516,22 @@ mod tests {
                 retry_on_nack: false,
                 retry_on_socket_error: true,
             },
+            resync_config: Some(ResyncConfig {
+                quiet_period: Duration::from_millis(10),
+                max_flush_duration: Duration::from_millis(50),
+                simple_poll: true,
+            }),
+            health: CommsHealth::new(),
+            stats: TransportStats::new(),
+            frame_log: None,
+            unsolicited_policy: UnsolicitedFramePolicy::default(),
+            watchdog: None,
+            encryption_keys: EncryptionKeys::new(),
+            frame_cipher: None,
+            checksum_types: HashMap::new(),
+            response_caps: ResponseSizeCaps::default(),
+            machine_state: None,
+            last_exchange: Instant::now(),
             timeout: Duration::from_millis(100),
             minimum_delay: Duration::from_millis(0),
             send_buffer: vec![0u8; MAX_BLOCK_LENGTH],
@@ -490,11 +1635,13 @@ mod tests {
 
         let (response_tx, response_rx) = oneshot::channel();
         let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
             address: 2,
             checksum_type: ChecksumType::Crc8,
             header: Header::SimplePoll,
             data: vec![],
             respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
         };
 
         tx.send(message).await.unwrap();
@@ -514,6 +1661,51 @@ mod tests {
         transport_handle.abort();
     }
 
+    #[tokio::test]
+    async fn replies_reuse_the_request_buffer() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            mock_device_ack_responder(device_socket_path).await;
+        });
+
+        let transport = create_test_transport(rx, socket_path);
+        let stats = transport.stats();
+        let transport_handle = tokio::spawn(transport.run());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        for data in [TransportMessage::request_buffer(&[]), Vec::new()] {
+            let (response_tx, response_rx) = oneshot::channel();
+            tx.send(TransportMessage {
+                correlation_id: CorrelationId::next(),
+                address: 2,
+                checksum_type: ChecksumType::Crc8,
+                header: Header::SimplePoll,
+                data,
+                respond_to: response_tx,
+                received_at: ReceptionSlot::default(),
+            })
+            .await
+            .unwrap();
+            let response = tokio::time::timeout(Duration::from_millis(200), response_rx)
+                .await
+                .expect("Response timeout")
+                .expect("Response channel error")
+                .expect("Transport error");
+            assert_eq!(response, [1, 0, 2, 0, 253]);
+        }
+
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            (snapshot.reused_buffers, snapshot.buffer_allocations),
+            (1, 1)
+        );
+
+        transport_handle.abort();
+    }
+
     #[tokio::test]
     async fn test_command_with_data() {
         let (_temp_dir, socket_path) = create_test_socket_path();
@@ -572,11 +1764,13 @@ mod tests {
         let (response_tx, response_rx) = oneshot::channel();
         let test_data = vec![0x12, 0x34, 0x56];
         let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
             address: 3,
             checksum_type: ChecksumType::Crc8,
-            header: Header::ModifyInhibitStatus,
+            header: Header::ReadDataBlock,
             data: test_data.clone(),
             respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
         };
 
         tx.send(message).await.unwrap();
@@ -597,6 +1791,177 @@ mod tests {
         transport_handle.abort();
     }
 
+    #[tokio::test]
+    async fn oversized_reply_is_rejected() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        // Replies to every command with 5 bytes of data.
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            base_mock_device(device_socket_path, |mut stream: UnixStream| async move {
+                let mut buffer = [0u8; 256];
+                while let Ok(5..) = stream.read(&mut buffer).await {
+                    let mut response = vec![buffer[2], 5, buffer[0], 0, 1, 2, 3, 4, 5];
+                    let checksum: u16 = response.iter().map(|&b| b as u16).sum();
+                    response.push((256 - (checksum % 256)) as u8);
+                    let _ = stream.write_all(&response).await;
+                }
+            })
+            .await;
+        });
+
+        let transport_socket_path = socket_path.clone();
+        let transport_handle = tokio::spawn(async move {
+            let transport = create_test_transport(rx, transport_socket_path)
+                .with_response_size_caps(ResponseSizeCaps::default().with_device_cap(4, 3));
+            transport.run().await
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let send = |address, header| {
+            let tx = tx.clone();
+            async move {
+                let (response_tx, response_rx) = oneshot::channel();
+                tx.send(TransportMessage {
+                    correlation_id: CorrelationId::next(),
+                    address,
+                    checksum_type: ChecksumType::Crc8,
+                    header,
+                    data: vec![],
+                    respond_to: response_tx,
+                    received_at: ReceptionSlot::default(),
+                })
+                .await
+                .unwrap();
+                tokio::time::timeout(Duration::from_millis(500), response_rx)
+                    .await
+                    .expect("Response timeout")
+                    .expect("Response channel error")
+            }
+        };
+
+        // A hopper status has 4 bytes of data at most.
+        assert_eq!(
+            send(3, Header::RequestHopperStatus).await,
+            Err(TransportError::ResponseTooLong)
+        );
+        // The line stays in sync with the device.
+        let response = send(3, Header::RequestManufacturerId)
+            .await
+            .expect("Transport error");
+        assert_eq!(&response[4..9], &[1, 2, 3, 4, 5]);
+        assert_eq!(
+            send(4, Header::RequestManufacturerId).await,
+            Err(TransportError::ResponseTooLong)
+        );
+
+        transport_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn quirked_device_replies_longer_than_the_table() {
+        use crate::device::{
+            base::DeviceCommon,
+            payout::PayoutDevice,
+            quirks::{DeviceQuirks, ResponseLength},
+        };
+        use cc_talk_core::cc_talk::{Category, Device};
+
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        // A hopper appending two vendor specific bytes to its status.
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            base_mock_device(device_socket_path, |mut stream: UnixStream| async move {
+                let mut buffer = [0u8; 256];
+                while let Ok(5..) = stream.read(&mut buffer).await {
+                    let mut response = vec![buffer[2], 6, buffer[0], 0, 7, 3, 10, 2, 0xAA, 0xBB];
+                    let checksum: u16 = response.iter().map(|&b| b as u16).sum();
+                    response.push((256 - (checksum % 256)) as u8);
+                    let _ = stream.write_all(&response).await;
+                }
+            })
+            .await;
+        });
+
+        let transport = create_test_transport(rx, socket_path.clone());
+        let response_caps = transport.response_size_caps();
+        let transport_handle = tokio::spawn(transport.run());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let quirks: Arc<dyn DeviceQuirks> =
+            Arc::new(ResponseLength::new(Header::RequestHopperStatus, 4));
+        let hopper = PayoutDevice::new(Device::new(3, Category::Payout, ChecksumType::Crc8), tx)
+            .with_quirks(Arc::clone(&quirks));
+        assert!(matches!(
+            hopper.get_payout_status().await,
+            Err(crate::device::base::CommandError::ResponseTooLong)
+        ));
+
+        response_caps.raise_for_quirks(hopper.get_device().address(), quirks.as_ref());
+        let status = hopper
+            .get_payout_status()
+            .await
+            .expect("the quirks should accept the longer reply");
+        assert_eq!(status.event_counter, 7);
+
+        transport_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn configured_checksum_type_overrides_the_device() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        // A bill validator ignoring frames without a CRC-16 checksum.
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            base_mock_device(device_socket_path, |mut stream: UnixStream| async move {
+                let mut buffer = [0u8; 256];
+                while let Ok(n @ 5..) = stream.read(&mut buffer).await {
+                    if ChecksumType::Crc16.verify(&buffer[..n]).is_err() {
+                        continue;
+                    }
+                    let mut response = vec![1, 1, 0, 0, 42, 0];
+                    ChecksumType::Crc16.sign(&mut response).unwrap();
+                    let _ = stream.write_all(&response).await;
+                }
+            })
+            .await;
+        });
+
+        let transport_socket_path = socket_path.clone();
+        let transport_handle = tokio::spawn(async move {
+            let transport = create_test_transport(rx, transport_socket_path)
+                .with_checksum_type(40, ChecksumType::Crc16);
+            transport.run().await
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let (response_tx, response_rx) = oneshot::channel();
+        tx.send(TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address: 40,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::RequestStatus,
+            data: vec![],
+            respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
+        })
+        .await
+        .unwrap();
+        let response = tokio::time::timeout(Duration::from_millis(500), response_rx)
+            .await
+            .expect("Response timeout")
+            .expect("Response channel error")
+            .expect("Transport error");
+        assert_eq!(response[4], 42);
+
+        transport_handle.abort();
+    }
+
     #[tokio::test]
     async fn test_timeout_error() {
         let (_temp_dir, socket_path) = create_test_socket_path();
@@ -617,11 +1982,13 @@ mod tests {
 
         let (response_tx, response_rx) = oneshot::channel();
         let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
             address: 2,
             checksum_type: ChecksumType::Crc8,
             header: Header::SimplePoll,
             data: vec![],
             respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
         };
 
         tx.send(message).await.unwrap();
@@ -636,6 +2003,72 @@ mod tests {
         transport_handle.abort();
     }
 
+    async fn mock_device_slow_ack_responder(socket_path: String) {
+        base_mock_device(socket_path, |mut stream: UnixStream| async move {
+            let mut buffer = [0u8; 256];
+
+            while let Ok(n) = stream.read(&mut buffer).await {
+                if n < 5 {
+                    break;
+                }
+
+                let request = &buffer[..n];
+                let mut response = vec![request[2], 0x00, request[0], 0x00];
+                let checksum: u16 = response.iter().map(|&b| b as u16).sum();
+                response.push((256 - (checksum % 256)) as u8);
+
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                let _ = stream.write_all(&response).await;
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_long_operation_extends_reply_timeout() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            mock_device_slow_ack_responder(device_socket_path).await;
+        });
+
+        let transport_socket_path = socket_path.clone();
+        let transport_handle = tokio::spawn(async move {
+            let transport = create_test_transport(rx, transport_socket_path);
+            assert!(transport.reply_timeout(Header::PerformSelfCheck) > transport.timeout);
+            assert_eq!(
+                transport.reply_timeout(Header::SimplePoll),
+                transport.timeout
+            );
+            transport.run().await
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let (response_tx, response_rx) = oneshot::channel();
+        tx.send(TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address: 2,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::PerformSelfCheck,
+            data: vec![],
+            respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
+        })
+        .await
+        .unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(1), response_rx)
+            .await
+            .expect("Response timeout")
+            .expect("Response channel error");
+        assert!(result.is_ok(), "self-check should wait for the slow reply");
+
+        transport_handle.abort();
+    }
+
     #[tokio::test]
     async fn test_nack_response() {
         let (_temp_dir, socket_path) = create_test_socket_path();
@@ -656,11 +2089,13 @@ mod tests {
 
         let (response_tx, response_rx) = oneshot::channel();
         let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
             address: 2,
             checksum_type: ChecksumType::Crc8,
             header: Header::SimplePoll,
             data: vec![],
             respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
         };
 
         tx.send(message).await.unwrap();
@@ -675,51 +2110,763 @@ mod tests {
         transport_handle.abort();
     }
 
-    #[tokio::test]
-    async fn test_connection_failure() {
-        let (_temp_dir, socket_path) = create_test_socket_path();
-        let (_, rx) = mpsc::channel(10);
+    async fn mock_device_corrupts_first_reply(socket_path: String) {
+        base_mock_device(socket_path, |mut stream: UnixStream| async move {
+            let mut buffer = [0u8; 256];
+            let mut replies = 0;
 
-        let transport_socket_path = socket_path.clone();
-        let transport_result = tokio::spawn(async move {
-            let transport = create_test_transport(rx, transport_socket_path);
-            transport.run().await
-        });
+            while let Ok(n) = stream.read(&mut buffer).await {
+                if n == 0 {
+                    break;
+                }
 
-        let result = tokio::time::timeout(Duration::from_millis(100), transport_result)
-            .await
-            .expect("Transport should fail quickly")
-            .expect("Join error");
+                let request = &buffer[..n];
+                if n >= 5 {
+                    let dest = request[0];
+                    let src = request[2];
 
-        assert!(result.is_err());
+                    let mut response = vec![src, 0x00, dest, 0x00];
+
+                    let checksum: u16 = response.iter().map(|&b| b as u16).sum();
+                    let checksum = (256 - (checksum % 256)) as u8;
+                    response.push(if replies == 0 {
+                        checksum.wrapping_add(1)
+                    } else {
+                        checksum
+                    });
+                    replies += 1;
+
+                    let _ = stream.write_all(&response).await;
+                }
+            }
+        })
+        .await;
     }
 
     #[tokio::test]
-    async fn test_multiple_commands() {
+    async fn test_checksum_error_triggers_resync() {
         let (_temp_dir, socket_path) = create_test_socket_path();
         let (tx, rx) = mpsc::channel(10);
 
         let device_socket_path = socket_path.clone();
         tokio::spawn(async move {
-            mock_device_ack_responder(device_socket_path).await;
+            mock_device_corrupts_first_reply(device_socket_path).await;
         });
 
-        let transport_socket_path = socket_path.clone();
-        let transport_handle = tokio::spawn(async move {
-            let transport = create_test_transport(rx, transport_socket_path);
-            transport.run().await
-        });
+        let mut transport = create_test_transport(rx, socket_path.clone());
+        transport.retry_config.max_retries = 3;
+        transport.retry_config.retry_delay = Duration::ZERO;
+        let health = transport.health();
+        let transport_handle = tokio::spawn(async move { transport.run().await });
 
-        let mut response_receivers = vec![];
+        tokio::time::sleep(Duration::from_millis(10)).await;
 
-        for i in 2..5 {
-            let (response_tx, response_rx) = oneshot::channel();
-            let message = TransportMessage {
+        let (response_tx, response_rx) = oneshot::channel();
+        let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address: 2,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::RequestStatus,
+            data: vec![],
+            respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
+        };
+
+        tx.send(message).await.unwrap();
+
+        let response = tokio::time::timeout(Duration::from_millis(500), response_rx)
+            .await
+            .expect("Response timeout")
+            .expect("Response channel error")
+            .expect("Transport error");
+
+        assert_eq!(response[2], 2);
+        assert_eq!(health.device(2).resync_count, 1);
+        assert_eq!(health.device(3).resync_count, 0);
+
+        transport_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_frame_log_is_dumped_on_checksum_error() {
+        let (temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            mock_device_corrupts_first_reply(device_socket_path).await;
+        });
+
+        let dump_path = temp_dir.path().join("frames.log");
+        let mut transport = create_test_transport(rx, socket_path.clone())
+            .with_frame_log(FrameLog::new(16).with_dump_file(&dump_path));
+        transport.retry_config.max_retries = 3;
+        transport.retry_config.retry_delay = Duration::ZERO;
+        let frame_log = transport.frame_log().expect("frame log should be enabled");
+        let transport_handle = tokio::spawn(async move { transport.run().await });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let (response_tx, response_rx) = oneshot::channel();
+        let correlation_id = CorrelationId::next();
+        let message = TransportMessage {
+            correlation_id,
+            address: 2,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::RequestStatus,
+            data: vec![],
+            respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
+        };
+
+        tx.send(message).await.unwrap();
+
+        tokio::time::timeout(Duration::from_millis(500), response_rx)
+            .await
+            .expect("Response timeout")
+            .expect("Response channel error")
+            .expect("Transport error");
+
+        assert!(
+            frame_log
+                .frames()
+                .iter()
+                .all(|frame| frame.correlation_id == Some(correlation_id))
+        );
+
+        // Request, corrupted reply, resync poll and its reply, retried request and its reply.
+        let directions: Vec<FrameDirection> = frame_log
+            .frames()
+            .iter()
+            .map(|frame| frame.direction)
+            .collect();
+        assert_eq!(
+            directions,
+            vec![
+                FrameDirection::Tx,
+                FrameDirection::Rx,
+                FrameDirection::Tx,
+                FrameDirection::Rx,
+                FrameDirection::Tx,
+                FrameDirection::Rx,
+            ]
+        );
+        assert_eq!(frame_log.frames()[2].bytes[3], Header::SimplePoll as u8);
+
+        // Only the request and the corrupted reply were buffered when the error occurred.
+        let dump = std::fs::read_to_string(&dump_path).expect("dump should be written");
+        assert!(dump.starts_with(&format!(
+            "--- Checksum error for message {correlation_id} to 2"
+        )));
+        assert_eq!(dump.lines().count(), 3);
+
+        transport_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_frames_and_failures() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            mock_device_corrupts_first_reply(device_socket_path).await;
+        });
+
+        let mut transport = create_test_transport(rx, socket_path.clone());
+        transport.retry_config.max_retries = 3;
+        transport.retry_config.retry_delay = Duration::ZERO;
+        let stats = transport.stats();
+        let transport_handle = tokio::spawn(async move { transport.run().await });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let (response_tx, response_rx) = oneshot::channel();
+        let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address: 2,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::RequestStatus,
+            data: vec![],
+            respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
+        };
+
+        tx.send(message).await.unwrap();
+
+        tokio::time::timeout(Duration::from_millis(500), response_rx)
+            .await
+            .expect("Response timeout")
+            .expect("Response channel error")
+            .expect("Transport error");
+
+        // The resync poll is counted as frames but not as an exchange.
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.frames_tx, 3);
+        assert_eq!(snapshot.frames_rx, 3);
+        assert_eq!(snapshot.retries, 1);
+        assert_eq!(snapshot.checksum_failures, 1);
+        assert_eq!(snapshot.timeouts, 0);
+        assert_eq!(snapshot.exchanges, 1);
+        assert!(snapshot.average_latency().is_some());
+
+        stats.reset();
+        assert_eq!(stats.snapshot().frames_tx, 0);
+
+        transport_handle.abort();
+    }
+
+    fn simple_checksum_frame(mut frame: Vec<u8>) -> Vec<u8> {
+        let checksum: u16 = frame.iter().map(|&b| b as u16).sum();
+        frame.push((256 - (checksum % 256)) as u8);
+        frame
+    }
+
+    /// Answers every request after traffic addressed to another device, and follows the first
+    /// reply with a duplicate arriving too late.
+    async fn mock_device_with_foreign_traffic(socket_path: String) {
+        base_mock_device(socket_path, |mut stream: UnixStream| async move {
+            let mut buffer = [0u8; 256];
+            let mut first = true;
+
+            while let Ok(n) = stream.read(&mut buffer).await {
+                if n < 5 {
+                    break;
+                }
+                let mut bytes = simple_checksum_frame(vec![3, 0, 1, Header::SimplePoll as u8]);
+                let reply = simple_checksum_frame(vec![buffer[2], 1, buffer[0], 0, 42]);
+                bytes.extend(&reply);
+                if first {
+                    bytes.extend(&reply);
+                    first = false;
+                }
+                let _ = stream.write_all(&bytes).await;
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_unsolicited_frames_are_forwarded_and_not_returned() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            mock_device_with_foreign_traffic(device_socket_path).await;
+        });
+
+        let (unsolicited_tx, mut unsolicited_rx) = mpsc::channel(10);
+        let transport = create_test_transport(rx, socket_path.clone())
+            .with_unsolicited_frame_policy(UnsolicitedFramePolicy::Forward(unsolicited_tx));
+        let stats = transport.stats();
+        let transport_handle = tokio::spawn(async move { transport.run().await });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        for _ in 0..2 {
+            let (response_tx, response_rx) = oneshot::channel();
+            tx.send(TransportMessage {
+                correlation_id: CorrelationId::next(),
+                address: 2,
+                checksum_type: ChecksumType::Crc8,
+                header: Header::RequestStatus,
+                data: vec![],
+                respond_to: response_tx,
+                received_at: ReceptionSlot::default(),
+            })
+            .await
+            .unwrap();
+            let response = tokio::time::timeout(Duration::from_millis(200), response_rx)
+                .await
+                .expect("Response timeout")
+                .expect("Response channel error")
+                .expect("Transport error");
+            assert_eq!(&response[..5], &[1, 1, 2, 0, 42]);
+        }
+
+        let mut destinations = Vec::new();
+        while let Ok(frame) = unsolicited_rx.try_recv() {
+            destinations.push(frame.bytes[0]);
+        }
+        assert_eq!(destinations, vec![3, 1, 3]);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.unsolicited_frames, 3);
+        assert_eq!(snapshot.exchanges, 2);
+
+        transport_handle.abort();
+    }
+
+    #[test]
+    fn express_commands_are_detected() {
+        let message = |header, data: Vec<u8>| TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address: 3,
+            checksum_type: ChecksumType::Crc8,
+            header,
+            data,
+            respond_to: oneshot::channel().0,
+            received_at: ReceptionSlot::default(),
+        };
+
+        assert!(message(Header::EmergencyStop, vec![]).is_express());
+        assert!(message(Header::EmergencyStopValue, vec![]).is_express());
+        assert!(message(Header::ModifyMasterInhibitStatus, vec![0]).is_express());
+        assert!(!message(Header::ModifyMasterInhibitStatus, vec![1]).is_express());
+        assert!(!message(Header::SimplePoll, vec![]).is_express());
+    }
+
+    #[test]
+    fn full_queue_applies_the_backpressure_policy() {
+        let message = |header| {
+            let (respond_to, response) = oneshot::channel();
+            let message = TransportMessage {
+                correlation_id: CorrelationId::next(),
+                address: 3,
+                checksum_type: ChecksumType::Crc8,
+                header,
+                data: vec![],
+                respond_to,
+                received_at: ReceptionSlot::default(),
+            };
+            (message, response)
+        };
+        let (_tx, rx) = mpsc::channel(1);
+
+        let mut transport = create_test_transport(rx, String::new())
+            .with_queue_config(QueueConfig::new(2, BackpressurePolicy::FailFast));
+        let (first, _first) = message(Header::SimplePoll);
+        let (second, _second) = message(Header::RequestSerialNumber);
+        let (rejected, mut rejected_response) = message(Header::SimplePoll);
+        transport.enqueue(first);
+        transport.enqueue(second);
+        transport.enqueue(rejected);
+        assert_eq!(transport.scheduler.len(), 2);
+        assert_eq!(
+            rejected_response.try_recv(),
+            Ok(Err(TransportError::QueueFull))
+        );
+        assert_eq!(transport.stats.snapshot().rejected_messages, 1);
+
+        let (_tx, rx) = mpsc::channel(1);
+        let mut transport = create_test_transport(rx, String::new())
+            .with_queue_config(QueueConfig::new(2, BackpressurePolicy::DropOldestPoll));
+        let (serial, _serial) = message(Header::RequestSerialNumber);
+        let (poll, mut poll_response) = message(Header::SimplePoll);
+        let (dispense, _dispense) = message(Header::DispenseHopperCoins);
+        let (payout, mut payout_response) = message(Header::PurgeHopper);
+        transport.enqueue(serial);
+        transport.enqueue(poll);
+        transport.enqueue(dispense);
+        assert_eq!(poll_response.try_recv(), Ok(Err(TransportError::QueueFull)));
+        let headers: Vec<_> = transport
+            .scheduler
+            .messages()
+            .iter()
+            .map(|queued| queued.header)
+            .collect();
+        assert_eq!(
+            headers,
+            [Header::RequestSerialNumber, Header::DispenseHopperCoins]
+        );
+
+        transport.enqueue(payout);
+        assert_eq!(
+            payout_response.try_recv(),
+            Ok(Err(TransportError::QueueFull))
+        );
+        let snapshot = transport.stats.snapshot();
+        assert_eq!(snapshot.dropped_polls, 1);
+        assert_eq!(snapshot.rejected_messages, 1);
+    }
+
+    #[tokio::test]
+    async fn machine_state_fails_disallowed_messages() {
+        let message = |header, data| {
+            let (respond_to, response) = oneshot::channel();
+            let message = TransportMessage {
+                correlation_id: CorrelationId::next(),
+                address: 3,
+                checksum_type: ChecksumType::Crc8,
+                header,
+                data,
+                respond_to,
+                received_at: ReceptionSlot::default(),
+            };
+            (message, response)
+        };
+        let machine_state = MachineStateHandle::new();
+        let (_tx, rx) = mpsc::channel(1);
+        let mut transport =
+            create_test_transport(rx, String::new()).with_machine_state(machine_state.clone());
+
+        let (dispense, mut dispense_response) = message(Header::DispenseHopperCoins, vec![1]);
+        let (disable, _disable) = message(Header::EnableHopper, vec![0]);
+        transport.enqueue(dispense);
+        transport.enqueue(disable);
+        machine_state.set_state(MachineState::Maintenance);
+
+        let sent = transport.next_message().await.expect("should be queued");
+        assert_eq!(sent.header, Header::EnableHopper);
+        assert_eq!(
+            dispense_response.try_recv(),
+            Ok(Err(TransportError::NotAllowed(MachineState::Maintenance)))
+        );
+    }
+
+    #[tokio::test]
+    async fn blocking_queue_leaves_messages_in_the_channel() {
+        let (tx, rx) = mpsc::channel(4);
+        let mut transport = create_test_transport(rx, String::new())
+            .with_queue_config(QueueConfig::new(2, BackpressurePolicy::Block));
+        let _responses: Vec<_> = (0..4)
+            .map(|_| send_message(&tx, 3, Header::SimplePoll))
+            .collect();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        transport.drain_receivers();
+        assert_eq!(transport.scheduler.len(), 2);
+        let snapshot = transport.stats.snapshot();
+        assert_eq!(snapshot.queue_depth, 4);
+        assert_eq!(snapshot.max_queue_depth, 4);
+
+        assert!(transport.next_message().await.is_some());
+        assert_eq!(transport.scheduler.len(), 1);
+        assert_eq!(transport.stats.snapshot().queue_depth, 3);
+    }
+
+    #[tokio::test]
+    async fn minimum_gap_lets_other_addresses_through() {
+        let gap = Duration::from_millis(40);
+        let (tx, rx) = mpsc::channel(4);
+        let mut transport = create_test_transport(rx, String::new()).with_scheduler_config(
+            SchedulerConfig::new()
+                .with_minimum_gap(3, gap)
+                .with_priority(3, DevicePriority::High),
+        );
+        let mut responses = Vec::new();
+        for address in [3, 3, 4] {
+            let (respond_to, response) = oneshot::channel();
+            tx.send(TransportMessage {
+                correlation_id: CorrelationId::next(),
+                address,
+                checksum_type: ChecksumType::Crc8,
+                header: Header::SimplePoll,
+                data: vec![],
+                respond_to,
+                received_at: ReceptionSlot::default(),
+            })
+            .await
+            .expect("should queue");
+            responses.push(response);
+        }
+
+        let started = Instant::now();
+        let next = transport.next_message().await.expect("should be queued");
+        assert_eq!(next.address, 3);
+        transport.scheduler.exchange_done(3, Instant::now());
+        let next = transport.next_message().await.expect("should be queued");
+        assert_eq!(next.address, 4);
+        assert!(started.elapsed() < gap);
+        let next = transport.next_message().await.expect("should be queued");
+        assert_eq!(next.address, 3);
+        assert!(started.elapsed() >= gap);
+
+        drop(tx);
+        assert!(transport.next_message().await.is_none());
+    }
+
+    /// Replies to every address except `silent_address` after `delay`, recording the headers
+    /// received in order.
+    async fn mock_device_recording(
+        socket_path: String,
+        received: Arc<Mutex<Vec<u8>>>,
+        delay: Duration,
+        silent_address: u8,
+    ) {
+        if Path::new(&socket_path).exists() {
+            std::fs::remove_file(&socket_path).ok();
+        }
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buffer = [0u8; 256];
+
+            while let Ok(n) = stream.read(&mut buffer).await {
+                if n == 0 {
+                    break;
+                }
+
+                let request = &buffer[..n];
+                if n >= 5 {
+                    let dest = request[0];
+                    let src = request[2];
+                    received.lock().unwrap().push(request[3]);
+                    if dest == silent_address {
+                        continue;
+                    }
+
+                    tokio::time::sleep(delay).await;
+                    let mut response = vec![src, 0x00, dest, 0x00];
+
+                    let checksum: u16 = response.iter().map(|&b| b as u16).sum();
+                    response.push((256 - (checksum % 256)) as u8);
+
+                    let _ = stream.write_all(&response).await;
+                }
+            }
+        }
+    }
+
+    fn send_message(
+        tx: &mpsc::Sender<TransportMessage>,
+        address: u8,
+        header: Header,
+    ) -> oneshot::Receiver<Result<Vec<u8>, TransportError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            tx.send(TransportMessage {
+                correlation_id: CorrelationId::next(),
+                address,
+                checksum_type: ChecksumType::Crc8,
+                header,
+                data: vec![],
+                respond_to: response_tx,
+                received_at: ReceptionSlot::default(),
+            })
+            .await
+            .unwrap();
+        });
+        response_rx
+    }
+
+    #[tokio::test]
+    async fn test_express_message_overtakes_full_queue() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(2);
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let device_socket_path = socket_path.clone();
+        let device_received = Arc::clone(&received);
+        tokio::spawn(async move {
+            mock_device_recording(
+                device_socket_path,
+                device_received,
+                Duration::from_millis(20),
+                0,
+            )
+            .await;
+        });
+
+        let transport = create_test_transport(rx, socket_path.clone());
+        let latency_bound = transport.express_latency_bound();
+        let express_tx = transport.express_sender();
+        let transport_handle = tokio::spawn(async move { transport.run().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let mut regular = vec![];
+        for _ in 0..8 {
+            regular.push(send_message(&tx, 3, Header::SimplePoll));
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let sent_at = Instant::now();
+        let express = send_message(&express_tx, 3, Header::EmergencyStop);
+        express
+            .await
+            .expect("Response channel error")
+            .expect("Transport error");
+        assert!(sent_at.elapsed() <= latency_bound);
+
+        for response in regular {
+            response
+                .await
+                .expect("Response channel error")
+                .expect("Transport error");
+        }
+
+        let received = received.lock().unwrap();
+        let express_position = received
+            .iter()
+            .position(|&header| header == Header::EmergencyStop as u8)
+            .expect("emergency stop should be sent");
+        assert!(express_position <= 1, "sent at {express_position}");
+
+        transport_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_express_message_preempts_retries() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let device_socket_path = socket_path.clone();
+        let device_received = Arc::clone(&received);
+        tokio::spawn(async move {
+            mock_device_recording(device_socket_path, device_received, Duration::ZERO, 5).await;
+        });
+
+        let mut transport = create_test_transport(rx, socket_path.clone());
+        transport.retry_config.max_retries = 3;
+        transport.retry_config.retry_delay = Duration::ZERO;
+        let latency_bound = transport.express_latency_bound();
+        let transport_handle = tokio::spawn(async move { transport.run().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let failing = send_message(&tx, 5, Header::SimplePoll);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let sent_at = Instant::now();
+        let express = send_message(&tx, 3, Header::EmergencyStop);
+        express
+            .await
+            .expect("Response channel error")
+            .expect("Transport error");
+        assert!(sent_at.elapsed() <= latency_bound);
+
+        assert_eq!(
+            failing.await.expect("Response channel error"),
+            Err(TransportError::Timeout)
+        );
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                Header::SimplePoll as u8,
+                Header::EmergencyStop as u8,
+                Header::SimplePoll as u8,
+                Header::SimplePoll as u8,
+            ]
+        );
+
+        transport_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_watchdog_reopens_a_silent_bus() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            let listener = UnixListener::bind(&device_socket_path).unwrap();
+            let mut first = true;
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = [0u8; 256];
+                while let Ok(n) = stream.read(&mut buffer).await {
+                    if n == 0 {
+                        break;
+                    }
+                    // The first connection hangs, the device answers once reopened.
+                    if first || n < 5 {
+                        continue;
+                    }
+                    let mut response = vec![buffer[2], 0x00, buffer[0], 0x00];
+                    let checksum: u16 = response.iter().map(|&b| b as u16).sum();
+                    response.push((256 - (checksum % 256)) as u8);
+                    let _ = stream.write_all(&response).await;
+                }
+                first = false;
+            }
+        });
+
+        let (events_tx, mut events) = mpsc::channel(1);
+        let transport_socket_path = socket_path.clone();
+        let transport_handle = tokio::spawn(async move {
+            let transport =
+                create_test_transport(rx, transport_socket_path).with_watchdog(WatchdogConfig {
+                    silence_period: Duration::ZERO,
+                    steps: vec![RecoveryStep::Flush, RecoveryStep::Reopen],
+                    events: Some(events_tx),
+                });
+            transport.run().await
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let send_poll = async || {
+            let (response_tx, response_rx) = oneshot::channel();
+            tx.send(TransportMessage {
+                correlation_id: CorrelationId::next(),
+                address: 2,
+                checksum_type: ChecksumType::Crc8,
+                header: Header::SimplePoll,
+                data: vec![],
+                respond_to: response_tx,
+                received_at: ReceptionSlot::default(),
+            })
+            .await
+            .unwrap();
+            tokio::time::timeout(Duration::from_secs(1), response_rx)
+                .await
+                .expect("Response timeout")
+                .expect("Response channel error")
+        };
+
+        assert_eq!(send_poll().await, Err(TransportError::Timeout));
+        let recovery = tokio::time::timeout(Duration::from_secs(1), events.recv())
+            .await
+            .expect("Recovery timeout")
+            .expect("Recovery channel error");
+        assert_eq!(
+            recovery.attempted,
+            vec![RecoveryStep::Flush, RecoveryStep::Reopen]
+        );
+        assert_eq!(recovery.restored_by, Some(RecoveryStep::Reopen));
+        assert!(send_poll().await.is_ok(), "the bus is back");
+
+        transport_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_connection_failure() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (_, rx) = mpsc::channel(10);
+
+        let transport_socket_path = socket_path.clone();
+        let transport_result = tokio::spawn(async move {
+            let transport = create_test_transport(rx, transport_socket_path);
+            transport.run().await
+        });
+
+        let result = tokio::time::timeout(Duration::from_millis(100), transport_result)
+            .await
+            .expect("Transport should fail quickly")
+            .expect("Join error");
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_multiple_commands() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            mock_device_ack_responder(device_socket_path).await;
+        });
+
+        let transport_socket_path = socket_path.clone();
+        let transport_handle = tokio::spawn(async move {
+            let transport = create_test_transport(rx, transport_socket_path);
+            transport.run().await
+        });
+
+        let mut response_receivers = vec![];
+
+        for i in 2..5 {
+            let (response_tx, response_rx) = oneshot::channel();
+            let message = TransportMessage {
+                correlation_id: CorrelationId::next(),
                 address: i,
                 checksum_type: ChecksumType::Crc8,
                 header: Header::SimplePoll,
                 data: vec![],
                 respond_to: response_tx,
+                received_at: ReceptionSlot::default(),
             };
 
             tx.send(message).await.unwrap();
@@ -739,39 +2886,169 @@ mod tests {
         transport_handle.abort();
     }
 
+    #[tokio::test]
+    async fn switched_encryption_codes_apply_after_the_ack() {
+        use cc_talk_core::cc_talk::XorChainCipher;
+
+        let first = BnvKey::new(123_456).expect("valid key");
+        let second = BnvKey::new(654_321).expect("valid key");
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        // A device ignoring the frames it cannot decrypt, the ACK of a switch is still
+        // encrypted with the previous key.
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            base_mock_device(
+                device_socket_path,
+                move |mut stream: UnixStream| async move {
+                    let mut key = first;
+                    let mut buffer = [0u8; 256];
+                    while let Ok(n @ 5..) = stream.read(&mut buffer).await {
+                        let frame = &mut buffer[..n];
+                        XorChainCipher.decrypt(&key, frame);
+                        if ChecksumType::Crc8.verify(frame).is_err() {
+                            continue;
+                        }
+                        let mut response = vec![1, 0, frame[0], 0, 0];
+                        ChecksumType::Crc8.sign(&mut response).unwrap();
+                        XorChainCipher.encrypt(&key, &mut response);
+                        if frame[3] == Header::SwitchEncryptionMode as u8 {
+                            key = BnvKey::from_bytes([frame[4], frame[5], frame[6]]).unwrap();
+                        }
+                        let _ = stream.write_all(&response).await;
+                    }
+                },
+            )
+            .await;
+        });
+
+        let transport = create_test_transport(rx, socket_path.clone())
+            .with_frame_cipher(XorChainCipher)
+            .with_encryption_key(3, first);
+        let encryption_keys = transport.encryption_keys();
+        let transport_handle = tokio::spawn(transport.run());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let exchange = |header, data: &[u8]| {
+            let (response_tx, response_rx) = oneshot::channel();
+            let message = TransportMessage {
+                correlation_id: CorrelationId::next(),
+                address: 3,
+                checksum_type: ChecksumType::Crc8,
+                header,
+                data: data.to_vec(),
+                respond_to: response_tx,
+                received_at: ReceptionSlot::default(),
+            };
+            let tx = tx.clone();
+            async move {
+                tx.send(message).await.unwrap();
+                tokio::time::timeout(Duration::from_millis(500), response_rx)
+                    .await
+                    .expect("Response timeout")
+                    .expect("Response channel error")
+            }
+        };
+
+        assert!(exchange(Header::SimplePoll, &[]).await.is_ok());
+        assert!(
+            exchange(Header::SwitchEncryptionMode, &second.to_bytes())
+                .await
+                .is_ok()
+        );
+        assert_eq!(encryption_keys.get(3), Some(second));
+        assert!(exchange(Header::SimplePoll, &[]).await.is_ok());
+
+        // The device no longer understands the previous key.
+        encryption_keys.set(3, first);
+        assert_eq!(
+            exchange(Header::SimplePoll, &[]).await,
+            Err(TransportError::Timeout)
+        );
+
+        transport_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn replies_are_stamped_at_reception() {
+        let (_temp_dir, socket_path) = create_test_socket_path();
+        let (tx, rx) = mpsc::channel(10);
+
+        let device_socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            mock_device_ack_responder(device_socket_path).await;
+        });
+
+        let transport_socket_path = socket_path.clone();
+        let transport_handle = tokio::spawn(async move {
+            let transport = create_test_transport(rx, transport_socket_path);
+            transport.run().await
+        });
+
+        let sent_at = std::time::Instant::now();
+        let received_at = ReceptionSlot::default();
+        let (response_tx, response_rx) = oneshot::channel();
+        tx.send(TransportMessage {
+            correlation_id: CorrelationId::next(),
+            address: 2,
+            checksum_type: ChecksumType::Crc8,
+            header: Header::SimplePoll,
+            data: vec![],
+            respond_to: response_tx,
+            received_at: received_at.clone(),
+        })
+        .await
+        .unwrap();
+        tokio::time::timeout(Duration::from_millis(200), response_rx)
+            .await
+            .expect("Response timeout")
+            .expect("Response channel error")
+            .expect("Transport error");
+
+        let stamp = received_at.get().expect("reply should be stamped");
+        assert!(stamp.monotonic >= sent_at);
+        assert!(stamp.monotonic <= std::time::Instant::now());
+
+        transport_handle.abort();
+    }
+
     #[tokio::test]
     async fn test_packet_building() {
         let (response_tx, _response_rx) = oneshot::channel();
         let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
             address: 5,
             checksum_type: ChecksumType::Crc8,
             header: Header::RequestStatus,
             data: vec![0x01, 0x02],
             respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
         };
 
         let mut buffer = vec![0u8; MAX_BLOCK_LENGTH];
-        let mut packet = Packet::new(buffer.as_mut_slice());
 
-        let message = Message::from(&message);
-        let result = build_packet(&message, &mut packet);
-        assert!(result.is_ok());
+        let message = Message::from(&message, None);
+        let length = build_packet(&message, &mut buffer).unwrap();
+        let packet = Packet::parse(&buffer[..length], ChecksumType::Crc8).unwrap();
 
-        assert_eq!(packet.get_destination().unwrap(), 5);
-        assert_eq!(packet.get_source().unwrap(), 1);
-        assert_eq!(packet.get_header().unwrap(), Header::RequestStatus);
-        assert_eq!(packet.get_data().unwrap(), &[0x01, 0x02]);
+        assert_eq!(packet.destination(), 5);
+        assert_eq!(packet.source(), Some(1));
+        assert_eq!(packet.header(), Header::RequestStatus);
+        assert_eq!(packet.data(), &[0x01, 0x02]);
     }
 
     #[tokio::test]
     async fn test_error_handling() {
         let (response_tx, response_rx) = oneshot::channel();
         let message = TransportMessage {
+            correlation_id: CorrelationId::next(),
             address: 2,
             checksum_type: ChecksumType::Crc8,
             header: Header::SimplePoll,
             data: vec![],
             respond_to: response_tx,
+            received_at: ReceptionSlot::default(),
         };
 
         handle_error(message, TransportError::Timeout, "test error");
@@ -0,0 +1,103 @@
+use std::time::SystemTime;
+
+use cc_talk_core::cc_talk::Packet;
+use tokio::sync::mpsc;
+use tracing::{trace, warn};
+
+use super::frame_log::{FrameDirection, RawFrame};
+
+/// What the transport does with frames nobody asked for.
+///
+/// A frame is unsolicited when it is read while no request is outstanding, e.g. the late reply
+/// to a request which already timed out, or when it is not addressed to the host, e.g. the
+/// traffic of another master on a shared bus. Unsolicited frames are never returned as the reply
+/// to a request and are always counted in
+/// [`TransportStatsSnapshot::unsolicited_frames`](super::stats::TransportStatsSnapshot::unsolicited_frames).
+#[derive(Debug, Clone, Default)]
+pub enum UnsolicitedFramePolicy {
+    /// Discards the frames.
+    Drop,
+    /// Logs the frames as warnings, then discards them.
+    #[default]
+    Log,
+    /// Sends the frames to the channel, e.g. for a bus sniffer.
+    ///
+    /// The transport never waits for the receiver, frames which do not fit in the channel are
+    /// logged and discarded.
+    Forward(mpsc::Sender<RawFrame>),
+}
+
+impl UnsolicitedFramePolicy {
+    pub(crate) fn handle(&self, bytes: &[u8]) {
+        match self {
+            Self::Drop => trace!(
+                "dropping unsolicited frame {}",
+                Packet::new(bytes).display()
+            ),
+            Self::Log => warn!("unsolicited frame {}", Packet::new(bytes).display()),
+            Self::Forward(sender) => {
+                let frame = RawFrame {
+                    direction: FrameDirection::Rx,
+                    timestamp: SystemTime::now(),
+                    bytes: bytes.to_vec(),
+                    correlation_id: None,
+                };
+                if let Err(error) = sender.try_send(frame) {
+                    warn!(
+                        "unable to forward unsolicited frame {}: {}",
+                        Packet::new(bytes).display(),
+                        error
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Splits bytes read from the line into frames using their data length byte.
+///
+/// Trailing bytes which do not form a complete frame are returned as the last frame.
+pub(crate) fn split_frames(mut bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        if bytes.is_empty() {
+            return None;
+        }
+        let length = bytes
+            .get(1)
+            .map_or(bytes.len(), |&data_length| usize::from(data_length) + 5)
+            .min(bytes.len());
+        let (frame, rest) = bytes.split_at(length);
+        bytes = rest;
+        Some(frame)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_are_split_on_their_length() {
+        let bytes = [1, 0, 2, 0, 253, 1, 2, 3, 0, 7, 8, 9, 1, 1];
+        let frames: Vec<&[u8]> = split_frames(&bytes).collect();
+        assert_eq!(
+            frames,
+            vec![&[1, 0, 2, 0, 253][..], &[1, 2, 3, 0, 7, 8, 9], &[1, 1]]
+        );
+        assert_eq!(split_frames(&[5]).collect::<Vec<_>>(), vec![&[5][..]]);
+        assert_eq!(split_frames(&[]).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn forwarded_frames_never_block() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let policy = UnsolicitedFramePolicy::Forward(tx);
+        policy.handle(&[1, 0, 2, 0, 253]);
+        policy.handle(&[1, 0, 3, 0, 252]);
+
+        let frame = rx.recv().await.expect("should forward");
+        assert_eq!(frame.direction, FrameDirection::Rx);
+        assert_eq!(frame.bytes, vec![1, 0, 2, 0, 253]);
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use std::{fmt, time::Duration};
+
+use tokio::sync::mpsc;
+
+/// Step of the recovery ladder run by the bus watchdog, see [`WatchdogConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryStep {
+    /// Drains the line using the resync quiet period.
+    Flush,
+    /// Closes the connection to the bus and opens it again.
+    Reopen,
+    /// Sends a `ResetDevice` to the broadcast address, every device on the bus resets.
+    BroadcastReset,
+    /// Falls back to the default baud rate, only serial lines have a baud rate and the step is
+    /// skipped on other transports.
+    BaudFallback,
+}
+
+impl fmt::Display for RecoveryStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flush => write!(f, "flush"),
+            Self::Reopen => write!(f, "reopen"),
+            Self::BroadcastReset => write!(f, "broadcast reset"),
+            Self::BaudFallback => write!(f, "baud fallback"),
+        }
+    }
+}
+
+/// Host-side watchdog detecting a silent bus.
+///
+/// The bus is silent when a command fails and no exchange succeeded for `silence_period`. The
+/// transport then runs the `steps` in order, probing the device of the failed command with a
+/// `SimplePoll` after each step, until one restores the communication. The ladder runs at most
+/// once per silence period.
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    pub silence_period: Duration,
+    pub steps: Vec<RecoveryStep>,
+    /// Receives a [`BusRecovery`] after each ladder, reports which do not fit are dropped.
+    pub events: Option<mpsc::Sender<BusRecovery>>,
+}
+
+impl Default for WatchdogConfig {
+    /// Flushes then reopens the connection after 5 seconds of silence, devices are not reset.
+    fn default() -> Self {
+        WatchdogConfig {
+            silence_period: Duration::from_secs(5),
+            steps: vec![RecoveryStep::Flush, RecoveryStep::Reopen],
+            events: None,
+        }
+    }
+}
+
+/// Outcome of a recovery ladder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusRecovery {
+    /// Time since the last successful exchange when the ladder started.
+    pub silent_for: Duration,
+    /// Steps run, in order.
+    pub attempted: Vec<RecoveryStep>,
+    /// Step after which the probe succeeded, `None` if the bus is still silent.
+    pub restored_by: Option<RecoveryStep>,
+}